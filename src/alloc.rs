@@ -218,6 +218,57 @@ impl Region
         }
     }
 
+    /// Extends this region past its current end, making the added memory
+    /// available for allocation.
+    ///
+    /// * `end`: New end of the region, which must be aligned to 16 bytes.
+    ///
+    /// Panics if the new end is before the current end.
+    #[track_caller]
+    pub unsafe fn extend(&mut self, end: usize)
+    {
+        assert!(end >= self.range.end,
+                "Attempted to shrink an allocator region ending at 0x{:X} to 0x{end:X}",
+                self.range.end);
+        let start = self.range.end;
+        self.range.end = end;
+        // An uninitialized region picks the new range up on the first allocation.
+        if self.head.is_none() || start == end {
+            return;
+        }
+        let layout = Layout::from_size_align(end - start, 16).unwrap();
+        self.deallocate(NonNull::new_unchecked(start as *mut u8), layout);
+    }
+
+    /// Validates the free list.
+    ///
+    /// Panics with details about the first corrupted fragment if any fragment
+    /// is misaligned, empty, out of range, or not strictly after the end of the
+    /// previous fragment.
+    #[track_caller]
+    pub fn check_integrity(&self)
+    {
+        let Some(mut current) = self.head else { return };
+        let mut prev_end = None;
+        while !current.is_null() {
+            let start = current as usize;
+            let size = unsafe { (*current).size };
+            let end = start + size;
+            assert!(start & 0xF == 0 && size & 0xF == 0,
+                    "Misaligned free fragment at 0x{start:X} with size 0x{size:X}");
+            assert!(size != 0, "Empty free fragment at 0x{start:X}");
+            assert!(self.range.start <= start && end <= self.range.end,
+                    "Free fragment 0x{start:X} .. 0x{end:X} is out of range");
+            if let Some(prev_end) = prev_end {
+                assert!(start > prev_end,
+                        "Free fragment at 0x{start:X} is not strictly after the previous fragment ending at \
+                         0x{prev_end:X}");
+            }
+            prev_end = Some(end);
+            current = unsafe { (*current).next };
+        }
+    }
+
     /// Deallocates the memory starting at the specified base address with the
     /// specified layout.
     ///
@@ -688,6 +739,53 @@ mod tests
         assert_eq!(base, 0xA00);
     }
 
+    #[test]
+    fn extend()
+    {
+        test_extend(&[0x0 .. 0x400], &[0x0 .. 0x400, 0x800 .. 0x1000]).unwrap();
+    }
+
+    #[test]
+    fn extend_merge()
+    {
+        test_extend(&[0x0 .. 0x200, 0x400 .. 0x800], &[0x0 .. 0x200, 0x400 .. 0x1000]).unwrap();
+    }
+
+    #[test]
+    fn extend_full()
+    {
+        test_extend(&[], &[0x800 .. 0x1000]).unwrap();
+    }
+
+    #[test]
+    fn check_integrity()
+    {
+        let mut buf = Buffer::new();
+        let mut region = unsafe { Region::new(buf.range()) };
+        buf.provide(&mut region, &[0x0 .. 0x200, 0x400 .. 0x1000]).unwrap();
+        region.check_integrity();
+        let layout = Layout::from_size_align(0x400, 16).unwrap();
+        let base = region.allocate(layout).unwrap().as_mut_ptr();
+        region.check_integrity();
+        let new_layout = Layout::from_size_align(0x600, 16).unwrap();
+        let grown = unsafe { region.grow(NonNull::new_unchecked(base), layout, new_layout) };
+        let base = grown.unwrap().as_mut_ptr();
+        region.check_integrity();
+        unsafe { region.deallocate(NonNull::new_unchecked(base), new_layout) };
+        region.check_integrity();
+        buf.validate(&mut region, &[0x0 .. 0x200, 0x400 .. 0x1000]).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn check_integrity_unordered()
+    {
+        let mut buf = Buffer::new();
+        let mut region = unsafe { Region::new(buf.range()) };
+        buf.provide(&mut region, &[0x400 .. 0x500, 0x200 .. 0x300]).unwrap();
+        region.check_integrity();
+    }
+
     fn test_alloc(layout: Layout, input: &[Range<usize>], output: &[Range<usize>]) -> Result<usize, ()>
     {
         let mut buf = Buffer::new();
@@ -715,6 +813,18 @@ mod tests
         Ok(())
     }
 
+    fn test_extend(input: &[Range<usize>], output: &[Range<usize>]) -> Result<(), ()>
+    {
+        let mut buf = Buffer::new();
+        let range = buf.range();
+        let mut region = unsafe { Region::new(range.start .. range.start + 0x800) };
+        buf.provide(&mut region, input)?;
+        unsafe { region.extend(range.end) };
+        buf.validate(&mut region, output)?;
+        region.check_integrity();
+        Ok(())
+    }
+
     fn test_realloc(base: usize, layout: Layout, new_size: usize, input: &[Range<usize>], output: &[Range<usize>])
                     -> Result<usize, ()>
     {
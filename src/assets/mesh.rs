@@ -0,0 +1,41 @@
+//! Mesh data.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::simd::f32x4;
+
+/// Triangle mesh produced by the loaders.
+#[derive(Debug, Default)]
+pub struct Mesh
+{
+    /// Triangles with vertices in counter-clockwise order.
+    pub tris: Vec<[MeshVertex; 3]>,
+    /// Names of the materials referred to by the parts.
+    pub materials: Vec<String>,
+    /// Ranges of triangles sharing a material, which is empty if the source
+    /// doesn't assign any materials.
+    pub parts: Vec<MeshPart>,
+}
+
+/// Mesh vertex.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshVertex
+{
+    /// Position, with the last lane set to 1.
+    pub pos: f32x4,
+    /// Unit normal, with the last lane set to 0.
+    pub normal: f32x4,
+}
+
+/// Range of triangles sharing a material.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MeshPart
+{
+    /// Indices of the triangles.
+    pub tris: Range<usize>,
+    /// Index of the material name.
+    pub material: usize,
+}
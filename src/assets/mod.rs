@@ -0,0 +1,25 @@
+//! Assets.
+//!
+//! Stores the files loaded at run time and converts them into the
+//! representations used by the rest of the game.  The loaders have no
+//! dependencies on the hardware and are tested on the host, whereas the store
+//! relies on the kernel's locks.
+
+mod mesh;
+mod nbm;
+mod ntx;
+mod obj;
+#[cfg(not(test))]
+mod store;
+mod texture;
+
+pub use self::mesh::*;
+#[cfg(not(test))]
+pub use self::nbm::*;
+#[cfg(not(test))]
+pub use self::ntx::*;
+#[cfg(not(test))]
+pub use self::obj::*;
+#[cfg(not(test))]
+pub use self::store::*;
+pub use self::texture::*;
@@ -0,0 +1,309 @@
+//! Binary mesh loader.
+//!
+//! Meshes are converted from OBJ on the development machine into a compact
+//! format that can be loaded without any parsing beyond bounds checks.  All
+//! values are little-endian and the file is laid out as follows:
+//!
+//! | Offset | Size | Contents                          |
+//! |--------|------|-----------------------------------|
+//! | 0      | 4    | Magic `NBM1`                      |
+//! | 4      | 2    | Format version, currently 1       |
+//! | 6      | 2    | Reserved, must be 0               |
+//! | 8      | 4    | Vertex count `V`, at most 65536   |
+//! | 12     | 4    | Index count `I`, a multiple of 3  |
+//! | 16     | 2    | Part count `P`                    |
+//! | 18     | 2    | Material count `M`                |
+//! | 20     | 24V  | Vertices                          |
+//! | ...    | 2I   | Indices as `u16`, 3 per triangle  |
+//! | ...    | 12P  | Parts                             |
+//! | ...    | ...  | Materials                         |
+//!
+//! Each vertex is made of the position followed by the normal, both as three
+//! `f32` coordinates.  Triangles list their vertices in counter-clockwise
+//! order.  Each part is made of the index of its first triangle as `u32`, its
+//! triangle count as `u32`, the index of its material as `u16`, and a reserved
+//! `u16` that must be 0.  Each material is made of the length of its name as
+//! `u8` followed by the UTF-8 name.
+//!
+//! The file must end right after the last material name, and parts must not
+//! overlap nor extend past the last triangle.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FormatResult};
+use core::simd::f32x4;
+use core::str::from_utf8;
+
+use super::{Mesh, MeshPart, MeshVertex};
+
+/// Magic number at the beginning of every file.
+const MAGIC: [u8; 4] = *b"NBM1";
+/// Supported format version.
+const VERSION: u16 = 1;
+/// Size of the header.
+const HEADER_SIZE: usize = 20;
+/// Size of a vertex.
+const VERTEX_SIZE: usize = 24;
+/// Size of a part.
+const PART_SIZE: usize = 12;
+/// Maximum number of vertices addressable by the indices.
+const MAX_VERTICES: usize = 0x10000;
+
+/// Errors that can occur when loading a binary mesh.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NbmError
+{
+    /// The file doesn't start with the magic number.
+    Magic,
+    /// The file uses an unsupported version of the format.
+    Version(u16),
+    /// The header contains invalid counts or reserved bits.
+    Header,
+    /// The file ends before all the declared data.
+    Truncated,
+    /// The file contains data past the last material name.
+    Trailing(usize),
+    /// A triangle refers to a vertex that doesn't exist.
+    Index
+    {
+        /// Position of the index in the index array.
+        pos: usize,
+        /// Value of the index.
+        index: u16,
+    },
+    /// A part overlaps another part, extends past the last triangle, or refers
+    /// to a material that doesn't exist.
+    Part(usize),
+    /// A material name isn't valid UTF-8.
+    Material(usize),
+}
+
+/// Cursor reading little-endian values from a file.
+#[derive(Debug)]
+struct Reader<'a>
+{
+    /// Unread data.
+    data: &'a [u8],
+}
+
+/// Loads a binary mesh.
+///
+/// * `src`: Contents of the file.
+///
+/// Returns the loaded mesh, or the first problem found.
+pub fn parse_nbm(src: &[u8]) -> Result<Mesh, NbmError>
+{
+    if src.len() < HEADER_SIZE {
+        return Err(NbmError::Truncated);
+    }
+    let mut reader = Reader { data: src };
+    if reader.bytes(4)? != MAGIC {
+        return Err(NbmError::Magic);
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(NbmError::Version(version));
+    }
+    let reserved = reader.u16()?;
+    let vcount = reader.u32()? as usize;
+    let icount = reader.u32()? as usize;
+    let pcount = reader.u16()? as usize;
+    let mcount = reader.u16()? as usize;
+    if reserved != 0 || vcount > MAX_VERTICES || icount % 3 != 0 {
+        return Err(NbmError::Header);
+    }
+    // Check the size of the fixed size sections up front so that nothing is
+    // allocated for files that lie about their contents.
+    if reader.data.len() < vcount * VERTEX_SIZE + icount * 2 + pcount * PART_SIZE + mcount {
+        return Err(NbmError::Truncated);
+    }
+    let mut verts = Vec::with_capacity(vcount);
+    for _ in 0 .. vcount {
+        let mut vals = [0.0; 6];
+        for val in vals.iter_mut() {
+            *val = reader.f32()?;
+        }
+        let [px, py, pz, nx, ny, nz] = vals;
+        let pos = f32x4::from_array([px, py, pz, 1.0]);
+        let normal = f32x4::from_array([nx, ny, nz, 0.0]);
+        verts.push(MeshVertex { pos, normal });
+    }
+    let mut tris = Vec::with_capacity(icount / 3);
+    for tri in 0 .. icount / 3 {
+        let mut vert = |pos| {
+            let index = reader.u16()?;
+            verts.get(index as usize).copied().ok_or(NbmError::Index { pos, index })
+        };
+        tris.push([vert(tri * 3)?, vert(tri * 3 + 1)?, vert(tri * 3 + 2)?]);
+    }
+    let mut parts = Vec::<MeshPart>::with_capacity(pcount);
+    for idx in 0 .. pcount {
+        let first = reader.u32()? as usize;
+        let count = reader.u32()? as usize;
+        let material = reader.u16()? as usize;
+        let reserved = reader.u16()?;
+        let end = first.checked_add(count).ok_or(NbmError::Part(idx))?;
+        let overlaps = parts.iter().any(|part| part.tris.start < end && first < part.tris.end);
+        if reserved != 0 || end > tris.len() || material >= mcount || overlaps {
+            return Err(NbmError::Part(idx));
+        }
+        parts.push(MeshPart { tris: first .. end,
+                              material });
+    }
+    let mut materials = Vec::with_capacity(mcount);
+    for idx in 0 .. mcount {
+        let len = reader.u8()? as usize;
+        let name = from_utf8(reader.bytes(len)?).map_err(|_| NbmError::Material(idx))?;
+        materials.push(String::from(name));
+    }
+    if !reader.data.is_empty() {
+        return Err(NbmError::Trailing(reader.data.len()));
+    }
+    Ok(Mesh { tris, materials, parts })
+}
+
+impl Display for NbmError
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        match self {
+            Self::Magic => write!(fmt, "not a binary mesh"),
+            Self::Version(version) => write!(fmt, "unsupported format version {version}"),
+            Self::Header => write!(fmt, "invalid header"),
+            Self::Truncated => write!(fmt, "truncated file"),
+            Self::Trailing(len) => write!(fmt, "{len} unexpected bytes at the end"),
+            Self::Index { pos, index } => write!(fmt, "index #{pos} refers to missing vertex {index}"),
+            Self::Part(idx) => write!(fmt, "part #{idx} is out of range or overlaps another part"),
+            Self::Material(idx) => write!(fmt, "material #{idx} has an invalid name"),
+        }
+    }
+}
+
+impl<'a> Reader<'a>
+{
+    /// Reads raw bytes.
+    ///
+    /// * `len`: Number of bytes to read.
+    ///
+    /// Returns the read bytes, or an error if the file is too short.
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], NbmError>
+    {
+        if self.data.len() < len {
+            return Err(NbmError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Reads a byte.
+    ///
+    /// Returns the read value, or an error if the file is too short.
+    fn u8(&mut self) -> Result<u8, NbmError>
+    {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Reads a 16-bit integer.
+    ///
+    /// Returns the read value, or an error if the file is too short.
+    fn u16(&mut self) -> Result<u16, NbmError>
+    {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    /// Reads a 32-bit integer.
+    ///
+    /// Returns the read value, or an error if the file is too short.
+    fn u32(&mut self) -> Result<u32, NbmError>
+    {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Reads a 32-bit float.
+    ///
+    /// Returns the read value, or an error if the file is too short.
+    fn f32(&mut self) -> Result<f32, NbmError>
+    {
+        Ok(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Builds a file with a quad made of two triangles, each with its own
+    /// material.
+    fn quad() -> Vec<u8>
+    {
+        let mut file = Vec::new();
+        file.extend_from_slice(&MAGIC);
+        file.extend_from_slice(&VERSION.to_le_bytes());
+        file.extend_from_slice(&0u16.to_le_bytes());
+        file.extend_from_slice(&4u32.to_le_bytes());
+        file.extend_from_slice(&6u32.to_le_bytes());
+        file.extend_from_slice(&2u16.to_le_bytes());
+        file.extend_from_slice(&2u16.to_le_bytes());
+        for [x, y] in [[0.0f32, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]] {
+            for val in [x, y, 0.0, 0.0, 0.0, 1.0] {
+                file.extend_from_slice(&val.to_le_bytes());
+            }
+        }
+        for index in [0u16, 1, 2, 0, 2, 3] {
+            file.extend_from_slice(&index.to_le_bytes());
+        }
+        for (first, material) in [(0u32, 1u16), (1, 0)] {
+            file.extend_from_slice(&first.to_le_bytes());
+            file.extend_from_slice(&1u32.to_le_bytes());
+            file.extend_from_slice(&material.to_le_bytes());
+            file.extend_from_slice(&0u16.to_le_bytes());
+        }
+        for name in ["stone", "gold"] {
+            file.push(name.len() as u8);
+            file.extend_from_slice(name.as_bytes());
+        }
+        file
+    }
+
+    #[test]
+    fn parse()
+    {
+        let mesh = parse_nbm(&quad()).unwrap();
+        assert_eq!(mesh.tris.len(), 2);
+        assert_eq!(mesh.tris[1][2].pos, f32x4::from_array([0.0, 1.0, 0.0, 1.0]));
+        assert_eq!(mesh.tris[1][2].normal, f32x4::from_array([0.0, 0.0, 1.0, 0.0]));
+        assert_eq!(mesh.materials, ["stone", "gold"]);
+        assert_eq!(mesh.parts[0],
+                   MeshPart { tris: 0 .. 1,
+                              material: 1 });
+    }
+
+    #[test]
+    fn parse_errors()
+    {
+        let file = quad();
+        assert_eq!(parse_nbm(&file[.. file.len() - 1]).unwrap_err(), NbmError::Truncated);
+        assert_eq!(parse_nbm(&file[.. 30]).unwrap_err(), NbmError::Truncated);
+        let mut bad = file.clone();
+        bad.push(0);
+        assert_eq!(parse_nbm(&bad).unwrap_err(), NbmError::Trailing(1));
+        let mut bad = file.clone();
+        bad[0] = b'X';
+        assert_eq!(parse_nbm(&bad).unwrap_err(), NbmError::Magic);
+        let mut bad = file.clone();
+        bad[12] = 5;
+        assert_eq!(parse_nbm(&bad).unwrap_err(), NbmError::Header);
+        // Points the last index at a fifth vertex.
+        let mut bad = file.clone();
+        bad[HEADER_SIZE + 4 * VERTEX_SIZE + 10] = 4;
+        assert_eq!(parse_nbm(&bad).unwrap_err(), NbmError::Index { pos: 5, index: 4 });
+        // Makes the second part start at the first triangle.
+        let mut bad = file;
+        bad[HEADER_SIZE + 4 * VERTEX_SIZE + 12 + PART_SIZE] = 0;
+        assert_eq!(parse_nbm(&bad).unwrap_err(), NbmError::Part(1));
+    }
+}
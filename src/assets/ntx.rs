@@ -0,0 +1,201 @@
+//! Texture loader.
+//!
+//! Textures are stored in a raw container with all their mip levels generated
+//! ahead of time on the development machine, so loading them only requires
+//! validating the header and copying the texels into cache line aligned
+//! memory.  All values are little-endian and the file is laid out as follows:
+//!
+//! | Offset | Size | Contents                                   |
+//! |--------|------|--------------------------------------------|
+//! | 0      | 4    | Magic `NTX1`                               |
+//! | 4      | 2    | Format version, currently 1                |
+//! | 6      | 1    | Texel format (0 RGB565, 1 RGBA8888)        |
+//! | 7      | 1    | Mip level count                            |
+//! | 8      | 2    | Width of the base level                    |
+//! | 10     | 2    | Height of the base level                   |
+//! | 12     | 4    | FNV-1a hash of the texels                  |
+//! | 16     | ...  | Texels of every level                      |
+//!
+//! Each level is half the width and height of the previous one rounded down,
+//! but never smaller than 1, and there can't be more levels than needed to
+//! reach 1x1.  Levels are tightly packed starting with the base level, with
+//! rows from bottom to top, and the file must end right after the last level.
+
+use core::fmt::{Display, Formatter, Result as FormatResult};
+
+use super::{Texture, TextureFormat};
+
+/// Magic number at the beginning of every file.
+const MAGIC: [u8; 4] = *b"NTX1";
+/// Supported format version.
+const VERSION: u16 = 1;
+/// Size of the header.
+const HEADER_SIZE: usize = 16;
+/// FNV-1a offset basis.
+const FNV_BASIS: u32 = 0x811C9DC5;
+/// FNV-1a prime.
+const FNV_PRIME: u32 = 0x1000193;
+
+/// Errors that can occur when loading a texture.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NtxError
+{
+    /// The file doesn't start with the magic number.
+    Magic,
+    /// The file uses an unsupported version of the format.
+    Version(u16),
+    /// The file uses an unknown texel format.
+    Format(u8),
+    /// The base level is empty.
+    Size,
+    /// The file has no levels or more levels than the size allows.
+    Levels(u8),
+    /// The file ends before all the declared levels.
+    Truncated,
+    /// The file contains data past the last level.
+    Trailing(usize),
+    /// The texels don't match the hash in the header.
+    Checksum
+    {
+        /// Hash in the header.
+        expected: u32,
+        /// Hash of the texels.
+        actual: u32,
+    },
+}
+
+/// Loads a texture.
+///
+/// * `src`: Contents of the file.
+///
+/// Returns the loaded texture, or the first problem found.
+pub fn parse_ntx(src: &[u8]) -> Result<Texture, NtxError>
+{
+    if src.len() < HEADER_SIZE {
+        return Err(NtxError::Truncated);
+    }
+    let (header, texels) = src.split_at(HEADER_SIZE);
+    if header[0 .. 4] != MAGIC {
+        return Err(NtxError::Magic);
+    }
+    let version = u16::from_le_bytes([header[4], header[5]]);
+    if version != VERSION {
+        return Err(NtxError::Version(version));
+    }
+    let format = match header[6] {
+        0 => TextureFormat::Rgb565,
+        1 => TextureFormat::Rgba8888,
+        other => return Err(NtxError::Format(other)),
+    };
+    let count = header[7];
+    let width = u16::from_le_bytes([header[8], header[9]]) as usize;
+    let height = u16::from_le_bytes([header[10], header[11]]) as usize;
+    let expected = u32::from_le_bytes([header[12], header[13], header[14], header[15]]);
+    if width == 0 || height == 0 {
+        return Err(NtxError::Size);
+    }
+    let max = width.max(height).ilog2() + 1;
+    if count == 0 || count as u32 > max {
+        return Err(NtxError::Levels(count));
+    }
+    let sizes = (0 .. count as usize).map(|idx| (width >> idx).max(1) * (height >> idx).max(1) * format.depth());
+    let total = sizes.clone().sum::<usize>();
+    if texels.len() < total {
+        return Err(NtxError::Truncated);
+    }
+    if texels.len() > total {
+        return Err(NtxError::Trailing(texels.len() - total));
+    }
+    let actual = texels.iter()
+                       .fold(FNV_BASIS, |hash, byte| (hash ^ *byte as u32).wrapping_mul(FNV_PRIME));
+    if actual != expected {
+        return Err(NtxError::Checksum { expected, actual });
+    }
+    let levels = sizes.scan(0, |start, size| {
+                          let level = &texels[*start .. *start + size];
+                          *start += size;
+                          Some(level)
+                      });
+    Ok(Texture::from_levels(format, width, height, levels))
+}
+
+impl Display for NtxError
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        match self {
+            Self::Magic => write!(fmt, "not a texture"),
+            Self::Version(version) => write!(fmt, "unsupported format version {version}"),
+            Self::Format(format) => write!(fmt, "unknown texel format {format}"),
+            Self::Size => write!(fmt, "empty base level"),
+            Self::Levels(count) => write!(fmt, "invalid mip level count {count}"),
+            Self::Truncated => write!(fmt, "truncated file"),
+            Self::Trailing(len) => write!(fmt, "{len} unexpected bytes at the end"),
+            Self::Checksum { expected, actual } => {
+                write!(fmt, "texel hash is 0x{actual:08X} instead of 0x{expected:08X}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    extern crate alloc;
+
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// Builds a file with a 4x2 RGBA8888 texture and a complete mip chain.
+    fn texture() -> Vec<u8>
+    {
+        let texels = (0 .. (8 + 2 + 1) * 4).map(|byte| byte as u8).collect::<Vec<_>>();
+        let hash = texels.iter()
+                         .fold(FNV_BASIS, |hash, byte| (hash ^ *byte as u32).wrapping_mul(FNV_PRIME));
+        let mut file = Vec::new();
+        file.extend_from_slice(&MAGIC);
+        file.extend_from_slice(&VERSION.to_le_bytes());
+        file.extend_from_slice(&[TextureFormat::Rgba8888 as u8, 3]);
+        file.extend_from_slice(&4u16.to_le_bytes());
+        file.extend_from_slice(&2u16.to_le_bytes());
+        file.extend_from_slice(&hash.to_le_bytes());
+        file.extend_from_slice(&texels);
+        file
+    }
+
+    #[test]
+    fn parse()
+    {
+        let tex = parse_ntx(&texture()).unwrap();
+        assert_eq!(tex.format(), TextureFormat::Rgba8888);
+        let level = tex.level(1).unwrap();
+        assert_eq!((level.width, level.height), (2, 1));
+        assert_eq!(level.data, &(32 .. 40).collect::<Vec<u8>>()[..]);
+        assert_eq!(level.data.as_ptr() as usize % 0x40, 0);
+        let level = tex.level(2).unwrap();
+        assert_eq!((level.width, level.height), (1, 1));
+        assert_eq!(level.data, [40, 41, 42, 43]);
+        assert!(tex.level(3).is_none());
+    }
+
+    #[test]
+    fn parse_errors()
+    {
+        let file = texture();
+        assert_eq!(parse_ntx(&file[.. 10]).unwrap_err(), NtxError::Truncated);
+        assert_eq!(parse_ntx(&file[.. file.len() - 1]).unwrap_err(), NtxError::Truncated);
+        let mut bad = file.clone();
+        bad.push(0);
+        assert_eq!(parse_ntx(&bad).unwrap_err(), NtxError::Trailing(1));
+        let mut bad = file.clone();
+        bad[6] = 2;
+        assert_eq!(parse_ntx(&bad).unwrap_err(), NtxError::Format(2));
+        let mut bad = file.clone();
+        bad[7] = 4;
+        assert_eq!(parse_ntx(&bad).unwrap_err(), NtxError::Levels(4));
+        let mut bad = file;
+        bad[HEADER_SIZE] ^= 1;
+        assert!(matches!(parse_ntx(&bad).unwrap_err(), NtxError::Checksum { .. }));
+    }
+}
@@ -0,0 +1,282 @@
+//! Wavefront OBJ mesh loader.
+//!
+//! Understands vertex positions, normals, texture coordinates, and polygonal
+//! faces, which are split into triangle fans, and ignores the statements that
+//! only matter to other tools, like object, group, and material names.  Faces
+//! without normals get flat normals computed from their positions, and texture
+//! coordinates are only validated until meshes have somewhere to put them.
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FormatResult};
+use core::simd::f32x4;
+use core::str::from_utf8;
+
+use super::{Mesh, MeshVertex};
+use crate::simd::SimdFloatExtra;
+
+/// Error found while parsing an OBJ file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ObjError
+{
+    /// Line number starting at 1.
+    pub line: usize,
+    /// Problem found in the line.
+    pub kind: ObjErrorKind,
+}
+
+/// Problems that can be found in an OBJ file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ObjErrorKind
+{
+    /// The file isn't valid UTF-8.
+    Encoding,
+    /// A statement has fewer values than it requires.
+    MissingValues
+    {
+        /// Statement keyword.
+        keyword: String,
+        /// Number of values required.
+        expected: usize,
+    },
+    /// A value isn't a number.
+    InvalidNumber(String),
+    /// A face vertex isn't made of up to three slash separated indices.
+    InvalidIndex(String),
+    /// A face vertex refers to an element that hasn't been defined.
+    IndexOutOfRange
+    {
+        /// Kind of the referred element.
+        element: &'static str,
+        /// Index as written in the file.
+        index: isize,
+        /// Number of elements of this kind defined so far.
+        count: usize,
+    },
+}
+
+/// Indices of the elements referred to by a face vertex.
+#[derive(Clone, Copy, Debug)]
+struct FaceVertex
+{
+    /// Position index.
+    pos: usize,
+    /// Normal index, if any.
+    normal: Option<usize>,
+}
+
+/// Parses an OBJ file.
+///
+/// * `src`: Contents of the file.
+///
+/// Returns the parsed mesh, or an error describing the first problem found.
+pub fn parse_obj(src: &[u8]) -> Result<Mesh, ObjError>
+{
+    let src = from_utf8(src).map_err(|err| {
+                                let line = src[.. err.valid_up_to()].iter().filter(|byte| **byte == b'\n').count() + 1;
+                                ObjError { line,
+                                           kind: ObjErrorKind::Encoding }
+                            })?;
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = 0;
+    let mut mesh = Mesh::default();
+    let mut face = Vec::new();
+    for (idx, line) in src.lines().enumerate() {
+        let error = |kind| ObjError { line: idx + 1, kind };
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        match keyword {
+            "v" => {
+                let [x, y, z] = parse_floats(keyword, words).map_err(error)?;
+                positions.push(f32x4::from_array([x, y, z, 1.0]));
+            }
+            "vn" => {
+                let [x, y, z] = parse_floats(keyword, words).map_err(error)?;
+                let normal = f32x4::from_array([x, y, z, 0.0]);
+                normals.push(normal.normalize().unwrap_or(normal));
+            }
+            "vt" => {
+                parse_floats::<2>(keyword, words).map_err(error)?;
+                uvs += 1;
+            }
+            "f" => {
+                face.clear();
+                for word in words {
+                    let vert = parse_face_vertex(word, positions.len(), uvs, normals.len()).map_err(error)?;
+                    face.push(vert);
+                }
+                if face.len() < 3 {
+                    return Err(error(ObjErrorKind::MissingValues { keyword: keyword.to_string(),
+                                                                   expected: 3 }));
+                }
+                for idx in 1 .. face.len() - 1 {
+                    let verts = [face[0], face[idx], face[idx + 1]];
+                    mesh.tris.push(triangle(verts, &positions, &normals));
+                }
+            }
+            _ => continue,
+        }
+    }
+    Ok(mesh)
+}
+
+impl Display for ObjError
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        write!(fmt, "line {}: ", self.line)?;
+        match &self.kind {
+            ObjErrorKind::Encoding => write!(fmt, "invalid UTF-8"),
+            ObjErrorKind::MissingValues { keyword, expected } => {
+                write!(fmt, "`{keyword}` requires at least {expected} values")
+            }
+            ObjErrorKind::InvalidNumber(word) => write!(fmt, "`{word}` is not a number"),
+            ObjErrorKind::InvalidIndex(word) => write!(fmt, "`{word}` is not a valid face vertex"),
+            ObjErrorKind::IndexOutOfRange { element, index, count } => {
+                write!(fmt, "{element} {index} is out of range, only {count} defined so far")
+            }
+        }
+    }
+}
+
+/// Parses the leading values of a statement as numbers.
+///
+/// * `keyword`: Statement keyword, for error reporting.
+/// * `words`: Values following the keyword.
+///
+/// Returns the parsed numbers, or the problem found.
+fn parse_floats<'a, const COUNT: usize>(keyword: &str, mut words: impl Iterator<Item = &'a str>)
+                                        -> Result<[f32; COUNT], ObjErrorKind>
+{
+    let mut vals = [0.0; COUNT];
+    for val in vals.iter_mut() {
+        let word = words.next()
+                        .ok_or_else(|| ObjErrorKind::MissingValues { keyword: keyword.to_string(),
+                                                                     expected: COUNT })?;
+        *val = word.parse()
+                   .map_err(|_| ObjErrorKind::InvalidNumber(word.to_string()))?;
+    }
+    Ok(vals)
+}
+
+/// Parses a face vertex in the `v`, `v/vt`, `v//vn`, or `v/vt/vn` forms.
+///
+/// * `word`: Face vertex to parse.
+/// * `positions`: Number of positions defined so far.
+/// * `uvs`: Number of texture coordinates defined so far.
+/// * `normals`: Number of normals defined so far.
+///
+/// Returns the resolved indices, or the problem found.
+fn parse_face_vertex(word: &str, positions: usize, uvs: usize, normals: usize) -> Result<FaceVertex, ObjErrorKind>
+{
+    let invalid = || ObjErrorKind::InvalidIndex(word.to_string());
+    let mut parts = word.split('/');
+    let pos = parts.next().filter(|part| !part.is_empty()).ok_or_else(invalid)?;
+    let uv = parts.next().filter(|part| !part.is_empty());
+    let normal = parts.next().filter(|part| !part.is_empty());
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    let pos = resolve_index(pos, "position", positions).ok_or_else(invalid)??;
+    if let Some(uv) = uv {
+        resolve_index(uv, "texture coordinate", uvs).ok_or_else(invalid)??;
+    }
+    let normal = match normal {
+        Some(normal) => Some(resolve_index(normal, "normal", normals).ok_or_else(invalid)??),
+        None => None,
+    };
+    Ok(FaceVertex { pos, normal })
+}
+
+/// Converts a 1-based index, or a negative index relative to the end, into a
+/// 0-based index.
+///
+/// * `word`: Index as written in the file.
+/// * `element`: Kind of the referred element, for error reporting.
+/// * `count`: Number of elements of this kind defined so far.
+///
+/// Returns `None` if the index isn't an integer, or the resolved index or the
+/// problem found.
+fn resolve_index(word: &str, element: &'static str, count: usize) -> Option<Result<usize, ObjErrorKind>>
+{
+    let index = word.parse::<isize>().ok()?;
+    let resolved = match index {
+        1 .. => index as usize - 1,
+        ..= -1 => count.wrapping_sub(index.unsigned_abs()),
+        0 => usize::MAX,
+    };
+    if resolved >= count {
+        return Some(Err(ObjErrorKind::IndexOutOfRange { element, index, count }));
+    }
+    Some(Ok(resolved))
+}
+
+/// Builds a mesh triangle, computing a flat normal for vertices without one.
+///
+/// * `verts`: Face vertices in counter-clockwise order.
+/// * `positions`: Positions defined so far.
+/// * `normals`: Normals defined so far.
+///
+/// Returns the built triangle.
+fn triangle(verts: [FaceVertex; 3], positions: &[f32x4], normals: &[f32x4]) -> [MeshVertex; 3]
+{
+    let [pos0, pos1, pos2] = verts.map(|vert| positions[vert.pos]);
+    let flat = (pos1 - pos0).cross_dot(pos2 - pos0)
+                            .replace_lane::<3>(0.0)
+                            .normalize()
+                            .unwrap_or_default();
+    verts.map(|vert| MeshVertex { pos: positions[vert.pos],
+                                  normal: vert.normal.map_or(flat, |normal| normals[normal]) })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parse_quad()
+    {
+        let src = b"# Quad\no quad\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvn 0 0 2\ns off\nf 1/1/1 2//1 \
+                    3/1/-1 -1\n";
+        let mesh = parse_obj(src).unwrap();
+        assert_eq!(mesh.tris.len(), 2);
+        let [vert0, vert1, vert2] = mesh.tris[1];
+        assert_eq!(vert0.pos, f32x4::from_array([0.0, 0.0, 0.0, 1.0]));
+        assert_eq!(vert1.pos, f32x4::from_array([1.0, 1.0, 0.0, 1.0]));
+        assert_eq!(vert2.pos, f32x4::from_array([0.0, 1.0, 0.0, 1.0]));
+        let normal = f32x4::from_array([0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(vert0.normal, normal);
+        assert_eq!(vert1.normal, normal);
+        // The last vertex has no normal so it gets the flat normal of the face.
+        assert_eq!(vert2.normal, normal);
+    }
+
+    #[test]
+    fn parse_errors()
+    {
+        let err = parse_obj(b"v 0 0 0\nv 1 0\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.to_string(), "line 2: `v` requires at least 3 values");
+        let err = parse_obj(b"v 0 zero 0\n").unwrap_err();
+        assert_eq!(err.kind, ObjErrorKind::InvalidNumber("zero".to_string()));
+        let err = parse_obj(b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n").unwrap_err();
+        assert_eq!(err.to_string(),
+                   "line 4: position 4 is out of range, only 3 defined so far");
+        let err = parse_obj(b"v 0 0 0\nf 1 1\n").unwrap_err();
+        assert_eq!(err.kind,
+                   ObjErrorKind::MissingValues { keyword: "f".to_string(),
+                                                 expected: 3 });
+        let err = parse_obj(b"v 0 0 0\nf 1/2/3/4 1 1\n").unwrap_err();
+        assert_eq!(err.kind, ObjErrorKind::InvalidIndex("1/2/3/4".to_string()));
+        let err = parse_obj(b"v 0 0 0\n\xFF\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.kind, ObjErrorKind::Encoding);
+    }
+}
@@ -0,0 +1,123 @@
+//! Asset store.
+//!
+//! Keeps the contents of the meshes, textures, and levels loaded at run time
+//! in the cached region indexed by file name, so that loaders can look them up
+//! regardless of where they came from.  The files in the assets directory of
+//! the SD card are loaded at boot, and when built with the `netassets` option,
+//! every file listed in a manifest is also downloaded from a TFTP server,
+//! which allows iterating on assets on a development machine without writing
+//! them to the boot medium every time.
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+#[cfg(netassets)]
+use core::str::from_utf8;
+
+use crate::debug;
+use crate::emmc::STORAGE;
+use crate::fat::FatError;
+#[cfg(netassets)]
+use crate::net::{tftp_get, Ipv4Address, TftpError};
+use crate::sync::{Lazy, Lock};
+
+/// Directory of the SD card holding the assets.
+const ASSETS_DIR: &str = "ASSETS";
+
+/// Name of the file listing the assets to download, one per line, with empty
+/// lines and lines starting with `#` ignored.
+#[cfg(netassets)]
+const MANIFEST: &str = "manifest.txt";
+
+/// Global asset store instance.
+pub static ASSETS: Lazy<Assets> = Lazy::new(Assets::new);
+
+/// Asset store.
+#[derive(Debug)]
+pub struct Assets
+{
+    /// Asset contents indexed by name.
+    files: Lock<BTreeMap<String, Arc<Vec<u8>>>>,
+}
+
+impl Assets
+{
+    /// Creates and initializes a new asset store.
+    ///
+    /// Returns the newly created store.
+    fn new() -> Self
+    {
+        Self { files: Lock::new(BTreeMap::new()) }
+    }
+
+    /// Adds an asset, replacing any asset with the same name.
+    ///
+    /// * `name`: Name of the asset.
+    /// * `contents`: Contents of the asset.
+    pub fn insert(&self, name: &str, contents: Vec<u8>)
+    {
+        self.files.lock().insert(String::from(name), Arc::new(contents));
+    }
+
+    /// Looks up an asset.
+    ///
+    /// * `name`: Name of the asset.
+    ///
+    /// Returns the contents of the asset, or `None` if it isn't loaded.
+    pub fn get(&self, name: &str) -> Option<Arc<Vec<u8>>>
+    {
+        self.files.lock().get(name).cloned()
+    }
+
+    /// Reads every file in the assets directory of the SD card, replacing any
+    /// assets with the same names.  Names are converted to lowercase, since
+    /// the card only keeps short names in uppercase.
+    ///
+    /// Returns the number of loaded assets, or an error if the directory
+    /// couldn't be listed.  Files that can't be read are skipped.
+    pub fn load(&self) -> Result<usize, FatError>
+    {
+        let mut count = 0;
+        for entry in STORAGE.list(ASSETS_DIR)?.into_iter().filter(|entry| !entry.is_dir) {
+            let name = entry.name.to_ascii_lowercase();
+            match STORAGE.read(&format!("{ASSETS_DIR}/{}", entry.name)) {
+                Ok(contents) => {
+                    debug!("Loaded asset {name}: {} bytes", contents.len());
+                    self.insert(&name, contents);
+                    count += 1;
+                }
+                Err(err) => debug!("Failed to load asset {name}: {err}"),
+            }
+        }
+        Ok(count)
+    }
+
+    /// Downloads the manifest and every asset listed in it from a TFTP
+    /// server, replacing any assets with the same names.
+    ///
+    /// * `server`: Address of the server.
+    ///
+    /// Returns the number of downloaded assets, or an error if any download
+    /// fails, in which case the assets downloaded so far are kept.
+    #[cfg(netassets)]
+    pub async fn fetch(&self, server: Ipv4Address) -> Result<usize, TftpError>
+    {
+        let manifest = tftp_get(server, MANIFEST).await?;
+        let names = from_utf8(&manifest).unwrap_or_default()
+                                        .lines()
+                                        .map(str::trim)
+                                        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let mut count = 0;
+        for name in names {
+            let contents = tftp_get(server, name).await?;
+            debug!("Downloaded asset {name}: {} bytes", contents.len());
+            self.insert(name, contents);
+            count += 1;
+        }
+        Ok(count)
+    }
+}
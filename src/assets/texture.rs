@@ -0,0 +1,121 @@
+//! Texture data.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::slice::from_raw_parts as slice_from_raw_parts;
+
+/// Size of a cache line, which every mip level is aligned to.
+const LINE_SIZE: usize = 0x40;
+
+/// Texel encodings.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextureFormat
+{
+    /// 16-bit texels with 5 bits of red, 6 bits of green, and 5 bits of blue.
+    Rgb565 = 0,
+    /// 32-bit texels with 8 bits of red, green, blue, and alpha in this order.
+    Rgba8888 = 1,
+}
+
+/// Texture with a chain of mip levels, each half the size of the previous one.
+#[derive(Debug)]
+pub struct Texture
+{
+    /// Texel encoding.
+    format: TextureFormat,
+    /// Width of the base level.
+    width: usize,
+    /// Height of the base level.
+    height: usize,
+    /// Byte ranges of the levels in the data.
+    levels: Vec<Range<usize>>,
+    /// Texel data, with each level starting at a cache line boundary.
+    data: Vec<Line>,
+}
+
+/// Mip level of a texture.
+#[derive(Clone, Copy, Debug)]
+pub struct Level<'a>
+{
+    /// Width.
+    pub width: usize,
+    /// Height.
+    pub height: usize,
+    /// Texels in rows from bottom to top.
+    pub data: &'a [u8],
+}
+
+/// Cache line.
+#[repr(C, align(0x40))]
+#[derive(Clone, Copy, Debug)]
+struct Line([u8; LINE_SIZE]);
+
+impl TextureFormat
+{
+    /// Returns the size of a texel in bytes.
+    pub fn depth(self) -> usize
+    {
+        match self {
+            Self::Rgb565 => 2,
+            Self::Rgba8888 => 4,
+        }
+    }
+}
+
+impl Texture
+{
+    /// Creates and initializes a new texture by copying its levels into cached
+    /// memory.
+    ///
+    /// * `format`: Texel encoding.
+    /// * `width`: Width of the base level.
+    /// * `height`: Height of the base level.
+    /// * `levels`: Texel data of each level, starting with the base level.
+    ///
+    /// Returns the newly created texture.
+    pub(super) fn from_levels<'a>(format: TextureFormat, width: usize, height: usize,
+                                  levels: impl Iterator<Item = &'a [u8]> + Clone)
+                                  -> Self
+    {
+        let lines = levels.clone().map(|level| level.len().div_ceil(LINE_SIZE)).sum();
+        let mut data = Vec::with_capacity(lines);
+        let mut ranges = Vec::new();
+        for level in levels {
+            let start = data.len() * LINE_SIZE;
+            ranges.push(start .. start + level.len());
+            for chunk in level.chunks(LINE_SIZE) {
+                let mut line = Line([0; LINE_SIZE]);
+                line.0[.. chunk.len()].copy_from_slice(chunk);
+                data.push(line);
+            }
+        }
+        Self { format,
+               width,
+               height,
+               levels: ranges,
+               data }
+    }
+
+    /// Returns the texel encoding.
+    pub fn format(&self) -> TextureFormat
+    {
+        self.format
+    }
+
+    /// Looks up a mip level.
+    ///
+    /// * `idx`: Index of the level, with 0 being the base level.
+    ///
+    /// Returns the level, or `None` if the texture doesn't have it.
+    pub fn level(&self, idx: usize) -> Option<Level<'_>>
+    {
+        let range = self.levels.get(idx)?.clone();
+        let bytes = unsafe { slice_from_raw_parts(self.data.as_ptr().cast::<u8>(), self.data.len() * LINE_SIZE) };
+        Some(Level { width: (self.width >> idx).max(1),
+                     height: (self.height >> idx).max(1),
+                     data: &bytes[range] })
+    }
+}
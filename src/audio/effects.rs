@@ -0,0 +1,132 @@
+//! Mix effects.
+//!
+//! Processes the mixed output before it's quantized for the PWM: a delay line
+//! echo whose repeats fade by a configurable feedback, followed by a one-pole
+//! low-pass filter [1] that muffles everything, which together make caves
+//! sound like caves.  The echo works on whole vectors of samples since its
+//! delay is rounded to a multiple of the vector width, whereas the filter
+//! depends on the previous sample so it processes both channels of one sample
+//! at a time.
+//!
+//! [1]: https://en.wikipedia.org/wiki/Low-pass_filter#Simple_infinite_impulse_response_filter
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f32::consts::PI;
+use core::simd::prelude::*;
+
+use super::SMPL_RATE;
+use crate::simd::SimdFloatExtra;
+
+/// Effects applied to the mix.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Effects
+{
+    /// Cutoff frequency in hertz of the low-pass filter, if enabled.
+    pub low_pass: Option<f32>,
+    /// Echo, if enabled.
+    pub echo: Option<Echo>,
+}
+
+/// Delay line echo.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Echo
+{
+    /// Time in milliseconds between repeats.
+    pub delay: u32,
+    /// Fraction of each repeat fed back into the delay line.
+    pub feedback: f32,
+    /// Fraction of the delayed signal added to the output.
+    pub mix: f32,
+}
+
+/// Effects processing state.
+#[derive(Debug, Default)]
+pub(super) struct Processor
+{
+    /// Effects being applied.
+    effects: Effects,
+    /// Low-pass filter smoothing factor.
+    alpha: f32,
+    /// Last low-pass filter output for the left and right channels in the
+    /// first two lanes.
+    last: f32x4,
+    /// Left channel delay line.
+    left: Vec<f32x4>,
+    /// Right channel delay line.
+    right: Vec<f32x4>,
+    /// Position in the delay lines.
+    pos: usize,
+}
+
+impl Effects
+{
+    /// Checks whether any effect is enabled.
+    ///
+    /// Returns the result of the check.
+    pub fn is_enabled(&self) -> bool
+    {
+        self.low_pass.is_some() || self.echo.is_some()
+    }
+}
+
+impl Processor
+{
+    /// Returns the effects being applied.
+    pub(super) fn effects(&self) -> Effects
+    {
+        self.effects
+    }
+
+    /// Replaces the effects being applied, clearing the state of the previous
+    /// ones.
+    ///
+    /// * `effects`: Effects to apply.
+    pub(super) fn configure(&mut self, effects: Effects)
+    {
+        let omega = 2.0 * PI * effects.low_pass.unwrap_or(0.0);
+        let len = effects.echo
+                         .map_or(0, |echo| (echo.delay * SMPL_RATE / 1000 / 4).max(1) as usize);
+        *self = Self { effects,
+                       alpha: omega / (omega + SMPL_RATE as f32),
+                       last: f32x4::splat(0.0),
+                       left: vec![f32x4::splat(0.0); len],
+                       right: vec![f32x4::splat(0.0); len],
+                       pos: 0 };
+    }
+
+    /// Applies the effects to a vector of samples of each channel.
+    ///
+    /// * `left`: Left channel samples.
+    /// * `right`: Right channel samples.
+    ///
+    /// Returns the processed samples of each channel.
+    pub(super) fn process(&mut self, left: f32x4, right: f32x4) -> (f32x4, f32x4)
+    {
+        let (mut left, mut right) = (left, right);
+        if let Some(echo) = self.effects.echo {
+            let (feedback, mix) = (f32x4::splat(echo.feedback), f32x4::splat(echo.mix));
+            let (dleft, dright) = (self.left[self.pos], self.right[self.pos]);
+            self.left[self.pos] = left.fused_mul_add(dleft, feedback);
+            self.right[self.pos] = right.fused_mul_add(dright, feedback);
+            self.pos = (self.pos + 1) % self.left.len();
+            left = left.fused_mul_add(dleft, mix);
+            right = right.fused_mul_add(dright, mix);
+        }
+        if self.effects.low_pass.is_some() {
+            let alpha = f32x4::splat(self.alpha);
+            let mut out = [[0.0; 4]; 2];
+            for idx in 0 .. 4 {
+                let input = f32x4::from_array([left[idx], right[idx], 0.0, 0.0]);
+                self.last = self.last.fused_mul_add(input - self.last, alpha);
+                out[0][idx] = self.last[0];
+                out[1][idx] = self.last[1];
+            }
+            left = f32x4::from_array(out[0]);
+            right = f32x4::from_array(out[1]);
+        }
+        (left, right)
+    }
+}
@@ -0,0 +1,126 @@
+//! Sound events.
+//!
+//! Game systems emit semantic events at world positions without knowing
+//! anything about the synthesizer.  Events are queued on a bus that the audio
+//! task flushes before every buffer swap, resolving each event to a tone
+//! through a mapping table and panning it according to where it happened
+//! relative to the listening camera.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::mem::take;
+use core::simd::prelude::*;
+
+use super::Audio;
+use crate::math::Transform;
+use crate::simd::SimdFloatExtra;
+use crate::sync::Lock;
+
+/// Global sound event bus.
+pub static SOUNDS: SoundBus = SoundBus::new();
+
+/// Tones played for each event, in hertz.
+const TONES: [(SoundEvent, u16); 5] = [(SoundEvent::DigComplete, 330),
+                                       (SoundEvent::CreatureHit, 110),
+                                       (SoundEvent::CreatureDeath, 55),
+                                       (SoundEvent::SpellCast, 660),
+                                       (SoundEvent::GoldDeposited, 880)];
+
+/// Sound event bus.
+#[derive(Debug)]
+pub struct SoundBus
+{
+    /// Events waiting to be played along with their world positions.
+    queue: Lock<Vec<(SoundEvent, f32x4)>>,
+    /// Camera to world transformation of the listener, if any.
+    listener: Lock<Option<Transform>>,
+}
+
+/// Something that happened in the game that should be heard.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SoundEvent
+{
+    /// An imp finished digging out a tile.
+    DigComplete,
+    /// A creature was hit.
+    CreatureHit,
+    /// A creature died.
+    CreatureDeath,
+    /// The keeper cast a spell.
+    SpellCast,
+    /// Gold was delivered to the treasury.
+    GoldDeposited,
+}
+
+impl SoundBus
+{
+    /// Creates and initializes a new empty sound event bus.
+    ///
+    /// Returns the newly created bus.
+    const fn new() -> Self
+    {
+        Self { queue: Lock::new(Vec::new()),
+               listener: Lock::new(None) }
+    }
+
+    /// Queues an event to be played.
+    ///
+    /// * `event`: Event to play.
+    /// * `pos`: World position where the event happened.
+    pub fn emit(&self, event: SoundEvent, pos: f32x4)
+    {
+        self.queue.lock().push((event, pos));
+    }
+
+    /// Moves the listener, or removes it so that events are no longer panned.
+    ///
+    /// * `cam`: Camera to world transformation of the listener.
+    pub fn set_listener(&self, cam: Option<Transform>)
+    {
+        *self.listener.lock() = cam;
+    }
+
+    /// Plays all the queued events.
+    ///
+    /// * `audio`: Audio driver to play the events with.
+    pub fn flush(&self, audio: &mut Audio)
+    {
+        let listener = *self.listener.lock();
+        for (event, pos) in take(&mut *self.queue.lock()) {
+            let pan = listener.map_or(0.0, |cam| pan(cam, pos));
+            audio.play_tone(event.tone(), pan);
+        }
+    }
+}
+
+impl SoundEvent
+{
+    /// Returns the frequency in hertz of the tone played for this event.
+    pub fn tone(self) -> u16
+    {
+        TONES.iter()
+             .find(|(event, _)| *event == self)
+             .map(|(_, tone)| *tone)
+             .unwrap()
+    }
+}
+
+/// Computes the stereo pan of a sound from the direction it comes from as seen
+/// by the listener.
+///
+/// * `cam`: Camera to world transformation of the listener.
+/// * `pos`: World position of the sound.
+///
+/// Returns the computed pan, from -1 for fully left to 1 for fully right.
+fn pan(cam: Transform, pos: f32x4) -> f32
+{
+    // Project the sound onto the camera's horizontal plane.
+    let rel = (pos - cam.position()) * cam.rotation().recip();
+    let rel = rel.xyz0().replace_lane::<1>(0.0);
+    let dist = rel.len();
+    if dist == 0.0 {
+        return 0.0;
+    }
+    (rel.x() / dist).clamp(-1.0, 1.0)
+}
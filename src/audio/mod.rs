@@ -0,0 +1,526 @@
+//! PWM audio driver.
+//!
+//! This code interfaces with five distinct peripherals: the PWM, a channel of
+//! the DMA controller, two GPIOs, a GP clock, and the interrupt controller.
+//! Since all of these peripherals, except for the interrupt controller for
+//! which I've already implemented a driver, are properly documented in the
+//! BCM2711 peripherals datasheet [1], I didn't have to read Linux code for
+//! once. !
+//! [1]: https://datasheets.raspberrypi.com/bcm2711/bcm2711-peripherals.pdf
+
+extern crate alloc;
+
+mod effects;
+mod events;
+mod sequencer;
+
+use alloc::boxed::Box;
+use core::future::Future;
+use core::hint::spin_loop;
+use core::pin::Pin;
+use core::simd::prelude::*;
+use core::sync::atomic::{fence, Ordering};
+use core::task::{Context, Poll};
+
+use self::effects::Processor;
+pub use self::effects::{Echo, Effects};
+pub use self::events::*;
+pub use self::sequencer::*;
+use crate::alloc::{Alloc, UNCACHED_REGION};
+use crate::dma::{Block, Chain, Channel, DMA};
+use crate::gpio::{Function, Pin as GpioPin, Pull, GPIO};
+use crate::prim::FloatExtra;
+use crate::settings::{Category, Volume, UNITY_GAIN};
+use crate::simd::SimdFloatExtra;
+use crate::sync::{critical, Lazy, Lock, Notify};
+use crate::wait::WaitId;
+use crate::{debug, to_dma, PERRY_RANGE};
+
+/// PWM data request signal.
+const PWM_DREQ: u8 = 1;
+/// Not sure what this register is supposed to be, but it must have a bit set in
+/// order to enable DMA DREQs for the PWM.
+const PACTL_CS: *mut u32 = (PERRY_RANGE.start + 0x2204E00) as _;
+/// Left channel PWM output pin.
+const LEFT_PIN: usize = 40;
+/// Right channel PWM output pin.
+const RIGHT_PIN: usize = 41;
+/// General purpose clock base address.
+const GPCLK_BASE: usize = PERRY_RANGE.start + 0x2101000;
+/// General purpose clock control register.
+const GPCLK_CTL: *mut u32 = (GPCLK_BASE + 0xA0) as _;
+/// General purpose clock divisor register.
+const GPCLK_DIV: *mut u32 = (GPCLK_BASE + 0xA4) as _;
+/// PWM base address.
+const PWM_BASE: usize = PERRY_RANGE.start + 0x220C800;
+/// PWM control register.
+const PWM_CTL: *mut u32 = PWM_BASE as _;
+/// PWM status register.
+const PWM_STAT: *mut u32 = (PWM_BASE + 0x4) as _;
+/// PWM DMA configuration.
+const PWM_DMAC: *mut u32 = (PWM_BASE + 0x8) as _;
+/// PWM range register for channel 0.
+const PWM_RNG0: *mut u32 = (PWM_BASE + 0x10) as _;
+/// PWM FIFO register.
+const PWM_FIFO: *mut u32 = (PWM_BASE + 0x18) as _;
+/// PWM range register for channel 1.
+const PWM_RNG1: *mut u32 = (PWM_BASE + 0x20) as _;
+/// Number of channels to sample.
+const SMPL_CHAN_COUNT: usize = 2;
+/// Number of audio samples per DMA buffer.
+const SMPL_BUF_LEN: usize = 1600 * SMPL_CHAN_COUNT;
+/// Sample bit depth.
+const SMPL_DEPTH: usize = 10;
+/// Sample rate.
+const SMPL_RATE: u32 = 48000;
+/// Clock rate.
+const CLOCK_RATE: u32 = 54000000;
+/// Maximum number of tones to process.
+const POLYPHONY: usize = 8;
+/// Minimum number of samples between underrun reports.
+const UNDERRUN_REPORT_PERIOD: u64 = SMPL_RATE as u64 * 5;
+
+/// Audio driver instance.
+pub static AUDIO: Lazy<Lock<Audio>> = Lazy::new(Audio::new);
+
+/// Uncached memory allocator.
+static UNCACHED: Alloc<0x40> = Alloc::with_region(&UNCACHED_REGION);
+
+/// Audio driver.
+pub struct Audio
+{
+    /// Audio buffer 0.
+    ab0: Box<[u32; SMPL_BUF_LEN], Alloc<'static, 0x40>>,
+    /// Audio buffer 1.
+    ab1: Box<[u32; SMPL_BUF_LEN], Alloc<'static, 0x40>>,
+    /// Time counter.
+    time: u64,
+    /// Scheduled tones.
+    tones: [Tone; POLYPHONY],
+    /// Volume controls applied while mixing.
+    volume: Volume,
+    /// Effects applied after mixing.
+    effects: Processor,
+    /// Tasks waiting for the next buffer swap.
+    swapped: Notify,
+    /// Whether the play tone commands have been committed.
+    did_commit: bool,
+    /// Whether a commit was attempted since the last buffer swap, or `None`
+    /// before the first attempt.
+    serviced: Option<bool>,
+    /// Number of buffer swaps that happened without a commit attempt.
+    underruns: u64,
+    /// Number of underruns at the last report.
+    reported: u64,
+    /// Time of the last underrun report.
+    report_time: u64,
+    /// DMA channel feeding the PWM.
+    chan: Channel,
+    /// PWM output pins.
+    _pins: [GpioPin; SMPL_CHAN_COUNT],
+}
+
+/// Tone scheduled to play for the duration of a buffer.
+#[derive(Clone, Copy, Debug, Default)]
+struct Tone
+{
+    /// Wave period in samples, or 0 if the slot is free.
+    period: u32,
+    /// Stereo pan.
+    pan: f32,
+    /// Amplitude relative to a full scale tone.
+    gain: f32,
+    /// Oscillator shape.
+    wave: Wave,
+    /// Volume control category.
+    category: Category,
+}
+
+/// Oscillator shape.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Wave
+{
+    /// Square wave.
+    #[default]
+    Square,
+    /// Triangle wave.
+    Triangle,
+    /// Sawtooth wave.
+    Sawtooth,
+}
+
+/// Future that that becomes ready at the next buffer swap.
+#[derive(Debug)]
+pub struct WillSwap
+{
+    /// Time at which this future was created.
+    time: u64,
+    /// Registration with the buffer swap notifier, if parked.
+    waiter: Option<WaitId>,
+}
+
+impl Audio
+{
+    /// Creates and initializes a new audio driver instance.
+    ///
+    /// Returns the newly created instance.
+    fn new() -> Lock<Self>
+    {
+        let mut chan = DMA.allocate().expect("No DMA channels available for audio");
+        chan.on_interrupt(Self::refill);
+        // Set up the GPIO.
+        let mut pins = [LEFT_PIN, RIGHT_PIN].map(|idx| GPIO.claim(idx).expect("Audio pin already claimed"));
+        for pin in &mut pins {
+            pin.set_function(Function::Alt0);
+            pin.set_pull(Pull::None);
+        }
+        // Set up a general purpose clock.
+        fence(Ordering::Acquire);
+        unsafe {
+            let val = GPCLK_CTL.read_volatile();
+            GPCLK_CTL.write_volatile(val & 0xFFFFEF | 0x5A000000);
+            while GPCLK_CTL.read_volatile() & 0x80 != 0 {
+                spin_loop();
+            }
+            GPCLK_CTL.write_volatile(0x5A000001);
+            GPCLK_DIV.write_volatile(0x5A002000);
+            GPCLK_CTL.write_volatile(0x5A000011);
+        }
+        fence(Ordering::Release);
+        // Set up the PWM.
+        unsafe {
+            PWM_CTL.write_volatile(0x2161);
+            PWM_RNG0.write_volatile(CLOCK_RATE / SMPL_RATE / 2);
+            PWM_RNG1.write_volatile(CLOCK_RATE / SMPL_RATE / 2);
+            PWM_STAT.write_volatile(0x13C);
+            PWM_DMAC.write_volatile(0x80000606);
+            fence(Ordering::Release);
+        }
+        // Set up the DMA controller.
+        let mut ab0 = Box::new_in([1 << (SMPL_DEPTH - 1); SMPL_BUF_LEN], UNCACHED);
+        let mut ab1 = Box::new_in([1 << (SMPL_DEPTH - 1); SMPL_BUF_LEN], UNCACHED);
+        let block = |buf: &mut [u32; SMPL_BUF_LEN]| {
+            let src = to_dma(buf.as_mut_ptr() as _);
+            Block::new(src, to_dma(PWM_FIFO as _), SMPL_BUF_LEN * 4).increment_src()
+                                                                    .wide_src()
+                                                                    .paced_dst(PWM_DREQ)
+                                                                    .no_wide_bursts()
+                                                                    .interrupt()
+        };
+        let chain = Chain::looping([block(&mut ab0), block(&mut ab1)]);
+        unsafe {
+            let val = PACTL_CS.read_volatile();
+            PACTL_CS.write_volatile(val | 0x800000);
+        }
+        fence(Ordering::Release);
+        chan.start(chain);
+        let this = Self { ab0,
+                          ab1,
+                          time: 0,
+                          tones: Default::default(),
+                          volume: Volume::default(),
+                          effects: Processor::default(),
+                          swapped: Notify::new(),
+                          did_commit: false,
+                          serviced: None,
+                          underruns: 0,
+                          reported: 0,
+                          report_time: 0,
+                          chan,
+                          _pins: pins };
+        Lock::new(this)
+    }
+
+    /// Adds a full scale square sound effect tone to the command queue,
+    /// ignoring it if maximum polyphony has already been reached.
+    ///
+    /// * `freq`: Frequency of the tone.
+    /// * `pan`: Stereo pan.
+    ///
+    /// Panics if the frequency is 0.
+    #[track_caller]
+    pub fn play_tone(&mut self, freq: u16, pan: f32)
+    {
+        self.play_note(freq, pan, 1.0, Wave::Square, Category::Sfx);
+    }
+
+    /// Adds a tone with a specific amplitude and shape to the command queue,
+    /// ignoring it if maximum polyphony has already been reached.
+    ///
+    /// * `freq`: Frequency of the tone.
+    /// * `pan`: Stereo pan.
+    /// * `gain`: Amplitude relative to a full scale tone.
+    /// * `wave`: Oscillator shape.
+    /// * `category`: Volume control category.
+    ///
+    /// Panics if the frequency is 0.
+    #[track_caller]
+    pub fn play_note(&mut self, freq: u16, pan: f32, gain: f32, wave: Wave, category: Category)
+    {
+        assert!(freq > 0, "Invalid zero frequency");
+        for tone in self.tones.iter_mut() {
+            if tone.period == 0 {
+                *tone = Tone { period: SMPL_RATE / freq as u32,
+                               pan,
+                               gain,
+                               wave,
+                               category };
+                break;
+            }
+        }
+    }
+
+    /// Returns the volume controls.
+    pub fn volume(&self) -> Volume
+    {
+        self.volume
+    }
+
+    /// Changes the volume controls, taking effect at the next commit.
+    ///
+    /// * `volume`: New volume controls.
+    pub fn set_volume(&mut self, volume: Volume)
+    {
+        self.volume = volume;
+    }
+
+    /// Replaces the effects applied after mixing, taking effect at the next
+    /// commit.
+    ///
+    /// * `effects`: New effects.
+    pub fn set_effects(&mut self, effects: Effects)
+    {
+        self.effects.configure(effects);
+    }
+
+    /// Commits all scheduled tones to be played at the next buffer swap.
+    ///
+    /// Returns a future that, when awaited on, blocks the task until the next
+    /// buffer swap.
+    pub fn commit(&mut self) -> WillSwap
+    {
+        let future = WillSwap::new(self.time);
+        self.serviced = Some(true);
+        self.report_underruns();
+        let ct = self.tones.iter().filter(|tone| tone.period > 0).count();
+        // Effects keep producing output after the last tone, such as the tail of an
+        // echo.
+        if self.did_commit || ct == 0 && !self.effects.effects().is_enabled() {
+            return future;
+        }
+        let buf = if self.inactive_buffer() == 0 {
+            &mut self.ab0[..]
+        } else {
+            &mut self.ab1[..]
+        };
+        let ict = f32x4::splat(ct.max(1) as f32).fast_recip();
+        let gains =
+            [Category::Music, Category::Sfx].map(|category| self.volume.gain(category) as f32 / UNITY_GAIN as f32);
+        let hamp = f32x4::splat((1 << (SMPL_DEPTH - 1)) as f32);
+        let one = f32x4::splat(1.0);
+        for time in (self.time .. self.time + (SMPL_BUF_LEN / SMPL_CHAN_COUNT) as u64).step_by(4) {
+            let samples = self.tones
+                              .iter()
+                              .map(|tone| Self::compute_sample(time, tone.period, tone.wave))
+                              .array_chunks::<POLYPHONY>()
+                              .next()
+                              .unwrap();
+            let left = Self::pan_mix(&self.tones, gains, samples, -1.0);
+            let right = Self::pan_mix(&self.tones, gains, samples, 1.0);
+            let (left, right) = self.effects.process(left * ict, right * ict);
+            let left = (left.simd_min(one).simd_max(-one) + one) * hamp;
+            let right = (right.simd_min(one).simd_max(-one) + one) * hamp;
+            // The audio jack is wired such that the first PWM channel plays on the right
+            // side, and the second PWM channel plays on the left side, so even indices are
+            // for the right channel, and odd indices are for the right channel.
+            let (first, second) = right.interleave(left);
+            let time = (time - self.time) as usize * SMPL_CHAN_COUNT;
+            first.cast::<u32>().copy_to_slice(&mut buf[time .. time + 4]);
+            second.cast::<u32>().copy_to_slice(&mut buf[time + 4 .. time + 8]);
+        }
+        self.tones = Default::default();
+        self.did_commit = true;
+        future
+    }
+
+    /// Returns the number of buffer swaps that happened before the tones to
+    /// play in the next buffer were committed, leaving it silent.
+    pub fn underruns(&self) -> u64
+    {
+        self.underruns
+    }
+
+    /// Logs the underruns since the last report, at most once per report
+    /// period.
+    fn report_underruns(&mut self)
+    {
+        if self.underruns == self.reported || self.time - self.report_time < UNDERRUN_REPORT_PERIOD {
+            return;
+        }
+        debug!("Audio underruns: {} new, {} total",
+               self.underruns - self.reported,
+               self.underruns);
+        self.reported = self.underruns;
+        self.report_time = self.time;
+    }
+
+    /// Stops playback by aborting the DMA transfer and disabling the PWM.
+    /// Playback does not resume afterwards.
+    pub fn stop(&mut self)
+    {
+        self.chan.stop();
+        unsafe {
+            PWM_DMAC.write_volatile(0x0);
+            PWM_CTL.write_volatile(0x0);
+        }
+        fence(Ordering::Release);
+    }
+
+    /// Computes a vector of samples starting at the specified time with the
+    /// specified period and shape.
+    ///
+    /// * `time`: Base time.
+    /// * `period`: Wave period.
+    /// * `wave`: Oscillator shape.
+    ///
+    /// Returns the computed vector of samples.
+    #[inline(always)]
+    fn compute_sample(time: u64, period: u32, wave: Wave) -> f32x4
+    {
+        if period == 0 {
+            return f32x4::splat(0.0);
+        }
+        let offset = u32x4::splat((time % period as u64) as u32);
+        let pos = u32x4::from_array([0, 1, 2, 3]);
+        let offset = (offset + pos) % u32x4::splat(period);
+        let half = f32x4::splat(0.5);
+        let phase = offset.cast::<f32>() / f32x4::splat(period as f32);
+        match wave {
+            Wave::Square => phase.simd_ge(half).select(half, -half),
+            Wave::Triangle => (phase * f32x4::splat(2.0) - f32x4::splat(1.0)).abs() - half,
+            Wave::Sawtooth => phase - half,
+        }
+    }
+
+    /// Pans and mixes a given array of vectors of samples into a single vector
+    /// of samples.
+    ///
+    /// * `tones`: Tones that produced the samples.
+    /// * `gains`: Music and sound effect volume gains.
+    /// * `samples`: Input samples.
+    /// * `bias`: Pan bias.
+    ///
+    /// Returns a mixed vector of samples with panning and volume applied.
+    #[inline(always)]
+    fn pan_mix(tones: &[Tone], gains: [f32; 2], samples: [f32x4; POLYPHONY], bias: f32) -> f32x4
+    {
+        let one = f32x4::splat(1.0);
+        tones.iter()
+             .enumerate()
+             .map(|(idx, tone)| {
+                 samples[idx].mul_scalar((tone.pan + bias).abs() * tone.gain * gains[tone.category as usize])
+             })
+             .map(|sample| sample.simd_min(one).simd_max(-one))
+             .array_chunks::<POLYPHONY>()
+             .next()
+             .unwrap()
+             .iter()
+             .array_chunks::<2>()
+             .map(|samples| Self::mix(*samples[0], *samples[1]))
+             .array_chunks::<2>()
+             .map(|samples| Self::mix(samples[0], samples[1]))
+             .array_chunks::<2>()
+             .map(|samples| Self::mix(samples[0], samples[1]))
+             .next()
+             .unwrap()
+    }
+
+    /// Mixes the respective lanes of two vectors of samples into a single
+    /// vector of samples.
+    ///
+    /// * `s0`: First vector of samples.
+    /// * `s1`: Second vector of samples.
+    ///
+    /// Returns the computed results.
+    #[inline(always)]
+    fn mix(s0: f32x4, s1: f32x4) -> f32x4
+    {
+        s0 + s1
+    }
+
+    /// Returns the index of the buffer not currently being read.
+    fn inactive_buffer(&self) -> u8
+    {
+        if self.chan.current_block() == Some(0) {
+            return 1;
+        }
+        0
+    }
+
+    /// Refills the buffer not currently in use with silence, counting an
+    /// underrun if nothing tried to commit to it since the previous swap.
+    fn refill()
+    {
+        unsafe { PWM_STAT.write_volatile(0x13C) };
+        fence(Ordering::Release);
+        let mut audio = AUDIO.lock();
+        let buf = if audio.inactive_buffer() == 0 {
+            &mut audio.ab0[..]
+        } else {
+            &mut audio.ab1[..]
+        };
+        buf.fill(1 << (SMPL_DEPTH - 1));
+        audio.time += (SMPL_BUF_LEN / SMPL_CHAN_COUNT) as u64;
+        audio.swapped.notify_all();
+        audio.did_commit = false;
+        if audio.serviced == Some(false) {
+            audio.underruns += 1;
+        }
+        audio.serviced = audio.serviced.and(Some(false));
+    }
+}
+
+impl WillSwap
+{
+    /// Creates and initialize a new will swap future.
+    ///
+    /// * `time`: Time at which this future was created.
+    ///
+    /// Returns the newly created future.
+    fn new(time: u64) -> Self
+    {
+        Self { time, waiter: None }
+    }
+}
+
+impl Future for WillSwap
+{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()>
+    {
+        // The lock is shared with the DMA IRQ handler.
+        let _critical = critical();
+        let audio = AUDIO.lock();
+        if audio.time != self.time {
+            if let Some(waiter) = self.waiter.take() {
+                audio.swapped.unregister(waiter);
+            }
+            return Poll::Ready(());
+        }
+        self.waiter = Some(audio.swapped.register(self.waiter, ctx.waker()));
+        Poll::Pending
+    }
+}
+
+impl Drop for WillSwap
+{
+    fn drop(&mut self)
+    {
+        let Some(waiter) = self.waiter else {
+            return;
+        };
+        let _critical = critical();
+        AUDIO.lock().swapped.unregister(waiter);
+    }
+}
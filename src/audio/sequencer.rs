@@ -0,0 +1,225 @@
+//! Music sequencer.
+//!
+//! Plays songs defined as data: a song is an order list of patterns played in
+//! a loop at a fixed tempo, each pattern holds notes starting at and lasting
+//! for a number of steps, and each note is voiced by an instrument made of an
+//! oscillator shape and an ADSR envelope [1].  The sequencer streams into the
+//! tone mixer once per buffer swap, so envelopes are evaluated at the middle
+//! of each buffer.
+//!
+//! [1]: https://en.wikipedia.org/wiki/Envelope_(music)#ADSR
+
+use super::{Audio, Wave, SMPL_BUF_LEN, SMPL_CHAN_COUNT, SMPL_RATE};
+use crate::settings::Category;
+use crate::sync::AsyncLock;
+
+/// Global music sequencer instance.
+pub static MUSIC: AsyncLock<Sequencer> = AsyncLock::new(Sequencer::new());
+
+/// Number of samples per channel played between buffer swaps.
+const SWAP_SAMPLES: u64 = (SMPL_BUF_LEN / SMPL_CHAN_COUNT) as u64;
+
+/// Song made of looping patterns.
+#[derive(Clone, Copy, Debug)]
+pub struct Song
+{
+    /// Tempo in steps per minute.
+    pub tempo: u32,
+    /// Instruments referenced by the notes.
+    pub instruments: &'static [Instrument],
+    /// Patterns referenced by the order list.
+    pub patterns: &'static [Pattern],
+    /// Indices of the patterns in the order in which they're played.
+    pub order: &'static [usize],
+}
+
+/// Sequence of notes.
+#[derive(Clone, Copy, Debug)]
+pub struct Pattern
+{
+    /// Length in steps.
+    pub steps: u32,
+    /// Notes in any order.
+    pub notes: &'static [Note],
+}
+
+/// Note in a pattern.
+#[derive(Clone, Copy, Debug)]
+pub struct Note
+{
+    /// Step at which the note starts.
+    pub step: u32,
+    /// Number of steps for which the note is held.
+    pub len: u32,
+    /// Frequency in hertz.
+    pub freq: u16,
+    /// Index of the instrument that plays the note.
+    pub instrument: usize,
+}
+
+/// Instrument voicing notes.
+#[derive(Clone, Copy, Debug)]
+pub struct Instrument
+{
+    /// Oscillator shape.
+    pub wave: Wave,
+    /// Amplitude envelope.
+    pub envelope: Envelope,
+    /// Stereo pan.
+    pub pan: f32,
+}
+
+/// ADSR amplitude envelope.
+#[derive(Clone, Copy, Debug)]
+pub struct Envelope
+{
+    /// Time in milliseconds to rise from silence to full amplitude.
+    pub attack: u32,
+    /// Time in milliseconds to fall from full amplitude to the sustain level.
+    pub decay: u32,
+    /// Amplitude while the note is held after decaying.
+    pub sustain: f32,
+    /// Time in milliseconds to fade out after the note is released.
+    pub release: u32,
+}
+
+/// Music sequencer.
+#[derive(Debug)]
+pub struct Sequencer
+{
+    /// Song being played, if any.
+    song: Option<&'static Song>,
+    /// Samples played since the song started.
+    time: u64,
+}
+
+impl Sequencer
+{
+    /// Creates and initializes a new idle sequencer.
+    ///
+    /// Returns the newly created sequencer.
+    const fn new() -> Self
+    {
+        Self { song: None, time: 0 }
+    }
+
+    /// Starts playing a song from the beginning, replacing the song being
+    /// played.
+    ///
+    /// * `song`: Song to play.
+    ///
+    /// Panics if the song has no tempo or steps to play.
+    #[track_caller]
+    pub fn play(&mut self, song: &'static Song)
+    {
+        let steps = song.order.iter().map(|&idx| song.patterns[idx].steps).sum::<u32>();
+        assert!(song.tempo > 0 && steps > 0, "Song has nothing to play");
+        self.song = Some(song);
+        self.time = 0;
+    }
+
+    /// Stops playing music.
+    pub fn stop(&mut self)
+    {
+        self.song = None;
+    }
+
+    /// Queues the notes sounding until the next buffer swap on the mixer and
+    /// advances the song.
+    ///
+    /// * `audio`: Audio driver to play the notes with.
+    pub fn stream(&mut self, audio: &mut Audio)
+    {
+        let Some(song) = self.song else {
+            return;
+        };
+        let step_len = SMPL_RATE as u64 * 60 / song.tempo as u64;
+        let loop_len = song.order
+                           .iter()
+                           .map(|&idx| song.patterns[idx].steps as u64 * step_len)
+                           .sum::<u64>();
+        let now = (self.time + SWAP_SAMPLES / 2) % loop_len;
+        self.time += SWAP_SAMPLES;
+        // Find the pattern playing and the time since it started, also checking the
+        // previous pattern for released notes still fading out.
+        let mut start = 0;
+        let mut prev = None;
+        for &idx in song.order.iter().cycle() {
+            let pattern = &song.patterns[idx];
+            let end = start + pattern.steps as u64 * step_len;
+            if now < end {
+                Self::voice(audio, song, pattern, now - start, step_len);
+                if let Some((prev, prev_len)) = prev {
+                    Self::voice(audio, song, prev, now - start + prev_len, step_len);
+                }
+                break;
+            }
+            prev = Some((pattern, end - start));
+            start = end;
+        }
+    }
+
+    /// Queues the notes of a pattern that are sounding at a given time.
+    ///
+    /// * `audio`: Audio driver to play the notes with.
+    /// * `song`: Song containing the pattern.
+    /// * `pattern`: Pattern to voice.
+    /// * `time`: Samples since the pattern started.
+    /// * `step_len`: Samples per step.
+    fn voice(audio: &mut Audio, song: &Song, pattern: &Pattern, time: u64, step_len: u64)
+    {
+        for note in pattern.notes {
+            let start = note.step as u64 * step_len;
+            if time < start {
+                continue;
+            }
+            let instrument = &song.instruments[note.instrument];
+            let gain = instrument.envelope
+                                 .gain(to_millis(time - start), to_millis(note.len as u64 * step_len));
+            if gain > 0.0 {
+                audio.play_note(note.freq, instrument.pan, gain, instrument.wave, Category::Music);
+            }
+        }
+    }
+}
+
+impl Envelope
+{
+    /// Computes the amplitude of a note at a given time.
+    ///
+    /// * `time`: Time in milliseconds since the note started.
+    /// * `held`: Time in milliseconds for which the note is held.
+    ///
+    /// Returns the computed amplitude.
+    pub fn gain(&self, time: u32, held: u32) -> f32
+    {
+        let level = |time: u32| {
+            if time < self.attack {
+                return time as f32 / self.attack as f32;
+            }
+            let time = time - self.attack;
+            if time < self.decay {
+                return 1.0 - (1.0 - self.sustain) * time as f32 / self.decay as f32;
+            }
+            self.sustain
+        };
+        if time < held {
+            return level(time);
+        }
+        let time = time - held;
+        if time >= self.release {
+            return 0.0;
+        }
+        level(held) * (1.0 - time as f32 / self.release as f32)
+    }
+}
+
+/// Converts a number of samples to milliseconds.
+///
+/// * `samples`: Number of samples to convert.
+///
+/// Returns the converted value.
+fn to_millis(samples: u64) -> u32
+{
+    (samples * 1000 / SMPL_RATE as u64) as u32
+}
@@ -0,0 +1,130 @@
+//! Board information.
+//!
+//! Queries the firmware once at boot for the properties that identify the
+//! board, such as its model, revision, MAC address, and serial number, as well
+//! as for the split of memory between the ARM cores and the video core, which
+//! varies with both the model and the firmware configuration.  The property
+//! tags are documented in the firmware wiki [1], and the revision codes are
+//! documented in the Raspberry Pi hardware documentation [2].
+//!
+//! [1]: https://github.com/raspberrypi/firmware/wiki/Mailbox-property-interface
+//! [2]: https://www.raspberrypi.com/documentation/computers/raspberry-pi.html#raspberry-pi-revision-codes
+
+use core::fmt::{Display, Formatter, Result as FormatResult};
+use core::ops::Range;
+
+use crate::mbox;
+use crate::sync::Lazy;
+
+/// Get firmware revision property tag.
+const GET_FIRMWARE_REVISION_TAG: u32 = 0x1;
+/// Get board model property tag.
+const GET_BOARD_MODEL_TAG: u32 = 0x10001;
+/// Get board revision property tag.
+const GET_BOARD_REVISION_TAG: u32 = 0x10002;
+/// Get board MAC address property tag.
+const GET_BOARD_MAC_TAG: u32 = 0x10003;
+/// Get board serial number property tag.
+const GET_BOARD_SERIAL_TAG: u32 = 0x10004;
+/// Get ARM memory property tag.
+const GET_ARM_MEMORY_TAG: u32 = 0x10005;
+/// Get video core memory property tag.
+const GET_VC_MEMORY_TAG: u32 = 0x10006;
+/// Revision code new style flag.
+const REV_NEW_STYLE: u32 = 0x800000;
+/// Revision code memory size shift.
+const REV_MEMORY_SHIFT: u32 = 20;
+/// Revision code memory size mask.
+const REV_MEMORY_MASK: u32 = 0x7;
+
+/// Board information queried at boot.
+pub static INFO: Lazy<Info> = Lazy::new(Info::query);
+
+/// Board information.
+#[derive(Clone, Debug)]
+pub struct Info
+{
+    /// Firmware revision.
+    pub firmware: u32,
+    /// Board model.
+    pub model: u32,
+    /// Board revision code.
+    pub revision: u32,
+    /// MAC address of the ethernet controller.
+    pub mac: [u8; 6],
+    /// Serial number.
+    pub serial: u64,
+    /// Physical memory range available to the ARM cores.
+    pub arm_memory: Range<usize>,
+    /// Physical memory range reserved for the video core.
+    pub vc_memory: Range<usize>,
+}
+
+impl Info
+{
+    /// Queries the firmware for the board information.
+    ///
+    /// Returns the queried information.
+    fn query() -> Self
+    {
+        let firmware: u32;
+        let model: u32;
+        let revision: u32;
+        let mac: [u8; 6];
+        let serial: [u32; 2];
+        let arm: [u32; 2];
+        let vc: [u32; 2];
+        mbox! {
+            GET_FIRMWARE_REVISION_TAG: _ => firmware,
+            GET_BOARD_MODEL_TAG: _ => model,
+            GET_BOARD_REVISION_TAG: _ => revision,
+            GET_BOARD_MAC_TAG: _ => mac,
+            GET_BOARD_SERIAL_TAG: _ => serial,
+            GET_ARM_MEMORY_TAG: _ => arm,
+            GET_VC_MEMORY_TAG: _ => vc,
+        };
+        let arm_memory = arm[0] as usize .. arm[0] as usize + arm[1] as usize;
+        let vc_memory = vc[0] as usize .. vc[0] as usize + vc[1] as usize;
+        Self { firmware,
+               model,
+               revision,
+               mac,
+               serial: (serial[1] as u64) << 32 | serial[0] as u64,
+               arm_memory,
+               vc_memory }
+    }
+
+    /// Returns the total amount of memory installed on the board in bytes, as
+    /// encoded in the revision code, or `None` if the revision code uses the
+    /// old style.
+    pub fn memory_size(&self) -> Option<usize>
+    {
+        if self.revision & REV_NEW_STYLE == 0 {
+            return None;
+        }
+        let code = self.revision >> REV_MEMORY_SHIFT & REV_MEMORY_MASK;
+        Some(0x10000000 << code)
+    }
+}
+
+impl Display for Info
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        let [m0, m1, m2, m3, m4, m5] = self.mac;
+        write!(fmt,
+               "Model: {}, revision: 0x{:X}, firmware: 0x{:X}, serial: 0x{:016X}, MAC: {m0:02X}:{m1:02X}:{m2:02X}:{m3:02X}:{m4:02X}:{m5:02X}, ARM memory: 0x{:X}..0x{:X}, VC memory: 0x{:X}..0x{:X}",
+               self.model,
+               self.revision,
+               self.firmware,
+               self.serial,
+               self.arm_memory.start,
+               self.arm_memory.end,
+               self.vc_memory.start,
+               self.vc_memory.end)?;
+        if let Some(size) = self.memory_size() {
+            write!(fmt, ", installed memory: {}MB", size >> 20)?;
+        }
+        Ok(())
+    }
+}
@@ -11,6 +11,8 @@ root_tt:
 .zero 0x1000
 static_tt:
 .zero 0x1000
+// Translation table of the cached range, extended by the memory map.
+.globl heap_tt
 heap_tt:
 .zero 0x1000
 perry_tt:
@@ -57,6 +59,10 @@ boot:
     msr vpidr_el2, x0
     mrs x0, mpidr_el1
     msr vmpidr_el2, x0
+    // Give EL1 access to all the performance counters.
+    mrs x0, pmcr_el0
+    ubfx x0, x0, #11, #5
+    msr mdcr_el2, x0
     mov x0, #0xc4
     msr spsr_el2, x0
     adr x0, start
@@ -138,7 +144,7 @@ boot:
     bl map
     mov x0, xzr
     mov x1, #32 << 20
-    mov x2, #960 << 20 // 1024MB minus 32MB for the VC minus 32MB reserved for static code and data growth.
+    mov x2, #64 << 20 // Only enough to boot, with the rest mapped once the memory split is known.
     mov x3, #0x30 << 48
     movk x3, #0x721
     adrp x4, heap_tt
@@ -1,26 +1,76 @@
 //! System timer driver.
 //!
-//! Provides time information backed by the system timer as described in the
-//! BCM2711 peripherals datasheet [1].  The clock frequency was obtained by
-//! following the device tree source includes for the Raspberry Pi 4 B in the
-//! Linux source code [2].
+//! Provides time information and a one-shot alarm backed by the system timer as
+//! described in the BCM2711 peripherals datasheet [1].  The clock frequency was
+//! obtained by following the device tree source includes for the Raspberry Pi 4
+//! B in the Linux source code [2].  Of the four compare channels, 0 and 2 are
+//! used by the Video Core firmware, so the alarm uses channel 1.
 //!
 //! [1]: https://datasheets.raspberrypi.com/bcm2711/bcm2711-peripherals.pdf
 //! [2]: https://github.com/raspberrypi/linux/blob/rpi-5.15.y/arch/arm/boot/dts/bcm283x.dtsi
 
 use crate::PERRY_RANGE;
 
+/// IRQ triggered when the alarm goes off.
+pub const ALARM_IRQ: u32 = 97;
+
 /// System timer base address.
 const BASE: usize = PERRY_RANGE.start + 0x2003000;
+/// System timer control and status register.
+const CS: *mut u32 = BASE as _;
 /// System timer current time lower 32 bit register.
 const CLO: *const u32 = (BASE + 0x4) as _;
 /// System timer current time higher 32 bit register.
 const CHI: *const u32 = (BASE + 0x8) as _;
+/// System timer compare 1 register.
+const C1: *mut u32 = (BASE + 0x10) as _;
+/// Compare 1 match flag in the control and status register.
+const CS_M1: u32 = 0x2;
 /// System timer frequency.
 const FREQ: u64 = 1000000;
+/// Minimum time in microseconds between arming the alarm and it going off,
+/// which prevents the counter from overtaking the compare value while it's
+/// being written.
+const ALARM_MIN_DELAY: u64 = 50;
+/// Maximum time in microseconds between arming the alarm and it going off,
+/// kept well within the range of the 32 bit compare register.
+const ALARM_MAX_DELAY: u64 = 0x80000000;
 
 /// Returns the current system time in milliseconds.
 pub fn now() -> u64
 {
-    unsafe { (((CHI.read_volatile() as u64) << 32) | CLO.read_volatile() as u64) / (FREQ / 1000) }
+    now_micros() / 1000
+}
+
+/// Returns the current system time in microseconds.
+pub fn now_micros() -> u64
+{
+    unsafe { (((CHI.read_volatile() as u64) << 32) | CLO.read_volatile() as u64) / (FREQ / 1000000) }
+}
+
+/// Arms the alarm to go off at the specified time, replacing any previous
+/// deadline.  Deadlines that are too close or too far in the future are clamped
+/// to the range supported by the hardware, so the alarm may go off earlier than
+/// requested, in which case it's up to the caller to arm it again.
+///
+/// * `deadline`: System time in microseconds at which to trigger the alarm.
+pub fn set_alarm(deadline: u64)
+{
+    let now = now_micros();
+    let deadline = deadline.clamp(now + ALARM_MIN_DELAY, now + ALARM_MAX_DELAY);
+    unsafe { C1.write_volatile((deadline * (FREQ / 1000000)) as u32) };
+}
+
+/// Acknowledges the alarm.
+///
+/// Returns whether the alarm had gone off.
+pub fn clear_alarm() -> bool
+{
+    unsafe {
+        if CS.read_volatile() & CS_M1 == 0 {
+            return false;
+        }
+        CS.write_volatile(CS_M1);
+    }
+    true
 }
@@ -3,16 +3,23 @@
 //! Contains several utilities to control CPU features used throughout the
 //! project.
 
+mod perf;
+
 use core::arch::asm;
 use core::cmp::min;
-use core::mem::size_of_val;
+use core::iter::StepBy;
+use core::ops::Range;
 use core::sync::atomic::{compiler_fence, Ordering};
 
+pub use self::perf::Perf;
 use crate::clock::now;
 use crate::sync::Lock;
 
 /// Number of logical CPUs in the system.
 pub const COUNT: usize = 4;
+/// Logical CPU reserved for latency-critical tasks, which is kept free of long
+/// rendering work.
+pub const RESERVED: usize = COUNT - 1;
 
 /// Size of a cache line.
 const CACHELINE_SIZE: usize = 64;
@@ -90,69 +97,72 @@ pub fn sleep()
     LOAD.idle_since(start);
 }
 
-/// Invalidates the cache associated with the specified data to point of
-/// coherence, effectively purging the data object from cache without writing it
-/// out to memory.  Other objects sharing the same initial or final cache lines
-/// as the object being purged will have their contents restored at the end of
-/// this operation.
+/// Cleans the data cache lines covering the specified address range to point
+/// of coherence, writing any modifications out to memory so that they become
+/// visible to other bus masters.
 ///
-/// * `data`: Data object to purge from cache.
-pub fn invalidate_cache<T: Copy>(data: &mut T)
+/// * `range`: Address range to clean.
+pub fn clean(range: Range<usize>)
 {
-    let size = size_of_val(data);
-    if size == 0 {
-        return;
-    }
-    let start = data as *mut T as usize;
-    let end = data as *mut T as usize + size;
-    let algn_start = start & !(CACHELINE_SIZE - 1);
-    let algn_end = (end + (CACHELINE_SIZE - 1)) & !(CACHELINE_SIZE - 1);
-    // Save the first and last cache lines.
-    let start_cl = unsafe { *(algn_start as *const [u8; CACHELINE_SIZE]) };
-    let end_cl = unsafe { *((algn_end - CACHELINE_SIZE) as *const [u8; CACHELINE_SIZE]) };
-    // Invalidate the cache.
     compiler_fence(Ordering::Release);
     unsafe { asm!("dsb sy", options(nomem, nostack, preserves_flags)) };
-    for addr in (algn_start .. algn_end).step_by(CACHELINE_SIZE) {
-        unsafe { asm!("dc ivac, {addr}", addr = in (reg) addr, options (preserves_flags)) };
+    for addr in lines(&range) {
+        unsafe { asm!("dc cvac, {addr}", addr = in (reg) addr, options (nostack, preserves_flags)) };
     }
     unsafe { asm!("dsb sy", options(nomem, nostack, preserves_flags)) };
-    compiler_fence(Ordering::Acquire);
-    // Restore the parts of the first and last cachelines shared with this data
-    // object.
-    if algn_start != start {
-        let count = start - algn_start;
-        unsafe {
-            (algn_start as *mut u8).copy_from_nonoverlapping(&start_cl[0], count);
-        }
-    }
-    if algn_end != end {
-        let count = algn_end - end;
-        let idx = CACHELINE_SIZE - count;
-        unsafe {
-            (end as *mut u8).copy_from_nonoverlapping(&end_cl[idx], count);
+}
+
+/// Invalidates the data cache lines covering the specified address range to
+/// point of coherence, discarding their contents so that subsequent reads
+/// fetch data written to memory by other bus masters.  Cache lines that are
+/// only partially covered by the range are cleaned before being invalidated, so
+/// modifications to data sharing them with the range are preserved.
+///
+/// * `range`: Address range to invalidate.
+pub fn invalidate(range: Range<usize>)
+{
+    compiler_fence(Ordering::Release);
+    unsafe { asm!("dsb sy", options(nomem, nostack, preserves_flags)) };
+    for addr in lines(&range) {
+        if addr < range.start || addr + CACHELINE_SIZE > range.end {
+            unsafe { asm!("dc civac, {addr}", addr = in (reg) addr, options (nostack, preserves_flags)) };
+        } else {
+            unsafe { asm!("dc ivac, {addr}", addr = in (reg) addr, options (nostack, preserves_flags)) };
         }
     }
+    unsafe { asm!("dsb sy", options(nomem, nostack, preserves_flags)) };
+    compiler_fence(Ordering::Acquire);
 }
 
-/// Cleans up the cache associated with the specified data object, effectively
-/// flushing its contents to main memory.
+/// Cleans and invalidates the data cache lines covering the specified address
+/// range to point of coherence, writing any modifications out to memory and
+/// then discarding them from the cache.
 ///
-/// * `data`: Data object to flush.
-pub fn cleanup_cache<T: Copy>(data: &T)
+/// * `range`: Address range to clean and invalidate.
+pub fn clean_invalidate(range: Range<usize>)
 {
-    let size = size_of_val(data);
-    if size == 0 {
-        return;
-    }
-    let start = data as *const T as usize & !(CACHELINE_SIZE - 1);
-    let end = (data as *const T as usize + size + (CACHELINE_SIZE - 1)) & !(CACHELINE_SIZE - 1);
     compiler_fence(Ordering::Release);
     unsafe { asm!("dsb sy", options(nomem, nostack, preserves_flags)) };
-    for addr in (start .. end).step_by(CACHELINE_SIZE) {
-        unsafe { asm!("dc cvac, {addr}", addr = in (reg) addr, options (nomem, nostack, preserves_flags)) };
+    for addr in lines(&range) {
+        unsafe { asm!("dc civac, {addr}", addr = in (reg) addr, options (nostack, preserves_flags)) };
     }
     unsafe { asm!("dsb sy", options(nomem, nostack, preserves_flags)) };
+    compiler_fence(Ordering::Acquire);
+}
+
+/// Computes the base addresses of the cache lines covering an address range.
+///
+/// * `range`: Address range to cover.
+///
+/// Returns an iterator over the base addresses.
+fn lines(range: &Range<usize>) -> StepBy<Range<usize>>
+{
+    if range.is_empty() {
+        return (0 .. 0).step_by(CACHELINE_SIZE);
+    }
+    let start = range.start & !(CACHELINE_SIZE - 1);
+    let end = (range.end + (CACHELINE_SIZE - 1)) & !(CACHELINE_SIZE - 1);
+    (start .. end).step_by(CACHELINE_SIZE)
 }
 
 /// Returns the ID of the calling logical CPU.
@@ -0,0 +1,135 @@
+//! Performance monitoring unit.
+//!
+//! Exposes the cycle counter along with event counters for retired
+//! instructions, level 1 data cache refills, and mispredicted branches, as
+//! described in the ARM Architecture Reference Manual [1] and the Cortex-A72
+//! Technical Reference Manual [2].  Counters are private to each logical CPU,
+//! so code being measured must not migrate, which for tasks means that it must
+//! not await.
+//!
+//! [1]: https://developer.arm.com/documentation/ddi0487/latest
+//! [2]: https://developer.arm.com/documentation/100095/0003
+
+use core::arch::asm;
+use core::fmt::{Display, Formatter, Result as FormatResult};
+
+/// Retired instructions event.
+const INST_RETIRED: u64 = 0x08;
+/// Level 1 data cache refill event.
+const L1D_CACHE_REFILL: u64 = 0x03;
+/// Mispredicted branch event.
+const BR_MIS_PRED: u64 = 0x10;
+/// Counter enable mask covering the cycle counter and the first three event
+/// counters.
+const COUNTERS: u64 = 0x80000007;
+
+/// Performance counter values, either as a snapshot or as the difference
+/// between two snapshots.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Perf
+{
+    /// Elapsed CPU cycles.
+    pub cycles: u64,
+    /// Retired instructions.
+    pub instructions: u64,
+    /// Level 1 data cache refills.
+    pub cache_misses: u64,
+    /// Mispredicted branches.
+    pub branch_misses: u64,
+}
+
+impl Perf
+{
+    /// Enables the performance counters of the calling logical CPU, if they
+    /// weren't already, and takes a snapshot of their values.
+    ///
+    /// Returns the snapshot.
+    pub fn start() -> Self
+    {
+        unsafe {
+            asm!(
+                "msr pmevtyper0_el0, {inst}",
+                "msr pmevtyper1_el0, {refill}",
+                "msr pmevtyper2_el0, {mispred}",
+                "msr pmccfiltr_el0, xzr",
+                "mrs {tmp}, pmcr_el0",
+                "orr {tmp}, {tmp}, #0x41", // Enable with a 64 bit cycle counter.
+                "msr pmcr_el0, {tmp}",
+                "msr pmcntenset_el0, {counters}",
+                "isb",
+                inst = in (reg) INST_RETIRED,
+                refill = in (reg) L1D_CACHE_REFILL,
+                mispred = in (reg) BR_MIS_PRED,
+                counters = in (reg) COUNTERS,
+                tmp = out (reg) _,
+                options (nomem, nostack, preserves_flags));
+        }
+        Self::read()
+    }
+
+    /// Measures the counter increments since a snapshot.
+    ///
+    /// Returns the difference between the current counter values and those in
+    /// the snapshot.
+    pub fn stop(self) -> Self
+    {
+        let now = Self::read();
+        // Event counters are only 32 bits wide.
+        let delta = |now: u64, then: u64| (now as u32).wrapping_sub(then as u32) as u64;
+        Self { cycles: now.cycles.wrapping_sub(self.cycles),
+               instructions: delta(now.instructions, self.instructions),
+               cache_misses: delta(now.cache_misses, self.cache_misses),
+               branch_misses: delta(now.branch_misses, self.branch_misses) }
+    }
+
+    /// Returns the average number of instructions retired per cycle.
+    pub fn ipc(&self) -> f32
+    {
+        if self.cycles == 0 {
+            return 0.0;
+        }
+        self.instructions as f32 / self.cycles as f32
+    }
+
+    /// Takes a snapshot of the counters of the calling logical CPU.
+    ///
+    /// Returns the snapshot.
+    fn read() -> Self
+    {
+        let cycles: u64;
+        let instructions: u64;
+        let cache_misses: u64;
+        let branch_misses: u64;
+        unsafe {
+            asm!(
+                "isb",
+                "mrs {cycles}, pmccntr_el0",
+                "mrs {inst}, pmevcntr0_el0",
+                "mrs {refill}, pmevcntr1_el0",
+                "mrs {mispred}, pmevcntr2_el0",
+                cycles = out (reg) cycles,
+                inst = out (reg) instructions,
+                refill = out (reg) cache_misses,
+                mispred = out (reg) branch_misses,
+                options (nomem, nostack, preserves_flags));
+        }
+        Self { cycles,
+               instructions,
+               cache_misses,
+               branch_misses }
+    }
+}
+
+impl Display for Perf
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        write!(fmt,
+               "{} cycles, {} instructions ({:.2} IPC), {} cache misses, {} branch misses",
+               self.cycles,
+               self.instructions,
+               self.ipc(),
+               self.cache_misses,
+               self.branch_misses)
+    }
+}
@@ -0,0 +1,107 @@
+//! Display detection.
+//!
+//! Queries the firmware for the connected displays at boot so that a single
+//! binary can drive either the official 7" DSI touchscreen or an HDMI monitor.
+//! When both are connected the DSI touchscreen is preferred, unless the `hdmi`
+//! build option is provided, in which case HDMI is preferred instead.  The
+//! firmware display identifiers can be found in the Linux kernel source [1].
+//!
+//! [1]: https://github.com/raspberrypi/linux/blob/rpi-5.15.y/drivers/gpu/drm/vc4/vc4_firmware_kms.c
+
+use crate::sync::Lazy;
+use crate::{mbox, PERRY_RANGE};
+
+/// Get number of displays property tag.
+const GET_NUM_DISPLAYS_TAG: u32 = 0x40013;
+/// Get display identifier property tag.
+const GET_DISPLAY_ID_TAG: u32 = 0x40016;
+/// Firmware identifier of the DSI display.
+const DSI_ID: u32 = 0;
+/// Firmware identifier of the first HDMI display.
+const HDMI_ID: u32 = 2;
+
+/// Detected display.
+pub static DISPLAY: Lazy<Display> = Lazy::new(Display::detect);
+
+/// Supported display.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Display
+{
+    /// Official 7" DSI touchscreen.
+    Dsi,
+    /// 1080p HDMI monitor connected to the first HDMI port.
+    Hdmi,
+}
+
+impl Display
+{
+    /// Queries the firmware for the connected displays and picks one.
+    ///
+    /// Returns the picked display.
+    ///
+    /// Panics if no supported display is connected.
+    #[track_caller]
+    fn detect() -> Self
+    {
+        let count: u32;
+        mbox! {GET_NUM_DISPLAYS_TAG: _ => count};
+        let (mut dsi, mut hdmi) = (false, false);
+        for idx in 0 .. count {
+            let id: u32;
+            mbox! {GET_DISPLAY_ID_TAG: idx => id};
+            dsi |= id == DSI_ID;
+            hdmi |= id == HDMI_ID;
+        }
+        match (dsi, hdmi) {
+            (true, true) if cfg!(hdmi) => Self::Hdmi,
+            (true, _) => Self::Dsi,
+            (false, true) => Self::Hdmi,
+            (false, false) => panic!("No supported display connected"),
+        }
+    }
+
+    /// Returns the width of this display in pixels.
+    pub fn width(self) -> usize
+    {
+        match self {
+            Self::Dsi => 800,
+            Self::Hdmi => 1920,
+        }
+    }
+
+    /// Returns the height of this display in pixels.
+    pub fn height(self) -> usize
+    {
+        match self {
+            Self::Dsi => 480,
+            Self::Hdmi => 1080,
+        }
+    }
+
+    /// Returns the firmware identifier of this display.
+    pub fn id(self) -> u8
+    {
+        match self {
+            Self::Dsi => DSI_ID as _,
+            Self::Hdmi => HDMI_ID as _,
+        }
+    }
+
+    /// Returns the IRQ of the pixel valve feeding this display.
+    pub fn pixel_valve_irq(self) -> u32
+    {
+        match self {
+            Self::Dsi => 142,
+            Self::Hdmi => 133,
+        }
+    }
+
+    /// Returns the base address of the pixel valve feeding this display.
+    pub fn pixel_valve_base(self) -> usize
+    {
+        match self {
+            Self::Dsi => 0x2207000 + PERRY_RANGE.start,
+            Self::Hdmi => 0x220A000 + PERRY_RANGE.start,
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! DMA buffers.
+//!
+//! Provides buffers in cached memory that can be lent to other bus masters,
+//! taking care of the cache maintenance required to keep both views of the
+//! data coherent.  Buffers are aligned to and padded to a multiple of the cache
+//! line size, so maintenance operations never affect unrelated data.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::mem::size_of;
+use core::ops::{Deref, DerefMut, Range};
+
+use crate::alloc::{Alloc, CACHED_REGION};
+use crate::cpu::{clean_invalidate, invalidate};
+use crate::to_dma;
+
+/// Cache line aligned cached memory allocator.
+static CACHED: Alloc<0x40> = Alloc::with_region(&CACHED_REGION);
+
+/// Buffer shared with other bus masters.
+#[derive(Debug)]
+pub struct DmaBuffer<T>
+{
+    /// Buffer contents.
+    data: Box<Lines<T>, Alloc<'static, 0x40>>,
+}
+
+/// Lease of a buffer to other bus masters, during which the buffer cannot be
+/// accessed by the CPU.
+#[derive(Debug)]
+pub struct Lease<'a, T>
+{
+    /// Leased buffer.
+    buf: &'a mut DmaBuffer<T>,
+}
+
+/// Buffer lent to other bus masters by value, for transfers that outlive the
+/// scope that started them.  The CPU cannot access the buffer until the loan
+/// is repaid.
+#[derive(Debug)]
+pub struct Loan<T>
+{
+    /// Lent buffer.
+    buf: DmaBuffer<T>,
+}
+
+/// Wrapper that aligns and pads its contents to the cache line size.
+#[repr(C, align(0x40))]
+#[derive(Debug)]
+struct Lines<T>(T);
+
+impl<T> DmaBuffer<T>
+{
+    /// Creates and initializes a new buffer.
+    ///
+    /// * `val`: Initial contents.
+    ///
+    /// Returns the newly created buffer.
+    pub fn new(val: T) -> Self
+    {
+        Self { data: Box::new_in(Lines(val), CACHED) }
+    }
+
+    /// Lends this buffer to other bus masters, writing its contents out to
+    /// memory and evicting them from the cache.  Any data written by other bus
+    /// masters becomes visible once the lease is dropped.
+    ///
+    /// Returns the lease.
+    pub fn lend(&mut self) -> Lease<'_, T>
+    {
+        clean_invalidate(self.range());
+        Lease { buf: self }
+    }
+
+    /// Lends this buffer to other bus masters by value, writing its contents
+    /// out to memory and evicting them from the cache.  Any data written by
+    /// other bus masters becomes visible once the loan is repaid.
+    ///
+    /// Returns the loan.
+    pub fn into_loan(self) -> Loan<T>
+    {
+        clean_invalidate(self.range());
+        Loan { buf: self }
+    }
+
+    /// Returns the virtual address range covered by this buffer, including
+    /// padding.
+    fn range(&self) -> Range<usize>
+    {
+        let start = &*self.data as *const Lines<T> as usize;
+        start .. start + size_of::<Lines<T>>()
+    }
+}
+
+impl<T> Deref for DmaBuffer<T>
+{
+    type Target = T;
+
+    fn deref(&self) -> &T
+    {
+        &self.data.0
+    }
+}
+
+impl<T> DerefMut for DmaBuffer<T>
+{
+    fn deref_mut(&mut self) -> &mut T
+    {
+        &mut self.data.0
+    }
+}
+
+impl<'a, T> Lease<'a, T>
+{
+    /// Returns the address of the leased buffer from the perspective of the DMA
+    /// controller.
+    pub fn addr(&self) -> usize
+    {
+        to_dma(self.buf.range().start)
+    }
+}
+
+impl<'a, T> Drop for Lease<'a, T>
+{
+    fn drop(&mut self)
+    {
+        // Discard anything speculatively loaded into the cache during the lease.
+        invalidate(self.buf.range());
+    }
+}
+
+impl<T> Loan<T>
+{
+    /// Returns the address of the lent buffer from the perspective of the DMA
+    /// controller.
+    pub fn addr(&self) -> usize
+    {
+        to_dma(self.buf.range().start)
+    }
+
+    /// Returns the buffer to the CPU, which must only happen once other bus
+    /// masters are done with it.
+    ///
+    /// Returns the buffer.
+    pub fn repay(self) -> DmaBuffer<T>
+    {
+        invalidate(self.buf.range());
+        self.buf
+    }
+}
@@ -0,0 +1,283 @@
+//! Control block chains.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use crate::alloc::{Alloc, UNCACHED_REGION};
+use crate::to_dma;
+
+/// Transfer information interrupt enable flag.
+const TI_INTEN: u32 = 0x1;
+/// Transfer information 2D mode flag.
+const TI_TDMODE: u32 = 0x2;
+/// Transfer information wait for write responses flag.
+const TI_WAIT_RESP: u32 = 0x8;
+/// Transfer information destination address increment flag.
+const TI_DEST_INC: u32 = 0x10;
+/// Transfer information 128 bit destination writes flag.
+const TI_DEST_WIDTH: u32 = 0x20;
+/// Transfer information destination pacing flag.
+const TI_DEST_DREQ: u32 = 0x40;
+/// Transfer information source address increment flag.
+const TI_SRC_INC: u32 = 0x100;
+/// Transfer information 128 bit source reads flag.
+const TI_SRC_WIDTH: u32 = 0x200;
+/// Transfer information source pacing flag.
+const TI_SRC_DREQ: u32 = 0x400;
+/// Transfer information peripheral mapping shift.
+const TI_PERMAP_SHIFT: u32 = 16;
+/// Transfer information no wide bursts flag.
+const TI_NO_WIDE_BURSTS: u32 = 0x4000000;
+
+/// Uncached memory allocator.
+static UNCACHED: Alloc<0x40> = Alloc::with_region(&UNCACHED_REGION);
+
+/// Builder of a single DMA transfer.
+#[derive(Clone, Copy, Debug)]
+pub struct Block
+{
+    /// Control block under construction.
+    cb: ControlBlock,
+}
+
+/// Chain of control blocks ready to be executed by a DMA channel.
+#[derive(Debug)]
+pub struct Chain
+{
+    /// Linked control blocks.
+    cbs: Vec<ControlBlock, Alloc<'static, 0x40>>,
+}
+
+/// Control block.
+#[repr(align(0x20), C)]
+#[derive(Clone, Copy, Debug)]
+struct ControlBlock
+{
+    /// Transfer information.
+    ti: u32,
+    /// Source DMA address.
+    src: u32,
+    /// Destination DMA address.
+    dst: u32,
+    /// Data length.
+    len: u32,
+    /// 2D mode stride.
+    stride: u32,
+    /// DMA address of the next control block.
+    next: u32,
+    /// Unused 0.
+    _unused0: u32,
+    /// Unused 1.
+    _unused1: u32,
+}
+
+impl Block
+{
+    /// Creates and initializes a new unpaced transfer that neither increments
+    /// its source nor its destination addresses.
+    ///
+    /// * `src`: Source address from the perspective of the DMA controller.
+    /// * `dst`: Destination address from the perspective of the DMA controller.
+    /// * `len`: Length of the transfer in bytes.
+    ///
+    /// Returns the newly created transfer.
+    pub fn new(src: usize, dst: usize, len: usize) -> Self
+    {
+        let cb = ControlBlock { ti: TI_WAIT_RESP,
+                                src: src as _,
+                                dst: dst as _,
+                                len: len as _,
+                                stride: 0,
+                                next: 0,
+                                _unused0: 0,
+                                _unused1: 0 };
+        Self { cb }
+    }
+
+    /// Increments the source address after each read.
+    ///
+    /// Returns the modified transfer.
+    pub fn increment_src(mut self) -> Self
+    {
+        self.cb.ti |= TI_SRC_INC;
+        self
+    }
+
+    /// Increments the destination address after each write.
+    ///
+    /// Returns the modified transfer.
+    pub fn increment_dst(mut self) -> Self
+    {
+        self.cb.ti |= TI_DEST_INC;
+        self
+    }
+
+    /// Reads 128 bits at a time from the source.
+    ///
+    /// Returns the modified transfer.
+    pub fn wide_src(mut self) -> Self
+    {
+        self.cb.ti |= TI_SRC_WIDTH;
+        self
+    }
+
+    /// Writes 128 bits at a time to the destination.
+    ///
+    /// Returns the modified transfer.
+    pub fn wide_dst(mut self) -> Self
+    {
+        self.cb.ti |= TI_DEST_WIDTH;
+        self
+    }
+
+    /// Paces reads by the data request signal of a peripheral.
+    ///
+    /// * `dreq`: Peripheral data request signal.
+    ///
+    /// Returns the modified transfer.
+    pub fn paced_src(mut self, dreq: u8) -> Self
+    {
+        self.cb.ti |= TI_SRC_DREQ | (dreq as u32) << TI_PERMAP_SHIFT;
+        self
+    }
+
+    /// Paces writes by the data request signal of a peripheral.
+    ///
+    /// * `dreq`: Peripheral data request signal.
+    ///
+    /// Returns the modified transfer.
+    pub fn paced_dst(mut self, dreq: u8) -> Self
+    {
+        self.cb.ti |= TI_DEST_DREQ | (dreq as u32) << TI_PERMAP_SHIFT;
+        self
+    }
+
+    /// Prevents the transfer from issuing wide bursts.
+    ///
+    /// Returns the modified transfer.
+    pub fn no_wide_bursts(mut self) -> Self
+    {
+        self.cb.ti |= TI_NO_WIDE_BURSTS;
+        self
+    }
+
+    /// Raises the channel's IRQ once the transfer completes.
+    ///
+    /// Returns the modified transfer.
+    pub fn interrupt(mut self) -> Self
+    {
+        self.cb.ti |= TI_INTEN;
+        self
+    }
+
+    /// Turns the transfer into a 2D transfer of multiple rows, with the length
+    /// specifying the size of each row.
+    ///
+    /// * `rows`: Number of rows to transfer.
+    /// * `src_stride`: Distance in bytes between the starts of consecutive
+    ///   source rows.
+    /// * `dst_stride`: Distance in bytes between the starts of consecutive
+    ///   destination rows.
+    ///
+    /// Returns the modified transfer.
+    ///
+    /// Panics if the number of rows is out of range, the row length doesn't
+    /// fit in 16 bits, or the gaps between rows don't fit in 16 bit signed
+    /// integers.
+    #[track_caller]
+    pub fn rows(mut self, rows: usize, src_stride: usize, dst_stride: usize) -> Self
+    {
+        assert!((1 ..= 0x4000).contains(&rows), "Invalid number of rows: {rows}");
+        let len = self.cb.len as usize;
+        assert!(len <= 0xFFFF,
+                "Row length of {len} bytes is too large for a 2D transfer");
+        let src_gap = src_stride as isize - len as isize;
+        let dst_gap = dst_stride as isize - len as isize;
+        assert!(i16::try_from(src_gap).is_ok() && i16::try_from(dst_gap).is_ok(),
+                "Strides of {src_stride} and {dst_stride} bytes are too large for a row of {len} bytes");
+        self.cb.ti |= TI_TDMODE;
+        self.cb.len = ((rows - 1) << 16 | len) as u32;
+        self.cb.stride = (dst_gap as u16 as u32) << 16 | src_gap as u16 as u32;
+        self
+    }
+}
+
+impl Chain
+{
+    /// Creates and initializes a new chain that runs the specified transfers
+    /// in order and then stops.
+    ///
+    /// * `blocks`: Transfers to run.
+    ///
+    /// Returns the newly created chain.
+    ///
+    /// Panics if there are no transfers.
+    #[track_caller]
+    pub fn new(blocks: impl IntoIterator<Item = Block>) -> Self
+    {
+        Self::link(blocks, false)
+    }
+
+    /// Creates and initializes a new chain that runs the specified transfers
+    /// in order forever.
+    ///
+    /// * `blocks`: Transfers to run.
+    ///
+    /// Returns the newly created chain.
+    ///
+    /// Panics if there are no transfers.
+    #[track_caller]
+    pub fn looping(blocks: impl IntoIterator<Item = Block>) -> Self
+    {
+        Self::link(blocks, true)
+    }
+
+    /// Returns the address of the first control block from the perspective of
+    /// the DMA controller.
+    pub fn addr(&self) -> usize
+    {
+        to_dma(self.cbs.as_ptr() as usize)
+    }
+
+    /// Looks up the transfer at an address.
+    ///
+    /// * `addr`: Control block address from the perspective of the DMA
+    ///   controller.
+    ///
+    /// Returns the index of the transfer, if the address belongs to this chain.
+    pub fn index_of(&self, addr: usize) -> Option<usize>
+    {
+        let offset = addr.checked_sub(self.addr())?;
+        let idx = offset / size_of::<ControlBlock>();
+        (offset % size_of::<ControlBlock>() == 0 && idx < self.cbs.len()).then_some(idx)
+    }
+
+    /// Copies and links transfers into uncached memory.
+    ///
+    /// * `blocks`: Transfers to link.
+    /// * `is_looping`: Whether the last transfer links back to the first.
+    ///
+    /// Returns the linked chain.
+    ///
+    /// Panics if there are no transfers.
+    #[track_caller]
+    fn link(blocks: impl IntoIterator<Item = Block>, is_looping: bool) -> Self
+    {
+        let mut cbs = Vec::new_in(UNCACHED);
+        cbs.extend(blocks.into_iter().map(|block| block.cb));
+        assert!(!cbs.is_empty(), "Attempted to create an empty DMA chain");
+        // The vector must not grow past this point, as that would invalidate the links.
+        let base = to_dma(cbs.as_ptr() as usize);
+        let count = cbs.len();
+        for (idx, cb) in cbs.iter_mut().enumerate() {
+            cb.next = match idx + 1 {
+                next if next < count => (base + next * size_of::<ControlBlock>()) as _,
+                _ if is_looping => base as _,
+                _ => 0,
+            };
+        }
+        Self { cbs }
+    }
+}
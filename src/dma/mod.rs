@@ -0,0 +1,311 @@
+//! DMA controller driver.
+//!
+//! Allocates the full channels of the DMA controller described in the BCM2711
+//! peripherals datasheet [1] to drivers, runs chains of control blocks built
+//! with [`Block`] and [`Chain`] on them, and reports the completion of
+//! transfers that request an interrupt both to an optional handler running in
+//! IRQ context and to any tasks awaiting on a [`Completion`].  Also provides
+//! buffers in cached memory that take care of the maintenance required to
+//! share them with the DMA controller.
+//!
+//! [1]: https://datasheets.raspberrypi.com/bcm2711/bcm2711-peripherals.pdf
+
+mod buffer;
+mod chain;
+
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{fence, AtomicU64, Ordering};
+use core::task::{Context, Poll};
+
+pub use self::buffer::{DmaBuffer, Loan};
+pub use self::chain::{Block, Chain};
+use crate::irq::{Config as IrqConfig, IRQ};
+use crate::sync::{critical, Lazy, Lock, Notify};
+use crate::wait::WaitId;
+use crate::PERRY_RANGE;
+
+/// DMA controller base address.
+const BASE: usize = PERRY_RANGE.start + 0x2007000;
+/// Distance between the registers of consecutive channels.
+const CHAN_STRIDE: usize = 0x100;
+/// Channel control and status register offset.
+const CHAN_CS: usize = 0x0;
+/// Channel control block address register offset.
+const CHAN_CONBLK_AD: usize = 0x4;
+/// Channel debug register offset.
+const CHAN_DEBUG: usize = 0x20;
+/// Number of channels with registers at regular offsets.
+const CHAN_COUNT: usize = 15;
+/// Channels available for allocation, which are the full channels not used by
+/// the firmware.
+const CHAN_MASK: u16 = 0x7E;
+/// IRQ of the first channel, with the rest following in order.
+const CHAN_IRQ_BASE: u32 = 112;
+/// Priority level of the channel IRQs, which is high enough for pending DMA
+/// completions to be handled ahead of pending vertical synchronization work.
+const CHAN_IRQ_PRIORITY: u8 = 7;
+/// Control and status active flag.
+const CS_ACTIVE: u32 = 0x1;
+/// Control and status end flag.
+const CS_END: u32 = 0x2;
+/// Control and status interrupt flag.
+const CS_INT: u32 = 0x4;
+/// Control and status AXI priority, which is the default.
+const CS_PRIORITY: u32 = 0x70000;
+/// Control and status AXI panic priority, which is the highest.
+const CS_PANIC_PRIORITY: u32 = 0xF00000;
+/// Control and status reset flag.
+const CS_RESET: u32 = 0x80000000;
+/// Debug register error flags.
+const DEBUG_ERRORS: u32 = 0x7;
+
+/// Global DMA controller driver instance.
+pub static DMA: Lazy<Dma> = Lazy::new(Dma::new);
+
+/// DMA controller driver.
+#[derive(Debug)]
+pub struct Dma
+{
+    /// Bit mask of the channels available for allocation.
+    free: Lock<u16>,
+    /// State of each channel.
+    chans: [State; CHAN_COUNT],
+}
+
+/// Allocated DMA channel, which is stopped and returned to the controller when
+/// dropped.
+#[derive(Debug)]
+pub struct Channel
+{
+    /// Channel index.
+    idx: usize,
+    /// Chain being run.
+    chain: Option<Chain>,
+}
+
+/// Future that completes on the next DMA interrupt raised by a channel.
+#[derive(Debug)]
+pub struct Completion
+{
+    /// Channel index.
+    idx: usize,
+    /// Number of interrupts raised by the channel when this future was
+    /// created.
+    count: u64,
+    /// Registration with the channel's completion notifier, if parked.
+    waiter: Option<WaitId>,
+}
+
+/// Channel state shared with the IRQ handler.
+#[derive(Debug)]
+struct State
+{
+    /// Number of interrupts raised.
+    count: AtomicU64,
+    /// Handler to call in IRQ context when an interrupt is raised.
+    handler: Lock<Option<fn()>>,
+    /// Tasks waiting for the next interrupt.
+    done: Notify,
+}
+
+impl Dma
+{
+    /// Creates and initializes a new DMA controller driver.
+    ///
+    /// Returns the newly created driver.
+    fn new() -> Self
+    {
+        let config = IrqConfig::with_priority(CHAN_IRQ_PRIORITY);
+        for idx in (0 .. CHAN_COUNT).filter(|idx| CHAN_MASK & 0x1 << idx != 0) {
+            IRQ.register_with(CHAN_IRQ_BASE + idx as u32, config, move || DMA.interrupt(idx));
+        }
+        Self { free: Lock::new(CHAN_MASK),
+               chans: [const { State::new() }; CHAN_COUNT] }
+    }
+
+    /// Allocates a channel.
+    ///
+    /// Returns the allocated channel, or `None` if all channels are taken.
+    pub fn allocate(&self) -> Option<Channel>
+    {
+        let mut free = self.free.lock();
+        if *free == 0 {
+            return None;
+        }
+        let idx = free.trailing_zeros() as usize;
+        *free &= !(0x1 << idx);
+        drop(free);
+        let chan = Channel { idx, chain: None };
+        chan.reset();
+        Some(chan)
+    }
+
+    /// Channel IRQ handler.
+    ///
+    /// * `idx`: Channel index.
+    ///
+    /// Returns whether the channel had raised an interrupt.
+    fn interrupt(&self, idx: usize) -> bool
+    {
+        let cs = Channel::register(idx, CHAN_CS);
+        fence(Ordering::Acquire);
+        let val = unsafe { cs.read_volatile() };
+        if val & CS_INT == 0 {
+            return false;
+        }
+        // Preserve the active flag and priorities while acknowledging.
+        unsafe { cs.write_volatile(val | CS_INT | CS_END) };
+        fence(Ordering::Release);
+        let state = &self.chans[idx];
+        state.count.fetch_add(1, Ordering::Release);
+        let handler = *state.handler.lock();
+        if let Some(handler) = handler {
+            handler();
+        }
+        state.done.notify_all();
+        true
+    }
+}
+
+impl Channel
+{
+    /// Runs a chain on this channel, stopping any chain that was running
+    /// before.
+    ///
+    /// * `chain`: Chain to run, which is kept alive until it's stopped.
+    ///
+    /// Returns a future that completes on the next interrupt raised by this
+    /// channel.
+    pub fn start(&mut self, chain: Chain) -> Completion
+    {
+        self.stop();
+        let completion = self.completion();
+        let addr = chain.addr();
+        self.chain = Some(chain);
+        // Clear any stale end and interrupt flags while activating.
+        let val = CS_PANIC_PRIORITY | CS_PRIORITY | CS_INT | CS_END | CS_ACTIVE;
+        unsafe {
+            Self::register(self.idx, CHAN_CONBLK_AD).write_volatile(addr as _);
+            fence(Ordering::Release);
+            Self::register(self.idx, CHAN_CS).write_volatile(val);
+        }
+        fence(Ordering::Release);
+        completion
+    }
+
+    /// Aborts the running chain, if any.
+    ///
+    /// Returns the chain that was running.
+    pub fn stop(&mut self) -> Option<Chain>
+    {
+        self.reset();
+        self.chain.take()
+    }
+
+    /// Sets the handler to call in IRQ context whenever this channel raises an
+    /// interrupt, replacing any previous handler.
+    ///
+    /// * `handler`: Handler to call.
+    pub fn on_interrupt(&mut self, handler: fn())
+    {
+        // The lock is shared with the IRQ handler.
+        let _critical = critical();
+        *DMA.chans[self.idx].handler.lock() = Some(handler);
+    }
+
+    /// Creates a future that completes on the next interrupt raised by this
+    /// channel.
+    ///
+    /// Returns the newly created future.
+    pub fn completion(&self) -> Completion
+    {
+        let count = DMA.chans[self.idx].count.load(Ordering::Acquire);
+        Completion { idx: self.idx,
+                     count,
+                     waiter: None }
+    }
+
+    /// Returns the index of the transfer being run in the running chain, if
+    /// any.
+    pub fn current_block(&self) -> Option<usize>
+    {
+        fence(Ordering::Acquire);
+        let addr = unsafe { Self::register(self.idx, CHAN_CONBLK_AD).read_volatile() } as usize;
+        self.chain.as_ref()?.index_of(addr)
+    }
+
+    /// Resets the hardware of this channel, aborting any transfer and clearing
+    /// its error flags.
+    fn reset(&self)
+    {
+        unsafe {
+            Self::register(self.idx, CHAN_CS).write_volatile(CS_RESET);
+            Self::register(self.idx, CHAN_DEBUG).write_volatile(DEBUG_ERRORS);
+        }
+        fence(Ordering::Release);
+    }
+
+    /// Computes the address of a channel register.
+    ///
+    /// * `idx`: Channel index.
+    /// * `offset`: Register offset.
+    ///
+    /// Returns the computed address.
+    fn register(idx: usize, offset: usize) -> *mut u32
+    {
+        (BASE + idx * CHAN_STRIDE + offset) as _
+    }
+}
+
+impl Drop for Channel
+{
+    fn drop(&mut self)
+    {
+        self.stop();
+        let _critical = critical();
+        *DMA.chans[self.idx].handler.lock() = None;
+        *DMA.free.lock() |= 0x1 << self.idx;
+    }
+}
+
+impl Future for Completion
+{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()>
+    {
+        let state = &DMA.chans[self.idx];
+        // Register before checking so that an interrupt in between isn't missed.
+        let waiter = state.done.register(self.waiter.take(), ctx.waker());
+        if state.count.load(Ordering::Acquire) != self.count {
+            state.done.unregister(waiter);
+            return Poll::Ready(());
+        }
+        self.waiter = Some(waiter);
+        Poll::Pending
+    }
+}
+
+impl Drop for Completion
+{
+    fn drop(&mut self)
+    {
+        if let Some(waiter) = self.waiter {
+            DMA.chans[self.idx].done.unregister(waiter);
+        }
+    }
+}
+
+impl State
+{
+    /// Creates and initializes a new channel state.
+    ///
+    /// Returns the newly created state.
+    const fn new() -> Self
+    {
+        Self { count: AtomicU64::new(0),
+               handler: Lock::new(None),
+               done: Notify::new() }
+    }
+}
@@ -0,0 +1,446 @@
+//! SD card driver.
+//!
+//! Drives the EMMC2 controller that the Raspberry Pi 4 B's micro SD card slot
+//! is wired to.  The controller follows the SD Host Controller Simplified
+//! Specification [1], with its registers named after those of the BCM2835
+//! EMMC controller in the peripherals datasheet [2], and the card is brought up
+//! following the SD Physical Layer Simplified Specification [3], which Circle
+//! [4] also does.  Only cards conforming to version 2 or later of the physical
+//! layer specification are supported, at the default speed over a 4 bit bus,
+//! as fast cards would require switching the signalling voltage.
+//!
+//! Transfers are performed synchronously by polling the controller and moving
+//! data through its FIFO, which keeps them usable from the power management
+//! hooks that run right before a shutdown, but also means that they block the
+//! calling logical CPU, so the card is only meant to be accessed at startup,
+//! when saving, and when shutting down.  The card is exposed through the
+//! [`STORAGE`] global as a mounted FAT32 volume.
+//!
+//! [1]: https://www.sdcard.org/downloads/pls/pdf/?p=PartA2_SD%20Host_Controller_Simplified_Specification_Ver4.20.jpg
+//! [2]: https://datasheets.raspberrypi.com/bcm2835/bcm2835-peripherals.pdf
+//! [3]: https://www.sdcard.org/downloads/pls/pdf/?p=Part1_Physical_Layer_Simplified_Specification_Ver9.10.jpg
+//! [4]: https://github.com/rsta2/circle/blob/master/addon/SDCard/emmc.cpp
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+
+use crate::clock::now_micros;
+use crate::fat::{BlockDevice, DirEntry, FatError, Volume, SECTOR_SIZE};
+use crate::sync::{Lazy, Lock};
+use crate::{debug, mbox, PERRY_RANGE};
+
+/// EMMC2 base address.
+const BASE: usize = PERRY_RANGE.start + 0x2340000;
+/// Block size and count register.
+const BLKSIZECNT: *mut u32 = (BASE + 0x4) as _;
+/// Argument register.
+const ARG1: *mut u32 = (BASE + 0x8) as _;
+/// Command and transfer mode register.
+const CMDTM: *mut u32 = (BASE + 0xC) as _;
+/// First response register, followed by three more.
+const RESP0: *const u32 = (BASE + 0x10) as _;
+/// Data FIFO register.
+const DATA: *mut u32 = (BASE + 0x20) as _;
+/// Status register.
+const STATUS: *const u32 = (BASE + 0x24) as _;
+/// First control register.
+const CONTROL0: *mut u32 = (BASE + 0x28) as _;
+/// Second control register.
+const CONTROL1: *mut u32 = (BASE + 0x2C) as _;
+/// Interrupt flags register.
+const INTERRUPT: *mut u32 = (BASE + 0x30) as _;
+/// Interrupt flag enable register.
+const IRPT_MASK: *mut u32 = (BASE + 0x34) as _;
+/// Interrupt signal enable register.
+const IRPT_EN: *mut u32 = (BASE + 0x38) as _;
+/// Command and transfer mode block count enable flag.
+const TM_BLKCNT_EN: u32 = 0x2;
+/// Command and transfer mode automatic stop command flag.
+const TM_AUTO_CMD12: u32 = 0x4;
+/// Command and transfer mode card to host direction flag.
+const TM_DAT_DIR_READ: u32 = 0x10;
+/// Command and transfer mode multiple block flag.
+const TM_MULTI_BLOCK: u32 = 0x20;
+/// Command and transfer mode 136 bit response.
+const CMD_RSPNS_136: u32 = 0x10000;
+/// Command and transfer mode 48 bit response.
+const CMD_RSPNS_48: u32 = 0x20000;
+/// Command and transfer mode 48 bit response with busy signalling.
+const CMD_RSPNS_48_BUSY: u32 = 0x30000;
+/// Command and transfer mode response CRC check flag.
+const CMD_CRCCHK_EN: u32 = 0x80000;
+/// Command and transfer mode response index check flag.
+const CMD_IXCHK_EN: u32 = 0x100000;
+/// Command and transfer mode data transfer flag.
+const CMD_ISDATA: u32 = 0x200000;
+/// Status command line busy flag.
+const STATUS_CMD_INHIBIT: u32 = 0x1;
+/// Status data lines busy flag.
+const STATUS_DAT_INHIBIT: u32 = 0x2;
+/// First control 4 bit data bus flag.
+const C0_HCTL_DWIDTH: u32 = 0x2;
+/// First control bus power on at 3.3V flags.
+const C0_POWER_3V3: u32 = 0xF00;
+/// Second control internal clock enable flag.
+const C1_CLK_INTLEN: u32 = 0x1;
+/// Second control internal clock stable flag.
+const C1_CLK_STABLE: u32 = 0x2;
+/// Second control card clock enable flag.
+const C1_CLK_EN: u32 = 0x4;
+/// Second control data timeout of 2^27 clock cycles.
+const C1_DATA_TOUNIT_MAX: u32 = 0xE0000;
+/// Second control full reset flag.
+const C1_SRST_HC: u32 = 0x1000000;
+/// Second control command line reset flag.
+const C1_SRST_CMD: u32 = 0x2000000;
+/// Second control data lines reset flag.
+const C1_SRST_DATA: u32 = 0x4000000;
+/// Interrupt command done flag.
+const INT_CMD_DONE: u32 = 0x1;
+/// Interrupt data done flag.
+const INT_DATA_DONE: u32 = 0x2;
+/// Interrupt FIFO ready for writing flag.
+const INT_WRITE_RDY: u32 = 0x10;
+/// Interrupt FIFO ready for reading flag.
+const INT_READ_RDY: u32 = 0x20;
+/// Interrupt error flags.
+const INT_ERR: u32 = 0xFFFF8000;
+/// Go idle state command.
+const CMD0: u32 = 0;
+/// All send CID command.
+const CMD2: u32 = 2 << 24 | CMD_RSPNS_136 | CMD_CRCCHK_EN;
+/// Send relative address command.
+const CMD3: u32 = 3 << 24 | CMD_RSPNS_48 | CMD_CRCCHK_EN | CMD_IXCHK_EN;
+/// Select card command.
+const CMD7: u32 = 7 << 24 | CMD_RSPNS_48_BUSY | CMD_CRCCHK_EN | CMD_IXCHK_EN;
+/// Send interface condition command.
+const CMD8: u32 = 8 << 24 | CMD_RSPNS_48 | CMD_CRCCHK_EN | CMD_IXCHK_EN;
+/// Set block length command.
+const CMD16: u32 = 16 << 24 | CMD_RSPNS_48 | CMD_CRCCHK_EN | CMD_IXCHK_EN;
+/// Read multiple blocks command.
+const CMD18: u32 = 18 << 24
+                   | CMD_RSPNS_48
+                   | CMD_CRCCHK_EN
+                   | CMD_IXCHK_EN
+                   | CMD_ISDATA
+                   | TM_DAT_DIR_READ
+                   | TM_MULTI_BLOCK
+                   | TM_BLKCNT_EN
+                   | TM_AUTO_CMD12;
+/// Write multiple blocks command.
+const CMD25: u32 =
+    25 << 24 | CMD_RSPNS_48 | CMD_CRCCHK_EN | CMD_IXCHK_EN | CMD_ISDATA | TM_MULTI_BLOCK | TM_BLKCNT_EN | TM_AUTO_CMD12;
+/// Application specific command prefix.
+const CMD55: u32 = 55 << 24 | CMD_RSPNS_48 | CMD_CRCCHK_EN | CMD_IXCHK_EN;
+/// Set bus width application command.
+const ACMD6: u32 = 6 << 24 | CMD_RSPNS_48 | CMD_CRCCHK_EN | CMD_IXCHK_EN;
+/// Send operating condition application command.
+const ACMD41: u32 = 41 << 24 | CMD_RSPNS_48;
+/// Interface condition argument of 2.7V to 3.6V with a check pattern.
+const CMD8_ARG: u32 = 0x1AA;
+/// Operating condition argument of high capacity support at 2.7V to 3.6V.
+const ACMD41_ARG: u32 = 0x40FF8000;
+/// Operating condition ready flag.
+const OCR_READY: u32 = 0x80000000;
+/// Operating condition high capacity flag.
+const OCR_CCS: u32 = 0x40000000;
+/// Bus width argument of 4 bits.
+const ACMD6_4BIT: u32 = 0x2;
+/// Card clock frequency while identifying the card in hertz.
+const IDENT_CLOCK: u32 = 400000;
+/// Card clock frequency at the default speed in hertz.
+const DEFAULT_CLOCK: u32 = 25000000;
+/// Time to wait for the controller to respond in microseconds.
+const TIMEOUT: u64 = 1000000;
+/// Maximum blocks per transfer.
+const MAX_BLOCKS: usize = 0xFFFF;
+/// Get clock rate property tag.
+const GET_CLOCK_RATE_TAG: u32 = 0x30002;
+/// Set expander GPIO state property tag.
+const SET_GPIO_STATE_TAG: u32 = 0x38041;
+/// Firmware identifier of the EMMC2 clock.
+const EMMC2_CLOCK_ID: u32 = 12;
+/// Expander GPIO selecting the card's signalling voltage, with zero selecting
+/// 3.3V.
+const VDD_SD_IO_SEL: u32 = 132;
+
+/// Global storage instance.
+pub static STORAGE: Lazy<Storage> = Lazy::new(Storage::new);
+
+/// Storage driver.
+#[derive(Debug)]
+pub struct Storage
+{
+    /// Volume on the card, if one could be mounted.
+    volume: Lock<Option<Volume<Emmc>>>,
+}
+
+/// SD card.
+#[derive(Debug)]
+pub struct Emmc
+{
+    /// Whether the card is addressed in blocks rather than bytes.
+    high_capacity: bool,
+}
+
+impl Storage
+{
+    /// Creates and initializes a new storage driver, bringing up the card and
+    /// mounting its volume.
+    ///
+    /// Returns the newly created driver.
+    fn new() -> Self
+    {
+        let volume = Emmc::new().and_then(Volume::mount);
+        if let Err(err) = &volume {
+            debug!("No storage available: {err}");
+        }
+        Self { volume: Lock::new(volume.ok()) }
+    }
+
+    /// Reads a whole file from the card.
+    ///
+    /// * `path`: Path of the file relative to the root directory.
+    ///
+    /// Returns the contents of the file, or an error if it couldn't be read.
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, FatError>
+    {
+        self.volume.lock().as_mut().ok_or(FatError::NoVolume)?.read_file(path)
+    }
+
+    /// Lists the contents of a directory on the card.
+    ///
+    /// * `path`: Path of the directory relative to the root directory.
+    ///
+    /// Returns the entries of the directory, or an error if it couldn't be
+    /// read.
+    pub fn list(&self, path: &str) -> Result<Vec<DirEntry>, FatError>
+    {
+        self.volume.lock().as_mut().ok_or(FatError::NoVolume)?.list_dir(path)
+    }
+
+    /// Writes a whole file to the card, replacing it if it already exists.
+    ///
+    /// * `path`: Path of the file relative to the root directory.
+    /// * `data`: New contents of the file.
+    ///
+    /// Returns nothing on success, or an error if the file couldn't be
+    /// written.
+    pub fn write(&self, path: &str, data: &[u8]) -> Result<(), FatError>
+    {
+        self.volume
+            .lock()
+            .as_mut()
+            .ok_or(FatError::NoVolume)?
+            .write_file(path, data)
+    }
+}
+
+impl Emmc
+{
+    /// Resets the controller and brings up the card.
+    ///
+    /// Returns the card ready for transfers, or an error if there's no
+    /// supported card in the slot.
+    fn new() -> Result<Self, FatError>
+    {
+        let rate: [u32; 2];
+        mbox! {
+            SET_GPIO_STATE_TAG: [VDD_SD_IO_SEL, 0] => _,
+            GET_CLOCK_RATE_TAG: EMMC2_CLOCK_ID => rate,
+        };
+        let base = rate[1];
+        unsafe {
+            CONTROL0.write_volatile(0);
+            CONTROL1.write_volatile(C1_SRST_HC);
+        }
+        wait(|| unsafe { CONTROL1.read_volatile() } & C1_SRST_HC == 0)?;
+        unsafe {
+            CONTROL0.write_volatile(C0_POWER_3V3);
+            IRPT_EN.write_volatile(0);
+            IRPT_MASK.write_volatile(u32::MAX);
+            INTERRUPT.write_volatile(u32::MAX);
+        }
+        set_clock(base, IDENT_CLOCK)?;
+        let mut card = Self { high_capacity: false };
+        card.command(CMD0, 0)?;
+        if card.command(CMD8, CMD8_ARG)? & 0xFFF != CMD8_ARG {
+            return Err(FatError::NoVolume);
+        }
+        let start = now_micros();
+        let ocr = loop {
+            card.command(CMD55, 0)?;
+            let ocr = card.command(ACMD41, ACMD41_ARG)?;
+            if ocr & OCR_READY != 0 {
+                break ocr;
+            }
+            if now_micros() - start > TIMEOUT {
+                return Err(FatError::Io);
+            }
+        };
+        card.high_capacity = ocr & OCR_CCS != 0;
+        card.command(CMD2, 0)?;
+        let rca = card.command(CMD3, 0)? & 0xFFFF0000;
+        card.command(CMD7, rca)?;
+        set_clock(base, DEFAULT_CLOCK)?;
+        card.command(CMD55, rca)?;
+        card.command(ACMD6, ACMD6_4BIT)?;
+        unsafe { CONTROL0.write_volatile(CONTROL0.read_volatile() | C0_HCTL_DWIDTH) };
+        if !card.high_capacity {
+            card.command(CMD16, SECTOR_SIZE as u32)?;
+        }
+        debug!("SD card ready with {} capacity",
+               if card.high_capacity { "high" } else { "standard" });
+        Ok(card)
+    }
+
+    /// Sends a command without data to the card.
+    ///
+    /// * `cmd`: Command and transfer mode.
+    /// * `arg`: Command argument.
+    ///
+    /// Returns the first word of the response, or an error if the card
+    /// didn't respond.
+    fn command(&self, cmd: u32, arg: u32) -> Result<u32, FatError>
+    {
+        self.issue(cmd, arg, 0)?;
+        if cmd & CMD_RSPNS_48_BUSY == CMD_RSPNS_48_BUSY {
+            self.complete(INT_DATA_DONE)?;
+        }
+        Ok(unsafe { RESP0.read_volatile() })
+    }
+
+    /// Issues a command and waits for the card to respond to it.
+    ///
+    /// * `cmd`: Command and transfer mode.
+    /// * `arg`: Command argument.
+    /// * `blocks`: Number of blocks to transfer.
+    ///
+    /// Returns nothing on success, or an error if the card didn't respond.
+    fn issue(&self, cmd: u32, arg: u32, blocks: usize) -> Result<(), FatError>
+    {
+        let inhibit = if cmd & (CMD_ISDATA | CMD_RSPNS_48_BUSY) != 0 {
+            STATUS_CMD_INHIBIT | STATUS_DAT_INHIBIT
+        } else {
+            STATUS_CMD_INHIBIT
+        };
+        wait(|| unsafe { STATUS.read_volatile() } & inhibit == 0)?;
+        unsafe {
+            INTERRUPT.write_volatile(u32::MAX);
+            BLKSIZECNT.write_volatile((blocks as u32) << 16 | SECTOR_SIZE as u32);
+            ARG1.write_volatile(arg);
+            CMDTM.write_volatile(cmd);
+        }
+        self.complete(INT_CMD_DONE)
+    }
+
+    /// Waits for the controller to raise an interrupt flag, resetting the
+    /// command and data lines if an error is raised instead.
+    ///
+    /// * `flag`: Flag to wait for, which gets acknowledged.
+    ///
+    /// Returns nothing on success, or an error if the controller reported an
+    /// error or timed out.
+    fn complete(&self, flag: u32) -> Result<(), FatError>
+    {
+        let result = wait(|| unsafe { INTERRUPT.read_volatile() } & (flag | INT_ERR) != 0);
+        let status = unsafe { INTERRUPT.read_volatile() };
+        if result.is_err() || status & INT_ERR != 0 {
+            unsafe {
+                INTERRUPT.write_volatile(u32::MAX);
+                CONTROL1.write_volatile(CONTROL1.read_volatile() | C1_SRST_CMD | C1_SRST_DATA);
+            }
+            wait(|| unsafe { CONTROL1.read_volatile() } & (C1_SRST_CMD | C1_SRST_DATA) == 0)?;
+            return Err(FatError::Io);
+        }
+        unsafe { INTERRUPT.write_volatile(flag) };
+        Ok(())
+    }
+
+    /// Converts a sector address to a command argument.
+    ///
+    /// * `lba`: Address of the sector.
+    ///
+    /// Returns the argument, or an error if the address is out of range.
+    fn address(&self, lba: u64) -> Result<u32, FatError>
+    {
+        let addr = if self.high_capacity {
+            lba
+        } else {
+            lba * SECTOR_SIZE as u64
+        };
+        u32::try_from(addr).map_err(|_| FatError::Io)
+    }
+}
+
+impl BlockDevice for Emmc
+{
+    fn read(&self, lba: u64, buf: &mut [u8]) -> Result<(), FatError>
+    {
+        for (idx, chunk) in buf.chunks_mut(MAX_BLOCKS * SECTOR_SIZE).enumerate() {
+            let lba = lba + (idx * MAX_BLOCKS) as u64;
+            self.issue(CMD18, self.address(lba)?, chunk.len() / SECTOR_SIZE)?;
+            for block in chunk.chunks_exact_mut(SECTOR_SIZE) {
+                self.complete(INT_READ_RDY)?;
+                for word in block.chunks_exact_mut(4) {
+                    word.copy_from_slice(&unsafe { DATA.read_volatile() }.to_le_bytes());
+                }
+            }
+            self.complete(INT_DATA_DONE)?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, lba: u64, buf: &[u8]) -> Result<(), FatError>
+    {
+        for (idx, chunk) in buf.chunks(MAX_BLOCKS * SECTOR_SIZE).enumerate() {
+            let lba = lba + (idx * MAX_BLOCKS) as u64;
+            self.issue(CMD25, self.address(lba)?, chunk.len() / SECTOR_SIZE)?;
+            for block in chunk.chunks_exact(SECTOR_SIZE) {
+                self.complete(INT_WRITE_RDY)?;
+                for word in block.chunks_exact(4) {
+                    unsafe { DATA.write_volatile(u32::from_le_bytes(word.try_into().unwrap())) };
+                }
+            }
+            self.complete(INT_DATA_DONE)?;
+        }
+        Ok(())
+    }
+}
+
+/// Changes the frequency of the card clock.
+///
+/// * `base`: Frequency of the controller's base clock in hertz.
+/// * `freq`: Maximum card clock frequency in hertz.
+///
+/// Returns nothing on success, or an error if the clock didn't stabilize.
+fn set_clock(base: u32, freq: u32) -> Result<(), FatError>
+{
+    // The 10 bit divisor halves the base clock for each step.
+    let div = base.div_ceil(freq * 2).min(0x3FF);
+    let ctrl = (div & 0xFF) << 8 | (div >> 8) << 6 | C1_DATA_TOUNIT_MAX | C1_CLK_INTLEN;
+    unsafe { CONTROL1.write_volatile(ctrl) };
+    wait(|| unsafe { CONTROL1.read_volatile() } & C1_CLK_STABLE != 0)?;
+    unsafe { CONTROL1.write_volatile(ctrl | C1_CLK_EN) };
+    Ok(())
+}
+
+/// Busy-waits for a condition to hold.
+///
+/// * `cond`: Condition to wait for.
+///
+/// Returns nothing on success, or an error if the condition didn't hold in
+/// time.
+fn wait(mut cond: impl FnMut() -> bool) -> Result<(), FatError>
+{
+    let start = now_micros();
+    while !cond() {
+        if now_micros() - start > TIMEOUT {
+            return Err(FatError::Io);
+        }
+        spin_loop();
+    }
+    Ok(())
+}
@@ -0,0 +1,1148 @@
+//! FAT32 file system.
+//!
+//! Reads and writes files on the FAT32 volume that the firmware boots from,
+//! which is where the game keeps its assets, saved games, and settings.  Only
+//! what the game needs is implemented: files are looked up by path, with long
+//! names matched when present, directories can be listed, and files can be
+//! written whole, either replacing existing files of any name or creating new
+//! files with short 8.3 names.  Writes place the new contents in freshly
+//! allocated clusters before pointing the directory entry at them and freeing
+//! the old ones, so a write interrupted by a power loss leaves either the old
+//! or the new contents in place, plus some lost clusters at worst.  The volume
+//! may either start at the beginning of the device or be described by one of
+//! the primary partitions of a Master Boot Record.
+//!
+//! The file system has no dependencies on the hardware and is tested on the
+//! host, whereas block devices are provided by drivers such as the SD card
+//! one.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FormatResult};
+use core::mem::take;
+
+/// Size of a sector in bytes.
+pub const SECTOR_SIZE: usize = 512;
+/// Size of a directory entry in bytes.
+const ENTRY_SIZE: usize = 32;
+/// Boot sector signature.
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+/// Partition types of FAT32 volumes, with either CHS or LBA addressing.
+const FAT32_PARTITIONS: [u8; 2] = [0x0B, 0x0C];
+/// Offset of the partition table in the Master Boot Record.
+const PARTITION_TABLE: usize = 0x1BE;
+/// Size of a partition table entry.
+const PARTITION_SIZE: usize = 16;
+/// Mask of the meaningful bits of a FAT entry.
+const FAT_MASK: u32 = 0x0FFFFFFF;
+/// Smallest FAT entry value marking the end of a cluster chain.
+const FAT_EOC: u32 = 0x0FFFFFF8;
+/// First valid data cluster.
+const FIRST_CLUSTER: u32 = 2;
+/// Read only attribute.
+const ATTR_READ_ONLY: u8 = 0x01;
+/// Volume label attribute.
+const ATTR_VOLUME_ID: u8 = 0x08;
+/// Directory attribute.
+const ATTR_DIRECTORY: u8 = 0x10;
+/// Archive attribute, which new files get.
+const ATTR_ARCHIVE: u8 = 0x20;
+/// Combination of attributes that marks a long name entry.
+const ATTR_LONG_NAME: u8 = 0x0F;
+/// First name byte of deleted directory entries.
+const ENTRY_FREE: u8 = 0xE5;
+/// First name byte of the entry that ends a directory.
+const ENTRY_END: u8 = 0x00;
+/// Flag of the order byte of the last long name entry of a name.
+const LONG_NAME_LAST: u8 = 0x40;
+/// Offsets of the UTF-16 name units in a long name entry.
+const LONG_NAME_UNITS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// Reserved name byte flag of short names with a lowercase base.
+const CASE_LOWER_BASE: u8 = 0x08;
+/// Reserved name byte flag of short names with a lowercase extension.
+const CASE_LOWER_EXT: u8 = 0x10;
+/// Date stamped on written files, which is the first day that FAT can
+/// represent since there's no real time clock.
+const EPOCH_DATE: u16 = 0x21;
+/// FS information sector signatures and their offsets.
+const FSINFO_SIGNATURES: [(usize, u32); 3] = [(0x0, 0x41615252), (0x1E4, 0x61417272), (0x1FC, 0xAA550000)];
+/// Offset of the free cluster count in the FS information sector.
+const FSINFO_FREE: usize = 0x1E8;
+
+/// Block device that a volume is stored on.
+pub trait BlockDevice
+{
+    /// Reads consecutive sectors.
+    ///
+    /// * `lba`: Address of the first sector to read.
+    /// * `buf`: Buffer to fill, whose length must be a multiple of the sector
+    ///   size.
+    ///
+    /// Returns nothing on success, or an error if the device failed.
+    fn read(&self, lba: u64, buf: &mut [u8]) -> Result<(), FatError>;
+
+    /// Writes consecutive sectors.
+    ///
+    /// * `lba`: Address of the first sector to write.
+    /// * `buf`: Data to write, whose length must be a multiple of the sector
+    ///   size.
+    ///
+    /// Returns nothing on success, or an error if the device failed.
+    fn write(&self, lba: u64, buf: &[u8]) -> Result<(), FatError>;
+}
+
+/// Mounted FAT32 volume.
+#[derive(Debug)]
+pub struct Volume<D: BlockDevice>
+{
+    /// Device containing the volume.
+    dev: D,
+    /// Address of the first sector of the first FAT.
+    fat_start: u64,
+    /// Size of each FAT in sectors.
+    fat_size: u64,
+    /// Number of FATs, all of which are kept identical.
+    fat_count: u64,
+    /// Address of the first sector of the first data cluster.
+    data_start: u64,
+    /// Size of a cluster in sectors.
+    cluster_size: usize,
+    /// Number of data clusters.
+    clusters: u32,
+    /// First cluster of the root directory.
+    root: u32,
+    /// Address of the FS information sector, if any.
+    fsinfo: Option<u64>,
+    /// Cluster to start looking for free clusters from.
+    next_free: u32,
+    /// Cached sector of the first FAT.
+    cache: FatCache,
+}
+
+/// Cached sector of the first FAT, which is written to all the FATs when
+/// another sector is needed or the changes are flushed.
+#[derive(Debug)]
+struct FatCache
+{
+    /// Index of the cached sector within the FAT, if any.
+    sector: Option<u64>,
+    /// Contents of the cached sector.
+    data: [u8; SECTOR_SIZE],
+    /// Whether the contents have been changed since they were read.
+    dirty: bool,
+}
+
+/// Entry of a directory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirEntry
+{
+    /// Long name if present, or short name otherwise.
+    pub name: String,
+    /// Whether this entry is a directory.
+    pub is_dir: bool,
+    /// Size of the file in bytes.
+    pub size: u32,
+    /// First cluster of the contents, or zero if empty.
+    cluster: u32,
+    /// Position of the short name entry within its directory.
+    pos: EntryPos,
+}
+
+/// Position of a short name entry within a directory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct EntryPos
+{
+    /// Cluster containing the entry.
+    cluster: u32,
+    /// Index of the entry within the cluster.
+    idx: usize,
+}
+
+/// Errors that can occur when accessing a volume.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FatError
+{
+    /// The block device failed.
+    Io,
+    /// The device contains no FAT32 volume.
+    NoVolume,
+    /// The volume's structures are inconsistent.
+    Corrupt,
+    /// A component of the path doesn't exist.
+    NotFound,
+    /// A component of the path isn't of the expected kind.
+    Kind,
+    /// The name can't be given to a new file.
+    Name,
+    /// The file is read only.
+    ReadOnly,
+    /// The volume has no room left.
+    Full,
+}
+
+impl<D: BlockDevice> Volume<D>
+{
+    /// Mounts the FAT32 volume on a device.
+    ///
+    /// * `dev`: Device containing the volume.
+    ///
+    /// Returns the mounted volume, or an error if the device couldn't be read
+    /// or doesn't contain a FAT32 volume.
+    pub fn mount(dev: D) -> Result<Self, FatError>
+    {
+        let mut sector = [0; SECTOR_SIZE];
+        dev.read(0, &mut sector)?;
+        if sector[510 ..] != BOOT_SIGNATURE {
+            return Err(FatError::NoVolume);
+        }
+        let start = if is_fat32(&sector) {
+            0
+        } else {
+            let entry = sector[PARTITION_TABLE ..].chunks_exact(PARTITION_SIZE)
+                                                  .take(4)
+                                                  .find(|entry| FAT32_PARTITIONS.contains(&entry[4]))
+                                                  .ok_or(FatError::NoVolume)?;
+            let start = u32_at(entry, 8) as u64;
+            dev.read(start, &mut sector)?;
+            if sector[510 ..] != BOOT_SIGNATURE || !is_fat32(&sector) {
+                return Err(FatError::NoVolume);
+            }
+            start
+        };
+        let cluster_size = sector[13] as usize;
+        let reserved = u16_at(&sector, 14) as u64;
+        let fat_count = sector[16] as u64;
+        let total = u32_at(&sector, 32) as u64;
+        let fat_size = u32_at(&sector, 36) as u64;
+        let root = u32_at(&sector, 44);
+        let fsinfo = match u16_at(&sector, 48) {
+            0 | 0xFFFF => None,
+            fsinfo => Some(start + fsinfo as u64),
+        };
+        if !cluster_size.is_power_of_two() || fat_count == 0 || fat_size == 0 {
+            return Err(FatError::NoVolume);
+        }
+        let meta = reserved + fat_count * fat_size;
+        let clusters = (total.saturating_sub(meta) / cluster_size as u64).min((fat_size * SECTOR_SIZE as u64 / 4)
+                                                                          .saturating_sub(FIRST_CLUSTER as u64))
+                       as u32;
+        let vol = Self { dev,
+                         fat_start: start + reserved,
+                         fat_size,
+                         fat_count,
+                         data_start: start + meta,
+                         cluster_size,
+                         clusters,
+                         root,
+                         fsinfo,
+                         next_free: FIRST_CLUSTER,
+                         cache: FatCache { sector: None,
+                                           data: [0; SECTOR_SIZE],
+                                           dirty: false } };
+        if !vol.is_cluster(root) {
+            return Err(FatError::Corrupt);
+        }
+        Ok(vol)
+    }
+
+    /// Reads a whole file.
+    ///
+    /// * `path`: Path of the file relative to the root directory, with
+    ///   components separated by slashes and matched regardless of case.
+    ///
+    /// Returns the contents of the file, or an error if it doesn't exist or
+    /// couldn't be read.
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, FatError>
+    {
+        let entry = self.find(path)?;
+        if entry.is_dir {
+            return Err(FatError::Kind);
+        }
+        let mut contents = Vec::with_capacity(entry.size as usize);
+        let mut buf = vec![0; self.cluster_bytes()];
+        let mut cluster = entry.cluster;
+        while contents.len() < entry.size as usize {
+            if !self.is_cluster(cluster) {
+                return Err(FatError::Corrupt);
+            }
+            self.read_cluster(cluster, &mut buf)?;
+            let len = (entry.size as usize - contents.len()).min(buf.len());
+            contents.extend_from_slice(&buf[.. len]);
+            cluster = self.entry(cluster)?;
+        }
+        Ok(contents)
+    }
+
+    /// Lists the contents of a directory.
+    ///
+    /// * `path`: Path of the directory relative to the root directory, which is
+    ///   itself the empty path.
+    ///
+    /// Returns the entries of the directory other than the dot entries, or an
+    /// error if it doesn't exist or couldn't be read.
+    pub fn list_dir(&mut self, path: &str) -> Result<Vec<DirEntry>, FatError>
+    {
+        let cluster = self.dir_cluster(path)?;
+        let mut entries = self.entries(cluster)?;
+        entries.retain(|entry| entry.name != "." && entry.name != "..");
+        Ok(entries)
+    }
+
+    /// Writes a whole file, replacing its contents if it already exists or
+    /// creating it otherwise.
+    ///
+    /// * `path`: Path of the file relative to the root directory, whose
+    ///   directory must exist and whose name must be a valid short name if the
+    ///   file has to be created.
+    /// * `data`: New contents of the file.
+    ///
+    /// Returns nothing on success, or an error if the file couldn't be
+    /// written, in which case its old contents remain in place.
+    pub fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), FatError>
+    {
+        let size = u32::try_from(data.len()).map_err(|_| FatError::Full)?;
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let dir = self.dir_cluster(dir)?;
+        let old = self.entries(dir)?
+                      .into_iter()
+                      .find(|entry| entry.name.eq_ignore_ascii_case(name));
+        if let Some(old) = &old {
+            if old.is_dir {
+                return Err(FatError::Kind);
+            }
+        }
+        let short = match old {
+            Some(_) => None,
+            None => Some(short_name(name).ok_or(FatError::Name)?),
+        };
+        self.invalidate_fsinfo()?;
+        let first = self.store(data)?;
+        let result = match (&old, short) {
+            (Some(old), _) => self.update_entry(old.pos, first, size),
+            (None, Some((short, case))) => self.create_entry(dir, &short, case, first, size),
+            (None, None) => unreachable!(),
+        };
+        if let Err(err) = result {
+            // Give the new contents back, leaving the old ones in place.
+            self.free_chain(first)?;
+            self.flush()?;
+            return Err(err);
+        }
+        if let Some(old) = old {
+            self.free_chain(old.cluster)?;
+        }
+        self.flush()
+    }
+
+    /// Writes any pending changes to the FATs out to the device.
+    ///
+    /// Returns nothing on success, or an error if the device failed.
+    pub fn flush(&mut self) -> Result<(), FatError>
+    {
+        let Some(sector) = self.cache.sector else {
+            return Ok(());
+        };
+        if !self.cache.dirty {
+            return Ok(());
+        }
+        for fat in 0 .. self.fat_count {
+            self.dev
+                .write(self.fat_start + fat * self.fat_size + sector, &self.cache.data)?;
+        }
+        self.cache.dirty = false;
+        Ok(())
+    }
+
+    /// Looks up an entry by path.
+    ///
+    /// * `path`: Path of the entry relative to the root directory.
+    ///
+    /// Returns the entry, or an error if it doesn't exist.
+    fn find(&mut self, path: &str) -> Result<DirEntry, FatError>
+    {
+        let mut parts = path.split('/').filter(|part| !part.is_empty()).peekable();
+        let mut dir = self.root;
+        while let Some(part) = parts.next() {
+            let entry = self.entries(dir)?
+                            .into_iter()
+                            .find(|entry| entry.name.eq_ignore_ascii_case(part))
+                            .ok_or(FatError::NotFound)?;
+            if parts.peek().is_none() {
+                return Ok(entry);
+            }
+            if !entry.is_dir {
+                return Err(FatError::Kind);
+            }
+            dir = self.dir_start(entry.cluster);
+        }
+        Err(FatError::NotFound)
+    }
+
+    /// Looks up the first cluster of a directory by path.
+    ///
+    /// * `path`: Path of the directory relative to the root directory.
+    ///
+    /// Returns the first cluster, or an error if the directory doesn't exist.
+    fn dir_cluster(&mut self, path: &str) -> Result<u32, FatError>
+    {
+        if path.split('/').all(str::is_empty) {
+            return Ok(self.root);
+        }
+        let entry = self.find(path)?;
+        if !entry.is_dir {
+            return Err(FatError::Kind);
+        }
+        Ok(self.dir_start(entry.cluster))
+    }
+
+    /// Translates the first cluster recorded by a directory entry, in which
+    /// zero refers to the root directory.
+    ///
+    /// * `cluster`: Recorded cluster.
+    ///
+    /// Returns the first cluster of the directory.
+    fn dir_start(&self, cluster: u32) -> u32
+    {
+        if cluster == 0 {
+            return self.root;
+        }
+        cluster
+    }
+
+    /// Reads the entries of a directory.
+    ///
+    /// * `first`: First cluster of the directory.
+    ///
+    /// Returns the entries other than free entries and volume labels, or an
+    /// error if the directory couldn't be read.
+    fn entries(&mut self, first: u32) -> Result<Vec<DirEntry>, FatError>
+    {
+        let mut entries = Vec::new();
+        let mut buf = vec![0; self.cluster_bytes()];
+        let mut long = LongName::default();
+        let mut cluster = first;
+        while self.is_cluster(cluster) {
+            self.read_cluster(cluster, &mut buf)?;
+            for (idx, raw) in buf.chunks_exact(ENTRY_SIZE).enumerate() {
+                match raw[0] {
+                    ENTRY_END => return Ok(entries),
+                    ENTRY_FREE => {
+                        long = LongName::default();
+                        continue;
+                    }
+                    _ => (),
+                }
+                let attr = raw[11];
+                if attr & ATTR_LONG_NAME == ATTR_LONG_NAME {
+                    long.push(raw);
+                    continue;
+                }
+                let name = long.take(checksum(&raw[.. 11])).unwrap_or_else(|| short_to_string(raw));
+                if attr & ATTR_VOLUME_ID != 0 {
+                    continue;
+                }
+                let cluster_hi = u16_at(raw, 20) as u32;
+                let cluster_lo = u16_at(raw, 26) as u32;
+                entries.push(DirEntry { name,
+                                        is_dir: attr & ATTR_DIRECTORY != 0,
+                                        size: u32_at(raw, 28),
+                                        cluster: cluster_hi << 16 | cluster_lo,
+                                        pos: EntryPos { cluster, idx } });
+            }
+            cluster = self.entry(cluster)?;
+        }
+        Ok(entries)
+    }
+
+    /// Stores data in a newly allocated cluster chain.
+    ///
+    /// * `data`: Data to store.
+    ///
+    /// Returns the first cluster of the chain, or zero if the data is empty,
+    /// or an error if there's not enough room, in which case nothing is
+    /// allocated.
+    fn store(&mut self, data: &[u8]) -> Result<u32, FatError>
+    {
+        let mut first = 0;
+        let mut last = 0;
+        let mut buf = vec![0; self.cluster_bytes()];
+        for chunk in data.chunks(buf.len()) {
+            let cluster = match self.allocate() {
+                Ok(cluster) => cluster,
+                Err(err) => {
+                    self.free_chain(first)?;
+                    self.flush()?;
+                    return Err(err);
+                }
+            };
+            buf[.. chunk.len()].copy_from_slice(chunk);
+            buf[chunk.len() ..].fill(0);
+            self.write_cluster(cluster, &buf)?;
+            if last == 0 {
+                first = cluster;
+            } else {
+                self.set_entry(last, cluster)?;
+            }
+            last = cluster;
+        }
+        // The FATs must point at the new contents before the directory entry
+        // does.
+        self.flush()?;
+        Ok(first)
+    }
+
+    /// Allocates a free cluster and marks it as the end of a chain.
+    ///
+    /// Returns the allocated cluster, or an error if there are no free
+    /// clusters left.
+    fn allocate(&mut self) -> Result<u32, FatError>
+    {
+        for offset in 0 .. self.clusters {
+            let cluster = FIRST_CLUSTER + (self.next_free - FIRST_CLUSTER + offset) % self.clusters;
+            if self.entry(cluster)? == 0 {
+                self.set_entry(cluster, FAT_MASK)?;
+                self.next_free = if cluster + 1 < FIRST_CLUSTER + self.clusters {
+                    cluster + 1
+                } else {
+                    FIRST_CLUSTER
+                };
+                return Ok(cluster);
+            }
+        }
+        Err(FatError::Full)
+    }
+
+    /// Frees a cluster chain.
+    ///
+    /// * `first`: First cluster of the chain, with zero meaning an empty chain.
+    ///
+    /// Returns nothing on success, or an error if the FAT couldn't be
+    /// accessed.
+    fn free_chain(&mut self, first: u32) -> Result<(), FatError>
+    {
+        let mut cluster = first;
+        // Bounding the walk keeps a corrupted cyclic chain from hanging.
+        for _ in 0 .. self.clusters {
+            if !self.is_cluster(cluster) {
+                break;
+            }
+            let next = self.entry(cluster)?;
+            self.set_entry(cluster, 0)?;
+            cluster = next;
+        }
+        Ok(())
+    }
+
+    /// Points an existing short name entry at new contents.
+    ///
+    /// * `pos`: Position of the entry.
+    /// * `first`: First cluster of the new contents.
+    /// * `size`: Size of the new contents in bytes.
+    ///
+    /// Returns nothing on success, or an error if the entry couldn't be
+    /// updated.
+    fn update_entry(&mut self, pos: EntryPos, first: u32, size: u32) -> Result<(), FatError>
+    {
+        let (lba, offset) = self.entry_location(pos);
+        let mut sector = [0; SECTOR_SIZE];
+        self.dev.read(lba, &mut sector)?;
+        let raw = &mut sector[offset .. offset + ENTRY_SIZE];
+        if raw[11] & ATTR_READ_ONLY != 0 {
+            return Err(FatError::ReadOnly);
+        }
+        raw[11] |= ATTR_ARCHIVE;
+        fill_entry(raw, first, size);
+        self.dev.write(lba, &sector)
+    }
+
+    /// Adds a short name entry to a directory, extending it if it has no free
+    /// entries left.
+    ///
+    /// * `dir`: First cluster of the directory.
+    /// * `short`: Short name in directory entry form.
+    /// * `case`: Case flags of the short name.
+    /// * `first`: First cluster of the contents.
+    /// * `size`: Size of the contents in bytes.
+    ///
+    /// Returns nothing on success, or an error if the entry couldn't be added.
+    fn create_entry(&mut self, dir: u32, short: &[u8; 11], case: u8, first: u32, size: u32) -> Result<(), FatError>
+    {
+        let per_cluster = self.cluster_bytes() / ENTRY_SIZE;
+        let mut buf = vec![0; self.cluster_bytes()];
+        let mut cluster = dir;
+        let pos = loop {
+            self.read_cluster(cluster, &mut buf)?;
+            let free = buf.chunks_exact(ENTRY_SIZE)
+                          .position(|raw| raw[0] == ENTRY_END || raw[0] == ENTRY_FREE);
+            if let Some(idx) = free {
+                break EntryPos { cluster, idx };
+            }
+            let next = self.entry(cluster)?;
+            if self.is_cluster(next) {
+                cluster = next;
+                continue;
+            }
+            // Every entry is taken, so the directory grows by a cleared
+            // cluster.
+            let new = self.allocate()?;
+            buf.fill(0);
+            self.write_cluster(new, &buf)?;
+            self.set_entry(cluster, new)?;
+            self.flush()?;
+            break EntryPos { cluster: new, idx: 0 };
+        };
+        debug_assert!(pos.idx < per_cluster);
+        let (lba, offset) = self.entry_location(pos);
+        let mut sector = [0; SECTOR_SIZE];
+        self.dev.read(lba, &mut sector)?;
+        let raw = &mut sector[offset .. offset + ENTRY_SIZE];
+        raw.fill(0);
+        raw[.. 11].copy_from_slice(short);
+        raw[11] = ATTR_ARCHIVE;
+        raw[12] = case;
+        for offset in [16, 18, 24] {
+            raw[offset .. offset + 2].copy_from_slice(&EPOCH_DATE.to_le_bytes());
+        }
+        fill_entry(raw, first, size);
+        self.dev.write(lba, &sector)
+    }
+
+    /// Computes the location of a directory entry on the device.
+    ///
+    /// * `pos`: Position of the entry.
+    ///
+    /// Returns the address of the sector containing the entry and the offset
+    /// of the entry within it.
+    fn entry_location(&self, pos: EntryPos) -> (u64, usize)
+    {
+        let offset = pos.idx * ENTRY_SIZE;
+        let lba = self.cluster_lba(pos.cluster) + (offset / SECTOR_SIZE) as u64;
+        (lba, offset % SECTOR_SIZE)
+    }
+
+    /// Marks the free cluster count of the FS information sector as unknown,
+    /// since it's not kept up to date.
+    ///
+    /// Returns nothing on success, or an error if the device failed.
+    fn invalidate_fsinfo(&mut self) -> Result<(), FatError>
+    {
+        let Some(lba) = self.fsinfo.take() else {
+            return Ok(());
+        };
+        let mut sector = [0; SECTOR_SIZE];
+        self.dev.read(lba, &mut sector)?;
+        if FSINFO_SIGNATURES.iter()
+                            .any(|(offset, signature)| u32_at(&sector, *offset) != *signature)
+        {
+            return Ok(());
+        }
+        sector[FSINFO_FREE .. FSINFO_FREE + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        self.dev.write(lba, &sector)
+    }
+
+    /// Reads the FAT entry of a cluster.
+    ///
+    /// * `cluster`: Cluster whose entry to read.
+    ///
+    /// Returns the next cluster in the chain, zero if the cluster is free, or
+    /// a value that isn't a valid cluster at the end of the chain.
+    fn entry(&mut self, cluster: u32) -> Result<u32, FatError>
+    {
+        let offset = self.cache_entry(cluster)?;
+        Ok(u32_at(&self.cache.data, offset) & FAT_MASK)
+    }
+
+    /// Changes the FAT entry of a cluster, preserving its reserved bits.
+    ///
+    /// * `cluster`: Cluster whose entry to change.
+    /// * `val`: New value of the entry.
+    ///
+    /// Returns nothing on success, or an error if the FAT couldn't be
+    /// accessed.
+    fn set_entry(&mut self, cluster: u32, val: u32) -> Result<(), FatError>
+    {
+        let offset = self.cache_entry(cluster)?;
+        let val = u32_at(&self.cache.data, offset) & !FAT_MASK | val & FAT_MASK;
+        self.cache.data[offset .. offset + 4].copy_from_slice(&val.to_le_bytes());
+        self.cache.dirty = true;
+        Ok(())
+    }
+
+    /// Loads the FAT sector containing the entry of a cluster into the
+    /// cache, writing the previously cached sector out first if needed.
+    ///
+    /// * `cluster`: Cluster whose entry to load.
+    ///
+    /// Returns the offset of the entry within the cached sector.
+    fn cache_entry(&mut self, cluster: u32) -> Result<usize, FatError>
+    {
+        if !self.is_cluster(cluster) {
+            return Err(FatError::Corrupt);
+        }
+        let offset = cluster as usize * 4;
+        let sector = (offset / SECTOR_SIZE) as u64;
+        if self.cache.sector != Some(sector) {
+            self.flush()?;
+            self.cache.sector = None;
+            self.dev.read(self.fat_start + sector, &mut self.cache.data)?;
+            self.cache.sector = Some(sector);
+        }
+        Ok(offset % SECTOR_SIZE)
+    }
+
+    /// Reads a whole cluster.
+    ///
+    /// * `cluster`: Cluster to read.
+    /// * `buf`: Buffer to fill, which must be exactly one cluster long.
+    ///
+    /// Returns nothing on success, or an error if the device failed.
+    fn read_cluster(&mut self, cluster: u32, buf: &mut [u8]) -> Result<(), FatError>
+    {
+        self.dev.read(self.cluster_lba(cluster), buf)
+    }
+
+    /// Writes a whole cluster.
+    ///
+    /// * `cluster`: Cluster to write.
+    /// * `buf`: Data to write, which must be exactly one cluster long.
+    ///
+    /// Returns nothing on success, or an error if the device failed.
+    fn write_cluster(&mut self, cluster: u32, buf: &[u8]) -> Result<(), FatError>
+    {
+        self.dev.write(self.cluster_lba(cluster), buf)
+    }
+
+    /// Computes the address of the first sector of a cluster.
+    ///
+    /// * `cluster`: Cluster to locate.
+    fn cluster_lba(&self, cluster: u32) -> u64
+    {
+        self.data_start + (cluster - FIRST_CLUSTER) as u64 * self.cluster_size as u64
+    }
+
+    /// Returns the size of a cluster in bytes.
+    fn cluster_bytes(&self) -> usize
+    {
+        self.cluster_size * SECTOR_SIZE
+    }
+
+    /// Checks whether a FAT entry value refers to a data cluster.
+    ///
+    /// * `cluster`: Value to check.
+    ///
+    /// Returns whether the value is a data cluster.
+    fn is_cluster(&self, cluster: u32) -> bool
+    {
+        (FIRST_CLUSTER .. FIRST_CLUSTER + self.clusters).contains(&cluster) && cluster < FAT_EOC
+    }
+}
+
+/// Long name collected from the entries preceding a short name entry.
+#[derive(Debug, Default)]
+struct LongName
+{
+    /// UTF-16 units of the name, in order.
+    units: Vec<u16>,
+    /// Checksum of the short name that the entries belong to.
+    checksum: u8,
+    /// Order number expected of the next entry, or zero if the name is
+    /// complete or no name is being collected.
+    next: u8,
+    /// Whether the entries seen so far form a valid name.
+    valid: bool,
+}
+
+impl LongName
+{
+    /// Adds a long name entry, which come last part first.
+    ///
+    /// * `raw`: Long name entry.
+    fn push(&mut self, raw: &[u8])
+    {
+        let order = raw[0] & !LONG_NAME_LAST;
+        if raw[0] & LONG_NAME_LAST != 0 {
+            *self = Self { units: vec![0xFFFF; order as usize * LONG_NAME_UNITS.len()],
+                           checksum: raw[13],
+                           next: order,
+                           valid: order > 0 };
+        }
+        if !self.valid || order != self.next || raw[13] != self.checksum {
+            self.valid = false;
+            return;
+        }
+        let start = (order as usize - 1) * LONG_NAME_UNITS.len();
+        for (idx, offset) in LONG_NAME_UNITS.iter().enumerate() {
+            self.units[start + idx] = u16_at(raw, *offset);
+        }
+        self.next -= 1;
+    }
+
+    /// Takes the collected name if it belongs to a short name entry.
+    ///
+    /// * `checksum`: Checksum of the short name.
+    ///
+    /// Returns the long name, or nothing if there's no valid long name for the
+    /// short name.
+    fn take(&mut self, checksum: u8) -> Option<String>
+    {
+        let name = take(self);
+        if !name.valid || name.next != 0 || name.checksum != checksum {
+            return None;
+        }
+        let len = name.units
+                      .iter()
+                      .position(|unit| *unit == 0 || *unit == 0xFFFF)
+                      .unwrap_or(name.units.len());
+        char::decode_utf16(name.units[.. len].iter().copied()).collect::<Result<String, _>>()
+                                                              .ok()
+    }
+}
+
+impl Display for FatError
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        match self {
+            Self::Io => write!(fmt, "device error"),
+            Self::NoVolume => write!(fmt, "no FAT32 volume"),
+            Self::Corrupt => write!(fmt, "corrupt volume"),
+            Self::NotFound => write!(fmt, "not found"),
+            Self::Kind => write!(fmt, "not a file or directory as expected"),
+            Self::Name => write!(fmt, "not a valid short name"),
+            Self::ReadOnly => write!(fmt, "read only"),
+            Self::Full => write!(fmt, "volume full"),
+        }
+    }
+}
+
+/// Checks whether a boot sector describes a FAT32 volume.
+///
+/// * `sector`: Boot sector to check.
+///
+/// Returns whether the sector is a FAT32 boot sector with 512 byte sectors.
+fn is_fat32(sector: &[u8]) -> bool
+{
+    // FAT32 volumes have no fixed root directory and only 32-bit sizes.
+    u16_at(sector, 11) == SECTOR_SIZE as u16
+    && u16_at(sector, 17) == 0
+    && u16_at(sector, 19) == 0
+    && u16_at(sector, 22) == 0
+    && u32_at(sector, 36) != 0
+}
+
+/// Converts a name to a short name.
+///
+/// * `name`: Name to convert, which must fit the 8.3 format and have each of
+///   its parts entirely in either lowercase or uppercase.
+///
+/// Returns the short name in directory entry form along with its case flags,
+/// or nothing if the name can't be represented.
+fn short_name(name: &str) -> Option<([u8; 11], u8)>
+{
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+    let valid = |byte: u8| byte.is_ascii_alphanumeric() || b"!#$%&'()-@^_`{}~".contains(&byte);
+    let mut short = [b' '; 11];
+    let mut case = 0;
+    for (part, range, flag) in [(base, 0 .. 8, CASE_LOWER_BASE), (ext, 8 .. 11, CASE_LOWER_EXT)] {
+        let bytes = part.as_bytes();
+        if !bytes.iter().all(|byte| valid(*byte)) {
+            return None;
+        }
+        let lower = bytes.iter().any(u8::is_ascii_lowercase);
+        if lower && bytes.iter().any(u8::is_ascii_uppercase) {
+            return None;
+        }
+        if lower {
+            case |= flag;
+        }
+        for (dst, src) in short[range].iter_mut().zip(bytes) {
+            *dst = src.to_ascii_uppercase();
+        }
+    }
+    // A leading 0xE5 byte would mark the entry as free.
+    if short[0] == ENTRY_FREE {
+        return None;
+    }
+    Some((short, case))
+}
+
+/// Converts a short name entry to text, honoring its case flags.
+///
+/// * `raw`: Short name entry.
+///
+/// Returns the name.
+fn short_to_string(raw: &[u8]) -> String
+{
+    let part = |bytes: &[u8], lower: bool| {
+        bytes.iter()
+             .take_while(|byte| **byte != b' ')
+             .map(|byte| if lower { byte.to_ascii_lowercase() } else { *byte } as char)
+             .collect::<String>()
+    };
+    // A leading 0x05 byte stands for a 0xE5 byte.
+    let mut base = [0; 8];
+    base.copy_from_slice(&raw[.. 8]);
+    if base[0] == 0x05 {
+        base[0] = ENTRY_FREE;
+    }
+    let mut name = part(&base, raw[12] & CASE_LOWER_BASE != 0);
+    let ext = part(&raw[8 .. 11], raw[12] & CASE_LOWER_EXT != 0);
+    if !ext.is_empty() {
+        name.push('.');
+        name.push_str(&ext);
+    }
+    name
+}
+
+/// Computes the checksum of a short name that its long name entries carry.
+///
+/// * `short`: Short name in directory entry form.
+///
+/// Returns the checksum.
+fn checksum(short: &[u8]) -> u8
+{
+    short.iter()
+         .fold(0u8, |sum, byte| sum.rotate_right(1).wrapping_add(*byte))
+}
+
+/// Points a short name entry at contents.
+///
+/// * `raw`: Short name entry.
+/// * `first`: First cluster of the contents.
+/// * `size`: Size of the contents in bytes.
+fn fill_entry(raw: &mut [u8], first: u32, size: u32)
+{
+    raw[20 .. 22].copy_from_slice(&((first >> 16) as u16).to_le_bytes());
+    raw[26 .. 28].copy_from_slice(&(first as u16).to_le_bytes());
+    raw[28 .. 32].copy_from_slice(&size.to_le_bytes());
+}
+
+/// Reads a little-endian 16-bit value.
+///
+/// * `bytes`: Bytes to read from.
+/// * `offset`: Offset of the value.
+fn u16_at(bytes: &[u8], offset: usize) -> u16
+{
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+/// Reads a little-endian 32-bit value.
+///
+/// * `bytes`: Bytes to read from.
+/// * `offset`: Offset of the value.
+fn u32_at(bytes: &[u8], offset: usize) -> u32
+{
+    u32::from_le_bytes(bytes[offset .. offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use core::cell::RefCell;
+
+    use super::*;
+
+    /// Sectors before the volume, where the partition table lives.
+    const OFFSET: u64 = 8;
+    /// Sectors in the volume.
+    const SECTORS: u64 = 2048;
+    /// Sectors per cluster.
+    const CLUSTER_SIZE: usize = 2;
+    /// Reserved sectors at the start of the volume.
+    const RESERVED: u64 = 32;
+    /// Size of each FAT in sectors.
+    const FAT_SIZE: u64 = 8;
+
+    /// Block device backed by memory.
+    struct Ram(RefCell<Vec<u8>>);
+
+    impl BlockDevice for &Ram
+    {
+        fn read(&self, lba: u64, buf: &mut [u8]) -> Result<(), FatError>
+        {
+            let start = lba as usize * SECTOR_SIZE;
+            let data = self.0.borrow();
+            let src = data.get(start .. start + buf.len()).ok_or(FatError::Io)?;
+            buf.copy_from_slice(src);
+            Ok(())
+        }
+
+        fn write(&self, lba: u64, buf: &[u8]) -> Result<(), FatError>
+        {
+            let start = lba as usize * SECTOR_SIZE;
+            let mut data = self.0.borrow_mut();
+            let dst = data.get_mut(start .. start + buf.len()).ok_or(FatError::Io)?;
+            dst.copy_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    /// Formats a small partitioned FAT32 volume with an empty root directory.
+    fn format() -> Ram
+    {
+        let mut image = vec![0; ((OFFSET + SECTORS) as usize) * SECTOR_SIZE];
+        image[PARTITION_TABLE + 4] = 0x0C;
+        image[PARTITION_TABLE + 8 .. PARTITION_TABLE + 12].copy_from_slice(&(OFFSET as u32).to_le_bytes());
+        image[510 .. 512].copy_from_slice(&BOOT_SIGNATURE);
+        let boot = &mut image[OFFSET as usize * SECTOR_SIZE ..][.. SECTOR_SIZE];
+        boot[11 .. 13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+        boot[13] = CLUSTER_SIZE as u8;
+        boot[14 .. 16].copy_from_slice(&(RESERVED as u16).to_le_bytes());
+        boot[16] = 2;
+        boot[32 .. 36].copy_from_slice(&(SECTORS as u32).to_le_bytes());
+        boot[36 .. 40].copy_from_slice(&(FAT_SIZE as u32).to_le_bytes());
+        boot[44 .. 48].copy_from_slice(&2u32.to_le_bytes());
+        boot[48 .. 50].copy_from_slice(&1u16.to_le_bytes());
+        boot[82 .. 90].copy_from_slice(b"FAT32   ");
+        boot[510 .. 512].copy_from_slice(&BOOT_SIGNATURE);
+        let fsinfo = &mut image[(OFFSET + 1) as usize * SECTOR_SIZE ..][.. SECTOR_SIZE];
+        for (offset, signature) in FSINFO_SIGNATURES {
+            fsinfo[offset .. offset + 4].copy_from_slice(&signature.to_le_bytes());
+        }
+        for fat in 0 .. 2 {
+            let fat = &mut image[(OFFSET + RESERVED + fat * FAT_SIZE) as usize * SECTOR_SIZE ..];
+            fat[.. 4].copy_from_slice(&0x0FFFFFF8u32.to_le_bytes());
+            fat[4 .. 8].copy_from_slice(&FAT_MASK.to_le_bytes());
+            fat[8 .. 12].copy_from_slice(&FAT_MASK.to_le_bytes());
+        }
+        Ram(RefCell::new(image))
+    }
+
+    /// Counts the free clusters of a volume by scanning its FAT.
+    fn free_clusters(vol: &mut Volume<&Ram>) -> u32
+    {
+        (FIRST_CLUSTER .. FIRST_CLUSTER + vol.clusters).filter(|cluster| vol.entry(*cluster).unwrap() == 0)
+                                                       .count() as u32
+    }
+
+    /// Creates a directory in the root directory of a volume, with a long name
+    /// entry preceding the short one.
+    fn mkdir(vol: &mut Volume<&Ram>, long: &str, short: &[u8; 11])
+    {
+        let cluster = vol.allocate().unwrap();
+        vol.flush().unwrap();
+        vol.write_cluster(cluster, &vec![0; vol.cluster_bytes()]).unwrap();
+        let mut buf = vec![0; vol.cluster_bytes()];
+        vol.read_cluster(vol.root, &mut buf).unwrap();
+        let idx = buf.chunks_exact(ENTRY_SIZE)
+                     .position(|raw| raw[0] == ENTRY_END)
+                     .unwrap();
+        let units = long.encode_utf16().chain([0]).collect::<Vec<_>>();
+        let lfn = &mut buf[idx * ENTRY_SIZE ..][.. ENTRY_SIZE];
+        lfn[0] = 1 | LONG_NAME_LAST;
+        lfn[11] = ATTR_LONG_NAME;
+        lfn[13] = checksum(short);
+        for (offset, unit) in LONG_NAME_UNITS.iter().zip(units.iter().chain([0xFFFF].iter().cycle())) {
+            lfn[*offset .. *offset + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        let raw = &mut buf[(idx + 1) * ENTRY_SIZE ..][.. ENTRY_SIZE];
+        raw[.. 11].copy_from_slice(short);
+        raw[11] = ATTR_DIRECTORY;
+        fill_entry(raw, cluster, 0);
+        vol.write_cluster(vol.root, &buf).unwrap();
+    }
+
+    #[test]
+    fn write_and_read()
+    {
+        let ram = format();
+        let mut vol = Volume::mount(&ram).unwrap();
+        let free = free_clusters(&mut vol);
+        let data = (0 .. 3000).map(|idx| idx as u8).collect::<Vec<_>>();
+        vol.write_file("save.bin", &data).unwrap();
+        assert_eq!(free_clusters(&mut vol), free - 3);
+        // Mounting again must find the file as written.
+        let mut vol = Volume::mount(&ram).unwrap();
+        assert_eq!(vol.read_file("SAVE.BIN").unwrap(), data);
+        let entries = vol.list_dir("").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].name.as_str(), entries[0].size), ("save.bin", 3000));
+        // Replacing the contents frees the old clusters.
+        vol.write_file("save.bin", &data[.. 10]).unwrap();
+        assert_eq!(vol.read_file("save.bin").unwrap(), &data[.. 10]);
+        assert_eq!(free_clusters(&mut vol), free - 1);
+        vol.write_file("save.bin", &[]).unwrap();
+        assert_eq!(vol.read_file("save.bin").unwrap(), []);
+        assert_eq!(free_clusters(&mut vol), free);
+        assert_eq!(vol.list_dir("").unwrap().len(), 1);
+        assert_eq!(vol.read_file("other.bin"), Err(FatError::NotFound));
+        // Both FATs are kept identical.
+        let image = ram.0.borrow();
+        let fat = |idx: u64| &image[((OFFSET + RESERVED + idx * FAT_SIZE) as usize * SECTOR_SIZE) ..][.. 512];
+        assert_eq!(fat(0), fat(1));
+    }
+
+    #[test]
+    fn long_names_and_directories()
+    {
+        let ram = format();
+        let mut vol = Volume::mount(&ram).unwrap();
+        mkdir(&mut vol, "Assets", b"ASSETS     ");
+        vol.write_file("assets/cube.nbm", b"cube").unwrap();
+        vol.write_file("Assets/CRATE.NTX", b"crate").unwrap();
+        let mut vol = Volume::mount(&ram).unwrap();
+        let names = vol.list_dir("assets")
+                       .unwrap()
+                       .into_iter()
+                       .map(|entry| entry.name)
+                       .collect::<Vec<_>>();
+        assert_eq!(names, ["cube.nbm", "CRATE.NTX"]);
+        assert!(vol.list_dir("").unwrap()[0].is_dir);
+        assert_eq!(vol.list_dir("").unwrap()[0].name, "Assets");
+        assert_eq!(vol.read_file("ASSETS/crate.ntx").unwrap(), b"crate");
+        assert_eq!(vol.read_file("assets"), Err(FatError::Kind));
+        assert_eq!(vol.write_file("assets", b""), Err(FatError::Kind));
+        assert_eq!(vol.write_file("missing/file.bin", b""), Err(FatError::NotFound));
+        assert_eq!(vol.write_file("Mixed.bin", b""), Err(FatError::Name));
+        assert_eq!(vol.write_file("toolongname.bin", b""), Err(FatError::Name));
+    }
+
+    #[test]
+    fn directory_growth()
+    {
+        let ram = format();
+        let mut vol = Volume::mount(&ram).unwrap();
+        let per_cluster = vol.cluster_bytes() / ENTRY_SIZE;
+        for idx in 0 ..= per_cluster {
+            vol.write_file(&format!("F{idx}.BIN"), &[idx as u8]).unwrap();
+        }
+        let mut vol = Volume::mount(&ram).unwrap();
+        assert_eq!(vol.list_dir("").unwrap().len(), per_cluster + 1);
+        assert_eq!(vol.read_file(&format!("f{per_cluster}.bin")).unwrap(),
+                   [per_cluster as u8]);
+    }
+
+    #[test]
+    fn full_volume()
+    {
+        let ram = format();
+        let mut vol = Volume::mount(&ram).unwrap();
+        vol.write_file("keep.bin", b"old").unwrap();
+        let free = free_clusters(&mut vol);
+        let huge = vec![0xA5; (free as usize + 1) * vol.cluster_bytes()];
+        assert_eq!(vol.write_file("keep.bin", &huge), Err(FatError::Full));
+        assert_eq!(free_clusters(&mut vol), free);
+        assert_eq!(vol.read_file("keep.bin").unwrap(), b"old");
+    }
+
+    #[test]
+    fn short_names()
+    {
+        assert_eq!(short_name("save.bin"),
+                   Some((*b"SAVE    BIN", CASE_LOWER_BASE | CASE_LOWER_EXT)));
+        assert_eq!(short_name("README"), Some((*b"README     ", 0)));
+        assert_eq!(short_name("a.b.c"), None);
+        assert_eq!(short_name(".bin"), None);
+        assert_eq!(short_name("no space"), None);
+        let mut raw = [0; ENTRY_SIZE];
+        raw[.. 11].copy_from_slice(b"SAVE    BIN");
+        raw[12] = CASE_LOWER_EXT;
+        assert_eq!(short_to_string(&raw), "SAVE.bin");
+    }
+}
@@ -0,0 +1,482 @@
+//! Creature needs and behavior.
+//!
+//! Creatures grow hungry, tired, and angry over time, and every game tick each
+//! creature without an ongoing activity picks the one that best satisfies its
+//! most pressing need, falling back to wandering around when none is pressing
+//! enough.  Activities carry on over several ticks until the need is
+//! satisfied or they can no longer be carried out.  Creatures killed in fights
+//! are removed from the dungeon at the end of the tick.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::cmp::{Ordering, Reverse};
+
+use crate::game::{CombatEvent, Fighter, Map, Rng, Tile, Wage};
+
+/// Maximum value of any need.
+const NEED_MAX: u32 = 1000;
+/// Hunger gained per tick.
+const HUNGER_RATE: u32 = 2;
+/// Fatigue gained per tick.
+const FATIGUE_RATE: u32 = 1;
+/// Hunger above which creatures also grow angry.
+const STARVING: u32 = 600;
+/// Anger gained per tick while starving.
+const STARVING_ANGER: u32 = 2;
+/// Anger gained per tick for each consecutive missed payday.
+const UNPAID_ANGER: u32 = 1;
+/// Anger lost per tick while content.
+const CALM_RATE: u32 = 1;
+/// Hunger satisfied per tick of eating.
+const EAT_RATE: u32 = 50;
+/// Fatigue recovered per tick of sleeping.
+const SLEEP_RATE: u32 = 20;
+/// Anger vented per strike.
+const VENT_RATE: u32 = 100;
+/// Anger caused by being struck.
+const PROVOKED_ANGER: u32 = 50;
+/// Utility of wandering around, which any other activity must beat.
+const WANDER_UTILITY: u32 = 300;
+
+/// Creature in the dungeon.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Creature
+{
+    /// Position of the tile the creature stands on.
+    pub pos: (usize, usize),
+    /// Combat attributes.
+    pub fighter: Fighter,
+    /// Payroll record.
+    pub wage: Wage,
+    /// Needs driving the creature's decisions.
+    pub needs: Needs,
+    /// What the creature is doing.
+    pub activity: Activity,
+}
+
+/// Needs of a creature, each between 0 and 1000.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Needs
+{
+    /// Urge to eat.
+    pub hunger: u32,
+    /// Urge to sleep.
+    pub fatigue: u32,
+    /// Urge to fight.
+    pub anger: u32,
+}
+
+/// Creature activity.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum Activity
+{
+    /// About to choose an activity.
+    #[default]
+    Idle,
+    /// Walking to the food site and then eating.
+    Eat
+    {
+        /// Remaining positions to walk through, in reverse order.
+        path: Vec<(usize, usize)>,
+    },
+    /// Walking to the lair and then sleeping.
+    Sleep
+    {
+        /// Remaining positions to walk through, in reverse order.
+        path: Vec<(usize, usize)>,
+    },
+    /// Chasing and striking another creature.
+    Fight
+    {
+        /// Index of the other creature.
+        target: usize,
+    },
+}
+
+/// Need considered when choosing an activity.
+#[derive(Clone, Copy, Debug)]
+enum Need
+{
+    /// Satisfied by eating.
+    Hunger,
+    /// Satisfied by sleeping.
+    Fatigue,
+    /// Satisfied by fighting.
+    Anger,
+}
+
+/// Places where creatures satisfy their needs.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Sites
+{
+    /// Position where creatures eat.
+    pub food: Option<(usize, usize)>,
+    /// Position where creatures sleep.
+    pub lair: Option<(usize, usize)>,
+}
+
+impl Creature
+{
+    /// Creates and initializes a new idle and content creature.
+    ///
+    /// * `pos`: Position of the tile the creature stands on.
+    /// * `fighter`: Combat attributes.
+    /// * `wage`: Payroll record.
+    ///
+    /// Returns the newly created creature.
+    pub fn new(pos: (usize, usize), fighter: Fighter, wage: Wage) -> Self
+    {
+        Self { pos,
+               fighter,
+               wage,
+               needs: Needs::default(),
+               activity: Activity::Idle }
+    }
+
+    /// Moves this creature to an adjacent tile if it is still walkable.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `next`: Position of the adjacent tile.
+    ///
+    /// Returns whether the creature moved.
+    fn step(&mut self, map: &Map, next: (usize, usize)) -> bool
+    {
+        if !map.tile(next).is_some_and(Tile::is_walkable) {
+            return false;
+        }
+        self.pos = next;
+        true
+    }
+}
+
+impl Needs
+{
+    /// Grows these needs by one tick.
+    ///
+    /// * `wage`: Payroll record of the creature.
+    fn grow(&mut self, wage: &Wage)
+    {
+        self.hunger = (self.hunger + HUNGER_RATE).min(NEED_MAX);
+        self.fatigue = (self.fatigue + FATIGUE_RATE).min(NEED_MAX);
+        let mut anger = wage.missed.saturating_mul(UNPAID_ANGER);
+        if self.hunger > STARVING {
+            anger += STARVING_ANGER;
+        }
+        self.anger = if anger == 0 {
+            self.anger.saturating_sub(CALM_RATE)
+        } else {
+            self.anger.saturating_add(anger).min(NEED_MAX)
+        };
+    }
+}
+
+/// Advances every living creature by one game tick, in order, growing its
+/// needs and carrying on with its activity or choosing a new one, and then
+/// removes the creatures that died.
+///
+/// * `creatures`: Creatures in the dungeon.
+/// * `map`: Dungeon map.
+/// * `sites`: Places where creatures satisfy their needs.
+/// * `rng`: Random number generator.
+/// * `events`: Combat events to append to.
+pub fn tick_creatures(creatures: &mut Vec<Creature>, map: &Map, sites: Sites, rng: &mut Rng,
+                      events: &mut Vec<CombatEvent>)
+{
+    for idx in 0 .. creatures.len() {
+        if !creatures[idx].fighter.is_alive() {
+            continue;
+        }
+        let wage = creatures[idx].wage.clone();
+        creatures[idx].needs.grow(&wage);
+        creatures[idx].fighter.cool_down();
+        if creatures[idx].activity == Activity::Idle {
+            creatures[idx].activity = choose(creatures, idx, map, sites);
+        }
+        act(creatures, idx, map, rng, events);
+    }
+    despawn(creatures, events);
+}
+
+/// Chooses the activity that best satisfies the most pressing need of a
+/// creature.
+///
+/// * `creatures`: Creatures in the dungeon.
+/// * `idx`: Index of the creature choosing.
+/// * `map`: Dungeon map.
+/// * `sites`: Places where creatures satisfy their needs.
+///
+/// Returns the chosen activity, or [`Activity::Idle`] to wander around.
+fn choose(creatures: &[Creature], idx: usize, map: &Map, sites: Sites) -> Activity
+{
+    let this = &creatures[idx];
+    let walk = |site: Option<(usize, usize)>| {
+        let mut path = map.path(this.pos, site?)?;
+        path.reverse();
+        Some(path)
+    };
+    let mut needs = [(this.needs.hunger, Need::Hunger),
+                     (this.needs.fatigue, Need::Fatigue),
+                     (this.needs.anger, Need::Anger)];
+    // Stable sort so that ties are broken in the order above.
+    needs.sort_by_key(|&(utility, _)| Reverse(utility));
+    for (utility, need) in needs {
+        if utility <= WANDER_UTILITY {
+            break;
+        }
+        let activity = match need {
+            Need::Hunger => walk(sites.food).map(|path| Activity::Eat { path }),
+            Need::Fatigue => walk(sites.lair).map(|path| Activity::Sleep { path }),
+            Need::Anger => creatures.iter()
+                                    .enumerate()
+                                    .filter(|&(other, creature)| other != idx && creature.fighter.is_alive())
+                                    .filter(|(_, creature)| map.is_reachable(this.pos, creature.pos))
+                                    .min_by_key(|(_, creature)| map.path_cost(this.pos, creature.pos))
+                                    .map(|(target, _)| Activity::Fight { target }),
+        };
+        if let Some(activity) = activity {
+            return activity;
+        }
+    }
+    Activity::Idle
+}
+
+/// Carries on with the activity of a creature for one tick.
+///
+/// * `creatures`: Creatures in the dungeon.
+/// * `idx`: Index of the acting creature.
+/// * `map`: Dungeon map.
+/// * `rng`: Random number generator.
+/// * `events`: Combat events to append to.
+fn act(creatures: &mut [Creature], idx: usize, map: &Map, rng: &mut Rng, events: &mut Vec<CombatEvent>)
+{
+    let this = &mut creatures[idx];
+    match &mut this.activity {
+        Activity::Idle => {
+            let options = map.neighbors(this.pos)
+                             .filter(|&pos| map.tile(pos).is_some_and(Tile::is_walkable))
+                             .collect::<Vec<_>>();
+            if !options.is_empty() {
+                this.pos = options[rng.below(options.len() as u32) as usize];
+            }
+        }
+        Activity::Eat { path } | Activity::Sleep { path } if !path.is_empty() => {
+            let next = path.pop().unwrap();
+            if !this.step(map, next) {
+                this.activity = Activity::Idle;
+            }
+        }
+        Activity::Eat { .. } => {
+            this.needs.hunger = this.needs.hunger.saturating_sub(EAT_RATE);
+            if this.needs.hunger == 0 {
+                this.activity = Activity::Idle;
+            }
+        }
+        Activity::Sleep { .. } => {
+            this.needs.fatigue = this.needs.fatigue.saturating_sub(SLEEP_RATE);
+            if this.needs.fatigue == 0 {
+                this.activity = Activity::Idle;
+            }
+        }
+        Activity::Fight { target } => {
+            let target = *target;
+            let (this, other) = pair_mut(creatures, idx, target);
+            if this.needs.anger == 0 || !other.fighter.is_alive() {
+                this.activity = Activity::Idle;
+                return;
+            }
+            if distance(this.pos, other.pos) > 1 {
+                let next = map.path(this.pos, other.pos).and_then(|path| path.first().copied());
+                if !next.is_some_and(|next| this.step(map, next)) {
+                    this.activity = Activity::Idle;
+                }
+                return;
+            }
+            let Some(damage) = this.fighter.try_strike(&mut other.fighter, rng) else {
+                return;
+            };
+            events.push(CombatEvent::Hit { pos: other.pos, damage });
+            this.needs.anger = this.needs.anger.saturating_sub(VENT_RATE);
+            other.needs.anger = other.needs.anger.saturating_add(PROVOKED_ANGER).min(NEED_MAX);
+        }
+    }
+}
+
+/// Removes the dead creatures, retargeting the fights of the survivors.
+///
+/// * `creatures`: Creatures in the dungeon.
+/// * `events`: Combat events to append to.
+fn despawn(creatures: &mut Vec<Creature>, events: &mut Vec<CombatEvent>)
+{
+    let mut idx = 0;
+    while idx < creatures.len() {
+        if creatures[idx].fighter.is_alive() {
+            idx += 1;
+            continue;
+        }
+        let dead = creatures.remove(idx);
+        events.push(CombatEvent::Death { pos: dead.pos });
+        for creature in creatures.iter_mut() {
+            let Activity::Fight { target } = &mut creature.activity else {
+                continue;
+            };
+            match (*target).cmp(&idx) {
+                Ordering::Less => (),
+                Ordering::Equal => creature.activity = Activity::Idle,
+                Ordering::Greater => *target -= 1,
+            }
+        }
+    }
+}
+
+/// Computes the number of orthogonal steps between two positions.
+///
+/// * `from`: First position.
+/// * `to`: Second position.
+///
+/// Returns the computed distance.
+fn distance(from: (usize, usize), to: (usize, usize)) -> usize
+{
+    from.0.abs_diff(to.0) + from.1.abs_diff(to.1)
+}
+
+/// Borrows two distinct creatures mutably.
+///
+/// * `creatures`: Creatures in the dungeon.
+/// * `first`: Index of the first creature.
+/// * `second`: Index of the second creature.
+///
+/// Returns the two creatures in the order requested.
+fn pair_mut(creatures: &mut [Creature], first: usize, second: usize) -> (&mut Creature, &mut Creature)
+{
+    assert_ne!(first, second, "Creature can't be paired with itself");
+    if first < second {
+        let (left, right) = creatures.split_at_mut(second);
+        (&mut left[first], &mut right[0])
+    } else {
+        let (left, right) = creatures.split_at_mut(first);
+        (&mut right[0], &mut left[second])
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn corridor() -> Map
+    {
+        let mut map = Map::new(6, 1);
+        (0 .. 6).for_each(|x| map.set_tile((x, 0), Tile::Floor));
+        map
+    }
+
+    fn creature(pos: (usize, usize)) -> Creature
+    {
+        Creature::new(pos, Fighter::new(100, 10, 0), Wage::new(10))
+    }
+
+    #[test]
+    fn needs()
+    {
+        let mut needs = Needs { hunger: STARVING,
+                                fatigue: 0,
+                                anger: 10 };
+        let mut wage = Wage::new(10);
+        needs.grow(&wage);
+        assert_eq!(needs,
+                   Needs { hunger: STARVING + HUNGER_RATE,
+                           fatigue: FATIGUE_RATE,
+                           anger: 10 + STARVING_ANGER });
+        needs.hunger = 0;
+        needs.grow(&wage);
+        assert_eq!(needs.anger, 10 + STARVING_ANGER - CALM_RATE);
+        wage.missed = 3;
+        needs.grow(&wage);
+        assert_eq!(needs.anger, 10 + STARVING_ANGER - CALM_RATE + 3 * UNPAID_ANGER);
+    }
+
+    #[test]
+    fn eat()
+    {
+        let map = corridor();
+        let sites = Sites { food: Some((4, 0)),
+                            lair: Some((0, 0)) };
+        let mut creatures = Vec::from([creature((1, 0))]);
+        let mut events = Vec::new();
+        creatures[0].needs.hunger = 500;
+        creatures[0].needs.fatigue = 400;
+        let mut rng = Rng::new(1);
+        tick_creatures(&mut creatures, &map, sites, &mut rng, &mut events);
+        assert!(matches!(creatures[0].activity, Activity::Eat { .. }));
+        for _ in 0 .. 3 {
+            tick_creatures(&mut creatures, &map, sites, &mut rng, &mut events);
+        }
+        assert_eq!(creatures[0].pos, (4, 0));
+        while matches!(creatures[0].activity, Activity::Eat { .. }) {
+            tick_creatures(&mut creatures, &map, sites, &mut rng, &mut events);
+        }
+        assert_eq!(creatures[0].needs.hunger, 0);
+        // Still tired, so it goes to sleep next.
+        tick_creatures(&mut creatures, &map, sites, &mut rng, &mut events);
+        assert!(matches!(creatures[0].activity, Activity::Sleep { .. }));
+    }
+
+    #[test]
+    fn fight()
+    {
+        let map = corridor();
+        let mut creatures = Vec::from([creature((0, 0)), creature((3, 0))]);
+        let mut events = Vec::new();
+        creatures[0].needs.anger = 900;
+        // Keep the other creature asleep so that it stays put.
+        creatures[1].needs.fatigue = NEED_MAX;
+        creatures[1].activity = Activity::Sleep { path: Vec::new() };
+        let mut rng = Rng::new(1);
+        for _ in 0 .. 3 {
+            tick_creatures(&mut creatures, &map, Sites::default(), &mut rng, &mut events);
+        }
+        assert_eq!(creatures[0].activity, Activity::Fight { target: 1 });
+        assert_eq!(creatures[0].pos, (2, 0));
+        assert!(creatures[0].needs.anger < 900);
+        assert!(creatures[1].fighter.health < 100);
+        // The target calmed down a little on its own turn after being struck.
+        assert_eq!(creatures[1].needs.anger, PROVOKED_ANGER - CALM_RATE);
+        assert_eq!(events,
+                   [CombatEvent::Hit { pos: (3, 0),
+                                       damage: 100 - creatures[1].fighter.health }]);
+    }
+
+    #[test]
+    fn death()
+    {
+        let map = corridor();
+        let mut creatures = Vec::from([creature((0, 0)), creature((1, 0)), creature((4, 0))]);
+        let mut events = Vec::new();
+        creatures[0].fighter.health = 1;
+        creatures[0].needs.fatigue = NEED_MAX;
+        creatures[0].activity = Activity::Sleep { path: Vec::new() };
+        creatures[1].needs.anger = 900;
+        creatures[2].needs.anger = 900;
+        creatures[2].activity = Activity::Fight { target: 1 };
+        let mut rng = Rng::new(1);
+        tick_creatures(&mut creatures, &map, Sites::default(), &mut rng, &mut events);
+        assert!(matches!(events[..],
+                         [CombatEvent::Hit { pos: (0, 0), .. }, CombatEvent::Death { pos: (0, 0) }]));
+        assert_eq!(creatures.len(), 2);
+        assert_eq!(creatures[0].activity, Activity::Idle);
+        assert_eq!(creatures[1].activity, Activity::Fight { target: 0 });
+    }
+
+    #[test]
+    fn wander()
+    {
+        let map = corridor();
+        let mut creatures = Vec::from([creature((2, 0))]);
+        let mut events = Vec::new();
+        let mut rng = Rng::new(1);
+        tick_creatures(&mut creatures, &map, Sites::default(), &mut rng, &mut events);
+        assert_eq!(creatures[0].activity, Activity::Idle);
+        assert_eq!(distance(creatures[0].pos, (2, 0)), 1);
+    }
+}
@@ -0,0 +1,308 @@
+//! Imp workers.
+//!
+//! Imps take the nearest job on the job board whenever they are idle, walk to
+//! it along the cheapest path, work on it for a while, and then go back to
+//! being idle.  Digging out a gold seam leaves a pile of gold behind, which
+//! becomes a job for an imp to carry to the treasury.  Jobs that become
+//! unreachable midway are put back on the board for someone else, and so is
+//! gold whose way to the treasury gets blocked, which is dropped on the spot.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::mem::replace;
+
+use crate::game::{GoldPiles, Job, Jobs, Map, Tile, Treasury};
+
+/// Ticks spent digging out a tile.
+const DIG_TICKS: u32 = 6;
+/// Ticks spent claiming a tile.
+const CLAIM_TICKS: u32 = 3;
+/// Ticks spent picking up a pile of gold.
+const PICK_UP_TICKS: u32 = 1;
+/// Gold left behind by a dug out gold seam.
+const GOLD_PER_SEAM: u32 = 100;
+
+/// Imp crew.
+#[derive(Clone, Debug)]
+pub struct Imps
+{
+    /// Imps in order of spawning.
+    imps: Vec<Imp>,
+    /// Position where gold is delivered to the treasury.
+    drop: (usize, usize),
+}
+
+/// Imp worker.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Imp
+{
+    /// Position of the tile the imp stands on.
+    pos: (usize, usize),
+    /// What the imp is doing.
+    state: ImpState,
+}
+
+/// Imp behavior state.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ImpState
+{
+    /// Looking for a job.
+    Idle,
+    /// Walking to a job.
+    Walking
+    {
+        /// Job to do.
+        job: Job,
+        /// Remaining positions to walk through, in reverse order.
+        path: Vec<(usize, usize)>,
+    },
+    /// Working on a job.
+    Working
+    {
+        /// Job being done.
+        job: Job,
+        /// Remaining ticks of work.
+        ticks: u32,
+    },
+    /// Carrying gold to the treasury.
+    Hauling
+    {
+        /// Amount of gold carried.
+        amount: u32,
+        /// Remaining positions to walk through, in reverse order.
+        path: Vec<(usize, usize)>,
+    },
+}
+
+impl Imps
+{
+    /// Creates and initializes a new empty imp crew.
+    ///
+    /// * `drop`: Position where gold is delivered to the treasury.
+    ///
+    /// Returns the newly created crew.
+    pub fn new(drop: (usize, usize)) -> Self
+    {
+        Self { imps: Vec::new(), drop }
+    }
+
+    /// Adds an idle imp to the crew.
+    ///
+    /// * `pos`: Position of the tile the imp stands on.
+    pub fn spawn(&mut self, pos: (usize, usize))
+    {
+        self.imps.push(Imp { pos,
+                             state: ImpState::Idle });
+    }
+
+    /// Returns all the imps in order of spawning.
+    pub fn imps(&self) -> &[Imp]
+    {
+        &self.imps
+    }
+
+    /// Advances every imp by one game tick, in order of spawning.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `jobs`: Job board.
+    /// * `piles`: Gold piles on the floor.
+    /// * `treasury`: Treasury that gold is delivered to.
+    pub fn tick(&mut self, map: &mut Map, jobs: &mut Jobs, piles: &mut GoldPiles, treasury: &mut Treasury)
+    {
+        for imp in self.imps.iter_mut() {
+            imp.tick(map, jobs, piles, treasury, self.drop);
+        }
+    }
+}
+
+impl Imp
+{
+    /// Returns the position of the tile this imp stands on.
+    pub fn position(&self) -> (usize, usize)
+    {
+        self.pos
+    }
+
+    /// Returns what this imp is doing.
+    pub fn state(&self) -> &ImpState
+    {
+        &self.state
+    }
+
+    /// Advances this imp by one game tick.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `jobs`: Job board.
+    /// * `piles`: Gold piles on the floor.
+    /// * `treasury`: Treasury that gold is delivered to.
+    /// * `drop`: Position where gold is delivered to the treasury.
+    fn tick(&mut self, map: &mut Map, jobs: &mut Jobs, piles: &mut GoldPiles, treasury: &mut Treasury,
+            drop: (usize, usize))
+    {
+        self.state = match replace(&mut self.state, ImpState::Idle) {
+            ImpState::Idle => {
+                let Some((job, site)) = jobs.take(map, self.pos) else {
+                    return;
+                };
+                let Some(mut path) = map.path(self.pos, site) else {
+                    jobs.post(job);
+                    return;
+                };
+                path.reverse();
+                ImpState::Walking { job, path }
+            }
+            ImpState::Walking { job, .. } | ImpState::Working { job, .. } if !job.is_valid(map) => ImpState::Idle,
+            ImpState::Walking { job, path } if path.is_empty() => {
+                let ticks = match job {
+                    Job::Dig(_) => DIG_TICKS,
+                    Job::Claim(_) => CLAIM_TICKS,
+                    Job::Carry(_) => PICK_UP_TICKS,
+                };
+                ImpState::Working { job, ticks }
+            }
+            ImpState::Walking { job, mut path } => {
+                let next = path.pop().unwrap();
+                if !self.step(map, next) {
+                    jobs.post(job);
+                    return;
+                }
+                ImpState::Walking { job, path }
+            }
+            ImpState::Working { job, ticks } if ticks > 1 => ImpState::Working { job, ticks: ticks - 1 },
+            ImpState::Working { job, .. } => self.finish(job, map, jobs, piles, drop),
+            ImpState::Hauling { amount, mut path } => {
+                let Some(next) = path.pop() else {
+                    treasury.deposit(amount);
+                    return;
+                };
+                if !self.step(map, next) {
+                    piles.drop(self.pos, amount);
+                    jobs.post(Job::Carry(self.pos));
+                    return;
+                }
+                ImpState::Hauling { amount, path }
+            }
+        };
+    }
+
+    /// Moves this imp to an adjacent tile if it is still walkable.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `next`: Position of the adjacent tile.
+    ///
+    /// Returns whether the imp moved.
+    fn step(&mut self, map: &Map, next: (usize, usize)) -> bool
+    {
+        if !map.tile(next).is_some_and(Tile::is_walkable) {
+            return false;
+        }
+        self.pos = next;
+        true
+    }
+
+    /// Completes a job, posting any follow-up jobs.
+    ///
+    /// * `job`: Job to complete.
+    /// * `map`: Dungeon map.
+    /// * `jobs`: Job board.
+    /// * `piles`: Gold piles on the floor.
+    /// * `drop`: Position where gold is delivered to the treasury.
+    ///
+    /// Returns the state of this imp after completing the job.
+    fn finish(&mut self, job: Job, map: &mut Map, jobs: &mut Jobs, piles: &mut GoldPiles, drop: (usize, usize))
+              -> ImpState
+    {
+        match job {
+            Job::Dig(pos) => {
+                if map.dig(pos) == Some(Tile::Gold) {
+                    piles.drop(pos, GOLD_PER_SEAM);
+                    jobs.post(Job::Carry(pos));
+                }
+                jobs.post(Job::Claim(pos));
+                ImpState::Idle
+            }
+            Job::Claim(pos) => {
+                map.set_tile(pos, Tile::Claimed);
+                ImpState::Idle
+            }
+            Job::Carry(pos) => {
+                let amount = piles.pick_up(pos);
+                if amount == 0 {
+                    return ImpState::Idle;
+                }
+                let Some(mut path) = map.path(self.pos, drop) else {
+                    piles.drop(pos, amount);
+                    jobs.post(job);
+                    return ImpState::Idle;
+                };
+                path.reverse();
+                ImpState::Hauling { amount, path }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn dig_and_haul()
+    {
+        // A corridor with the treasury at the left end and a gold seam at the
+        // right end.
+        let mut map = Map::new(4, 1);
+        map.set_tile((0, 0), Tile::Claimed);
+        map.set_tile((1, 0), Tile::Floor);
+        map.set_tile((3, 0), Tile::Gold);
+        let mut jobs = Jobs::new();
+        let mut treasury = Treasury::new(0, 0);
+        let mut piles = GoldPiles::new();
+        let mut imps = Imps::new((0, 0));
+        imps.spawn((0, 0));
+        jobs.designate((2, 0));
+        jobs.designate((3, 0));
+        let mut ticks = 0;
+        while treasury.gold() == 0 {
+            imps.tick(&mut map, &mut jobs, &mut piles, &mut treasury);
+            ticks += 1;
+            assert!(ticks < 100, "Imp never delivered the gold");
+        }
+        assert_eq!(treasury.gold(), GOLD_PER_SEAM);
+        assert!(piles.piles().is_empty());
+        assert_eq!(map.tile((2, 0)), Some(Tile::Claimed));
+        assert_eq!(map.tile((3, 0)), Some(Tile::Floor));
+        assert_eq!(imps.imps()[0].position(), (0, 0));
+        // The last claim is still pending, and then there's nothing left to do.
+        for _ in 0 .. 20 {
+            imps.tick(&mut map, &mut jobs, &mut piles, &mut treasury);
+        }
+        assert_eq!(map.tile((1, 0)), Some(Tile::Floor));
+        assert_eq!(map.tile((3, 0)), Some(Tile::Claimed));
+        assert_eq!(*imps.imps()[0].state(), ImpState::Idle);
+        assert_eq!(jobs.pending(), 0);
+    }
+
+    #[test]
+    fn blocked()
+    {
+        let mut map = Map::new(3, 1);
+        (0 .. 3).for_each(|x| map.set_tile((x, 0), Tile::Floor));
+        let mut jobs = Jobs::new();
+        let mut treasury = Treasury::new(0, 0);
+        let mut piles = GoldPiles::new();
+        let mut imps = Imps::new((0, 0));
+        imps.spawn((0, 0));
+        jobs.post(Job::Claim((2, 0)));
+        imps.tick(&mut map, &mut jobs, &mut piles, &mut treasury);
+        assert!(matches!(imps.imps()[0].state(), ImpState::Walking { .. }));
+        // Cave in the corridor before the imp gets through.
+        map.set_tile((1, 0), Tile::Rock);
+        imps.tick(&mut map, &mut jobs, &mut piles, &mut treasury);
+        assert_eq!(*imps.imps()[0].state(), ImpState::Idle);
+        assert_eq!(imps.imps()[0].position(), (0, 0));
+        assert_eq!(jobs.pending(), 1);
+    }
+}
@@ -0,0 +1,7 @@
+//! Creature behavior.
+
+mod creature;
+mod imp;
+
+pub use self::creature::*;
+pub use self::imp::*;
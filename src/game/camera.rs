@@ -0,0 +1,279 @@
+//! Camera controller.
+//!
+//! Orbits, pans, and zooms a camera around a focus point on the ground in
+//! response to gesture deltas.  Gestures set the velocity of the camera
+//! instead of moving it directly, which smooths out noisy samples and lets the
+//! camera coast to a stop after the fingers are lifted.
+
+use core::f32::consts::{PI, TAU};
+use core::simd::f32x4;
+use core::simd::prelude::*;
+
+use crate::math::{Aabb, Angle, Quaternion, Transform};
+use crate::simd::SimdFloatExtra;
+
+/// Rate per second at which the velocity converges to the gesture velocity.
+const RESPONSE: f32 = 20.0;
+/// Rate per second at which the velocity decays once gestures stop.
+const DAMPING: f32 = 4.0;
+
+/// Constraints on the camera.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraLimits
+{
+    /// Minimum pitch in radians, with negative values looking down.
+    pub min_pitch: f32,
+    /// Maximum pitch in radians.
+    pub max_pitch: f32,
+    /// Minimum distance from the focus point.
+    pub min_distance: f32,
+    /// Maximum distance from the focus point.
+    pub max_distance: f32,
+    /// Region of the map that the focus point must stay in.
+    pub bounds: Aabb,
+}
+
+/// Camera orbiting a focus point.
+#[derive(Debug)]
+pub struct Camera
+{
+    /// Constraints.
+    limits: CameraLimits,
+    /// Point looked at.
+    focus: f32x4,
+    /// Rotation around the vertical axis in radians.
+    yaw: f32,
+    /// Rotation around the horizontal axis in radians.
+    pitch: f32,
+    /// Distance from the focus point.
+    distance: f32,
+    /// Yaw, pitch, and relative zoom per second.
+    vel: f32x4,
+    /// Focus point displacement per second.
+    pan_vel: f32x4,
+    /// Yaw, pitch, and relative zoom requested since the last update.
+    input: f32x4,
+    /// Focus point displacement requested since the last update.
+    pan_input: f32x4,
+    /// Whether any gesture was applied since the last update.
+    touched: bool,
+}
+
+impl Camera
+{
+    /// Creates and initializes a new camera.
+    ///
+    /// * `focus`: Point to look at.
+    /// * `yaw`: Rotation around the vertical axis in radians.
+    /// * `pitch`: Rotation around the horizontal axis in radians.
+    /// * `distance`: Distance from the focus point.
+    /// * `limits`: Constraints, which the initial state is clamped to.
+    ///
+    /// Returns the newly created camera.
+    pub fn new(focus: f32x4, yaw: f32, pitch: f32, distance: f32, limits: CameraLimits) -> Self
+    {
+        let zero = f32x4::splat(0.0);
+        let mut this = Self { limits,
+                              focus,
+                              yaw,
+                              pitch,
+                              distance,
+                              vel: zero,
+                              pan_vel: zero,
+                              input: zero,
+                              pan_input: zero,
+                              touched: false };
+        this.constrain();
+        this
+    }
+
+    /// Rotates the camera around the focus point.
+    ///
+    /// * `yaw`: Angle to rotate around the vertical axis in radians.
+    /// * `pitch`: Angle to rotate around the horizontal axis in radians.
+    pub fn orbit(&mut self, yaw: f32, pitch: f32)
+    {
+        self.input += f32x4::from_array([yaw, pitch, 0.0, 0.0]);
+        self.touched = true;
+    }
+
+    /// Moves the focus point along the ground.
+    ///
+    /// * `right`: Distance to move towards the right of the view.
+    /// * `forward`: Distance to move away from the camera.
+    ///
+    /// Both distances are proportional to the distance from the focus point so
+    /// that panning feels the same at every zoom level.
+    pub fn pan(&mut self, right: f32, forward: f32)
+    {
+        let rot = self.yaw_rotation();
+        let right = (f32x4::from_array([1.0, 0.0, 0.0, 0.0]) * rot).mul_scalar(right);
+        let forward = (f32x4::from_array([0.0, 0.0, -1.0, 0.0]) * rot).mul_scalar(forward);
+        self.pan_input += (right + forward).mul_scalar(self.distance);
+        self.touched = true;
+    }
+
+    /// Moves the camera towards or away from the focus point.
+    ///
+    /// * `factor`: Factor to divide the distance by, so that values above 1
+    ///   zoom in.
+    pub fn zoom(&mut self, factor: f32)
+    {
+        if factor <= 0.0 || !factor.is_finite() {
+            return;
+        }
+        self.input[2] += factor.recip() - 1.0;
+        self.touched = true;
+    }
+
+    /// Advances the camera by a time step, applying the gestures since the
+    /// last update or coasting if there were none.
+    ///
+    /// * `delta`: Time step in seconds.
+    pub fn update(&mut self, delta: f32)
+    {
+        if delta <= 0.0 {
+            return;
+        }
+        if self.touched {
+            let weight = f32x4::splat((delta * RESPONSE).min(1.0));
+            let rate = f32x4::splat(delta.recip());
+            self.vel += (self.input * rate - self.vel) * weight;
+            self.pan_vel += (self.pan_input * rate - self.pan_vel) * weight;
+        } else {
+            let decay = f32x4::splat((1.0 - delta * DAMPING).max(0.0));
+            self.vel *= decay;
+            self.pan_vel *= decay;
+        }
+        let motion = self.vel.mul_scalar(delta);
+        self.yaw += motion[0];
+        self.pitch += motion[1];
+        self.distance *= 1.0 + motion[2];
+        self.focus += self.pan_vel.mul_scalar(delta);
+        self.input = f32x4::splat(0.0);
+        self.pan_input = f32x4::splat(0.0);
+        self.touched = false;
+        self.constrain();
+    }
+
+    /// Returns the camera to world transformation.
+    pub fn transform(&self) -> Transform
+    {
+        let rot = Quaternion::from_euler(self.yaw, self.pitch, 0.0);
+        let offset = f32x4::from_array([0.0, 0.0, self.distance, 0.0]) * rot;
+        let pos = (self.focus + offset).xyz1();
+        Transform::from_components(pos, rot, 1.0)
+    }
+
+    /// Returns the point looked at.
+    pub fn focus(&self) -> f32x4
+    {
+        self.focus
+    }
+
+    /// Returns the rotation around the vertical axis.
+    fn yaw_rotation(&self) -> Quaternion
+    {
+        let axis = f32x4::from_array([0.0, 1.0, 0.0, 0.0]);
+        Quaternion::from_axis_angle(axis, Angle::from(self.yaw))
+    }
+
+    /// Clamps the state to the limits, stopping any motion that runs into
+    /// them.
+    fn constrain(&mut self)
+    {
+        let limits = self.limits;
+        if self.yaw > PI {
+            self.yaw -= TAU;
+        } else if self.yaw < -PI {
+            self.yaw += TAU;
+        }
+        let pitch = self.pitch.clamp(limits.min_pitch, limits.max_pitch);
+        if pitch != self.pitch {
+            self.pitch = pitch;
+            self.vel[1] = 0.0;
+        }
+        let distance = self.distance.clamp(limits.min_distance, limits.max_distance);
+        if distance != self.distance {
+            self.distance = distance;
+            self.vel[2] = 0.0;
+        }
+        let focus = self.focus.simd_clamp(limits.bounds.min(), limits.bounds.max());
+        let clamped = focus.simd_ne(self.focus);
+        self.pan_vel = clamped.select(f32x4::splat(0.0), self.pan_vel);
+        self.focus = focus;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn limits() -> CameraLimits
+    {
+        let min = f32x4::from_array([0.0, 0.0, 0.0, 0.0]);
+        let max = f32x4::from_array([10.0, 0.0, 10.0, 0.0]);
+        CameraLimits { min_pitch: -PI / 2.0 + 0.1,
+                       max_pitch: -0.1,
+                       min_distance: 2.0,
+                       max_distance: 20.0,
+                       bounds: Aabb::new(min, max) }
+    }
+
+    #[track_caller]
+    fn expect_roughly(actual: f32, expected: f32)
+    {
+        assert!((actual - expected).abs() < 1.0 / 256.0,
+                "Value {actual} isn't anywhere close to {expected}");
+    }
+
+    #[test]
+    fn transform()
+    {
+        let focus = f32x4::from_array([5.0, 0.0, 5.0, 0.0]);
+        let cam = Camera::new(focus, 0.0, -PI / 4.0, 4.0, limits());
+        let pos = cam.transform().position();
+        let side = 4.0 * 0.5f32.sqrt();
+        expect_roughly(pos[0], 5.0);
+        expect_roughly(pos[1], side);
+        expect_roughly(pos[2], 5.0 + side);
+        expect_roughly(pos[3], 1.0);
+    }
+
+    #[test]
+    fn constraints()
+    {
+        let focus = f32x4::from_array([5.0, 0.0, 5.0, 0.0]);
+        let mut cam = Camera::new(focus, 0.0, -1.0, 10.0, limits());
+        cam.orbit(0.0, 5.0);
+        cam.zoom(0.01);
+        cam.pan(0.0, 100.0);
+        cam.update(0.1);
+        let pos = cam.transform().position();
+        // The camera is as far and as level as allowed behind the clamped focus.
+        expect_roughly(cam.focus()[2], 0.0);
+        expect_roughly(pos[1], 20.0 * 0.1f32.sin());
+        expect_roughly(pos[2], 20.0 * 0.1f32.cos());
+    }
+
+    #[test]
+    fn inertia()
+    {
+        let focus = f32x4::from_array([5.0, 0.0, 5.0, 0.0]);
+        let mut cam = Camera::new(focus, 0.0, -1.0, 10.0, limits());
+        cam.pan(0.01, 0.0);
+        cam.update(0.05);
+        let first = cam.focus()[0] - 5.0;
+        assert!(first > 0.0);
+        cam.update(0.05);
+        let second = cam.focus()[0] - 5.0 - first;
+        assert!(second > 0.0 && second < first);
+        for _ in 0 .. 100 {
+            cam.update(0.05);
+        }
+        let last = cam.focus()[0];
+        cam.update(0.05);
+        expect_roughly(cam.focus()[0], last);
+    }
+}
@@ -0,0 +1,201 @@
+//! Creature combat.
+//!
+//! Fighters strike each other at most once every few game ticks for a random
+//! amount of damage reduced by the target's defense.  Hits and deaths are
+//! reported as events so that the audio and rendering code can react to them
+//! without the game rules knowing about either.
+
+use super::rng::Rng;
+
+/// Game ticks that a fighter has to wait between strikes.
+const COOLDOWN_TICKS: u32 = 4;
+
+/// Combat statistics of a creature.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Fighter
+{
+    /// Remaining health.
+    pub health: u32,
+    /// Maximum damage per hit.
+    pub attack: u32,
+    /// Damage absorbed from each hit.
+    pub defense: u32,
+    /// Game ticks left until the fighter can strike again.
+    pub cooldown: u32,
+}
+
+/// Something that happened during a fight.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CombatEvent
+{
+    /// A fighter was struck.
+    Hit
+    {
+        /// Position of the tile the struck fighter stands on.
+        pos: (usize, usize),
+        /// Damage dealt.
+        damage: u32,
+    },
+    /// A fighter died and was removed from the dungeon.
+    Death
+    {
+        /// Position of the tile the fighter died on.
+        pos: (usize, usize),
+    },
+}
+
+impl Fighter
+{
+    /// Creates and initializes a new fighter.
+    ///
+    /// * `health`: Initial health.
+    /// * `attack`: Maximum damage per hit.
+    /// * `defense`: Damage absorbed from each hit.
+    ///
+    /// Returns the newly created fighter.
+    pub const fn new(health: u32, attack: u32, defense: u32) -> Self
+    {
+        Self { health,
+               attack,
+               defense,
+               cooldown: 0 }
+    }
+
+    /// Returns whether this fighter is still alive.
+    pub fn is_alive(&self) -> bool
+    {
+        self.health > 0
+    }
+
+    /// Returns whether this fighter can strike.
+    pub fn is_ready(&self) -> bool
+    {
+        self.cooldown == 0
+    }
+
+    /// Advances the cooldown of this fighter by one game tick.
+    pub fn cool_down(&mut self)
+    {
+        self.cooldown = self.cooldown.saturating_sub(1);
+    }
+
+    /// Strikes another fighter regardless of the cooldown.
+    ///
+    /// * `target`: Fighter being struck.
+    /// * `rng`: Random number generator.
+    ///
+    /// Returns the damage dealt.
+    pub fn strike(&self, target: &mut Self, rng: &mut Rng) -> u32
+    {
+        let damage = roll_damage(self.attack, target.defense, rng);
+        target.health = target.health.saturating_sub(damage);
+        damage
+    }
+
+    /// Strikes another fighter if the cooldown has elapsed, and restarts it.
+    ///
+    /// * `target`: Fighter being struck.
+    /// * `rng`: Random number generator.
+    ///
+    /// Returns the damage dealt, or nothing if this fighter isn't ready.
+    pub fn try_strike(&mut self, target: &mut Self, rng: &mut Rng) -> Option<u32>
+    {
+        if !self.is_ready() {
+            return None;
+        }
+        self.cooldown = COOLDOWN_TICKS;
+        Some(self.strike(target, rng))
+    }
+}
+
+/// Rolls the damage of a hit.  Hits always deal at least one point of damage
+/// so that fights are guaranteed to end.
+///
+/// * `attack`: Maximum damage of the hit.
+/// * `defense`: Damage absorbed by the target.
+/// * `rng`: Random number generator.
+///
+/// Returns the rolled damage.
+pub fn roll_damage(attack: u32, defense: u32, rng: &mut Rng) -> u32
+{
+    rng.below(attack + 1).saturating_sub(defense).max(1)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Fights two creatures to the death, alternating strikes starting with the
+    /// first.
+    ///
+    /// * `first`: First fighter.
+    /// * `second`: Second fighter.
+    /// * `rng`: Random number generator.
+    ///
+    /// Returns whether the first fighter won.
+    fn duel(first: &mut Fighter, second: &mut Fighter, rng: &mut Rng) -> bool
+    {
+        loop {
+            first.strike(second, rng);
+            if !second.is_alive() {
+                return true;
+            }
+            second.strike(first, rng);
+            if !first.is_alive() {
+                return false;
+            }
+        }
+    }
+
+    #[test]
+    fn deterministic()
+    {
+        for seed in 0 .. 32 {
+            let (mut a0, mut b0) = (Fighter::new(50, 12, 2), Fighter::new(60, 10, 3));
+            let (mut a1, mut b1) = (a0.clone(), b0.clone());
+            let won0 = duel(&mut a0, &mut b0, &mut Rng::new(seed));
+            let won1 = duel(&mut a1, &mut b1, &mut Rng::new(seed));
+            assert_eq!(won0, won1);
+            assert_eq!(a0, a1);
+            assert_eq!(b0, b1);
+        }
+    }
+
+    #[test]
+    fn damage()
+    {
+        let mut rng = Rng::new(3);
+        let (min, max) = (0 .. 1000).map(|_| roll_damage(10, 4, &mut rng))
+                                    .fold((u32::MAX, 0), |(min, max), damage| (min.min(damage), max.max(damage)));
+        // Every roll up to the defense is absorbed down to the minimum.
+        assert_eq!((min, max), (1, 6));
+        assert!((0 .. 100).all(|_| roll_damage(5, 5, &mut rng) == 1));
+        assert!((0 .. 100).all(|_| roll_damage(0, 0, &mut rng) == 1));
+    }
+
+    #[test]
+    fn cooldown()
+    {
+        let mut rng = Rng::new(5);
+        let mut first = Fighter::new(100, 10, 0);
+        let mut second = Fighter::new(100, 10, 0);
+        let damage = first.try_strike(&mut second, &mut rng).unwrap();
+        assert_eq!(second.health, 100 - damage);
+        assert!(!first.is_ready());
+        for _ in 0 .. COOLDOWN_TICKS {
+            assert_eq!(first.try_strike(&mut second, &mut rng), None);
+            first.cool_down();
+        }
+        assert!(first.try_strike(&mut second, &mut rng).is_some());
+    }
+
+    #[test]
+    fn terminates()
+    {
+        let mut first = Fighter::new(10, 0, 100);
+        let mut second = Fighter::new(10, 0, 100);
+        assert!(duel(&mut first, &mut second, &mut Rng::new(1)));
+        assert_eq!(second.health, 0);
+    }
+}
@@ -0,0 +1,316 @@
+//! Dungeon economy.
+//!
+//! The keeper pays for things with gold, which imps dig out of gold seams and
+//! carry to the treasury in piles, and with mana, which every claimed tile
+//! produces on every game tick.  Every change to the treasury other than the
+//! steady trickle of mana is recorded as an event for the interface to report.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+/// Mana produced by each claimed tile per game tick.
+const MANA_PER_TILE: u32 = 1;
+/// Maximum amount of mana in store.
+const MANA_MAX: u32 = 100000;
+
+/// Dungeon treasury.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Treasury
+{
+    /// Gold in store.
+    gold: u32,
+    /// Mana in store.
+    mana: u32,
+    /// Events since they were last drained.
+    events: Vec<EconomyEvent>,
+}
+
+/// Price of something in gold and mana.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Cost
+{
+    /// Gold required.
+    pub gold: u32,
+    /// Mana required.
+    pub mana: u32,
+}
+
+/// Change to the treasury.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EconomyEvent
+{
+    /// Gold was delivered.
+    Deposited(u32),
+    /// A cost was paid.
+    Spent(Cost),
+    /// A cost was paid back.
+    Refunded(Cost),
+    /// A cost could not be afforded.
+    Denied(Cost),
+}
+
+/// Piles of gold lying on the dungeon floor waiting to be carried to the
+/// treasury.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GoldPiles
+{
+    /// Piles in order of dropping.
+    piles: Vec<GoldPile>,
+}
+
+/// Pile of gold.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GoldPile
+{
+    /// Position of the tile the pile lies on.
+    pub pos: (usize, usize),
+    /// Amount of gold in the pile.
+    pub amount: u32,
+}
+
+/// Payroll record of a creature.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Wage
+{
+    /// Gold owed each payday.
+    pub amount: u32,
+    /// Consecutive paydays without pay.
+    pub missed: u32,
+}
+
+impl Treasury
+{
+    /// Creates and initializes a new treasury.
+    ///
+    /// * `gold`: Initial amount of gold.
+    /// * `mana`: Initial amount of mana, which is capped to the maximum.
+    ///
+    /// Returns the newly created treasury.
+    pub const fn new(gold: u32, mana: u32) -> Self
+    {
+        let mana = if mana > MANA_MAX { MANA_MAX } else { mana };
+        Self { gold,
+               mana,
+               events: Vec::new() }
+    }
+
+    /// Returns the amount of gold in store.
+    pub fn gold(&self) -> u32
+    {
+        self.gold
+    }
+
+    /// Returns the amount of mana in store.
+    pub fn mana(&self) -> u32
+    {
+        self.mana
+    }
+
+    /// Deposits gold.
+    ///
+    /// * `amount`: Amount of gold to deposit.
+    pub fn deposit(&mut self, amount: u32)
+    {
+        self.gold = self.gold.saturating_add(amount);
+        self.events.push(EconomyEvent::Deposited(amount));
+    }
+
+    /// Withdraws gold if enough is available.
+    ///
+    /// * `amount`: Amount of gold to withdraw.
+    ///
+    /// Returns whether the gold was withdrawn.
+    pub fn withdraw(&mut self, amount: u32) -> bool
+    {
+        self.spend(Cost { gold: amount, mana: 0 })
+    }
+
+    /// Pays a cost if both the gold and the mana are available, paying
+    /// nothing otherwise.
+    ///
+    /// * `cost`: Cost to pay.
+    ///
+    /// Returns whether the cost was paid.
+    pub fn spend(&mut self, cost: Cost) -> bool
+    {
+        if cost.gold > self.gold || cost.mana > self.mana {
+            self.events.push(EconomyEvent::Denied(cost));
+            return false;
+        }
+        self.gold -= cost.gold;
+        self.mana -= cost.mana;
+        self.events.push(EconomyEvent::Spent(cost));
+        true
+    }
+
+    /// Pays back a cost, such as when something bought is sold or cancelled.
+    ///
+    /// * `cost`: Cost to pay back.
+    pub fn refund(&mut self, cost: Cost)
+    {
+        self.gold = self.gold.saturating_add(cost.gold);
+        self.mana = self.mana.saturating_add(cost.mana).min(MANA_MAX);
+        self.events.push(EconomyEvent::Refunded(cost));
+    }
+
+    /// Adds the mana produced by claimed tiles over one game tick.
+    ///
+    /// * `claimed`: Number of claimed tiles.
+    pub fn produce_mana(&mut self, claimed: usize)
+    {
+        let amount = u32::try_from(claimed).unwrap_or(u32::MAX).saturating_mul(MANA_PER_TILE);
+        self.mana = self.mana.saturating_add(amount).min(MANA_MAX);
+    }
+
+    /// Takes the events recorded since the last call.
+    ///
+    /// Returns an iterator over the events in the order in which they happened.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = EconomyEvent> + '_
+    {
+        self.events.drain(..)
+    }
+
+    /// Pays the wages of all creatures in order, skipping those that can no
+    /// longer be afforded.
+    ///
+    /// * `wages`: Payroll records of all creatures.
+    ///
+    /// Returns the number of creatures that were paid.
+    pub fn pay<'a>(&mut self, wages: impl IntoIterator<Item = &'a mut Wage>) -> usize
+    {
+        let mut paid = 0;
+        for wage in wages {
+            if self.withdraw(wage.amount) {
+                wage.missed = 0;
+                paid += 1;
+            } else {
+                wage.missed += 1;
+            }
+        }
+        paid
+    }
+}
+
+impl GoldPiles
+{
+    /// Creates and initializes a new empty set of piles.
+    ///
+    /// Returns the newly created set.
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Drops gold on a tile, adding it to the pile already there if any.
+    ///
+    /// * `pos`: Position of the tile.
+    /// * `amount`: Amount of gold to drop.
+    pub fn drop(&mut self, pos: (usize, usize), amount: u32)
+    {
+        if amount == 0 {
+            return;
+        }
+        match self.piles.iter_mut().find(|pile| pile.pos == pos) {
+            Some(pile) => pile.amount = pile.amount.saturating_add(amount),
+            None => self.piles.push(GoldPile { pos, amount }),
+        }
+    }
+
+    /// Picks up the whole pile lying on a tile.
+    ///
+    /// * `pos`: Position of the tile.
+    ///
+    /// Returns the amount of gold picked up, which is zero if there was no
+    /// pile.
+    pub fn pick_up(&mut self, pos: (usize, usize)) -> u32
+    {
+        let Some(idx) = self.piles.iter().position(|pile| pile.pos == pos) else {
+            return 0;
+        };
+        self.piles.remove(idx).amount
+    }
+
+    /// Returns all the piles in order of dropping.
+    pub fn piles(&self) -> &[GoldPile]
+    {
+        &self.piles
+    }
+}
+
+impl Wage
+{
+    /// Creates and initializes a new payroll record.
+    ///
+    /// * `amount`: Gold owed each payday.
+    ///
+    /// Returns the newly created record.
+    pub const fn new(amount: u32) -> Self
+    {
+        Self { amount, missed: 0 }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn pay_wages()
+    {
+        let mut treasury = Treasury::new(250, 0);
+        let mut wages = [Wage::new(100), Wage::new(200), Wage::new(50)];
+        assert_eq!(treasury.pay(&mut wages), 2);
+        assert_eq!(treasury.gold(), 100);
+        assert_eq!(wages[0].missed, 0);
+        assert_eq!(wages[1].missed, 1);
+        assert_eq!(wages[2].missed, 0);
+        treasury.deposit(250);
+        assert_eq!(treasury.pay(&mut wages), 3);
+        assert_eq!(treasury.gold(), 0);
+        assert_eq!(wages[1].missed, 0);
+    }
+
+    #[test]
+    fn spend_and_refund()
+    {
+        let mut treasury = Treasury::new(100, 0);
+        treasury.produce_mana(30);
+        treasury.produce_mana(30);
+        assert_eq!(treasury.mana(), 60 * MANA_PER_TILE);
+        let cheap = Cost { gold: 80, mana: 50 };
+        let dear = Cost { gold: 30, mana: 20 };
+        assert!(treasury.spend(cheap));
+        assert!(!treasury.spend(dear));
+        assert_eq!((treasury.gold(), treasury.mana()), (20, 10));
+        treasury.refund(cheap);
+        treasury.deposit(5);
+        assert_eq!((treasury.gold(), treasury.mana()), (105, 60));
+        let events = treasury.drain_events().collect::<Vec<_>>();
+        assert_eq!(events,
+                   [EconomyEvent::Spent(cheap),
+                    EconomyEvent::Denied(dear),
+                    EconomyEvent::Refunded(cheap),
+                    EconomyEvent::Deposited(5)]);
+        assert_eq!(treasury.drain_events().count(), 0);
+        treasury.produce_mana(usize::MAX);
+        assert_eq!(treasury.mana(), MANA_MAX);
+    }
+
+    #[test]
+    fn piles()
+    {
+        let mut piles = GoldPiles::new();
+        piles.drop((1, 2), 100);
+        piles.drop((3, 4), 0);
+        piles.drop((1, 2), 50);
+        assert_eq!(piles.piles(),
+                   [GoldPile { pos: (1, 2),
+                               amount: 150 }]);
+        assert_eq!(piles.pick_up((3, 4)), 0);
+        assert_eq!(piles.pick_up((1, 2)), 150);
+        assert_eq!(piles.pick_up((1, 2)), 0);
+        assert!(piles.piles().is_empty());
+    }
+}
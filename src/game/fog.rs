@@ -0,0 +1,193 @@
+//! Fog of war.
+//!
+//! Tracks which tiles the keeper's minions can currently see and which they
+//! have ever seen.  Every imp and creature sees the tiles within a fixed
+//! radius to which it has an unobstructed line of sight, so digging out a
+//! tunnel or roaming through one reveals its surroundings, and tiles out of
+//! sight remember their last explored state.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::map::{Map, Tile};
+
+/// Radius in tiles that minions can see.
+const SIGHT_RADIUS: usize = 5;
+/// Brightness of tiles that have been explored but aren't currently visible.
+const EXPLORED_SHADE: f32 = 0.5;
+
+/// Visibility of the dungeon.
+#[derive(Clone, Debug)]
+pub struct Fog
+{
+    /// Width of the map in tiles.
+    width: usize,
+    /// Visibility of each tile in row-major order.
+    tiles: Vec<Visibility>,
+}
+
+/// Visibility of a tile.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Visibility
+{
+    /// Never seen.
+    Unexplored,
+    /// Seen before but not currently in sight.
+    Explored,
+    /// Currently in sight.
+    Visible,
+}
+
+impl Fog
+{
+    /// Creates and initializes a new fog covering the whole map.
+    ///
+    /// * `map`: Dungeon map.
+    ///
+    /// Returns the newly created fog.
+    pub fn new(map: &Map) -> Self
+    {
+        Self { width: map.width(),
+               tiles: vec![Visibility::Unexplored; map.width() * map.height()] }
+    }
+
+    /// Recomputes the tiles in sight.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `viewers`: Positions of the minions looking around.
+    pub fn update(&mut self, map: &Map, viewers: impl IntoIterator<Item = (usize, usize)>)
+    {
+        for vis in self.tiles.iter_mut().filter(|vis| **vis == Visibility::Visible) {
+            *vis = Visibility::Explored;
+        }
+        for viewer in viewers {
+            let xrange = viewer.0.saturating_sub(SIGHT_RADIUS) ..= viewer.0 + SIGHT_RADIUS;
+            for y in viewer.1.saturating_sub(SIGHT_RADIUS) ..= viewer.1 + SIGHT_RADIUS {
+                for x in xrange.clone() {
+                    let pos = (x, y);
+                    // Compared against the radius plus half a tile, squared and rounded down.
+                    let sq_dist = x.abs_diff(viewer.0).pow(2) + y.abs_diff(viewer.1).pow(2);
+                    if map.tile(pos).is_none() || sq_dist > SIGHT_RADIUS * (SIGHT_RADIUS + 1) {
+                        continue;
+                    }
+                    if is_in_sight(map, viewer, pos) {
+                        self.tiles[y * self.width + x] = Visibility::Visible;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the visibility of a tile, treating tiles outside the map as
+    /// unexplored.
+    ///
+    /// * `pos`: Position of the tile.
+    pub fn visibility(&self, pos: (usize, usize)) -> Visibility
+    {
+        if pos.0 >= self.width {
+            return Visibility::Unexplored;
+        }
+        self.tiles
+            .get(pos.1 * self.width + pos.0)
+            .copied()
+            .unwrap_or(Visibility::Unexplored)
+    }
+
+    /// Returns the brightness that a tile should be drawn with, from 0 for
+    /// tiles that should be skipped to 1 for tiles in sight.
+    ///
+    /// * `pos`: Position of the tile.
+    pub fn shade(&self, pos: (usize, usize)) -> f32
+    {
+        match self.visibility(pos) {
+            Visibility::Unexplored => 0.0,
+            Visibility::Explored => EXPLORED_SHADE,
+            Visibility::Visible => 1.0,
+        }
+    }
+
+    /// Returns a tile if it has been explored, hiding the contents of the rest
+    /// of the map.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `pos`: Position of the tile.
+    pub fn explored_tile(&self, map: &Map, pos: (usize, usize)) -> Option<Tile>
+    {
+        if self.visibility(pos) == Visibility::Unexplored {
+            return None;
+        }
+        map.tile(pos)
+    }
+}
+
+/// Checks whether a tile is in the line of sight of a viewer, which requires
+/// every tile strictly between them to be walkable.
+///
+/// * `map`: Dungeon map.
+/// * `from`: Position of the viewer.
+/// * `to`: Position of the tile.
+///
+/// Returns the result of the check.
+fn is_in_sight(map: &Map, from: (usize, usize), to: (usize, usize)) -> bool
+{
+    // Bresenham's line algorithm.
+    let (mut x, mut y) = (from.0 as isize, from.1 as isize);
+    let (tx, ty) = (to.0 as isize, to.1 as isize);
+    let (dx, dy) = ((tx - x).abs(), -(ty - y).abs());
+    let (sx, sy) = ((tx - x).signum(), (ty - y).signum());
+    let mut err = dx + dy;
+    loop {
+        let double = err * 2;
+        if double >= dy {
+            err += dy;
+            x += sx;
+        }
+        if double <= dx {
+            err += dx;
+            y += sy;
+        }
+        if (x, y) == (tx, ty) {
+            return true;
+        }
+        if !map.tile((x as usize, y as usize)).is_some_and(Tile::is_walkable) {
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn reveal()
+    {
+        // A corridor leading right from the left edge of the map.
+        let mut map = Map::new(12, 3);
+        (0 .. 3).for_each(|x| map.set_tile((x, 1), Tile::Floor));
+        let mut fog = Fog::new(&map);
+        fog.update(&map, [(0, 1)]);
+        // The walls of the corridor and its far end are visible, but not what
+        // lies behind them.
+        assert_eq!(fog.visibility((0, 0)), Visibility::Visible);
+        assert_eq!(fog.visibility((3, 1)), Visibility::Visible);
+        assert_eq!(fog.visibility((4, 1)), Visibility::Unexplored);
+        assert_eq!(fog.explored_tile(&map, (3, 1)), Some(Tile::Earth));
+        assert_eq!(fog.explored_tile(&map, (4, 1)), None);
+        // Digging further reveals more, but only within the sight radius.
+        (3 .. 12).for_each(|x| map.set_tile((x, 1), Tile::Floor));
+        fog.update(&map, [(0, 1)]);
+        assert_eq!(fog.visibility((SIGHT_RADIUS, 1)), Visibility::Visible);
+        assert_eq!(fog.visibility((SIGHT_RADIUS + 1, 1)), Visibility::Unexplored);
+        // Tiles out of sight stay explored.
+        fog.update(&map, [(11, 1)]);
+        assert_eq!(fog.visibility((0, 1)), Visibility::Explored);
+        assert_eq!(fog.shade((0, 1)), EXPLORED_SHADE);
+        assert_eq!(fog.visibility((11, 0)), Visibility::Visible);
+        assert_eq!(fog.shade((11, 0)), 1.0);
+        assert_eq!(fog.shade((12, 0)), 0.0);
+    }
+}
@@ -0,0 +1,151 @@
+//! Job board.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::map::{Map, Tile};
+
+/// Job board where the keeper and the imps post work for the imps.
+#[derive(Clone, Debug, Default)]
+pub struct Jobs
+{
+    /// Pending jobs in order of posting.
+    jobs: Vec<Job>,
+}
+
+/// Work for an imp.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Job
+{
+    /// Dig out a tile, working from any walkable neighbor.
+    Dig((usize, usize)),
+    /// Claim a floor tile for the keeper, working on the tile itself.
+    Claim((usize, usize)),
+    /// Pick up the pile of gold on a tile and carry it to the treasury.
+    Carry((usize, usize)),
+}
+
+impl Jobs
+{
+    /// Creates and initializes a new empty job board.
+    ///
+    /// Returns the newly created job board.
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Designates a tile for digging.
+    ///
+    /// * `pos`: Position of the tile.
+    pub fn designate(&mut self, pos: (usize, usize))
+    {
+        self.post(Job::Dig(pos));
+    }
+
+    /// Posts a job, unless the same job is already pending.
+    ///
+    /// * `job`: Job to post.
+    pub fn post(&mut self, job: Job)
+    {
+        if !self.jobs.contains(&job) {
+            self.jobs.push(job);
+        }
+    }
+
+    /// Returns the number of pending jobs.
+    pub fn pending(&self) -> usize
+    {
+        self.jobs.len()
+    }
+
+    /// Takes the job that the imp can reach the fastest, preferring the oldest
+    /// job in case of a tie, and dropping any jobs that can no longer be done.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `from`: Position of the imp.
+    ///
+    /// Returns the job along with the position that the imp has to walk to in
+    /// order to do it, or nothing if no pending job is reachable.
+    pub fn take(&mut self, map: &Map, from: (usize, usize)) -> Option<(Job, (usize, usize))>
+    {
+        self.jobs.retain(|job| job.is_valid(map));
+        let costs = map.path_costs(from)?;
+        let cost = |pos: (usize, usize)| costs[pos.1 * map.width() + pos.0];
+        let mut best: Option<(usize, (usize, usize), u32)> = None;
+        for (idx, job) in self.jobs.iter().enumerate() {
+            let site = match *job {
+                Job::Dig(pos) => map.neighbors(pos).min_by_key(|&pos| cost(pos)),
+                Job::Claim(pos) | Job::Carry(pos) => Some(pos),
+            };
+            let Some(site) = site.filter(|&pos| cost(pos) != u32::MAX) else {
+                continue;
+            };
+            if best.map_or(true, |(_, _, best)| cost(site) < best) {
+                best = Some((idx, site, cost(site)));
+            }
+        }
+        let (idx, site, _) = best?;
+        Some((self.jobs.remove(idx), site))
+    }
+}
+
+impl Job
+{
+    /// Checks whether this job can still be done.
+    ///
+    /// * `map`: Dungeon map.
+    ///
+    /// Returns whether the target tile is still in the right state.
+    pub fn is_valid(&self, map: &Map) -> bool
+    {
+        match *self {
+            Self::Dig(pos) => map.tile(pos).is_some_and(Tile::is_diggable),
+            Self::Claim(pos) => map.tile(pos) == Some(Tile::Floor),
+            Self::Carry(pos) => map.tile(pos).is_some_and(Tile::is_walkable),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn take_reachable()
+    {
+        let mut map = Map::new(4, 4);
+        map.set_tile((0, 0), Tile::Floor);
+        let mut jobs = Jobs::new();
+        jobs.designate((2, 0));
+        jobs.designate((1, 0));
+        jobs.designate((1, 0));
+        assert_eq!(jobs.pending(), 2);
+        assert_eq!(jobs.take(&map, (0, 0)), Some((Job::Dig((1, 0)), (0, 0))));
+        assert_eq!(jobs.take(&map, (0, 0)), None);
+        map.dig((1, 0));
+        assert_eq!(jobs.take(&map, (0, 0)), Some((Job::Dig((2, 0)), (1, 0))));
+        assert_eq!(jobs.pending(), 0);
+    }
+
+    #[test]
+    fn take_nearest()
+    {
+        let mut map = Map::new(5, 1);
+        (0 .. 4).for_each(|x| map.set_tile((x, 0), Tile::Floor));
+        let mut jobs = Jobs::new();
+        jobs.post(Job::Claim((0, 0)));
+        jobs.post(Job::Dig((4, 0)));
+        jobs.post(Job::Claim((2, 0)));
+        // Both claims are equally far, so the older one wins.
+        assert_eq!(jobs.take(&map, (1, 0)), Some((Job::Claim((0, 0)), (0, 0))));
+        assert_eq!(jobs.take(&map, (1, 0)), Some((Job::Claim((2, 0)), (2, 0))));
+        jobs.post(Job::Carry((3, 0)));
+        assert_eq!(jobs.take(&map, (1, 0)), Some((Job::Dig((4, 0)), (3, 0))));
+        map.set_tile((3, 0), Tile::Rock);
+        assert_eq!(jobs.take(&map, (1, 0)), None);
+        assert_eq!(jobs.pending(), 0);
+    }
+}
@@ -0,0 +1,514 @@
+//! Dungeon map.
+//!
+//! Tiles sit on a heightfield defined at their corners, so adjacent tiles
+//! sharing an edge always meet without gaps, and slopes make walking between
+//! tiles at different heights more expensive or outright impossible.
+
+extern crate alloc;
+
+use alloc::collections::{BinaryHeap, VecDeque};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::simd::f32x4;
+
+use crate::math::Rect;
+
+/// Cost of walking between two adjacent tiles at the same height.
+const STEP_COST: u32 = 10;
+/// Additional cost of walking between adjacent tiles per unit of height
+/// difference.
+const CLIMB_COST: u32 = 5;
+/// Maximum height difference between adjacent tiles that creatures can climb.
+const MAX_CLIMB: u32 = 4;
+
+/// Dungeon map.
+#[derive(Clone, Debug)]
+pub struct Map
+{
+    /// Width in tiles.
+    width: usize,
+    /// Height in tiles.
+    height: usize,
+    /// Tiles in row-major order.
+    tiles: Vec<Tile>,
+    /// Heights of the tile corners in row-major order.
+    heights: Vec<i16>,
+}
+
+/// Map tile.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Tile
+{
+    /// Impenetrable rock.
+    Rock,
+    /// Diggable earth.
+    Earth,
+    /// Diggable gold seam.
+    Gold,
+    /// Walkable floor.
+    Floor,
+    /// Walkable floor claimed by the keeper.
+    Claimed,
+}
+
+impl Map
+{
+    /// Creates and initializes a new flat map filled with earth.
+    ///
+    /// * `width`: Width in tiles.
+    /// * `height`: Height in tiles.
+    ///
+    /// Returns the newly created map.
+    ///
+    /// Panics if either dimension is zero.
+    #[track_caller]
+    pub fn new(width: usize, height: usize) -> Self
+    {
+        assert!(width > 0 && height > 0, "Attempted to create an empty map");
+        Self { width,
+               height,
+               tiles: vec![Tile::Earth; width * height],
+               heights: vec![0; (width + 1) * (height + 1)] }
+    }
+
+    /// Returns the width of the map in tiles.
+    pub fn width(&self) -> usize
+    {
+        self.width
+    }
+
+    /// Returns the height of the map in tiles.
+    pub fn height(&self) -> usize
+    {
+        self.height
+    }
+
+    /// Returns the area covered by the map in tile coordinates.
+    pub fn bounds(&self) -> Rect
+    {
+        Rect::new(0, 0, self.width as _, self.height as _)
+    }
+
+    /// Returns the tile at the specified position, or nothing if the position
+    /// is out of bounds.
+    ///
+    /// * `pos`: Position of the tile.
+    pub fn tile(&self, pos: (usize, usize)) -> Option<Tile>
+    {
+        self.index(pos).map(|idx| self.tiles[idx])
+    }
+
+    /// Replaces the tile at the specified position.
+    ///
+    /// * `pos`: Position of the tile.
+    /// * `tile`: New tile.
+    ///
+    /// Panics if the position is out of bounds.
+    #[track_caller]
+    pub fn set_tile(&mut self, pos: (usize, usize), tile: Tile)
+    {
+        let idx = self.index(pos).expect("Tile position out of bounds");
+        self.tiles[idx] = tile;
+    }
+
+    /// Counts the tiles of a kind.
+    ///
+    /// * `tile`: Kind of tile to count.
+    ///
+    /// Returns the number of tiles of that kind.
+    pub fn count(&self, tile: Tile) -> usize
+    {
+        self.tiles.iter().filter(|&&other| other == tile).count()
+    }
+
+    /// Returns the height of the specified tile corner, or nothing if the
+    /// corner is out of bounds.
+    ///
+    /// * `corner`: Position of the corner, with corner `(x, y)` being the top
+    ///   left corner of tile `(x, y)`.
+    pub fn corner_height(&self, corner: (usize, usize)) -> Option<i16>
+    {
+        self.corner_index(corner).map(|idx| self.heights[idx])
+    }
+
+    /// Changes the height of a tile corner, affecting all the tiles that share
+    /// it.
+    ///
+    /// * `corner`: Position of the corner.
+    /// * `height`: New height.
+    ///
+    /// Panics if the corner is out of bounds.
+    #[track_caller]
+    pub fn set_corner_height(&mut self, corner: (usize, usize), height: i16)
+    {
+        let idx = self.corner_index(corner).expect("Corner position out of bounds");
+        self.heights[idx] = height;
+    }
+
+    /// Returns the heights of the top left, top right, bottom left, and bottom
+    /// right corners of a tile, in that order, for building its mesh, or
+    /// nothing if the position is out of bounds.
+    ///
+    /// * `pos`: Position of the tile.
+    pub fn corner_heights(&self, pos: (usize, usize)) -> Option<[i16; 4]>
+    {
+        self.index(pos)?;
+        let (x, y) = pos;
+        let stride = self.width + 1;
+        let corners = [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)];
+        Some(corners.map(|(x, y)| self.heights[y * stride + x]))
+    }
+
+    /// Returns the average height of the corners of a tile, or nothing if the
+    /// position is out of bounds.
+    ///
+    /// * `pos`: Position of the tile.
+    pub fn tile_height(&self, pos: (usize, usize)) -> Option<i32>
+    {
+        let corners = self.corner_heights(pos)?;
+        Some(corners.iter().map(|&height| height as i32).sum::<i32>().div_euclid(4))
+    }
+
+    /// Computes a checksum of all the tiles and heights, which can be stored
+    /// and compared later to detect memory corruption.
+    ///
+    /// Returns the computed checksum.
+    pub fn checksum(&self) -> u64
+    {
+        // FNV-1a.
+        let tiles = self.tiles.iter().map(|&tile| tile as u64);
+        let heights = self.heights.iter().map(|&height| height as u16 as u64);
+        tiles.chain(heights)
+             .fold(0xCBF29CE484222325, |sum, val| (sum ^ val).wrapping_mul(0x100000001B3))
+    }
+
+    /// Digs out the tile at the specified position, turning it into floor.
+    ///
+    /// * `pos`: Position of the tile.
+    ///
+    /// Returns the tile that was dug out, or nothing if it was not diggable.
+    pub fn dig(&mut self, pos: (usize, usize)) -> Option<Tile>
+    {
+        let idx = self.index(pos)?;
+        let tile = self.tiles[idx];
+        if !tile.is_diggable() {
+            return None;
+        }
+        self.tiles[idx] = Tile::Floor;
+        Some(tile)
+    }
+
+    /// Checks whether a creature can walk between two positions.
+    ///
+    /// * `from`: Starting position.
+    /// * `to`: Target position.
+    ///
+    /// Returns whether a walkable path exists.
+    pub fn is_reachable(&self, from: (usize, usize), to: (usize, usize)) -> bool
+    {
+        self.index(to).is_some()
+        && self.flood(from)
+               .map(|seen| seen[to.1 * self.width + to.0])
+               .unwrap_or(false)
+    }
+
+    /// Checks whether a creature can dig a tile, which requires the tile to be
+    /// diggable and to border a floor tile reachable from the starting
+    /// position.
+    ///
+    /// * `from`: Position of the digger.
+    /// * `pos`: Position of the tile to dig.
+    ///
+    /// Returns whether the tile can be dug.
+    pub fn is_diggable_from(&self, from: (usize, usize), pos: (usize, usize)) -> bool
+    {
+        if !self.tile(pos).is_some_and(Tile::is_diggable) {
+            return false;
+        }
+        let Some(seen) = self.flood(from) else { return false };
+        self.neighbors(pos).any(|(x, y)| seen[y * self.width + x])
+    }
+
+    /// Computes the cost of walking the cheapest path between two positions,
+    /// taking slopes into account.
+    ///
+    /// * `from`: Starting position.
+    /// * `to`: Target position.
+    ///
+    /// Returns the computed cost, or nothing if no path exists.
+    pub fn path_cost(&self, from: (usize, usize), to: (usize, usize)) -> Option<u32>
+    {
+        let target = self.index(to)?;
+        let (costs, _) = self.search(from, Some(target))?;
+        (costs[target] != u32::MAX).then_some(costs[target])
+    }
+
+    /// Finds the cheapest path between two positions, taking slopes into
+    /// account.
+    ///
+    /// * `from`: Starting position.
+    /// * `to`: Target position.
+    ///
+    /// Returns the positions to walk through in order, excluding the starting
+    /// position and including the target, or nothing if no path exists.
+    pub fn path(&self, from: (usize, usize), to: (usize, usize)) -> Option<Vec<(usize, usize)>>
+    {
+        let target = self.index(to)?;
+        let (costs, prevs) = self.search(from, Some(target))?;
+        if costs[target] == u32::MAX {
+            return None;
+        }
+        let start = from.1 * self.width + from.0;
+        let mut path = Vec::new();
+        let mut idx = target;
+        while idx != start {
+            path.push((idx % self.width, idx / self.width));
+            idx = prevs[idx];
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Computes the cost of walking the cheapest path from a position to every
+    /// tile, taking slopes into account.
+    ///
+    /// * `from`: Starting position.
+    ///
+    /// Returns the computed costs in row-major order, with [`u32::MAX`] for
+    /// unreachable tiles, or nothing if the starting position is not
+    /// walkable.
+    pub fn path_costs(&self, from: (usize, usize)) -> Option<Vec<u32>>
+    {
+        self.search(from, None).map(|(costs, _)| costs)
+    }
+
+    /// Runs Dijkstra's algorithm from a starting position.
+    ///
+    /// * `from`: Starting position.
+    /// * `target`: Index of the tile at which to stop searching, if any.
+    ///
+    /// Returns the costs of reaching each tile along with the index of the
+    /// tile each was reached from, both in row-major order, or nothing if the
+    /// starting position is not walkable.  Tiles that were not reached before
+    /// the search stopped cost [`u32::MAX`].
+    fn search(&self, from: (usize, usize), target: Option<usize>) -> Option<(Vec<u32>, Vec<usize>)>
+    {
+        if !self.tile(from)?.is_walkable() {
+            return None;
+        }
+        let mut costs = vec![u32::MAX; self.tiles.len()];
+        let mut prevs = vec![usize::MAX; self.tiles.len()];
+        let mut queue = BinaryHeap::new();
+        costs[from.1 * self.width + from.0] = 0;
+        queue.push(Reverse((0, from)));
+        while let Some(Reverse((cost, pos))) = queue.pop() {
+            let idx = pos.1 * self.width + pos.0;
+            if Some(idx) == target {
+                break;
+            }
+            if cost > costs[idx] {
+                continue;
+            }
+            for next in self.neighbors(pos) {
+                let Some(step) = self.step_cost(pos, next) else {
+                    continue;
+                };
+                let next_idx = next.1 * self.width + next.0;
+                if cost + step < costs[next_idx] {
+                    costs[next_idx] = cost + step;
+                    prevs[next_idx] = idx;
+                    queue.push(Reverse((cost + step, next)));
+                }
+            }
+        }
+        Some((costs, prevs))
+    }
+
+    /// Computes the cost of walking between two adjacent tiles.
+    ///
+    /// * `from`: Position of the current tile.
+    /// * `to`: Position of the adjacent tile.
+    ///
+    /// Returns the computed cost, or nothing if the destination is not
+    /// walkable or too steep to climb.
+    fn step_cost(&self, from: (usize, usize), to: (usize, usize)) -> Option<u32>
+    {
+        if !self.tile(to)?.is_walkable() {
+            return None;
+        }
+        let climb = self.tile_height(from)?.abs_diff(self.tile_height(to)?);
+        (climb <= MAX_CLIMB).then_some(STEP_COST + climb * CLIMB_COST)
+    }
+
+    /// Finds all the walkable positions reachable from a starting position.
+    ///
+    /// * `from`: Starting position.
+    ///
+    /// Returns a row-major map of reached tiles, or nothing if the starting
+    /// position is not walkable.
+    fn flood(&self, from: (usize, usize)) -> Option<Vec<bool>>
+    {
+        if !self.tile(from)?.is_walkable() {
+            return None;
+        }
+        let mut seen = vec![false; self.tiles.len()];
+        let mut queue = VecDeque::new();
+        seen[from.1 * self.width + from.0] = true;
+        queue.push_back(from);
+        while let Some(pos) = queue.pop_front() {
+            for (x, y) in self.neighbors(pos) {
+                let idx = y * self.width + x;
+                if !seen[idx] && self.step_cost(pos, (x, y)).is_some() {
+                    seen[idx] = true;
+                    queue.push_back((x, y));
+                }
+            }
+        }
+        Some(seen)
+    }
+
+    /// Returns an iterator over the in-bounds orthogonal neighbors of a
+    /// position.
+    ///
+    /// * `pos`: Position whose neighbors to list.
+    pub(super) fn neighbors(&self, pos: (usize, usize)) -> impl Iterator<Item = (usize, usize)>
+    {
+        let (width, height) = (self.width, self.height);
+        let (x, y) = pos;
+        let neighbors = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+        neighbors.into_iter().filter(move |&(x, y)| x < width && y < height)
+    }
+
+    /// Computes the index of a tile corner.
+    ///
+    /// * `corner`: Position of the corner.
+    ///
+    /// Returns the computed index, or nothing if the position is out of
+    /// bounds.
+    fn corner_index(&self, corner: (usize, usize)) -> Option<usize>
+    {
+        (corner.0 <= self.width && corner.1 <= self.height).then(|| corner.1 * (self.width + 1) + corner.0)
+    }
+
+    /// Computes the index of a tile.
+    ///
+    /// * `pos`: Position of the tile.
+    ///
+    /// Returns the computed index, or nothing if the position is out of
+    /// bounds.
+    fn index(&self, pos: (usize, usize)) -> Option<usize>
+    {
+        self.bounds().contains(pos.into()).then(|| pos.1 * self.width + pos.0)
+    }
+}
+
+impl Tile
+{
+    /// Returns whether this tile can be dug out.
+    pub fn is_diggable(self) -> bool
+    {
+        matches!(self, Self::Earth | Self::Gold)
+    }
+
+    /// Returns whether creatures can walk over this tile.
+    pub fn is_walkable(self) -> bool
+    {
+        matches!(self, Self::Floor | Self::Claimed)
+    }
+
+    /// Returns the color that this tile is drawn with.
+    pub fn color(self) -> f32x4
+    {
+        let color = match self {
+            Self::Rock => [0.2, 0.2, 0.2, 1.0],
+            Self::Earth => [0.3, 0.2, 0.1, 1.0],
+            Self::Gold => [0.9, 0.8, 0.1, 1.0],
+            Self::Floor => [0.6, 0.6, 0.6, 1.0],
+            Self::Claimed => [0.7, 0.1, 0.1, 1.0],
+        };
+        f32x4::from_array(color)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn dig_reachability()
+    {
+        let mut map = Map::new(5, 5);
+        map.set_tile((0, 0), Tile::Floor);
+        map.set_tile((2, 0), Tile::Rock);
+        assert!(map.is_diggable_from((0, 0), (1, 0)));
+        assert!(map.is_diggable_from((0, 0), (0, 1)));
+        assert!(!map.is_diggable_from((0, 0), (2, 0)));
+        assert!(!map.is_diggable_from((0, 0), (3, 0)));
+        assert!(!map.is_diggable_from((0, 0), (1, 1)));
+        assert_eq!(map.dig((1, 0)), Some(Tile::Earth));
+        assert_eq!(map.dig((2, 0)), None);
+        assert_eq!(map.count(Tile::Floor), 2);
+        assert_eq!(map.count(Tile::Earth), 22);
+        assert!(map.is_diggable_from((0, 0), (1, 1)));
+        assert!(!map.is_diggable_from((0, 0), (3, 0)));
+    }
+
+    #[test]
+    fn checksum()
+    {
+        let mut map = Map::new(4, 4);
+        let sum = map.checksum();
+        assert_eq!(map.clone().checksum(), sum);
+        map.dig((1, 1));
+        assert_ne!(map.checksum(), sum);
+    }
+
+    #[test]
+    fn slopes()
+    {
+        let mut map = Map::new(3, 1);
+        (0 .. 3).for_each(|x| map.set_tile((x, 0), Tile::Floor));
+        assert_eq!(map.path_cost((0, 0), (2, 0)), Some(STEP_COST * 2));
+        map.set_corner_height((2, 0), 4);
+        map.set_corner_height((2, 1), 4);
+        assert_eq!(map.corner_heights((1, 0)), Some([0, 4, 0, 4]));
+        assert_eq!(map.tile_height((1, 0)), Some(2));
+        assert_eq!(map.tile_height((2, 0)), Some(2));
+        assert_eq!(map.path_cost((0, 0), (2, 0)), Some(STEP_COST * 2 + CLIMB_COST * 2));
+        map.set_corner_height((1, 0), 20);
+        map.set_corner_height((1, 1), 20);
+        assert_eq!(map.path_cost((0, 0), (2, 0)), None);
+        assert!(!map.is_reachable((0, 0), (2, 0)));
+    }
+
+    #[test]
+    fn paths()
+    {
+        let mut map = Map::new(3, 3);
+        [(0, 0), (0, 1), (0, 2), (1, 2), (2, 2), (2, 0)].into_iter()
+                                                        .for_each(|pos| map.set_tile(pos, Tile::Floor));
+        map.set_tile((1, 2), Tile::Claimed);
+        assert_eq!(map.path((0, 0), (2, 2)), Some([(0, 1), (0, 2), (1, 2), (2, 2)].into()));
+        assert_eq!(map.path((0, 0), (0, 0)), Some(Vec::new()));
+        assert_eq!(map.path((0, 0), (2, 0)), None);
+        let costs = map.path_costs((0, 0)).unwrap();
+        assert_eq!(costs[2 * 3 + 2], STEP_COST * 4);
+        assert_eq!(costs[2], u32::MAX);
+        assert!(map.path_costs((1, 1)).is_none());
+    }
+
+    #[test]
+    fn walk_reachability()
+    {
+        let mut map = Map::new(4, 1);
+        map.set_tile((0, 0), Tile::Floor);
+        map.set_tile((3, 0), Tile::Floor);
+        assert!(!map.is_reachable((0, 0), (3, 0)));
+        map.dig((1, 0));
+        map.dig((2, 0));
+        assert!(map.is_reachable((0, 0), (3, 0)));
+        assert!(!map.is_reachable((0, 0), (4, 0)));
+    }
+}
@@ -0,0 +1,136 @@
+//! Dungeon minimap.
+//!
+//! Rasterizes the map into a tiny XRGB8888 image with one pixel per tile,
+//! colored by what the keeper knows about each tile: furnished tiles take the
+//! floor color of their room, claimed tiles stand out from the rest, explored
+//! tiles out of sight are dimmed, and unexplored tiles stay black.  Redrawing
+//! reports whether any pixel changed, so callers can skip uploading the image
+//! otherwise.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::simd::f32x4;
+
+use super::{Fog, Map, Rooms};
+
+/// Color of unexplored tiles.
+const UNEXPLORED_COLOR: u32 = 0x000000;
+
+/// Minimap image.
+#[derive(Clone, Debug)]
+pub struct Minimap
+{
+    /// Width in pixels.
+    width: usize,
+    /// Height in pixels.
+    height: usize,
+    /// Pixels in row-major order.
+    pixels: Vec<u32>,
+}
+
+impl Minimap
+{
+    /// Creates and initializes a new blank minimap with the dimensions of a
+    /// map.
+    ///
+    /// * `map`: Dungeon map.
+    ///
+    /// Returns the newly created minimap.
+    pub fn new(map: &Map) -> Self
+    {
+        Self { width: map.width(),
+               height: map.height(),
+               pixels: vec![UNEXPLORED_COLOR; map.width() * map.height()] }
+    }
+
+    /// Redraws the minimap.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `rooms`: Rooms furnished on the map.
+    /// * `fog`: Fog of war over the map.
+    ///
+    /// Returns whether any pixel changed.
+    pub fn render(&mut self, map: &Map, rooms: &Rooms, fog: &Fog) -> bool
+    {
+        let mut changed = false;
+        for y in 0 .. self.height {
+            for x in 0 .. self.width {
+                let color = Self::color(map, rooms, fog, (x, y));
+                let pixel = &mut self.pixels[y * self.width + x];
+                changed |= *pixel != color;
+                *pixel = color;
+            }
+        }
+        changed
+    }
+
+    /// Returns the width of the minimap in pixels.
+    pub fn width(&self) -> usize
+    {
+        self.width
+    }
+
+    /// Returns the height of the minimap in pixels.
+    pub fn height(&self) -> usize
+    {
+        self.height
+    }
+
+    /// Returns the pixels of the minimap in row-major order.
+    pub fn pixels(&self) -> &[u32]
+    {
+        &self.pixels
+    }
+
+    /// Computes the color of a tile.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `rooms`: Rooms furnished on the map.
+    /// * `fog`: Fog of war over the map.
+    /// * `pos`: Position of the tile.
+    ///
+    /// Returns the computed color in XRGB8888 format.
+    fn color(map: &Map, rooms: &Rooms, fog: &Fog, pos: (usize, usize)) -> u32
+    {
+        let Some(tile) = fog.explored_tile(map, pos) else {
+            return UNEXPLORED_COLOR;
+        };
+        let color = rooms.floor_color(pos).unwrap_or(tile.color());
+        let color = color * f32x4::splat(fog.shade(pos) * 255.0);
+        let [red, green, blue, _] = color.to_array().map(|comp| comp as u32);
+        red << 16 | green << 8 | blue
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::game::{RoomKind, Tile, Treasury};
+
+    #[test]
+    fn render()
+    {
+        let mut map = Map::new(4, 1);
+        (0 .. 2).for_each(|x| map.set_tile((x, 0), Tile::Claimed));
+        let mut rooms = Rooms::new(&map);
+        let mut treasury = Treasury::new(1000, 0);
+        assert!(rooms.build(&map, (1, 0), RoomKind::Lair, &mut treasury));
+        let mut fog = Fog::new(&map);
+        let mut minimap = Minimap::new(&map);
+        assert_eq!((minimap.width(), minimap.height()), (4, 1));
+        assert!(!minimap.render(&map, &rooms, &fog));
+        assert_eq!(minimap.pixels(), [UNEXPLORED_COLOR; 4]);
+        fog.update(&map, [(0, 0)]);
+        assert!(minimap.render(&map, &rooms, &fog));
+        assert_eq!(minimap.pixels(), [0xB21919, 0x664C33, 0x4C3319, UNEXPLORED_COLOR]);
+        // Nothing changes until the map does.
+        assert!(!minimap.render(&map, &rooms, &fog));
+        map.set_tile((2, 0), Tile::Floor);
+        fog.update(&map, [(0, 0)]);
+        assert!(minimap.render(&map, &rooms, &fog));
+        assert_eq!(minimap.pixels()[2], 0x999999);
+    }
+}
@@ -0,0 +1,42 @@
+//! Game rules.
+//!
+//! Everything in this module is pure simulation logic with no dependencies on
+//! the hardware, so it also compiles and runs its tests on the host.
+
+mod ai;
+mod camera;
+mod combat;
+mod economy;
+mod fog;
+mod jobs;
+mod map;
+mod minimap;
+mod rng;
+mod rooms;
+mod save;
+mod scene;
+mod spells;
+mod stats;
+mod terrain;
+
+pub use self::ai::*;
+#[cfg(not(test))]
+pub use self::camera::*;
+pub use self::combat::*;
+pub use self::economy::*;
+pub use self::fog::*;
+pub use self::jobs::*;
+pub use self::map::*;
+#[cfg(not(test))]
+pub use self::minimap::*;
+pub use self::rng::*;
+pub use self::rooms::*;
+#[cfg(not(test))]
+pub use self::save::*;
+#[cfg(not(test))]
+pub use self::scene::*;
+#[cfg(not(test))]
+pub use self::spells::*;
+pub use self::stats::*;
+#[cfg(not(test))]
+pub use self::terrain::*;
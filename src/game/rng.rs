@@ -0,0 +1,87 @@
+//! Deterministic pseudo-random number generator.
+//!
+//! Implements xorshift64* [1], which is fast, tiny, and more than good enough
+//! for game rules, while guaranteeing that the same seed always produces the
+//! same simulation.
+//!
+//! [1]: https://en.wikipedia.org/wiki/Xorshift#xorshift*
+
+/// Pseudo-random number generator.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rng
+{
+    /// Current state.
+    state: u64,
+}
+
+impl Rng
+{
+    /// Creates and initializes a new generator.
+    ///
+    /// * `seed`: Initial seed.
+    ///
+    /// Returns the newly created generator.
+    pub const fn new(seed: u64) -> Self
+    {
+        // Zero is a fixed point of xorshift so replace it with something else.
+        let state = if seed == 0 { 0x9E3779B97F4A7C15 } else { seed };
+        Self { state }
+    }
+
+    /// Returns the current state, which recreates this generator when used as
+    /// a seed.
+    pub fn state(&self) -> u64
+    {
+        self.state
+    }
+
+    /// Generates the next pseudo-random number.
+    ///
+    /// Returns the generated number.
+    pub fn next(&mut self) -> u64
+    {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    /// Generates a pseudo-random number within a range.
+    ///
+    /// * `range`: Upper bound, exclusive.
+    ///
+    /// Returns the generated number.
+    ///
+    /// Panics if the range is empty.
+    #[track_caller]
+    pub fn below(&mut self, range: u32) -> u32
+    {
+        assert!(range > 0, "Attempted to generate a random number in an empty range");
+        (((self.next() >> 32) * range as u64) >> 32) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn deterministic()
+    {
+        let mut rng0 = Rng::new(42);
+        let mut rng1 = Rng::new(42);
+        for _ in 0 .. 100 {
+            assert_eq!(rng0.next(), rng1.next());
+        }
+    }
+
+    #[test]
+    fn below()
+    {
+        let mut rng = Rng::new(0);
+        for _ in 0 .. 1000 {
+            assert!(rng.below(6) < 6);
+        }
+    }
+}
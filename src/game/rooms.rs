@@ -0,0 +1,427 @@
+//! Dungeon rooms.
+//!
+//! The keeper builds rooms by paying to furnish claimed tiles, and every group
+//! of orthogonally contiguous tiles furnished the same way forms a single room
+//! whose capacity grows with its size.  Creatures standing in a room benefit
+//! from its effect, up to the capacity of the room, and the rooms also tell
+//! creatures where to eat and sleep.  Furnishings are lost along with the
+//! claim on their tiles.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::simd::f32x4;
+
+use crate::game::{Activity, Cost, Creature, Map, Rng, Sites, Tile, Treasury};
+
+/// Fatigue recovered per tick by creatures in a lair.
+const LAIR_REST: u32 = 10;
+/// Hunger satisfied per tick by creatures in a hatchery.
+const HATCHERY_FEED: u32 = 25;
+/// Gold that each treasury tile can hold.
+const GOLD_PER_TILE: usize = 1000;
+/// Odds of a creature improving its attack on each tick of training.
+const TRAINING_ODDS: u32 = 20;
+/// Attack above which creatures can no longer improve by training.
+const TRAINING_MAX: u32 = 50;
+/// Fatigue gained per tick of training.
+const TRAINING_FATIGUE: u32 = 2;
+
+/// Kind of room.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RoomKind
+{
+    /// Where creatures sleep.
+    Lair,
+    /// Where creatures eat.
+    Hatchery,
+    /// Where gold is stored.
+    Treasury,
+    /// Where creatures improve their attack.
+    Training,
+}
+
+/// Contiguous group of tiles of the same kind of room.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Room
+{
+    /// Kind of room.
+    kind: RoomKind,
+    /// Positions of the tiles in row-major order.
+    tiles: Vec<(usize, usize)>,
+}
+
+/// Rooms in the dungeon.
+#[derive(Clone, Debug)]
+pub struct Rooms
+{
+    /// Width of the map in tiles.
+    width: usize,
+    /// Kind of room furnished on each tile in row-major order.
+    kinds: Vec<Option<RoomKind>>,
+    /// Rooms detected in the last update, in row-major order of their first
+    /// tile.
+    rooms: Vec<Room>,
+}
+
+impl RoomKind
+{
+    /// Returns the cost of furnishing a single tile.
+    pub fn cost(self) -> Cost
+    {
+        let gold = match self {
+            Self::Lair => 50,
+            Self::Hatchery => 75,
+            Self::Treasury => 50,
+            Self::Training => 100,
+        };
+        Cost { gold, mana: 0 }
+    }
+
+    /// Returns the number of creatures, or the amount of gold for treasuries,
+    /// that a single tile can accommodate.
+    pub fn capacity(self) -> usize
+    {
+        match self {
+            Self::Lair | Self::Hatchery | Self::Training => 1,
+            Self::Treasury => GOLD_PER_TILE,
+        }
+    }
+
+    /// Returns the color that the floor of this kind of room is tinted with.
+    pub fn floor_color(self) -> f32x4
+    {
+        let color = match self {
+            Self::Lair => [0.4, 0.3, 0.2, 1.0],
+            Self::Hatchery => [0.5, 0.4, 0.1, 1.0],
+            Self::Treasury => [0.8, 0.7, 0.2, 1.0],
+            Self::Training => [0.5, 0.2, 0.2, 1.0],
+        };
+        f32x4::from_array(color)
+    }
+}
+
+impl Room
+{
+    /// Returns the kind of this room.
+    pub fn kind(&self) -> RoomKind
+    {
+        self.kind
+    }
+
+    /// Returns the positions of the tiles of this room in row-major order.
+    pub fn tiles(&self) -> &[(usize, usize)]
+    {
+        &self.tiles
+    }
+
+    /// Returns the number of creatures, or the amount of gold for treasuries,
+    /// that this room can accommodate.
+    pub fn capacity(&self) -> usize
+    {
+        self.tiles.len() * self.kind.capacity()
+    }
+
+    /// Checks whether a position is inside this room.
+    ///
+    /// * `pos`: Position to check.
+    ///
+    /// Returns the result of the check.
+    pub fn contains(&self, pos: (usize, usize)) -> bool
+    {
+        self.tiles
+            .binary_search_by_key(&(pos.1, pos.0), |&(x, y)| (y, x))
+            .is_ok()
+    }
+}
+
+impl Rooms
+{
+    /// Creates and initializes a new dungeon without rooms.
+    ///
+    /// * `map`: Dungeon map.
+    ///
+    /// Returns the newly created rooms.
+    pub fn new(map: &Map) -> Self
+    {
+        Self { width: map.width(),
+               kinds: vec![None; map.width() * map.height()],
+               rooms: Vec::new() }
+    }
+
+    /// Furnishes a claimed tile as part of a room, paying for it from the
+    /// treasury.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `pos`: Position of the tile.
+    /// * `kind`: Kind of room.
+    /// * `treasury`: Treasury to pay from.
+    ///
+    /// Returns whether the tile was furnished, which requires it to be claimed,
+    /// free of other rooms, and affordable.
+    pub fn build(&mut self, map: &Map, pos: (usize, usize), kind: RoomKind, treasury: &mut Treasury) -> bool
+    {
+        if map.tile(pos) != Some(Tile::Claimed) || self.kind(pos).is_some() {
+            return false;
+        }
+        if !treasury.spend(kind.cost()) {
+            return false;
+        }
+        self.kinds[pos.1 * self.width + pos.0] = Some(kind);
+        self.detect(map);
+        true
+    }
+
+    /// Removes the furnishings from a tile, refunding half of their cost.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `pos`: Position of the tile.
+    /// * `treasury`: Treasury to refund to.
+    ///
+    /// Returns the kind of room that was removed, if any.
+    pub fn sell(&mut self, map: &Map, pos: (usize, usize), treasury: &mut Treasury) -> Option<RoomKind>
+    {
+        let kind = self.kind(pos)?;
+        self.kinds[pos.1 * self.width + pos.0] = None;
+        let cost = kind.cost();
+        treasury.refund(Cost { gold: cost.gold / 2,
+                               mana: cost.mana / 2 });
+        self.detect(map);
+        Some(kind)
+    }
+
+    /// Returns the kind of room furnished on a tile, if any.
+    ///
+    /// * `pos`: Position of the tile.
+    pub fn kind(&self, pos: (usize, usize)) -> Option<RoomKind>
+    {
+        if pos.0 >= self.width {
+            return None;
+        }
+        self.kinds.get(pos.1 * self.width + pos.0).copied().flatten()
+    }
+
+    /// Returns all the rooms in row-major order of their first tile.
+    pub fn rooms(&self) -> &[Room]
+    {
+        &self.rooms
+    }
+
+    /// Drops the furnishings of tiles that are no longer claimed and detects
+    /// the rooms again if anything changed.
+    ///
+    /// * `map`: Dungeon map.
+    pub fn update(&mut self, map: &Map)
+    {
+        let mut changed = false;
+        for (idx, kind) in self.kinds.iter_mut().enumerate() {
+            let pos = (idx % self.width, idx / self.width);
+            if kind.is_some() && map.tile(pos) != Some(Tile::Claimed) {
+                *kind = None;
+                changed = true;
+            }
+        }
+        if changed {
+            self.detect(map);
+        }
+    }
+
+    /// Returns the color that the floor of a tile should be tinted with, or
+    /// nothing if it isn't part of any room.
+    ///
+    /// * `pos`: Position of the tile.
+    pub fn floor_color(&self, pos: (usize, usize)) -> Option<f32x4>
+    {
+        self.kind(pos).map(RoomKind::floor_color)
+    }
+
+    /// Picks where creatures should eat and sleep, preferring the first room of
+    /// each kind that isn't full.
+    ///
+    /// * `creatures`: Creatures in the dungeon.
+    ///
+    /// Returns the picked sites.
+    pub fn sites(&self, creatures: &[Creature]) -> Sites
+    {
+        let pick = |kind: RoomKind| {
+            let mut rooms = self.rooms.iter().filter(|room| room.kind == kind);
+            let room = rooms.clone()
+                            .find(|room| self.occupancy(room, creatures) < room.capacity())
+                            .or_else(|| rooms.next())?;
+            Some(room.tiles[room.tiles.len() / 2])
+        };
+        Sites { food: pick(RoomKind::Hatchery),
+                lair: pick(RoomKind::Lair) }
+    }
+
+    /// Applies the effects of the rooms for one game tick to the creatures in
+    /// them, in order, up to the capacity of each room.
+    ///
+    /// * `creatures`: Creatures in the dungeon.
+    /// * `rng`: Random number generator.
+    pub fn apply(&self, creatures: &mut [Creature], rng: &mut Rng)
+    {
+        for room in &self.rooms {
+            let occupants = creatures.iter_mut()
+                                     .filter(|creature| creature.fighter.is_alive() && room.contains(creature.pos))
+                                     .take(room.capacity());
+            for creature in occupants {
+                let needs = &mut creature.needs;
+                match room.kind {
+                    RoomKind::Lair => needs.fatigue = needs.fatigue.saturating_sub(LAIR_REST),
+                    RoomKind::Hatchery => needs.hunger = needs.hunger.saturating_sub(HATCHERY_FEED),
+                    RoomKind::Treasury => (),
+                    RoomKind::Training if creature.activity == Activity::Idle => {
+                        needs.fatigue = needs.fatigue.saturating_add(TRAINING_FATIGUE);
+                        let fighter = &mut creature.fighter;
+                        if fighter.attack < TRAINING_MAX && rng.below(TRAINING_ODDS) == 0 {
+                            fighter.attack += 1;
+                        }
+                    }
+                    RoomKind::Training => (),
+                }
+            }
+        }
+    }
+
+    /// Counts the living creatures standing in a room.
+    ///
+    /// * `room`: Room to count the creatures in.
+    /// * `creatures`: Creatures in the dungeon.
+    ///
+    /// Returns the number of creatures in the room.
+    fn occupancy(&self, room: &Room, creatures: &[Creature]) -> usize
+    {
+        creatures.iter()
+                 .filter(|creature| creature.fighter.is_alive() && room.contains(creature.pos))
+                 .count()
+    }
+
+    /// Flood fills the furnished tiles to detect the rooms.
+    ///
+    /// * `map`: Dungeon map.
+    fn detect(&mut self, map: &Map)
+    {
+        let mut seen = vec![false; self.kinds.len()];
+        self.rooms.clear();
+        for start in 0 .. self.kinds.len() {
+            let Some(kind) = self.kinds[start] else {
+                continue;
+            };
+            if seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut tiles = Vec::new();
+            let mut stack = vec![start];
+            while let Some(idx) = stack.pop() {
+                let pos = (idx % self.width, idx / self.width);
+                tiles.push(pos);
+                for (x, y) in map.neighbors(pos) {
+                    let idx = y * self.width + x;
+                    if !seen[idx] && self.kinds[idx] == Some(kind) {
+                        seen[idx] = true;
+                        stack.push(idx);
+                    }
+                }
+            }
+            tiles.sort_unstable_by_key(|&(x, y)| (y, x));
+            self.rooms.push(Room { kind, tiles });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::game::{Fighter, Wage};
+
+    fn claimed(width: usize, height: usize) -> Map
+    {
+        let mut map = Map::new(width, height);
+        for y in 0 .. height {
+            (0 .. width).for_each(|x| map.set_tile((x, y), Tile::Claimed));
+        }
+        map
+    }
+
+    #[test]
+    fn detect()
+    {
+        let mut map = claimed(4, 3);
+        let mut rooms = Rooms::new(&map);
+        let mut treasury = Treasury::new(1000, 0);
+        // Two lairs separated by a hatchery, with one of them L-shaped.
+        for pos in [(0, 0), (0, 1), (1, 1), (3, 0)] {
+            assert!(rooms.build(&map, pos, RoomKind::Lair, &mut treasury));
+        }
+        assert!(rooms.build(&map, (2, 0), RoomKind::Hatchery, &mut treasury));
+        assert!(!rooms.build(&map, (2, 0), RoomKind::Lair, &mut treasury));
+        assert_eq!(treasury.gold(), 1000 - 4 * 50 - 75);
+        let kinds = rooms.rooms().iter().map(Room::kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [RoomKind::Lair, RoomKind::Hatchery, RoomKind::Lair]);
+        assert_eq!(rooms.rooms()[0].tiles(), [(0, 0), (0, 1), (1, 1)]);
+        assert_eq!(rooms.rooms()[0].capacity(), 3);
+        // Joining the two lairs through the middle.
+        assert_eq!(rooms.sell(&map, (2, 0), &mut treasury), Some(RoomKind::Hatchery));
+        assert_eq!(treasury.gold(), 1000 - 4 * 50 - 75 + 37);
+        for pos in [(2, 0), (2, 1)] {
+            assert!(rooms.build(&map, pos, RoomKind::Lair, &mut treasury));
+        }
+        assert_eq!(rooms.rooms().len(), 1);
+        assert_eq!(rooms.rooms()[0].capacity(), 6);
+        // Losing the claim on the joint splits the lair again.
+        map.set_tile((2, 0), Tile::Floor);
+        map.set_tile((2, 1), Tile::Floor);
+        rooms.update(&map);
+        assert_eq!(rooms.rooms().len(), 2);
+        assert_eq!(rooms.kind((2, 0)), None);
+        assert!(!rooms.build(&map, (2, 0), RoomKind::Lair, &mut treasury));
+        assert_eq!(rooms.floor_color((3, 0)), Some(RoomKind::Lair.floor_color()));
+    }
+
+    #[test]
+    fn effects()
+    {
+        let map = claimed(3, 1);
+        let mut rooms = Rooms::new(&map);
+        let mut treasury = Treasury::new(1000, 0);
+        rooms.build(&map, (0, 0), RoomKind::Hatchery, &mut treasury);
+        rooms.build(&map, (2, 0), RoomKind::Lair, &mut treasury);
+        let creature = Creature::new((0, 0), Fighter::new(10, 5, 0), Wage::new(10));
+        let mut creatures = [creature.clone(), creature.clone(), creature];
+        creatures.iter_mut().for_each(|creature| creature.needs.hunger = 100);
+        creatures[2].pos = (1, 0);
+        // The hatchery is full, so only the first creature eats there, and the
+        // others are told to look for food there anyway.
+        rooms.apply(&mut creatures, &mut Rng::new(1));
+        let hunger = creatures.iter()
+                              .map(|creature| creature.needs.hunger)
+                              .collect::<Vec<_>>();
+        assert_eq!(hunger, [100 - HATCHERY_FEED, 100, 100]);
+        assert_eq!(rooms.sites(&creatures),
+                   Sites { food: Some((0, 0)),
+                           lair: Some((2, 0)) });
+    }
+
+    #[test]
+    fn training()
+    {
+        let map = claimed(2, 1);
+        let mut rooms = Rooms::new(&map);
+        let mut treasury = Treasury::new(1000, 0);
+        assert!(rooms.build(&map, (0, 0), RoomKind::Treasury, &mut treasury));
+        assert!(rooms.build(&map, (1, 0), RoomKind::Training, &mut treasury));
+        assert_eq!(treasury.gold(), 1000 - 50 - 100);
+        assert_eq!(rooms.rooms()[0].capacity(), GOLD_PER_TILE);
+        // Idle creatures keep training until their attack reaches the limit.
+        let mut creatures = [Creature::new((1, 0), Fighter::new(10, 5, 0), Wage::new(10))];
+        let fatigue = creatures[0].needs.fatigue;
+        let mut rng = Rng::new(1);
+        (0 .. 2000).for_each(|_| rooms.apply(&mut creatures, &mut rng));
+        assert_eq!(creatures[0].fighter.attack, TRAINING_MAX);
+        assert_eq!(creatures[0].needs.fatigue, fatigue + 2000 * TRAINING_FATIGUE);
+    }
+}
@@ -0,0 +1,344 @@
+//! Saved games.
+//!
+//! Serializes the map, the creatures, and the player's state into a versioned
+//! little-endian record ending with a CRC-32 of everything before it, so that
+//! corrupted saves are rejected instead of loading a broken dungeon.  Storing
+//! the record is left to the caller.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FormatResult};
+
+use super::economy::{Treasury, Wage};
+use super::map::{Map, Tile};
+use super::rng::Rng;
+use super::stats::{Stats, RECORD_LEN as STATS_LEN};
+use super::{Creature, Fighter, Needs};
+
+/// Magic bytes identifying a saved game.
+const MAGIC: [u8; 4] = *b"NBSV";
+/// Version of the saved game record.
+const VERSION: u8 = 3;
+/// Reversed CRC-32 polynomial.
+const CRC_POLY: u32 = 0xEDB88320;
+
+/// Saved game.
+#[derive(Clone, Debug)]
+pub struct Save
+{
+    /// Dungeon map.
+    pub map: Map,
+    /// Creatures in the dungeon.
+    pub creatures: Vec<Creature>,
+    /// Player's treasury.
+    pub treasury: Treasury,
+    /// Player's statistics.
+    pub stats: Stats,
+    /// Generator driving the game rules.
+    pub rng: Rng,
+}
+
+/// Errors that can occur when loading a saved game.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SaveError
+{
+    /// The record isn't a saved game.
+    Magic,
+    /// The record is from an unsupported version.
+    Version(u8),
+    /// The record ends before all the declared data.
+    Truncated,
+    /// The record contains data between the last field and the CRC.
+    Trailing(usize),
+    /// The record doesn't match its CRC.
+    Crc
+    {
+        /// CRC stored in the record.
+        expected: u32,
+        /// CRC of the record.
+        actual: u32,
+    },
+    /// The map is empty or has an unknown tile.
+    Map,
+    /// A creature stands outside the map.
+    Creature(usize),
+    /// The statistics record is invalid.
+    Stats,
+}
+
+/// Cursor reading little-endian values from a record.
+#[derive(Debug)]
+struct Reader<'a>
+{
+    /// Unread data.
+    data: &'a [u8],
+}
+
+impl Save
+{
+    /// Serializes this game for storage.
+    ///
+    /// Returns the serialized record.
+    ///
+    /// Panics if the map or the number of creatures don't fit the record.
+    #[track_caller]
+    pub fn to_bytes(&self) -> Vec<u8>
+    {
+        let (width, height) = (self.map.width(), self.map.height());
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&u16::try_from(width).expect("Map too wide to save").to_le_bytes());
+        bytes.extend_from_slice(&u16::try_from(height).expect("Map too tall to save").to_le_bytes());
+        for row in 0 .. height {
+            bytes.extend((0 .. width).map(|col| self.map.tile((col, row)).unwrap() as u8));
+        }
+        for row in 0 ..= height {
+            for col in 0 ..= width {
+                bytes.extend_from_slice(&self.map.corner_height((col, row)).unwrap().to_le_bytes());
+            }
+        }
+        let count = u16::try_from(self.creatures.len()).expect("Too many creatures to save");
+        bytes.extend_from_slice(&count.to_le_bytes());
+        for creature in self.creatures.iter() {
+            bytes.extend_from_slice(&(creature.pos.0 as u16).to_le_bytes());
+            bytes.extend_from_slice(&(creature.pos.1 as u16).to_le_bytes());
+            for val in [creature.fighter.health,
+                        creature.fighter.attack,
+                        creature.fighter.defense,
+                        creature.wage.amount,
+                        creature.wage.missed,
+                        creature.needs.hunger,
+                        creature.needs.fatigue,
+                        creature.needs.anger]
+            {
+                bytes.extend_from_slice(&val.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&self.treasury.gold().to_le_bytes());
+        bytes.extend_from_slice(&self.treasury.mana().to_le_bytes());
+        bytes.extend_from_slice(&self.stats.to_bytes());
+        bytes.extend_from_slice(&self.rng.state().to_le_bytes());
+        let crc = crc32(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    /// Deserializes a game from storage.
+    ///
+    /// * `bytes`: Serialized record.
+    ///
+    /// Returns the deserialized game, or the first problem found.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SaveError>
+    {
+        if bytes.len() < MAGIC.len() + 1 + 4 {
+            return Err(SaveError::Truncated);
+        }
+        if bytes[.. 4] != MAGIC {
+            return Err(SaveError::Magic);
+        }
+        if bytes[4] != VERSION {
+            return Err(SaveError::Version(bytes[4]));
+        }
+        let (body, crc) = bytes.split_at(bytes.len() - 4);
+        let expected = u32::from_le_bytes(crc.try_into().unwrap());
+        let actual = crc32(body);
+        if actual != expected {
+            return Err(SaveError::Crc { expected, actual });
+        }
+        let mut reader = Reader { data: &body[5 ..] };
+        let width = reader.u16()? as usize;
+        let height = reader.u16()? as usize;
+        if width == 0 || height == 0 {
+            return Err(SaveError::Map);
+        }
+        let tiles = reader.bytes(width * height)?;
+        let heights = reader.bytes((width + 1) * (height + 1) * 2)?;
+        let mut map = Map::new(width, height);
+        for (idx, tile) in tiles.iter().enumerate() {
+            let tile = match tile {
+                0 => Tile::Rock,
+                1 => Tile::Earth,
+                2 => Tile::Gold,
+                3 => Tile::Floor,
+                4 => Tile::Claimed,
+                _ => return Err(SaveError::Map),
+            };
+            map.set_tile((idx % width, idx / width), tile);
+        }
+        for (idx, height) in heights.chunks_exact(2).enumerate() {
+            let corner = (idx % (width + 1), idx / (width + 1));
+            map.set_corner_height(corner, i16::from_le_bytes([height[0], height[1]]));
+        }
+        let count = reader.u16()? as usize;
+        let mut creatures = Vec::with_capacity(count);
+        for idx in 0 .. count {
+            let pos = (reader.u16()? as usize, reader.u16()? as usize);
+            if pos.0 >= width || pos.1 >= height {
+                return Err(SaveError::Creature(idx));
+            }
+            let fighter = Fighter::new(reader.u32()?, reader.u32()?, reader.u32()?);
+            let wage = Wage { amount: reader.u32()?,
+                              missed: reader.u32()? };
+            let mut creature = Creature::new(pos, fighter, wage);
+            creature.needs = Needs { hunger: reader.u32()?,
+                                     fatigue: reader.u32()?,
+                                     anger: reader.u32()? };
+            creatures.push(creature);
+        }
+        let treasury = Treasury::new(reader.u32()?, reader.u32()?);
+        let stats = Stats::from_bytes(reader.bytes(STATS_LEN)?).ok_or(SaveError::Stats)?;
+        let rng = Rng::new(reader.u64()?);
+        if !reader.data.is_empty() {
+            return Err(SaveError::Trailing(reader.data.len()));
+        }
+        Ok(Self { map,
+                  creatures,
+                  treasury,
+                  stats,
+                  rng })
+    }
+}
+
+impl Display for SaveError
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        match self {
+            Self::Magic => write!(fmt, "not a saved game"),
+            Self::Version(version) => write!(fmt, "unsupported save version {version}"),
+            Self::Truncated => write!(fmt, "truncated save"),
+            Self::Trailing(len) => write!(fmt, "{len} unexpected bytes at the end"),
+            Self::Crc { expected, actual } => write!(fmt, "CRC is 0x{actual:08X} instead of 0x{expected:08X}"),
+            Self::Map => write!(fmt, "invalid map"),
+            Self::Creature(idx) => write!(fmt, "creature #{idx} is outside the map"),
+            Self::Stats => write!(fmt, "invalid statistics"),
+        }
+    }
+}
+
+impl<'a> Reader<'a>
+{
+    /// Reads raw bytes.
+    ///
+    /// * `len`: Number of bytes to read.
+    ///
+    /// Returns the read bytes, or an error if the record is too short.
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SaveError>
+    {
+        if self.data.len() < len {
+            return Err(SaveError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Reads a 16-bit integer.
+    ///
+    /// Returns the read value, or an error if the record is too short.
+    fn u16(&mut self) -> Result<u16, SaveError>
+    {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    /// Reads a 32-bit integer.
+    ///
+    /// Returns the read value, or an error if the record is too short.
+    fn u32(&mut self) -> Result<u32, SaveError>
+    {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Reads a 64-bit integer.
+    ///
+    /// Returns the read value, or an error if the record is too short.
+    fn u64(&mut self) -> Result<u64, SaveError>
+    {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+/// Computes the CRC-32 used by Ethernet and zip files.
+///
+/// * `bytes`: Data to compute the CRC of.
+///
+/// Returns the computed CRC.
+fn crc32(bytes: &[u8]) -> u32
+{
+    let crc = bytes.iter().fold(!0u32, |crc, byte| {
+                              (0 .. 8).fold(crc ^ *byte as u32, |crc, _| {
+                                          let mask = (crc & 0x1).wrapping_neg();
+                                          crc >> 1 ^ CRC_POLY & mask
+                                      })
+                          });
+    !crc
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::game::Stat;
+
+    fn save() -> Save
+    {
+        let mut map = Map::new(3, 2);
+        map.set_tile((1, 1), Tile::Gold);
+        map.set_tile((2, 0), Tile::Floor);
+        map.set_corner_height((3, 2), -7);
+        let mut creature = Creature::new((2, 0), Fighter::new(40, 12, 3), Wage { amount: 50, missed: 1 });
+        creature.needs = Needs { hunger: 300,
+                                 fatigue: 20,
+                                 anger: 700 };
+        let mut stats = Stats::new();
+        stats.record(Stat::GoldMined, 1500);
+        let mut rng = Rng::new(7);
+        rng.next();
+        Save { map,
+               creatures: [creature].to_vec(),
+               treasury: Treasury::new(320, 45),
+               stats,
+               rng }
+    }
+
+    #[test]
+    fn crc()
+    {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn round_trip()
+    {
+        let save = save();
+        let bytes = save.to_bytes();
+        let loaded = Save::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.map.checksum(), save.map.checksum());
+        assert_eq!(loaded.creatures, save.creatures);
+        assert_eq!(loaded.treasury, save.treasury);
+        assert_eq!(loaded.stats, save.stats);
+        assert_eq!(loaded.rng, save.rng);
+    }
+
+    #[test]
+    fn corruption()
+    {
+        let bytes = save().to_bytes();
+        let mut bad = bytes.clone();
+        bad[10] ^= 0x1;
+        assert!(matches!(Save::from_bytes(&bad).unwrap_err(), SaveError::Crc { .. }));
+        assert!(matches!(Save::from_bytes(&bytes[.. bytes.len() - 1]).unwrap_err(),
+                         SaveError::Crc { .. }));
+        assert_eq!(Save::from_bytes(&bytes[.. 8]).unwrap_err(), SaveError::Truncated);
+        // Appends a field that the current version doesn't know about.
+        let mut bad = bytes[.. bytes.len() - 4].to_vec();
+        bad.push(0);
+        bad.extend_from_slice(&crc32(&bad).to_le_bytes());
+        assert_eq!(Save::from_bytes(&bad).unwrap_err(), SaveError::Trailing(1));
+        let mut bad = bytes;
+        bad[4] = VERSION + 1;
+        assert_eq!(Save::from_bytes(&bad).unwrap_err(), SaveError::Version(VERSION + 1));
+    }
+}
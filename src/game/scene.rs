@@ -0,0 +1,240 @@
+//! Scene management.
+//!
+//! The game moves between scenes like the boot splash, the main menu, and the
+//! dungeon itself.  Scenes are stacked so that one can cover another, like the
+//! pause menu covering the dungeon, in which case every scene in the stack is
+//! drawn from the bottom up but only the top scene is updated.  Scenes start
+//! the tasks they need when entered and stop them when exited, which doesn't
+//! happen to scenes that are merely covered.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::future::Future;
+
+/// Game scene.
+pub trait Scene: Send + Sized
+{
+    /// Starts this scene when it's added to the stage.
+    ///
+    /// Returns a future that completes once the scene is ready.
+    fn enter(&mut self) -> impl Future<Output = ()> + Send + '_;
+
+    /// Stops this scene when it's removed from the stage.
+    ///
+    /// Returns a future that completes once every task started by the scene
+    /// has terminated.
+    fn exit(&mut self) -> impl Future<Output = ()> + Send + '_;
+
+    /// Advances this scene by one frame while it's at the top of the stage.
+    ///
+    /// Returns a future that resolves to the change to apply to the stage.
+    fn update(&mut self) -> impl Future<Output = Transition<Self>> + Send + '_;
+
+    /// Queues the draw commands of this scene for the current frame.
+    ///
+    /// Returns a future that completes once the commands are queued.
+    fn draw(&mut self) -> impl Future<Output = ()> + Send + '_;
+
+    /// Notifies this scene that the frame it drew while at the top of the
+    /// stage has been presented.
+    ///
+    /// * `time`: Time of the presentation in microseconds.
+    fn presented(&mut self, _time: u64) {}
+}
+
+/// Change to the scene stack.
+#[derive(Debug)]
+pub enum Transition<S>
+{
+    /// Keeps the current scene.
+    Stay,
+    /// Replaces the current scene.
+    Switch(S),
+    /// Covers the current scene.
+    Push(S),
+    /// Removes the current scene, uncovering the one below.
+    Pop,
+}
+
+/// Stack of scenes.
+#[derive(Debug)]
+pub struct Stage<S: Scene>
+{
+    /// Scenes from the bottom up.
+    scenes: Vec<S>,
+}
+
+impl<S: Scene> Stage<S>
+{
+    /// Creates and initializes a new stage.
+    ///
+    /// * `scene`: Initial scene, which is entered right away.
+    ///
+    /// Returns the newly created stage.
+    pub async fn new(mut scene: S) -> Self
+    {
+        scene.enter().await;
+        Self { scenes: [scene].into() }
+    }
+
+    /// Returns whether every scene has been removed.
+    pub fn is_empty(&self) -> bool
+    {
+        self.scenes.is_empty()
+    }
+
+    /// Updates the top scene and applies the resulting transition.
+    pub async fn update(&mut self)
+    {
+        let Some(top) = self.scenes.last_mut() else {
+            return;
+        };
+        match top.update().await {
+            Transition::Stay => (),
+            Transition::Switch(mut scene) => {
+                top.exit().await;
+                scene.enter().await;
+                *top = scene;
+            }
+            Transition::Push(mut scene) => {
+                scene.enter().await;
+                self.scenes.push(scene);
+            }
+            Transition::Pop => {
+                top.exit().await;
+                self.scenes.pop();
+            }
+        }
+    }
+
+    /// Draws every scene from the bottom up.
+    pub async fn draw(&mut self)
+    {
+        for scene in self.scenes.iter_mut() {
+            scene.draw().await;
+        }
+    }
+
+    /// Notifies the top scene that its frame has been presented.
+    ///
+    /// * `time`: Time of the presentation in microseconds.
+    pub fn presented(&mut self, time: u64)
+    {
+        if let Some(top) = self.scenes.last_mut() {
+            top.presented(time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use alloc::string::String;
+    use alloc::sync::Arc;
+    use core::pin::pin;
+    use core::ptr::null;
+    use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+    use std::format;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Scene that logs its hooks and follows a script of transitions.
+    struct Logger
+    {
+        /// Name of the scene.
+        name: &'static str,
+        /// Transitions to return from updates, in reverse order.
+        script: Vec<Transition<Self>>,
+        /// Shared log.
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Logger
+    {
+        fn new(name: &'static str, mut script: Vec<Transition<Self>>, log: &Arc<Mutex<Vec<String>>>) -> Self
+        {
+            script.reverse();
+            Self { name,
+                   script,
+                   log: log.clone() }
+        }
+
+        fn log(&self, hook: &str)
+        {
+            self.log.lock().unwrap().push(format!("{} {hook}", self.name));
+        }
+    }
+
+    impl Scene for Logger
+    {
+        async fn enter(&mut self)
+        {
+            self.log("enter");
+        }
+
+        async fn exit(&mut self)
+        {
+            self.log("exit");
+        }
+
+        async fn update(&mut self) -> Transition<Self>
+        {
+            self.log("update");
+            self.script.pop().unwrap_or(Transition::Stay)
+        }
+
+        async fn draw(&mut self)
+        {
+            self.log("draw");
+        }
+
+        fn presented(&mut self, _time: u64)
+        {
+            self.log("presented");
+        }
+    }
+
+    /// Polls a future that never waits to completion.
+    fn run<F: Future>(fut: F) -> F::Output
+    {
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(|_| RawWaker::new(null(), &VTABLE), |_| (), |_| (), |_| ());
+        let waker = unsafe { Waker::from_raw(RawWaker::new(null(), &VTABLE)) };
+        let mut ctx = Context::from_waker(&waker);
+        match pin!(fut).poll(&mut ctx) {
+            Poll::Ready(val) => val,
+            Poll::Pending => panic!("Future is waiting"),
+        }
+    }
+
+    #[test]
+    fn transitions()
+    {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let pause = Logger::new("pause", [Transition::Pop].into(), &log);
+        let game = Logger::new("game", [Transition::Push(pause)].into(), &log);
+        let menu = Logger::new("menu", [Transition::Switch(game)].into(), &log);
+        let mut stage = run(Stage::new(menu));
+        run(stage.update());
+        run(stage.update());
+        run(stage.draw());
+        stage.presented(0);
+        run(stage.update());
+        run(stage.draw());
+        assert!(!stage.is_empty());
+        let expected = ["menu enter",
+                        "menu update",
+                        "menu exit",
+                        "game enter",
+                        "game update",
+                        "pause enter",
+                        "game draw",
+                        "pause draw",
+                        "pause presented",
+                        "pause update",
+                        "pause exit",
+                        "game draw"];
+        assert_eq!(*log.lock().unwrap(), expected);
+    }
+}
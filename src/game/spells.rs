@@ -0,0 +1,208 @@
+//! Keeper spells.
+//!
+//! Spells cost mana and are aimed at a tile, with each spell either affecting
+//! the tile itself, the creature standing on it, or every creature within an
+//! area around it.  Casts are validated before any mana is spent, so failed
+//! casts are free.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FormatResult};
+
+use super::{CombatEvent, Cost, Creature, Imps, Map, Tile, Treasury};
+
+/// Health restored by the heal spell.
+const HEAL_AMOUNT: u32 = 30;
+/// Damage dealt by the lightning spell, which ignores defense.
+const LIGHTNING_DAMAGE: u32 = 40;
+/// Radius in tiles of the area affected by the calm spell.
+const CALM_RADIUS: usize = 2;
+
+/// Spell that the keeper can cast.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Spell
+{
+    /// Summons an imp on a claimed tile.
+    CreateImp,
+    /// Restores some health to a creature.
+    Heal,
+    /// Strikes a creature with lightning.
+    Lightning,
+    /// Calms down all creatures around a tile.
+    Calm,
+}
+
+/// What a spell is aimed at.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Targeting
+{
+    /// The target tile itself.
+    Tile,
+    /// A living creature standing on the target tile.
+    Creature,
+    /// Every living creature within a number of tiles of the target tile.
+    Area(usize),
+}
+
+/// Reasons for a cast to fail.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CastError
+{
+    /// The spell can't be aimed at the target tile.
+    Target,
+    /// There isn't enough mana to cast the spell.
+    Mana,
+}
+
+impl Spell
+{
+    /// Returns the cost of casting this spell.
+    pub fn cost(self) -> Cost
+    {
+        let mana = match self {
+            Self::CreateImp => 300,
+            Self::Heal => 100,
+            Self::Lightning => 200,
+            Self::Calm => 150,
+        };
+        Cost { gold: 0, mana }
+    }
+
+    /// Returns what this spell is aimed at.
+    pub fn targeting(self) -> Targeting
+    {
+        match self {
+            Self::CreateImp => Targeting::Tile,
+            Self::Heal | Self::Lightning => Targeting::Creature,
+            Self::Calm => Targeting::Area(CALM_RADIUS),
+        }
+    }
+
+    /// Casts this spell at a tile, paying for it from the treasury.
+    ///
+    /// * `pos`: Position of the target tile.
+    /// * `map`: Dungeon map.
+    /// * `creatures`: Creatures in the dungeon.
+    /// * `imps`: Imp crew.
+    /// * `treasury`: Treasury to pay from.
+    /// * `events`: Combat events to append to.
+    ///
+    /// Returns an error if the spell can't be aimed at the tile or can't be
+    /// afforded, in which case nothing is spent.
+    pub fn cast(self, pos: (usize, usize), map: &Map, creatures: &mut [Creature], imps: &mut Imps,
+                treasury: &mut Treasury, events: &mut Vec<CombatEvent>)
+                -> Result<(), CastError>
+    {
+        let targets = match self.targeting() {
+            Targeting::Tile if map.tile(pos) == Some(Tile::Claimed) => Vec::new(),
+            Targeting::Tile => return Err(CastError::Target),
+            Targeting::Creature => {
+                let target = creatures.iter()
+                                      .position(|creature| creature.fighter.is_alive() && creature.pos == pos)
+                                      .ok_or(CastError::Target)?;
+                Vec::from([target])
+            }
+            Targeting::Area(radius) => {
+                map.tile(pos).ok_or(CastError::Target)?;
+                let within = |other: (usize, usize)| pos.0.abs_diff(other.0).max(pos.1.abs_diff(other.1)) <= radius;
+                (0 .. creatures.len()).filter(|&idx| creatures[idx].fighter.is_alive() && within(creatures[idx].pos))
+                                      .collect()
+            }
+        };
+        if !treasury.spend(self.cost()) {
+            return Err(CastError::Mana);
+        }
+        if self == Self::CreateImp {
+            imps.spawn(pos);
+        }
+        for idx in targets {
+            let creature = &mut creatures[idx];
+            match self {
+                Self::CreateImp => (),
+                Self::Heal => creature.fighter.health = creature.fighter.health.saturating_add(HEAL_AMOUNT),
+                Self::Lightning => {
+                    creature.fighter.health = creature.fighter.health.saturating_sub(LIGHTNING_DAMAGE);
+                    events.push(CombatEvent::Hit { pos: creature.pos,
+                                                   damage: LIGHTNING_DAMAGE });
+                }
+                Self::Calm => creature.needs.anger = 0,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for CastError
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        match self {
+            Self::Target => write!(fmt, "invalid target"),
+            Self::Mana => write!(fmt, "not enough mana"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::game::{Fighter, Wage};
+
+    fn dungeon() -> (Map, Vec<Creature>)
+    {
+        let mut map = Map::new(6, 1);
+        (0 .. 6).for_each(|x| map.set_tile((x, 0), Tile::Claimed));
+        let fighter = Fighter::new(50, 5, 5);
+        let mut creatures =
+            Vec::from([(0, 0), (2, 0), (5, 0)].map(|pos| Creature::new(pos, fighter.clone(), Wage::new(10))));
+        creatures.iter_mut().for_each(|creature| creature.needs.anger = 500);
+        (map, creatures)
+    }
+
+    #[test]
+    fn validate()
+    {
+        let (mut map, mut creatures) = dungeon();
+        let mut imps = Imps::new((0, 0));
+        let mut treasury = Treasury::new(0, 250);
+        let mut events = Vec::new();
+        let mut cast =
+            |spell: Spell, pos, map: &Map| spell.cast(pos, map, &mut creatures, &mut imps, &mut treasury, &mut events);
+        assert_eq!(cast(Spell::Heal, (1, 0), &map), Err(CastError::Target));
+        assert_eq!(cast(Spell::Calm, (6, 0), &map), Err(CastError::Target));
+        assert_eq!(cast(Spell::CreateImp, (1, 0), &map), Err(CastError::Mana));
+        map.set_tile((1, 0), Tile::Floor);
+        assert_eq!(cast(Spell::CreateImp, (1, 0), &map), Err(CastError::Target));
+        assert_eq!(cast(Spell::Heal, (2, 0), &map), Ok(()));
+        assert_eq!(cast(Spell::Lightning, (2, 0), &map), Err(CastError::Mana));
+        assert_eq!(treasury.mana(), 150);
+        assert!(imps.imps().is_empty());
+        assert_eq!(creatures[1].fighter.health, 50 + HEAL_AMOUNT);
+    }
+
+    #[test]
+    fn effects()
+    {
+        let (map, mut creatures) = dungeon();
+        let mut imps = Imps::new((0, 0));
+        let mut treasury = Treasury::new(0, 1000);
+        let mut events = Vec::new();
+        let mut cast = |spell: Spell, pos| spell.cast(pos, &map, &mut creatures, &mut imps, &mut treasury, &mut events);
+        assert_eq!(cast(Spell::Calm, (1, 0)), Ok(()));
+        assert_eq!(cast(Spell::Lightning, (5, 0)), Ok(()));
+        assert_eq!(cast(Spell::Lightning, (5, 0)), Ok(()));
+        // The creature is dead until despawned, so it can't be struck again.
+        assert_eq!(cast(Spell::Lightning, (5, 0)), Err(CastError::Target));
+        assert_eq!(cast(Spell::CreateImp, (3, 0)), Ok(()));
+        let anger = creatures.iter()
+                             .map(|creature| creature.needs.anger)
+                             .collect::<Vec<_>>();
+        assert_eq!(anger, [0, 0, 500]);
+        assert!(!creatures[2].fighter.is_alive());
+        assert_eq!(events.len(), 2);
+        assert_eq!(imps.imps()[0].position(), (3, 0));
+        assert_eq!(treasury.mana(), 1000 - 150 - 2 * 200 - 300);
+    }
+}
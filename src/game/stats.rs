@@ -0,0 +1,188 @@
+//! Player statistics and achievements.
+//!
+//! Statistics accumulate across sessions, so they can be serialized into a
+//! compact versioned record for storage, and unlock achievements once they
+//! reach certain thresholds.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+/// Magic bytes identifying a serialized statistics record.
+const MAGIC: [u8; 4] = *b"NBST";
+/// Version of the serialized statistics record.
+const VERSION: u8 = 1;
+/// Number of tracked statistics.
+const STAT_COUNT: usize = 3;
+/// Size of a serialized statistics record.
+pub(super) const RECORD_LEN: usize = MAGIC.len() + 1 + STAT_COUNT * 8 + 4;
+
+/// All achievements in unlock bit order.
+pub const ACHIEVEMENTS: [Achievement; 5] = [Achievement { name: "Prospector",
+                                                          stat: Stat::GoldMined,
+                                                          threshold: 1000 },
+                                            Achievement { name: "Tycoon",
+                                                          stat: Stat::GoldMined,
+                                                          threshold: 100000 },
+                                            Achievement { name: "Drill Sergeant",
+                                                          stat: Stat::CreaturesTrained,
+                                                          threshold: 50 },
+                                            Achievement { name: "First Blood",
+                                                          stat: Stat::LevelsWon,
+                                                          threshold: 1 },
+                                            Achievement { name: "Overlord",
+                                                          stat: Stat::LevelsWon,
+                                                          threshold: 20 }];
+
+/// Accumulated statistics.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stats
+{
+    /// Statistic values indexed by [`Stat`].
+    vals: [u64; STAT_COUNT],
+    /// Bit mask of unlocked achievements indexed like [`ACHIEVEMENTS`].
+    unlocked: u32,
+}
+
+/// Tracked statistic.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stat
+{
+    /// Total gold mined.
+    GoldMined,
+    /// Total creatures trained.
+    CreaturesTrained,
+    /// Total levels won.
+    LevelsWon,
+}
+
+/// Achievement unlocked by a statistic reaching a threshold.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Achievement
+{
+    /// Name to show when unlocked.
+    pub name: &'static str,
+    /// Statistic that unlocks this achievement.
+    pub stat: Stat,
+    /// Value the statistic must reach.
+    pub threshold: u64,
+}
+
+impl Stats
+{
+    /// Creates and initializes new empty statistics.
+    ///
+    /// Returns the newly created statistics.
+    pub const fn new() -> Self
+    {
+        Self { vals: [0; STAT_COUNT],
+               unlocked: 0 }
+    }
+
+    /// Returns the current value of a statistic.
+    ///
+    /// * `stat`: Statistic to query.
+    pub fn get(&self, stat: Stat) -> u64
+    {
+        self.vals[stat as usize]
+    }
+
+    /// Adds to a statistic and unlocks any achievements that it reaches.
+    ///
+    /// * `stat`: Statistic to add to.
+    /// * `amount`: Amount to add.
+    ///
+    /// Returns the newly unlocked achievements, to be announced by the caller.
+    pub fn record(&mut self, stat: Stat, amount: u64) -> Vec<&'static Achievement>
+    {
+        let val = &mut self.vals[stat as usize];
+        *val = val.saturating_add(amount);
+        let val = *val;
+        let mut unlocked = Vec::new();
+        for (idx, achievement) in ACHIEVEMENTS.iter().enumerate() {
+            if achievement.stat == stat && val >= achievement.threshold && self.unlocked & 1 << idx == 0 {
+                self.unlocked |= 1 << idx;
+                unlocked.push(achievement);
+            }
+        }
+        unlocked
+    }
+
+    /// Returns whether an achievement has been unlocked.
+    ///
+    /// * `name`: Name of the achievement.
+    pub fn is_unlocked(&self, name: &str) -> bool
+    {
+        ACHIEVEMENTS.iter()
+                    .position(|achievement| achievement.name == name)
+                    .is_some_and(|idx| self.unlocked & 1 << idx != 0)
+    }
+
+    /// Serializes these statistics for storage.
+    ///
+    /// Returns the serialized record.
+    pub fn to_bytes(&self) -> [u8; RECORD_LEN]
+    {
+        let mut bytes = [0; RECORD_LEN];
+        bytes[.. 4].copy_from_slice(&MAGIC);
+        bytes[4] = VERSION;
+        for (chunk, val) in bytes[5 ..].chunks_exact_mut(8).zip(self.vals) {
+            chunk.copy_from_slice(&val.to_le_bytes());
+        }
+        bytes[RECORD_LEN - 4 ..].copy_from_slice(&self.unlocked.to_le_bytes());
+        bytes
+    }
+
+    /// Deserializes statistics from storage.
+    ///
+    /// * `bytes`: Serialized record.
+    ///
+    /// Returns the deserialized statistics, or [`None`] if the record is
+    /// invalid or from an unsupported version.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self>
+    {
+        if bytes.len() != RECORD_LEN || bytes[.. 4] != MAGIC || bytes[4] != VERSION {
+            return None;
+        }
+        let mut this = Self::new();
+        for (val, chunk) in this.vals.iter_mut().zip(bytes[5 ..].chunks_exact(8)) {
+            *val = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+        this.unlocked = u32::from_le_bytes(bytes[RECORD_LEN - 4 ..].try_into().unwrap());
+        Some(this)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn unlock()
+    {
+        let mut stats = Stats::new();
+        assert!(stats.record(Stat::GoldMined, 999).is_empty());
+        let unlocked = stats.record(Stat::GoldMined, 1);
+        assert_eq!(unlocked.iter().map(|a| a.name).collect::<Vec<_>>(), ["Prospector"]);
+        assert!(stats.record(Stat::GoldMined, 1).is_empty());
+        assert!(stats.is_unlocked("Prospector"));
+        assert!(!stats.is_unlocked("Tycoon"));
+        assert_eq!(stats.record(Stat::LevelsWon, 20).len(), 2);
+        assert_eq!(stats.get(Stat::GoldMined), 1001);
+    }
+
+    #[test]
+    fn persistence()
+    {
+        let mut stats = Stats::new();
+        stats.record(Stat::CreaturesTrained, 60);
+        stats.record(Stat::GoldMined, 5);
+        let bytes = stats.to_bytes();
+        assert_eq!(Stats::from_bytes(&bytes), Some(stats));
+        assert_eq!(Stats::from_bytes(&bytes[1 ..]), None);
+        let mut bytes = bytes;
+        bytes[4] = VERSION + 1;
+        assert_eq!(Stats::from_bytes(&bytes), None);
+    }
+}
@@ -0,0 +1,264 @@
+//! Dungeon terrain mesh.
+//!
+//! Builds flat shaded triangles out of the map in map space, where tile `(x,
+//! y)` spans from `x` to `x + 1` along the X axis and from `y` to `y + 1` along
+//! the Z axis, with the Y axis pointing up.  Walkable tiles are floors laid
+//! over the heightfield at their corners, so dug out trenches and raised
+//! platforms slope into their surroundings, and solid tiles are blocks whose
+//! tops follow the same heightfield at a fixed height above it, with walls
+//! facing the walkable tiles next to them.  Furnished floors take the color of
+//! their room, tiles out of sight are darkened, and unexplored tiles are left
+//! out altogether.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::simd::f32x4;
+
+use super::{Fog, Map, Rooms, Tile};
+use crate::simd::SimdFloatExtra;
+
+/// World units per unit of corner height.
+const HEIGHT_UNIT: f32 = 0.125;
+/// Height of solid blocks above the heightfield in world units.
+const BLOCK_HEIGHT: f32 = 1.0;
+/// Upward direction.
+const UP: f32x4 = f32x4::from_array([0.0, 1.0, 0.0, 0.0]);
+
+/// Dungeon terrain mesh.
+#[derive(Clone, Debug, Default)]
+pub struct Terrain
+{
+    /// Triangles of the mesh.
+    facets: Vec<Facet>,
+}
+
+/// Flat shaded triangle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Facet
+{
+    /// Corners in map space, counter-clockwise when seen from the front.
+    pub corners: [f32x4; 3],
+    /// Normal pointing out of the front.
+    pub normal: f32x4,
+    /// Color.
+    pub color: f32x4,
+}
+
+impl Terrain
+{
+    /// Creates and initializes a new empty terrain mesh.
+    ///
+    /// Returns the newly created mesh.
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Rebuilds the mesh.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `rooms`: Rooms furnished on the map.
+    /// * `fog`: What the keeper knows about the map.
+    ///
+    /// Returns whether any triangle changed.
+    pub fn build(&mut self, map: &Map, rooms: &Rooms, fog: &Fog) -> bool
+    {
+        let mut facets = Vec::with_capacity(self.facets.len());
+        for y in 0 .. map.height() {
+            for x in 0 .. map.width() {
+                Self::build_tile(&mut facets, map, rooms, fog, (x, y));
+            }
+        }
+        if facets == self.facets {
+            return false;
+        }
+        self.facets = facets;
+        true
+    }
+
+    /// Returns the triangles of the mesh.
+    pub fn facets(&self) -> &[Facet]
+    {
+        &self.facets
+    }
+
+    /// Computes the height of the ground in the middle of a tile.
+    ///
+    /// * `map`: Dungeon map.
+    /// * `pos`: Position of the tile.
+    ///
+    /// Returns the computed height in world units, or zero if the position is
+    /// out of bounds.
+    pub fn elevation(map: &Map, pos: (usize, usize)) -> f32
+    {
+        map.tile_height(pos).unwrap_or_default() as f32 * HEIGHT_UNIT
+    }
+
+    /// Builds the triangles of a tile.
+    ///
+    /// * `facets`: Triangles to append to.
+    /// * `map`: Dungeon map.
+    /// * `rooms`: Rooms furnished on the map.
+    /// * `fog`: What the keeper knows about the map.
+    /// * `pos`: Position of the tile.
+    fn build_tile(facets: &mut Vec<Facet>, map: &Map, rooms: &Rooms, fog: &Fog, pos: (usize, usize))
+    {
+        let (Some(tile), Some(heights)) = (fog.explored_tile(map, pos), map.corner_heights(pos)) else {
+            return;
+        };
+        let shade = fog.shade(pos);
+        let shade = f32x4::from_array([shade, shade, shade, 1.0]);
+        let (x, z) = (pos.0 as f32, pos.1 as f32);
+        let corners = [(x, z), (x + 1.0, z), (x, z + 1.0), (x + 1.0, z + 1.0)];
+        let ground = [0, 1, 2, 3].map(|idx| {
+                                     let (x, z) = corners[idx];
+                                     f32x4::from_array([x, heights[idx] as f32 * HEIGHT_UNIT, z, 1.0])
+                                 });
+        if tile.is_walkable() {
+            let color = rooms.floor_color(pos).unwrap_or(tile.color());
+            quad(facets, ground, UP, color * shade);
+            return;
+        }
+        let lift = UP.mul_scalar(BLOCK_HEIGHT);
+        let top = ground.map(|corner| corner + lift);
+        let color = tile.color() * shade;
+        quad(facets, top, UP, color);
+        // Sides as pairs of corner indices along with the direction they face
+        // and the position of the neighbor across them.
+        let sides = [([0, 1], [0.0, -1.0], pos.1.checked_sub(1).map(|y| (pos.0, y))),
+                     ([3, 2], [0.0, 1.0], Some((pos.0, pos.1 + 1))),
+                     ([2, 0], [-1.0, 0.0], pos.0.checked_sub(1).map(|x| (x, pos.1))),
+                     ([1, 3], [1.0, 0.0], Some((pos.0 + 1, pos.1)))];
+        for ([start, end], [dx, dz], neighbor) in sides {
+            if !neighbor.and_then(|pos| fog.explored_tile(map, pos))
+                        .is_some_and(Tile::is_walkable)
+            {
+                continue;
+            }
+            let facing = f32x4::from_array([dx, 0.0, dz, 0.0]);
+            quad(facets,
+                 [ground[start], ground[end], top[start], top[end]],
+                 facing,
+                 color);
+        }
+    }
+}
+
+/// Appends a quad as a pair of triangles facing a direction, split along the
+/// diagonal through its lowest corner so that pits and trenches fold inwards.
+///
+/// * `facets`: Triangles to append to.
+/// * `corners`: Corners of the quad, with the first two and the last two along
+///   opposite edges in the same direction.
+/// * `facing`: Direction that the quad faces.
+/// * `color`: Color of the quad.
+fn quad(facets: &mut Vec<Facet>, corners: [f32x4; 4], facing: f32x4, color: f32x4)
+{
+    let [c0, c1, c2, c3] = corners;
+    let heights = f32x4::from_array(corners.map(SimdFloatExtra::y));
+    let tris = match heights.reduce_min_index() {
+        Some(1 | 2) => [[c0, c2, c1], [c1, c2, c3]],
+        _ => [[c0, c3, c1], [c0, c2, c3]],
+    };
+    for [a, b, c] in tris {
+        let normal = (b - a).cross_dot(c - a).xyz0();
+        let Some(normal) = normal.normalize() else {
+            // Degenerate triangle.
+            continue;
+        };
+        let facet = if normal.dot(facing) >= 0.0 {
+            Facet { corners: [a, b, c],
+                    normal,
+                    color }
+        } else {
+            Facet { corners: [a, c, b],
+                    normal: -normal,
+                    color }
+        };
+        facets.push(facet);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn floors_and_walls()
+    {
+        let mut map = Map::new(3, 1);
+        map.set_tile((1, 0), Tile::Floor);
+        let rooms = Rooms::new(&map);
+        let mut fog = Fog::new(&map);
+        fog.update(&map, [(1, 0)]);
+        let mut terrain = Terrain::new();
+        assert!(terrain.build(&map, &rooms, &fog));
+        // A floor, two block tops, and the walls facing the floor.
+        assert_eq!(terrain.facets().len(), 2 * 5);
+        for facet in terrain.facets() {
+            let [a, b, c] = facet.corners;
+            let normal = (b - a).cross_dot(c - a).xyz0();
+            assert!(normal.dot(facet.normal) > 0.0);
+        }
+        let walls = terrain.facets().iter().filter(|facet| facet.normal[1] == 0.0);
+        assert!(walls.flat_map(|facet| facet.corners)
+                     .all(|corner| corner[0] == 1.0 || corner[0] == 2.0));
+        assert!(!terrain.build(&map, &rooms, &fog));
+    }
+
+    #[test]
+    fn slopes()
+    {
+        let mut map = Map::new(2, 1);
+        (0 .. 2).for_each(|x| map.set_tile((x, 0), Tile::Floor));
+        map.set_corner_height((2, 0), 8);
+        map.set_corner_height((2, 1), 8);
+        let rooms = Rooms::new(&map);
+        let mut fog = Fog::new(&map);
+        fog.update(&map, [(0, 0)]);
+        let mut terrain = Terrain::new();
+        assert!(terrain.build(&map, &rooms, &fog));
+        let (flat, sloped) = terrain.facets().split_at(2);
+        assert!(flat.iter().all(|facet| facet.normal == UP));
+        assert!(sloped.iter()
+                      .all(|facet| facet.normal[0] < 0.0 && facet.normal[1] > 0.0));
+        assert_eq!(Terrain::elevation(&map, (0, 0)), 0.0);
+        assert_eq!(Terrain::elevation(&map, (1, 0)), 4.0 * HEIGHT_UNIT);
+        // A pit at a single corner folds along the diagonal through it.
+        map.set_corner_height((0, 0), -8);
+        assert!(terrain.build(&map, &rooms, &fog));
+        let pit = f32x4::from_array([0.0, -8.0 * HEIGHT_UNIT, 0.0, 1.0]);
+        assert!(terrain.facets()[.. 2].iter().all(|facet| facet.corners.contains(&pit)));
+    }
+
+    #[test]
+    fn fog()
+    {
+        // A corridor with the imp at its left end unable to see past
+        // the earth near it.
+        let mut map = Map::new(12, 1);
+        (0 .. 12).filter(|&x| x != 4)
+                 .for_each(|x| map.set_tile((x, 0), Tile::Floor));
+        let rooms = Rooms::new(&map);
+        let mut fog = Fog::new(&map);
+        let mut terrain = Terrain::new();
+        assert!(!terrain.build(&map, &rooms, &fog));
+        fog.update(&map, [(0, 0)]);
+        assert!(terrain.build(&map, &rooms, &fog));
+        // The floors up to the earth, the top of the earth, and the wall facing
+        // the explored side.
+        assert_eq!(terrain.facets().len(), 2 * 6);
+        assert!(terrain.facets()
+                       .iter()
+                       .flat_map(|facet| facet.corners)
+                       .all(|corner| corner[0] <= 5.0));
+        // Walking away leaves the explored tiles darkened.
+        let lit = terrain.facets()[0].color;
+        fog.update(&map, []);
+        assert!(terrain.build(&map, &rooms, &fog));
+        assert_eq!(terrain.facets()[0].color[0], lit[0] * fog.shade((0, 0)));
+        assert_eq!(terrain.facets()[0].color[3], 1.0);
+    }
+}
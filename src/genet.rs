@@ -0,0 +1,584 @@
+//! Ethernet driver.
+//!
+//! Drives the GENET controller that the Raspberry Pi 4 B's Ethernet port is
+//! wired to through a BCM54213PE PHY.  Broadcom doesn't document the
+//! controller, so its registers and bring-up sequence follow the Linux [1] and
+//! U-Boot [2] drivers, whereas the PHY is driven through the standard MII
+//! management registers, with the negotiated speed read from its auxiliary
+//! status register.
+//!
+//! Only the default queue is used in each direction, with frames copied
+//! between the interface and descriptor ring buffers in the uncached region.
+//! Received frames raise an IRQ that wakes the task feeding them to the
+//! network interface, since the interface must not process them in IRQ
+//! context, and the same task attaches the driver to the interface once the
+//! link first comes up.  Frames queued while the transmit ring is full are
+//! dropped, like the [`Device`] trait allows.
+//!
+//! [1]: https://github.com/torvalds/linux/tree/master/drivers/net/ethernet/broadcom/genet
+//! [2]: https://github.com/u-boot/u-boot/blob/master/drivers/net/bcmgenet.c
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+use core::sync::atomic::{fence, Ordering};
+
+use crate::alloc::{Alloc, UNCACHED_REGION};
+use crate::board::INFO;
+use crate::clock::now_micros;
+use crate::irq::IRQ;
+use crate::mmu::to_phys;
+use crate::net::{Config, Device, MacAddress, NET};
+use crate::sched::timeout;
+use crate::sync::{Lazy, Lock, Notify};
+use crate::timer::TIMER;
+use crate::{debug, PERRY_RANGE};
+
+/// GENET base address.
+const BASE: usize = PERRY_RANGE.start + 0x1580000;
+/// Port control register.
+const SYS_PORT_CTRL: *mut u32 = (BASE + 0x4) as _;
+/// Receive buffer flush control register.
+const SYS_RBUF_FLUSH_CTRL: *mut u32 = (BASE + 0x8) as _;
+/// RGMII out of band control register.
+const EXT_RGMII_OOB_CTRL: *mut u32 = (BASE + 0x8C) as _;
+/// Interrupt status register.
+const INTRL2_STAT: *const u32 = (BASE + 0x200) as _;
+/// Interrupt clear register.
+const INTRL2_CLEAR: *mut u32 = (BASE + 0x208) as _;
+/// Interrupt mask status register.
+const INTRL2_MASK_STATUS: *const u32 = (BASE + 0x20C) as _;
+/// Interrupt mask set register.
+const INTRL2_MASK_SET: *mut u32 = (BASE + 0x210) as _;
+/// Interrupt mask clear register.
+const INTRL2_MASK_CLEAR: *mut u32 = (BASE + 0x214) as _;
+/// Receive buffer control register.
+const RBUF_CTRL: *mut u32 = (BASE + 0x300) as _;
+/// Receive and transmit buffer size control register.
+const RBUF_TBUF_SIZE_CTRL: *mut u32 = (BASE + 0x3B4) as _;
+/// MAC command register.
+const UMAC_CMD: *mut u32 = (BASE + 0x808) as _;
+/// First hardware address register.
+const UMAC_MAC0: *mut u32 = (BASE + 0x80C) as _;
+/// Second hardware address register.
+const UMAC_MAC1: *mut u32 = (BASE + 0x810) as _;
+/// Maximum frame length register.
+const UMAC_MAX_FRAME_LEN: *mut u32 = (BASE + 0x814) as _;
+/// Transmit flush register.
+const UMAC_TX_FLUSH: *mut u32 = (BASE + 0xB34) as _;
+/// Statistics counters control register.
+const UMAC_MIB_CTRL: *mut u32 = (BASE + 0xD80) as _;
+/// MDIO command register.
+const MDIO_CMD: *mut u32 = (BASE + 0xE14) as _;
+/// First receive descriptor, with the rest following every [`DESC_SIZE`]
+/// bytes.
+const RX_DESCS_BASE: usize = BASE + 0x2000;
+/// Registers of the default receive queue.
+const RDMA_RING: usize = BASE + 0x3000;
+/// Receive write pointer register.
+const RDMA_WRITE_PTR: *mut u32 = RDMA_RING as _;
+/// Receive producer index register.
+const RDMA_PROD_INDEX: *mut u32 = (RDMA_RING + 0x8) as _;
+/// Receive consumer index register.
+const RDMA_CONS_INDEX: *mut u32 = (RDMA_RING + 0xC) as _;
+/// Receive ring and buffer size register.
+const RDMA_RING_BUF_SIZE: *mut u32 = (RDMA_RING + 0x10) as _;
+/// Receive ring start address register.
+const RDMA_START_ADDR: *mut u32 = (RDMA_RING + 0x14) as _;
+/// Receive ring end address register.
+const RDMA_END_ADDR: *mut u32 = (RDMA_RING + 0x1C) as _;
+/// Receive interrupt threshold register.
+const RDMA_MBUF_DONE_THRESH: *mut u32 = (RDMA_RING + 0x24) as _;
+/// Receive flow control threshold register.
+const RDMA_XON_XOFF_THRESH: *mut u32 = (RDMA_RING + 0x28) as _;
+/// Receive read pointer register.
+const RDMA_READ_PTR: *mut u32 = (RDMA_RING + 0x2C) as _;
+/// Receive queue configuration register.
+const RDMA_RING_CFG: *mut u32 = (BASE + 0x3040) as _;
+/// Receive DMA control register.
+const RDMA_CTRL: *mut u32 = (BASE + 0x3044) as _;
+/// Receive DMA burst size register.
+const RDMA_SCB_BURST_SIZE: *mut u32 = (BASE + 0x304C) as _;
+/// First transmit descriptor, with the rest following every [`DESC_SIZE`]
+/// bytes.
+const TX_DESCS_BASE: usize = BASE + 0x4000;
+/// Registers of the default transmit queue.
+const TDMA_RING: usize = BASE + 0x5000;
+/// Transmit read pointer register.
+const TDMA_READ_PTR: *mut u32 = TDMA_RING as _;
+/// Transmit consumer index register.
+const TDMA_CONS_INDEX: *mut u32 = (TDMA_RING + 0x8) as _;
+/// Transmit producer index register.
+const TDMA_PROD_INDEX: *mut u32 = (TDMA_RING + 0xC) as _;
+/// Transmit ring and buffer size register.
+const TDMA_RING_BUF_SIZE: *mut u32 = (TDMA_RING + 0x10) as _;
+/// Transmit ring start address register.
+const TDMA_START_ADDR: *mut u32 = (TDMA_RING + 0x14) as _;
+/// Transmit ring end address register.
+const TDMA_END_ADDR: *mut u32 = (TDMA_RING + 0x1C) as _;
+/// Transmit interrupt threshold register.
+const TDMA_MBUF_DONE_THRESH: *mut u32 = (TDMA_RING + 0x24) as _;
+/// Transmit flow period register.
+const TDMA_FLOW_PERIOD: *mut u32 = (TDMA_RING + 0x28) as _;
+/// Transmit write pointer register.
+const TDMA_WRITE_PTR: *mut u32 = (TDMA_RING + 0x2C) as _;
+/// Transmit queue configuration register.
+const TDMA_RING_CFG: *mut u32 = (BASE + 0x5040) as _;
+/// Transmit DMA control register.
+const TDMA_CTRL: *mut u32 = (BASE + 0x5044) as _;
+/// Transmit DMA burst size register.
+const TDMA_SCB_BURST_SIZE: *mut u32 = (BASE + 0x504C) as _;
+/// Port mode of an external gigabit PHY.
+const PORT_MODE_EXT_GPHY: u32 = 0x3;
+/// Receive buffer flush reset flag.
+const RBUF_FLUSH_RESET: u32 = 0x2;
+/// RGMII link up flag.
+const OOB_RGMII_LINK: u32 = 0x10;
+/// RGMII out of band signalling disable flag.
+const OOB_DISABLE: u32 = 0x20;
+/// RGMII mode enable flag.
+const OOB_RGMII_MODE_EN: u32 = 0x40;
+/// RGMII internal delay disable flag, as the PHY delays the receive clock.
+const OOB_ID_MODE_DIS: u32 = 0x10000;
+/// Receive buffer flag aligning the payload of frames to 4 bytes by
+/// prepending [`RX_PAD`] bytes to them.
+const RBUF_ALIGN_2B: u32 = 0x2;
+/// MAC command transmit enable flag.
+const CMD_TX_EN: u32 = 0x1;
+/// MAC command receive enable flag.
+const CMD_RX_EN: u32 = 0x2;
+/// MAC command speed shift.
+const CMD_SPEED_SHIFT: u32 = 2;
+/// MAC command software reset flag.
+const CMD_SW_RESET: u32 = 0x2000;
+/// MAC command local loopback flag.
+const CMD_LCL_LOOP_EN: u32 = 0x8000;
+/// Statistics counters reset flags.
+const MIB_RESET: u32 = 0x7;
+/// MDIO command read operation.
+const MDIO_RD: u32 = 0x8000000;
+/// MDIO command write operation.
+const MDIO_WR: u32 = 0x4000000;
+/// MDIO command read failure flag.
+const MDIO_READ_FAIL: u32 = 0x10000000;
+/// MDIO command start and busy flag.
+const MDIO_START_BUSY: u32 = 0x20000000;
+/// MDIO command PHY address shift.
+const MDIO_PMD_SHIFT: u32 = 21;
+/// MDIO command register number shift.
+const MDIO_REG_SHIFT: u32 = 16;
+/// DMA control enable flag.
+const DMA_EN: u32 = 0x1;
+/// DMA control flag enabling the default queue.
+const DMA_DEFAULT_QUEUE_EN: u32 = 0x20000;
+/// Queue configuration flag enabling the default queue.
+const RING_CFG_DEFAULT_QUEUE: u32 = 0x10000;
+/// Maximum DMA burst length.
+const DMA_MAX_BURST_LENGTH: u32 = 0x8;
+/// Interrupt flag raised when a frame is received on the default queue.
+const INT_RXDMA_MBDONE: u32 = 0x2000;
+/// Descriptor length and status register offset.
+const DESC_LENGTH_STATUS: usize = 0x0;
+/// Descriptor low address register offset.
+const DESC_ADDRESS_LO: usize = 0x4;
+/// Descriptor high address register offset.
+const DESC_ADDRESS_HI: usize = 0x8;
+/// Size of a descriptor.
+const DESC_SIZE: usize = 12;
+/// Descriptor length shift.
+const DESC_LENGTH_SHIFT: u32 = 16;
+/// Descriptor length mask after shifting.
+const DESC_LENGTH_MASK: u32 = 0xFFF;
+/// Descriptor owned by the controller flag.
+const DESC_OWN: u32 = 0x8000;
+/// Descriptor end of packet flag.
+const DESC_EOP: u32 = 0x4000;
+/// Descriptor start of packet flag.
+const DESC_SOP: u32 = 0x2000;
+/// Transmit descriptor frame check sequence append flag.
+const DESC_TX_APPEND_CRC: u32 = 0x40;
+/// Transmit descriptor queue tag of the default queue.
+const DESC_TX_QTAG: u32 = 0x3F << 7;
+/// Receive descriptor error flags.
+const DESC_RX_ERRORS: u32 = 0x1F;
+/// Number of descriptors in each ring, which must divide the 16 bit producer
+/// and consumer indices evenly.
+const RING_LEN: usize = 64;
+/// Size of each descriptor's buffer.
+const BUF_LEN: usize = 2048;
+/// Maximum size of a frame, including a VLAN tag, the frame check sequence,
+/// and some slack required by the controller.
+const MAX_FRAME_LEN: u32 = 1536;
+/// Minimum size of a frame without the frame check sequence, with shorter
+/// frames padded with zeros.
+const MIN_FRAME_LEN: usize = 60;
+/// Padding prepended to received frames.
+const RX_PAD: usize = 2;
+/// Receive flow control thresholds.
+const RX_FLOW_THRESH: u32 = 5 << 16 | (RING_LEN as u32 >> 4);
+/// IRQ raised by the controller.
+const GENET_IRQ: u32 = 189;
+/// MDIO address of the PHY.
+const PHY_ADDR: u32 = 1;
+/// PHY basic control register.
+const MII_BMCR: u32 = 0x0;
+/// PHY basic status register.
+const MII_BMSR: u32 = 0x1;
+/// PHY auto-negotiation advertisement register.
+const MII_ADVERTISE: u32 = 0x4;
+/// PHY 1000BASE-T control register.
+const MII_CTRL1000: u32 = 0x9;
+/// PHY auxiliary status register.
+const MII_AUX_STATUS: u32 = 0x19;
+/// Basic control auto-negotiation restart flag.
+const BMCR_ANRESTART: u32 = 0x200;
+/// Basic control auto-negotiation enable flag.
+const BMCR_ANENABLE: u32 = 0x1000;
+/// Basic control reset flag.
+const BMCR_RESET: u32 = 0x8000;
+/// Basic status link up flag.
+const BMSR_LSTATUS: u32 = 0x4;
+/// Basic status auto-negotiation complete flag.
+const BMSR_ANEGCOMPLETE: u32 = 0x20;
+/// Advertisement of all 10BASE-T and 100BASE-TX modes over IEEE 802.3.
+const ADVERTISE_ALL: u32 = 0x1E1;
+/// Advertisement of all 1000BASE-T modes.
+const ADVERTISE_1000: u32 = 0x300;
+/// Auxiliary status resolved speed and duplex shift.
+const AUX_STATUS_HCD_SHIFT: u32 = 8;
+/// Auxiliary status resolved speed and duplex mask after shifting.
+const AUX_STATUS_HCD_MASK: u32 = 0x7;
+/// Time to wait for the MDIO bus and PHY resets in microseconds.
+const TIMEOUT: u64 = 100000;
+/// Time in milliseconds between link status checks.
+const LINK_POLL_PERIOD: u64 = 500;
+/// Time in milliseconds after which received frames are looked for even if no
+/// IRQ arrives, which covers frames received between draining the ring and
+/// waiting for the next IRQ.
+const RX_POLL_PERIOD: u64 = 10;
+
+/// Global Ethernet driver instance.
+pub static GENET: Lazy<Genet> = Lazy::new(Genet::new);
+
+/// Uncached memory allocator for the descriptor ring buffers.
+static UNCACHED: Alloc<0x40> = Alloc::with_region(&UNCACHED_REGION);
+
+/// Ethernet driver.
+#[derive(Debug)]
+pub struct Genet
+{
+    /// Hardware address.
+    mac: MacAddress,
+    /// Transmit ring.
+    tx: Lock<Ring>,
+    /// Receive ring.
+    rx: Lock<Ring>,
+    /// Task waiting for received frames.
+    received: Notify,
+}
+
+/// Descriptor ring.
+#[derive(Debug)]
+struct Ring
+{
+    /// Buffer of each descriptor.
+    bufs: Vec<[u8; BUF_LEN], Alloc<'static, 0x40>>,
+    /// Producer index of the next frame to transmit, or consumer index of the
+    /// next frame to receive.
+    index: u16,
+}
+
+impl Genet
+{
+    /// Creates and initializes a new Ethernet driver, resetting the controller
+    /// and starting the PHY's auto-negotiation.
+    ///
+    /// Returns the newly created driver.
+    fn new() -> Self
+    {
+        let mac = INFO.mac;
+        unsafe {
+            SYS_PORT_CTRL.write_volatile(PORT_MODE_EXT_GPHY);
+            SYS_RBUF_FLUSH_CTRL.write_volatile(RBUF_FLUSH_RESET);
+            delay(10);
+            SYS_RBUF_FLUSH_CTRL.write_volatile(0);
+            delay(10);
+            // Resetting with loopback enabled ensures a stable receive clock.
+            UMAC_CMD.write_volatile(0);
+            UMAC_CMD.write_volatile(CMD_SW_RESET | CMD_LCL_LOOP_EN);
+            delay(2);
+            UMAC_CMD.write_volatile(0);
+            UMAC_MIB_CTRL.write_volatile(MIB_RESET);
+            UMAC_MIB_CTRL.write_volatile(0);
+            UMAC_MAX_FRAME_LEN.write_volatile(MAX_FRAME_LEN);
+            RBUF_CTRL.write_volatile(RBUF_CTRL.read_volatile() | RBUF_ALIGN_2B);
+            RBUF_TBUF_SIZE_CTRL.write_volatile(1);
+            UMAC_MAC0.write_volatile(u32::from_be_bytes([mac[0], mac[1], mac[2], mac[3]]));
+            UMAC_MAC1.write_volatile(u32::from_be_bytes([0, 0, mac[4], mac[5]]));
+            INTRL2_MASK_SET.write_volatile(u32::MAX);
+            INTRL2_CLEAR.write_volatile(u32::MAX);
+            TDMA_CTRL.write_volatile(0);
+            RDMA_CTRL.write_volatile(0);
+            UMAC_TX_FLUSH.write_volatile(1);
+            delay(10);
+            UMAC_TX_FLUSH.write_volatile(0);
+        }
+        let rx = Ring::new();
+        let tx = Ring::new();
+        let ring_size = (RING_LEN as u32) << 16 | BUF_LEN as u32;
+        let end = (RING_LEN * DESC_SIZE / 4 - 1) as u32;
+        let status = (BUF_LEN as u32) << DESC_LENGTH_SHIFT | DESC_OWN;
+        unsafe {
+            // The indices can only be reset while the DMA engines are disabled.
+            RDMA_SCB_BURST_SIZE.write_volatile(DMA_MAX_BURST_LENGTH);
+            RDMA_START_ADDR.write_volatile(0);
+            RDMA_END_ADDR.write_volatile(end);
+            RDMA_READ_PTR.write_volatile(0);
+            RDMA_WRITE_PTR.write_volatile(0);
+            RDMA_PROD_INDEX.write_volatile(0);
+            RDMA_CONS_INDEX.write_volatile(0);
+            RDMA_RING_BUF_SIZE.write_volatile(ring_size);
+            RDMA_MBUF_DONE_THRESH.write_volatile(1);
+            RDMA_XON_XOFF_THRESH.write_volatile(RX_FLOW_THRESH);
+            RDMA_RING_CFG.write_volatile(RING_CFG_DEFAULT_QUEUE);
+            for (idx, buf) in rx.bufs.iter().enumerate() {
+                let addr = to_phys(buf.as_ptr().addr());
+                descriptor(RX_DESCS_BASE, idx, DESC_ADDRESS_LO).write_volatile(addr as u32);
+                descriptor(RX_DESCS_BASE, idx, DESC_ADDRESS_HI).write_volatile((addr >> 32) as u32);
+                descriptor(RX_DESCS_BASE, idx, DESC_LENGTH_STATUS).write_volatile(status);
+            }
+            TDMA_SCB_BURST_SIZE.write_volatile(DMA_MAX_BURST_LENGTH);
+            TDMA_START_ADDR.write_volatile(0);
+            TDMA_END_ADDR.write_volatile(end);
+            TDMA_READ_PTR.write_volatile(0);
+            TDMA_WRITE_PTR.write_volatile(0);
+            TDMA_CONS_INDEX.write_volatile(0);
+            TDMA_PROD_INDEX.write_volatile(0);
+            TDMA_RING_BUF_SIZE.write_volatile(ring_size);
+            TDMA_MBUF_DONE_THRESH.write_volatile(1);
+            TDMA_FLOW_PERIOD.write_volatile(0);
+            TDMA_RING_CFG.write_volatile(RING_CFG_DEFAULT_QUEUE);
+            TDMA_CTRL.write_volatile(DMA_DEFAULT_QUEUE_EN | DMA_EN);
+            RDMA_CTRL.write_volatile(DMA_DEFAULT_QUEUE_EN | DMA_EN);
+        }
+        fence(Ordering::Release);
+        if negotiate().is_none() {
+            debug!("Ethernet PHY didn't respond");
+        }
+        IRQ.register(GENET_IRQ, || GENET.interrupt());
+        Self { mac,
+               tx: Lock::new(tx),
+               rx: Lock::new(rx),
+               received: Notify::new() }
+    }
+
+    /// Waits for the link to come up, attaches the driver to the network
+    /// interface, and feeds every received frame to the interface.
+    pub async fn run(&'static self) -> !
+    {
+        let hcd = loop {
+            let bmsr = mdio_read(MII_BMSR).unwrap_or_default();
+            if bmsr & (BMSR_LSTATUS | BMSR_ANEGCOMPLETE) == BMSR_LSTATUS | BMSR_ANEGCOMPLETE {
+                let aux = mdio_read(MII_AUX_STATUS).unwrap_or_default();
+                break aux >> AUX_STATUS_HCD_SHIFT & AUX_STATUS_HCD_MASK;
+            }
+            TIMER.sleep(LINK_POLL_PERIOD).await;
+        };
+        // The PHY reports the highest common denominator of both ends, which
+        // the MAC only needs the speed of.
+        let (speed, mbps) = match hcd {
+            6 | 7 => (2, 1000),
+            3 ..= 5 => (1, 100),
+            _ => (0, 10),
+        };
+        unsafe {
+            let oob = EXT_RGMII_OOB_CTRL.read_volatile() & !OOB_DISABLE;
+            EXT_RGMII_OOB_CTRL.write_volatile(oob | OOB_RGMII_LINK | OOB_RGMII_MODE_EN | OOB_ID_MODE_DIS);
+            UMAC_CMD.write_volatile(speed << CMD_SPEED_SHIFT | CMD_TX_EN | CMD_RX_EN);
+        }
+        debug!("Ethernet link up at {mbps}Mbps");
+        NET.attach(self, Config::default());
+        unsafe { INTRL2_MASK_CLEAR.write_volatile(INT_RXDMA_MBDONE) };
+        loop {
+            self.drain();
+            timeout(RX_POLL_PERIOD, self.received.notified()).await;
+        }
+    }
+
+    /// Feeds every frame in the receive ring to the network interface and
+    /// hands their descriptors back to the controller.
+    fn drain(&self)
+    {
+        loop {
+            let frame = {
+                let mut rx = self.rx.lock();
+                let prod = unsafe { RDMA_PROD_INDEX.read_volatile() } as u16;
+                if prod == rx.index {
+                    return;
+                }
+                fence(Ordering::Acquire);
+                let slot = rx.index as usize % RING_LEN;
+                let status = unsafe { descriptor(RX_DESCS_BASE, slot, DESC_LENGTH_STATUS).read_volatile() };
+                let len = (status >> DESC_LENGTH_SHIFT & DESC_LENGTH_MASK) as usize;
+                // Frames spanning multiple buffers or with errors are dropped.
+                let whole = status & (DESC_SOP | DESC_EOP) == DESC_SOP | DESC_EOP;
+                let frame = (whole && status & DESC_RX_ERRORS == 0 && (RX_PAD .. BUF_LEN).contains(&len))
+                    .then(|| rx.bufs[slot][RX_PAD .. len].to_vec());
+                rx.index = rx.index.wrapping_add(1);
+                fence(Ordering::Release);
+                unsafe { RDMA_CONS_INDEX.write_volatile(rx.index as u32) };
+                frame
+            };
+            if let Some(frame) = frame {
+                NET.input(&frame);
+            }
+        }
+    }
+
+    /// Ethernet IRQ handler, which acknowledges received frame interrupts and
+    /// wakes the task waiting for them.
+    ///
+    /// Returns whether a frame had been received.
+    fn interrupt(&self) -> bool
+    {
+        let pending = unsafe { INTRL2_STAT.read_volatile() & !INTRL2_MASK_STATUS.read_volatile() };
+        if pending & INT_RXDMA_MBDONE == 0 {
+            return false;
+        }
+        unsafe { INTRL2_CLEAR.write_volatile(INT_RXDMA_MBDONE) };
+        self.received.notify_all();
+        true
+    }
+}
+
+impl Device for Genet
+{
+    fn mac(&self) -> MacAddress
+    {
+        self.mac
+    }
+
+    fn transmit(&self, frame: &[u8])
+    {
+        if frame.len() > BUF_LEN {
+            return;
+        }
+        let mut tx = self.tx.lock();
+        let cons = unsafe { TDMA_CONS_INDEX.read_volatile() } as u16;
+        if tx.index.wrapping_sub(cons) as usize >= RING_LEN {
+            return;
+        }
+        let slot = tx.index as usize % RING_LEN;
+        let len = frame.len().max(MIN_FRAME_LEN);
+        let buf = &mut tx.bufs[slot];
+        buf[.. frame.len()].copy_from_slice(frame);
+        buf[frame.len() .. len].fill(0);
+        let addr = to_phys(buf.as_ptr().addr());
+        let status = (len as u32) << DESC_LENGTH_SHIFT | DESC_TX_QTAG | DESC_TX_APPEND_CRC | DESC_SOP | DESC_EOP;
+        fence(Ordering::Release);
+        unsafe {
+            descriptor(TX_DESCS_BASE, slot, DESC_ADDRESS_LO).write_volatile(addr as u32);
+            descriptor(TX_DESCS_BASE, slot, DESC_ADDRESS_HI).write_volatile((addr >> 32) as u32);
+            descriptor(TX_DESCS_BASE, slot, DESC_LENGTH_STATUS).write_volatile(status);
+        }
+        tx.index = tx.index.wrapping_add(1);
+        unsafe { TDMA_PROD_INDEX.write_volatile(tx.index as u32) };
+    }
+}
+
+impl Ring
+{
+    /// Creates and initializes a new ring with zeroed buffers.
+    ///
+    /// Returns the newly created ring.
+    fn new() -> Self
+    {
+        let mut bufs = Vec::with_capacity_in(RING_LEN, UNCACHED);
+        bufs.resize(RING_LEN, [0; BUF_LEN]);
+        Self { bufs, index: 0 }
+    }
+}
+
+/// Computes the address of a descriptor register.
+///
+/// * `base`: Address of the first descriptor of the ring.
+/// * `idx`: Index of the descriptor.
+/// * `reg`: Offset of the register within the descriptor.
+///
+/// Returns the address of the register.
+fn descriptor(base: usize, idx: usize, reg: usize) -> *mut u32
+{
+    (base + idx * DESC_SIZE + reg) as _
+}
+
+/// Resets the PHY and starts auto-negotiating every supported mode.
+///
+/// Returns nothing on success, or `None` if the PHY didn't respond.
+fn negotiate() -> Option<()>
+{
+    mdio_write(MII_BMCR, BMCR_RESET)?;
+    wait(|| mdio_read(MII_BMCR).is_some_and(|bmcr| bmcr & BMCR_RESET == 0))?;
+    mdio_write(MII_ADVERTISE, ADVERTISE_ALL)?;
+    mdio_write(MII_CTRL1000, ADVERTISE_1000)?;
+    mdio_write(MII_BMCR, BMCR_ANENABLE | BMCR_ANRESTART)
+}
+
+/// Reads a PHY register.
+///
+/// * `reg`: Register to read.
+///
+/// Returns the value of the register, or `None` if the PHY didn't respond.
+fn mdio_read(reg: u32) -> Option<u32>
+{
+    unsafe {
+        MDIO_CMD.write_volatile(MDIO_RD | PHY_ADDR << MDIO_PMD_SHIFT | reg << MDIO_REG_SHIFT);
+        MDIO_CMD.write_volatile(MDIO_CMD.read_volatile() | MDIO_START_BUSY);
+    }
+    wait(|| unsafe { MDIO_CMD.read_volatile() } & MDIO_START_BUSY == 0)?;
+    let cmd = unsafe { MDIO_CMD.read_volatile() };
+    (cmd & MDIO_READ_FAIL == 0).then_some(cmd & 0xFFFF)
+}
+
+/// Writes a PHY register.
+///
+/// * `reg`: Register to write.
+/// * `val`: Value to write.
+///
+/// Returns nothing on success, or `None` if the MDIO bus stayed busy.
+fn mdio_write(reg: u32, val: u32) -> Option<()>
+{
+    unsafe {
+        MDIO_CMD.write_volatile(MDIO_WR | PHY_ADDR << MDIO_PMD_SHIFT | reg << MDIO_REG_SHIFT | val & 0xFFFF);
+        MDIO_CMD.write_volatile(MDIO_CMD.read_volatile() | MDIO_START_BUSY);
+    }
+    wait(|| unsafe { MDIO_CMD.read_volatile() } & MDIO_START_BUSY == 0)
+}
+
+/// Busy-waits for a condition to hold.
+///
+/// * `cond`: Condition to wait for.
+///
+/// Returns nothing if the condition held in time, or `None` otherwise.
+fn wait(mut cond: impl FnMut() -> bool) -> Option<()>
+{
+    let start = now_micros();
+    while !cond() {
+        if now_micros() - start > TIMEOUT {
+            return None;
+        }
+        spin_loop();
+    }
+    Some(())
+}
+
+/// Busy-waits for a time interval.
+///
+/// * `micros`: Time interval in microseconds.
+fn delay(micros: u64)
+{
+    let start = now_micros();
+    while now_micros() - start < micros {
+        spin_loop();
+    }
+}
@@ -0,0 +1,362 @@
+//! GPIO driver.
+//!
+//! Hands out the pins of the GPIO controller described in the BCM2711
+//! peripherals datasheet [1] as claimed objects, so that no two drivers can
+//! configure the same pin, and serializes the read-modify-write accesses to
+//! the registers that several pins share.  Claimed pins can have their
+//! function and pull resistors selected, be driven or sampled, and report edge
+//! or level events to any tasks awaiting on an [`Event`].
+//!
+//! [1]: https://datasheets.raspberrypi.com/bcm2711/bcm2711-peripherals.pdf
+
+use core::future::Future;
+use core::pin::Pin as FuturePin;
+use core::sync::atomic::{fence, AtomicU64, Ordering};
+use core::task::{Context, Poll};
+
+use crate::irq::IRQ;
+use crate::sync::{critical, Lazy, Lock, Notify};
+use crate::wait::WaitId;
+use crate::PERRY_RANGE;
+
+/// GPIO controller base address.
+const BASE: usize = PERRY_RANGE.start + 0x2200000;
+/// Function select registers offset.
+const GPFSEL: usize = 0x0;
+/// Output set registers offset.
+const GPSET: usize = 0x1C;
+/// Output clear registers offset.
+const GPCLR: usize = 0x28;
+/// Level registers offset.
+const GPLEV: usize = 0x34;
+/// Event detect status registers offset.
+const GPEDS: usize = 0x40;
+/// Rising edge detect enable registers offset.
+const GPREN: usize = 0x4C;
+/// Falling edge detect enable registers offset.
+const GPFEN: usize = 0x58;
+/// High level detect enable registers offset.
+const GPHEN: usize = 0x64;
+/// Low level detect enable registers offset.
+const GPLEN: usize = 0x70;
+/// Pull-up / pull-down registers offset.
+const GPIO_PUP_PDN_CNTRL: usize = 0xE4;
+/// Number of pins.
+const PIN_COUNT: usize = 58;
+/// IRQs of the three banks of pins.
+const BANK_IRQS: [u32; 3] = [145, 146, 147];
+
+/// Global GPIO driver instance.
+pub static GPIO: Lazy<Gpio> = Lazy::new(Gpio::new);
+
+/// GPIO driver.
+#[derive(Debug)]
+pub struct Gpio
+{
+    /// Bit mask of the claimed pins, whose lock also serializes accesses to
+    /// registers shared by several pins, including by the IRQ handler.
+    claims: Lock<u64>,
+    /// Event state of each pin.
+    pins: [State; PIN_COUNT],
+}
+
+/// Claimed pin, which is reset to an input without pulls and released when
+/// dropped.
+#[derive(Debug)]
+pub struct Pin
+{
+    /// Pin index.
+    idx: usize,
+}
+
+/// Future that completes on the next event detected on a pin.
+#[derive(Debug)]
+pub struct Event
+{
+    /// Pin index.
+    idx: usize,
+    /// Number of events detected on the pin when this future was created.
+    count: u64,
+    /// Registration with the pin's notifier, if parked.
+    waiter: Option<WaitId>,
+}
+
+/// Pin function.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Function
+{
+    /// General purpose input.
+    Input,
+    /// General purpose output.
+    Output,
+    /// Alternate function 0.
+    Alt0,
+    /// Alternate function 5.
+    Alt5,
+}
+
+/// Pull resistor configuration.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Pull
+{
+    /// No pull resistor.
+    None,
+    /// Pull-up resistor.
+    Up,
+}
+
+/// Condition that raises an event.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Trigger
+{
+    /// Transition from high to low.
+    Falling,
+    /// High level, which only raises a single event until listened for again.
+    High,
+}
+
+/// Pin state shared with the IRQ handler.
+#[derive(Debug)]
+struct State
+{
+    /// Number of events detected.
+    count: AtomicU64,
+    /// Tasks waiting for the next event.
+    changed: Notify,
+}
+
+impl Gpio
+{
+    /// Creates and initializes a new GPIO driver.
+    ///
+    /// Returns the newly created driver.
+    fn new() -> Self
+    {
+        for irq in BANK_IRQS {
+            IRQ.register(irq, || GPIO.interrupt());
+        }
+        Self { claims: Lock::new(0),
+               pins: [const { State::new() }; PIN_COUNT] }
+    }
+
+    /// Claims a pin.
+    ///
+    /// * `idx`: Index of the pin to claim.
+    ///
+    /// Returns the claimed pin, or `None` if it's already claimed.
+    ///
+    /// Panics if the pin doesn't exist.
+    #[track_caller]
+    pub fn claim(&self, idx: usize) -> Option<Pin>
+    {
+        assert!(idx < PIN_COUNT, "GPIO #{idx} doesn't exist");
+        // The lock is shared with the GPIO IRQ handler.
+        let _critical = critical();
+        let mut claims = self.claims.lock();
+        if *claims & 0x1 << idx != 0 {
+            return None;
+        }
+        *claims |= 0x1 << idx;
+        Some(Pin { idx })
+    }
+
+    /// GPIO IRQ handler.
+    ///
+    /// Returns whether any events were detected.
+    fn interrupt(&self) -> bool
+    {
+        let mut found = false;
+        for reg in 0 .. PIN_COUNT.div_ceil(32) {
+            fence(Ordering::Acquire);
+            let events = unsafe { register(GPEDS, reg).read_volatile() };
+            if events == 0 {
+                continue;
+            }
+            found = true;
+            // Level detection keeps raising events until the level changes.
+            let claims = self.claims.lock();
+            unsafe {
+                let val = register(GPHEN, reg).read_volatile();
+                register(GPHEN, reg).write_volatile(val & !events);
+                let val = register(GPLEN, reg).read_volatile();
+                register(GPLEN, reg).write_volatile(val & !events);
+                register(GPEDS, reg).write_volatile(events);
+            }
+            fence(Ordering::Release);
+            drop(claims);
+            for bit in (0 .. 32).filter(|bit| events & 0x1 << bit != 0) {
+                let state = &self.pins[reg * 32 + bit];
+                state.count.fetch_add(1, Ordering::Release);
+                state.changed.notify_all();
+            }
+        }
+        found
+    }
+}
+
+impl Pin
+{
+    /// Selects the function of this pin.
+    ///
+    /// * `func`: Function to select.
+    pub fn set_function(&mut self, func: Function)
+    {
+        let val = match func {
+            Function::Input => 0b000,
+            Function::Output => 0b001,
+            Function::Alt0 => 0b100,
+            Function::Alt5 => 0b010,
+        };
+        let shift = self.idx % 10 * 3;
+        self.modify(GPFSEL, self.idx / 10, 0x7 << shift, val << shift);
+    }
+
+    /// Configures the pull resistor of this pin.
+    ///
+    /// * `pull`: Pull resistor configuration.
+    pub fn set_pull(&mut self, pull: Pull)
+    {
+        let val = match pull {
+            Pull::None => 0b00,
+            Pull::Up => 0b01,
+        };
+        let shift = self.idx % 16 * 2;
+        self.modify(GPIO_PUP_PDN_CNTRL, self.idx / 16, 0x3 << shift, val << shift);
+    }
+
+    /// Drives this pin high or low while it's configured as an output.
+    ///
+    /// * `high`: Whether to drive the pin high.
+    pub fn set(&mut self, high: bool)
+    {
+        let offset = if high { GPSET } else { GPCLR };
+        unsafe { register(offset, self.idx / 32).write_volatile(0x1 << (self.idx % 32)) };
+        fence(Ordering::Release);
+    }
+
+    /// Returns whether this pin is high.
+    pub fn is_high(&self) -> bool
+    {
+        fence(Ordering::Acquire);
+        unsafe { register(GPLEV, self.idx / 32).read_volatile() & 0x1 << (self.idx % 32) != 0 }
+    }
+
+    /// Starts raising events on this pin, replacing any previous trigger.
+    ///
+    /// * `trigger`: Condition that raises an event.
+    pub fn listen(&mut self, trigger: Trigger)
+    {
+        self.ignore();
+        let offset = match trigger {
+            Trigger::Falling => GPFEN,
+            Trigger::High => GPHEN,
+        };
+        let bit = 0x1 << (self.idx % 32);
+        self.modify(offset, self.idx / 32, bit, bit);
+    }
+
+    /// Stops raising events on this pin.
+    pub fn ignore(&mut self)
+    {
+        let bit = 0x1 << (self.idx % 32);
+        for offset in [GPREN, GPFEN, GPHEN, GPLEN] {
+            self.modify(offset, self.idx / 32, bit, 0);
+        }
+        unsafe { register(GPEDS, self.idx / 32).write_volatile(bit) };
+        fence(Ordering::Release);
+    }
+
+    /// Creates a future that completes on the next event raised on this pin.
+    ///
+    /// Returns the newly created future.
+    pub fn event(&self) -> Event
+    {
+        let count = GPIO.pins[self.idx].count.load(Ordering::Acquire);
+        Event { idx: self.idx,
+                count,
+                waiter: None }
+    }
+
+    /// Changes some bits of a register shared by several pins.
+    ///
+    /// * `offset`: Offset of the first register of the kind.
+    /// * `reg`: Index of the register of the kind.
+    /// * `mask`: Bits to change.
+    /// * `val`: New value of the bits.
+    fn modify(&self, offset: usize, reg: usize, mask: u32, val: u32)
+    {
+        // The lock is shared with the GPIO IRQ handler.
+        let _critical = critical();
+        let _claims = GPIO.claims.lock();
+        fence(Ordering::Acquire);
+        unsafe {
+            let old = register(offset, reg).read_volatile();
+            register(offset, reg).write_volatile(old & !mask | val);
+        }
+        fence(Ordering::Release);
+    }
+}
+
+impl Drop for Pin
+{
+    fn drop(&mut self)
+    {
+        self.ignore();
+        self.set_function(Function::Input);
+        self.set_pull(Pull::None);
+        let _critical = critical();
+        *GPIO.claims.lock() &= !(0x1 << self.idx);
+    }
+}
+
+impl Future for Event
+{
+    type Output = ();
+
+    fn poll(mut self: FuturePin<&mut Self>, ctx: &mut Context) -> Poll<()>
+    {
+        let state = &GPIO.pins[self.idx];
+        // Register before checking so that an event in between isn't missed.
+        let waiter = state.changed.register(self.waiter, ctx.waker());
+        if state.count.load(Ordering::Acquire) != self.count {
+            state.changed.unregister(waiter);
+            self.waiter = None;
+            return Poll::Ready(());
+        }
+        self.waiter = Some(waiter);
+        Poll::Pending
+    }
+}
+
+impl Drop for Event
+{
+    fn drop(&mut self)
+    {
+        if let Some(waiter) = self.waiter {
+            GPIO.pins[self.idx].changed.unregister(waiter);
+        }
+    }
+}
+
+impl State
+{
+    /// Creates and initializes a new pin state.
+    ///
+    /// Returns the newly created state.
+    const fn new() -> Self
+    {
+        Self { count: AtomicU64::new(0),
+               changed: Notify::new() }
+    }
+}
+
+/// Computes the address of a register.
+///
+/// * `offset`: Offset of the first register of the kind.
+/// * `reg`: Index of the register of the kind.
+///
+/// Returns the computed address.
+fn register(offset: usize, reg: usize) -> *mut u32
+{
+    (BASE + offset + reg * 4) as _
+}
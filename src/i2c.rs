@@ -0,0 +1,481 @@
+//! I2C master driver.
+//!
+//! Drives the Broadcom Serial Controllers (BSC) described in the BCM2711
+//! peripherals datasheet [1] as I2C masters, handing them out as claimed
+//! [`Master`] objects that own their pins and run transfers asynchronously,
+//! refilling and draining the FIFOs whenever the controller raises an
+//! interrupt.  Device drivers are meant to be written against the [`I2c`]
+//! trait, which follows the shape of the embedded-hal [2] one.  Controllers 2
+//! and 7 are wired to the HDMI ports and belong to the firmware, so they can't
+//! be claimed.  The controllers can't reliably issue repeated start
+//! conditions, so every operation in a transaction ends with a stop condition.
+//!
+//! [1]: https://datasheets.raspberrypi.com/bcm2711/bcm2711-peripherals.pdf
+//! [2]: https://docs.rs/embedded-hal/1.0.0/embedded_hal/i2c/trait.I2c.html
+
+use core::future::Future;
+use core::pin::Pin as FuturePin;
+use core::sync::atomic::{fence, AtomicU64, Ordering};
+use core::task::{Context, Poll};
+
+use crate::gpio::{Function, Pin, Pull, GPIO};
+use crate::irq::IRQ;
+use crate::sync::{Lazy, Lock, Notify};
+use crate::wait::WaitId;
+use crate::PERRY_RANGE;
+
+/// Control register offset.
+const C: usize = 0x0;
+/// Status register offset.
+const S: usize = 0x4;
+/// Data length register offset.
+const DLEN: usize = 0x8;
+/// Slave address register offset.
+const A: usize = 0xC;
+/// Data FIFO register offset.
+const FIFO: usize = 0x10;
+/// Clock divider register offset.
+const DIV: usize = 0x14;
+/// Control read transfer flag.
+const C_READ: u32 = 0x1;
+/// Control clear FIFO flags.
+const C_CLEAR: u32 = 0x30;
+/// Control start transfer flag.
+const C_ST: u32 = 0x80;
+/// Control interrupt on done flag.
+const C_INTD: u32 = 0x100;
+/// Control interrupt on transmit flag.
+const C_INTT: u32 = 0x200;
+/// Control interrupt on receive flag.
+const C_INTR: u32 = 0x400;
+/// Control controller enable flag.
+const C_I2CEN: u32 = 0x8000;
+/// All the control interrupt flags.
+const C_INTS: u32 = C_INTD | C_INTT | C_INTR;
+/// Status transfer done flag.
+const S_DONE: u32 = 0x2;
+/// Status FIFO needs writing flag.
+const S_TXW: u32 = 0x4;
+/// Status FIFO needs reading flag.
+const S_RXR: u32 = 0x8;
+/// Status FIFO can accept data flag.
+const S_TXD: u32 = 0x10;
+/// Status FIFO contains data flag.
+const S_RXD: u32 = 0x20;
+/// Status acknowledge error flag.
+const S_ERR: u32 = 0x100;
+/// Status clock stretch timeout flag.
+const S_CLKT: u32 = 0x200;
+/// Maximum length of a single transfer.
+const DLEN_MAX: usize = 0xFFFF;
+/// Clock feeding the controllers in hertz.
+const CORE_CLOCK: u32 = 500000000;
+/// Number of controllers.
+const BSC_COUNT: usize = 7;
+/// Controllers available for claiming.
+const CONTROLLERS: [Option<Controller>; BSC_COUNT] = [Some(Controller::new(0x2205000, 0, Function::Alt0)),
+                                                      Some(Controller::new(0x2804000, 2, Function::Alt0)),
+                                                      None,
+                                                      Some(Controller::new(0x2205600, 4, Function::Alt5)),
+                                                      Some(Controller::new(0x2205800, 6, Function::Alt5)),
+                                                      Some(Controller::new(0x2205A00, 10, Function::Alt5)),
+                                                      Some(Controller::new(0x2205C00, 22, Function::Alt5))];
+/// IRQ shared by all the controllers.
+const BSC_IRQ: u32 = 149;
+
+/// Global BSC driver instance.
+pub static BSC: Lazy<Bsc> = Lazy::new(Bsc::new);
+
+/// Interface to an I2C bus, modeled after the embedded-hal one.
+pub trait I2c
+{
+    /// Runs a sequence of operations addressed to a single device.
+    ///
+    /// * `addr`: 7 bit address of the device.
+    /// * `ops`: Operations to run.
+    ///
+    /// Returns a future that completes with the outcome of the operations.
+    fn transaction<'a>(&'a mut self, addr: u8, ops: &'a mut [Operation<'_>])
+                       -> impl Future<Output = Result<(), Error>> + Send + 'a;
+
+    /// Reads bytes from a device.
+    ///
+    /// * `addr`: 7 bit address of the device.
+    /// * `buf`: Buffer to fill.
+    ///
+    /// Returns a future that completes with the outcome of the read.
+    fn read<'a>(&'a mut self, addr: u8, buf: &'a mut [u8]) -> impl Future<Output = Result<(), Error>> + Send + 'a
+        where Self: Send
+    {
+        async move { self.transaction(addr, &mut [Operation::Read(buf)]).await }
+    }
+
+    /// Writes bytes to a device.
+    ///
+    /// * `addr`: 7 bit address of the device.
+    /// * `bytes`: Bytes to write.
+    ///
+    /// Returns a future that completes with the outcome of the write.
+    fn write<'a>(&'a mut self, addr: u8, bytes: &'a [u8]) -> impl Future<Output = Result<(), Error>> + Send + 'a
+        where Self: Send
+    {
+        async move { self.transaction(addr, &mut [Operation::Write(bytes)]).await }
+    }
+
+    /// Writes bytes to a device and then reads bytes back, usually to read a
+    /// register.
+    ///
+    /// * `addr`: 7 bit address of the device.
+    /// * `bytes`: Bytes to write.
+    /// * `buf`: Buffer to fill.
+    ///
+    /// Returns a future that completes with the outcome of the operations.
+    fn write_read<'a>(&'a mut self, addr: u8, bytes: &'a [u8], buf: &'a mut [u8])
+                      -> impl Future<Output = Result<(), Error>> + Send + 'a
+        where Self: Send
+    {
+        async move {
+            self.transaction(addr, &mut [Operation::Write(bytes), Operation::Read(buf)])
+                .await
+        }
+    }
+}
+
+/// BSC driver.
+#[derive(Debug)]
+pub struct Bsc
+{
+    /// Bit mask of the claimed controllers.
+    claims: Lock<u8>,
+    /// State of each controller.
+    states: [State; BSC_COUNT],
+}
+
+/// Claimed controller acting as an I2C master, which is disabled and released
+/// when dropped.
+#[derive(Debug)]
+pub struct Master
+{
+    /// Controller index.
+    idx: usize,
+    /// Data and clock pins.
+    _pins: [Pin; 2],
+}
+
+/// I2C operation.
+#[derive(Debug)]
+pub enum Operation<'a>
+{
+    /// Reads bytes into a buffer.
+    Read(&'a mut [u8]),
+    /// Writes bytes from a buffer.
+    Write(&'a [u8]),
+}
+
+/// I2C error.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error
+{
+    /// The device didn't acknowledge its address or some data.
+    NoAcknowledge,
+    /// The device stretched the clock for too long.
+    Timeout,
+    /// The operation is too long for a single transfer.
+    TooLong,
+}
+
+/// Future that completes on the next interrupt raised by a controller.
+#[derive(Debug)]
+struct Wake
+{
+    /// Controller index.
+    idx: usize,
+    /// Number of interrupts raised by the controller when this future was
+    /// created.
+    count: u64,
+    /// Registration with the controller's notifier, if parked.
+    waiter: Option<WaitId>,
+}
+
+/// Controller wiring.
+#[derive(Clone, Copy, Debug)]
+struct Controller
+{
+    /// Register base address.
+    base: usize,
+    /// Data pin, with the clock pin following it.
+    sda: usize,
+    /// Function that connects the pins to the controller.
+    func: Function,
+}
+
+/// Controller state shared with the IRQ handler.
+#[derive(Debug)]
+struct State
+{
+    /// Number of interrupts raised.
+    count: AtomicU64,
+    /// Task waiting for the next interrupt.
+    wake: Notify,
+}
+
+impl Bsc
+{
+    /// Creates and initializes a new BSC driver.
+    ///
+    /// Returns the newly created driver.
+    fn new() -> Self
+    {
+        IRQ.register(BSC_IRQ, || BSC.interrupt());
+        Self { claims: Lock::new(0),
+               states: [const { State::new() }; BSC_COUNT] }
+    }
+
+    /// Claims a controller along with its pins.
+    ///
+    /// * `idx`: Index of the controller to claim.
+    /// * `freq`: Clock frequency of the bus in hertz.
+    ///
+    /// Returns the claimed controller, or `None` if either the controller or
+    /// its pins are already claimed.
+    ///
+    /// Panics if the controller doesn't exist, belongs to the firmware, or the
+    /// frequency is zero.
+    #[track_caller]
+    pub fn claim(&self, idx: usize, freq: u32) -> Option<Master>
+    {
+        assert!(idx < BSC_COUNT, "BSC #{idx} doesn't exist");
+        let ctrl = CONTROLLERS[idx].unwrap_or_else(|| panic!("BSC #{idx} belongs to the firmware"));
+        assert!(freq > 0, "Invalid I2C clock frequency: {freq}Hz");
+        {
+            let mut claims = self.claims.lock();
+            if *claims & 0x1 << idx != 0 {
+                return None;
+            }
+            *claims |= 0x1 << idx;
+        }
+        let pins = [GPIO.claim(ctrl.sda), GPIO.claim(ctrl.sda + 1)];
+        let [Some(sda), Some(scl)] = pins else {
+            *self.claims.lock() &= !(0x1 << idx);
+            return None;
+        };
+        let mut pins = [sda, scl];
+        for pin in &mut pins {
+            pin.set_function(ctrl.func);
+            pin.set_pull(Pull::Up);
+        }
+        // The divider is rounded down to an even number by the hardware.
+        let div = (CORE_CLOCK / freq).clamp(2, 0xFFFE);
+        unsafe {
+            register(idx, C).write_volatile(C_CLEAR);
+            register(idx, S).write_volatile(S_DONE | S_ERR | S_CLKT);
+            register(idx, DIV).write_volatile(div);
+            register(idx, C).write_volatile(C_I2CEN);
+        }
+        fence(Ordering::Release);
+        Some(Master { idx, _pins: pins })
+    }
+
+    /// BSC IRQ handler, which masks the interrupts of the controllers that
+    /// raised them and wakes the tasks waiting on them.
+    ///
+    /// Returns whether any controller had raised an interrupt.
+    fn interrupt(&self) -> bool
+    {
+        let mut found = false;
+        for idx in (0 .. BSC_COUNT).filter(|idx| CONTROLLERS[*idx].is_some()) {
+            fence(Ordering::Acquire);
+            let ctrl = unsafe { register(idx, C).read_volatile() };
+            let status = unsafe { register(idx, S).read_volatile() };
+            let raised = ctrl & C_INTD != 0 && status & (S_DONE | S_ERR | S_CLKT) != 0
+                         || ctrl & C_INTT != 0 && status & S_TXW != 0
+                         || ctrl & C_INTR != 0 && status & S_RXR != 0;
+            if !raised {
+                continue;
+            }
+            found = true;
+            // The task unmasks the interrupts again once it's done with the FIFO.
+            unsafe { register(idx, C).write_volatile(ctrl & !C_INTS) };
+            fence(Ordering::Release);
+            let state = &self.states[idx];
+            state.count.fetch_add(1, Ordering::Release);
+            state.wake.notify_all();
+        }
+        found
+    }
+}
+
+impl Master
+{
+    /// Runs a single operation.
+    ///
+    /// * `addr`: 7 bit address of the device.
+    /// * `op`: Operation to run.
+    ///
+    /// Returns the outcome of the operation.
+    async fn run(&mut self, addr: u8, op: &mut Operation<'_>) -> Result<(), Error>
+    {
+        let (len, read) = match op {
+            Operation::Read(buf) => (buf.len(), C_READ),
+            Operation::Write(bytes) => (bytes.len(), 0),
+        };
+        if len > DLEN_MAX {
+            return Err(Error::TooLong);
+        }
+        unsafe {
+            register(self.idx, A).write_volatile(addr as u32 & 0x7F);
+            register(self.idx, DLEN).write_volatile(len as u32);
+            register(self.idx, S).write_volatile(S_DONE | S_ERR | S_CLKT);
+            register(self.idx, C).write_volatile(C_I2CEN | C_CLEAR);
+            register(self.idx, C).write_volatile(C_I2CEN | C_ST | read);
+        }
+        fence(Ordering::Release);
+        let mut pos = 0;
+        loop {
+            let wake = self.wake();
+            fence(Ordering::Acquire);
+            let mut status = unsafe { register(self.idx, S).read_volatile() };
+            match op {
+                Operation::Read(buf) => {
+                    while pos < buf.len() && status & S_RXD != 0 {
+                        buf[pos] = unsafe { register(self.idx, FIFO).read_volatile() } as u8;
+                        pos += 1;
+                        status = unsafe { register(self.idx, S).read_volatile() };
+                    }
+                }
+                Operation::Write(bytes) => {
+                    while pos < bytes.len() && status & S_TXD != 0 {
+                        unsafe { register(self.idx, FIFO).write_volatile(bytes[pos] as u32) };
+                        pos += 1;
+                        status = unsafe { register(self.idx, S).read_volatile() };
+                    }
+                }
+            }
+            if status & (S_ERR | S_CLKT) != 0 {
+                let err = if status & S_CLKT != 0 {
+                    Error::Timeout
+                } else {
+                    Error::NoAcknowledge
+                };
+                unsafe {
+                    register(self.idx, S).write_volatile(S_DONE | S_ERR | S_CLKT);
+                    register(self.idx, C).write_volatile(C_I2CEN | C_CLEAR);
+                }
+                fence(Ordering::Release);
+                return Err(err);
+            }
+            if status & S_DONE != 0 && (read == 0 || pos == len) {
+                unsafe { register(self.idx, S).write_volatile(S_DONE) };
+                fence(Ordering::Release);
+                return Ok(());
+            }
+            unsafe { register(self.idx, C).write_volatile(C_I2CEN | C_INTS | read) };
+            fence(Ordering::Release);
+            wake.await;
+        }
+    }
+
+    /// Creates a future that completes on the next interrupt raised by this
+    /// controller.
+    ///
+    /// Returns the newly created future.
+    fn wake(&self) -> Wake
+    {
+        let count = BSC.states[self.idx].count.load(Ordering::Acquire);
+        Wake { idx: self.idx,
+               count,
+               waiter: None }
+    }
+}
+
+impl I2c for Master
+{
+    async fn transaction<'a>(&'a mut self, addr: u8, ops: &'a mut [Operation<'_>]) -> Result<(), Error>
+    {
+        for op in ops {
+            self.run(addr, op).await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Master
+{
+    fn drop(&mut self)
+    {
+        unsafe { register(self.idx, C).write_volatile(C_CLEAR) };
+        fence(Ordering::Release);
+        *BSC.claims.lock() &= !(0x1 << self.idx);
+    }
+}
+
+impl Future for Wake
+{
+    type Output = ();
+
+    fn poll(mut self: FuturePin<&mut Self>, ctx: &mut Context) -> Poll<()>
+    {
+        let state = &BSC.states[self.idx];
+        // Register before checking so that an interrupt in between isn't missed.
+        let waiter = state.wake.register(self.waiter, ctx.waker());
+        if state.count.load(Ordering::Acquire) != self.count {
+            state.wake.unregister(waiter);
+            self.waiter = None;
+            return Poll::Ready(());
+        }
+        self.waiter = Some(waiter);
+        Poll::Pending
+    }
+}
+
+impl Drop for Wake
+{
+    fn drop(&mut self)
+    {
+        if let Some(waiter) = self.waiter {
+            BSC.states[self.idx].wake.unregister(waiter);
+        }
+    }
+}
+
+impl Controller
+{
+    /// Creates and initializes a new controller wiring.
+    ///
+    /// * `offset`: Offset of the registers from the start of the peripherals
+    ///   range.
+    /// * `sda`: Data pin, with the clock pin following it.
+    /// * `func`: Function that connects the pins to the controller.
+    ///
+    /// Returns the newly created wiring.
+    const fn new(offset: usize, sda: usize, func: Function) -> Self
+    {
+        Self { base: PERRY_RANGE.start + offset,
+               sda,
+               func }
+    }
+}
+
+impl State
+{
+    /// Creates and initializes a new controller state.
+    ///
+    /// Returns the newly created state.
+    const fn new() -> Self
+    {
+        Self { count: AtomicU64::new(0),
+               wake: Notify::new() }
+    }
+}
+
+/// Computes the address of a controller register.
+///
+/// * `idx`: Controller index.
+/// * `offset`: Register offset.
+///
+/// Returns the computed address.
+fn register(idx: usize, offset: usize) -> *mut u32
+{
+    let ctrl = CONTROLLERS[idx].expect("Attempted to access the registers of a firmware controller");
+    (ctrl.base + offset) as _
+}
@@ -0,0 +1,106 @@
+//! Cross-core function calls.
+//!
+//! Runs closures on specific logical CPUs by queuing them and raising a
+//! Software Generated Interrupt on the target, which is required for
+//! maintaining per-core state such as caches, performance counters, and
+//! translation tables.  Callers spin until the call completes while serving
+//! calls targeting their own logical CPU, so two logical CPUs calling each
+//! other at the same time cannot deadlock.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::cpu::{id as cpu_id, COUNT as CPU_COUNT};
+use crate::irq::IRQ;
+use crate::sync::{Lazy, Lock};
+
+/// Cross-core call IRQ.
+const IPI_IRQ: u32 = 2;
+
+/// Global cross-core call facility.
+pub static IPI: Lazy<Ipi> = Lazy::new(Ipi::new);
+
+/// Cross-core call facility.
+pub struct Ipi
+{
+    /// Pending calls indexed by target logical CPU.
+    queues: [Lock<VecDeque<Arc<Call>>>; CPU_COUNT],
+}
+
+/// Queued call.
+struct Call
+{
+    /// Closure to run, taken when the call is served.
+    func: Lock<Option<Box<dyn FnOnce() + Send>>>,
+    /// Whether the call has completed.
+    is_done: AtomicBool,
+}
+
+impl Ipi
+{
+    /// Creates and initializes a new cross-core call facility.
+    ///
+    /// Returns the newly created facility.
+    fn new() -> Self
+    {
+        IRQ.register(IPI_IRQ, || IPI.serve());
+        Self { queues: [const { Lock::new(VecDeque::new()) }; CPU_COUNT] }
+    }
+
+    /// Runs a closure on the specified logical CPU and waits for it to
+    /// complete.  The closure runs directly if the target is the current
+    /// logical CPU.
+    ///
+    /// * `cpu`: Logical CPU to run the closure on.
+    /// * `func`: Closure to run.
+    ///
+    /// Returns the value returned by the closure.
+    ///
+    /// Panics if the logical CPU is out of range.
+    #[track_caller]
+    pub fn call<T: Send + 'static>(&self, cpu: usize, func: impl FnOnce() -> T + Send + 'static) -> T
+    {
+        assert!(cpu < CPU_COUNT, "Logical CPU #{cpu} is out of range");
+        if cpu == cpu_id() {
+            return func();
+        }
+        let result = Arc::new(Lock::new(None));
+        let output = result.clone();
+        let call = Call { func: Lock::new(Some(Box::new(move || *output.lock() = Some(func())))),
+                          is_done: AtomicBool::new(false) };
+        let call = Arc::new(call);
+        self.queues[cpu].lock().push_back(call.clone());
+        IRQ.notify_cpu(IPI_IRQ, cpu);
+        while !call.is_done.load(Ordering::Acquire) {
+            self.serve();
+            spin_loop();
+        }
+        let val = result.lock().take();
+        val.expect("Cross-core call completed without a result")
+    }
+
+    /// Runs all the calls queued for the current logical CPU.
+    ///
+    /// Returns whether any calls were run.
+    fn serve(&self) -> bool
+    {
+        let queue = &self.queues[cpu_id()];
+        let mut served = false;
+        loop {
+            // Release the queue before running the call as it may queue more calls.
+            let Some(call) = queue.lock().pop_front() else { break };
+            let func = call.func.lock().take();
+            if let Some(func) = func {
+                func();
+            }
+            call.is_done.store(true, Ordering::Release);
+            served = true;
+        }
+        served
+    }
+}
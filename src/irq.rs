@@ -9,18 +9,31 @@
 
 extern crate alloc;
 
-use alloc::collections::BTreeMap;
-use core::ptr::write_volatile;
-use core::sync::atomic::{fence, Ordering};
+use alloc::boxed::Box;
+use core::fmt::Write;
+use core::ptr::{null_mut, read_volatile, write_volatile};
+use core::sync::atomic::{fence, AtomicPtr, AtomicU64, Ordering};
 
-use crate::cpu::sleep;
-use crate::sync::{Lazy, RwLock};
+use crate::clock::now_micros;
+use crate::cpu::{sleep, COUNT as CPU_COUNT};
+use crate::sync::Lazy;
+use crate::uart::UART;
 use crate::PERRY_RANGE;
 
 /// Number of SPIs on the BCM2711.
 const SPI_COUNT: usize = 192;
 /// Total number of IRQs on the BCM2711.
 const IRQ_COUNT: usize = SPI_COUNT + 32;
+/// Maximum number of handlers sharing a single IRQ.
+const SHARED_MAX: usize = 4;
+/// Priority level of IRQs registered without an explicit configuration.
+const DEFAULT_PRIORITY: u8 = 15;
+/// Number of priority levels implemented by the GIC 400, the lowest of which
+/// is masked by the minimum priority register.
+const PRIORITY_LEVELS: u8 = 32;
+/// Shift of the priority levels into the implemented most significant bits of
+/// the priority fields.
+const PRIORITY_SHIFT: u32 = 3;
 /// Base address of theGIC 400.
 const GIC_BASE: usize = 0x3840000 + PERRY_RANGE.start;
 /// IRQ set enable registers.
@@ -42,14 +55,123 @@ const GICC_IAR: *mut u32 = (GIC_BASE + 0x200C) as _;
 /// IRQ dismissal register.
 const GICC_EOIR: *mut u32 = (GIC_BASE + 0x2010) as _;
 
+/// IRQ handler, which returns whether it found work to do for the IRQ.
+type Handler = Box<dyn Fn() -> bool + Send + Sync>;
+
 /// Global interrupt controller driver.
 pub static IRQ: Lazy<Irq> = Lazy::new(Irq::new);
 
+/// IRQ line configuration.
+#[derive(Clone, Copy, Debug)]
+pub struct Config
+{
+    /// Priority level between 0 and 30, with lower values corresponding to
+    /// higher priorities.  The GIC 400 implements the 5 most significant bits
+    /// of each priority field, and this level is shifted into them.
+    pub priority: u8,
+    /// Trigger mode, ignored for SGIs which are always edge triggered.
+    pub trigger: Trigger,
+    /// Bit mask of the logical CPUs to deliver the IRQ to, ignored for SGIs and
+    /// PPIs.
+    pub targets: u8,
+}
+
+/// IRQ trigger mode.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Trigger
+{
+    /// Pending for as long as the signal is asserted.
+    Level,
+    /// Pending when the signal is asserted.
+    Edge,
+}
+
 /// IRQ driver.
 pub struct Irq
 {
-    /// Registered handlers.
-    handlers: RwLock<BTreeMap<u32, fn()>>,
+    /// Registered handlers indexed by IRQ in registration order, with null
+    /// meaning no handler.  Handlers are never unregistered so they are leaked.
+    handlers: [[AtomicPtr<Handler>; SHARED_MAX]; IRQ_COUNT],
+    /// Dispatch statistics indexed by IRQ.
+    stats: [Stats; IRQ_COUNT],
+}
+
+/// IRQ dispatch statistics.
+#[derive(Debug)]
+struct Stats
+{
+    /// Number of times the IRQ was dispatched.
+    count: AtomicU64,
+    /// Total time in microseconds between acknowledging the IRQ and entering
+    /// its first handler.
+    latency: AtomicU64,
+    /// Maximum time in microseconds between acknowledging the IRQ and entering
+    /// its first handler.
+    max_latency: AtomicU64,
+    /// Total time in microseconds spent in the handlers.
+    busy: AtomicU64,
+    /// Maximum time in microseconds spent in the handlers in a single dispatch.
+    max_busy: AtomicU64,
+    /// Number of dispatches in which no handler found work to do.
+    spurious: AtomicU64,
+}
+
+impl Config
+{
+    /// Creates and initializes a new level triggered configuration that targets
+    /// all logical CPUs.
+    ///
+    /// * `priority`: Priority level.
+    ///
+    /// Returns the newly created configuration.
+    pub const fn with_priority(priority: u8) -> Self
+    {
+        Self { priority,
+               trigger: Trigger::Level,
+               targets: 0xFF }
+    }
+}
+
+impl Default for Config
+{
+    fn default() -> Self
+    {
+        Self::with_priority(DEFAULT_PRIORITY)
+    }
+}
+
+impl Stats
+{
+    /// Creates and initializes new empty statistics.
+    ///
+    /// Returns the newly created statistics.
+    const fn new() -> Self
+    {
+        Self { count: AtomicU64::new(0),
+               latency: AtomicU64::new(0),
+               max_latency: AtomicU64::new(0),
+               busy: AtomicU64::new(0),
+               max_busy: AtomicU64::new(0),
+               spurious: AtomicU64::new(0) }
+    }
+
+    /// Records a dispatch.
+    ///
+    /// * `latency`: Time in microseconds between acknowledging the IRQ and
+    ///   entering its first handler.
+    /// * `busy`: Time in microseconds spent in the handlers.
+    /// * `handled`: Whether any handler found work to do.
+    fn record(&self, latency: u64, busy: u64, handled: bool)
+    {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.latency.fetch_add(latency, Ordering::Relaxed);
+        self.max_latency.fetch_max(latency, Ordering::Relaxed);
+        self.busy.fetch_add(busy, Ordering::Relaxed);
+        self.max_busy.fetch_max(busy, Ordering::Relaxed);
+        if !handled {
+            self.spurious.fetch_add(1, Ordering::Relaxed);
+        }
+    }
 }
 
 impl Irq
@@ -69,7 +191,7 @@ impl Irq
             // Raise the priority of every IRQ as matching the lowest priority level masks
             // them.
             (*GICD_IPRIORITYR).iter_mut()
-                              .for_each(|element| write_volatile(element, 0x7F));
+                              .for_each(|element| write_volatile(element, DEFAULT_PRIORITY << PRIORITY_SHIFT));
             // Make all IRQs level triggered.
             (*GICD_ICFGR).iter_mut()
                          .for_each(|element| write_volatile(element, 0x55555555));
@@ -78,28 +200,105 @@ impl Irq
                              .skip(32)
                              .for_each(|element| write_volatile(element, 0xFF));
         }
-        Self { handlers: RwLock::new(BTreeMap::new()) }
+        Self { handlers: [const { [const { AtomicPtr::new(null_mut()) }; SHARED_MAX] }; IRQ_COUNT],
+               stats: [const { Stats::new() }; IRQ_COUNT] }
     }
 
-    /// Registers a handler to be called when the specified IRQ is triggered.
+    /// Registers a handler to be called when the specified IRQ is triggered
+    /// using the default configuration.  Multiple handlers can share the same
+    /// IRQ, in which case all of them are called in registration order.
     ///
     /// * `irq`: IRQ to wait for.
-    /// * `handler`: Handler function to register.
+    /// * `handler`: Handler function or closure to register, which returns
+    ///   whether it found work to do for the IRQ.
     ///
-    /// Panics if a handler is already registered for the specified IRQ.
+    /// Panics if the IRQ is out of range or already has the maximum number of
+    /// handlers.
     #[track_caller]
-    pub fn register(&self, irq: u32, handler: fn())
+    pub fn register(&self, irq: u32, handler: impl Fn() -> bool + Send + Sync + 'static)
+    {
+        self.register_with(irq, Config::default(), handler);
+    }
+
+    /// Registers a handler to be called when the specified IRQ is triggered
+    /// and configures the IRQ line.  Since the configuration applies to the
+    /// whole line, it replaces that of any handlers already sharing it.
+    ///
+    /// When several IRQs are pending, the one with the highest priority is
+    /// always dispatched first.  IRQs are dispatched synchronously by the loop
+    /// on each logical CPU, so a higher priority only moves an IRQ ahead of
+    /// the others pending on the same logical CPU and never preempts a handler
+    /// that is already running.
+    ///
+    /// * `irq`: IRQ to wait for.
+    /// * `config`: Line configuration.
+    /// * `handler`: Handler function or closure to register, which returns
+    ///   whether it found work to do for the IRQ.
+    ///
+    /// Panics if the IRQ is out of range, already has the maximum number of
+    /// handlers, or the priority level is out of range.
+    #[track_caller]
+    pub fn register_with(&self, irq: u32, config: Config, handler: impl Fn() -> bool + Send + Sync + 'static)
     {
         assert!((irq as usize) < IRQ_COUNT, "IRQ #{irq} is out of range");
-        let mut handlers = self.handlers.wlock();
-        assert!(handlers.insert(irq, handler).is_none(),
-                "Attempted to add a second handler for IRQ {irq}");
+        assert!(config.priority < PRIORITY_LEVELS - 1,
+                "Priority level {} of IRQ #{irq} is out of range",
+                config.priority);
+        let handler = Box::into_raw(Box::new(Box::new(handler) as Handler));
+        let slots = &self.handlers[irq as usize];
+        let registered = slots.iter().any(|slot| {
+                                         slot.compare_exchange(null_mut(), handler, Ordering::SeqCst, Ordering::Relaxed)
+                                             .is_ok()
+                                     });
+        if !registered {
+            drop(unsafe { Box::from_raw(handler) });
+            panic!("Attempted to add more than {SHARED_MAX} handlers for IRQ {irq}");
+        }
+        self.configure(irq, config);
         // Figure out which register and bit to enable for the given IRQ.
         let val = 0x1 << (irq & 0x1F);
         let idx = irq as usize >> 5;
         unsafe { write_volatile((*GICD_ISENABLER).get_mut(idx).unwrap(), val) };
     }
 
+    /// Applies a configuration to an IRQ line.
+    ///
+    /// * `irq`: IRQ to configure.
+    /// * `config`: Configuration to apply.
+    fn configure(&self, irq: u32, config: Config)
+    {
+        let irq = irq as usize;
+        unsafe {
+            write_volatile((*GICD_IPRIORITYR).get_mut(irq).unwrap(),
+                           config.priority << PRIORITY_SHIFT);
+            if irq >= 32 {
+                write_volatile((*GICD_ITARGETSR).get_mut(irq).unwrap(), config.targets);
+            }
+            if irq >= 16 {
+                // Two bits per IRQ, with the most significant one selecting edge triggering.
+                let shift = (irq & 0xF) << 1;
+                let reg = (*GICD_ICFGR).get_mut(irq >> 4).unwrap();
+                let val = read_volatile(reg) & !(0x3 << shift);
+                let mode = match config.trigger {
+                    Trigger::Level => 0x1,
+                    Trigger::Edge => 0x3,
+                };
+                write_volatile(reg, val | mode << shift);
+            }
+        }
+    }
+
+    /// Disables every IRQ line, preventing any further IRQs from being
+    /// dispatched to any logical CPU.
+    pub fn disable_all(&self)
+    {
+        unsafe {
+            (*GICD_ICENABLER).iter_mut()
+                             .for_each(|element| write_volatile(element, 0xFFFFFFFF));
+        }
+        fence(Ordering::Release);
+    }
+
     /// Raises the specified Software Generated Interrupt on all CPUs.
     ///
     /// * `irq`: IRQ to raise.
@@ -113,6 +312,23 @@ impl Irq
         unsafe { GICD_SGIR.write_volatile(val) };
     }
 
+    /// Raises a Software Generated Interrupt on a specific CPU.
+    ///
+    /// * `irq`: IRQ to raise.
+    /// * `cpu`: Logical CPU to raise the IRQ on.
+    ///
+    /// Panics if an attempt is made to raise an IRQ of any other kind or the
+    /// logical CPU is out of range.
+    #[track_caller]
+    pub fn notify_cpu(&self, irq: u32, cpu: usize)
+    {
+        assert!(irq < 16,
+                "Attempted to trigger a Software Generated Interrupt outside of the valid range");
+        assert!(cpu < CPU_COUNT, "Logical CPU #{cpu} is out of range");
+        let val = 0x8000 | 0x1 << (16 + cpu) | irq; // Target the specified CPU.
+        unsafe { GICD_SGIR.write_volatile(val) };
+    }
+
     /// Raises a Software Generated Interrupt on all CPUs except the one that is
     /// calling this function.
     ///
@@ -141,22 +357,59 @@ impl Irq
         unsafe { GICD_SGIR.write_volatile(val) };
     }
 
+    /// Sends a table with the dispatch statistics of every IRQ that has been
+    /// triggered through the UART.
+    pub fn dump(&self)
+    {
+        let mut uart = UART.lock();
+        writeln!(uart,
+                 "{:>4} {:>10} {:>10} {:>12} {:>12} {:>12} {:>12}",
+                 "IRQ", "COUNT", "SPURIOUS", "LAT AVG (us)", "LAT MAX (us)", "RUN AVG (us)", "RUN MAX (us)").unwrap();
+        for (irq, stats) in self.stats.iter().enumerate() {
+            let count = stats.count.load(Ordering::Relaxed);
+            if count == 0 {
+                continue;
+            }
+            writeln!(uart,
+                     "{:>4} {:>10} {:>10} {:>12} {:>12} {:>12} {:>12}",
+                     irq,
+                     count,
+                     stats.spurious.load(Ordering::Relaxed),
+                     stats.latency.load(Ordering::Relaxed) / count,
+                     stats.max_latency.load(Ordering::Relaxed),
+                     stats.busy.load(Ordering::Relaxed) / count,
+                     stats.max_busy.load(Ordering::Relaxed)).unwrap();
+        }
+    }
+
     /// Checks for and processes pending IRQs in an infinite loop.
     pub fn dispatch(&self) -> !
     {
         loop {
             let val = unsafe { GICC_IAR.read_volatile() };
+            let ack = now_micros();
             let irq = val & 0x3FF; // Strip sender info from SGIs.
             if irq as usize >= IRQ_COUNT {
                 sleep();
                 continue;
             }
             fence(Ordering::SeqCst);
-            let handler = *self.handlers
-                               .rlock()
-                               .get(&irq)
-                               .expect("Received an IRQ without a handler");
-            handler();
+            let mut called = false;
+            let mut handled = false;
+            let mut start = None;
+            for slot in &self.handlers[irq as usize] {
+                // Handlers are leaked on registration so they live forever.
+                let Some(handler) = (unsafe { slot.load(Ordering::Acquire).as_ref() }) else {
+                    break;
+                };
+                called = true;
+                start.get_or_insert_with(now_micros);
+                handled |= handler();
+            }
+            assert!(called, "Received IRQ #{irq} without a handler");
+            if let Some(start) = start {
+                self.stats[irq as usize].record(start - ack, now_micros() - start, handled);
+            }
             fence(Ordering::SeqCst);
             unsafe { GICC_EOIR.write_volatile(val as _) };
         }
@@ -0,0 +1,187 @@
+//! Input latency log.
+//!
+//! Records the timestamps of input events as they progress from the IRQ that
+//! captured them through gesture recognition and simulation up to the frame in
+//! which they first become visible, and reports latency percentiles for each
+//! of these stages.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+
+/// Percentiles included in reports.
+const PERCENTILES: [u32; 3] = [50, 90, 99];
+
+/// Bounded log of input event traces.
+#[derive(Debug)]
+pub struct LatencyLog
+{
+    /// Recorded traces, overwritten in a circular fashion once full.
+    traces: Vec<Trace>,
+    /// Maximum number of traces kept.
+    capacity: usize,
+    /// Index of the next trace to overwrite.
+    next: usize,
+}
+
+/// Timestamps in microseconds of a single input event at each stage.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Trace
+{
+    /// Time at which the IRQ captured the event.
+    pub input: u64,
+    /// Time at which the event was recognized as a gesture.
+    pub recognized: u64,
+    /// Time at which the gesture was applied to the simulation.
+    pub simulated: u64,
+    /// Time at which the first frame affected by the event was displayed.
+    pub displayed: u64,
+}
+
+/// Processing stage of an input event.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stage
+{
+    /// Gesture recognition.
+    Recognition,
+    /// Simulation update.
+    Simulation,
+    /// Frame display.
+    Display,
+}
+
+impl LatencyLog
+{
+    /// Creates and initializes a new latency log.
+    ///
+    /// * `capacity`: Maximum number of traces to keep.
+    ///
+    /// Returns the newly created log.
+    ///
+    /// Panics if the capacity is zero.
+    #[track_caller]
+    pub fn new(capacity: usize) -> Self
+    {
+        assert!(capacity > 0, "Latency log capacity must not be zero");
+        Self { traces: Vec::with_capacity(capacity),
+               capacity,
+               next: 0 }
+    }
+
+    /// Records a trace, replacing the oldest one if the log is full.
+    ///
+    /// * `trace`: Trace to record.
+    pub fn record(&mut self, trace: Trace)
+    {
+        if self.traces.len() < self.capacity {
+            self.traces.push(trace);
+        } else {
+            self.traces[self.next] = trace;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    /// Returns the number of recorded traces.
+    pub fn len(&self) -> usize
+    {
+        self.traces.len()
+    }
+
+    /// Computes the latency from input to the end of a stage below which the
+    /// given percentage of the recorded traces fall.
+    ///
+    /// * `stage`: Stage whose end to measure.
+    /// * `pct`: Percentile between 0 and 100.
+    ///
+    /// Returns the latency in microseconds, or [`None`] if nothing has been
+    /// recorded.
+    ///
+    /// Panics if the percentile is above 100.
+    #[track_caller]
+    pub fn percentile(&self, stage: Stage, pct: u32) -> Option<u64>
+    {
+        assert!(pct <= 100, "Percentile {pct} is out of range");
+        if self.traces.is_empty() {
+            return None;
+        }
+        let mut lats = self.traces.iter().map(|trace| trace.latency(stage)).collect::<Vec<_>>();
+        lats.sort_unstable();
+        let idx = (lats.len() - 1) * pct as usize / 100;
+        Some(lats[idx])
+    }
+}
+
+impl Display for LatencyLog
+{
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result
+    {
+        write!(fmt, "Input latency over {} events (p50/p90/p99 µs):", self.len())?;
+        for stage in [Stage::Recognition, Stage::Simulation, Stage::Display] {
+            write!(fmt, " {stage:?}")?;
+            for (idx, pct) in PERCENTILES.iter().enumerate() {
+                let sep = if idx == 0 { ' ' } else { '/' };
+                write!(fmt, "{sep}{}", self.percentile(stage, *pct).unwrap_or(0))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Trace
+{
+    /// Computes the time elapsed between the input event and the end of a
+    /// stage.
+    ///
+    /// * `stage`: Stage whose end to measure.
+    ///
+    /// Returns the latency in microseconds.
+    pub fn latency(&self, stage: Stage) -> u64
+    {
+        let end = match stage {
+            Stage::Recognition => self.recognized,
+            Stage::Simulation => self.simulated,
+            Stage::Display => self.displayed,
+        };
+        end.saturating_sub(self.input)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn percentiles()
+    {
+        let mut log = LatencyLog::new(100);
+        assert_eq!(log.percentile(Stage::Display, 50), None);
+        for lat in (1 ..= 100).rev() {
+            let trace = Trace { input: 1000,
+                                recognized: 1000 + lat,
+                                simulated: 1000 + lat * 2,
+                                displayed: 1000 + lat * 10 };
+            log.record(trace);
+        }
+        assert_eq!(log.percentile(Stage::Recognition, 0), Some(1));
+        assert_eq!(log.percentile(Stage::Recognition, 50), Some(50));
+        assert_eq!(log.percentile(Stage::Simulation, 100), Some(200));
+        assert_eq!(log.percentile(Stage::Display, 90), Some(900));
+    }
+
+    #[test]
+    fn overwrite_oldest()
+    {
+        let mut log = LatencyLog::new(2);
+        for lat in [100, 1, 2] {
+            let trace = Trace { input: 0,
+                                recognized: lat,
+                                simulated: lat,
+                                displayed: lat };
+            log.record(trace);
+        }
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.percentile(Stage::Display, 100), Some(2));
+    }
+}
@@ -13,39 +13,81 @@
 extern crate alloc as rust_alloc;
 
 mod alloc;
+mod assets;
 #[cfg(not(test))]
 mod audio;
 #[cfg(not(test))]
+mod board;
+#[cfg(not(test))]
 mod clock;
 #[cfg(not(test))]
 mod cpu;
 #[cfg(not(test))]
+mod display;
+#[cfg(not(test))]
+mod dma;
+#[cfg(not(test))]
+mod emmc;
+mod fat;
+mod game;
+#[cfg(not(test))]
+mod genet;
+#[cfg(not(test))]
+mod gpio;
+#[cfg(not(test))]
+mod i2c;
+#[cfg(not(test))]
+mod ipi;
+#[cfg(not(test))]
 mod irq;
+mod latency;
 mod math;
 #[cfg(not(test))]
 mod mbox;
 #[cfg(not(test))]
+mod mmu;
+mod net;
+#[cfg(not(test))]
 mod pixvalve;
 #[cfg(not(test))]
+mod power;
+#[cfg(not(test))]
 mod prim;
+#[cfg(all(profile, not(test)))]
+mod profile;
+mod raster;
 #[cfg(not(test))]
+mod remote;
+#[cfg(not(test))]
+mod report;
+#[cfg(not(test))]
+mod scenes;
 mod sched;
+#[cfg(not(test))]
+mod scrub;
+mod settings;
 mod simd;
 #[cfg(not(test))]
+mod spi;
+#[cfg(not(test))]
 mod sync;
 #[cfg(not(test))]
+mod thermal;
+#[cfg(not(test))]
 mod timer;
 #[cfg(not(test))]
 mod touch;
 #[cfg(not(test))]
 mod uart;
+mod ui;
 #[cfg(not(test))]
 mod video;
+mod wait;
 
 #[cfg(not(test))]
 use core::arch::{asm, global_asm};
 #[cfg(not(test))]
-use core::f32::consts::FRAC_PI_2;
+use core::array::from_fn;
 #[cfg(not(test))]
 use core::fmt::Write;
 #[cfg(not(test))]
@@ -53,42 +95,74 @@ use core::ops::Range;
 #[cfg(not(test))]
 use core::panic::PanicInfo;
 #[cfg(not(test))]
-use core::simd::f32x4;
-#[cfg(not(test))]
 use core::write;
 
 #[cfg(not(test))]
-use rust_alloc::sync::Arc;
+use self::alloc::{CACHED_REGION, UNCACHED_REGION};
 #[cfg(not(test))]
-use rust_alloc::vec;
-
+use self::assets::ASSETS;
+#[cfg(not(test))]
+use self::audio::{AUDIO, MUSIC, SOUNDS};
+#[cfg(not(test))]
+use self::clock::now_micros;
+#[cfg(not(test))]
+use self::cpu::{id as cpu_id, Perf, COUNT as CPU_COUNT, LOAD as CPU_LOAD, RESERVED as CPU_RESERVED};
 #[cfg(not(test))]
-use self::audio::AUDIO;
+use self::emmc::STORAGE;
 #[cfg(not(test))]
-use self::cpu::{id as cpu_id, COUNT as CPU_COUNT, LOAD as CPU_LOAD};
+use self::game::Stage;
+#[cfg(not(test))]
+use self::genet::GENET;
+#[cfg(not(test))]
+use self::gpio::{Function, Pull, Trigger, GPIO};
+#[cfg(not(test))]
+use self::i2c::{I2c, BSC};
+#[cfg(not(test))]
+use self::ipi::IPI;
 #[cfg(not(test))]
 use self::irq::IRQ;
 #[cfg(not(test))]
-use self::math::{Angle, Quaternion, Transform};
+use self::mmu::to_dma;
+#[cfg(not(test))]
+use self::net::dhcp_configure;
+#[cfg(not(test))]
+use self::power::POWER;
+#[cfg(all(profile, not(test)))]
+use self::profile::PROFILE;
+#[cfg(not(test))]
+use self::remote::REMOTE;
+#[cfg(not(test))]
+use self::report::report;
+#[cfg(not(test))]
+use self::scenes::GameScene;
+#[cfg(not(test))]
+use self::sched::{recover, TaskFailed, SCHED};
 #[cfg(not(test))]
-use self::sched::SCHED;
+use self::scrub::SCRUB;
 #[cfg(not(test))]
-use self::simd::SimdFloatExtra;
+use self::settings::{Graphics, Settings};
+#[cfg(not(test))]
+use self::spi::{ChipSelect, Mode, SPI};
+#[cfg(not(test))]
+use self::sync::critical;
+#[cfg(not(test))]
+use self::thermal::THERMAL;
 #[cfg(not(test))]
 use self::timer::TIMER;
 #[cfg(not(test))]
 use self::touch::Recognizer;
 #[cfg(not(test))]
-use self::uart::UART;
+use self::uart::Blocking;
 #[cfg(not(test))]
-use self::video::{Cube, Light, VIDEO};
+use self::video::{DebugMode, PixelFormat, BLITTER, VIDEO};
 
 /// uncached RANGE.
 #[cfg(not(test))]
 const UNCACHED_RANGE: Range<usize> = 0x84000000 .. 0x85600000;
-/// Cached range.
+/// Cached range mapped by the boot code, which grows to cover all the memory
+/// available to the ARM cores once the memory split is known.
 #[cfg(not(test))]
-const CACHED_RANGE: Range<usize> = 0x40000000 .. 0x7C000000;
+const CACHED_RANGE: Range<usize> = 0x40000000 .. 0x44000000;
 /// Peripherals range.
 #[cfg(not(test))]
 const PERRY_RANGE: Range<usize> = 0x80000000 .. 0x84000000;
@@ -98,21 +172,53 @@ const STACK_RANGES: [Range<usize>; CPU_COUNT] = [0xFFE00000 .. 0x100000000,
                                                  0xFFA00000 .. 0xFFC00000,
                                                  0xFF600000 .. 0xFF800000,
                                                  0xFF200000 .. 0xFF400000];
-/// Uncached range from the perspective of the DMA controller.
+/// Logical CPU that the video task is pinned to.
+#[cfg(not(test))]
+const VIDEO_CPU: usize = 0;
+/// Highest render scale in percent while the board needs thermal or power
+/// relief.
+#[cfg(not(test))]
+const RELIEF_RENDER_SCALE: u32 = 50;
+/// Highest render scale in percent while the board is healthy.
+#[cfg(not(test))]
+const NORMAL_RENDER_SCALE: u32 = 100;
+/// Time in milliseconds between attempts to obtain a network configuration.
+#[cfg(not(test))]
+const DHCP_RETRY_PERIOD: u64 = 10000;
+/// Time in milliseconds that the performance counters are sampled for when
+/// requested remotely.
+#[cfg(not(test))]
+const PERF_PERIOD: u64 = 1000;
+/// GPIO pin driving the green activity LED.
+#[cfg(not(test))]
+const ACT_LED_PIN: usize = 42;
+/// Time in milliseconds between toggles of the activity LED.
 #[cfg(not(test))]
-const DMA_UNCACHED_RANGE: Range<usize> = 0xC0200000 .. 0xC1800000;
-/// Cached range from the perspective of the DMA controller.
+const HEARTBEAT_PERIOD: u64 = 500;
+/// GPIO pin of the pause button, which shorts it to ground when pressed.
 #[cfg(not(test))]
-const DMA_CACHED_RANGE: Range<usize> = 0xC2000000 .. 0xCE000000;
-/// Peripherals range from the perspective of the DMA controller.
+const PAUSE_BUTTON_PIN: usize = 26;
+/// Time in milliseconds that the pause button must stay pressed to count.
 #[cfg(not(test))]
-const DMA_PERRY_RANGE: Range<usize> = 0x7C000000 .. 0x80000000;
-/// Stack ranges from the perspective of the DMA controller.
+const DEBOUNCE_PERIOD: u64 = 20;
+/// I2C controller wired to the pins of the expansion header.
 #[cfg(not(test))]
-const DMA_STACK_RANGES: [Range<usize>; CPU_COUNT] = [0xC1E00000 .. 0xC2000000,
-                                                     0xC1C00000 .. 0xC1E00000,
-                                                     0xC1A00000 .. 0xC1C00000,
-                                                     0xC1800000 .. 0xC1A00000];
+const HEADER_BSC: usize = 1;
+/// Clock frequency in hertz of the I2C bus on the expansion header.
+#[cfg(not(test))]
+const HEADER_I2C_FREQ: u32 = 100000;
+/// Maximum number of registers read by a single remote command.
+#[cfg(not(test))]
+const I2C_READ_MAX: usize = 32;
+/// Clock frequency in hertz of the SPI bus on the expansion header.
+#[cfg(not(test))]
+const HEADER_SPI_FREQ: u32 = 1000000;
+/// Maximum number of bytes exchanged by a single remote command.
+#[cfg(not(test))]
+const SPI_XFER_MAX: usize = 32;
+/// Path of the player settings on the SD card.
+#[cfg(not(test))]
+const SETTINGS_PATH: &str = "SETTINGS.BIN";
 /// Software generated IRQ that halts the system.
 #[cfg(not(test))]
 const HALT_IRQ: u32 = 0;
@@ -120,6 +226,14 @@ const HALT_IRQ: u32 = 0;
 #[cfg(not(test))]
 global_asm!(include_str!("boot.s"));
 
+/// Stands in for the scoped profiler macro when the kernel isn't built with the
+/// `profile` option.
+#[cfg(all(not(profile), not(test)))]
+#[macro_export]
+macro_rules! profile {
+    ($label:expr) => {};
+}
+
 /// Entry point.
 #[cfg(not(test))]
 #[no_mangle]
@@ -128,18 +242,146 @@ pub extern "C" fn start() -> !
     let affinity = cpu_id();
     debug!("Booted core #{affinity}");
     if affinity == 0 {
+        report();
+        // Pin the maximum clock so that profiles are comparable across runs.
+        #[cfg(profile)]
+        POWER.set_arm_clock(*POWER.arm_clock_range().end());
         IRQ.register(HALT_IRQ, || halt());
         let load = || {
             let (active, idle) = CPU_LOAD.report();
             let load = active * 100 / (active + idle);
             debug!("Load average: {load}%");
+            #[cfg(profile)]
+            {
+                PROFILE.dump();
+                PROFILE.reset();
+            }
             CPU_LOAD.reset();
             true
         };
         CPU_LOAD.reset();
         TIMER.schedule(10000, load);
-        SCHED.spawn(audio_ticker());
-        SCHED.spawn(video_ticker());
+        SCRUB.register(|| CACHED_REGION.lock().check_integrity());
+        SCRUB.register(|| UNCACHED_REGION.lock().check_integrity());
+        SCRUB.register(|| SCHED.check_integrity());
+        SCRUB.register(GameScene::check_map);
+        SCHED.spawn_named("scrub", SCRUB.run());
+        THERMAL.register(|status| {
+                   let cap = if status.needs_relief() {
+                       RELIEF_RENDER_SCALE
+                   } else {
+                       NORMAL_RENDER_SCALE
+                   };
+                   VIDEO.set_render_scale_cap(cap);
+               });
+        SCHED.spawn_named("thermal", THERMAL.run());
+        REMOTE.register("stats", || {
+                  SCHED.dump();
+                  IRQ.dump();
+                  let underruns = {
+                      let _critical = critical();
+                      AUDIO.lock().underruns()
+                  };
+                  debug!("Audio underruns: {underruns}");
+                  let range = POWER.arm_clock_range();
+                  debug!("ARM clock: {}MHz ({}MHz to {}MHz, turbo {})",
+                         POWER.arm_clock() / 1000000,
+                         range.start() / 1000000,
+                         range.end() / 1000000,
+                         if POWER.is_turbo() { "on" } else { "off" });
+              });
+        REMOTE.register_with_args("clock", |mhz| {
+                  let Ok(mhz) = mhz.parse::<u32>() else {
+                      return false;
+                  };
+                  POWER.set_arm_clock(mhz.saturating_mul(1000000));
+                  true
+              });
+        REMOTE.register("turbo", || POWER.set_turbo(!POWER.is_turbo()));
+        REMOTE.register("perf", || {
+                  SCHED.spawn(async {
+                           // Counters are private to each logical CPU, so every one
+                           // of them is sampled over the same period.
+                           let start: [Perf; CPU_COUNT] = from_fn(|cpu| IPI.call(cpu, Perf::start));
+                           TIMER.sleep(PERF_PERIOD).await;
+                           for (cpu, start) in start.into_iter().enumerate() {
+                               let perf = IPI.call(cpu, move || start.stop());
+                               debug!("CPU #{cpu}: {perf}");
+                           }
+                       });
+              });
+        REMOTE.register("screenshot", || {
+                  SCHED.spawn(REMOTE.send(VIDEO.capture_frame()));
+              });
+        REMOTE.register("drawstats", || {
+                  for (id, stats) in VIDEO.draw_stats().iter().enumerate() {
+                      debug!("Draw {id}: {stats}");
+                  }
+              });
+        REMOTE.register("shaded", || VIDEO.set_debug_mode(DebugMode::Off));
+        REMOTE.register("wireframe", || VIDEO.set_debug_mode(DebugMode::Wireframe));
+        REMOTE.register("depth", || VIDEO.set_debug_mode(DebugMode::Depth));
+        REMOTE.register("tiles", || VIDEO.set_debug_mode(DebugMode::Tiles));
+        REMOTE.register("overdraw", || VIDEO.set_debug_mode(DebugMode::Overdraw));
+        REMOTE.register("gamma", || VIDEO.set_gamma_correction(!VIDEO.gamma_correction()));
+        REMOTE.register("ssaa", || VIDEO.set_supersampling(!VIDEO.supersampling()));
+        REMOTE.register("burnin", || VIDEO.set_burn_in_mitigation(!VIDEO.burn_in_mitigation()));
+        REMOTE.register("rgb565", || {
+                  let format = match VIDEO.pixel_format() {
+                      PixelFormat::Xrgb8888 => PixelFormat::Rgb565,
+                      PixelFormat::Rgb565 => PixelFormat::Xrgb8888,
+                  };
+                  VIDEO.set_pixel_format(format);
+              });
+        REMOTE.register("dmablit", || BLITTER.set_enabled(!BLITTER.is_enabled()));
+        POWER.register(GameScene::flush_save);
+        REMOTE.register("pausegame", GameScene::toggle_pause);
+        REMOTE.register("gizmos", GameScene::toggle_gizmos);
+        REMOTE.register_with_args("dig", GameScene::dig);
+        REMOTE.register_with_args("furnish", GameScene::furnish);
+        REMOTE.register("mute", || {
+                  let _critical = critical();
+                  let mut audio = AUDIO.lock();
+                  let mut volume = audio.volume();
+                  volume.muted = !volume.muted;
+                  audio.set_volume(volume);
+              });
+        load_settings();
+        load_assets();
+        REMOTE.register_with_args("set", set_setting);
+        REMOTE.register("settings", || {
+                  let inspector = settings().inspect();
+                  inspector.rows()
+                           .iter()
+                           .for_each(|row| debug!("{} = {}", row.path, row.val));
+              });
+        REMOTE.register("i2cscan", scan_i2c);
+        REMOTE.register_with_args("i2cget", get_i2c);
+        REMOTE.register_with_args("i2cset", set_i2c);
+        REMOTE.register_with_args("spixfer", transfer_spi);
+        POWER.register(flush_settings);
+        REMOTE.register("reboot", || {
+                  POWER.reboot();
+              });
+        REMOTE.register("poweroff", || {
+                  POWER.power_off();
+              });
+        // Commands come from the network, so a panic while handling one should
+        // only take down the remote server.
+        SCHED.spawn_named("remote", async {
+                 match SCHED.spawn_catching(REMOTE.run()).await {
+                     Ok(never) => never,
+                     Err(TaskFailed) => debug!("Remote command server failed"),
+                 }
+             });
+        SCHED.spawn_named("ethernet", GENET.run());
+        SCHED.spawn_named("dhcp", dhcp_ticker());
+        SCHED.spawn_named("heartbeat", heartbeat_ticker());
+        SCHED.spawn_named("button", button_ticker());
+        SCHED.spawn_pinned_named("audio", CPU_RESERVED, audio_ticker());
+        // The video task drives the scenes and the rasterizer, so it's kept off
+        // the logical CPU reserved for latency-critical tasks.
+        SCHED.spawn_pinned_named("video", VIDEO_CPU, video_ticker());
     }
     IRQ.dispatch()
 }
@@ -148,27 +390,95 @@ pub extern "C" fn start() -> !
 #[cfg(not(test))]
 async fn video_ticker() -> !
 {
-    let fov = Angle::from(FRAC_PI_2);
-    let cam = Transform::default();
-    let cube = Cube::new();
-    let pos = f32x4::from_array([0.0, 0.0, -3.0, 1.0]);
-    let mut rot = Quaternion::default();
-    let scale = 1.0;
-    let lights = Arc::new(vec![Light::new_omni(f32x4::splat(0.0), f32x4::splat(1.0), 10.0)]);
-    let mut recog = Recognizer::new();
-    let norm = Recognizer::WIDTH.min(Recognizer::HEIGHT).recip();
-    let norm = f32x4::from_array([norm, norm, 0.0, 0.0]);
+    let mut stage = Stage::new(GameScene::new()).await;
     loop {
-        recog.sample();
-        let vec0 = f32x4::from_array([0.0, 0.0, 1.0, 0.0]);
-        let vec1 = recog.translation_delta() * norm;
-        let axis = vec0.cross_dot(vec0 + vec1);
-        let angle = Angle::from(vec1.len());
-        rot *= Quaternion::from_axis_angle(axis, angle);
-        rot *= recog.rotation_delta();
-        let mdl = Transform::from_components(pos, rot, scale);
-        VIDEO.draw_triangles(cube.geom(), lights.clone(), mdl, cam, fov);
+        REMOTE.checkpoint().await;
+        stage.update().await;
+        // Start over from the splash screen rather than showing nothing.
+        if stage.is_empty() {
+            stage = Stage::new(GameScene::new()).await;
+        }
+        stage.draw().await;
         VIDEO.commit().await;
+        // The frame buffers have been flipped by the time the commit completes.
+        stage.presented(now_micros());
+    }
+}
+
+/// Main loop for the DHCP client task, which keeps the network interface
+/// configured and, when built with the `netassets` option, downloads the
+/// assets from the boot server named by the first lease.
+#[cfg(not(test))]
+async fn dhcp_ticker() -> !
+{
+    #[cfg(netassets)]
+    let mut fetched = false;
+    loop {
+        let lease = match dhcp_configure().await {
+            Ok(lease) => lease,
+            Err(err) => {
+                debug!("Failed to obtain a network configuration: {err:?}");
+                TIMER.sleep(DHCP_RETRY_PERIOD).await;
+                continue;
+            }
+        };
+        let [a0, a1, a2, a3] = lease.config.address;
+        debug!("Leased address {a0}.{a1}.{a2}.{a3} for {}s", lease.time);
+        #[cfg(netassets)]
+        if !fetched {
+            fetched = true;
+            SCHED.spawn_named("assets", async move {
+                     match ASSETS.fetch(lease.server).await {
+                         Ok(count) => debug!("Downloaded {count} assets"),
+                         Err(err) => debug!("Failed to download assets: {err:?}"),
+                     }
+                 });
+        }
+        // Leases are renewed halfway through, like RFC 2131 suggests.
+        TIMER.sleep((u64::from(lease.time) * 500).max(DHCP_RETRY_PERIOD)).await;
+    }
+}
+
+/// Main loop for the heartbeat task, which blinks the activity LED for as long
+/// as tasks keep running.
+#[cfg(not(test))]
+async fn heartbeat_ticker() -> !
+{
+    let mut led = GPIO.claim(ACT_LED_PIN).expect("Activity LED pin already claimed");
+    led.set_function(Function::Output);
+    let mut lit = false;
+    loop {
+        lit = !lit;
+        led.set(lit);
+        TIMER.sleep(HEARTBEAT_PERIOD).await;
+    }
+}
+
+/// Main loop for the pause button task, which toggles the pause screen
+/// whenever the button is pressed.
+#[cfg(not(test))]
+async fn button_ticker() -> !
+{
+    let mut button = GPIO.claim(PAUSE_BUTTON_PIN).expect("Pause button pin already claimed");
+    button.set_function(Function::Input);
+    button.set_pull(Pull::Up);
+    loop {
+        let pressed = button.event();
+        button.listen(Trigger::Falling);
+        pressed.await;
+        // Contacts bounce for a while when pressed and released, so only
+        // presses that are still held afterwards count.
+        TIMER.sleep(DEBOUNCE_PERIOD).await;
+        if button.is_high() {
+            continue;
+        }
+        GameScene::toggle_pause();
+        // Wait for the button to be released before listening for presses
+        // again, so that its contacts bouncing on release can't count as one.
+        let released = button.event();
+        button.listen(Trigger::High);
+        released.await;
+        TIMER.sleep(DEBOUNCE_PERIOD).await;
     }
 }
 
@@ -180,6 +490,8 @@ async fn audio_ticker()
     loop {
         recog.sample();
         let tick = {
+            let mut music = MUSIC.lock().await;
+            let _critical = critical();
             let mut audio = AUDIO.lock();
             if let Some(pos) = recog.first_position() {
                 let freq = 200.0 + pos[1];
@@ -191,12 +503,229 @@ async fn audio_ticker()
                 let pan = pos[0] / Recognizer::WIDTH * 2.0 - 1.0;
                 audio.play_tone(freq as u16, pan);
             }
+            // Sound effects take precedence over music when polyphony runs out.
+            SOUNDS.flush(&mut audio);
+            music.stream(&mut audio);
             audio.commit()
         };
         tick.await;
     }
 }
 
+/// Gathers the player settings from the subsystems that apply them.
+///
+/// Returns the current settings.
+#[cfg(not(test))]
+fn settings() -> Settings
+{
+    let graphics = Graphics { render_scale: VIDEO.render_scale() as _ };
+    let _critical = critical();
+    Settings { volume: AUDIO.lock().volume(),
+               graphics }
+}
+
+/// Hands the player settings to the subsystems that apply them.
+///
+/// * `settings`: Settings to apply.
+#[cfg(not(test))]
+fn apply_settings(settings: Settings)
+{
+    VIDEO.set_render_scale(settings.graphics.render_scale as _);
+    let _critical = critical();
+    AUDIO.lock().set_volume(settings.volume);
+}
+
+/// Loads the player settings from the SD card and applies them, keeping the
+/// defaults if there are none.
+#[cfg(not(test))]
+fn load_settings()
+{
+    let bytes = match STORAGE.read(SETTINGS_PATH) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            debug!("No settings: {err}");
+            return;
+        }
+    };
+    match Settings::from_bytes(&bytes) {
+        Ok(settings) => apply_settings(settings),
+        Err(err) => debug!("Failed to load the settings: {err}"),
+    }
+}
+
+/// Loads the assets on the SD card into the asset store.
+#[cfg(not(test))]
+fn load_assets()
+{
+    match ASSETS.load() {
+        Ok(count) => debug!("Loaded {count} assets"),
+        Err(err) => debug!("No assets: {err}"),
+    }
+}
+
+/// Writes the player settings to the SD card, blocking until done so that it
+/// can run right before a shutdown.
+#[cfg(not(test))]
+fn flush_settings()
+{
+    if let Err(err) = STORAGE.write(SETTINGS_PATH, &settings().to_bytes()) {
+        debug!("Failed to save the settings: {err}");
+    }
+}
+
+/// Changes a player setting from a remote command.
+///
+/// * `binding`: Binding in `component.field=value` form.
+///
+/// Returns whether the binding was accepted.
+#[cfg(not(test))]
+fn set_setting(binding: &str) -> bool
+{
+    let mut settings = settings();
+    if !settings.tweak(binding) {
+        return false;
+    }
+    apply_settings(settings);
+    true
+}
+
+/// Probes every address on the I2C bus of the expansion header and logs the
+/// devices that respond, from a remote command.
+#[cfg(not(test))]
+fn scan_i2c()
+{
+    SCHED.spawn(async {
+             let Some(mut bus) = BSC.claim(HEADER_BSC, HEADER_I2C_FREQ) else {
+                 debug!("I2C bus busy");
+                 return;
+             };
+             let mut byte = [0];
+             for addr in 0x8 .. 0x78 {
+                 if bus.read(addr, &mut byte).await.is_ok() {
+                     debug!("I2C device at {addr:#04X}");
+                 }
+             }
+         });
+}
+
+/// Reads consecutive registers of a device on the I2C bus of the expansion
+/// header from a remote command.
+///
+/// * `args`: Hexadecimal address of the device and of the first register,
+///   followed by the number of registers to read.
+///
+/// Returns whether the arguments were valid.
+#[cfg(not(test))]
+fn get_i2c(args: &str) -> bool
+{
+    let mut args = args.split_whitespace();
+    let (Some(addr), Some(reg), Some(len)) = (args.next(), args.next(), args.next()) else {
+        return false;
+    };
+    let (Some(addr), Some(reg), Ok(len @ 0 ..= I2C_READ_MAX)) = (parse_hex(addr), parse_hex(reg), len.parse()) else {
+        return false;
+    };
+    SCHED.spawn(async move {
+             let Some(mut bus) = BSC.claim(HEADER_BSC, HEADER_I2C_FREQ) else {
+                 debug!("I2C bus busy");
+                 return;
+             };
+             let mut buf = [0; I2C_READ_MAX];
+             match bus.write_read(addr, &[reg], &mut buf[.. len]).await {
+                 Ok(()) => debug!("I2C {addr:#04X}: {:02X?}", &buf[.. len]),
+                 Err(err) => debug!("I2C {addr:#04X}: {err:?}"),
+             }
+         });
+    true
+}
+
+/// Writes a register of a device on the I2C bus of the expansion header from
+/// a remote command.
+///
+/// * `args`: Hexadecimal address of the device, of the register, and of the
+///   value to write.
+///
+/// Returns whether the arguments were valid.
+#[cfg(not(test))]
+fn set_i2c(args: &str) -> bool
+{
+    let mut args = args.split_whitespace();
+    let (Some(addr), Some(reg), Some(val)) = (args.next(), args.next(), args.next()) else {
+        return false;
+    };
+    let (Some(addr), Some(reg), Some(val)) = (parse_hex(addr), parse_hex(reg), parse_hex(val)) else {
+        return false;
+    };
+    SCHED.spawn(async move {
+             let Some(mut bus) = BSC.claim(HEADER_BSC, HEADER_I2C_FREQ) else {
+                 debug!("I2C bus busy");
+                 return;
+             };
+             if let Err(err) = bus.write(addr, &[reg, val]).await {
+                 debug!("I2C {addr:#04X}: {err:?}");
+             }
+         });
+    true
+}
+
+/// Exchanges bytes with a device on the SPI bus of the expansion header from a
+/// remote command.
+///
+/// * `args`: Clock mode from 0 to 3 and chip select line from 0 to 1, followed
+///   by the hexadecimal bytes to send.
+///
+/// Returns whether the arguments were valid.
+#[cfg(not(test))]
+fn transfer_spi(args: &str) -> bool
+{
+    let mut args = args.split_whitespace();
+    let (Some(mode), Some(cs)) = (args.next(), args.next()) else {
+        return false;
+    };
+    let mode = match mode {
+        "0" => Mode::Mode0,
+        "1" => Mode::Mode1,
+        "2" => Mode::Mode2,
+        "3" => Mode::Mode3,
+        _ => return false,
+    };
+    let cs = match cs {
+        "0" => ChipSelect::Ce0,
+        "1" => ChipSelect::Ce1,
+        _ => return false,
+    };
+    let mut tx = [0; SPI_XFER_MAX];
+    let mut len = 0;
+    for arg in args {
+        let (Some(slot), Some(byte)) = (tx.get_mut(len), parse_hex(arg)) else {
+            return false;
+        };
+        *slot = byte;
+        len += 1;
+    }
+    SCHED.spawn(async move {
+             let Some(mut master) = SPI.claim(HEADER_SPI_FREQ, mode) else {
+                 debug!("SPI bus busy");
+                 return;
+             };
+             let mut rx = [0; SPI_XFER_MAX];
+             master.transfer(cs, &tx[.. len], &mut rx[.. len]).await;
+             debug!("SPI: {:02X?}", &rx[.. len]);
+         });
+    true
+}
+
+/// Parses a hexadecimal byte, with or without a `0x` prefix.
+///
+/// * `arg`: Text to parse.
+///
+/// Returns the parsed byte, or `None` if the text isn't a hexadecimal byte.
+#[cfg(not(test))]
+fn parse_hex(arg: &str) -> Option<u8>
+{
+    u8::from_str_radix(arg.trim_start_matches("0x"), 16).ok()
+}
+
 /// Panics with diagnostic information about a fault.
 #[cfg(not(test))]
 #[no_mangle]
@@ -261,12 +790,14 @@ pub extern "C" fn halt() -> !
 #[cfg(test)]
 fn main() {}
 
-/// Halts the system with a diagnostic error message.
+/// Halts the system with a diagnostic error message, unless the panic happened
+/// in a task spawned to catch its own panics, in which case that task fails
+/// instead.
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> !
 {
-    let mut uart = UART.lock();
+    let mut uart = Blocking;
     let affinity = cpu_id();
     if let Some(location) = info.location() {
         write!(uart,
@@ -282,47 +813,18 @@ fn panic(info: &PanicInfo) -> !
         uart.write_str("Unknown reason").unwrap()
     }
     uart.write_char('\n').unwrap();
-    drop(uart);
     backtrace();
+    recover();
     IRQ.notify_others(HALT_IRQ);
     halt();
 }
 
-/// Converts the specified virtual address to a physical address from the
-/// perspective of the DMA controller.
-///
-/// * `addr`: Address to convert.
-///
-/// Returns the converted address.
-///
-/// Panics if the requested address is not accessible by the DMA controller.
-#[cfg(not(test))]
-#[track_caller]
-fn to_dma(addr: usize) -> usize
-{
-    if UNCACHED_RANGE.contains(&addr) {
-        return addr - UNCACHED_RANGE.start + DMA_UNCACHED_RANGE.start;
-    }
-    if CACHED_RANGE.contains(&addr) {
-        return addr - CACHED_RANGE.start + DMA_CACHED_RANGE.start;
-    }
-    if PERRY_RANGE.contains(&addr) {
-        return addr - PERRY_RANGE.start + DMA_PERRY_RANGE.start;
-    }
-    for cpu in 0 .. CPU_COUNT {
-        if STACK_RANGES[cpu].contains(&addr) {
-            return addr - STACK_RANGES[cpu].start + DMA_STACK_RANGES[cpu].start;
-        }
-    }
-    panic!("Requested address is either not mapped or not accessible by the DMA controller: 0x{addr:X}");
-}
-
 /// Sends the return addresses of all the function calls from this function all
 /// the way to the boot code through the UART.
 #[cfg(not(test))]
 fn backtrace()
 {
-    let mut uart = UART.lock();
+    let mut uart = Blocking;
     let mut fp: usize;
     let mut lr: usize;
     unsafe {
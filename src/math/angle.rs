@@ -3,9 +3,23 @@
 use core::cmp::{Ordering, PartialOrd, Reverse};
 use core::f32::consts::PI;
 use core::fmt::{Display, Formatter, Result as FormatResult};
+use core::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
 use super::*;
 
+/// Computes fast approximations of the sine and cosine of an angle, suitable
+/// for per-frame animation and audio.
+///
+/// * `radians`: Angle in radians, accurate to within 1e-6 within 4096 quarter
+///   turns of zero.
+///
+/// Returns the computed sine and cosine.
+pub fn fast_sin_cos(radians: f32) -> (f32, f32)
+{
+    let (sin, cos) = f32x4::splat(radians).fast_sin_cos();
+    (sin[0], cos[0])
+}
+
 /// Angle.
 #[repr(transparent)]
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -35,6 +49,72 @@ impl Angle
         Self { w }
     }
 
+    /// Creates and initializes a new angle from its sine and cosine, or any
+    /// multiple of both.
+    ///
+    /// * `sin`: Sine of the angle.
+    /// * `cos`: Cosine of the angle.
+    ///
+    /// Returns the newly created angle.
+    pub fn from_sin_cos(sin: f32, cos: f32) -> Self
+    {
+        let len = (sin * sin + cos * cos).sqrt();
+        if len == 0.0 {
+            return Self::default();
+        }
+        let angle = Self::from_cos(cos / len);
+        if sin < 0.0 {
+            return -angle;
+        }
+        angle
+    }
+
+    /// Creates and initializes a new angle from degrees.
+    ///
+    /// * `degrees`: Angle in degrees.
+    ///
+    /// Returns the newly created angle.
+    pub fn from_degrees(degrees: f32) -> Self
+    {
+        Self::from(degrees / 180.0 * PI)
+    }
+
+    /// Converts this angle to degrees, between 0 and 360.
+    ///
+    /// Returns the converted value.
+    pub fn to_degrees(self) -> f32
+    {
+        f32::from(self) / PI * 180.0
+    }
+
+    /// Creates an angle from the cosine and sine of half of an angle that may
+    /// be outside of the range of a full turn, wrapping it around.
+    ///
+    /// * `cos`: Cosine of half the angle.
+    /// * `sin`: Sine of half the angle.
+    ///
+    /// Returns the created angle.
+    fn from_half(cos: f32, sin: f32) -> Self
+    {
+        // Half angles between a half and a full turn correspond to angles between
+        // one and two full turns, or between minus one and zero full turns, both of
+        // which are one full turn away from the angle with the opposite half cosine.
+        // A half cosine of -1 is exactly one full turn, whose half sine may not round
+        // to zero.
+        if sin < 0.0 || cos <= -1.0 {
+            return Self { w: -cos };
+        }
+        Self { w: cos }
+    }
+
+    /// Computes the sine of half this angle, which is never negative.
+    ///
+    /// Returns the computed value.
+    fn half_sin(self) -> f32
+    {
+        (1.0 - self.w * self.w).max(0.0).sqrt()
+    }
+
     /// Computes the sine and cosine of this angle.
     ///
     /// Returns the computed values.
@@ -61,21 +141,73 @@ impl From<f32> for Angle
 {
     fn from(radians: f32) -> Self
     {
-        let angle = radians.abs() / 2.0 % PI;
-        let mut res = 1.0f32;
-        let mut n = 2.0f32;
-        let mut fact = 1.0f32;
-        let mut diff = 1.0f32;
-        let mut mul = 1.0f32;
-        while diff.abs() > TOLERANCE / 2.0 {
-            mul = -mul * angle * angle;
-            fact *= (n - 1.0) * n;
-            n += 2.0;
-            diff = mul / fact;
-            res += diff;
-        }
-        res *= radians.signum();
-        Self { w: res }
+        let (_, cos) = fast_sin_cos(radians.abs() / 2.0 % PI);
+        Self { w: cos * radians.signum() }
+    }
+}
+
+impl Add for Angle
+{
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self
+    {
+        let (sin0, sin1) = (self.half_sin(), other.half_sin());
+        Self::from_half(self.w * other.w - sin0 * sin1, sin0 * other.w + self.w * sin1)
+    }
+}
+
+impl AddAssign for Angle
+{
+    fn add_assign(&mut self, other: Self)
+    {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Angle
+{
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self
+    {
+        self + -other
+    }
+}
+
+impl SubAssign for Angle
+{
+    fn sub_assign(&mut self, other: Self)
+    {
+        *self = *self - other;
+    }
+}
+
+impl Neg for Angle
+{
+    type Output = Self;
+
+    fn neg(self) -> Self
+    {
+        Self::from_half(self.w, -self.half_sin())
+    }
+}
+
+impl Mul<f32> for Angle
+{
+    type Output = Self;
+
+    fn mul(self, scalar: f32) -> Self
+    {
+        Self::from(f32::from(self) * scalar)
+    }
+}
+
+impl MulAssign<f32> for Angle
+{
+    fn mul_assign(&mut self, scalar: f32)
+    {
+        *self = *self * scalar;
     }
 }
 
@@ -100,7 +232,7 @@ impl Display for Angle
     fn fmt(&self, fmt: &mut Formatter) -> FormatResult
     {
         let radians = f32::from(*self);
-        let degrees = radians / PI * 180.0;
+        let degrees = self.to_degrees();
         write!(fmt, "{radians} radians ({degrees} degrees)")
     }
 }
@@ -218,6 +350,61 @@ mod tests
         expect_roughly(tan, (PI / 6.0).tan());
     }
 
+    #[test]
+    fn fast_sin_cos()
+    {
+        for idx in -1000 ..= 1000 {
+            let radians = idx as f32 / 10.0;
+            let (sin, cos) = super::fast_sin_cos(radians);
+            assert!((sin - radians.sin()).abs() < 1e-6, "sin({radians}) = {sin}");
+            assert!((cos - radians.cos()).abs() < 1e-6, "cos({radians}) = {cos}");
+        }
+    }
+
+    #[test]
+    fn from_sin_cos()
+    {
+        for degrees in [0.0, 45.0, 135.0, 180.0, 225.0, 315.0] {
+            let (sin, cos) = (degrees / 180.0 * PI).sin_cos();
+            expect_degrees(Angle::from_sin_cos(sin * 2.0, cos * 2.0), degrees);
+        }
+    }
+
+    #[test]
+    fn degrees()
+    {
+        let angle = Angle::from_degrees(60.0);
+        expect_roughly(angle.w, (PI / 6.0).cos());
+        expect_degrees(angle, 60.0);
+        expect_degrees(Angle::from_degrees(-90.0), 270.0);
+    }
+
+    #[test]
+    fn arithmetic()
+    {
+        let (a, b) = (Angle::from_degrees(120.0), Angle::from_degrees(90.0));
+        expect_degrees(a + b, 210.0);
+        expect_degrees(a - b, 30.0);
+        expect_degrees(b - a, 330.0);
+        expect_degrees(-b, 270.0);
+        expect_degrees(a * 1.5, 180.0);
+        // Sums past a full turn wrap around.
+        let mut angle = Angle::from_degrees(300.0);
+        angle += Angle::from_degrees(90.0);
+        expect_degrees(angle, 30.0);
+        angle -= Angle::from_degrees(60.0);
+        expect_degrees(angle, 330.0);
+        angle *= 0.5;
+        expect_degrees(angle, 165.0);
+        expect_roughly((Angle::from_degrees(180.0) + Angle::from_degrees(180.0)).w, 1.0);
+    }
+
+    #[track_caller]
+    fn expect_degrees(actual: Angle, expected: f32)
+    {
+        expect_roughly(f32::from(actual), expected / 180.0 * PI);
+    }
+
     #[test]
     fn into_radians()
     {
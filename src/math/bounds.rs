@@ -0,0 +1,344 @@
+//! Bounding volumes.
+
+use core::array::from_fn;
+use core::simd::prelude::*;
+
+use super::trans::Transform;
+use super::*;
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb
+{
+    /// Minimum corner with a zero W component.
+    min: f32x4,
+    /// Maximum corner with a zero W component.
+    max: f32x4,
+}
+
+/// Bounding sphere.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sphere
+{
+    /// Center with a zero W component.
+    center: f32x4,
+    /// Radius.
+    radius: f32,
+}
+
+impl Aabb
+{
+    /// Creates and initializes a new axis-aligned bounding box.
+    ///
+    /// * `min`: Minimum corner.
+    /// * `max`: Maximum corner.
+    ///
+    /// Returns the newly created box.
+    pub fn new(min: f32x4, max: f32x4) -> Self
+    {
+        Self { min: min.replace_lane::<3>(0.0),
+               max: max.replace_lane::<3>(0.0) }
+    }
+
+    /// Creates and initializes the smallest axis-aligned bounding box
+    /// containing a set of points.
+    ///
+    /// * `points`: Points to enclose.
+    ///
+    /// Returns the newly created box, or `None` if there are no points.
+    pub fn from_points(points: &[f32x4]) -> Option<Self>
+    {
+        let (first, rest) = points.split_first()?;
+        let (min, max) = rest.iter().fold((*first, *first), |(min, max), point| {
+                                        (min.simd_min(*point), max.simd_max(*point))
+                                    });
+        Some(Self::new(min, max))
+    }
+
+    /// Returns the minimum corner of this box.
+    pub fn min(self) -> f32x4
+    {
+        self.min
+    }
+
+    /// Returns the maximum corner of this box.
+    pub fn max(self) -> f32x4
+    {
+        self.max
+    }
+
+    /// Returns the center of this box.
+    pub fn center(self) -> f32x4
+    {
+        (self.min + self.max).mul_scalar(0.5)
+    }
+
+    /// Checks whether a point is inside this box.
+    ///
+    /// * `point`: Point to check.
+    ///
+    /// Returns whether the point is inside, including the boundaries.
+    pub fn contains(self, point: f32x4) -> bool
+    {
+        let point = point.replace_lane::<3>(0.0);
+        (point.simd_ge(self.min) & point.simd_le(self.max)).all()
+    }
+
+    /// Checks whether this box overlaps another box.
+    ///
+    /// * `other`: Box to check against.
+    ///
+    /// Returns whether the boxes overlap, including when they only touch.
+    pub fn overlaps(self, other: Self) -> bool
+    {
+        (self.min.simd_le(other.max) & self.max.simd_ge(other.min)).all()
+    }
+
+    /// Checks whether this box overlaps a sphere.
+    ///
+    /// * `sphere`: Sphere to check against.
+    ///
+    /// Returns whether the box and sphere overlap, including when they only
+    /// touch.
+    pub fn overlaps_sphere(self, sphere: Sphere) -> bool
+    {
+        let closest = sphere.center.simd_clamp(self.min, self.max);
+        (closest - sphere.center).sq_len() <= sphere.radius * sphere.radius
+    }
+
+    /// Computes the axis-aligned bounding box containing this box after a
+    /// transformation.
+    ///
+    /// * `trans`: Transformation to apply.
+    ///
+    /// Returns a newly created box with the result.
+    pub fn transform(self, trans: Transform) -> Self
+    {
+        let mat = trans.into_matrix();
+        let corners: [f32x4; 8] = from_fn(|idx| self.corner(idx).mul_mat(mat));
+        // There are always corners, so this never fails.
+        Self::from_points(&corners).unwrap()
+    }
+
+    /// Returns one of the corners of this box with a W component of 1.
+    ///
+    /// * `idx`: Index of the corner, whose bits 0, 1, and 2 select the maximum
+    ///   X, Y, and Z respectively.
+    pub fn corner(self, idx: usize) -> f32x4
+    {
+        let mask = mask32x4::from_array([idx & 0x1 != 0, idx & 0x2 != 0, idx & 0x4 != 0, false]);
+        mask.select(self.max, self.min).replace_lane::<3>(1.0)
+    }
+
+    /// Intersects a ray with this box.
+    ///
+    /// * `origin`: Origin of the ray.
+    /// * `dir`: Direction of the ray, which doesn't need to be normalized.
+    ///
+    /// Returns the distance to the nearest intersection in multiples of the
+    /// direction, which is zero if the origin is inside the box, or `None` if
+    /// the ray misses the box.
+    pub fn intersect_ray(self, origin: f32x4, dir: f32x4) -> Option<f32>
+    {
+        let inv = dir.recip();
+        let near = (self.min - origin) * inv;
+        let far = (self.max - origin) * inv;
+        let (near, far) = (near.simd_min(far), near.simd_max(far));
+        let near = near[0].max(near[1]).max(near[2]).max(0.0);
+        let far = far[0].min(far[1]).min(far[2]);
+        (near <= far).then_some(near)
+    }
+}
+
+impl Sphere
+{
+    /// Creates and initializes a new bounding sphere.
+    ///
+    /// * `center`: Center of the sphere.
+    /// * `radius`: Radius of the sphere.
+    ///
+    /// Returns the newly created sphere.
+    pub fn new(center: f32x4, radius: f32) -> Self
+    {
+        Self { center: center.replace_lane::<3>(0.0),
+               radius }
+    }
+
+    /// Creates and initializes the bounding sphere of an axis-aligned bounding
+    /// box.
+    ///
+    /// * `aabb`: Box to enclose.
+    ///
+    /// Returns the newly created sphere.
+    pub fn from_aabb(aabb: Aabb) -> Self
+    {
+        let center = aabb.center();
+        Self { center,
+               radius: (aabb.max - center).len() }
+    }
+
+    /// Returns the center of this sphere.
+    pub fn center(self) -> f32x4
+    {
+        self.center
+    }
+
+    /// Returns the radius of this sphere.
+    pub fn radius(self) -> f32
+    {
+        self.radius
+    }
+
+    /// Checks whether a point is inside this sphere.
+    ///
+    /// * `point`: Point to check.
+    ///
+    /// Returns whether the point is inside, including the boundary.
+    pub fn contains(self, point: f32x4) -> bool
+    {
+        (point.replace_lane::<3>(0.0) - self.center).sq_len() <= self.radius * self.radius
+    }
+
+    /// Checks whether this sphere overlaps another sphere.
+    ///
+    /// * `other`: Sphere to check against.
+    ///
+    /// Returns whether the spheres overlap, including when they only touch.
+    pub fn overlaps(self, other: Self) -> bool
+    {
+        let radius = self.radius + other.radius;
+        (other.center - self.center).sq_len() <= radius * radius
+    }
+
+    /// Checks whether this sphere overlaps an axis-aligned bounding box.
+    ///
+    /// * `aabb`: Box to check against.
+    ///
+    /// Returns whether the sphere and box overlap, including when they only
+    /// touch.
+    pub fn overlaps_aabb(self, aabb: Aabb) -> bool
+    {
+        aabb.overlaps_sphere(self)
+    }
+
+    /// Computes the bounding sphere of this sphere after a transformation.
+    ///
+    /// * `trans`: Transformation to apply.
+    ///
+    /// Returns a newly created sphere with the result.
+    pub fn transform(self, trans: Transform) -> Self
+    {
+        let center = self.center.replace_lane::<3>(1.0).mul_mat(trans.into_matrix());
+        Self::new(center, self.radius * trans.scale())
+    }
+
+    /// Intersects a ray with this sphere.
+    ///
+    /// * `origin`: Origin of the ray.
+    /// * `dir`: Direction of the ray, which doesn't need to be normalized.
+    ///
+    /// Returns the distance to the nearest intersection in multiples of the
+    /// direction, which is zero if the origin is inside the sphere, or `None`
+    /// if the ray misses the sphere.
+    pub fn intersect_ray(self, origin: f32x4, dir: f32x4) -> Option<f32>
+    {
+        let offset = (origin - self.center).replace_lane::<3>(0.0);
+        let dir = dir.replace_lane::<3>(0.0);
+        let dist = offset.sq_len() - self.radius * self.radius;
+        if dist <= 0.0 {
+            return Some(0.0);
+        }
+        let proj = (offset * dir).reduce_sum();
+        let len = dir.sq_len();
+        let disc = proj * proj - len * dist;
+        if proj > 0.0 || disc < 0.0 || len == 0.0 {
+            return None;
+        }
+        Some((-proj - disc.sqrt()) / len)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use core::f32::consts::PI;
+
+    use super::*;
+
+    fn unit_box() -> Aabb
+    {
+        Aabb::new(f32x4::splat(-1.0), f32x4::splat(1.0))
+    }
+
+    #[test]
+    fn aabb_from_points()
+    {
+        let points = [f32x4::from_array([1.0, -2.0, 3.0, 1.0]),
+                      f32x4::from_array([-1.0, 2.0, 0.0, 1.0]),
+                      f32x4::from_array([0.0, 0.0, -3.0, 1.0])];
+        let aabb = Aabb::from_points(&points).unwrap();
+        assert_eq!(aabb.min(), f32x4::from_array([-1.0, -2.0, -3.0, 0.0]));
+        assert_eq!(aabb.max(), f32x4::from_array([1.0, 2.0, 3.0, 0.0]));
+        assert!(points.iter().all(|point| aabb.contains(*point)));
+        assert!(!aabb.contains(f32x4::from_array([1.5, 0.0, 0.0, 1.0])));
+        assert_eq!(aabb.corner(0), f32x4::from_array([-1.0, -2.0, -3.0, 1.0]));
+        assert_eq!(aabb.corner(5), f32x4::from_array([1.0, -2.0, 3.0, 1.0]));
+        assert_eq!(Aabb::from_points(&[]), None);
+    }
+
+    #[test]
+    fn overlaps()
+    {
+        let aabb = unit_box();
+        let near = Aabb::new(f32x4::splat(1.0), f32x4::splat(2.0));
+        let far = Aabb::new(f32x4::from_array([1.5, 0.0, 0.0, 0.0]), f32x4::splat(2.0));
+        assert!(aabb.overlaps(near) && near.overlaps(aabb));
+        assert!(!aabb.overlaps(far) && !far.overlaps(aabb));
+        let sphere = Sphere::new(f32x4::from_array([2.0, 2.0, 0.0, 1.0]), 1.5);
+        assert!(aabb.overlaps_sphere(sphere) && sphere.overlaps_aabb(aabb));
+        let sphere = Sphere::new(f32x4::from_array([2.0, 2.0, 0.0, 1.0]), 1.4);
+        assert!(!aabb.overlaps_sphere(sphere) && !sphere.overlaps_aabb(aabb));
+        let inside = Sphere::new(f32x4::splat(0.0), 0.1);
+        assert!(aabb.overlaps_sphere(inside));
+        let other = Sphere::new(f32x4::from_array([4.0, 2.0, 0.0, 1.0]), 0.0);
+        assert!(!sphere.overlaps(other));
+        let other = Sphere::new(f32x4::from_array([4.0, 2.0, 0.0, 1.0]), 1.0);
+        assert!(sphere.overlaps(other) && other.overlaps(sphere));
+    }
+
+    #[test]
+    fn transform()
+    {
+        let pos = f32x4::from_array([2.0, 3.0, 4.0, 1.0]);
+        let axis = f32x4::from_array([0.0, 0.0, 1.0, 0.0]);
+        let rot = Quaternion::from_axis_angle(axis, Angle::from(PI / 4.0));
+        let trans = Transform::from_components(pos, rot, 2.0);
+        let aabb = unit_box().transform(trans);
+        let diag = 2.0 * 2.0f32.sqrt();
+        expect_roughly_vec(aabb.min(), f32x4::from_array([2.0 - diag, 3.0 - diag, 2.0, 0.0]));
+        expect_roughly_vec(aabb.max(), f32x4::from_array([2.0 + diag, 3.0 + diag, 6.0, 0.0]));
+        let sphere = Sphere::from_aabb(unit_box()).transform(trans);
+        expect_roughly_vec(sphere.center(), f32x4::from_array([2.0, 3.0, 4.0, 0.0]));
+        expect_roughly(sphere.radius(), 2.0 * 3.0f32.sqrt());
+        assert!(sphere.contains(f32x4::from_array([2.0 + 2.0 * 3.0f32.sqrt() - 0.01, 3.0, 4.0, 1.0])));
+        assert!(!sphere.contains(f32x4::from_array([2.0, 3.0, 8.0, 1.0])));
+    }
+
+    #[test]
+    fn intersect_ray()
+    {
+        let origin = f32x4::from_array([-5.0, 0.5, 0.0, 1.0]);
+        let dir = f32x4::from_array([2.0, 0.0, 0.0, 0.0]);
+        expect_roughly(unit_box().intersect_ray(origin, dir).unwrap(), 2.0);
+        assert_eq!(unit_box().intersect_ray(origin, -dir), None);
+        assert_eq!(unit_box().intersect_ray(f32x4::splat(0.0), dir), Some(0.0));
+        let sphere = Sphere::new(f32x4::splat(0.0), 1.0);
+        let hit = 5.0 - 0.75f32.sqrt();
+        expect_roughly(sphere.intersect_ray(origin, dir).unwrap(), hit / 2.0);
+        assert_eq!(sphere.intersect_ray(origin, -dir), None);
+        let miss = f32x4::from_array([-5.0, 1.5, 0.0, 1.0]);
+        assert_eq!(sphere.intersect_ray(miss, dir), None);
+        assert_eq!(unit_box().intersect_ray(miss, dir), None);
+        assert_eq!(sphere.intersect_ray(f32x4::splat(0.0), dir), Some(0.0));
+    }
+}
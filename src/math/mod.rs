@@ -1,17 +1,25 @@
-//! Linear algebra and trigonometry.
+//! Linear algebra, trigonometry, and integer geometry.
 
 mod angle;
+mod bounds;
 mod proj;
 mod quat;
+mod rect;
+mod spline;
+mod track;
 mod trans;
 
 use core::simd::f32x4;
 
 pub use angle::*;
+pub use bounds::*;
 #[cfg(not(test))]
 pub use proj::*;
 pub use quat::*;
+pub use rect::*;
+pub use spline::*;
 #[cfg(not(test))]
+pub use track::*;
 pub use trans::*;
 
 #[cfg(not(test))]
@@ -1,5 +1,6 @@
 //! Rotations in 3D space.
 
+use core::f32::consts::{FRAC_PI_2, PI, TAU};
 use core::ops::{Mul, MulAssign};
 
 use super::*;
@@ -35,6 +36,97 @@ impl Quaternion
         Self { vec }
     }
 
+    /// Creates and initializes a new quaternion from Euler angles, applying the
+    /// roll first, then the pitch, and finally the yaw, like the camera does.
+    ///
+    /// * `yaw`: Rotation around the vertical axis in radians.
+    /// * `pitch`: Rotation around the horizontal axis in radians, with negative
+    ///   values looking down.
+    /// * `roll`: Rotation around the view axis in radians.
+    ///
+    /// Returns the newly created quaternion.
+    pub fn from_euler(yaw: f32, pitch: f32, roll: f32) -> Self
+    {
+        let yaw = Self::from_axis_angle(f32x4::from_array([0.0, 1.0, 0.0, 0.0]), Angle::from(yaw));
+        let pitch = Self::from_axis_angle(f32x4::from_array([1.0, 0.0, 0.0, 0.0]), Angle::from(pitch));
+        let roll = Self::from_axis_angle(f32x4::from_array([0.0, 0.0, 1.0, 0.0]), Angle::from(roll));
+        roll * pitch * yaw
+    }
+
+    /// Extracts the Euler angles of this rotation as applied by
+    /// [`Self::from_euler`].
+    ///
+    /// Returns the yaw and roll between -pi and pi, and the pitch clamped
+    /// between -pi/2 and pi/2, all in radians.  Looking straight up or down
+    /// leaves the roll indistinguishable from the yaw, in which case the roll
+    /// is zero.
+    pub fn to_euler(self) -> (f32, f32, f32)
+    {
+        // Rotated axes, which are the rows of the rotation matrix.
+        let right = f32x4::from_array([1.0, 0.0, 0.0, 0.0]) * self;
+        let up = f32x4::from_array([0.0, 1.0, 0.0, 0.0]) * self;
+        let back = f32x4::from_array([0.0, 0.0, 1.0, 0.0]) * self;
+        let sin_pitch = (-back.y()).clamp(-1.0, 1.0);
+        let cos_pitch = (1.0 - sin_pitch * sin_pitch).sqrt();
+        let pitch = signed(Angle::from_sin_cos(sin_pitch, cos_pitch)).clamp(-FRAC_PI_2, FRAC_PI_2);
+        if cos_pitch < TOLERANCE {
+            let yaw = Angle::from_sin_cos(-right.z(), right.x());
+            return (signed(yaw), pitch, 0.0);
+        }
+        let yaw = Angle::from_sin_cos(back.x(), back.z());
+        let roll = Angle::from_sin_cos(right.y(), up.y());
+        (signed(yaw), pitch, signed(roll))
+    }
+
+    /// Interpolates between this and another rotation along the shortest path,
+    /// normalizing the result, which is cheaper than a spherical interpolation
+    /// and close enough for rotations that are not far apart.
+    ///
+    /// * `other`: Rotation to interpolate towards.
+    /// * `weight`: Weight of the other rotation, between 0 and 1.
+    ///
+    /// Returns a newly created quaternion with the results.
+    pub fn lerp(self, other: Self, weight: f32) -> Self
+    {
+        let dot = (self.vec * other.vec).to_array().iter().sum::<f32>();
+        let other = if dot < 0.0 { -other.vec } else { other.vec };
+        let vec = self.vec + (other - self.vec).mul_scalar(weight);
+        let Some(vec) = vec.normalize() else {
+            return Self::default();
+        };
+        Self { vec }
+    }
+
+    /// Interpolates between this and another rotation along the shortest path
+    /// at a constant angular velocity.
+    ///
+    /// * `other`: Rotation to interpolate towards.
+    /// * `weight`: Weight of the other rotation, between 0 and 1.
+    ///
+    /// Returns a newly created quaternion with the results.
+    pub fn slerp(self, other: Self, weight: f32) -> Self
+    {
+        let dot = (self.vec * other.vec).to_array().iter().sum::<f32>();
+        let (other, dot) = if dot < 0.0 {
+            (-other.vec, -dot)
+        } else {
+            (other.vec, dot)
+        };
+        let angle = Angle::from_cos(dot);
+        let (sin, _) = angle.sin_cos();
+        // Nearly identical rotations make the spherical weights unstable.
+        if sin < TOLERANCE {
+            return self.lerp(Self { vec: other }, weight);
+        }
+        let (sin0, _) = (angle * (1.0 - weight)).sin_cos();
+        let (sin1, _) = (angle * weight).sin_cos();
+        let vec = self.vec.mul_scalar(sin0 / sin) + other.mul_scalar(sin1 / sin);
+        let Some(vec) = vec.normalize() else {
+            return Self::default();
+        };
+        Self { vec }
+    }
+
     /// Computes the reciprocal of this quaternion.
     ///
     /// Returns a newly created quaternion with the results.
@@ -118,11 +210,23 @@ impl MulAssign for Quaternion
     }
 }
 
+/// Converts an angle to radians between -pi and pi.
+///
+/// * `angle`: Angle to convert.
+///
+/// Returns the converted value.
+fn signed(angle: Angle) -> f32
+{
+    let radians = f32::from(angle);
+    if radians > PI {
+        return radians - TAU;
+    }
+    radians
+}
+
 #[cfg(test)]
 mod tests
 {
-    use core::f32::consts::PI;
-
     use super::*;
 
     #[test]
@@ -144,6 +248,68 @@ mod tests
         expect_roughly_vec(actual.vec, expected);
     }
 
+    #[test]
+    fn from_euler()
+    {
+        let yaw = Quaternion::from_axis_angle(f32x4::from_array([0.0, 1.0, 0.0, 0.0]), Angle::from(0.5));
+        let pitch = Quaternion::from_axis_angle(f32x4::from_array([1.0, 0.0, 0.0, 0.0]), Angle::from(-0.25));
+        let actual = Quaternion::from_euler(0.5, -0.25, 0.0);
+        expect_roughly_vec(actual.vec, (pitch * yaw).vec);
+    }
+
+    #[test]
+    fn to_euler()
+    {
+        for (yaw, pitch, roll) in [(0.0, 0.0, 0.0),
+                                   (0.5, -0.25, 0.125),
+                                   (-2.5, 1.0, -3.0),
+                                   (3.0, -1.5, 1.0)]
+        {
+            let (actual_yaw, actual_pitch, actual_roll) = Quaternion::from_euler(yaw, pitch, roll).to_euler();
+            expect_roughly(actual_yaw, yaw);
+            expect_roughly(actual_pitch, pitch);
+            expect_roughly(actual_roll, roll);
+        }
+        // Looking straight down folds the roll into the yaw.
+        let (yaw, pitch, roll) = Quaternion::from_euler(0.5, -FRAC_PI_2, 0.25).to_euler();
+        expect_roughly(yaw, 0.75);
+        expect_roughly(pitch, -FRAC_PI_2);
+        expect_roughly(roll, 0.0);
+    }
+
+    #[test]
+    fn lerp()
+    {
+        let lhs = Quaternion::default();
+        let rhs = Quaternion { vec: f32x4::from_array([0.0, 0.0, 0.5f32.sqrt(), 0.5f32.sqrt()]) };
+        let actual = lhs.lerp(rhs, 0.5);
+        let expected = f32x4::from_array([0.0, 0.0, (PI / 8.0).sin(), (PI / 8.0).cos()]);
+        expect_roughly_vec(actual.vec, expected);
+        // The negated quaternion represents the same rotation, so the result must not
+        // take the long way around.
+        let rhs = Quaternion { vec: -rhs.vec };
+        let actual = lhs.lerp(rhs, 0.5);
+        expect_roughly_vec(actual.vec, expected);
+        let actual = lhs.lerp(rhs, 0.0);
+        expect_roughly_vec(actual.vec, lhs.vec);
+    }
+
+    #[test]
+    fn slerp()
+    {
+        let axis = f32x4::from_array([0.0, 0.0, 1.0, 0.0]);
+        let lhs = Quaternion::default();
+        let rhs = Quaternion::from_axis_angle(axis, Angle::from(PI * 2.0 / 3.0));
+        let actual = lhs.slerp(rhs, 0.25);
+        let expected = f32x4::from_array([0.0, 0.0, (PI / 12.0).sin(), (PI / 12.0).cos()]);
+        expect_roughly_vec(actual.vec, expected);
+        let rhs = Quaternion { vec: -rhs.vec };
+        let actual = lhs.slerp(rhs, 0.25);
+        expect_roughly_vec(actual.vec, expected);
+        let actual = lhs.slerp(lhs, 0.5);
+        expect_roughly_vec(actual.vec, lhs.vec);
+    }
+
     #[test]
     fn into_matrix()
     {
@@ -0,0 +1,259 @@
+//! Integer vectors and rectangles.
+//!
+//! Shared by everything that addresses discrete grids: tiles on the map,
+//! pixels in user interface layouts, and the rasterizer's clipping rectangles.
+//! Rectangles are agnostic to the direction of the vertical axis, so their
+//! position is always that of the corner with the lowest coordinates.
+
+use core::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+
+/// Integer 2D vector.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct IVec2
+{
+    /// Horizontal component.
+    pub x: i32,
+    /// Vertical component.
+    pub y: i32,
+}
+
+/// Integer axis aligned rectangle.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Rect
+{
+    /// Horizontal position of the edge with the lowest coordinate.
+    pub x: i32,
+    /// Vertical position of the edge with the lowest coordinate.
+    pub y: i32,
+    /// Width.
+    pub width: i32,
+    /// Height.
+    pub height: i32,
+}
+
+impl IVec2
+{
+    /// Creates and initializes a new vector.
+    ///
+    /// * `x`: Horizontal component.
+    /// * `y`: Vertical component.
+    ///
+    /// Returns the newly created vector.
+    pub const fn new(x: i32, y: i32) -> Self
+    {
+        Self { x, y }
+    }
+
+    /// Computes the component-wise minimum of this and another vector.
+    ///
+    /// * `other`: Vector to compare against.
+    ///
+    /// Returns the computed result.
+    pub fn min(self, other: Self) -> Self
+    {
+        Self { x: self.x.min(other.x),
+               y: self.y.min(other.y) }
+    }
+
+    /// Computes the component-wise maximum of this and another vector.
+    ///
+    /// * `other`: Vector to compare against.
+    ///
+    /// Returns the computed result.
+    pub fn max(self, other: Self) -> Self
+    {
+        Self { x: self.x.max(other.x),
+               y: self.y.max(other.y) }
+    }
+}
+
+impl Rect
+{
+    /// Creates and initializes a new rectangle.
+    ///
+    /// * `x`: Horizontal position of the edge with the lowest coordinate.
+    /// * `y`: Vertical position of the edge with the lowest coordinate.
+    /// * `width`: Width.
+    /// * `height`: Height.
+    ///
+    /// Returns the newly created rectangle.
+    pub const fn new(x: i32, y: i32, width: i32, height: i32) -> Self
+    {
+        Self { x, y, width, height }
+    }
+
+    /// Creates and initializes a new rectangle spanning two corners.
+    ///
+    /// * `min`: Corner with the lowest coordinates, inclusive.
+    /// * `max`: Corner with the highest coordinates, exclusive.
+    ///
+    /// Returns the newly created rectangle, which is empty if the maximum
+    /// corner isn't past the minimum corner on both axes.
+    pub fn from_corners(min: IVec2, max: IVec2) -> Self
+    {
+        Self { x: min.x,
+               y: min.y,
+               width: (max.x - min.x).max(0),
+               height: (max.y - min.y).max(0) }
+    }
+
+    /// Returns the corner with the lowest coordinates, which is inside this
+    /// rectangle unless it's empty.
+    pub fn min(&self) -> IVec2
+    {
+        IVec2::new(self.x, self.y)
+    }
+
+    /// Returns the corner with the highest coordinates, which is just outside
+    /// this rectangle.
+    pub fn max(&self) -> IVec2
+    {
+        IVec2::new(self.x + self.width, self.y + self.height)
+    }
+
+    /// Checks whether this rectangle contains no points.
+    pub fn is_empty(&self) -> bool
+    {
+        self.width <= 0 || self.height <= 0
+    }
+
+    /// Checks whether a point lies within this rectangle.
+    ///
+    /// * `point`: Point to check.
+    ///
+    /// Returns whether the point is inside.
+    pub fn contains(&self, point: IVec2) -> bool
+    {
+        (self.x .. self.x + self.width).contains(&point.x) && (self.y .. self.y + self.height).contains(&point.y)
+    }
+
+    /// Checks whether another rectangle lies entirely within this rectangle.
+    ///
+    /// * `other`: Rectangle to check.
+    ///
+    /// Returns whether the other rectangle is inside, which is always the case
+    /// for empty rectangles.
+    pub fn contains_rect(&self, other: Self) -> bool
+    {
+        other.is_empty() || self.intersect(other) == other
+    }
+
+    /// Computes the intersection between this and another rectangle.
+    ///
+    /// * `other`: Rectangle to intersect with.
+    ///
+    /// Returns the intersection, which may be empty.
+    pub fn intersect(self, other: Self) -> Self
+    {
+        Self::from_corners(self.min().max(other.min()), self.max().min(other.max()))
+    }
+}
+
+impl Add for IVec2
+{
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self
+    {
+        Self { x: self.x + other.x,
+               y: self.y + other.y }
+    }
+}
+
+impl AddAssign for IVec2
+{
+    fn add_assign(&mut self, other: Self)
+    {
+        *self = *self + other;
+    }
+}
+
+impl Sub for IVec2
+{
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self
+    {
+        Self { x: self.x - other.x,
+               y: self.y - other.y }
+    }
+}
+
+impl SubAssign for IVec2
+{
+    fn sub_assign(&mut self, other: Self)
+    {
+        *self = *self - other;
+    }
+}
+
+impl Neg for IVec2
+{
+    type Output = Self;
+
+    fn neg(self) -> Self
+    {
+        Self { x: -self.x, y: -self.y }
+    }
+}
+
+impl From<(usize, usize)> for IVec2
+{
+    fn from(pos: (usize, usize)) -> Self
+    {
+        Self { x: pos.0 as _,
+               y: pos.1 as _ }
+    }
+}
+
+impl Add<IVec2> for Rect
+{
+    type Output = Self;
+
+    fn add(self, offset: IVec2) -> Self
+    {
+        Self { x: self.x + offset.x,
+               y: self.y + offset.y,
+               ..self }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn ivec2_ops()
+    {
+        let vec = IVec2::new(3, -2) + IVec2::new(1, 4);
+        assert_eq!(vec, IVec2::new(4, 2));
+        assert_eq!(vec - IVec2::new(5, 5), IVec2::new(-1, -3));
+        assert_eq!(-vec, IVec2::new(-4, -2));
+        assert_eq!(vec.min(IVec2::new(5, 1)), IVec2::new(4, 1));
+        assert_eq!(IVec2::from((7, 9)), IVec2::new(7, 9));
+    }
+
+    #[test]
+    fn rect_intersect()
+    {
+        let rect = Rect::new(0, 0, 10, 10);
+        assert_eq!(rect.intersect(Rect::new(5, -5, 10, 10)), Rect::new(5, 0, 5, 5));
+        assert!(rect.intersect(Rect::new(10, 0, 5, 5)).is_empty());
+        assert!(rect.intersect(Rect::new(20, 20, 5, 5)).is_empty());
+        assert!(rect.contains_rect(Rect::new(2, 2, 8, 8)));
+        assert!(!rect.contains_rect(Rect::new(2, 2, 9, 8)));
+        assert!(rect.contains_rect(Rect::default()));
+    }
+
+    #[test]
+    fn rect_contains()
+    {
+        let rect = Rect::new(2, 3, 4, 5) + IVec2::new(1, 1);
+        assert!(rect.contains(IVec2::new(3, 4)));
+        assert!(rect.contains(IVec2::new(6, 8)));
+        assert!(!rect.contains(IVec2::new(7, 8)));
+        assert!(!rect.contains(IVec2::new(3, 9)));
+        assert_eq!(rect.max(), IVec2::new(7, 9));
+    }
+}
@@ -0,0 +1,257 @@
+//! Cubic splines.
+//!
+//! Smooth paths for camera flythroughs, patrol routes, and projectile arcs.
+//! Catmull-Rom splines [1] pass through all their control points, which makes
+//! them convenient for routes laid out by hand, whereas Bézier splines [2] only
+//! pass through every third control point and use the ones in between to shape
+//! the curve, which gives precise control over arcs.  Catmull-Rom segments are
+//! converted to Bézier segments so that both are evaluated the same way.
+//!
+//! Since the parameter of a cubic curve doesn't advance at a constant speed
+//! along it, splines also tabulate their arc length at evenly spaced parameters
+//! so that they can be sampled by the distance travelled instead.
+//!
+//! [1]: https://en.wikipedia.org/wiki/Cubic_Hermite_spline#Catmull%E2%80%93Rom_spline
+//! [2]: https://en.wikipedia.org/wiki/B%C3%A9zier_curve#Cubic_B%C3%A9zier_curves
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::*;
+
+/// Number of arc length samples per segment.
+const ARC_SAMPLES: usize = 32;
+
+/// Way in which the control points shape a spline.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SplineKind
+{
+    /// Passes through all control points.
+    CatmullRom,
+    /// Passes through every third control point, using the two in between as
+    /// handles.
+    Bezier,
+}
+
+/// Cubic spline.
+#[derive(Clone, Debug)]
+pub struct Spline
+{
+    /// Bézier control points, four per segment with the first and last shared
+    /// between adjacent segments.
+    points: Vec<f32x4>,
+    /// Arc length from the start at evenly spaced parameters.
+    arcs: Vec<f32>,
+}
+
+impl Spline
+{
+    /// Creates and initializes a new spline.
+    ///
+    /// * `kind`: Way in which the control points shape the spline.
+    /// * `points`: Control points.
+    ///
+    /// Returns the newly created spline.
+    ///
+    /// Panics if there are less than two control points, or if a Bézier spline
+    /// has a number of control points that isn't one more than a multiple of
+    /// three.
+    #[track_caller]
+    pub fn new(kind: SplineKind, points: Vec<f32x4>) -> Self
+    {
+        let points = match kind {
+            SplineKind::CatmullRom => {
+                assert!(points.len() >= 2,
+                        "Catmull-Rom splines require at least two control points");
+                let last = points.len() - 1;
+                let mut bezier = Vec::with_capacity(last * 3 + 1);
+                bezier.push(points[0]);
+                for idx in 0 .. last {
+                    // The tangent at each point is parallel to the line between its neighbors,
+                    // with the end points acting as their own missing neighbors.
+                    let prev = points[idx.saturating_sub(1)];
+                    let next = points[(idx + 2).min(last)];
+                    let (start, end) = (points[idx], points[idx + 1]);
+                    bezier.push(start + (end - prev).mul_scalar(1.0 / 6.0));
+                    bezier.push(end - (next - start).mul_scalar(1.0 / 6.0));
+                    bezier.push(end);
+                }
+                bezier
+            }
+            SplineKind::Bezier => {
+                assert!(points.len() >= 4 && points.len() % 3 == 1,
+                        "Bézier splines require three control points per segment plus one");
+                points
+            }
+        };
+        let mut this = Self { points,
+                              arcs: Vec::new() };
+        let count = this.segments() * ARC_SAMPLES;
+        let mut last = this.point(0.0);
+        let mut arc = 0.0;
+        this.arcs.reserve(count + 1);
+        this.arcs.push(0.0);
+        for idx in 1 ..= count {
+            let point = this.point(idx as f32 / ARC_SAMPLES as f32);
+            arc += (point - last).xyz0().len();
+            this.arcs.push(arc);
+            last = point;
+        }
+        this
+    }
+
+    /// Returns the number of segments, which is the range of the parameter.
+    pub fn segments(&self) -> usize
+    {
+        (self.points.len() - 1) / 3
+    }
+
+    /// Returns the approximate length of the whole spline.
+    pub fn length(&self) -> f32
+    {
+        self.arcs[self.arcs.len() - 1]
+    }
+
+    /// Computes a point on the spline.
+    ///
+    /// * `param`: Parameter, from 0 at the start to the number of segments at
+    ///   the end, clamped to that range.
+    ///
+    /// Returns the computed point.
+    pub fn point(&self, param: f32) -> f32x4
+    {
+        let (idx, weight) = self.segment(param);
+        let [p0, p1, p2, p3] = [0, 1, 2, 3].map(|offset| self.points[idx * 3 + offset]);
+        let inv = 1.0 - weight;
+        p0.mul_scalar(inv * inv * inv)
+        + p1.mul_scalar(3.0 * inv * inv * weight)
+        + p2.mul_scalar(3.0 * inv * weight * weight)
+        + p3.mul_scalar(weight * weight * weight)
+    }
+
+    /// Computes the direction of the spline at a point.
+    ///
+    /// * `param`: Parameter, from 0 at the start to the number of segments at
+    ///   the end, clamped to that range.
+    ///
+    /// Returns the computed derivative with respect to the parameter, whose
+    /// length is the speed at which the point moves.
+    pub fn tangent(&self, param: f32) -> f32x4
+    {
+        let (idx, weight) = self.segment(param);
+        let [p0, p1, p2, p3] = [0, 1, 2, 3].map(|offset| self.points[idx * 3 + offset]);
+        let inv = 1.0 - weight;
+        (p1 - p0).mul_scalar(3.0 * inv * inv)
+        + (p2 - p1).mul_scalar(6.0 * inv * weight)
+        + (p3 - p2).mul_scalar(3.0 * weight * weight)
+    }
+
+    /// Computes the parameter at which a given distance along the spline is
+    /// travelled.
+    ///
+    /// * `dist`: Distance from the start, clamped to the length of the spline.
+    ///
+    /// Returns the computed parameter.
+    pub fn param_at(&self, dist: f32) -> f32
+    {
+        let next = self.arcs.partition_point(|arc| *arc <= dist);
+        if next == 0 {
+            return 0.0;
+        }
+        if next == self.arcs.len() {
+            return self.segments() as f32;
+        }
+        let (arc0, arc1) = (self.arcs[next - 1], self.arcs[next]);
+        let weight = (dist - arc0) / (arc1 - arc0);
+        (next - 1) as f32 / ARC_SAMPLES as f32 + weight / ARC_SAMPLES as f32
+    }
+
+    /// Computes the point at a given distance along the spline, so that
+    /// advancing the distance at a constant rate moves at a constant speed.
+    ///
+    /// * `dist`: Distance from the start, clamped to the length of the spline.
+    ///
+    /// Returns the computed point.
+    pub fn point_at(&self, dist: f32) -> f32x4
+    {
+        self.point(self.param_at(dist))
+    }
+
+    /// Splits a parameter into a segment and the progress along it.
+    ///
+    /// * `param`: Parameter, clamped to the range of the spline.
+    ///
+    /// Returns the index of the segment and the progress along it, between 0
+    /// and 1.
+    fn segment(&self, param: f32) -> (usize, f32)
+    {
+        let last = self.segments() - 1;
+        let param = param.clamp(0.0, self.segments() as f32);
+        let idx = (param as usize).min(last);
+        (idx, param - idx as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use alloc::vec;
+
+    use super::*;
+
+    fn point(x: f32, y: f32) -> f32x4
+    {
+        f32x4::from_array([x, y, 0.0, 1.0])
+    }
+
+    #[test]
+    fn catmull_rom()
+    {
+        let points = vec![point(0.0, 0.0), point(1.0, 1.0), point(2.0, 0.0), point(3.0, 1.0)];
+        let spline = Spline::new(SplineKind::CatmullRom, points.clone());
+        assert_eq!(spline.segments(), 3);
+        for (idx, point) in points.into_iter().enumerate() {
+            expect_roughly_vec(spline.point(idx as f32), point);
+        }
+        // The tangent at an inner point is parallel to the line between its neighbors.
+        let tangent = spline.tangent(1.0);
+        expect_roughly(tangent[1], 0.0);
+        assert!(tangent[0] > 0.0);
+        expect_roughly_vec(spline.point(-1.0), point(0.0, 0.0));
+        expect_roughly_vec(spline.point(4.0), point(3.0, 1.0));
+    }
+
+    #[test]
+    fn bezier()
+    {
+        let points = vec![point(0.0, 0.0), point(0.0, 2.0), point(2.0, 2.0), point(2.0, 0.0)];
+        let spline = Spline::new(SplineKind::Bezier, points);
+        assert_eq!(spline.segments(), 1);
+        expect_roughly_vec(spline.point(0.0), point(0.0, 0.0));
+        expect_roughly_vec(spline.point(0.5), point(1.0, 1.5));
+        expect_roughly_vec(spline.point(1.0), point(2.0, 0.0));
+        expect_roughly_vec(spline.tangent(0.5), f32x4::from_array([3.0, 0.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn arc_length()
+    {
+        // A straight line whose handles bunch the parameter up towards the ends.
+        let points = vec![point(0.0, 0.0), point(3.0, 0.0), point(1.0, 0.0), point(4.0, 0.0)];
+        let spline = Spline::new(SplineKind::Bezier, points);
+        expect_roughly(spline.length(), 4.0);
+        for dist in [0.0, 1.0, 2.5, 4.0] {
+            expect_roughly(spline.point_at(dist)[0], dist);
+        }
+        expect_roughly(spline.param_at(-1.0), 0.0);
+        expect_roughly(spline.param_at(5.0), 1.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bezier_points()
+    {
+        Spline::new(SplineKind::Bezier, vec![point(0.0, 0.0); 5]);
+    }
+}
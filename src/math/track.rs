@@ -0,0 +1,207 @@
+//! Keyframed transformation tracks.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::trans::Transform;
+use super::*;
+
+/// Curve shaping the interpolation between two keyframes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Easing
+{
+    /// Constant speed.
+    #[default]
+    Linear,
+    /// Starts slow and speeds up.
+    In,
+    /// Starts fast and slows down.
+    Out,
+    /// Starts and ends slow.
+    InOut,
+    /// Holds the keyframe until the next one is reached.
+    Step,
+}
+
+/// Transformation at a point in time.
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe
+{
+    /// Time in seconds from the beginning of the track.
+    pub time: f32,
+    /// Position.
+    pub pos: f32x4,
+    /// Rotation.
+    pub rot: Quaternion,
+    /// Scale.
+    pub scale: f32,
+    /// Curve shaping the interpolation towards the next keyframe.
+    pub easing: Easing,
+}
+
+/// Sequence of keyframes animating a transformation.
+#[derive(Debug)]
+pub struct Track
+{
+    /// Keyframes sorted by time.
+    keys: Vec<Keyframe>,
+    /// Whether the track starts over once it ends.
+    looping: bool,
+}
+
+impl Easing
+{
+    /// Shapes the progress between two keyframes.
+    ///
+    /// * `weight`: Linear progress, between 0 and 1.
+    ///
+    /// Returns the shaped progress.
+    pub fn apply(self, weight: f32) -> f32
+    {
+        let weight = weight.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => weight,
+            Self::In => weight * weight,
+            Self::Out => weight * (2.0 - weight),
+            Self::InOut => weight * weight * (3.0 - 2.0 * weight),
+            Self::Step => 0.0,
+        }
+    }
+}
+
+impl Track
+{
+    /// Creates and initializes a new track.
+    ///
+    /// * `keys`: Keyframes, which are sorted by time.
+    /// * `looping`: Whether the track starts over once it ends.
+    ///
+    /// Returns the newly created track.
+    ///
+    /// Panics if no keyframes are provided.
+    #[track_caller]
+    pub fn new(mut keys: Vec<Keyframe>, looping: bool) -> Self
+    {
+        assert!(!keys.is_empty(), "Tracks require at least one keyframe");
+        keys.sort_by(|key0, key1| key0.time.total_cmp(&key1.time));
+        Self { keys, looping }
+    }
+
+    /// Returns the time of the last keyframe in seconds.
+    pub fn duration(&self) -> f32
+    {
+        self.keys[self.keys.len() - 1].time
+    }
+
+    /// Returns whether a non-looping track has finished playing.
+    ///
+    /// * `time`: Time in seconds since the track started playing.
+    pub fn is_finished(&self, time: f32) -> bool
+    {
+        !self.looping && time >= self.duration()
+    }
+
+    /// Computes the transformation at a point in time.
+    ///
+    /// * `time`: Time in seconds since the track started playing.
+    ///
+    /// Returns the interpolated transformation.
+    pub fn sample(&self, time: f32) -> Transform
+    {
+        let (pos, rot, scale) = self.interpolate(time);
+        Transform::from_components(pos, rot, scale)
+    }
+
+    /// Interpolates the keyframes surrounding a point in time.
+    ///
+    /// * `time`: Time in seconds since the track started playing.
+    ///
+    /// Returns the interpolated position, rotation, and scale.
+    fn interpolate(&self, time: f32) -> (f32x4, Quaternion, f32)
+    {
+        let duration = self.duration();
+        let time = if self.looping && duration > 0.0 {
+            let time = time % duration;
+            if time < 0.0 {
+                time + duration
+            } else {
+                time
+            }
+        } else {
+            time
+        };
+        let next = self.keys.partition_point(|key| key.time <= time);
+        if next == 0 || next == self.keys.len() {
+            let key = self.keys[next.saturating_sub(1)];
+            return (key.pos, key.rot, key.scale);
+        }
+        let key0 = self.keys[next - 1];
+        let key1 = self.keys[next];
+        let weight = key0.easing.apply((time - key0.time) / (key1.time - key0.time));
+        let pos = key0.pos + (key1.pos - key0.pos).mul_scalar(weight);
+        let rot = key0.rot.slerp(key1.rot, weight);
+        let scale = key0.scale + (key1.scale - key0.scale) * weight;
+        (pos, rot, scale)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use alloc::vec;
+
+    use super::*;
+
+    fn key(time: f32, x: f32, easing: Easing) -> Keyframe
+    {
+        Keyframe { time,
+                   pos: f32x4::from_array([x, 0.0, 0.0, 1.0]),
+                   rot: Quaternion::default(),
+                   scale: 1.0 + x,
+                   easing }
+    }
+
+    #[test]
+    fn easing()
+    {
+        expect_roughly(Easing::Linear.apply(0.25), 0.25);
+        expect_roughly(Easing::In.apply(0.5), 0.25);
+        expect_roughly(Easing::Out.apply(0.5), 0.75);
+        expect_roughly(Easing::InOut.apply(0.25), 0.15625);
+        expect_roughly(Easing::InOut.apply(0.5), 0.5);
+        expect_roughly(Easing::Step.apply(0.99), 0.0);
+        expect_roughly(Easing::In.apply(2.0), 1.0);
+    }
+
+    #[test]
+    fn interpolate()
+    {
+        let track = Track::new(vec![key(2.0, 4.0, Easing::Linear), key(0.0, 0.0, Easing::In)], false);
+        let (pos, _, scale) = track.interpolate(1.0);
+        expect_roughly_vec(pos, f32x4::from_array([1.0, 0.0, 0.0, 1.0]));
+        expect_roughly(scale, 2.0);
+        let (pos, ..) = track.interpolate(-1.0);
+        expect_roughly(pos[0], 0.0);
+        let (pos, ..) = track.interpolate(3.0);
+        expect_roughly(pos[0], 4.0);
+        assert!(track.is_finished(3.0));
+        let trans = track.sample(1.0);
+        expect_roughly_vec(trans.position(), f32x4::from_array([1.0, 0.0, 0.0, 1.0]));
+        expect_roughly(trans.scale(), 2.0);
+    }
+
+    #[test]
+    fn interpolate_looping()
+    {
+        let keys = vec![key(0.0, 0.0, Easing::Linear),
+                        key(1.0, 2.0, Easing::Step),
+                        key(2.0, 0.0, Easing::Linear)];
+        let track = Track::new(keys, true);
+        let (pos, ..) = track.interpolate(2.5);
+        expect_roughly(pos[0], 1.0);
+        let (pos, ..) = track.interpolate(-0.5);
+        expect_roughly(pos[0], 2.0);
+        assert!(!track.is_finished(10.0));
+    }
+}
@@ -41,6 +41,13 @@ impl Transform
         Self { pos, rot, scale }
     }
 
+    /// Returns the position component of this transformation.
+    #[inline]
+    pub fn position(self) -> f32x4
+    {
+        self.pos
+    }
+
     /// Returns the roetation component of this transformation.
     #[cfg(not(test))]
     #[inline]
@@ -49,6 +56,13 @@ impl Transform
         self.rot
     }
 
+    /// Returns the scale component of this transformation.
+    #[inline]
+    pub fn scale(self) -> f32
+    {
+        self.scale
+    }
+
     /// Converts this transformation into a matrix with the same properties.
     ///
     /// Returns a newly created matrix with the results.
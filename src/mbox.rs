@@ -14,9 +14,9 @@ use core::hint::spin_loop;
 use core::mem::{align_of, size_of, size_of_val};
 use core::slice::from_raw_parts as slice_from_raw_parts;
 
-use crate::cpu::{cleanup_cache, invalidate_cache};
+use crate::dma::DmaBuffer;
 use crate::sync::{Lazy, Lock};
-use crate::{to_dma, PERRY_RANGE};
+use crate::PERRY_RANGE;
 
 /// Assembles a buffer with the properties specified on input, sends it through
 /// the Mailbox interface, and populates the outputs with the returned
@@ -92,8 +92,8 @@ pub static MBOX: Lazy<Lock<Mailbox>> = Lazy::new(Mailbox::new);
 #[derive(Debug)]
 pub struct Mailbox
 {
-    /// Private zero-sized type to prevent public initialization.
-    _data: (),
+    /// Buffer shared with the firmware.
+    buf: DmaBuffer<[u32; BUF_SIZE / 4]>,
 }
 
 /// Message buffer.
@@ -165,7 +165,7 @@ impl Mailbox
     /// Returns the newly created driver.
     fn new() -> Lock<Self>
     {
-        let this = Self { _data: () };
+        let this = Self { buf: DmaBuffer::new([0; BUF_SIZE / 4]) };
         Lock::new(this)
     }
 
@@ -181,18 +181,19 @@ impl Mailbox
         let code = unsafe { msg.header.code };
         assert!(code == REQUEST_CODE,
                 "Attempted to deliver a message to the firmware that is not a request");
-        let buf = unsafe { &mut msg.byte_view };
+        *self.buf = unsafe { msg.int_view };
         while unsafe { OUTBOX_STATUS.read_volatile() } & FULL_STATUS != 0 {
             spin_loop()
         }
-        let data = to_dma(buf.as_ptr() as usize) as u32 | 0x8;
-        cleanup_cache(buf);
+        let lease = self.buf.lend();
+        let data = lease.addr() as u32 | 0x8;
         unsafe { OUTBOX_DATA.write_volatile(data) };
         while unsafe { INBOX_STATUS.read_volatile() } & EMPTY_STATUS != 0 {
             spin_loop()
         }
         unsafe { INBOX_DATA.read_volatile() }; // Don't care about this value, just reading it to empty the inbox.
-        invalidate_cache(buf);
+        drop(lease);
+        msg.int_view = *self.buf;
         let code = unsafe { msg.header.code };
         assert!(code == SUCCESS_CODE,
                 "Firmware reply contains an unexpected code: 0x{code:X}");
@@ -238,30 +239,28 @@ impl Message
         unsafe { self.int_view[(idx + 3) / 4] = END_TAG };
     }
 
-    // Commenting this out to prevent dead code warnings as well as because this has
-    // been needed in the past and might be needed in the future.
-    // Finds a property by its tag.
-    //
-    // * `tag`: Property tag to search for.
-    //
-    // Returns the property.
-    //
-    // Panics if there's no property with the specified tag in the message.
-    // #[track_caller]
-    // pub fn find_property<I: Copy, O: Copy>(&mut self, tag: u32) -> Property<I, O>
-    // {
-    // let code = unsafe { self.header.code };
-    // assert!(code == SUCCESS_CODE,
-    // "Message was either not parsed by the firmware or it returned an error (code:
-    // 0x{code:X})"); Look for the requested tag.
-    // let mut idx = 8;
-    // while unsafe { self.int_view[idx / 4] } != tag {
-    // assert!(unsafe { self.int_view[idx / 4] } != END_TAG,
-    // "Tag 0x{tag:X} not found in message");
-    // idx += ((unsafe { self.int_view[idx / 4 + 1] } as usize + 0x3) & !0x3) + 12;
-    // }
-    // Property::from_bytes(unsafe { &self.byte_view[idx .. idx +
-    // size_of::<Property<I, O>>()] }) }
+    /// Finds a property by its tag.
+    ///
+    /// * `tag`: Property tag to search for.
+    ///
+    /// Returns the property.
+    ///
+    /// Panics if there's no property with the specified tag in the message.
+    #[track_caller]
+    pub fn find_property<I: Copy, O: Copy>(&mut self, tag: u32) -> Property<I, O>
+    {
+        let code = unsafe { self.header.code };
+        assert!(code == SUCCESS_CODE,
+                "Message was either not parsed by the firmware or it returned an error (code: 0x{code:X})");
+        // Look for the requested tag.
+        let mut idx = 8;
+        while unsafe { self.int_view[idx / 4] } != tag {
+            assert!(unsafe { self.int_view[idx / 4] } != END_TAG,
+                    "Tag 0x{tag:X} not found in message");
+            idx += ((unsafe { self.int_view[idx / 4 + 1] } as usize + 0x3) & !0x3) + 12;
+        }
+        Property::from_bytes(unsafe { &self.byte_view[idx .. idx + size_of::<Property<I, O>>()] })
+    }
 }
 
 impl<I: Copy, O: Copy> Property<I, O>
@@ -288,26 +287,24 @@ impl<I: Copy, O: Copy> Property<I, O>
         Self { input }
     }
 
-    // Commenting this out to prevent dead code warnings as well as because this has
-    // been needed in the past and might be needed in the future. Creates and
-    // initializes a new property from its byte representation.
-    //
-    // * `bytes`: Byte representation of the property.
-    //
-    // Returns the newly created property.
-    //
-    // Panics if the alignment of either the request or response types is not
-    // supported or the length of the slice doesn't match the size of the property
-    // being created. #[track_caller]
-    // fn from_bytes(bytes: &[u8]) -> Self
-    // {
-    // let align = align_of::<Self>();
-    // assert!(align == 4, "Property has an unsupported alignment");
-    // let size = size_of::<Self>();
-    // assert!(bytes.len() == size,
-    // "Slice size doesn't match the property's size");
-    // unsafe { *(bytes.as_ptr() as *const Self) }
-    // }
+    /// Creates and initializes a new property from its byte representation.
+    ///
+    /// * `bytes`: Byte representation of the property.
+    ///
+    /// Returns the newly created property.
+    ///
+    /// Panics if the alignment of either the request or response types is not
+    /// supported or the length of the slice doesn't match the size of the
+    /// property being created.
+    #[track_caller]
+    fn from_bytes(bytes: &[u8]) -> Self
+    {
+        let align = align_of::<Self>();
+        assert!(align == 4, "Property has an unsupported alignment");
+        let size = size_of::<Self>();
+        assert!(bytes.len() == size, "Slice size doesn't match the property's size");
+        unsafe { *(bytes.as_ptr() as *const Self) }
+    }
 
     /// Returns this property's tag.
     fn tag(&self) -> u32
@@ -315,25 +312,22 @@ impl<I: Copy, O: Copy> Property<I, O>
         unsafe { self.header.tag }
     }
 
-    // Commenting this out to prevent dead code warnings as well as because this has
-    // been needed in the past and might be needed in the future. Returns this
-    // property's payload.
-    //
-    // Panics if this is not a response.
-    // #[track_caller]
-    // pub fn payload(&self) -> O
-    // {
-    // let resp_size = unsafe { self.header.resp_size };
-    // let tag = unsafe { self.header.tag };
-    // assert!(resp_size & 0x80000000 != 0,
-    // "No response for property with tag 0x{tag:X}");
-    // let tag = unsafe { self.header.tag };
-    // let resp_size = resp_size & !0x80000000;
-    // let buf_size = unsafe { self.header.buf_size };
-    // assert!(resp_size <= buf_size,
-    // "Response to tag 0x{tag:X} is truncated (capacity: {buf_size}, size:
-    // {resp_size})"); unsafe { self.output.payload }
-    // }
+    /// Returns this property's payload.
+    ///
+    /// Panics if this is not a response.
+    #[track_caller]
+    pub fn payload(&self) -> O
+    {
+        let resp_size = unsafe { self.header.resp_size };
+        let tag = unsafe { self.header.tag };
+        assert!(resp_size & 0x80000000 != 0,
+                "No response for property with tag 0x{tag:X}");
+        let resp_size = resp_size & !0x80000000;
+        let buf_size = unsafe { self.header.buf_size };
+        assert!(resp_size <= buf_size,
+                "Response to tag 0x{tag:X} is truncated (capacity: {buf_size}, size: {resp_size})");
+        unsafe { self.output.payload }
+    }
 
     /// Returns a byte representation of this property.
     fn bytes(&self) -> &[u8]
@@ -0,0 +1,186 @@
+//! Memory map.
+//!
+//! The boot code only maps enough of the cached range for the drivers required
+//! to query the firmware to initialize.  Once the split of memory between the
+//! ARM cores and the video core is known, the rest of the memory available to
+//! the ARM cores is mapped into the cached range and handed to the allocator,
+//! so the same kernel runs regardless of the amount of installed memory or the
+//! memory reserved for the video core.  Also translates virtual addresses to
+//! addresses from the perspective of the DMA controller, which can only access
+//! the first gigabyte of memory as described in the BCM2711 peripherals
+//! datasheet [1], through a table of mappings, as well as to the physical
+//! addresses used by the bus masters that can access all of it.
+//!
+//! [1]: https://datasheets.raspberrypi.com/bcm2711/bcm2711-peripherals.pdf
+
+use core::arch::asm;
+use core::cmp::min;
+use core::ops::Range;
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::alloc::CACHED_REGION;
+use crate::board::INFO;
+use crate::cpu::COUNT as CPU_COUNT;
+use crate::sync::Lazy;
+use crate::{CACHED_RANGE, PERRY_RANGE, STACK_RANGES, UNCACHED_RANGE};
+
+/// Physical address of the memory mapped to the uncached range.
+const UNCACHED_PHYS: usize = 0x200000;
+/// Physical address of the memory mapped to the cached range.
+const CACHED_PHYS: usize = 0x2000000;
+/// Physical address of the memory mapped to the stack of the last core, with
+/// the stacks of the remaining cores following in reverse order.
+const STACK_PHYS: usize = 0x1800000;
+/// Size of the memory reserved for each stack.
+const STACK_SIZE: usize = 0x200000;
+/// Address of the first gigabyte of memory from the perspective of the DMA
+/// controller.
+const DMA_RAM_BASE: usize = 0xC0000000;
+/// Address of the peripherals from the perspective of the DMA controller.
+const DMA_PERRY_BASE: usize = 0x7C000000;
+/// Amount of memory accessible by the DMA controller.
+const DMA_RAM_SIZE: usize = 0x40000000;
+/// Size of the blocks mapped by the cached range's translation table.
+const BLOCK_SIZE: usize = 0x200000;
+/// Descriptor template of the cached range's blocks, marking them as normal
+/// cacheable memory that is never executable.
+const BLOCK_ATTRS: u64 = 0x30 << 48 | 0x721;
+/// Index of the cached range in the mapping table.
+const CACHED_IDX: usize = 1;
+
+/// Global memory map instance.
+pub static MMU: Lazy<Mmu> = Lazy::new(Mmu::new);
+
+/// Table of mappings accessible by the DMA controller.
+static MAPPINGS: [Mapping; 3 + CPU_COUNT] =
+    [Mapping::new(&UNCACHED_RANGE, DMA_RAM_BASE + UNCACHED_PHYS),
+     Mapping::new(&CACHED_RANGE, DMA_RAM_BASE + CACHED_PHYS),
+     Mapping::new(&PERRY_RANGE, DMA_PERRY_BASE),
+     Mapping::new(&STACK_RANGES[0], DMA_RAM_BASE + STACK_PHYS + STACK_SIZE * 3),
+     Mapping::new(&STACK_RANGES[1], DMA_RAM_BASE + STACK_PHYS + STACK_SIZE * 2),
+     Mapping::new(&STACK_RANGES[2], DMA_RAM_BASE + STACK_PHYS + STACK_SIZE),
+     Mapping::new(&STACK_RANGES[3], DMA_RAM_BASE + STACK_PHYS)];
+
+extern "C" {
+    /// Translation table of the cached range, defined in the boot code.
+    static mut heap_tt: [u64; 512];
+}
+
+/// Memory map.
+#[derive(Debug)]
+pub struct Mmu
+{
+    /// Cached range after mapping all the memory available to the ARM cores.
+    cached: Range<usize>,
+}
+
+/// Translation of a virtual range to addresses from the perspective of the DMA
+/// controller.
+#[derive(Debug)]
+struct Mapping
+{
+    /// Start of the virtual range.
+    start: usize,
+    /// End of the virtual range, which grows as memory is mapped.
+    end: AtomicUsize,
+    /// Start of the range from the perspective of the DMA controller.
+    dma: usize,
+}
+
+impl Mmu
+{
+    /// Maps the memory available to the ARM cores that the boot code left
+    /// unmapped and hands it to the allocator.
+    ///
+    /// Returns the newly created memory map.
+    ///
+    /// Panics if the memory available to the ARM cores doesn't cover the
+    /// memory mapped by the boot code.
+    #[track_caller]
+    fn new() -> Self
+    {
+        let arm = &INFO.arm_memory;
+        let boot_end = CACHED_PHYS + CACHED_RANGE.len();
+        // Memory beyond the reach of the DMA controller isn't mapped, as
+        // drivers assume that any cached memory can be lent to it.
+        let phys_end = min(arm.end, DMA_RAM_SIZE) & !(BLOCK_SIZE - 1);
+        assert!(arm.start == 0 && phys_end >= boot_end,
+                "ARM memory range 0x{:X}..0x{:X} doesn't cover the boot mappings up to 0x{boot_end:X}",
+                arm.start,
+                arm.end);
+        let tt = addr_of_mut!(heap_tt) as *mut u64;
+        for phys in (boot_end .. phys_end).step_by(BLOCK_SIZE) {
+            let idx = (phys - CACHED_PHYS) / BLOCK_SIZE;
+            unsafe { tt.add(idx).write_volatile(phys as u64 | BLOCK_ATTRS) };
+        }
+        // Invalid descriptors are never cached by the TLBs, so making the new
+        // descriptors visible to the table walkers of all cores is enough.
+        unsafe { asm!("dsb ish", "isb", options(nostack, preserves_flags)) };
+        let end = CACHED_RANGE.start + phys_end - CACHED_PHYS;
+        MAPPINGS[CACHED_IDX].end.store(end, Ordering::Release);
+        unsafe { CACHED_REGION.lock().extend(end) };
+        Self { cached: CACHED_RANGE.start .. end }
+    }
+
+    /// Returns the cached range covering all the memory available to the ARM
+    /// cores.
+    pub fn cached_range(&self) -> Range<usize>
+    {
+        self.cached.clone()
+    }
+}
+
+impl Mapping
+{
+    /// Creates and initializes a new mapping.
+    ///
+    /// * `range`: Virtual range.
+    /// * `dma`: Start of the range from the perspective of the DMA controller.
+    ///
+    /// Returns the newly created mapping.
+    const fn new(range: &Range<usize>, dma: usize) -> Self
+    {
+        Self { start: range.start,
+               end: AtomicUsize::new(range.end),
+               dma }
+    }
+}
+
+/// Converts the specified virtual address to a physical address from the
+/// perspective of the DMA controller.
+///
+/// * `addr`: Address to convert.
+///
+/// Returns the converted address.
+///
+/// Panics if the requested address is not accessible by the DMA controller.
+#[track_caller]
+pub fn to_dma(addr: usize) -> usize
+{
+    for mapping in &MAPPINGS {
+        if (mapping.start .. mapping.end.load(Ordering::Acquire)).contains(&addr) {
+            return addr - mapping.start + mapping.dma;
+        }
+    }
+    panic!("Requested address is either not mapped or not accessible by the DMA controller: 0x{addr:X}");
+}
+
+/// Converts the specified virtual address of some memory to its physical
+/// address, which is what bus masters on the system bus, such as the Ethernet
+/// controller, address memory with.
+///
+/// * `addr`: Address to convert.
+///
+/// Returns the converted address.
+///
+/// Panics if the requested address is not mapped to memory accessible by the
+/// DMA controller.
+#[track_caller]
+pub fn to_phys(addr: usize) -> usize
+{
+    let dma = to_dma(addr);
+    assert!((DMA_RAM_BASE .. DMA_RAM_BASE + DMA_RAM_SIZE).contains(&dma),
+            "Requested address is not mapped to memory: 0x{addr:X}");
+    dma - DMA_RAM_BASE
+}
@@ -0,0 +1,378 @@
+//! DHCP client.
+//!
+//! Configures the interface with a lease obtained from the local network's
+//! DHCP server as described in RFC 2131 [1], using the options from RFC 2132
+//! [2].  Only the exchange that obtains a new lease is implemented, so leases
+//! are renewed by running the whole exchange again, and the server is always
+//! asked to broadcast its replies, since the interface doesn't accept
+//! datagrams addressed to it until it's configured.  Besides the interface
+//! configuration, the lease carries the address of the boot server, which
+//! development setups point at the machine serving the kernel and the assets.
+//!
+//! [1]: https://www.rfc-editor.org/rfc/rfc2131
+//! [2]: https://www.rfc-editor.org/rfc/rfc2132
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+#[cfg(not(test))]
+use core::pin::pin;
+
+#[cfg(not(test))]
+use super::{Config, Error as NetError, UdpSocket, BROADCAST_IP, NET};
+use super::{Ipv4Address, MacAddress};
+#[cfg(not(test))]
+use crate::clock::now_micros;
+#[cfg(not(test))]
+use crate::sched::timeout;
+
+/// Server port.
+#[cfg(not(test))]
+const SERVER_PORT: u16 = 67;
+/// Client port.
+#[cfg(not(test))]
+const CLIENT_PORT: u16 = 68;
+/// Time in milliseconds to wait for an answer before retransmitting.
+#[cfg(not(test))]
+const RETRY_TIMEOUT: u64 = 2000;
+/// Number of times a message is sent before giving up.
+#[cfg(not(test))]
+const ATTEMPTS: usize = 4;
+/// Size of the fixed part of a message, up to and including the magic cookie.
+const FIXED_LEN: usize = 240;
+/// Magic cookie preceding the options.
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+/// Boot request operation code.
+const OP_REQUEST: u8 = 1;
+/// Boot reply operation code.
+const OP_REPLY: u8 = 2;
+/// Ethernet hardware type.
+const HTYPE_ETHERNET: u8 = 1;
+/// Flag asking the server to broadcast its replies.
+const FLAG_BROADCAST: u16 = 0x8000;
+/// Padding option.
+const OPT_PAD: u8 = 0;
+/// Subnet mask option.
+const OPT_NETMASK: u8 = 1;
+/// Router option.
+const OPT_ROUTER: u8 = 3;
+/// Requested address option.
+const OPT_REQUESTED: u8 = 50;
+/// Lease time option.
+const OPT_LEASE_TIME: u8 = 51;
+/// Message type option.
+const OPT_KIND: u8 = 53;
+/// Server identifier option.
+const OPT_SERVER: u8 = 54;
+/// Parameter request list option.
+const OPT_PARAMS: u8 = 55;
+/// End option.
+const OPT_END: u8 = 255;
+/// Discover message type.
+const DHCP_DISCOVER: u8 = 1;
+/// Offer message type.
+const DHCP_OFFER: u8 = 2;
+/// Request message type.
+const DHCP_REQUEST: u8 = 3;
+/// Acknowledgement message type.
+const DHCP_ACK: u8 = 5;
+/// Negative acknowledgement message type.
+const DHCP_NAK: u8 = 6;
+
+/// DHCP message, with absent addresses and times left as zeros.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Dhcp
+{
+    /// Message type.
+    pub kind: u8,
+    /// Transaction identifier chosen by the client.
+    pub xid: u32,
+    /// Hardware address of the client.
+    pub mac: MacAddress,
+    /// Address offered or assigned to the client.
+    pub yours: Ipv4Address,
+    /// Address of the boot server.
+    pub next_server: Ipv4Address,
+    /// Address requested by the client.
+    pub requested: Ipv4Address,
+    /// Identifier of the server, which is its address.
+    pub server: Ipv4Address,
+    /// Mask of the local network.
+    pub netmask: Ipv4Address,
+    /// Router that packets to other networks are sent through.
+    pub router: Ipv4Address,
+    /// Duration of the lease in seconds.
+    pub lease_time: u32,
+}
+
+/// Lease obtained from a DHCP server.
+#[cfg(not(test))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Lease
+{
+    /// Configuration that the interface was given.
+    pub config: Config,
+    /// Address of the boot server, or of the DHCP server if the lease doesn't
+    /// name one.
+    pub server: Ipv4Address,
+    /// Duration of the lease in seconds.
+    pub time: u32,
+}
+
+/// Errors that can occur when obtaining a lease.
+#[cfg(not(test))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DhcpError
+{
+    /// A message couldn't be sent.
+    Net(NetError),
+    /// Another client is already bound to the client port.
+    Busy,
+    /// No server answered.
+    Timeout,
+    /// The server refused the requested address.
+    Refused,
+}
+
+impl Dhcp
+{
+    /// Parses a message.
+    ///
+    /// * `msg`: Message to parse.
+    ///
+    /// Returns the parsed message, or `None` if it's malformed or not an
+    /// Ethernet DHCP message.
+    pub fn parse(msg: &[u8]) -> Option<Self>
+    {
+        if msg.len() < FIXED_LEN
+           || msg[0] != OP_REQUEST && msg[0] != OP_REPLY
+           || msg[1] != HTYPE_ETHERNET
+           || msg[2] != 6
+           || msg[236 .. 240] != MAGIC_COOKIE
+        {
+            return None;
+        }
+        let mut dhcp = Self { xid: u32::from_be_bytes(msg[4 .. 8].try_into().unwrap()),
+                              mac: msg[28 .. 34].try_into().unwrap(),
+                              yours: msg[16 .. 20].try_into().unwrap(),
+                              next_server: msg[20 .. 24].try_into().unwrap(),
+                              ..Self::default() };
+        let mut opts = &msg[FIXED_LEN ..];
+        loop {
+            match *opts {
+                [OPT_END, ..] => break,
+                [OPT_PAD, ref rest @ ..] => opts = rest,
+                [code, len, ref rest @ ..] if rest.len() >= len as usize => {
+                    let (val, rest) = rest.split_at(len as usize);
+                    let addr = val.try_into().ok();
+                    match code {
+                        OPT_KIND if len == 1 => dhcp.kind = val[0],
+                        OPT_NETMASK => dhcp.netmask = addr?,
+                        // Only the first of the listed routers is used.
+                        OPT_ROUTER => dhcp.router = val.get(.. 4)?.try_into().unwrap(),
+                        OPT_REQUESTED => dhcp.requested = addr?,
+                        OPT_SERVER => dhcp.server = addr?,
+                        OPT_LEASE_TIME => dhcp.lease_time = u32::from_be_bytes(val.try_into().ok()?),
+                        _ => (),
+                    }
+                    opts = rest;
+                }
+                _ => return None,
+            }
+        }
+        (dhcp.kind != 0).then_some(dhcp)
+    }
+
+    /// Appends this message to a buffer, as a request if it's of a type sent
+    /// by clients or as a reply otherwise.
+    ///
+    /// * `buf`: Buffer to append to.
+    pub fn emit(&self, buf: &mut Vec<u8>)
+    {
+        let request = matches!(self.kind, DHCP_DISCOVER | DHCP_REQUEST);
+        buf.extend_from_slice(&[if request { OP_REQUEST } else { OP_REPLY }, HTYPE_ETHERNET, 6, 0]);
+        buf.extend_from_slice(&self.xid.to_be_bytes());
+        buf.extend_from_slice(&[0, 0]);
+        buf.extend_from_slice(&FLAG_BROADCAST.to_be_bytes());
+        buf.extend_from_slice(&[0; 4]);
+        buf.extend_from_slice(&self.yours);
+        buf.extend_from_slice(&self.next_server);
+        buf.extend_from_slice(&[0; 4]);
+        buf.extend_from_slice(&self.mac);
+        // The rest of the hardware address field, followed by the unused
+        // server host name and boot file name fields.
+        buf.extend_from_slice(&[0; 10 + 64 + 128]);
+        buf.extend_from_slice(&MAGIC_COOKIE);
+        buf.extend_from_slice(&[OPT_KIND, 1, self.kind]);
+        for (code, addr) in [(OPT_REQUESTED, self.requested),
+                             (OPT_SERVER, self.server),
+                             (OPT_NETMASK, self.netmask),
+                             (OPT_ROUTER, self.router)]
+        {
+            if addr != [0; 4] {
+                buf.extend_from_slice(&[code, 4]);
+                buf.extend_from_slice(&addr);
+            }
+        }
+        if self.lease_time != 0 {
+            buf.extend_from_slice(&[OPT_LEASE_TIME, 4]);
+            buf.extend_from_slice(&self.lease_time.to_be_bytes());
+        }
+        if request {
+            buf.extend_from_slice(&[OPT_PARAMS, 4, OPT_NETMASK, OPT_ROUTER, OPT_LEASE_TIME, OPT_SERVER]);
+        }
+        buf.push(OPT_END);
+    }
+}
+
+/// Obtains a lease from a DHCP server and configures the interface with it.
+///
+/// Returns the obtained lease, or an error if none could be obtained, in which
+/// case the interface keeps its previous configuration.
+#[cfg(not(test))]
+pub async fn dhcp_configure() -> Result<Lease, DhcpError>
+{
+    let socket = NET.bind(CLIENT_PORT).ok_or(DhcpError::Busy)?;
+    let mac = NET.mac().ok_or(DhcpError::Net(NetError::Detached))?;
+    let xid = now_micros() as u32;
+    let discover = Dhcp { kind: DHCP_DISCOVER,
+                          xid,
+                          mac,
+                          ..Dhcp::default() };
+    let offer = exchange(&socket, &discover, &[DHCP_OFFER]).await?;
+    let request = Dhcp { kind: DHCP_REQUEST,
+                         requested: offer.yours,
+                         server: offer.server,
+                         ..discover };
+    let ack = exchange(&socket, &request, &[DHCP_ACK, DHCP_NAK]).await?;
+    if ack.kind == DHCP_NAK {
+        return Err(DhcpError::Refused);
+    }
+    let config = Config { address: ack.yours,
+                          netmask: ack.netmask,
+                          gateway: ack.router };
+    NET.configure(config);
+    let server = if ack.next_server != [0; 4] {
+        ack.next_server
+    } else {
+        ack.server
+    };
+    Ok(Lease { config,
+               server,
+               time: ack.lease_time })
+}
+
+/// Broadcasts a message until the server answers it.
+///
+/// * `socket`: Socket bound to the client port.
+/// * `msg`: Message to broadcast.
+/// * `expected`: Types of the answers to wait for.
+///
+/// Returns the first answer addressed to this client, or an error if no answer
+/// arrives in time.
+#[cfg(not(test))]
+async fn exchange(socket: &UdpSocket, msg: &Dhcp, expected: &[u8]) -> Result<Dhcp, DhcpError>
+{
+    let mut out = Vec::new();
+    msg.emit(&mut out);
+    // Answers to other clients are broadcast as well, so they're filtered out.
+    let answer = async {
+        loop {
+            let datagram = socket.recv_from().await;
+            match Dhcp::parse(&datagram.payload) {
+                Some(reply) if reply.xid == msg.xid && reply.mac == msg.mac && expected.contains(&reply.kind) => {
+                    return reply
+                }
+                _ => continue,
+            }
+        }
+    };
+    let mut answer = pin!(answer);
+    for _ in 0 .. ATTEMPTS {
+        socket.send_to(&out, BROADCAST_IP, SERVER_PORT)
+              .await
+              .map_err(DhcpError::Net)?;
+        if let Some(reply) = timeout(RETRY_TIMEOUT, answer.as_mut()).await {
+            return Ok(reply);
+        }
+    }
+    Err(DhcpError::Timeout)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn request_roundtrip()
+    {
+        let request = Dhcp { kind: DHCP_REQUEST,
+                             xid: 0xDEADBEEF,
+                             mac: [0xDC, 0xA6, 0x32, 1, 2, 3],
+                             requested: [192, 168, 1, 50],
+                             server: [192, 168, 1, 1],
+                             ..Dhcp::default() };
+        let mut buf = Vec::new();
+        request.emit(&mut buf);
+        assert_eq!(buf[0], OP_REQUEST);
+        assert_eq!(buf[10 .. 12], FLAG_BROADCAST.to_be_bytes());
+        assert!(buf.ends_with(&[OPT_PARAMS,
+                                4,
+                                OPT_NETMASK,
+                                OPT_ROUTER,
+                                OPT_LEASE_TIME,
+                                OPT_SERVER,
+                                OPT_END]));
+        assert_eq!(Dhcp::parse(&buf), Some(request));
+    }
+
+    #[test]
+    fn reply_options()
+    {
+        let ack = Dhcp { kind: DHCP_ACK,
+                         xid: 7,
+                         mac: [2; 6],
+                         yours: [10, 0, 0, 9],
+                         next_server: [10, 0, 0, 2],
+                         server: [10, 0, 0, 1],
+                         netmask: [255, 255, 255, 0],
+                         router: [10, 0, 0, 1],
+                         lease_time: 86400,
+                         ..Dhcp::default() };
+        let offer = Dhcp { kind: DHCP_OFFER,
+                           ..ack };
+        let mut buf = Vec::new();
+        offer.emit(&mut buf);
+        assert_eq!(buf[0], OP_REPLY);
+        assert_eq!(Dhcp::parse(&buf), Some(offer));
+        buf.clear();
+        ack.emit(&mut buf);
+        assert_eq!(Dhcp::parse(&buf), Some(ack));
+        // Padding, unknown options, and extra routers are skipped.
+        buf.pop();
+        buf.extend_from_slice(&[OPT_PAD, 12, 2, b'p', b'i', OPT_ROUTER, 8, 10, 0, 0, 254, 10, 0, 0, 253, OPT_END]);
+        assert_eq!(Dhcp::parse(&buf),
+                   Some(Dhcp { router: [10, 0, 0, 254],
+                               ..ack }));
+    }
+
+    #[test]
+    fn malformed()
+    {
+        let mut buf = Vec::new();
+        Dhcp { kind: DHCP_NAK,
+               ..Dhcp::default() }.emit(&mut buf);
+        assert!(Dhcp::parse(&buf).is_some());
+        // Options running past the end of the message.
+        let mut truncated = buf.clone();
+        truncated.pop();
+        truncated.extend_from_slice(&[OPT_LEASE_TIME, 4, 0]);
+        assert_eq!(Dhcp::parse(&truncated), None);
+        // Missing message type.
+        assert_eq!(Dhcp::parse(&buf[.. FIXED_LEN]), None);
+        let mut cookie = buf.clone();
+        cookie[236] = 0;
+        assert_eq!(Dhcp::parse(&cookie), None);
+    }
+}
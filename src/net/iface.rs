@@ -0,0 +1,468 @@
+//! Network interface.
+//!
+//! Binds the protocol layers to a single Ethernet device, answering ARP
+//! requests and ICMP echo requests addressed to the interface, resolving the
+//! hardware addresses of outgoing packets, and delivering incoming UDP
+//! datagrams to the sockets bound to their destination ports.
+
+extern crate alloc;
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use super::wire::*;
+use crate::sched::{bounded, timeout, BoundedReceiver, BoundedSender};
+use crate::sync::{Lazy, Lock, Notify};
+
+/// Maximum size of the payload of an Ethernet frame.
+const MTU: usize = 1500;
+/// Maximum size of the payload of a UDP datagram that fits in a frame.
+pub const UDP_PAYLOAD_MAX: usize = MTU - IPV4_HEADER_LEN - UDP_HEADER_LEN;
+/// Maximum number of hardware addresses remembered.
+const ARP_CACHE_LEN: usize = 16;
+/// Number of ARP requests sent before giving up on resolving an address.
+const ARP_ATTEMPTS: usize = 3;
+/// Time in milliseconds to wait for each ARP reply.
+const ARP_TIMEOUT: u64 = 500;
+/// Maximum number of datagrams queued in each socket, beyond which incoming
+/// datagrams are dropped.
+const SOCKET_QUEUE_LEN: usize = 16;
+/// First port handed out to sockets bound without a specific port.
+const EPHEMERAL_START: u16 = 49152;
+
+/// Global network interface instance.
+pub static NET: Lazy<Net> = Lazy::new(Net::new);
+
+/// Ethernet device that the interface sends and receives frames through.
+pub trait Device: Debug + Send + Sync
+{
+    /// Returns the hardware address of this device.
+    fn mac(&self) -> MacAddress;
+
+    /// Queues a frame for transmission, dropping it if there's no room.
+    ///
+    /// * `frame`: Frame to transmit, without the frame check sequence.
+    fn transmit(&self, frame: &[u8]);
+}
+
+/// IPv4 configuration of the interface.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Config
+{
+    /// Address of the interface.
+    pub address: Ipv4Address,
+    /// Mask of the local network.
+    pub netmask: Ipv4Address,
+    /// Router that packets to other networks are sent through.
+    pub gateway: Ipv4Address,
+}
+
+/// Network interface.
+#[derive(Debug)]
+pub struct Net
+{
+    /// Interface state.
+    state: Lock<State>,
+    /// Tasks waiting for ARP replies.
+    resolved: Notify,
+}
+
+/// UDP socket bound to a local port, which is released when dropped.
+#[derive(Debug)]
+pub struct UdpSocket
+{
+    /// Local port.
+    port: u16,
+    /// Incoming datagrams.
+    rx: BoundedReceiver<Datagram>,
+}
+
+/// Received UDP datagram.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Datagram
+{
+    /// Source address.
+    pub src: Ipv4Address,
+    /// Source port.
+    pub src_port: u16,
+    /// Payload.
+    pub payload: Vec<u8>,
+}
+
+/// Errors that can occur when sending datagrams.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error
+{
+    /// No device is attached to the interface.
+    Detached,
+    /// The hardware address of the destination or gateway couldn't be
+    /// resolved.
+    Unreachable,
+    /// The payload doesn't fit in a single frame.
+    TooLong,
+}
+
+/// Interface state.
+#[derive(Debug)]
+struct State
+{
+    /// Attached device.
+    device: Option<&'static dyn Device>,
+    /// IPv4 configuration.
+    config: Config,
+    /// Recently resolved hardware addresses, newest last.
+    arp: VecDeque<(Ipv4Address, MacAddress)>,
+    /// Incoming datagram queues of the bound sockets indexed by port.
+    sockets: BTreeMap<u16, BoundedSender<Datagram>>,
+    /// Next port to try handing out to sockets bound without a specific port.
+    ephemeral: u16,
+}
+
+impl Net
+{
+    /// Creates and initializes a new network interface.
+    ///
+    /// Returns the newly created interface.
+    fn new() -> Self
+    {
+        let state = State { device: None,
+                            config: Config::default(),
+                            arp: VecDeque::with_capacity(ARP_CACHE_LEN),
+                            sockets: BTreeMap::new(),
+                            ephemeral: EPHEMERAL_START };
+        Self { state: Lock::new(state),
+               resolved: Notify::new() }
+    }
+
+    /// Attaches a device to the interface, replacing any previous device and
+    /// configuration.
+    ///
+    /// * `device`: Device to send and receive frames through.
+    /// * `config`: IPv4 configuration.
+    pub fn attach(&self, device: &'static dyn Device, config: Config)
+    {
+        let mut state = self.state.lock();
+        state.device = Some(device);
+        state.config = config;
+        state.arp.clear();
+    }
+
+    /// Replaces the IPv4 configuration of the interface, keeping the attached
+    /// device.
+    ///
+    /// * `config`: IPv4 configuration.
+    pub fn configure(&self, config: Config)
+    {
+        let mut state = self.state.lock();
+        state.config = config;
+        state.arp.clear();
+    }
+
+    /// Returns the hardware address of the attached device, if any.
+    pub fn mac(&self) -> Option<MacAddress>
+    {
+        self.state.lock().device.map(|device| device.mac())
+    }
+
+    /// Returns the IPv4 configuration of the interface.
+    pub fn config(&self) -> Config
+    {
+        self.state.lock().config
+    }
+
+    /// Processes a frame received by the attached device.  Must not be called
+    /// from IRQ context.
+    ///
+    /// * `frame`: Received frame, without the frame check sequence.
+    pub fn input(&self, frame: &[u8])
+    {
+        let Some(eth) = Ethernet::parse(frame) else { return };
+        match eth.ethertype {
+            ETHERTYPE_ARP => self.input_arp(eth.payload),
+            ETHERTYPE_IPV4 => self.input_ipv4(eth.src, eth.payload),
+            _ => (),
+        }
+    }
+
+    /// Binds a UDP socket to a local port.
+    ///
+    /// * `port`: Port to bind to, or zero to pick an unused port.
+    ///
+    /// Returns the bound socket, or `None` if the port is already bound.
+    pub fn bind(&self, port: u16) -> Option<UdpSocket>
+    {
+        let mut state = self.state.lock();
+        let port = if port != 0 {
+            if state.sockets.contains_key(&port) {
+                return None;
+            }
+            port
+        } else {
+            let count = (u16::MAX - EPHEMERAL_START) as usize + 1;
+            let first = (state.ephemeral - EPHEMERAL_START) as usize;
+            let port = (0 .. count).map(|offset| EPHEMERAL_START + ((first + offset) % count) as u16)
+                                   .find(|port| !state.sockets.contains_key(port))?;
+            state.ephemeral = port.checked_add(1).unwrap_or(EPHEMERAL_START);
+            port
+        };
+        let (tx, rx) = bounded(SOCKET_QUEUE_LEN);
+        state.sockets.insert(port, tx);
+        Some(UdpSocket { port, rx })
+    }
+
+    /// Handles an ARP packet, learning the sender's hardware address and
+    /// answering requests for the interface's address.
+    ///
+    /// * `packet`: ARP packet.
+    fn input_arp(&self, packet: &[u8])
+    {
+        let Some(arp) = Arp::parse(packet) else { return };
+        let mut state = self.state.lock();
+        let Some(device) = state.device else { return };
+        if arp.target_ip != state.config.address {
+            return;
+        }
+        state.learn(arp.sender_ip, arp.sender_mac);
+        if arp.op == ARP_REQUEST {
+            let reply = Arp { op: ARP_REPLY,
+                              sender_mac: device.mac(),
+                              sender_ip: state.config.address,
+                              target_mac: arp.sender_mac,
+                              target_ip: arp.sender_ip };
+            let mut payload = Vec::with_capacity(MTU);
+            reply.emit(&mut payload);
+            drop(state);
+            transmit(device, arp.sender_mac, ETHERTYPE_ARP, &payload);
+        } else {
+            drop(state);
+        }
+        self.resolved.notify_all();
+    }
+
+    /// Handles an IPv4 packet addressed to the interface.
+    ///
+    /// * `src_mac`: Hardware address of the sender.
+    /// * `packet`: IPv4 packet.
+    fn input_ipv4(&self, src_mac: MacAddress, packet: &[u8])
+    {
+        let Some(ip) = Ipv4::parse(packet) else { return };
+        let state = self.state.lock();
+        let Some(device) = state.device else { return };
+        if ip.dst != state.config.address && !state.config.is_broadcast(ip.dst) {
+            return;
+        }
+        match ip.protocol {
+            PROTOCOL_ICMP => {
+                drop(state);
+                let Some(icmp) = Icmp::parse(ip.payload) else { return };
+                if icmp.kind != ICMP_ECHO_REQUEST || ip.dst != self.config().address {
+                    return;
+                }
+                let reply = Icmp { kind: ICMP_ECHO_REPLY,
+                                   code: 0,
+                                   rest: icmp.rest,
+                                   payload: icmp.payload };
+                let mut msg = Vec::with_capacity(MTU);
+                reply.emit(&mut msg);
+                transmit_ipv4(device, src_mac, ip.dst, ip.src, PROTOCOL_ICMP, &msg);
+            }
+            PROTOCOL_UDP => {
+                let Some(udp) = Udp::parse(ip.payload, ip.src, ip.dst) else {
+                    return;
+                };
+                let Some(tx) = state.sockets.get(&udp.dst_port) else {
+                    return;
+                };
+                let datagram = Datagram { src: ip.src,
+                                          src_port: udp.src_port,
+                                          payload: udp.payload.to_vec() };
+                // Datagrams that don't fit in the queue are dropped.
+                let _ = tx.try_send(datagram);
+            }
+            _ => (),
+        }
+    }
+
+    /// Resolves the hardware address of a host on the local network, sending
+    /// ARP requests until it replies or too many attempts fail.
+    ///
+    /// * `ip`: Address of the host.
+    ///
+    /// Returns the resolved hardware address, or `None` if the host didn't
+    /// reply.
+    async fn resolve(&self, ip: Ipv4Address) -> Option<MacAddress>
+    {
+        for _ in 0 .. ARP_ATTEMPTS {
+            let (device, request) = {
+                let state = self.state.lock();
+                if let Some(mac) = state.lookup(ip) {
+                    return Some(mac);
+                }
+                let device = state.device?;
+                let request = Arp { op: ARP_REQUEST,
+                                    sender_mac: device.mac(),
+                                    sender_ip: state.config.address,
+                                    target_mac: [0; 6],
+                                    target_ip: ip };
+                (device, request)
+            };
+            let mut payload = Vec::with_capacity(MTU);
+            request.emit(&mut payload);
+            transmit(device, BROADCAST_MAC, ETHERTYPE_ARP, &payload);
+            // Replies for other hosts also wake this task up, in which case it
+            // just sends another request.
+            timeout(ARP_TIMEOUT, self.resolved.notified()).await;
+        }
+        self.state.lock().lookup(ip)
+    }
+}
+
+impl UdpSocket
+{
+    /// Sends a datagram.
+    ///
+    /// * `payload`: Payload to send.
+    /// * `dst`: Destination address.
+    /// * `dst_port`: Destination port.
+    ///
+    /// Returns nothing if the datagram was handed to the device, or an error
+    /// otherwise.
+    pub async fn send_to(&self, payload: &[u8], dst: Ipv4Address, dst_port: u16) -> Result<(), Error>
+    {
+        if payload.len() > UDP_PAYLOAD_MAX {
+            return Err(Error::TooLong);
+        }
+        let (device, config) = {
+            let state = NET.state.lock();
+            (state.device.ok_or(Error::Detached)?, state.config)
+        };
+        let mac = if config.is_broadcast(dst) {
+            BROADCAST_MAC
+        } else {
+            let hop = if config.is_local(dst) { dst } else { config.gateway };
+            NET.resolve(hop).await.ok_or(Error::Unreachable)?
+        };
+        let udp = Udp { src_port: self.port,
+                        dst_port,
+                        payload };
+        let mut datagram = Vec::with_capacity(MTU);
+        udp.emit(config.address, dst, &mut datagram);
+        transmit_ipv4(device, mac, config.address, dst, PROTOCOL_UDP, &datagram);
+        Ok(())
+    }
+
+    /// Waits for a datagram addressed to this socket.
+    ///
+    /// Returns the oldest queued datagram.
+    pub async fn recv_from(&self) -> Datagram
+    {
+        // The sending end stays in the interface for as long as the socket is
+        // bound.
+        self.rx.recv().await.expect("Socket queue closed while still bound")
+    }
+
+    /// Attempts to dequeue a datagram without waiting.
+    ///
+    /// Returns the oldest queued datagram, or `None` if there are none.
+    pub fn try_recv_from(&self) -> Option<Datagram>
+    {
+        self.rx.try_recv()
+    }
+}
+
+impl Drop for UdpSocket
+{
+    fn drop(&mut self)
+    {
+        NET.state.lock().sockets.remove(&self.port);
+    }
+}
+
+impl Config
+{
+    /// Checks whether an address belongs to the local network.
+    ///
+    /// * `ip`: Address to check.
+    ///
+    /// Returns whether the address is on the local network.
+    fn is_local(&self, ip: Ipv4Address) -> bool
+    {
+        (0 .. 4).all(|idx| (ip[idx] ^ self.address[idx]) & self.netmask[idx] == 0)
+    }
+
+    /// Checks whether an address is either the limited broadcast address or
+    /// the broadcast address of the local network.
+    ///
+    /// * `ip`: Address to check.
+    ///
+    /// Returns whether the address is a broadcast address.
+    fn is_broadcast(&self, ip: Ipv4Address) -> bool
+    {
+        ip == BROADCAST_IP || self.is_local(ip) && (0 .. 4).all(|idx| ip[idx] | self.netmask[idx] == 0xFF)
+    }
+}
+
+impl State
+{
+    /// Looks up a hardware address in the cache.
+    ///
+    /// * `ip`: Address whose hardware address to look up.
+    ///
+    /// Returns the cached hardware address, if any.
+    fn lookup(&self, ip: Ipv4Address) -> Option<MacAddress>
+    {
+        self.arp.iter().find(|(cached, _)| *cached == ip).map(|(_, mac)| *mac)
+    }
+
+    /// Adds or refreshes a hardware address in the cache, evicting the oldest
+    /// entry if it's full.
+    ///
+    /// * `ip`: Address of the host.
+    /// * `mac`: Hardware address of the host.
+    fn learn(&mut self, ip: Ipv4Address, mac: MacAddress)
+    {
+        self.arp.retain(|(cached, _)| *cached != ip);
+        if self.arp.len() == ARP_CACHE_LEN {
+            self.arp.pop_front();
+        }
+        self.arp.push_back((ip, mac));
+    }
+}
+
+/// Wraps a payload in an Ethernet frame and hands it to a device.
+///
+/// * `device`: Device to transmit through.
+/// * `dst`: Destination hardware address.
+/// * `ethertype`: Type of the payload.
+/// * `payload`: Payload to send.
+fn transmit(device: &dyn Device, dst: MacAddress, ethertype: u16, payload: &[u8])
+{
+    let eth = Ethernet { dst,
+                         src: device.mac(),
+                         ethertype,
+                         payload };
+    let mut frame = Vec::with_capacity(ETHERNET_HEADER_LEN + payload.len());
+    eth.emit(&mut frame);
+    device.transmit(&frame);
+}
+
+/// Wraps a payload in IPv4 and Ethernet headers and hands it to a device.
+///
+/// * `device`: Device to transmit through.
+/// * `dst_mac`: Hardware address of the next hop.
+/// * `src`: Source address.
+/// * `dst`: Destination address.
+/// * `protocol`: Protocol of the payload.
+/// * `payload`: Payload to send.
+fn transmit_ipv4(device: &dyn Device, dst_mac: MacAddress, src: Ipv4Address, dst: Ipv4Address, protocol: u8,
+                 payload: &[u8])
+{
+    let ip = Ipv4 { src,
+                    dst,
+                    protocol,
+                    payload };
+    let mut packet = Vec::with_capacity(IPV4_HEADER_LEN + payload.len());
+    ip.emit(&mut packet);
+    transmit(device, dst_mac, ETHERTYPE_IPV4, &packet);
+}
@@ -0,0 +1,24 @@
+//! Minimal IPv4 network stack.
+//!
+//! Implements just enough of ARP, IPv4, ICMP, UDP, DHCP, and TFTP for the game
+//! to configure itself, stream diagnostics to, accept commands from, and
+//! download assets from a development machine on the local network, with TFTP
+//! only built with the `netassets` option.  The packet formats have no
+//! dependencies on the hardware and are tested on the host, whereas the
+//! interface expects an Ethernet driver to attach itself as a [`Device`] and
+//! to feed it every frame it receives.
+
+mod dhcp;
+#[cfg(not(test))]
+mod iface;
+#[cfg(any(netassets, test))]
+mod tftp;
+mod wire;
+
+#[cfg(not(test))]
+pub use self::dhcp::*;
+#[cfg(not(test))]
+pub use self::iface::*;
+#[cfg(netassets)]
+pub use self::tftp::*;
+pub use self::wire::*;
@@ -0,0 +1,247 @@
+//! TFTP client.
+//!
+//! Downloads files in octet mode as described in RFC 1350 [1], which is enough
+//! to pull assets from the same kind of server that development setups already
+//! use to network boot the kernel.  Every packet is retransmitted a few times
+//! if the server doesn't answer in time, and transfers larger than 32MB are
+//! supported by letting the block number wrap around.
+//!
+//! [1]: https://www.rfc-editor.org/rfc/rfc1350
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::str::from_utf8;
+
+#[cfg(not(test))]
+use super::{Error as NetError, Ipv4Address, NET};
+#[cfg(not(test))]
+use crate::sched::timeout;
+
+/// Well known server port.
+#[cfg(not(test))]
+const SERVER_PORT: u16 = 69;
+/// Size of a full data block, with shorter blocks marking the end of a file.
+const BLOCK_SIZE: usize = 512;
+/// Time in milliseconds to wait for an answer before retransmitting.
+#[cfg(not(test))]
+const RETRY_TIMEOUT: u64 = 1000;
+/// Number of times a packet is sent before giving up.
+#[cfg(not(test))]
+const ATTEMPTS: usize = 5;
+/// Read request operation code.
+const OP_RRQ: u16 = 1;
+/// Data operation code.
+const OP_DATA: u16 = 3;
+/// Acknowledgement operation code.
+const OP_ACK: u16 = 4;
+/// Error operation code.
+const OP_ERROR: u16 = 5;
+
+/// TFTP packet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Tftp<'a>
+{
+    /// Request to read a file in octet mode.
+    Read
+    {
+        /// Name of the file.
+        name: &'a str,
+    },
+    /// Block of file data.
+    Data
+    {
+        /// Block number, starting at one.
+        block: u16,
+        /// Block contents.
+        data: &'a [u8],
+    },
+    /// Acknowledgement of a block.
+    Ack
+    {
+        /// Block number.
+        block: u16,
+    },
+    /// Termination of a transfer.
+    Error
+    {
+        /// Error code.
+        code: u16,
+        /// Error message.
+        msg: &'a str,
+    },
+}
+
+/// Errors that can occur when downloading a file.
+#[cfg(not(test))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TftpError
+{
+    /// A packet couldn't be sent.
+    Net(NetError),
+    /// The server stopped answering.
+    Timeout,
+    /// The server terminated the transfer with an error code.
+    Remote(u16),
+}
+
+impl<'a> Tftp<'a>
+{
+    /// Parses a packet.
+    ///
+    /// * `packet`: Packet to parse.
+    ///
+    /// Returns the parsed packet, or `None` if it's malformed, oversized, or
+    /// of an unsupported kind.
+    pub fn parse(packet: &'a [u8]) -> Option<Self>
+    {
+        if packet.len() < 4 {
+            return None;
+        }
+        let op = u16::from_be_bytes([packet[0], packet[1]]);
+        let arg = u16::from_be_bytes([packet[2], packet[3]]);
+        match op {
+            OP_DATA if packet.len() <= 4 + BLOCK_SIZE => Some(Self::Data { block: arg,
+                                                                           data: &packet[4 ..] }),
+            OP_ACK => Some(Self::Ack { block: arg }),
+            OP_ERROR => {
+                let msg = packet[4 ..].split(|byte| *byte == 0).next()?;
+                Some(Self::Error { code: arg,
+                                   msg: from_utf8(msg).ok()? })
+            }
+            _ => None,
+        }
+    }
+
+    /// Appends this packet to a buffer.
+    ///
+    /// * `buf`: Buffer to append to.
+    pub fn emit(&self, buf: &mut Vec<u8>)
+    {
+        match *self {
+            Self::Read { name } => {
+                buf.extend_from_slice(&OP_RRQ.to_be_bytes());
+                buf.extend_from_slice(name.as_bytes());
+                buf.extend_from_slice(b"\0octet\0");
+            }
+            Self::Data { block, data } => {
+                buf.extend_from_slice(&OP_DATA.to_be_bytes());
+                buf.extend_from_slice(&block.to_be_bytes());
+                buf.extend_from_slice(data);
+            }
+            Self::Ack { block } => {
+                buf.extend_from_slice(&OP_ACK.to_be_bytes());
+                buf.extend_from_slice(&block.to_be_bytes());
+            }
+            Self::Error { code, msg } => {
+                buf.extend_from_slice(&OP_ERROR.to_be_bytes());
+                buf.extend_from_slice(&code.to_be_bytes());
+                buf.extend_from_slice(msg.as_bytes());
+                buf.push(0);
+            }
+        }
+    }
+}
+
+/// Downloads a file.
+///
+/// * `server`: Address of the server.
+/// * `name`: Name of the file.
+///
+/// Returns the contents of the file, or an error if the transfer fails.
+#[cfg(not(test))]
+pub async fn tftp_get(server: Ipv4Address, name: &str) -> Result<Vec<u8>, TftpError>
+{
+    let socket = NET.bind(0).ok_or(TftpError::Net(NetError::Detached))?;
+    let mut out = Vec::with_capacity(BLOCK_SIZE + 4);
+    Tftp::Read { name }.emit(&mut out);
+    // The server answers from a port chosen for the transfer, which all the
+    // acknowledgements must be sent to.
+    let mut peer = (server, SERVER_PORT);
+    let mut contents = Vec::new();
+    let mut block = 1u16;
+    loop {
+        let mut attempts = 0;
+        let (port, len) = loop {
+            if attempts == ATTEMPTS {
+                return Err(TftpError::Timeout);
+            }
+            attempts += 1;
+            socket.send_to(&out, peer.0, peer.1).await.map_err(TftpError::Net)?;
+            let Some(datagram) = timeout(RETRY_TIMEOUT, socket.recv_from()).await else {
+                continue;
+            };
+            if datagram.src != server || peer.1 != SERVER_PORT && datagram.src_port != peer.1 {
+                continue;
+            }
+            match Tftp::parse(&datagram.payload) {
+                Some(Tftp::Data { block: other, data }) if other == block => {
+                    contents.extend_from_slice(data);
+                    break (datagram.src_port, data.len());
+                }
+                Some(Tftp::Error { code, .. }) => return Err(TftpError::Remote(code)),
+                // Duplicates of the previous block mean that the last
+                // acknowledgement was lost, so it's sent again.
+                _ => continue,
+            }
+        };
+        peer.1 = port;
+        out.clear();
+        Tftp::Ack { block }.emit(&mut out);
+        if len < BLOCK_SIZE {
+            // The final acknowledgement isn't retransmitted, as the server
+            // resends the last block if it's lost and nobody is listening.
+            socket.send_to(&out, peer.0, peer.1).await.map_err(TftpError::Net)?;
+            return Ok(contents);
+        }
+        block = block.wrapping_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn read_request()
+    {
+        let mut buf = Vec::new();
+        Tftp::Read { name: "mesh.bin" }.emit(&mut buf);
+        assert_eq!(buf, b"\0\x01mesh.bin\0octet\0");
+        // Servers never send read requests.
+        assert_eq!(Tftp::parse(&buf), None);
+    }
+
+    #[test]
+    fn data_ack_roundtrip()
+    {
+        let data = [0xA5; BLOCK_SIZE];
+        for packet in [Tftp::Data { block: 7, data: &data },
+                       Tftp::Data { block: 0xFFFF,
+                                    data: &[] },
+                       Tftp::Ack { block: 7 }]
+        {
+            let mut buf = Vec::new();
+            packet.emit(&mut buf);
+            assert_eq!(Tftp::parse(&buf), Some(packet));
+        }
+        let mut buf = Vec::new();
+        Tftp::Data { block: 1, data: &data }.emit(&mut buf);
+        buf.push(0);
+        assert_eq!(Tftp::parse(&buf), None);
+        assert_eq!(Tftp::parse(&buf[.. 3]), None);
+    }
+
+    #[test]
+    fn error_message()
+    {
+        let buf = b"\0\x05\0\x01File not found\0";
+        assert_eq!(Tftp::parse(buf),
+                   Some(Tftp::Error { code: 1,
+                                      msg: "File not found" }));
+        let mut out = Vec::new();
+        Tftp::parse(buf).unwrap().emit(&mut out);
+        assert_eq!(out, buf);
+    }
+}
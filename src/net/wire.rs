@@ -0,0 +1,552 @@
+//! Packet formats.
+//!
+//! Parses and builds the headers of the Ethernet II [1], ARP [2], IPv4 [3],
+//! ICMP [4], and UDP [5] protocols.  Parsing validates lengths and checksums
+//! and borrows the payload from the original buffer, whereas building appends
+//! the header followed by the payload to a buffer, so that each layer can
+//! wrap the output of the layer above it.  IP options are skipped and
+//! fragmented packets are rejected, as nothing in the stack needs either.
+//!
+//! [1]: https://www.rfc-editor.org/rfc/rfc894
+//! [2]: https://www.rfc-editor.org/rfc/rfc826
+//! [3]: https://www.rfc-editor.org/rfc/rfc791
+//! [4]: https://www.rfc-editor.org/rfc/rfc792
+//! [5]: https://www.rfc-editor.org/rfc/rfc768
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+/// Ethernet hardware address.
+pub type MacAddress = [u8; 6];
+/// IPv4 address.
+pub type Ipv4Address = [u8; 4];
+
+/// Ethernet broadcast address.
+pub const BROADCAST_MAC: MacAddress = [0xFF; 6];
+/// Limited broadcast IPv4 address.
+pub const BROADCAST_IP: Ipv4Address = [0xFF; 4];
+/// Ethernet type of IPv4 packets.
+pub const ETHERTYPE_IPV4: u16 = 0x800;
+/// Ethernet type of ARP packets.
+pub const ETHERTYPE_ARP: u16 = 0x806;
+/// IP protocol number of ICMP.
+pub const PROTOCOL_ICMP: u8 = 1;
+/// IP protocol number of UDP.
+pub const PROTOCOL_UDP: u8 = 17;
+/// ARP request operation.
+pub const ARP_REQUEST: u16 = 1;
+/// ARP reply operation.
+pub const ARP_REPLY: u16 = 2;
+/// ICMP echo reply type.
+pub const ICMP_ECHO_REPLY: u8 = 0;
+/// ICMP echo request type.
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+/// Size of an Ethernet header.
+pub const ETHERNET_HEADER_LEN: usize = 14;
+/// Size of an IPv4 header without options.
+pub const IPV4_HEADER_LEN: usize = 20;
+/// Size of a UDP header.
+pub const UDP_HEADER_LEN: usize = 8;
+/// Size of an ARP packet for IPv4 over Ethernet.
+const ARP_LEN: usize = 28;
+/// Size of an ICMP header.
+const ICMP_HEADER_LEN: usize = 8;
+/// IPv4 don't fragment flag.
+const IPV4_DONT_FRAGMENT: u16 = 0x4000;
+/// IPv4 more fragments flag.
+const IPV4_MORE_FRAGMENTS: u16 = 0x2000;
+/// IPv4 fragment offset mask.
+const IPV4_OFFSET_MASK: u16 = 0x1FFF;
+/// Time to live of outgoing IPv4 packets.
+const DEFAULT_TTL: u8 = 64;
+
+/// Ethernet II frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Ethernet<'a>
+{
+    /// Destination hardware address.
+    pub dst: MacAddress,
+    /// Source hardware address.
+    pub src: MacAddress,
+    /// Type of the payload.
+    pub ethertype: u16,
+    /// Payload, possibly followed by padding.
+    pub payload: &'a [u8],
+}
+
+/// ARP packet for IPv4 over Ethernet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Arp
+{
+    /// Operation.
+    pub op: u16,
+    /// Hardware address of the sender.
+    pub sender_mac: MacAddress,
+    /// Protocol address of the sender.
+    pub sender_ip: Ipv4Address,
+    /// Hardware address of the target, or zeros in requests.
+    pub target_mac: MacAddress,
+    /// Protocol address of the target.
+    pub target_ip: Ipv4Address,
+}
+
+/// IPv4 packet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Ipv4<'a>
+{
+    /// Source address.
+    pub src: Ipv4Address,
+    /// Destination address.
+    pub dst: Ipv4Address,
+    /// Protocol of the payload.
+    pub protocol: u8,
+    /// Payload.
+    pub payload: &'a [u8],
+}
+
+/// ICMP message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Icmp<'a>
+{
+    /// Message type.
+    pub kind: u8,
+    /// Message code.
+    pub code: u8,
+    /// Type specific header field, which holds the identifier and sequence
+    /// number of echo messages.
+    pub rest: [u8; 4],
+    /// Payload.
+    pub payload: &'a [u8],
+}
+
+/// UDP datagram.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Udp<'a>
+{
+    /// Source port.
+    pub src_port: u16,
+    /// Destination port.
+    pub dst_port: u16,
+    /// Payload.
+    pub payload: &'a [u8],
+}
+
+impl<'a> Ethernet<'a>
+{
+    /// Parses a frame.
+    ///
+    /// * `frame`: Frame to parse, without the frame check sequence.
+    ///
+    /// Returns the parsed frame, or `None` if it's too short.
+    pub fn parse(frame: &'a [u8]) -> Option<Self>
+    {
+        if frame.len() < ETHERNET_HEADER_LEN {
+            return None;
+        }
+        Some(Self { dst: frame[0 .. 6].try_into().unwrap(),
+                    src: frame[6 .. 12].try_into().unwrap(),
+                    ethertype: be16(frame, 12),
+                    payload: &frame[ETHERNET_HEADER_LEN ..] })
+    }
+
+    /// Appends this frame to a buffer.
+    ///
+    /// * `buf`: Buffer to append to.
+    pub fn emit(&self, buf: &mut Vec<u8>)
+    {
+        buf.extend_from_slice(&self.dst);
+        buf.extend_from_slice(&self.src);
+        buf.extend_from_slice(&self.ethertype.to_be_bytes());
+        buf.extend_from_slice(self.payload);
+    }
+}
+
+impl Arp
+{
+    /// Parses a packet.
+    ///
+    /// * `packet`: Packet to parse.
+    ///
+    /// Returns the parsed packet, or `None` if it's either too short or not
+    /// for IPv4 over Ethernet.
+    pub fn parse(packet: &[u8]) -> Option<Self>
+    {
+        if packet.len() < ARP_LEN
+           || be16(packet, 0) != 1
+           || be16(packet, 2) != ETHERTYPE_IPV4
+           || packet[4] != 6
+           || packet[5] != 4
+        {
+            return None;
+        }
+        Some(Self { op: be16(packet, 6),
+                    sender_mac: packet[8 .. 14].try_into().unwrap(),
+                    sender_ip: packet[14 .. 18].try_into().unwrap(),
+                    target_mac: packet[18 .. 24].try_into().unwrap(),
+                    target_ip: packet[24 .. 28].try_into().unwrap() })
+    }
+
+    /// Appends this packet to a buffer.
+    ///
+    /// * `buf`: Buffer to append to.
+    pub fn emit(&self, buf: &mut Vec<u8>)
+    {
+        buf.extend_from_slice(&1u16.to_be_bytes());
+        buf.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        buf.extend_from_slice(&[6, 4]);
+        buf.extend_from_slice(&self.op.to_be_bytes());
+        buf.extend_from_slice(&self.sender_mac);
+        buf.extend_from_slice(&self.sender_ip);
+        buf.extend_from_slice(&self.target_mac);
+        buf.extend_from_slice(&self.target_ip);
+    }
+}
+
+impl<'a> Ipv4<'a>
+{
+    /// Parses a packet.
+    ///
+    /// * `packet`: Packet to parse, possibly followed by padding.
+    ///
+    /// Returns the parsed packet, or `None` if it's malformed, fails its
+    /// checksum, or is a fragment.
+    pub fn parse(packet: &'a [u8]) -> Option<Self>
+    {
+        if packet.len() < IPV4_HEADER_LEN || packet[0] >> 4 != 4 {
+            return None;
+        }
+        let header_len = (packet[0] & 0xF) as usize * 4;
+        let total_len = be16(packet, 2) as usize;
+        if header_len < IPV4_HEADER_LEN || total_len < header_len || total_len > packet.len() {
+            return None;
+        }
+        if checksum(&[&packet[.. header_len]]) != 0 {
+            return None;
+        }
+        let frag = be16(packet, 6);
+        if frag & IPV4_MORE_FRAGMENTS != 0 || frag & IPV4_OFFSET_MASK != 0 {
+            return None;
+        }
+        Some(Self { src: packet[12 .. 16].try_into().unwrap(),
+                    dst: packet[16 .. 20].try_into().unwrap(),
+                    protocol: packet[9],
+                    payload: &packet[header_len .. total_len] })
+    }
+
+    /// Appends this packet to a buffer.
+    ///
+    /// * `buf`: Buffer to append to.
+    ///
+    /// Panics if the payload doesn't fit in a packet.
+    #[track_caller]
+    pub fn emit(&self, buf: &mut Vec<u8>)
+    {
+        let total_len: u16 = (IPV4_HEADER_LEN + self.payload.len()).try_into()
+                                                                   .expect("IPv4 payload is too long");
+        let mut header = [0u8; IPV4_HEADER_LEN];
+        header[0] = 0x45;
+        header[2 .. 4].copy_from_slice(&total_len.to_be_bytes());
+        header[6 .. 8].copy_from_slice(&IPV4_DONT_FRAGMENT.to_be_bytes());
+        header[8] = DEFAULT_TTL;
+        header[9] = self.protocol;
+        header[12 .. 16].copy_from_slice(&self.src);
+        header[16 .. 20].copy_from_slice(&self.dst);
+        let sum = checksum(&[&header]);
+        header[10 .. 12].copy_from_slice(&sum.to_be_bytes());
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(self.payload);
+    }
+}
+
+impl<'a> Icmp<'a>
+{
+    /// Parses a message.
+    ///
+    /// * `msg`: Message to parse.
+    ///
+    /// Returns the parsed message, or `None` if it's either too short or fails
+    /// its checksum.
+    pub fn parse(msg: &'a [u8]) -> Option<Self>
+    {
+        if msg.len() < ICMP_HEADER_LEN || checksum(&[msg]) != 0 {
+            return None;
+        }
+        Some(Self { kind: msg[0],
+                    code: msg[1],
+                    rest: msg[4 .. 8].try_into().unwrap(),
+                    payload: &msg[ICMP_HEADER_LEN ..] })
+    }
+
+    /// Appends this message to a buffer.
+    ///
+    /// * `buf`: Buffer to append to.
+    pub fn emit(&self, buf: &mut Vec<u8>)
+    {
+        let mut header = [0u8; ICMP_HEADER_LEN];
+        header[0] = self.kind;
+        header[1] = self.code;
+        header[4 .. 8].copy_from_slice(&self.rest);
+        let sum = checksum(&[&header, self.payload]);
+        header[2 .. 4].copy_from_slice(&sum.to_be_bytes());
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(self.payload);
+    }
+}
+
+impl<'a> Udp<'a>
+{
+    /// Parses a datagram.
+    ///
+    /// * `datagram`: Datagram to parse.
+    /// * `src`: Source address of the enclosing IPv4 packet.
+    /// * `dst`: Destination address of the enclosing IPv4 packet.
+    ///
+    /// Returns the parsed datagram, or `None` if it's either malformed or
+    /// fails its checksum.
+    pub fn parse(datagram: &'a [u8], src: Ipv4Address, dst: Ipv4Address) -> Option<Self>
+    {
+        if datagram.len() < UDP_HEADER_LEN {
+            return None;
+        }
+        let len = be16(datagram, 4) as usize;
+        if len < UDP_HEADER_LEN || len > datagram.len() {
+            return None;
+        }
+        let datagram = &datagram[.. len];
+        // A zero checksum means that the sender didn't compute one.
+        if be16(datagram, 6) != 0 && checksum(&[&pseudo_header(src, dst, len), datagram]) != 0 {
+            return None;
+        }
+        Some(Self { src_port: be16(datagram, 0),
+                    dst_port: be16(datagram, 2),
+                    payload: &datagram[UDP_HEADER_LEN ..] })
+    }
+
+    /// Appends this datagram to a buffer.
+    ///
+    /// * `src`: Source address of the enclosing IPv4 packet.
+    /// * `dst`: Destination address of the enclosing IPv4 packet.
+    /// * `buf`: Buffer to append to.
+    ///
+    /// Panics if the payload doesn't fit in a datagram.
+    #[track_caller]
+    pub fn emit(&self, src: Ipv4Address, dst: Ipv4Address, buf: &mut Vec<u8>)
+    {
+        let len = UDP_HEADER_LEN + self.payload.len();
+        let len16: u16 = len.try_into().expect("UDP payload is too long");
+        let mut header = [0u8; UDP_HEADER_LEN];
+        header[0 .. 2].copy_from_slice(&self.src_port.to_be_bytes());
+        header[2 .. 4].copy_from_slice(&self.dst_port.to_be_bytes());
+        header[4 .. 6].copy_from_slice(&len16.to_be_bytes());
+        // A computed checksum of zero is transmitted as all ones, since zero
+        // means that no checksum was computed.
+        let sum = match checksum(&[&pseudo_header(src, dst, len), &header, self.payload]) {
+            0 => 0xFFFF,
+            sum => sum,
+        };
+        header[6 .. 8].copy_from_slice(&sum.to_be_bytes());
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(self.payload);
+    }
+}
+
+/// Computes the Internet checksum of the concatenation of several buffers, all
+/// of which except for the last must have an even length.
+///
+/// * `bufs`: Buffers to checksum.
+///
+/// Returns the computed checksum, which is zero when computed over data that
+/// already includes a valid checksum.
+pub fn checksum(bufs: &[&[u8]]) -> u16
+{
+    let mut sum = 0u32;
+    for buf in bufs {
+        let mut words = buf.chunks_exact(2);
+        for word in &mut words {
+            sum += u16::from_be_bytes([word[0], word[1]]) as u32;
+        }
+        if let [byte] = words.remainder() {
+            sum += (*byte as u32) << 8;
+        }
+    }
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Builds the pseudo header covered by UDP checksums.
+///
+/// * `src`: Source address.
+/// * `dst`: Destination address.
+/// * `len`: Length of the UDP datagram.
+///
+/// Returns the built pseudo header.
+fn pseudo_header(src: Ipv4Address, dst: Ipv4Address, len: usize) -> [u8; 12]
+{
+    let mut header = [0u8; 12];
+    header[0 .. 4].copy_from_slice(&src);
+    header[4 .. 8].copy_from_slice(&dst);
+    header[9] = PROTOCOL_UDP;
+    header[10 .. 12].copy_from_slice(&(len as u16).to_be_bytes());
+    header
+}
+
+/// Reads a big endian 16-bit value.
+///
+/// * `buf`: Buffer to read from.
+/// * `offset`: Offset of the value in the buffer.
+///
+/// Returns the read value.
+fn be16(buf: &[u8], offset: usize) -> u16
+{
+    u16::from_be_bytes([buf[offset], buf[offset + 1]])
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const HOST: Ipv4Address = [192, 168, 1, 2];
+    const PEER: Ipv4Address = [192, 168, 1, 10];
+
+    #[test]
+    fn checksum_rfc1071()
+    {
+        let data = [0x00, 0x01, 0xF2, 0x03, 0xF4, 0xF5, 0xF6, 0xF7];
+        assert_eq!(checksum(&[&data]), !0xDDF2);
+        assert_eq!(checksum(&[&data[.. 4], &data[4 ..]]), !0xDDF2);
+        assert_eq!(checksum(&[&[0xAB]]), !0xAB00);
+    }
+
+    #[test]
+    fn ethernet_arp_roundtrip()
+    {
+        let arp = Arp { op: ARP_REQUEST,
+                        sender_mac: [0xDC, 0xA6, 0x32, 0x00, 0x00, 0x01],
+                        sender_ip: HOST,
+                        target_mac: [0; 6],
+                        target_ip: PEER };
+        let mut payload = Vec::new();
+        arp.emit(&mut payload);
+        assert_eq!(payload.len(), ARP_LEN);
+        let frame = Ethernet { dst: BROADCAST_MAC,
+                               src: arp.sender_mac,
+                               ethertype: ETHERTYPE_ARP,
+                               payload: &payload };
+        let mut buf = Vec::new();
+        frame.emit(&mut buf);
+        let parsed = Ethernet::parse(&buf).unwrap();
+        assert_eq!(parsed, frame);
+        assert_eq!(Arp::parse(parsed.payload), Some(arp));
+        assert_eq!(Arp::parse(&payload[.. ARP_LEN - 1]), None);
+        assert_eq!(Ethernet::parse(&buf[.. ETHERNET_HEADER_LEN - 1]), None);
+        // The reply swaps the roles and is sent back to the requester only.
+        let reply = Arp { op: ARP_REPLY,
+                          sender_mac: [0xDC, 0xA6, 0x32, 0x00, 0x00, 0x02],
+                          sender_ip: PEER,
+                          target_mac: arp.sender_mac,
+                          target_ip: HOST };
+        payload.clear();
+        reply.emit(&mut payload);
+        assert_eq!(Arp::parse(&payload), Some(reply));
+    }
+
+    #[test]
+    fn ipv4_udp_roundtrip()
+    {
+        let udp = Udp { src_port: 5000,
+                        dst_port: 6000,
+                        payload: b"hello" };
+        let mut datagram = Vec::new();
+        udp.emit(HOST, PEER, &mut datagram);
+        let ip = Ipv4 { src: HOST,
+                        dst: PEER,
+                        protocol: PROTOCOL_UDP,
+                        payload: &datagram };
+        let mut packet = Vec::new();
+        ip.emit(&mut packet);
+        // Ethernet pads short frames, so trailing bytes must be ignored.
+        packet.extend_from_slice(&[0; 4]);
+        let parsed = Ipv4::parse(&packet).unwrap();
+        assert_eq!(parsed, ip);
+        assert_eq!(Udp::parse(parsed.payload, HOST, PEER), Some(udp));
+        // The checksum covers the addresses of the enclosing packet.
+        assert_eq!(Udp::parse(parsed.payload, HOST, [192, 168, 1, 11]), None);
+    }
+
+    #[test]
+    fn ipv4_reject()
+    {
+        let ip = Ipv4 { src: HOST,
+                        dst: PEER,
+                        protocol: PROTOCOL_UDP,
+                        payload: &[0; 8] };
+        let mut packet = Vec::new();
+        ip.emit(&mut packet);
+        let mut corrupt = packet.clone();
+        corrupt[8] ^= 0x1;
+        assert_eq!(Ipv4::parse(&corrupt), None);
+        let mut fragment = packet.clone();
+        fragment[6] |= 0x20;
+        fragment[10 .. 12].fill(0);
+        let sum = checksum(&[&fragment[.. IPV4_HEADER_LEN]]);
+        fragment[10 .. 12].copy_from_slice(&sum.to_be_bytes());
+        assert_eq!(Ipv4::parse(&fragment), None);
+        assert_eq!(Ipv4::parse(&packet[.. packet.len() - 1]), None);
+    }
+
+    #[test]
+    fn udp_broadcast()
+    {
+        // DHCP discovery is sent before the host has an address.
+        let udp = Udp { src_port: 68,
+                        dst_port: 67,
+                        payload: b"discover" };
+        let mut datagram = Vec::new();
+        udp.emit([0; 4], BROADCAST_IP, &mut datagram);
+        assert_eq!(Udp::parse(&datagram, [0; 4], BROADCAST_IP), Some(udp));
+    }
+
+    #[test]
+    fn udp_without_checksum()
+    {
+        let mut datagram = Vec::new();
+        Udp { src_port: 1,
+              dst_port: 2,
+              payload: b"abc" }.emit(HOST, PEER, &mut datagram);
+        datagram[6 .. 8].fill(0);
+        let parsed = Udp::parse(&datagram, PEER, HOST).unwrap();
+        assert_eq!(parsed.payload, b"abc");
+    }
+
+    #[test]
+    fn icmp_roundtrip()
+    {
+        let icmp = Icmp { kind: ICMP_ECHO_REQUEST,
+                          code: 0,
+                          rest: [0x12, 0x34, 0x00, 0x01],
+                          payload: b"ping" };
+        let mut msg = Vec::new();
+        icmp.emit(&mut msg);
+        assert_eq!(Icmp::parse(&msg), Some(icmp));
+        msg[9] ^= 0x1;
+        assert_eq!(Icmp::parse(&msg), None);
+        // The reply echoes the identifier, sequence number, and payload.
+        let reply = Icmp { kind: ICMP_ECHO_REPLY,
+                           ..icmp };
+        msg.clear();
+        reply.emit(&mut msg);
+        let ip = Ipv4 { src: PEER,
+                        dst: HOST,
+                        protocol: PROTOCOL_ICMP,
+                        payload: &msg };
+        let mut packet = Vec::new();
+        ip.emit(&mut packet);
+        let parsed = Ipv4::parse(&packet).unwrap();
+        assert_eq!(parsed.protocol, PROTOCOL_ICMP);
+        assert_eq!(Icmp::parse(parsed.payload), Some(reply));
+    }
+}
@@ -15,24 +15,14 @@ extern crate alloc;
 
 use alloc::vec::Vec;
 
+use crate::display::DISPLAY;
 use crate::irq::IRQ;
 use crate::sync::{Lazy, Lock};
-use crate::PERRY_RANGE;
 
-/// Pixel valve IRQ.
-#[cfg(not(hdmi))]
-const PV_IRQ: u32 = 142;
-#[cfg(hdmi)]
-const PV_IRQ: u32 = 133;
-/// Pixel valve base address.
-#[cfg(not(hdmi))]
-const PV_BASE: usize = 0x2207000 + PERRY_RANGE.start;
-#[cfg(hdmi)]
-const PV_BASE: usize = 0x220A000 + PERRY_RANGE.start;
-/// Pixel valve interrupt enable register.
-const PV_INTEN: *mut u32 = (PV_BASE + 0x24) as _;
-/// Pixel valve status and acknowledgement register.
-const PV_STAT: *mut u32 = (PV_BASE + 0x28) as _;
+/// Pixel valve interrupt enable register offset.
+const PV_INTEN: usize = 0x24;
+/// Pixel valve status and acknowledgement register offset.
+const PV_STAT: usize = 0x28;
 /// Pixel valve VSync interrupt flag.
 const PV_VSYNC: u32 = 0x10;
 
@@ -43,6 +33,8 @@ pub static PIXVALVE: Lazy<PixelValve> = Lazy::new(PixelValve::new);
 #[derive(Debug)]
 pub struct PixelValve
 {
+    /// Status and acknowledgement register.
+    stat: *mut u32,
     /// Vertical synchronization event handlers.
     vsync_hdlrs: Lock<Vec<fn()>>,
     /// Vertical synchronization event handlers scheduled to be added to the
@@ -57,13 +49,17 @@ impl PixelValve
     /// Returns the newly created driver instance.
     fn new() -> Self
     {
-        IRQ.register(PV_IRQ, Self::vsync);
+        let base = DISPLAY.pixel_valve_base();
+        let inten = (base + PV_INTEN) as *mut u32;
+        let stat = (base + PV_STAT) as *mut u32;
+        IRQ.register(DISPLAY.pixel_valve_irq(), Self::vsync);
         unsafe {
-            PV_STAT.write_volatile(PV_VSYNC);
-            let evs = PV_INTEN.read_volatile();
-            PV_INTEN.write_volatile(evs | PV_VSYNC);
+            stat.write_volatile(PV_VSYNC);
+            let evs = inten.read_volatile();
+            inten.write_volatile(evs | PV_VSYNC);
         }
-        Self { vsync_hdlrs: Lock::new(Vec::new()),
+        Self { stat,
+               vsync_hdlrs: Lock::new(Vec::new()),
                vsync_new_hdlrs: Lock::new(Vec::new()) }
     }
 
@@ -78,12 +74,14 @@ impl PixelValve
 
     /// Dispatches the vertical synchronization event to all the registered
     /// handlers.
-    fn vsync()
+    ///
+    /// Returns whether a vertical synchronization event was pending.
+    fn vsync() -> bool
     {
-        if unsafe { PV_STAT.read_volatile() } & PV_VSYNC == 0 {
-            return;
+        if unsafe { PIXVALVE.stat.read_volatile() } & PV_VSYNC == 0 {
+            return false;
         }
-        unsafe { PV_STAT.write_volatile(PV_VSYNC) };
+        unsafe { PIXVALVE.stat.write_volatile(PV_VSYNC) };
         // Append all scheduled handlers to the handler list.  Doing it this way avoids
         // a potential deadlock if a handler tries to schedule another handler, and also
         // avoids unnecessary memory allocations and deallocations that would result
@@ -93,5 +91,10 @@ impl PixelValve
         hdlrs.append(&mut *new_hdlrs);
         drop(new_hdlrs);
         hdlrs.iter().for_each(|hdlr| hdlr());
+        true
     }
 }
+
+unsafe impl Send for PixelValve {}
+
+unsafe impl Sync for PixelValve {}
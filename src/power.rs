@@ -0,0 +1,195 @@
+//! Power management driver.
+//!
+//! Controls the frequency of the ARM cores through the clock properties of the
+//! Video Core firmware [2], with turbo mode additionally raising the core
+//! voltage as configured in the firmware's configuration file.  Also provides
+//! orderly shutdown and reboot paths that give registered hooks a
+//! chance to flush any persistent state, stop the audio DMA transfers, halt the
+//! remaining logical CPUs, and finally reset the system through the power
+//! management watchdog.  The Video Core firmware does not expose a property to
+//! reset the system, so, like Linux [1], this driver arms the watchdog for a
+//! full reset, optionally flagging the reset partition that makes the firmware
+//! halt instead of booting the kernel again.
+//!
+//! [1]: https://github.com/raspberrypi/linux/blob/rpi-5.15.y/drivers/watchdog/bcm2835_wdt.c
+//! [2]: https://github.com/raspberrypi/firmware/wiki/Mailbox-property-interface
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+use core::sync::atomic::{fence, Ordering};
+
+use crate::audio::AUDIO;
+use crate::irq::IRQ;
+use crate::sync::{critical, Lazy, Lock};
+use crate::{debug, halt, mbox, HALT_IRQ, PERRY_RANGE};
+
+/// Power management base address.
+const PM_BASE: usize = PERRY_RANGE.start + 0x2100000;
+/// Reset control register.
+const PM_RSTC: *mut u32 = (PM_BASE + 0x1C) as _;
+/// Reset status register.
+const PM_RSTS: *mut u32 = (PM_BASE + 0x20) as _;
+/// Watchdog timer register.
+const PM_WDOG: *mut u32 = (PM_BASE + 0x24) as _;
+/// Password required by every write to the power management registers.
+const PM_PASSWORD: u32 = 0x5A000000;
+/// Reset control configuration field mask.
+const PM_RSTC_WRCFG: u32 = 0x30;
+/// Reset control configuration that performs a full reset.
+const PM_RSTC_WRCFG_FULL_RESET: u32 = 0x20;
+/// Reset status partition flags that tell the firmware to halt after reset.
+const PM_RSTS_HALT: u32 = 0x555;
+/// Watchdog ticks before the reset, with each tick lasting about 16
+/// microseconds.
+const WDOG_TICKS: u32 = 10;
+
+/// Get clock rate property tag.
+const GET_CLOCK_RATE_TAG: u32 = 0x30002;
+/// Get maximum clock rate property tag.
+const GET_MAX_CLOCK_RATE_TAG: u32 = 0x30004;
+/// Get minimum clock rate property tag.
+const GET_MIN_CLOCK_RATE_TAG: u32 = 0x30007;
+/// Get turbo property tag.
+const GET_TURBO_TAG: u32 = 0x30009;
+/// Set clock rate property tag.
+const SET_CLOCK_RATE_TAG: u32 = 0x38002;
+/// Set turbo property tag.
+const SET_TURBO_TAG: u32 = 0x38009;
+/// Firmware identifier of the ARM clock.
+const ARM_CLOCK_ID: u32 = 3;
+/// Firmware identifier of the turbo setting.
+const TURBO_ID: u32 = 0;
+
+/// Global power management driver instance.
+pub static POWER: Lazy<Power> = Lazy::new(Power::new);
+
+/// Power management driver.
+#[derive(Debug)]
+pub struct Power
+{
+    /// Hooks to run before shutting down.
+    hooks: Lock<Vec<fn()>>,
+}
+
+impl Power
+{
+    /// Creates and initializes a new power management driver.
+    ///
+    /// Returns the newly created driver.
+    fn new() -> Self
+    {
+        Self { hooks: Lock::new(Vec::new()) }
+    }
+
+    /// Registers a hook to run before shutting down, such as one that flushes
+    /// saved state to storage.  Hooks run in registration order on the logical
+    /// CPU initiating the shutdown while the rest of the system is still fully
+    /// operational.
+    ///
+    /// * `hook`: Function to run.
+    pub fn register(&self, hook: fn())
+    {
+        self.hooks.lock().push(hook);
+    }
+
+    /// Returns the current frequency of the ARM cores in hertz.
+    pub fn arm_clock(&self) -> u32
+    {
+        let out: [u32; 2];
+        mbox! {GET_CLOCK_RATE_TAG: ARM_CLOCK_ID => out};
+        out[1]
+    }
+
+    /// Returns the range of frequencies supported by the ARM cores in hertz.
+    pub fn arm_clock_range(&self) -> RangeInclusive<u32>
+    {
+        let min: [u32; 2];
+        let max: [u32; 2];
+        mbox! {
+            GET_MIN_CLOCK_RATE_TAG: ARM_CLOCK_ID => min,
+            GET_MAX_CLOCK_RATE_TAG: ARM_CLOCK_ID => max,
+        };
+        min[1] ..= max[1]
+    }
+
+    /// Changes the frequency of the ARM cores, which the firmware clamps to
+    /// the supported range and may still lower when throttling.
+    ///
+    /// * `rate`: Requested frequency in hertz.
+    ///
+    /// Returns the frequency actually set in hertz.
+    pub fn set_arm_clock(&self, rate: u32) -> u32
+    {
+        let out: [u32; 2];
+        // Leave the turbo setting alone.
+        mbox! {SET_CLOCK_RATE_TAG: [ARM_CLOCK_ID, rate, 1] => out};
+        debug!("ARM clock set to {}MHz", out[1] / 1000000);
+        out[1]
+    }
+
+    /// Returns whether turbo mode is enabled.
+    pub fn is_turbo(&self) -> bool
+    {
+        let out: [u32; 2];
+        mbox! {GET_TURBO_TAG: TURBO_ID => out};
+        out[1] != 0
+    }
+
+    /// Enables or disables turbo mode, which runs the ARM cores and the rest
+    /// of the SoC at their maximum frequencies and raises the core voltage to
+    /// match.
+    ///
+    /// * `enable`: Whether to enable turbo mode.
+    pub fn set_turbo(&self, enable: bool)
+    {
+        mbox! {SET_TURBO_TAG: [TURBO_ID, enable as u32] => _};
+    }
+
+    /// Shuts the system down and reboots it.
+    pub fn reboot(&self) -> !
+    {
+        debug!("Rebooting");
+        self.shutdown(false)
+    }
+
+    /// Shuts the system down and leaves it halted until power is cycled.
+    pub fn power_off(&self) -> !
+    {
+        debug!("Powering off");
+        self.shutdown(true)
+    }
+
+    /// Runs the shutdown sequence and resets the system.
+    ///
+    /// * `should_halt`: Whether the firmware should halt after the reset
+    ///   instead of booting again.
+    fn shutdown(&self, should_halt: bool) -> !
+    {
+        let count = self.hooks.lock().len();
+        for idx in 0 .. count {
+            let hook = self.hooks.lock()[idx];
+            hook();
+        }
+        {
+            let _critical = critical();
+            AUDIO.lock().stop();
+        }
+        IRQ.notify_others(HALT_IRQ);
+        let _critical = critical();
+        IRQ.disable_all();
+        fence(Ordering::AcqRel);
+        unsafe {
+            if should_halt {
+                let val = PM_RSTS.read_volatile();
+                PM_RSTS.write_volatile(val | PM_PASSWORD | PM_RSTS_HALT);
+            }
+            PM_WDOG.write_volatile(PM_PASSWORD | WDOG_TICKS);
+            let val = PM_RSTC.read_volatile();
+            PM_RSTC.write_volatile(val & !PM_RSTC_WRCFG | PM_PASSWORD | PM_RSTC_WRCFG_FULL_RESET);
+        }
+        fence(Ordering::Release);
+        halt()
+    }
+}
@@ -0,0 +1,180 @@
+//! Scoped profiler.
+//!
+//! Measures how long labeled scopes take to run using the ARM generic timer,
+//! and keeps the minimum, mean, and maximum durations of each label in a table
+//! that can be sent through the UART.  Scopes are declared with the
+//! [`profile!`](crate::profile!) macro.  This module is only built with the
+//! `profile` option, and the macro expands to nothing otherwise, so
+//! instrumentation can be left in hot paths at no cost.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::fmt::Write;
+
+use crate::sync::{critical, Lock};
+use crate::uart::UART;
+
+/// Global profiler instance.
+pub static PROFILE: Profiler = Profiler::new();
+
+/// Measures the time from this point until the end of the enclosing scope,
+/// recording it under the specified label.
+#[macro_export]
+macro_rules! profile {
+    ($label:expr) => {
+        let _profile = $crate::profile::Scope::new($label);
+    };
+}
+
+/// Profiler.
+#[derive(Debug)]
+pub struct Profiler
+{
+    /// Measurements in order of first appearance.
+    entries: Lock<Vec<Entry>>,
+}
+
+/// Guard that records the time elapsed since its creation when dropped.
+#[derive(Debug)]
+pub struct Scope
+{
+    /// Label to record under.
+    label: &'static str,
+    /// Generic timer count at creation.
+    start: u64,
+}
+
+/// Measurements of a single label.
+#[derive(Debug)]
+struct Entry
+{
+    /// Label.
+    label: &'static str,
+    /// Number of measurements.
+    count: u64,
+    /// Sum of all the measured durations in timer ticks.
+    total: u64,
+    /// Shortest measured duration in timer ticks.
+    min: u64,
+    /// Longest measured duration in timer ticks.
+    max: u64,
+}
+
+impl Profiler
+{
+    /// Creates and initializes a new profiler.
+    ///
+    /// Returns the newly created profiler.
+    const fn new() -> Self
+    {
+        Self { entries: Lock::new(Vec::new()) }
+    }
+
+    /// Records a measurement.
+    ///
+    /// * `label`: Label to record under.
+    /// * `ticks`: Measured duration in timer ticks.
+    fn record(&self, label: &'static str, ticks: u64)
+    {
+        let _critical = critical();
+        let mut entries = self.entries.lock();
+        if let Some(entry) = entries.iter_mut().find(|entry| entry.label == label) {
+            entry.count += 1;
+            entry.total += ticks;
+            entry.min = entry.min.min(ticks);
+            entry.max = entry.max.max(ticks);
+            return;
+        }
+        let entry = Entry { label,
+                            count: 1,
+                            total: ticks,
+                            min: ticks,
+                            max: ticks };
+        entries.push(entry);
+    }
+
+    /// Sends a table with the measurements of every label through the UART.
+    pub fn dump(&self)
+    {
+        let freq = frequency();
+        let nanos = |ticks: u64| (ticks as u128 * 1000000000 / freq as u128) as u64;
+        let entries = {
+            let _critical = critical();
+            self.entries
+                .lock()
+                .iter()
+                .map(|entry| (entry.label, entry.count, entry.total / entry.count, entry.min, entry.max))
+                .collect::<Vec<_>>()
+        };
+        let mut uart = UART.lock();
+        writeln!(uart,
+                 "{:<24} {:>10} {:>12} {:>12} {:>12}",
+                 "LABEL", "COUNT", "MIN (ns)", "AVG (ns)", "MAX (ns)").unwrap();
+        for (label, count, mean, min, max) in entries {
+            writeln!(uart,
+                     "{:<24} {:>10} {:>12} {:>12} {:>12}",
+                     label,
+                     count,
+                     nanos(min),
+                     nanos(mean),
+                     nanos(max)).unwrap();
+        }
+    }
+
+    /// Discards all measurements.
+    pub fn reset(&self)
+    {
+        let _critical = critical();
+        self.entries.lock().clear();
+    }
+}
+
+impl Scope
+{
+    /// Creates and initializes a new scope guard.
+    ///
+    /// * `label`: Label to record under.
+    ///
+    /// Returns the newly created guard.
+    pub fn new(label: &'static str) -> Self
+    {
+        Self { label, start: ticks() }
+    }
+}
+
+impl Drop for Scope
+{
+    fn drop(&mut self)
+    {
+        PROFILE.record(self.label, ticks() - self.start);
+    }
+}
+
+/// Returns the current count of the generic timer.
+fn ticks() -> u64
+{
+    let ticks: u64;
+    unsafe {
+        asm!(
+            "isb",
+            "mrs {ticks}, cntpct_el0",
+            ticks = out (reg) ticks,
+            options (nomem, nostack, preserves_flags));
+    }
+    ticks
+}
+
+/// Returns the frequency of the generic timer in hertz.
+fn frequency() -> u64
+{
+    let freq: u64;
+    unsafe {
+        asm!(
+            "mrs {freq}, cntfrq_el0",
+            freq = out (reg) freq,
+            options (nomem, nostack, preserves_flags));
+    }
+    freq
+}
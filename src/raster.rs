@@ -0,0 +1,203 @@
+//! Fragment coverage.
+//!
+//! Decides which fragments belong to a triangle from their barycentric
+//! coordinates, for groups of 2x2 fragments as well as for groups of 4x2 made
+//! of two groups of 2x2 side by side, so that both of the rasterizer's inner
+//! loops produce the same images.  Fragments lying exactly on an edge belong
+//! to the triangle only if the edge owns them, which happens for exactly one
+//! of the two triangles sharing the edge, so that meshes have neither gaps
+//! nor fragments drawn twice along their seams.
+
+use core::simd::prelude::*;
+
+use crate::simd::{SimdPartialEqExtra, SimdPartialOrdExtra};
+
+/// Ownership of the fragments lying on the edges of a triangle.
+#[derive(Clone, Copy, Debug)]
+pub struct Edges
+{
+    /// Whether each edge owns the fragments lying on it, indexed by the
+    /// barycentric coordinate that is zero along the edge.
+    owns: [bool; 3],
+}
+
+impl Edges
+{
+    /// Creates and initializes the edges of a triangle.
+    ///
+    /// * `hincs`: Change in each barycentric coordinate from one column to the
+    ///   next, in the first three lanes.
+    /// * `vincs`: Change in each barycentric coordinate from one row to the
+    ///   next, in the first three lanes.
+    ///
+    /// Returns the newly created edges.
+    pub fn new(hincs: f32x4, vincs: f32x4) -> Self
+    {
+        // An edge owns its fragments when the triangle lies to its left, or on
+        // the side of the earlier rows if it's horizontal.
+        let owns = hincs.simd_ltz() | hincs.simd_eqz() & vincs.simd_ltz();
+        Self { owns: [owns.test(0), owns.test(1), owns.test(2)] }
+    }
+
+    /// Validates the fragments in a group of 2x2.
+    ///
+    /// * `bary`: Barycentric coordinates of the fragments.
+    ///
+    /// Returns a mask of the fragments that belong to the triangle.
+    pub fn cover(&self, bary: [f32x4; 3]) -> mask32x4
+    {
+        let [owns0, owns1, owns2] = self.owns.map(mask32x4::splat);
+        let [bary0, bary1, bary2] = bary;
+        (bary0.simd_gtz() | bary0.simd_eqz() & owns0)
+        & (bary1.simd_gtz() | bary1.simd_eqz() & owns1)
+        & (bary2.simd_gtz() | bary2.simd_eqz() & owns2)
+    }
+
+    /// Validates the fragments in a group of 4x2 made of two groups of 2x2
+    /// side by side, with the first group in the lower half of the vectors.
+    ///
+    /// * `bary`: Barycentric coordinates of the fragments.
+    ///
+    /// Returns a mask of the fragments that belong to the triangle.
+    #[cfg(any(test, raster8))]
+    pub fn cover8(&self, bary: [f32x8; 3]) -> mask32x8
+    {
+        let zero = f32x8::splat(0.0);
+        let [owns0, owns1, owns2] = self.owns.map(mask32x8::splat);
+        let [bary0, bary1, bary2] = bary;
+        (bary0.simd_gt(zero) | bary0.simd_eq(zero) & owns0)
+        & (bary1.simd_gt(zero) | bary1.simd_eq(zero) & owns1)
+        & (bary2.simd_gt(zero) | bary2.simd_eq(zero) & owns2)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    extern crate alloc;
+
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// Width of the rendered images, which isn't a multiple of 4 so that the
+    /// groups of 4x2 leave a group of 2x2 at the end of each row.
+    const WIDTH: usize = 10;
+
+    /// Renders a triangle, laid out like the rasterizer does with the
+    /// fragments of each group of 2x2 in row-major order.
+    ///
+    /// * `verts`: Vertices at the positions of fragments, in an order that
+    ///   makes the triangle's area positive.
+    /// * `height`: Number of rows, which must be even.
+    /// * `wide`: Whether to test as much of each row as possible in groups of
+    ///   4x2 first.
+    ///
+    /// Returns the rows of the image, with `#` marking the covered fragments.
+    fn render(verts: [(f32, f32); 3], height: usize, wide: bool) -> Vec<String>
+    {
+        // Linear barycentric coordinates, computed the same way as the
+        // rasterizer does so that fragments on edges get exact zeros.
+        let bary = |col: usize, row: usize| {
+            let [(x0, y0), (x1, y1), (x2, y2)] = verts.map(|(x, y)| (x - col as f32, y - row as f32));
+            let areas = [x1 * y2 - x2 * y1, x2 * y0 - x0 * y2, x0 * y1 - x1 * y0];
+            let total = areas[0] + areas[1] + areas[2];
+            areas.map(|area| area / total)
+        };
+        let group = |col: usize, row: usize| {
+            let frags = [(col, row), (col + 1, row), (col, row + 1), (col + 1, row + 1)];
+            [0, 1, 2].map(|idx| f32x4::from_array(frags.map(|(col, row)| bary(col, row)[idx])))
+        };
+        let [origin, right, down] = [bary(0, 0), bary(1, 0), bary(0, 1)];
+        let incs = |next: [f32; 3]| f32x4::from_array([0, 1, 2, 0].map(|idx| next[idx] - origin[idx]));
+        let edges = Edges::new(incs(right), incs(down));
+        let mut image = vec![vec![b'.'; WIDTH]; height];
+        let mut plot = |col: usize, row: usize, valid: u64| {
+            for lane in (0 .. 8).filter(|lane| valid & 1 << lane != 0) {
+                let col = col + lane / 4 * 2 + lane % 2;
+                image[row + lane % 4 / 2][col] = b'#';
+            }
+        };
+        for row in (0 .. height).step_by(2) {
+            let mut col = 0;
+            while wide && col + 2 < WIDTH {
+                let (left, right) = (group(col, row), group(col + 2, row));
+                let bary = [0, 1, 2].map(|idx| simd_swizzle!(left[idx], right[idx], [0, 1, 2, 3, 4, 5, 6, 7]));
+                plot(col, row, edges.cover8(bary).to_bitmask());
+                col += 4;
+            }
+            while col < WIDTH {
+                plot(col, row, edges.cover(group(col, row)).to_bitmask());
+                col += 2;
+            }
+        }
+        image.into_iter().map(|row| String::from_utf8(row).unwrap()).collect()
+    }
+
+    /// Renders a triangle in groups of both sizes and compares the images
+    /// against the expected one.
+    ///
+    /// * `verts`: Vertices of the triangle.
+    /// * `golden`: Expected image.
+    fn assert_renders(verts: [(f32, f32); 3], golden: &[&str])
+    {
+        assert_eq!(render(verts, golden.len(), false), golden);
+        assert_eq!(render(verts, golden.len(), true), golden);
+    }
+
+    #[test]
+    fn golden()
+    {
+        assert_renders([(1.0, 1.0), (8.0, 2.0), (3.0, 7.0)],
+                       &["..........",
+                         "..........",
+                         "..#######.",
+                         "..######..",
+                         "...####...",
+                         "...###....",
+                         "...##.....",
+                         ".........."]);
+    }
+
+    #[test]
+    fn shared_edges()
+    {
+        // A square split along a diagonal that runs through fragments.
+        let upper = ["..........",
+                     "..........",
+                     "...#####..",
+                     "....####..",
+                     ".....###..",
+                     "......##..",
+                     ".......#..",
+                     ".........."];
+        let lower = ["..........",
+                     "..........",
+                     "..#.......",
+                     "..##......",
+                     "..###.....",
+                     "..####....",
+                     "..#####...",
+                     "..######.."];
+        assert_renders([(1.0, 1.0), (7.0, 1.0), (7.0, 7.0)], &upper);
+        assert_renders([(1.0, 1.0), (7.0, 7.0), (1.0, 7.0)], &lower);
+        for (upper, lower) in upper.iter().zip(lower) {
+            assert!(upper.bytes().zip(lower.bytes()).all(|pair| pair != (b'#', b'#')));
+        }
+    }
+
+    #[test]
+    fn edge_extensions()
+    {
+        // Fragments on the lines through the edges but outside the triangle,
+        // such as the vertices owned by only one of their edges, are left out.
+        assert_renders([(0.0, 0.0), (8.0, 4.0), (2.0, 2.0)],
+                       &["..........",
+                         "..#.......",
+                         "...##.....",
+                         "......#...",
+                         "..........",
+                         ".........."]);
+    }
+}
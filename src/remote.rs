@@ -0,0 +1,242 @@
+//! Remote debugging.
+//!
+//! Mirrors the diagnostic output sent through the UART to a host on the
+//! network, and listens for single line text commands on a UDP port so that a
+//! board mounted where its serial console can't be reached can still be
+//! debugged.  Commands are matched by their first word, answered with a single
+//! line, and include `log`, which starts mirroring the output to the port the
+//! command was sent from, `nolog`, which stops mirroring, `pause` and
+//! `resume`, which control the tasks that check in with the remote debugger,
+//! as well as any commands registered by other modules, which may take the
+//! rest of the line as arguments.  Other modules can also queue binary data,
+//! such as screenshots, to be sent to the same host as the mirrored output.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::str::from_utf8;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::clock::now;
+use crate::net::{Datagram, Ipv4Address, UdpSocket, NET, UDP_PAYLOAD_MAX};
+use crate::sched::{bounded, timeout, BoundedReceiver, BoundedSender, Scheduler};
+use crate::sync::{Lazy, Lock, Notify};
+use crate::uart::UART;
+
+/// UDP port that commands are received on.
+const COMMAND_PORT: u16 = 7777;
+/// Time in milliseconds between log flushes.
+const FLUSH_PERIOD: u64 = 100;
+/// Time in milliseconds between pause checks of paused tasks.
+const PAUSE_PERIOD: u64 = 100;
+/// Maximum number of binary transfers waiting to be sent.
+const OUTBOX_LEN: usize = 2;
+
+/// Name of a command and function to call when it's received.
+type Command = (&'static str, Handler);
+
+/// Global remote debugger instance.
+pub static REMOTE: Lazy<Remote> = Lazy::new(Remote::new);
+
+/// Function handling a command.
+#[derive(Clone, Copy, Debug)]
+enum Handler
+{
+    /// Function taking no arguments.
+    Plain(fn()),
+    /// Function taking the rest of the command line, which returns whether
+    /// the arguments were valid.
+    Args(fn(&str) -> bool),
+}
+
+/// Remote debugger.
+#[derive(Debug)]
+pub struct Remote
+{
+    /// Address and port that the output is mirrored to, if any.
+    sink: Lock<Option<(Ipv4Address, u16)>>,
+    /// Registered commands.
+    commands: Lock<Vec<Command>>,
+    /// Sending end of the binary data waiting to be sent to the sink.
+    outbox_tx: BoundedSender<Vec<u8>>,
+    /// Receiving end of the binary data waiting to be sent to the sink.
+    outbox_rx: BoundedReceiver<Vec<u8>>,
+    /// Whether the tasks that check in are paused.
+    paused: AtomicBool,
+    /// Tasks waiting to be resumed.
+    resumed: Notify,
+}
+
+impl Remote
+{
+    /// Creates and initializes a new remote debugger.
+    ///
+    /// Returns the newly created debugger.
+    fn new() -> Self
+    {
+        let (outbox_tx, outbox_rx) = bounded(OUTBOX_LEN);
+        Self { sink: Lock::new(None),
+               commands: Lock::new(Vec::new()),
+               outbox_tx,
+               outbox_rx,
+               paused: AtomicBool::new(false),
+               resumed: Notify::new() }
+    }
+
+    /// Starts or stops mirroring the output to a host.
+    ///
+    /// * `sink`: Address and port to send the output to, or `None` to stop
+    ///   mirroring.
+    pub fn set_sink(&self, sink: Option<(Ipv4Address, u16)>)
+    {
+        *self.sink.lock() = sink;
+        UART.mirror(sink.is_some());
+    }
+
+    /// Queues binary data to be sent to the host that the output is mirrored
+    /// to, split into as many datagrams as necessary, waiting for earlier
+    /// transfers to go out if too many are already queued.
+    ///
+    /// * `data`: Data to send.
+    pub async fn send(&self, data: Vec<u8>)
+    {
+        // The receiving end lives as long as the debugger.
+        self.outbox_tx.send(data).await.ok();
+    }
+
+    /// Registers a command, replacing any command with the same name.
+    ///
+    /// * `name`: Word that invokes the command.
+    /// * `cmd`: Function to call when the command is received.
+    pub fn register(&self, name: &'static str, cmd: fn())
+    {
+        self.insert(name, Handler::Plain(cmd));
+    }
+
+    /// Registers a command that takes arguments, replacing any command with
+    /// the same name.
+    ///
+    /// * `name`: Word that invokes the command.
+    /// * `cmd`: Function to call with the rest of the command line when the
+    ///   command is received, which returns whether the arguments were valid.
+    pub fn register_with_args(&self, name: &'static str, cmd: fn(&str) -> bool)
+    {
+        self.insert(name, Handler::Args(cmd));
+    }
+
+    /// Adds a command, replacing any command with the same name.
+    ///
+    /// * `name`: Word that invokes the command.
+    /// * `handler`: Function to call when the command is received.
+    fn insert(&self, name: &'static str, handler: Handler)
+    {
+        let mut commands = self.commands.lock();
+        commands.retain(|(other, _)| *other != name);
+        commands.push((name, handler));
+    }
+
+    /// Returns whether the tasks that check in are paused.
+    pub fn is_paused(&self) -> bool
+    {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Waits for as long as the tasks that check in are paused.
+    pub async fn checkpoint(&self)
+    {
+        while self.is_paused() {
+            // A resume between the check and the registration is only noticed
+            // after the timeout.
+            timeout(PAUSE_PERIOD, self.resumed.notified()).await;
+        }
+    }
+
+    /// Receives commands and flushes the mirrored output periodically.
+    pub async fn run(&self) -> !
+    {
+        let socket = NET.bind(COMMAND_PORT).expect("Remote debugging port already bound");
+        let mut next_flush = 0;
+        loop {
+            if let Some(datagram) = timeout(FLUSH_PERIOD, socket.recv_from()).await {
+                self.answer(&socket, datagram).await;
+                // Commands sent in a burst are all answered before flushing.
+                while let Some(datagram) = socket.try_recv_from() {
+                    self.answer(&socket, datagram).await;
+                }
+            }
+            if now() < next_flush {
+                continue;
+            }
+            next_flush = now() + FLUSH_PERIOD;
+            let Some((addr, port)) = *self.sink.lock() else {
+                continue;
+            };
+            let output = UART.take_mirrored();
+            for chunk in output.chunks(UDP_PAYLOAD_MAX) {
+                // Lost output can't be reported without generating more.
+                socket.send_to(chunk, addr, port).await.ok();
+            }
+            while let Some(data) = self.outbox_rx.try_recv() {
+                for chunk in data.chunks(UDP_PAYLOAD_MAX) {
+                    socket.send_to(chunk, addr, port).await.ok();
+                    // Large transfers would otherwise starve the other tasks.
+                    Scheduler::relent().await;
+                }
+            }
+        }
+    }
+
+    /// Executes a received command and sends the reply back to where it came
+    /// from.
+    ///
+    /// * `socket`: Socket that the command was received on.
+    /// * `datagram`: Datagram containing the command.
+    async fn answer(&self, socket: &UdpSocket, datagram: Datagram)
+    {
+        let reply = self.execute(&datagram.payload, (datagram.src, datagram.src_port));
+        socket.send_to(reply.as_bytes(), datagram.src, datagram.src_port)
+              .await
+              .ok();
+    }
+
+    /// Executes a command.
+    ///
+    /// * `line`: Command line.
+    /// * `src`: Address and port that the command was sent from.
+    ///
+    /// Returns the reply to send.
+    fn execute(&self, line: &[u8], src: (Ipv4Address, u16)) -> &'static str
+    {
+        let line = from_utf8(line).map(str::trim).unwrap_or_default();
+        let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        if name.is_empty() {
+            return "Invalid command\n";
+        }
+        match name {
+            "log" => self.set_sink(Some(src)),
+            "nolog" => self.set_sink(None),
+            "pause" => self.paused.store(true, Ordering::Relaxed),
+            "resume" => {
+                self.paused.store(false, Ordering::Relaxed);
+                self.resumed.notify_all();
+            }
+            _ => {
+                let cmd = self.commands
+                              .lock()
+                              .iter()
+                              .find(|(other, _)| *other == name)
+                              .map(|(_, cmd)| *cmd);
+                match cmd {
+                    Some(Handler::Plain(cmd)) => cmd(),
+                    Some(Handler::Args(cmd)) => {
+                        if !cmd(args.trim()) {
+                            return "Invalid arguments\n";
+                        }
+                    }
+                    None => return "Unknown command\n",
+                }
+            }
+        }
+        "OK\n"
+    }
+}
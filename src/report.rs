@@ -0,0 +1,93 @@
+//! Boot budget report.
+//!
+//! Logs the size of each section of the kernel image along with how long the
+//! heap and each driver take to initialize, so that regressions in either
+//! image size or boot time show up in the diagnostic output of every build.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::ptr::addr_of;
+
+use crate::audio::AUDIO;
+use crate::board::INFO;
+use crate::clock::{now, now_micros};
+use crate::debug;
+use crate::display::DISPLAY;
+use crate::dma::DMA;
+use crate::gpio::GPIO;
+use crate::i2c::BSC;
+use crate::ipi::IPI;
+use crate::irq::IRQ;
+use crate::mbox::MBOX;
+use crate::mmu::MMU;
+use crate::pixvalve::PIXVALVE;
+use crate::sched::SCHED;
+use crate::scrub::SCRUB;
+use crate::sync::Lazy;
+use crate::thermal::THERMAL;
+use crate::timer::TIMER;
+use crate::touch::TOUCH;
+use crate::video::VIDEO;
+
+extern "C" {
+    /// Size of the code sections, defined by the linker script.
+    static text_size: u8;
+    /// Size of the read-only data section, defined by the linker script.
+    static rodata_size: u8;
+    /// Size of the initialized data section, defined by the linker script.
+    static data_size: u8;
+    /// Size of the zero-initialized data section, defined by the linker
+    /// script.
+    static bss_size: u8;
+}
+
+/// Initializes the heap and all the drivers in dependency order, logging
+/// section sizes and initialization times through the UART.
+pub fn report()
+{
+    let boot = now();
+    let (text, rodata, data, bss) = (addr_of!(text_size) as usize,
+                                     addr_of!(rodata_size) as usize,
+                                     addr_of!(data_size) as usize,
+                                     addr_of!(bss_size) as usize);
+    debug!("Sections: text: {text} bytes, rodata: {rodata} bytes, data: {data} bytes, bss: {bss} bytes");
+    let start = now_micros();
+    drop(Box::new(0u8));
+    debug!("Heap initialized in {}us", now_micros() - start);
+    init("IRQ", &IRQ);
+    init("Cross-core calls", &IPI);
+    init("GPIO", &GPIO);
+    init("I2C", &BSC);
+    init("Mailbox", &MBOX);
+    init("Board information", &INFO);
+    debug!("{}", *INFO);
+    init("Memory map", &MMU);
+    let cached = MMU.cached_range();
+    debug!("Heap covers {}MB", (cached.end - cached.start) >> 20);
+    init("Display", &DISPLAY);
+    init("Pixel valve", &PIXVALVE);
+    init("Timer", &TIMER);
+    init("Scheduler", &SCHED);
+    init("DMA", &DMA);
+    init("Audio", &AUDIO);
+    init("Video", &VIDEO);
+    init("Touch", &TOUCH);
+    init("Scrubber", &SCRUB);
+    init("Thermal monitor", &THERMAL);
+    debug!("Thermal status: {}", THERMAL.status());
+    debug!("Boot completed {}ms after power on, {}ms of which in the kernel",
+           now(),
+           now() - boot);
+}
+
+/// Initializes a driver and logs how long it took.
+///
+/// * `name`: Name of the driver.
+/// * `driver`: Lazily initialized driver instance.
+fn init<T: Send + Sync + 'static>(name: &str, driver: &Lazy<T>)
+{
+    let start = now_micros();
+    let _ = &**driver;
+    debug!("{name} initialized in {}us", now_micros() - start);
+}
@@ -0,0 +1,1531 @@
+//! Game scenes.
+//!
+//! Implements the boot splash, the main menu, the dungeon, and the pause
+//! screen on top of the generic scene stack, each owning the state and tasks
+//! that it needs to produce its draw commands.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::f32::consts::{FRAC_PI_2, PI, TAU};
+use core::future::Future;
+use core::iter::once;
+use core::mem::take;
+use core::ops::Range;
+use core::simd::f32x4;
+use core::str::from_utf8;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::assets::{parse_nbm, parse_ntx, parse_obj, ASSETS};
+use crate::audio::{Echo, Effects, Envelope, Instrument, Note, Pattern, Song, SoundEvent, Wave, AUDIO, MUSIC, SOUNDS};
+use crate::clock::now_micros;
+use crate::debug;
+use crate::emmc::STORAGE;
+use crate::game::{tick_creatures, Achievement, Camera, CameraLimits, CombatEvent, Creature, EconomyEvent, Fighter,
+                  Fog, GoldPiles, Imp, ImpState, Imps, Jobs, Map, Minimap, Rng, RoomKind, Rooms, Save, Scene, Spell,
+                  Stat, Stats, Terrain, Tile, Transition, Treasury, Wage, ACHIEVEMENTS};
+use crate::latency::{LatencyLog, Trace};
+use crate::math::{Aabb, Angle, Easing, IVec2, Keyframe, Quaternion, Rect, Spline, SplineKind, Track, Transform};
+use crate::sched::{select, JoinHandle, SCHED};
+use crate::simd::SimdFloatExtra;
+use crate::sync::{critical, snapshot, Lock, Publisher, Subscriber};
+use crate::timer::TIMER;
+use crate::touch::Recognizer;
+use crate::ui::{Anchor, Cutscene, Layout, Length, Notifications, ParseError, Severity};
+use crate::video::{aabb_lines, axes_lines, path_lines, Animation, Billboard, Cube, Light, Material, Model, Overlay,
+                   Pass, Rect as ScreenRect, Skeleton, SkinnedMesh, PARTICLES, VIDEO};
+
+/// Resting position of the cube.
+const CUBE_POS: f32x4 = f32x4::from_array([0.0, 0.0, -3.0, 1.0]);
+/// Keyframes of the cube on the boot splash, which stays on screen until the
+/// last one, as times in seconds, heights above the resting position, scales,
+/// turns about the vertical axis, and curves easing into the next keyframe.
+const SPLASH_KEYS: [(f32, f32, f32, f32, Easing); 6] = [(0.0, 2.0, 0.0, 0.0, Easing::Out),
+                                                        (0.5, 0.0, 1.0, 0.25, Easing::In),
+                                                        (1.0, 0.0, 1.0, 0.5, Easing::Linear),
+                                                        (1.5, 0.0, 1.0, 0.75, Easing::InOut),
+                                                        (1.75, 0.0, 1.0, 1.0, Easing::Step),
+                                                        (2.0, 0.0, 1.0, 1.0, Easing::Linear)];
+/// Time interval in milliseconds between checks of whether a scene task
+/// should stop.
+const STOP_PERIOD: u64 = 10;
+/// Range of camera pitches in radians in the dungeon.
+const CAMERA_PITCH: Range<f32> = -1.4 .. -0.2;
+/// Range of camera distances from the focus point in the dungeon.
+const CAMERA_DISTANCE: Range<f32> = 3.0 .. 12.0;
+/// Rotation of the light casting shadows in the dungeon around the vertical
+/// axis in radians.
+const SHADOW_YAW: f32 = 0.5;
+/// Pitch of the light casting shadows in the dungeon in radians.
+const SHADOW_PITCH: f32 = -1.2;
+/// Distance of the light casting shadows from the center of the dungeon.
+const SHADOW_DISTANCE: f32 = 16.0;
+/// Maximum distance that the camera focus can be panned from the cube along
+/// each ground axis.
+const CAMERA_REACH: f32x4 = f32x4::from_array([8.0, 0.0, 8.0, 0.0]);
+/// Time interval in milliseconds between game rule ticks.
+const TICK_PERIOD: u64 = 250;
+/// Width and height of the dungeon in tiles.
+const DUNGEON_SIZE: usize = 16;
+/// Number of tile rows along the back of the dungeon raised into a ridge.
+const RIDGE_ROWS: usize = 3;
+/// Rise of the ridge per tile row in units of corner height.
+const RIDGE_STEP: i16 = 2;
+/// Distance from the dungeon heart up to which the imps are set to dig.
+const DIG_RADIUS: usize = 3;
+/// Number of imps in the dungeon.
+const IMP_COUNT: usize = 3;
+/// Number of creatures in the demonstration dungeon.
+const CREATURE_COUNT: usize = 2;
+/// Gold in the treasury at the start of the demonstration dungeon.
+const START_GOLD: u32 = 500;
+/// Rule ticks between paydays.
+const PAYDAY_PERIOD: usize = 120;
+/// Hardware Video Scaler plane ID of the minimap.
+const MINIMAP_PLANE: u8 = 1;
+/// Display pixels along each axis per minimap tile.
+const MINIMAP_SCALE: usize = 8;
+/// Placement of the minimap on the display, 16 pixels away from its top right
+/// corner.
+const MINIMAP_LAYOUT: Layout = Layout::new(Anchor::TopRight).with_offset(Length::Pixels(16), Length::Pixels(16));
+/// Gold below which the player is warned that reserves are low.
+const LOW_GOLD: u32 = 100;
+/// Anger at which the player is warned about a creature's mood.
+const ANGER_ALERT: u32 = 500;
+/// Color of blood particles.
+const BLOOD_COLOR: f32x4 = f32x4::from_array([0.6, 0.0, 0.0, 1.0]);
+/// Number of blood particles splattered by a hit.
+const HIT_BLOOD: usize = 8;
+/// Number of blood particles splattered by a death.
+const DEATH_BLOOD: usize = 64;
+/// Maximum speed of blood particles in units per second.
+const BLOOD_SPEED: f32 = 1.0;
+/// Size of blood particles.
+const BLOOD_SIZE: f32 = 0.05;
+/// Lifetime of blood particles in seconds.
+const BLOOD_LIFETIME: f32 = 0.5;
+/// Height of the arc traced by spells above the ground.
+const SPELL_ARC_HEIGHT: f32 = 3.0;
+/// Distance between the sparks along the arc of a spell.
+const SPARK_SPACING: f32 = 0.25;
+/// Speed of sparks along the arc of a spell in units per second.
+const SPARK_SPEED: f32 = 0.5;
+/// Color of spell sparks.
+const SPARK_COLOR: f32x4 = f32x4::from_array([0.4, 0.6, 1.0, 1.0]);
+/// Length of the sides of spell sparks.
+const SPARK_SIZE: f32 = 0.08;
+/// Lifetime of spell sparks in seconds.
+const SPARK_LIFETIME: f32 = 0.75;
+/// Fraction of the screen's width taken by the overview of the dungeon shown
+/// along with the gizmos.
+const OVERVIEW_WIDTH: f32 = 0.3;
+/// Position of the camera looking straight down at the dungeon in the
+/// overview, high enough to fit the whole dungeon in its narrow viewport.
+const OVERVIEW_POS: f32x4 = f32x4::from_array([0.0, 16.0, 0.0, 1.0]);
+/// Fraction of the screen's height covered by each of the bars letterboxing
+/// the intro cutscene.
+const LETTERBOX_HEIGHT: f32 = 0.1;
+/// Color of the marker on the tile that spells are cast at.
+const TARGET_COLOR: f32x4 = f32x4::from_array([1.0, 1.0, 1.0, 0.4]);
+/// Length of the sides of the marker on the tile that spells are cast at.
+const TARGET_SIZE: f32 = 0.5;
+/// Color of the markers above the selected creatures.
+const SELECTION_COLOR: f32x4 = f32x4::from_array([0.2, 1.0, 0.2, 0.6]);
+/// Length of the sides of the markers above the selected creatures.
+const SELECTION_SIZE: f32 = 0.2;
+/// Light emitted by the selected creatures.
+const SELECTION_GLOW: f32x4 = f32x4::from_array([0.0, 0.3, 0.0, 0.0]);
+/// Maximum time in microseconds that a finger can rest on the screen for the
+/// touch to count as a tap.
+const TAP_DURATION: u64 = 250000;
+/// Maximum distance in pixels that a finger can move for the touch to count
+/// as a tap.
+const TAP_SLOP: f32 = 16.0;
+/// Spells along with the layouts of the buttons that cast them.
+const SPELL_BUTTONS: [(Spell, Layout); 4] = [(Spell::CreateImp, spell_button(0)),
+                                             (Spell::Heal, spell_button(1)),
+                                             (Spell::Lightning, spell_button(2)),
+                                             (Spell::Calm, spell_button(3))];
+/// Path of the saved game on the SD card.
+const SAVE_PATH: &str = "SAVE.BIN";
+/// Rule ticks between snapshots of the game for saving.
+const SAVE_PERIOD: usize = 40;
+/// Number of input events between input latency reports.
+const LATENCY_REPORT_INTERVAL: usize = 256;
+/// Effects that make the dungeon sound like a cave.
+const CAVE_EFFECTS: Effects = Effects { low_pass: Some(2000.0),
+                                        echo: Some(Echo { delay: 180,
+                                                          feedback: 0.35,
+                                                          mix: 0.4 }) };
+/// Name of the mesh asset drawn in place of the rainbow cube when present.
+const MODEL_ASSET: &str = "model.obj";
+/// Name of the binary mesh asset drawn in place of the rainbow cube when
+/// present, which takes precedence over the OBJ one.
+const MODEL_BIN_ASSET: &str = "model.nbm";
+/// Colors of the vertices of the model by material name, with white for any
+/// other material.
+const MODEL_PALETTE: [(&str, f32x4); 2] = [("gold", f32x4::from_array([1.0, 0.8, 0.2, 1.0])),
+                                           ("stone", f32x4::from_array([0.5, 0.5, 0.5, 1.0]))];
+/// Name of the texture asset applied to the rainbow cube when present.
+const CUBE_TEXTURE_ASSET: &str = "cube.ntx";
+/// Time in microseconds that the cube texture takes to scroll across each face.
+const CUBE_SCROLL_PERIOD: u64 = 8000000;
+/// Name of the cutscene asset played when entering the dungeon, which takes
+/// precedence over the built-in one.
+const INTRO_ASSET: &str = "intro.txt";
+/// Built-in cutscene played when entering the dungeon, with camera positions
+/// relative to the player's camera.
+const INTRO_SCRIPT: &str = "# Level intro.
+camera 0 0 6 6
+camera 1500 4 3 3
+camera 3000 0 0 0
+caption 0 1500 A new dungeon awaits.
+caption 1500 3000 Dig deep, keeper.
+stinger 0 440
+stinger 1500 660
+stinger 2900 880
+";
+/// Time in milliseconds between cutscene playback steps.
+const INTRO_STEP: u32 = 20;
+/// Scale of the cubes standing in for the creatures.
+const CREATURE_SCALE: f32 = 0.25;
+/// Intensity and exponent of the specular highlights on the creatures.
+const CREATURE_SPECULAR: (f32, u32) = (0.5, 16);
+/// Duration in seconds of the walk cycle of the creatures.
+const WALK_PERIOD: f32 = 1.0;
+/// Color of the routes walked by the imps when drawing gizmos.
+const ROUTE_COLOR: f32x4 = f32x4::from_array([1.0, 1.0, 0.0, 1.0]);
+
+/// Kind of room to build on a tile, or nothing to sell its furnishings, along
+/// with the position of the tile.
+type Furnishing = (Option<RoomKind>, (usize, usize));
+
+/// Whether the player asked to pause the game.
+static PAUSED: AtomicBool = AtomicBool::new(false);
+/// Whether debug gizmos are drawn over the scenery.
+static GIZMOS: AtomicBool = AtomicBool::new(false);
+/// Spells waiting to be cast by the game rules along with their target tiles.
+static CASTS: Lock<Vec<(Spell, (usize, usize))>> = Lock::new(Vec::new());
+/// Tiles waiting to be designated for digging by the game rules.
+static DIGS: Lock<Vec<(usize, usize)>> = Lock::new(Vec::new());
+/// Tiles waiting to be furnished by the game rules.
+static FURNISHINGS: Lock<Vec<Furnishing>> = Lock::new(Vec::new());
+/// Notices posted by the game rules.
+static NOTICES: Lock<Notifications> = Lock::new(Notifications::new());
+/// Statistics as of the last rule tick.
+static STATS: Lock<Stats> = Lock::new(Stats::new());
+/// Latest snapshot of the game serialized for saving, if any.
+static LATEST_SAVE: Lock<Option<Vec<u8>>> = Lock::new(None);
+/// Dungeon map played by the game rules along with its checksum as of the end
+/// of the last rule tick, if the dungeon has been entered.
+static DUNGEON: Lock<Option<(Map, u64)>> = Lock::new(None);
+/// Offset of the camera from the player's camera while a cutscene is playing.
+static INTRO_CAMERA: Lock<Option<f32x4>> = Lock::new(None);
+/// Terrain of the dungeon as of the last rule tick that changed it.
+static TERRAIN: Lock<Option<Arc<Model>>> = Lock::new(None);
+/// Positions of the creatures in world space as of the last rule tick.
+static CREATURES: Lock<Vec<f32x4>> = Lock::new(Vec::new());
+/// Routes left for the imps to walk in world space as of the last rule tick,
+/// only updated while gizmos are drawn.
+static ROUTES: Lock<Vec<Vec<f32x4>>> = Lock::new(Vec::new());
+/// Background music played in the dungeon.
+static DUNGEON_THEME: Song = Song { tempo: 240,
+                                    instruments: &[Instrument { wave: Wave::Triangle,
+                                                                envelope: Envelope { attack: 10,
+                                                                                     decay: 200,
+                                                                                     sustain: 0.6,
+                                                                                     release: 150 },
+                                                                pan: 0.0 },
+                                                   Instrument { wave: Wave::Sawtooth,
+                                                                envelope: Envelope { attack: 30,
+                                                                                     decay: 300,
+                                                                                     sustain: 0.2,
+                                                                                     release: 400 },
+                                                                pan: 0.3 }],
+                                    patterns: &[Pattern { steps: 16,
+                                                          notes: &[bass(0, 110),
+                                                                   bass(4, 110),
+                                                                   bass(8, 131),
+                                                                   bass(12, 98),
+                                                                   lead(0, 440),
+                                                                   lead(6, 523),
+                                                                   lead(12, 494)] },
+                                                Pattern { steps: 16,
+                                                          notes: &[bass(0, 87),
+                                                                   bass(4, 87),
+                                                                   bass(8, 82),
+                                                                   bass(12, 82),
+                                                                   lead(0, 349),
+                                                                   lead(8, 330),
+                                                                   lead(12, 415)] }],
+                                    order: &[0, 0, 1, 0] };
+
+/// Scenes of the game.
+#[derive(Debug)]
+pub enum GameScene
+{
+    /// Boot splash.
+    Splash(Splash),
+    /// Main menu.
+    Menu(Menu),
+    /// Dungeon, boxed since its state dwarfs that of the other scenes.
+    InGame(Box<InGame>),
+    /// Pause screen covering the dungeon.
+    Paused,
+}
+
+/// Boot splash, animating the cube along a track until it ends.
+#[derive(Debug)]
+pub struct Splash
+{
+    /// Time in microseconds at which the splash was entered.
+    start: u64,
+    /// Animation of the cube.
+    track: Track,
+    /// Scenery.
+    view: View,
+}
+
+/// Main menu, left by touching the screen.
+#[derive(Debug)]
+pub struct Menu
+{
+    /// Gesture recognizer.
+    recog: Recognizer,
+    /// Scenery.
+    view: View,
+}
+
+/// Dungeon, where the camera responds to gestures.
+#[derive(Debug)]
+pub struct InGame
+{
+    /// Gesture recognizer.
+    recog: Recognizer,
+    /// Scenery.
+    view: View,
+    /// Camera controller.
+    camera: Camera,
+    /// State of the touch in progress.
+    touch: TouchState,
+    /// Publisher of the camera to world transformation.
+    cam_pub: Publisher<Transform>,
+    /// Subscriber to the camera to world transformation.
+    cam_sub: Subscriber<Transform>,
+    /// Time in microseconds at which the camera was last updated.
+    last: u64,
+    /// Time in microseconds at which the last gesture was recognized.
+    recognized: u64,
+    /// Time in microseconds at which the last simulation step completed.
+    simulated: u64,
+    /// Time in microseconds at which the newest notice shown as a toast was
+    /// posted.
+    toasted: u64,
+    /// Input latency log.
+    latency: LatencyLog,
+    /// Tasks running while the scene is on the stage.
+    tasks: Vec<SceneTask>,
+    /// Skeleton of the creatures.
+    skeleton: Skeleton,
+    /// Walk cycle of the creatures.
+    walk: Animation,
+    /// Mesh of the creatures bound to their skeleton.
+    body: SkinnedMesh,
+    /// Indices of the creatures selected by dragging across the screen.
+    selected: Vec<usize>,
+}
+
+/// State of a touch, used to tell taps apart from other gestures.
+#[derive(Clone, Copy, Debug)]
+enum TouchState
+{
+    /// No fingers on the screen.
+    Released,
+    /// One finger resting on the screen, which may still turn out to be a
+    /// tap.
+    Tap
+    {
+        /// Time in microseconds at which the finger touched the screen.
+        start: u64,
+        /// Position where the finger touched the screen.
+        origin: f32x4,
+        /// Last position of the finger.
+        last: f32x4,
+    },
+    /// One finger held still on the screen for longer than a tap and then
+    /// dragged across it to select the creatures in a rectangle.
+    Select
+    {
+        /// Position where the finger touched the screen.
+        origin: f32x4,
+        /// Last position of the finger.
+        last: f32x4,
+    },
+    /// Any other gesture.
+    Gesture,
+}
+
+/// Camera, lighting, and cube or model shared by the scenes.
+#[derive(Debug)]
+struct View
+{
+    /// Field of view.
+    fov: Angle,
+    /// Camera to world transformation.
+    cam: Transform,
+    /// Cube geometry.
+    cube: Cube,
+    /// Model drawn in place of the cube, if one could be loaded.
+    model: Option<Model>,
+    /// Material of the cube, textured if a texture could be loaded.
+    material: Material,
+    /// Position of the cube.
+    pos: f32x4,
+    /// Orientation of the cube.
+    rot: Quaternion,
+    /// Scale of the cube.
+    scale: f32,
+    /// Lights.
+    lights: Arc<Vec<Light>>,
+}
+
+/// Task started by a scene that stops once the scene exits.
+#[derive(Debug)]
+struct SceneTask
+{
+    /// Whether the task should stop.
+    stop: Arc<AtomicBool>,
+    /// Handle to await on the task's termination.
+    handle: JoinHandle<()>,
+}
+
+impl GameScene
+{
+    /// Creates and initializes the first scene.
+    ///
+    /// Returns the newly created scene.
+    pub fn new() -> Self
+    {
+        Self::Splash(Splash { start: 0,
+                              track: splash_track(),
+                              view: View::new() })
+    }
+
+    /// Toggles the pause screen while in the dungeon.
+    pub fn toggle_pause()
+    {
+        PAUSED.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    /// Toggles the debug gizmos showing the axes and bounds of the cube.
+    pub fn toggle_gizmos()
+    {
+        GIZMOS.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    /// Queues the designation of a tile in the dungeon for digging from a
+    /// remote command.
+    ///
+    /// * `args`: Column and row of the tile.
+    ///
+    /// Returns whether the arguments were valid.
+    pub fn dig(args: &str) -> bool
+    {
+        let mut args = args.split_whitespace();
+        let (Some(x), Some(y)) = (args.next(), args.next()) else {
+            return false;
+        };
+        let (Ok(x), Ok(y)) = (x.parse(), y.parse()) else {
+            return false;
+        };
+        DIGS.lock().push((x, y));
+        true
+    }
+
+    /// Queues the furnishing of a tile in the dungeon from a remote command.
+    ///
+    /// * `args`: Kind of room to build, or `sell` to sell the furnishings,
+    ///   followed by the column and row of the tile.
+    ///
+    /// Returns whether the arguments were valid.
+    pub fn furnish(args: &str) -> bool
+    {
+        let mut args = args.split_whitespace();
+        let (Some(kind), Some(x), Some(y)) = (args.next(), args.next(), args.next()) else {
+            return false;
+        };
+        let kind = match kind {
+            "lair" => Some(RoomKind::Lair),
+            "hatchery" => Some(RoomKind::Hatchery),
+            "treasury" => Some(RoomKind::Treasury),
+            "training" => Some(RoomKind::Training),
+            "sell" => None,
+            _ => return false,
+        };
+        let (Ok(x), Ok(y)) = (x.parse(), y.parse()) else {
+            return false;
+        };
+        FURNISHINGS.lock().push((kind, (x, y)));
+        true
+    }
+
+    /// Writes the latest snapshot of the game to the SD card, blocking until
+    /// done so that it can run right before a shutdown.
+    pub fn flush_save()
+    {
+        let Some(bytes) = LATEST_SAVE.lock().clone() else {
+            return;
+        };
+        match STORAGE.write(SAVE_PATH, &bytes) {
+            Ok(()) => debug!("Saved the game"),
+            Err(err) => debug!("Failed to save the game: {err}"),
+        }
+    }
+
+    /// Checks that the dungeon map hasn't changed since the end of the last
+    /// rule tick, which would mean that its memory was corrupted.
+    ///
+    /// Panics if the map doesn't match its checksum.
+    pub fn check_map()
+    {
+        if let Some((map, checksum)) = &*DUNGEON.lock() {
+            assert_eq!(map.checksum(), *checksum, "Dungeon map corrupted");
+        }
+    }
+}
+
+impl Scene for GameScene
+{
+    async fn enter(&mut self)
+    {
+        match self {
+            Self::Splash(splash) => splash.start = now_micros(),
+            Self::InGame(game) => {
+                PAUSED.store(false, Ordering::Relaxed);
+                game.last = now_micros();
+                MUSIC.lock().await.play(&DUNGEON_THEME);
+                set_effects(CAVE_EFFECTS);
+                let rot = Quaternion::from_euler(SHADOW_YAW, SHADOW_PITCH, 0.0);
+                let pos = (f32x4::from_array([0.0, 0.0, SHADOW_DISTANCE, 0.0]) * rot).xyz1();
+                VIDEO.set_shadows(Some(Transform::from_components(pos, rot, 1.0)), Angle::from(FRAC_PI_2));
+                game.tasks.push(SceneTask::spawn("particles", async {
+                                    PARTICLES.run().await;
+                                }));
+                game.tasks.push(SceneTask::spawn("rules", async {
+                                    run_rules().await;
+                                }));
+                game.tasks.push(SceneTask::spawn("intro", async {
+                                    play_intro().await;
+                                }));
+            }
+            Self::Paused => {
+                debug!("Messages:");
+                NOTICES.lock().scrollback().for_each(|notice| debug!("{notice}"));
+                let stats = STATS.lock().clone();
+                debug!("Statistics:");
+                for stat in [Stat::GoldMined, Stat::CreaturesTrained, Stat::LevelsWon] {
+                    debug!("{stat:?}: {}", stats.get(stat));
+                }
+                ACHIEVEMENTS.iter()
+                            .filter(|achievement| stats.is_unlocked(achievement.name))
+                            .for_each(|achievement| debug!("Unlocked {}", achievement.name));
+            }
+            Self::Menu(_) => (),
+        }
+    }
+
+    async fn exit(&mut self)
+    {
+        if let Self::InGame(game) = self {
+            for task in game.tasks.drain(..) {
+                task.stop().await;
+            }
+            *INTRO_CAMERA.lock() = None;
+            SOUNDS.set_listener(None);
+            VIDEO.set_shadows(None, Angle::from(FRAC_PI_2));
+            MUSIC.lock().await.stop();
+            set_effects(Effects::default());
+        }
+    }
+
+    async fn update(&mut self) -> Transition<Self>
+    {
+        match self {
+            Self::Splash(splash) => {
+                let elapsed = (now_micros() - splash.start) as f32 / 1000000.0;
+                if !splash.track.is_finished(elapsed) {
+                    let trans = splash.track.sample(elapsed);
+                    splash.view.pos = trans.position();
+                    splash.view.rot = trans.rotation();
+                    splash.view.scale = trans.scale();
+                    return Transition::Stay;
+                }
+                Transition::Switch(Self::Menu(Menu { recog: Recognizer::new(),
+                                                     view: View::new() }))
+            }
+            Self::Menu(menu) => {
+                menu.recog.sample();
+                if menu.recog.first_position().is_none() {
+                    return Transition::Stay;
+                }
+                Transition::Switch(Self::InGame(Box::new(InGame::new())))
+            }
+            Self::InGame(game) => {
+                if PAUSED.load(Ordering::Relaxed) {
+                    return Transition::Push(Self::Paused);
+                }
+                game.update();
+                Transition::Stay
+            }
+            Self::Paused => {
+                if PAUSED.load(Ordering::Relaxed) {
+                    return Transition::Stay;
+                }
+                Transition::Pop
+            }
+        }
+    }
+
+    async fn draw(&mut self)
+    {
+        match self {
+            Self::Splash(splash) => splash.view.draw().await,
+            Self::Menu(menu) => menu.view.draw().await,
+            Self::InGame(game) => {
+                game.view.cam = *game.cam_sub.read();
+                let gizmos = GIZMOS.load(Ordering::Relaxed);
+                if gizmos {
+                    // Make room for the overview on the left side of the screen.
+                    let width = 1.0 - OVERVIEW_WIDTH;
+                    VIDEO.set_viewport(Some(ScreenRect::new(OVERVIEW_WIDTH, 0.0, width, 1.0)));
+                }
+                let intro = INTRO_CAMERA.lock().is_some();
+                if intro {
+                    let height = 1.0 - LETTERBOX_HEIGHT * 2.0;
+                    VIDEO.set_scissor(Some(ScreenRect::new(0.0, LETTERBOX_HEIGHT, 1.0, height)));
+                }
+                game.draw_terrain().await;
+                game.view.draw().await;
+                VIDEO.draw_particles(&PARTICLES, game.view.lights.clone(), game.view.cam, game.view.fov)
+                     .await;
+                game.draw_creatures().await;
+                game.draw_markers().await;
+                if gizmos {
+                    let lines = ROUTES.lock()
+                                      .iter()
+                                      .flat_map(|route| path_lines(route, ROUTE_COLOR))
+                                      .collect::<Vec<_>>();
+                    VIDEO.draw_lines(&lines, game.view.cam, game.view.fov).await;
+                    game.draw_overview().await;
+                }
+                VIDEO.set_viewport(None);
+                VIDEO.set_scissor(None);
+            }
+            Self::Paused => (),
+        }
+    }
+
+    fn presented(&mut self, time: u64)
+    {
+        if let Self::InGame(game) = self {
+            game.presented(time);
+        }
+    }
+}
+
+impl InGame
+{
+    /// Creates and initializes a new dungeon scene.
+    ///
+    /// Returns the newly created scene.
+    fn new() -> Self
+    {
+        let view = View::new();
+        let limits = CameraLimits { min_pitch: CAMERA_PITCH.start,
+                                    max_pitch: CAMERA_PITCH.end,
+                                    min_distance: CAMERA_DISTANCE.start,
+                                    max_distance: CAMERA_DISTANCE.end,
+                                    bounds: Aabb::new(view.pos - CAMERA_REACH, view.pos + CAMERA_REACH) };
+        let camera = Camera::new(view.pos, 0.0, CAMERA_PITCH.end, CAMERA_DISTANCE.start, limits);
+        let (cam_pub, cam_sub) = snapshot(camera.transform());
+        let (skeleton, walk, body) = creature_rig();
+        Self { recog: Recognizer::new(),
+               view,
+               camera,
+               touch: TouchState::Released,
+               cam_pub,
+               cam_sub,
+               last: 0,
+               recognized: 0,
+               simulated: 0,
+               toasted: 0,
+               latency: LatencyLog::new(LATENCY_REPORT_INTERVAL),
+               tasks: Vec::new(),
+               skeleton,
+               walk,
+               body,
+               selected: Vec::new() }
+    }
+
+    /// Moves the camera according to the recognized gestures, orbiting with
+    /// one finger, and panning, zooming, and turning with two.
+    fn update(&mut self)
+    {
+        let norm = Recognizer::WIDTH.min(Recognizer::HEIGHT).recip();
+        self.recog.sample();
+        self.recognized = now_micros();
+        self.detect_tap();
+        let trans = self.recog.translation_delta().mul_scalar(norm);
+        if self.recog.second_position().is_some() {
+            self.camera.pan(-trans[0], -trans[1]);
+            self.camera.zoom(self.recog.scale_delta());
+            // Twisting turns the dungeon along with the fingers.
+            let (_, _, twist) = self.recog.rotation_delta().to_euler();
+            self.camera.orbit(-twist, 0.0);
+        } else if self.recog.first_position().is_some() && !matches!(self.touch, TouchState::Select { .. }) {
+            self.camera.orbit(-trans[0] * PI, trans[1] * PI);
+        }
+        let now = now_micros();
+        self.camera.update((now - self.last) as f32 / 1000000.0);
+        self.last = now;
+        let mut cam = self.camera.transform();
+        if let Some(offset) = *INTRO_CAMERA.lock() {
+            let pos = cam.position() + offset.xyz0();
+            cam = Transform::from_components(pos, cam.rotation(), cam.scale());
+        }
+        self.cam_pub.publish_value(cam);
+        SOUNDS.set_listener(Some(cam));
+        self.simulated = now_micros();
+        self.show_toasts(now);
+    }
+
+    /// Shows the notices posted since the last update as toasts, which go to
+    /// the console as there's no text renderer yet.
+    ///
+    /// * `now`: Current time in microseconds.
+    fn show_toasts(&mut self, now: u64)
+    {
+        let notes = NOTICES.lock();
+        let shown = self.toasted;
+        for notice in notes.toasts(now).filter(|notice| notice.time > shown) {
+            debug!("{notice}");
+            self.toasted = notice.time;
+        }
+    }
+
+    /// Follows the touch in progress, handling the tap or selection once the
+    /// finger is lifted if the touch turns out to be one.
+    fn detect_tap(&mut self)
+    {
+        let now = now_micros();
+        self.touch = match (self.touch, self.recog.first_position(), self.recog.second_position()) {
+            (_, Some(_), Some(_)) => TouchState::Gesture,
+            (TouchState::Released, Some(pos), None) => TouchState::Tap { start: now,
+                                                                         origin: pos,
+                                                                         last: pos },
+            (TouchState::Tap { start, origin, .. }, Some(pos), None) => {
+                let moved = (pos - origin).len() > TAP_SLOP;
+                if moved {
+                    TouchState::Gesture
+                } else if now - start > TAP_DURATION {
+                    TouchState::Select { origin, last: pos }
+                } else {
+                    TouchState::Tap { start,
+                                      origin,
+                                      last: pos }
+                }
+            }
+            (TouchState::Tap { last, .. }, None, _) => {
+                self.tapped(last);
+                TouchState::Released
+            }
+            (TouchState::Select { origin, .. }, Some(pos), None) => TouchState::Select { origin, last: pos },
+            (TouchState::Select { origin, last }, None, _) => {
+                self.select(origin, last);
+                TouchState::Released
+            }
+            (TouchState::Gesture, Some(_), None) => TouchState::Gesture,
+            (_, None, _) => TouchState::Released,
+        };
+    }
+
+    /// Queues the spell of the tapped button, if any, to be cast at the tile
+    /// under the camera focus.
+    ///
+    /// * `pos`: Position of the tap on the touchscreen.
+    fn tapped(&self, pos: f32x4)
+    {
+        // The touchscreen's vertical axis points up, unlike the layout's.
+        let tap = IVec2::new(pos[0] as i32, (Recognizer::HEIGHT - pos[1]) as i32);
+        let screen = Rect::new(0, 0, Recognizer::WIDTH as i32, Recognizer::HEIGHT as i32);
+        let Some(&(spell, _)) = SPELL_BUTTONS.iter()
+                                             .find(|(_, layout)| layout.resolve(screen).contains(tap))
+        else {
+            return;
+        };
+        let Some(target) = tile_at(self.camera.focus()) else {
+            return;
+        };
+        CASTS.lock().push((spell, target));
+    }
+
+    /// Selects the creatures under a rectangle dragged across the screen.
+    ///
+    /// * `origin`: Position where the drag started on the touchscreen.
+    /// * `last`: Position where the drag ended on the touchscreen.
+    fn select(&mut self, origin: f32x4, last: f32x4)
+    {
+        // Both the touchscreen's and the selection rectangle's vertical axes
+        // point up.
+        let size = f32x4::from_array([Recognizer::WIDTH, Recognizer::HEIGHT, 1.0, 1.0]);
+        let (origin, last) = (origin / size, last / size);
+        let rect = ScreenRect::from_corners(origin[0], origin[1], last[0], last[1]);
+        let positions = CREATURES.lock().clone();
+        let creatures = positions.into_iter().enumerate().map(|(idx, pos)| {
+                                                             let (bounds, mdl) = creature_placement(pos);
+                                                             (idx, bounds, mdl)
+                                                         });
+        self.selected = VIDEO.select(rect, creatures, self.view.cam, self.view.fov);
+        debug!("Selected {} creatures", self.selected.len());
+    }
+
+    /// Records the latency of the input that led to the presented frame.
+    ///
+    /// * `time`: Time of the presentation in microseconds.
+    fn presented(&mut self, time: u64)
+    {
+        let Some(input) = self.recog.input_time() else {
+            return;
+        };
+        let trace = Trace { input,
+                            recognized: self.recognized,
+                            simulated: self.simulated,
+                            displayed: time };
+        self.latency.record(trace);
+        if self.latency.len() == LATENCY_REPORT_INTERVAL {
+            debug!("{}", self.latency);
+            self.latency = LatencyLog::new(LATENCY_REPORT_INTERVAL);
+        }
+    }
+
+    /// Queues the dungeon terrain for drawing as an occluder, so that whatever
+    /// the walls hide is culled for the rest of the frame.
+    async fn draw_terrain(&mut self)
+    {
+        let terrain = TERRAIN.lock().clone();
+        let Some(terrain) = terrain else {
+            return;
+        };
+        let mdl = terrain_transform();
+        if VIDEO.is_occluded(terrain.bounds(), mdl, self.view.cam, self.view.fov) {
+            return;
+        }
+        VIDEO.set_occluding(true);
+        VIDEO.draw_triangles(terrain.geom(),
+                             self.view.lights.clone(),
+                             mdl,
+                             self.view.cam,
+                             self.view.fov)
+             .await;
+        VIDEO.set_occluding(false);
+    }
+
+    /// Queues the terrain seen from straight above for drawing in the overview
+    /// on the left side of the screen.
+    async fn draw_overview(&mut self)
+    {
+        let terrain = TERRAIN.lock().clone();
+        let Some(terrain) = terrain else {
+            return;
+        };
+        let cam = Transform::from_components(OVERVIEW_POS, Quaternion::from_euler(0.0, -FRAC_PI_2, 0.0), 1.0);
+        VIDEO.set_viewport(Some(ScreenRect::new(0.0, 0.0, OVERVIEW_WIDTH, 1.0)));
+        VIDEO.draw_triangles(terrain.geom(),
+                             self.view.lights.clone(),
+                             terrain_transform(),
+                             cam,
+                             Angle::from(FRAC_PI_2))
+             .await;
+    }
+
+    /// Queues the markers on the tile that spells are cast at and above the
+    /// selected creatures for drawing over everything else, so that they can
+    /// be seen through walls.
+    async fn draw_markers(&mut self)
+    {
+        let positions = CREATURES.lock().clone();
+        let lift = f32x4::from_array([0.0, CREATURE_SCALE * 3.0, 0.0, 0.0]);
+        let mut markers = self.selected
+                              .iter()
+                              .filter_map(|&idx| positions.get(idx))
+                              .map(|&pos| Billboard::new(pos + lift, SELECTION_SIZE, SELECTION_SIZE, SELECTION_COLOR))
+                              .collect::<Vec<_>>();
+        if let Some(target) = tile_at(self.camera.focus()) {
+            let lift = f32x4::from_array([0.0, TARGET_SIZE, 0.0, 0.0]);
+            markers.push(Billboard::new(tile_center(target) + lift, TARGET_SIZE, TARGET_SIZE, TARGET_COLOR));
+        }
+        VIDEO.set_pass(Pass::Overlay);
+        VIDEO.draw_billboards(&markers, self.view.lights.clone(), self.view.cam, self.view.fov)
+             .await;
+        VIDEO.set_pass(Pass::Opaque);
+    }
+
+    /// Queues the creatures for drawing, skinned into the current frame of
+    /// their walk cycle, with the selected ones glowing.
+    async fn draw_creatures(&mut self)
+    {
+        let positions = CREATURES.lock().clone();
+        if positions.is_empty() {
+            return;
+        }
+        let pose = self.skeleton.pose(&self.walk, now_micros() as f32 / 1000000.0);
+        let tris = self.body.skin(&pose);
+        let (intensity, power) = CREATURE_SPECULAR;
+        let material = Material::default().with_specular(intensity, power);
+        for (idx, pos) in positions.into_iter().enumerate() {
+            let (bounds, mdl) = creature_placement(pos);
+            if VIDEO.is_occluded(bounds, mdl, self.view.cam, self.view.fov) {
+                continue;
+            }
+            if self.selected.contains(&idx) {
+                VIDEO.set_material(material.clone().with_emissive(SELECTION_GLOW));
+            } else {
+                VIDEO.set_material(material.clone());
+            }
+            VIDEO.draw_triangles(&tris, self.view.lights.clone(), mdl, self.view.cam, self.view.fov)
+                 .await;
+        }
+        VIDEO.set_material(Material::default());
+    }
+}
+
+impl View
+{
+    /// Creates and initializes the default scenery.
+    ///
+    /// Returns the newly created scenery.
+    fn new() -> Self
+    {
+        Self { fov: Angle::from(FRAC_PI_2),
+               cam: Transform::default(),
+               cube: Cube::new(),
+               model: load_model(),
+               material: load_cube_material(),
+               pos: CUBE_POS,
+               rot: Quaternion::default(),
+               scale: 1.0,
+               lights: Arc::new(vec![Light::new_omni(f32x4::splat(0.0), f32x4::splat(1.0), 10.0)]) }
+    }
+
+    /// Queues the cube, or the model replacing it, for drawing.
+    async fn draw(&self)
+    {
+        let mdl = Transform::from_components(self.pos, self.rot, self.scale);
+        // Models have no texture coordinates, so only the cube is textured.
+        let (geom, bounds, material) = match &self.model {
+            Some(model) => (model.geom(), model.bounds(), Material::default()),
+            None => {
+                let offset = (now_micros() % CUBE_SCROLL_PERIOD) as f32 / CUBE_SCROLL_PERIOD as f32;
+                let offset = f32x4::from_array([offset, 0.0, 0.0, 0.0]);
+                let material = self.material.clone().with_uv_transform(offset, f32x4::splat(1.0));
+                (self.cube.geom(), self.cube.bounds(), material)
+            }
+        };
+        if VIDEO.is_occluded(bounds, mdl, self.cam, self.fov) {
+            return;
+        }
+        // Only the lights reaching the object need to be shaded.
+        let reach = bounds.transform(mdl);
+        let lights = self.lights
+                         .iter()
+                         .filter(|light| light.reach().overlaps_aabb(reach))
+                         .copied()
+                         .collect::<Vec<_>>();
+        VIDEO.set_material(material);
+        VIDEO.draw_triangles(geom, Arc::new(lights), mdl, self.cam, self.fov)
+             .await;
+        VIDEO.set_material(Material::default());
+        if GIZMOS.load(Ordering::Relaxed) {
+            let mut lines = axes_lines(mdl, 1.5).to_vec();
+            lines.extend(aabb_lines(bounds, mdl, f32x4::splat(1.0)));
+            VIDEO.draw_lines(&lines, self.cam, self.fov).await;
+        }
+    }
+}
+
+/// Builds the animation of the cube on the boot splash, which drops down and
+/// grows into its resting position while spinning.
+///
+/// Returns the newly created track.
+fn splash_track() -> Track
+{
+    let up = f32x4::from_array([0.0, 1.0, 0.0, 0.0]);
+    let keys = SPLASH_KEYS.iter()
+                          .map(|&(time, height, scale, turns, easing)| {
+                              Keyframe { time,
+                                         pos: CUBE_POS + up.mul_scalar(height),
+                                         rot: Quaternion::from_axis_angle(up, Angle::from(turns * TAU)),
+                                         scale,
+                                         easing }
+                          })
+                          .collect();
+    Track::new(keys, false)
+}
+
+/// Builds the rig of the creatures, which are rainbow cubes whose upper half
+/// sways from side to side while the whole body bobs up and down.
+///
+/// Returns the skeleton, the walk cycle, and the mesh bound to the skeleton.
+fn creature_rig() -> (Skeleton, Animation, SkinnedMesh)
+{
+    let mut skeleton = Skeleton::new();
+    let body = skeleton.add_bone(None, Transform::default());
+    let head = skeleton.add_bone(Some(body), Transform::default());
+    let key = |time, height, sway| {
+        let up = f32x4::from_array([0.0, 1.0, 0.0, 0.0]);
+        let front = f32x4::from_array([0.0, 0.0, 1.0, 0.0]);
+        Keyframe { time,
+                   pos: f32x4::from_array([0.0, 0.0, 0.0, 1.0]) + up.mul_scalar(height),
+                   rot: Quaternion::from_axis_angle(front, Angle::from(sway)),
+                   scale: 1.0,
+                   easing: Easing::InOut }
+    };
+    let half = WALK_PERIOD / 2.0;
+    let mut walk = Animation::new(WALK_PERIOD, true);
+    walk.add_track(body,
+                   vec![key(0.0, 0.0, 0.0), key(half, 0.3, 0.0), key(WALK_PERIOD, 0.0, 0.0)]);
+    walk.add_track(head,
+                   vec![key(0.0, 0.0, -0.3), key(half, 0.0, 0.3), key(WALK_PERIOD, 0.0, -0.3)]);
+    let mesh = SkinnedMesh::bind(Cube::new().geom(), |pos| {
+        let weights = if pos[1] > 0.0 {
+            [0.0, 1.0, 0.0, 0.0]
+        } else {
+            [1.0, 0.0, 0.0, 0.0]
+        };
+        ([body as u8, head as u8, 0, 0], weights)
+    });
+    (skeleton, walk, mesh)
+}
+
+/// Loads the cutscene played when entering the dungeon from the asset store,
+/// falling back to the built-in one.
+///
+/// Returns the loaded cutscene.
+fn load_intro() -> Cutscene
+{
+    let builtin = || INTRO_SCRIPT.parse().unwrap();
+    let Some(src) = ASSETS.get(INTRO_ASSET) else {
+        return builtin();
+    };
+    match from_utf8(&src).map(str::parse::<Cutscene>) {
+        Ok(Ok(scene)) => scene,
+        Ok(Err(ParseError { line })) => {
+            debug!("Failed to load {INTRO_ASSET}: Invalid directive at line {line}");
+            builtin()
+        }
+        Err(err) => {
+            debug!("Failed to load {INTRO_ASSET}: {err}");
+            builtin()
+        }
+    }
+}
+
+/// Loads the model drawn in place of the rainbow cube from the asset store.
+///
+/// Returns the model, or `None` if there's no such asset or it couldn't be
+/// parsed.
+fn load_model() -> Option<Model>
+{
+    let mesh = if let Some(src) = ASSETS.get(MODEL_BIN_ASSET) {
+        parse_nbm(&src).map_err(|err| debug!("Failed to load {MODEL_BIN_ASSET}: {err}"))
+    } else {
+        let src = ASSETS.get(MODEL_ASSET)?;
+        parse_obj(&src).map_err(|err| debug!("Failed to load {MODEL_ASSET}: {err}"))
+    };
+    Some(Model::from_mesh(&mesh.ok()?, model_color))
+}
+
+/// Picks the color of the vertices of the model from the name of their
+/// material.
+///
+/// * `material`: Name of the material, if any.
+///
+/// Returns the picked color.
+fn model_color(material: Option<&str>) -> f32x4
+{
+    MODEL_PALETTE.iter()
+                 .find(|(name, _)| Some(*name) == material)
+                 .map_or(f32x4::splat(1.0), |(_, color)| *color)
+}
+
+/// Loads the material of the rainbow cube, applying the cube texture from the
+/// asset store if there is one.
+///
+/// Returns the material, which is untextured if the texture is missing or
+/// couldn't be parsed.
+fn load_cube_material() -> Material
+{
+    let Some(src) = ASSETS.get(CUBE_TEXTURE_ASSET) else {
+        return Material::default();
+    };
+    match parse_ntx(&src) {
+        Ok(texture) => Material::default().with_texture(Arc::new(texture)),
+        Err(err) => {
+            debug!("Failed to load {CUBE_TEXTURE_ASSET}: {err}");
+            Material::default()
+        }
+    }
+}
+
+/// Plays the cutscene introducing the dungeon, moving the camera, logging the
+/// captions, and playing the stingers.
+async fn play_intro()
+{
+    let scene = load_intro();
+    let mut player = scene.play();
+    let mut shown = None;
+    while let Some(stingers) = player.advance(INTRO_STEP) {
+        for freq in stingers {
+            let _critical = critical();
+            AUDIO.lock().play_tone(freq, 0.0);
+        }
+        let caption = player.caption();
+        if caption != shown {
+            caption.inspect(|text| debug!("{text}"));
+            shown = caption;
+        }
+        *INTRO_CAMERA.lock() = player.camera();
+        TIMER.sleep(u64::from(INTRO_STEP)).await;
+    }
+    *INTRO_CAMERA.lock() = None;
+}
+
+/// Runs the game rules at a fixed rate in a small dungeon where a few imps dig
+/// out the earth and gold around the dungeon heart.
+async fn run_rules() -> !
+{
+    let mut map = Map::new(DUNGEON_SIZE, DUNGEON_SIZE);
+    let heart = (DUNGEON_SIZE / 2, DUNGEON_SIZE / 2);
+    let mut jobs = Jobs::new();
+    let mut treasury = Treasury::new(START_GOLD, 0);
+    let mut piles = GoldPiles::new();
+    let mut imps = Imps::new(heart);
+    for y in heart.1 - DIG_RADIUS ..= heart.1 + DIG_RADIUS {
+        for x in heart.0 - DIG_RADIUS ..= heart.0 + DIG_RADIUS {
+            let dist = x.abs_diff(heart.0).max(y.abs_diff(heart.1));
+            match dist {
+                0 ..= 1 => map.set_tile((x, y), Tile::Claimed),
+                DIG_RADIUS => map.set_tile((x, y), Tile::Gold),
+                _ => (),
+            }
+            if dist > 1 {
+                jobs.designate((x, y));
+            }
+        }
+    }
+    // Raise the back of the dungeon into a ridge sloping down towards the
+    // heart.
+    for y in 0 .. RIDGE_ROWS {
+        let height = (RIDGE_ROWS - y) as i16 * RIDGE_STEP;
+        (0 ..= DUNGEON_SIZE).for_each(|x| map.set_corner_height((x, y), height));
+    }
+    (0 .. IMP_COUNT).for_each(|idx| imps.spawn((heart.0 - 1 + idx, heart.1 - 1)));
+    let spawn = |idx| Creature::new((heart.0 - 1 + idx, heart.1 + 1), Fighter::new(50, 10, 2), Wage::new(20));
+    let mut creatures = (0 .. CREATURE_COUNT).map(spawn).collect::<Vec<_>>();
+    let mut rng = Rng::new(now_micros());
+    let mut stats = Stats::new();
+    let loaded = load_save();
+    let is_loaded = loaded.is_some();
+    if let Some(save) = loaded {
+        // Digging orders aren't saved, so the ones issued for a new dungeon
+        // would only send the imps to tiles that may be long dug out.
+        jobs = Jobs::new();
+        Save { map,
+               creatures,
+               treasury,
+               stats,
+               rng } = save;
+    }
+    // Furnish the rows above and below the heart as a hatchery and a lair.
+    // Rooms aren't saved either, so a loaded dungeon gets them back for free.
+    let mut rooms = Rooms::new(&map);
+    let mut spare = treasury.clone();
+    let funds = if is_loaded { &mut spare } else { &mut treasury };
+    for x in heart.0 - 1 ..= heart.0 + 1 {
+        rooms.build(&map, (x, heart.1 - 1), RoomKind::Hatchery, funds);
+        rooms.build(&map, (x, heart.1 + 1), RoomKind::Lair, funds);
+    }
+    let mut fog = Fog::new(&map);
+    let mut minimap = Minimap::new(&map);
+    let mut terrain = Terrain::new();
+    let mut overlay = Overlay::new(MINIMAP_PLANE,
+                                   minimap.width(),
+                                   minimap.height(),
+                                   MINIMAP_SCALE,
+                                   MINIMAP_LAYOUT);
+    let mut events = Vec::new();
+    let (mut gold_low, mut angry, mut idle) = (false, false, false);
+    // The map is only changed while its lock is held by a rule tick, so that
+    // the scrubber can check it in between.
+    let checksum = map.checksum();
+    *DUNGEON.lock() = Some((map, checksum));
+    for tick in 1 .. {
+        TIMER.sleep(TICK_PERIOD).await;
+        let mut dungeon = DUNGEON.lock();
+        let Some((map, checksum)) = &mut *dungeon else {
+            unreachable!()
+        };
+        for (spell, pos) in take(&mut *CASTS.lock()) {
+            match spell.cast(pos, map, &mut creatures, &mut imps, &mut treasury, &mut events) {
+                Ok(()) => {
+                    SOUNDS.emit(SoundEvent::SpellCast, tile_center(pos));
+                    trace_spell(heart, pos);
+                }
+                Err(err) => debug!("Failed to cast {spell:?}: {err}"),
+            }
+        }
+        for pos in take(&mut *DIGS.lock()) {
+            if !map.is_diggable_from(heart, pos) {
+                debug!("No way to dig {pos:?} from the heart yet");
+            }
+            jobs.designate(pos);
+        }
+        let orders = take(&mut *FURNISHINGS.lock());
+        for &(kind, pos) in &orders {
+            let done = match kind {
+                Some(kind) => rooms.build(map, pos, kind, &mut treasury),
+                None => rooms.sell(map, pos, &mut treasury).is_some(),
+            };
+            if !done {
+                debug!("Failed to furnish {pos:?}");
+            }
+        }
+        if !orders.is_empty() {
+            for room in rooms.rooms() {
+                debug!("{:?} of {} tiles", room.kind(), room.tiles().len());
+            }
+        }
+        let before = map.clone();
+        imps.tick(map, &mut jobs, &mut piles, &mut treasury);
+        for pos in (0 .. DUNGEON_SIZE).flat_map(|y| (0 .. DUNGEON_SIZE).map(move |x| (x, y))) {
+            if !before.tile(pos).is_some_and(Tile::is_walkable) && map.tile(pos).is_some_and(Tile::is_walkable) {
+                SOUNDS.emit(SoundEvent::DigComplete, tile_center(pos));
+            }
+        }
+        rooms.update(map);
+        let sites = rooms.sites(&creatures);
+        tick_creatures(&mut creatures, map, sites, &mut rng, &mut events);
+        let attacks = creatures.iter()
+                               .map(|creature| creature.fighter.attack)
+                               .collect::<Vec<_>>();
+        rooms.apply(&mut creatures, &mut rng);
+        let trained = creatures.iter()
+                               .zip(attacks)
+                               .filter(|(creature, attack)| creature.fighter.attack > *attack)
+                               .count();
+        announce(stats.record(Stat::CreaturesTrained, trained as u64));
+        *CREATURES.lock() = creatures.iter()
+                                     .map(|creature| {
+                                         let lift = Terrain::elevation(map, creature.pos);
+                                         tile_center(creature.pos) + f32x4::from_array([0.0, lift, 0.0, 0.0])
+                                     })
+                                     .collect();
+        if GIZMOS.load(Ordering::Relaxed) {
+            *ROUTES.lock() = imps.imps().iter().filter_map(imp_route).collect();
+        }
+        let minions = imps.imps().iter().map(Imp::position);
+        fog.update(map, minions.chain(creatures.iter().map(|creature| creature.pos)));
+        treasury.produce_mana(map.count(Tile::Claimed));
+        if minimap.render(map, &rooms, &fog) {
+            overlay.update(minimap.pixels());
+        }
+        if terrain.build(map, &rooms, &fog) {
+            *TERRAIN.lock() = Some(Arc::new(Model::from_terrain(&terrain)));
+        }
+        events.drain(..).for_each(react);
+        if tick % PAYDAY_PERIOD == 0 {
+            let paid = treasury.pay(creatures.iter_mut().map(|creature| &mut creature.wage));
+            if paid < creatures.len() {
+                NOTICES.lock()
+                       .post(Severity::Warning, "Not enough gold to pay wages", now_micros());
+            }
+        }
+        // Gold lying around is on its way to the treasury.
+        let hauled = piles.piles().iter().map(|pile| pile.amount).sum::<u32>();
+        let low = treasury.gold().saturating_add(hauled) < LOW_GOLD;
+        if low && !gold_low {
+            NOTICES.lock()
+                   .post(Severity::Warning, "Gold reserves low", now_micros());
+        }
+        gold_low = low;
+        let done = jobs.pending() == 0;
+        if done && !idle {
+            NOTICES.lock()
+                   .post(Severity::Info, "The imps have nothing left to do", now_micros());
+        }
+        idle = done;
+        let mad = creatures.iter().any(|creature| creature.needs.anger >= ANGER_ALERT);
+        if mad && !angry {
+            NOTICES.lock()
+                   .post(Severity::Critical, "Your creatures are angry", now_micros());
+        }
+        angry = mad;
+        for event in treasury.drain_events() {
+            if let EconomyEvent::Deposited(amount) = event {
+                SOUNDS.emit(SoundEvent::GoldDeposited, tile_center(heart));
+                announce(stats.record(Stat::GoldMined, amount as u64));
+            }
+            debug!("Treasury: {event:?}");
+        }
+        *STATS.lock() = stats.clone();
+        if tick % SAVE_PERIOD == 0 {
+            let save = Save { map: map.clone(),
+                              creatures: creatures.clone(),
+                              treasury: treasury.clone(),
+                              stats: stats.clone(),
+                              rng: rng.clone() };
+            *LATEST_SAVE.lock() = Some(save.to_bytes());
+        }
+        *checksum = map.checksum();
+    }
+    unreachable!()
+}
+
+/// Posts a notice for each newly unlocked achievement.
+///
+/// * `achievements`: Achievements to announce.
+fn announce(achievements: Vec<&'static Achievement>)
+{
+    for achievement in achievements {
+        NOTICES.lock().post(Severity::Info,
+                            format!("Achievement unlocked: {}", achievement.name),
+                            now_micros());
+    }
+}
+
+/// Loads the game to resume, which is the latest snapshot if the dungeon has
+/// already been entered, or the one saved on the SD card otherwise.
+///
+/// Returns the loaded game, or nothing to start a new one.
+fn load_save() -> Option<Save>
+{
+    let bytes = match LATEST_SAVE.lock().clone() {
+        Some(bytes) => bytes,
+        None => match STORAGE.read(SAVE_PATH) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                debug!("No saved game: {err}");
+                return None;
+            }
+        },
+    };
+    match Save::from_bytes(&bytes) {
+        Ok(save) => Some(save),
+        Err(err) => {
+            debug!("Failed to load the saved game: {err}");
+            None
+        }
+    }
+}
+
+/// Replaces the effects applied to the audio mix.
+///
+/// * `effects`: Effects to apply.
+fn set_effects(effects: Effects)
+{
+    // The audio driver is shared with the DMA IRQ handler.
+    let _critical = critical();
+    AUDIO.lock().set_effects(effects);
+}
+
+/// Plays the sound and splatters the blood of a combat event.
+///
+/// * `event`: Event to react to.
+fn react(event: CombatEvent)
+{
+    let (pos, sound, blood) = match event {
+        CombatEvent::Hit { pos, .. } => (pos, SoundEvent::CreatureHit, HIT_BLOOD),
+        CombatEvent::Death { pos } => {
+            NOTICES.lock().post(Severity::Info, "A creature has died", now_micros());
+            (pos, SoundEvent::CreatureDeath, DEATH_BLOOD)
+        }
+    };
+    let pos = tile_center(pos).replace_lane::<1>(0.5);
+    SOUNDS.emit(sound, pos);
+    PARTICLES.burst(blood, pos, BLOOD_SPEED, BLOOD_COLOR, BLOOD_SIZE, BLOOD_LIFETIME);
+}
+
+/// Scatters sparks along the arc of a spell, drifting in the direction that
+/// the spell travels.
+///
+/// * `from`: Position of the tile the spell was cast from.
+/// * `to`: Position of the target tile.
+fn trace_spell(from: (usize, usize), to: (usize, usize))
+{
+    let (start, end) = (tile_center(from), tile_center(to));
+    let up = f32x4::from_array([0.0, SPELL_ARC_HEIGHT, 0.0, 0.0]);
+    let arc = Spline::new(SplineKind::Bezier, vec![start, start + up, end + up, end]);
+    let count = (arc.length() / SPARK_SPACING) as usize;
+    for idx in 0 ..= count {
+        let dist = idx as f32 * SPARK_SPACING;
+        let dir = arc.tangent(arc.param_at(dist)).normalize().unwrap_or_default();
+        if !PARTICLES.emit(arc.point_at(dist),
+                           dir.mul_scalar(SPARK_SPEED),
+                           SPARK_COLOR,
+                           SPARK_SIZE,
+                           SPARK_LIFETIME)
+        {
+            break;
+        }
+    }
+}
+
+/// Creates a bass note for the dungeon theme.
+///
+/// * `step`: Step at which the note starts.
+/// * `freq`: Frequency in hertz.
+///
+/// Returns the created note.
+const fn bass(step: u32, freq: u16) -> Note
+{
+    Note { step,
+           len: 3,
+           freq,
+           instrument: 0 }
+}
+
+/// Creates a lead note for the dungeon theme.
+///
+/// * `step`: Step at which the note starts.
+/// * `freq`: Frequency in hertz.
+///
+/// Returns the created note.
+const fn lead(step: u32, freq: u16) -> Note
+{
+    Note { step,
+           len: 4,
+           freq,
+           instrument: 1 }
+}
+
+/// Computes the world position of the center of a tile on the ground, with the
+/// dungeon centered on the origin and each tile one unit wide.
+///
+/// * `pos`: Position of the tile.
+///
+/// Returns the computed world position.
+fn tile_center(pos: (usize, usize)) -> f32x4
+{
+    let half = DUNGEON_SIZE as f32 / 2.0;
+    f32x4::from_array([pos.0 as f32 + 0.5 - half, 0.0, pos.1 as f32 + 0.5 - half, 1.0])
+}
+
+/// Computes the model to world transformation of the terrain, which is built
+/// in map space with the dungeon's top left corner at the origin.
+///
+/// Returns the computed transformation.
+fn terrain_transform() -> Transform
+{
+    let half = DUNGEON_SIZE as f32 / 2.0;
+    let pos = f32x4::from_array([-half, 0.0, -half, 1.0]);
+    Transform::from_components(pos, Quaternion::default(), 1.0)
+}
+
+/// Places a creature standing on the ground.
+///
+/// * `pos`: World position of the creature's feet.
+///
+/// Returns the bounding box of the creature in model space, which leaves room
+/// for the bobbing and swaying of its walk, and its model to world
+/// transformation.
+fn creature_placement(pos: f32x4) -> (Aabb, Transform)
+{
+    // The cube spans two units, so lift it to stand on the ground.
+    let lift = f32x4::from_array([0.0, CREATURE_SCALE, 0.0, 0.0]);
+    let mdl = Transform::from_components(pos + lift, Quaternion::default(), CREATURE_SCALE);
+    (Aabb::new(f32x4::splat(-1.5), f32x4::splat(1.5)), mdl)
+}
+
+/// Computes the route left for an imp to walk in world space.
+///
+/// * `imp`: Imp whose route is to be computed.
+///
+/// Returns the route starting at the imp's position, or `None` if the imp isn't
+/// walking anywhere.
+fn imp_route(imp: &Imp) -> Option<Vec<f32x4>>
+{
+    let (ImpState::Walking { path, .. } | ImpState::Hauling { path, .. }) = imp.state() else {
+        return None;
+    };
+    let route = once(imp.position()).chain(path.iter().rev().copied())
+                                    .map(tile_center)
+                                    .collect();
+    Some(route)
+}
+
+/// Finds the tile containing a world position on the ground.
+///
+/// * `point`: World position.
+///
+/// Returns the position of the tile, or nothing if the point is outside the
+/// dungeon.
+fn tile_at(point: f32x4) -> Option<(usize, usize)>
+{
+    let half = DUNGEON_SIZE as f32 / 2.0;
+    let (x, z) = (point.x() + half, point.z() + half);
+    let range = 0.0 .. DUNGEON_SIZE as f32;
+    if !range.contains(&x) || !range.contains(&z) {
+        return None;
+    }
+    Some((x as usize, z as usize))
+}
+
+/// Computes the layout of a spell button.
+///
+/// * `idx`: Index of the button from the left.
+///
+/// Returns the computed layout.
+const fn spell_button(idx: i32) -> Layout
+{
+    Layout::new(Anchor::BottomLeft).with_offset(Length::Pixels(16 + idx * 96), Length::Pixels(16))
+                                   .with_size(Length::Pixels(80), Length::Pixels(80))
+}
+
+impl SceneTask
+{
+    /// Spawns a task that runs until stopped.  Stopping the task drops its
+    /// future wherever it's waiting, which relies on every future that it
+    /// awaits, such as [`Sleep`](crate::timer::Sleep), withdrawing its waker
+    /// when dropped.
+    ///
+    /// * `name`: Name of the task.
+    /// * `fut`: Future to run, which may complete on its own.
+    ///
+    /// Returns the handle to the spawned task.
+    fn spawn(name: &'static str, fut: impl Future<Output = ()> + Send + 'static) -> Self
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let stopped = async move {
+            while !flag.load(Ordering::Relaxed) {
+                TIMER.sleep(STOP_PERIOD).await;
+            }
+        };
+        let handle = SCHED.spawn_named(name, async move {
+                              select(fut, stopped).await;
+                          });
+        Self { stop, handle }
+    }
+
+    /// Stops the task.
+    ///
+    /// Returns after the task terminates.
+    async fn stop(self)
+    {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.await;
+    }
+}
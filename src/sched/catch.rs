@@ -0,0 +1,180 @@
+//! Task panic recovery.
+//!
+//! The kernel is built with the abort panic strategy, so panics cannot unwind.
+//! Instead, catching futures poll their inner futures through an assembly
+//! trampoline that saves the callee-saved register state of the core before
+//! calling the poll function, and the panic handler restores that state, making
+//! the trampoline return as though the poll function had returned with a
+//! failure.  Doing both the save and the call in assembly means that no Rust
+//! function ever returns twice.  This is the same idea as C's `setjmp` and
+//! `longjmp`, and comes with the same caveat: nothing in the abandoned stack
+//! frames is dropped, so any locks held by a failed task at the time of the
+//! panic remain locked forever.  The failed future itself is leaked rather than
+//! dropped, since its state may be in the middle of being changed by the
+//! abandoned frames.  Only tasks that are not critical to the rest of the
+//! system should be allowed to fail.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::arch::global_asm;
+use core::future::Future;
+use core::mem::forget;
+use core::pin::Pin;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::task::{Context, Poll};
+
+use crate::cpu::{id as cpu_id, COUNT as CPU_COUNT};
+
+global_asm!(".globl catch_call",
+            "catch_call:",
+            "mov x9, sp",
+            "mrs x10, daif",
+            "stp x19, x20, [x0, #0x0]",
+            "stp x21, x22, [x0, #0x10]",
+            "stp x23, x24, [x0, #0x20]",
+            "stp x25, x26, [x0, #0x30]",
+            "stp x27, x28, [x0, #0x40]",
+            "stp x29, x30, [x0, #0x50]",
+            "stp x9, x10, [x0, #0x60]",
+            "stp d8, d9, [x0, #0x70]",
+            "stp d10, d11, [x0, #0x80]",
+            "stp d12, d13, [x0, #0x90]",
+            "stp d14, d15, [x0, #0xA0]",
+            "stp x29, x30, [sp, #-0x10]!",
+            "mov x29, sp",
+            "mov x0, x2",
+            "blr x1",
+            "ldp x29, x30, [sp], #0x10",
+            "mov x0, #0",
+            "ret",
+            ".globl restore_context",
+            "restore_context:",
+            "ldp x19, x20, [x0, #0x0]",
+            "ldp x21, x22, [x0, #0x10]",
+            "ldp x23, x24, [x0, #0x20]",
+            "ldp x25, x26, [x0, #0x30]",
+            "ldp x27, x28, [x0, #0x40]",
+            "ldp x29, x30, [x0, #0x50]",
+            "ldp x9, x10, [x0, #0x60]",
+            "ldp d8, d9, [x0, #0x70]",
+            "ldp d10, d11, [x0, #0x80]",
+            "ldp d12, d13, [x0, #0x90]",
+            "ldp d14, d15, [x0, #0xA0]",
+            "mov sp, x9",
+            "msr daif, x10",
+            "mov x0, #1",
+            "ret");
+
+extern "C" {
+    /// Saves the callee-saved registers, stack pointer, and interrupt mask,
+    /// and then calls a function.
+    ///
+    /// * `ctx`: Buffer to save the state into.
+    /// * `func`: Function to call.
+    /// * `arg`: Argument to pass to the function.
+    ///
+    /// Returns zero if the function returned, or one if `restore_context` was
+    /// called with the saved state instead.
+    fn catch_call(ctx: *mut SavedContext, func: unsafe extern "C" fn(*mut ()), arg: *mut ()) -> usize;
+
+    /// Restores a state previously saved by `catch_call`, making it return.
+    ///
+    /// * `ctx`: Buffer to restore the state from.
+    fn restore_context(ctx: *const SavedContext) -> !;
+}
+
+/// Recovery points of all cores.
+static RECOVERY: [AtomicPtr<SavedContext>; CPU_COUNT] = [const { AtomicPtr::new(null_mut()) }; CPU_COUNT];
+
+/// Error delivered through the join handle of a task that panicked.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TaskFailed;
+
+/// Future that turns panics of its inner future into errors.
+#[derive(Debug)]
+pub struct Catch<F: Future>
+{
+    /// Inner future, or `None` if it failed.
+    fut: Option<Pin<Box<F>>>,
+}
+
+/// Arguments and result of a poll called through the trampoline.
+struct Polling<'a, 'b, F: Future>
+{
+    /// Future to poll.
+    fut: Pin<&'a mut F>,
+    /// Context to poll the future with.
+    ctx: &'a mut Context<'b>,
+    /// Result of the poll, if it returned.
+    poll: Option<Poll<F::Output>>,
+}
+
+/// Saved core state.
+#[repr(C)]
+#[derive(Debug)]
+struct SavedContext
+{
+    /// Register values.
+    regs: [u64; 22],
+}
+
+impl<F: Future> Catch<F>
+{
+    /// Creates and initializes a new catching future.
+    ///
+    /// * `fut`: Future to poll.
+    ///
+    /// Returns the newly created future.
+    pub fn new(fut: F) -> Self
+    {
+        Self { fut: Some(Box::pin(fut)) }
+    }
+}
+
+impl<F: Future> Future for Catch<F>
+{
+    type Output = Result<F::Output, TaskFailed>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output>
+    {
+        let fut = self.fut.as_mut().expect("Catching future polled after failing");
+        let mut polling = Polling { fut: fut.as_mut(),
+                                    ctx,
+                                    poll: None };
+        let affinity = cpu_id();
+        let mut saved = SavedContext { regs: [0; 22] };
+        let prev = RECOVERY[affinity].swap(&mut saved, Ordering::SeqCst);
+        let failed = unsafe { catch_call(&mut saved, poll_trampoline::<F>, &mut polling as *mut _ as _) } != 0;
+        RECOVERY[affinity].store(prev, Ordering::SeqCst);
+        let Polling { poll, .. } = polling;
+        if failed {
+            // Returned from the panic handler with the inner future in an
+            // unknown state.
+            forget(self.fut.take());
+            return Poll::Ready(Err(TaskFailed));
+        }
+        poll.expect("Poll trampoline returned without polling").map(Ok)
+    }
+}
+
+/// Polls a future on behalf of a catching future.
+///
+/// * `polling`: Future to poll, context to poll it with, and where to store the
+///   result.
+unsafe extern "C" fn poll_trampoline<F: Future>(polling: *mut ())
+{
+    let polling = &mut *(polling as *mut Polling<F>);
+    polling.poll = Some(polling.fut.as_mut().poll(polling.ctx));
+}
+
+/// Resumes execution from the recovery point of the innermost catching future
+/// being polled on this core, if any.  Meant to be called by the panic handler.
+pub fn recover()
+{
+    let saved = RECOVERY[cpu_id()].load(Ordering::SeqCst);
+    if !saved.is_null() {
+        unsafe { restore_context(saved) }
+    }
+}
@@ -0,0 +1,222 @@
+//! Future combinators.
+//!
+//! Allow a single task to await on multiple futures at once without spawning
+//! auxiliary tasks.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+#[cfg(not(test))]
+use crate::timer::{Sleep, TIMER};
+
+/// Future that completes once both of its futures complete.
+#[derive(Debug)]
+pub struct Join<A: Future, B: Future>
+{
+    /// First future.
+    first: Pin<Box<A>>,
+    /// Second future.
+    second: Pin<Box<B>>,
+    /// Output of the first future once complete.
+    first_val: Option<A::Output>,
+    /// Output of the second future once complete.
+    second_val: Option<B::Output>,
+}
+
+/// Future that completes as soon as either of its futures completes.
+#[derive(Debug)]
+pub struct Select<A: Future, B: Future>
+{
+    /// First future.
+    first: Pin<Box<A>>,
+    /// Second future.
+    second: Pin<Box<B>>,
+}
+
+/// Output of a select future.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Either<A, B>
+{
+    /// The first future completed first.
+    First(A),
+    /// The second future completed first.
+    Second(B),
+}
+
+/// Future that completes once its inner future completes or a time interval
+/// elapses, whichever happens first.
+#[cfg(not(test))]
+#[derive(Debug)]
+pub struct Timeout<F: Future>
+{
+    /// Inner select future.
+    select: Select<F, Sleep>,
+}
+
+impl<A: Future, B: Future> Future for Join<A, B>
+{
+    type Output = (A::Output, B::Output);
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output>
+    {
+        if self.first_val.is_none() {
+            if let Poll::Ready(val) = self.first.as_mut().poll(ctx) {
+                self.first_val = Some(val);
+            }
+        }
+        if self.second_val.is_none() {
+            if let Poll::Ready(val) = self.second.as_mut().poll(ctx) {
+                self.second_val = Some(val);
+            }
+        }
+        if self.first_val.is_none() || self.second_val.is_none() {
+            return Poll::Pending;
+        }
+        let first = self.first_val.take().unwrap();
+        let second = self.second_val.take().unwrap();
+        Poll::Ready((first, second))
+    }
+}
+
+impl<A: Future, B: Future> Unpin for Join<A, B> {}
+
+impl<A: Future, B: Future> Future for Select<A, B>
+{
+    type Output = Either<A::Output, B::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output>
+    {
+        if let Poll::Ready(val) = self.first.as_mut().poll(ctx) {
+            return Poll::Ready(Either::First(val));
+        }
+        if let Poll::Ready(val) = self.second.as_mut().poll(ctx) {
+            return Poll::Ready(Either::Second(val));
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(not(test))]
+impl<F: Future> Future for Timeout<F>
+{
+    type Output = Option<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output>
+    {
+        match Pin::new(&mut self.select).poll(ctx) {
+            Poll::Ready(Either::First(val)) => Poll::Ready(Some(val)),
+            Poll::Ready(Either::Second(())) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Combines two futures into one that awaits on both concurrently.
+///
+/// * `first`: First future.
+/// * `second`: Second future.
+///
+/// Returns a future that, when awaited on, resolves to the outputs of both
+/// futures.
+pub fn join<A: Future, B: Future>(first: A, second: B) -> Join<A, B>
+{
+    Join { first: Box::pin(first),
+           second: Box::pin(second),
+           first_val: None,
+           second_val: None }
+}
+
+/// Combines two futures into one that awaits on whichever completes first,
+/// dropping the other.
+///
+/// * `first`: First future, which takes precedence if both are ready.
+/// * `second`: Second future.
+///
+/// Returns a future that, when awaited on, resolves to the output of the
+/// future that completed first.
+pub fn select<A: Future, B: Future>(first: A, second: B) -> Select<A, B>
+{
+    Select { first: Box::pin(first),
+             second: Box::pin(second) }
+}
+
+/// Limits the time spent awaiting on a future.
+///
+/// * `interval`: Maximum time interval to wait in milliseconds.
+/// * `fut`: Future to await on.
+///
+/// Returns a future that, when awaited on, resolves to the output of the inner
+/// future, or nothing if the time interval elapses first.
+#[cfg(not(test))]
+pub fn timeout<F: Future>(interval: u64, fut: F) -> Timeout<F>
+{
+    Timeout { select: select(fut, TIMER.sleep(interval)) }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use alloc::sync::Arc;
+    use alloc::task::Wake;
+    use core::task::Waker;
+
+    use super::*;
+
+    /// Waker that does nothing, since the tests poll by hand.
+    struct Idle;
+
+    impl Wake for Idle
+    {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Future that completes with a value after being polled a number of
+    /// times.
+    struct Countdown
+    {
+        /// Number of polls left before completing.
+        polls: usize,
+        /// Value to complete with.
+        val: u32,
+    }
+
+    impl Future for Countdown
+    {
+        type Output = u32;
+
+        fn poll(mut self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<u32>
+        {
+            self.polls -= 1;
+            if self.polls > 0 {
+                return Poll::Pending;
+            }
+            Poll::Ready(self.val)
+        }
+    }
+
+    #[test]
+    fn join_awaits_both()
+    {
+        let waker = Waker::from(Arc::new(Idle));
+        let mut ctx = Context::from_waker(&waker);
+        let mut fut = join(Countdown { polls: 1, val: 1 }, Countdown { polls: 3, val: 2 });
+        assert_eq!(Pin::new(&mut fut).poll(&mut ctx), Poll::Pending);
+        assert_eq!(Pin::new(&mut fut).poll(&mut ctx), Poll::Pending);
+        // The first future completed on the first poll and isn't polled again.
+        assert_eq!(Pin::new(&mut fut).poll(&mut ctx), Poll::Ready((1, 2)));
+    }
+
+    #[test]
+    fn select_prefers_first()
+    {
+        let waker = Waker::from(Arc::new(Idle));
+        let mut ctx = Context::from_waker(&waker);
+        let mut fut = select(Countdown { polls: 2, val: 1 }, Countdown { polls: 2, val: 2 });
+        assert_eq!(Pin::new(&mut fut).poll(&mut ctx), Poll::Pending);
+        assert_eq!(Pin::new(&mut fut).poll(&mut ctx), Poll::Ready(Either::First(1)));
+    }
+}
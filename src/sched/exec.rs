@@ -0,0 +1,545 @@
+//! Task executor.
+//!
+//! Keeps track of the spawned tasks and polls them from the scheduler IRQ on
+//! every logical CPU, taking them from the queue of the logical CPU that they
+//! are pinned to before the queue shared by all of them.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FormatResult, Write};
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use super::catch::{Catch, TaskFailed};
+use super::chan::{channel, Receiver, Sender};
+use crate::clock::now_micros;
+use crate::cpu::{id as cpu_id, COUNT as CPU_COUNT};
+use crate::irq::IRQ;
+use crate::profile;
+use crate::sync::{Lazy, Lock};
+use crate::uart::UART;
+
+/// Scheduler alarm IRQ.
+const SCHED_IRQ: u32 = 1;
+
+/// Global scheduler instance.
+pub static SCHED: Lazy<Scheduler> = Lazy::new(Scheduler::new);
+
+/// Task scheduler.
+pub struct Scheduler
+{
+    /// Tasks scheduled for polling on any logical CPU.
+    scheduled: Lock<VecDeque<Arc<dyn Task>>>,
+    /// Tasks scheduled for polling indexed by the logical CPU they are pinned
+    /// to.
+    pinned: [Lock<VecDeque<Arc<dyn Task>>>; CPU_COUNT],
+    /// All running tasks.
+    running: Lock<BTreeMap<u64, Arc<dyn Task>>>,
+    /// Spawned task counter.
+    count: AtomicU64,
+}
+
+/// Future that can be awaited on until its corresponding task terminates.
+#[derive(Debug)]
+pub struct JoinHandle<T: Copy + Send>
+{
+    /// Receiving end of the notification channel.
+    rx: Receiver<T>,
+}
+
+/// Future that returns pending on the first poll and ready on subsequent polls.
+#[derive(Debug)]
+pub struct Relent
+{
+    /// Whether this future has been polled.
+    is_ready: bool,
+}
+
+/// Task status.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Status
+{
+    /// Being polled.
+    Running,
+    /// Scheduled for polling.
+    Ready,
+    /// Waiting to be woken up.
+    Waiting,
+}
+
+/// Task state.
+#[derive(Debug)]
+struct State<T: Copy + Send, F: Future<Output = T> + Send + 'static>
+{
+    /// Task identifier.
+    id: u64,
+    /// Task name.
+    name: Option<&'static str>,
+    /// Logical CPU to which the task is pinned, if any.
+    affinity: Option<usize>,
+    /// Whether the task is active.
+    is_active: AtomicBool,
+    /// Whether the task is being polled.
+    is_running: AtomicBool,
+    /// Total time spent polling the task in microseconds.
+    busy_time: AtomicU64,
+    /// Future polled by this task.
+    fut: Lock<Pin<Box<F>>>,
+    /// Join handler notification channel sender end.
+    tx: Lock<Option<Sender<T>>>,
+}
+
+/// Task waker.
+#[derive(Debug)]
+struct Alarm
+{
+    /// Task identifier.
+    id: u64,
+}
+
+/// Type-erased task state.
+trait Task: Send + Sync
+{
+    /// Returns the task's unique identifier.
+    fn id(&self) -> u64;
+
+    /// Returns the task's name, if it has one.
+    fn name(&self) -> Option<&'static str>;
+
+    /// Returns the logical CPU to which the task is pinned, if any.
+    fn affinity(&self) -> Option<usize>;
+
+    /// Returns the task's current status.
+    fn status(&self) -> Status;
+
+    /// Returns the total time spent polling the task in microseconds.
+    fn busy_time(&self) -> u64;
+
+    /// Sets the task to active and returns its previous status.
+    fn activate(&self) -> bool;
+
+    /// Resumes executing the task, notifying its join handler on completion.
+    ///
+    /// Returns whether the task has finished.
+    fn resume(&self) -> bool;
+}
+
+impl Scheduler
+{
+    /// Creates and initializes a new scheduler.
+    ///
+    /// Returns the created scheduler.
+    fn new() -> Self
+    {
+        IRQ.register(SCHED_IRQ, Self::poll);
+        Self { scheduled: Lock::new(VecDeque::new()),
+               pinned: [const { Lock::new(VecDeque::new()) }; CPU_COUNT],
+               running: Lock::new(BTreeMap::new()),
+               count: AtomicU64::new(1) /* Zero means no task. */ }
+    }
+
+    /// Spawns a new task.
+    ///
+    /// * `fut`: Future to poll to completion.
+    ///
+    /// Returns a join handle that can be used to await for the termination of
+    /// the new task and obtain the result of the future.
+    pub fn spawn<T: Send + Copy + 'static>(&self, fut: impl Future<Output = T> + Send + 'static) -> JoinHandle<T>
+    {
+        self.spawn_task(None, None, fut)
+    }
+
+    /// Spawns a new task that only ever runs on the specified logical CPU,
+    /// which is useful to keep latency-critical tasks away from logical CPUs
+    /// busy with long running work.
+    ///
+    /// * `cpu`: Logical CPU to pin the task to.
+    /// * `fut`: Future to poll to completion.
+    ///
+    /// Returns a join handle that can be used to await for the termination of
+    /// the new task and obtain the result of the future.
+    ///
+    /// Panics if the logical CPU is out of range.
+    #[track_caller]
+    pub fn spawn_pinned<T: Send + Copy + 'static>(&self, cpu: usize, fut: impl Future<Output = T> + Send + 'static)
+                                                  -> JoinHandle<T>
+    {
+        assert!(cpu < CPU_COUNT, "Logical CPU #{cpu} is out of range");
+        self.spawn_task(None, Some(cpu), fut)
+    }
+
+    /// Spawns a new task pinned to the specified logical CPU with a name that
+    /// identifies it in diagnostics.
+    ///
+    /// * `name`: Name of the task.
+    /// * `cpu`: Logical CPU to pin the task to.
+    /// * `fut`: Future to poll to completion.
+    ///
+    /// Returns a join handle that can be used to await for the termination of
+    /// the new task and obtain the result of the future.
+    ///
+    /// Panics if the logical CPU is out of range.
+    #[track_caller]
+    pub fn spawn_pinned_named<T: Send + Copy + 'static>(&self, name: &'static str, cpu: usize,
+                                                        fut: impl Future<Output = T> + Send + 'static)
+                                                        -> JoinHandle<T>
+    {
+        assert!(cpu < CPU_COUNT, "Logical CPU #{cpu} is out of range");
+        self.spawn_task(Some(name), Some(cpu), fut)
+    }
+
+    /// Spawns a new task with a name that identifies it in diagnostics.
+    ///
+    /// * `name`: Name of the task.
+    /// * `fut`: Future to poll to completion.
+    ///
+    /// Returns a join handle that can be used to await for the termination of
+    /// the new task and obtain the result of the future.
+    pub fn spawn_named<T: Send + Copy + 'static>(&self, name: &'static str,
+                                                 fut: impl Future<Output = T> + Send + 'static)
+                                                 -> JoinHandle<T>
+    {
+        self.spawn_task(Some(name), None, fut)
+    }
+
+    /// Spawns a new task whose panics are caught instead of halting the
+    /// system.  See the caveats in the catch module before using this.
+    ///
+    /// * `fut`: Future to poll to completion.
+    ///
+    /// Returns a join handle that can be used to await for the termination of
+    /// the new task and obtain either the result of the future or an error if
+    /// it panicked.
+    pub fn spawn_catching<T: Send + Copy + 'static>(&self, fut: impl Future<Output = T> + Send + 'static)
+                                                    -> JoinHandle<Result<T, TaskFailed>>
+    {
+        self.spawn_task(None, None, Catch::new(fut))
+    }
+
+    /// Sends a table describing every running task through the UART.
+    pub fn dump(&self)
+    {
+        let tasks = self.running.lock().values().cloned().collect::<Vec<_>>();
+        let mut uart = UART.lock();
+        writeln!(uart, "{:>6} {:<16} {:<8} {:>12}", "ID", "NAME", "STATUS", "BUSY (us)").unwrap();
+        for task in tasks {
+            writeln!(uart,
+                     "{:>6} {:<16} {:<8} {:>12}",
+                     task.id(),
+                     task.name().unwrap_or("-"),
+                     task.status(),
+                     task.busy_time()).unwrap();
+        }
+    }
+
+    /// Validates the consistency of the task queues.
+    ///
+    /// Panics with details if a scheduled task is not running, is not active,
+    /// is scheduled more than once, or is scheduled on the wrong logical CPU.
+    #[track_caller]
+    pub fn check_integrity(&self)
+    {
+        // Both locks must be held at once to get a consistent view.
+        let scheduled = self.scheduled.lock();
+        let running = self.running.lock();
+        for (idx, task) in scheduled.iter().enumerate() {
+            let id = task.id();
+            assert!(running.contains_key(&id), "Task #{id} is scheduled but not running");
+            assert!(task.status() != Status::Waiting,
+                    "Task #{id} is scheduled but not active");
+            assert!(scheduled.range(.. idx).all(|other| other.id() != id),
+                    "Task #{id} is scheduled more than once");
+            assert!(task.affinity().is_none(), "Pinned task #{id} is scheduled on any CPU");
+        }
+        for (cpu, pinned) in self.pinned.iter().enumerate() {
+            let pinned = pinned.lock();
+            for (idx, task) in pinned.iter().enumerate() {
+                let id = task.id();
+                assert!(running.contains_key(&id), "Task #{id} is scheduled but not running");
+                assert!(task.status() != Status::Waiting,
+                        "Task #{id} is scheduled but not active");
+                assert!(pinned.range(.. idx).all(|other| other.id() != id),
+                        "Task #{id} is scheduled more than once");
+                assert!(task.affinity() == Some(cpu),
+                        "Task #{id} is scheduled on CPU #{cpu} but not pinned to it");
+            }
+        }
+    }
+
+    /// Spawns a new task.
+    ///
+    /// * `name`: Optional name of the task.
+    /// * `affinity`: Optional logical CPU to pin the task to.
+    /// * `fut`: Future to poll to completion.
+    ///
+    /// Returns a join handle that can be used to await for the termination of
+    /// the new task and obtain the result of the future.
+    fn spawn_task<T: Send + Copy + 'static>(&self, name: Option<&'static str>, affinity: Option<usize>,
+                                            fut: impl Future<Output = T> + Send + 'static)
+                                            -> JoinHandle<T>
+    {
+        let id = self.count.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = channel::<T>();
+        let state = State::new(id, name, affinity, fut, tx);
+        let state = Arc::new(state);
+        self.running.lock().insert(id, state.clone());
+        self.schedule(state);
+        JoinHandle::new(rx)
+    }
+
+    /// Queues a task for polling and notifies the logical CPUs that can poll
+    /// it.
+    ///
+    /// * `task`: Task to schedule.
+    fn schedule(&self, task: Arc<dyn Task>)
+    {
+        if let Some(cpu) = task.affinity() {
+            self.pinned[cpu].lock().push_back(task);
+            IRQ.notify_cpu(SCHED_IRQ, cpu);
+            return;
+        }
+        let mut scheduled = self.scheduled.lock();
+        scheduled.push_back(task);
+        let count = scheduled.len();
+        drop(scheduled);
+        if count == 1 {
+            IRQ.notify_self(SCHED_IRQ);
+        } else {
+            IRQ.notify_all(SCHED_IRQ);
+        }
+    }
+
+    /// Returns a future that, when awaited on, yields execution to the other
+    /// tasks in the active queue once.
+    pub fn relent() -> Relent
+    {
+        Relent::new()
+    }
+
+    /// Schedules a task to be polled.
+    ///
+    /// * `id`: Task identifier.
+    fn wake(&self, id: u64)
+    {
+        let task = self.running
+                       .lock()
+                       .get(&id)
+                       .expect("Attempted to wake  up a non-existing task")
+                       .clone();
+        if !task.activate() {
+            self.schedule(task);
+        }
+    }
+
+    /// IRQ handler that polls all active tasks, giving priority to those
+    /// pinned to the current logical CPU.
+    ///
+    /// Always returns true as the scheduler alarm IRQ is not shared.
+    fn poll() -> bool
+    {
+        profile!("Scheduler::poll");
+        let mut pinned = SCHED.pinned[cpu_id()].lock();
+        let mut task = pinned.pop_front();
+        let pinned_count = pinned.len();
+        drop(pinned);
+        let mut scheduled = SCHED.scheduled.lock();
+        if task.is_none() {
+            task = scheduled.pop_front();
+        }
+        let count = scheduled.len();
+        drop(scheduled);
+        if let Some(task) = task {
+            let finished = task.resume();
+            if finished {
+                SCHED.running.lock().remove(&task.id());
+            }
+            if pinned_count > 0 {
+                IRQ.notify_self(SCHED_IRQ);
+            }
+            match count {
+                0 => (),
+                1 => IRQ.notify_self(SCHED_IRQ),
+                _ => IRQ.notify_all(SCHED_IRQ),
+            }
+        }
+        true
+    }
+}
+
+impl<T: Copy + Send> JoinHandle<T>
+{
+    /// Creates and initializes a new join handler.
+    ///
+    /// * `rx`: Task termination notification channel receiver.
+    ///
+    /// Returns the newly created join handler.
+    fn new(rx: Receiver<T>) -> Self
+    {
+        Self { rx }
+    }
+}
+
+impl<T: Copy + Send> Future for JoinHandle<T>
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output>
+    {
+        Pin::new(&mut self.rx).poll(ctx)
+    }
+}
+
+impl Relent
+{
+    /// Creates and initializes a new relent future.
+    ///
+    /// Returns the newly created future.
+    pub fn new() -> Self
+    {
+        Self { is_ready: false }
+    }
+}
+
+impl Future for Relent
+{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()>
+    {
+        if self.is_ready {
+            return Poll::Ready(());
+        }
+        self.as_mut().is_ready = true;
+        ctx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+impl Display for Status
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        let name = match self {
+            Self::Running => "Running",
+            Self::Ready => "Ready",
+            Self::Waiting => "Waiting",
+        };
+        fmt.pad(name)
+    }
+}
+
+impl<T: Copy + Send, F: Future<Output = T> + Send + 'static> State<T, F>
+{
+    /// Creates and initializes a new task state.
+    ///
+    /// * `id`: Task identifier.
+    /// * `name`: Optional task name.
+    /// * `affinity`: Optional logical CPU to which the task is pinned.
+    /// * `fut`: Future for this task to poll.
+    /// * `tx`: Join handler notification channel sender.
+    ///
+    /// Returns the newly created task state.
+    fn new(id: u64, name: Option<&'static str>, affinity: Option<usize>, fut: F, tx: Sender<T>) -> Self
+    {
+        Self { id,
+               name,
+               affinity,
+               is_active: AtomicBool::new(true),
+               is_running: AtomicBool::new(false),
+               busy_time: AtomicU64::new(0),
+               fut: Lock::new(Box::pin(fut)),
+               tx: Lock::new(Some(tx)) }
+    }
+}
+
+impl<T: Copy + Send, F: Future<Output = T> + Send + 'static> Task for State<T, F>
+{
+    fn id(&self) -> u64
+    {
+        self.id
+    }
+
+    fn name(&self) -> Option<&'static str>
+    {
+        self.name
+    }
+
+    fn affinity(&self) -> Option<usize>
+    {
+        self.affinity
+    }
+
+    fn status(&self) -> Status
+    {
+        if self.is_running.load(Ordering::Relaxed) {
+            return Status::Running;
+        }
+        if self.is_active.load(Ordering::Relaxed) {
+            return Status::Ready;
+        }
+        Status::Waiting
+    }
+
+    fn busy_time(&self) -> u64
+    {
+        self.busy_time.load(Ordering::Relaxed)
+    }
+
+    fn activate(&self) -> bool
+    {
+        self.is_active.swap(true, Ordering::SeqCst)
+    }
+
+    fn resume(&self) -> bool
+    {
+        let alarm = Arc::new(Alarm::new(self.id));
+        let waker = Waker::from(alarm);
+        let mut ctx = Context::from_waker(&waker);
+        self.is_active.swap(false, Ordering::SeqCst);
+        self.is_running.store(true, Ordering::Relaxed);
+        let start = now_micros();
+        let poll = self.fut.lock().as_mut().poll(&mut ctx);
+        self.busy_time.fetch_add(now_micros() - start, Ordering::Relaxed);
+        self.is_running.store(false, Ordering::Relaxed);
+        if let Poll::Ready(val) = poll {
+            self.tx
+                .lock()
+                .take()
+                .expect("Missing channel sender end to notify the join handle of a finished task")
+                .send(val);
+            return true;
+        }
+        false
+    }
+}
+
+impl Alarm
+{
+    /// Creates and initializes a new alarm.
+    ///
+    /// Returns the newly created alarm.
+    fn new(id: u64) -> Self
+    {
+        Self { id }
+    }
+}
+
+impl Wake for Alarm
+{
+    fn wake(self: Arc<Self>)
+    {
+        SCHED.wake(self.id);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>)
+    {
+        SCHED.wake(self.id);
+    }
+}
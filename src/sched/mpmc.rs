@@ -0,0 +1,294 @@
+//! Bounded multi-producer multi-consumer async channel.
+//!
+//! Senders awaiting on a full channel and receivers awaiting on an empty
+//! channel are parked until the other side makes progress, providing
+//! backpressure without spinning.  Each parked future keeps a single
+//! registration, which it withdraws when dropped, handing any wake-up that it
+//! had already received over to the next waiter on the same side.  The channel
+//! is closed for receivers once all senders are dropped, and closed for senders
+//! once all receivers are dropped.
+
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::sync::Lock;
+use crate::wait::{WaitId, WaitList};
+
+/// Sender end.
+#[derive(Debug)]
+pub struct Sender<T: Send>
+{
+    /// Channel state.
+    state: Arc<Lock<State<T>>>,
+}
+
+/// Receiver end.
+#[derive(Debug)]
+pub struct Receiver<T: Send>
+{
+    /// Channel state.
+    state: Arc<Lock<State<T>>>,
+}
+
+/// Future that completes once a value has been queued.
+#[derive(Debug)]
+pub struct Sending<'a, T: Send>
+{
+    /// Sender end that created this future.
+    tx: &'a Sender<T>,
+    /// Value waiting to be queued.
+    val: Option<T>,
+    /// Registration with the senders waiting for room, if parked.
+    waiter: Option<WaitId>,
+}
+
+/// Future that completes once a value has been dequeued.
+#[derive(Debug)]
+pub struct Receiving<'a, T: Send>
+{
+    /// Receiver end that created this future.
+    rx: &'a Receiver<T>,
+    /// Registration with the receivers waiting for values, if parked.
+    waiter: Option<WaitId>,
+}
+
+/// Channel state.
+#[derive(Debug)]
+struct State<T: Send>
+{
+    /// Queued values.
+    vals: VecDeque<T>,
+    /// Maximum number of queued values.
+    cap: usize,
+    /// Number of live senders.
+    senders: usize,
+    /// Number of live receivers.
+    receivers: usize,
+    /// Senders waiting for room in the queue.
+    send_waiters: WaitList,
+    /// Receivers waiting for values in the queue.
+    recv_waiters: WaitList,
+}
+
+impl<T: Send> Sender<T>
+{
+    /// Queues a value, waiting for room if the channel is full.
+    ///
+    /// * `val`: Value to send.
+    ///
+    /// Returns a future that, when awaited on, resolves to the value back if
+    /// all the receivers were dropped, or nothing if the value was queued.
+    pub fn send(&self, val: T) -> Sending<'_, T>
+    {
+        Sending { tx: self,
+                  val: Some(val),
+                  waiter: None }
+    }
+
+    /// Attempts to queue a value without waiting.
+    ///
+    /// * `val`: Value to send.
+    ///
+    /// Returns the value back if the channel is either full or closed.
+    pub fn try_send(&self, val: T) -> Result<(), T>
+    {
+        let mut state = self.state.lock();
+        if state.receivers == 0 || state.vals.len() == state.cap {
+            return Err(val);
+        }
+        state.vals.push_back(val);
+        if let Some(waker) = state.recv_waiters.pop() {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+impl<T: Send> Clone for Sender<T>
+{
+    fn clone(&self) -> Self
+    {
+        self.state.lock().senders += 1;
+        Self { state: self.state.clone() }
+    }
+}
+
+impl<T: Send> Drop for Sender<T>
+{
+    fn drop(&mut self)
+    {
+        let mut state = self.state.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            // Wake up all receivers so that they can find out that the channel is closed.
+            state.recv_waiters.drain().for_each(|waker| waker.wake());
+        }
+    }
+}
+
+impl<T: Send> Receiver<T>
+{
+    /// Dequeues a value, waiting for one if the channel is empty.
+    ///
+    /// Returns a future that, when awaited on, resolves to the dequeued value,
+    /// or nothing if the channel is empty and all the senders were dropped.
+    pub fn recv(&self) -> Receiving<'_, T>
+    {
+        Receiving { rx: self, waiter: None }
+    }
+
+    /// Attempts to dequeue a value without waiting.
+    ///
+    /// Returns the dequeued value, or nothing if the channel is empty.
+    pub fn try_recv(&self) -> Option<T>
+    {
+        let mut state = self.state.lock();
+        let val = state.vals.pop_front()?;
+        if let Some(waker) = state.send_waiters.pop() {
+            waker.wake();
+        }
+        Some(val)
+    }
+}
+
+impl<T: Send> Clone for Receiver<T>
+{
+    fn clone(&self) -> Self
+    {
+        self.state.lock().receivers += 1;
+        Self { state: self.state.clone() }
+    }
+}
+
+impl<T: Send> Drop for Receiver<T>
+{
+    fn drop(&mut self)
+    {
+        let mut state = self.state.lock();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            // Wake up all senders so that they can find out that the channel is closed.
+            state.send_waiters.drain().for_each(|waker| waker.wake());
+        }
+    }
+}
+
+impl<'a, T: Send> Future for Sending<'a, T>
+{
+    type Output = Result<(), T>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output>
+    {
+        let tx = self.tx;
+        let mut state = tx.state.lock();
+        if state.vals.len() == state.cap && state.receivers > 0 {
+            let waiter = state.send_waiters.register(self.waiter, (), ctx.waker());
+            self.waiter = Some(waiter);
+            return Poll::Pending;
+        }
+        if let Some(waiter) = self.waiter.take() {
+            state.send_waiters.unregister(waiter);
+        }
+        let val = self.val.take().expect("Sending future polled after completion");
+        if state.receivers == 0 {
+            return Poll::Ready(Err(val));
+        }
+        state.vals.push_back(val);
+        if let Some(waker) = state.recv_waiters.pop() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a, T: Send> Drop for Sending<'a, T>
+{
+    fn drop(&mut self)
+    {
+        let Some(waiter) = self.waiter else {
+            return;
+        };
+        let mut state = self.tx.state.lock();
+        if !state.send_waiters.unregister(waiter) && state.vals.len() < state.cap {
+            // Hand the wake-up meant for this future over to the next sender.
+            if let Some(waker) = state.send_waiters.pop() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<'a, T: Send> Unpin for Sending<'a, T> {}
+
+impl<'a, T: Send> Future for Receiving<'a, T>
+{
+    type Output = Option<T>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<T>>
+    {
+        let rx = self.rx;
+        let mut state = rx.state.lock();
+        if state.vals.is_empty() && state.senders > 0 {
+            let waiter = state.recv_waiters.register(self.waiter, (), ctx.waker());
+            self.waiter = Some(waiter);
+            return Poll::Pending;
+        }
+        if let Some(waiter) = self.waiter.take() {
+            state.recv_waiters.unregister(waiter);
+        }
+        let val = state.vals.pop_front();
+        if val.is_some() {
+            if let Some(waker) = state.send_waiters.pop() {
+                waker.wake();
+            }
+        }
+        Poll::Ready(val)
+    }
+}
+
+impl<'a, T: Send> Drop for Receiving<'a, T>
+{
+    fn drop(&mut self)
+    {
+        let Some(waiter) = self.waiter else {
+            return;
+        };
+        let mut state = self.rx.state.lock();
+        if !state.recv_waiters.unregister(waiter) && !state.vals.is_empty() {
+            // Hand the wake-up meant for this future over to the next receiver.
+            if let Some(waker) = state.recv_waiters.pop() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Creates a new bounded multi-producer multi-consumer channel.
+///
+/// * `cap`: Maximum number of values that can be queued before senders start
+///   waiting.
+///
+/// Returns the sender and receiver ends of the newly created channel, both of
+/// which can be cloned to obtain additional producers and consumers.
+///
+/// Panics if the capacity is zero.
+#[track_caller]
+pub fn bounded<T: Send>(cap: usize) -> (Sender<T>, Receiver<T>)
+{
+    assert!(cap > 0, "Attempted to create a bounded channel with no capacity");
+    let state = State { vals: VecDeque::with_capacity(cap),
+                        cap,
+                        senders: 1,
+                        receivers: 1,
+                        send_waiters: WaitList::new(),
+                        recv_waiters: WaitList::new() };
+    let state = Arc::new(Lock::new(state));
+    let tx = Sender { state: state.clone() };
+    let rx = Receiver { state };
+    (tx, rx)
+}
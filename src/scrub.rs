@@ -0,0 +1,62 @@
+//! Background integrity scrubber.
+//!
+//! Periodically runs integrity checks on critical data structures from a
+//! background task, so that memory corruption is caught closer to its source
+//! instead of manifesting as an unrelated crash much later.  Checks panic with
+//! details about the corruption they find.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use crate::sched::Scheduler;
+use crate::sync::{Lazy, Lock};
+use crate::timer::TIMER;
+
+/// Time in milliseconds between scrubbing passes.
+const SCRUB_PERIOD: u64 = 5000;
+
+/// Global scrubber instance.
+pub static SCRUB: Lazy<Scrubber> = Lazy::new(Scrubber::new);
+
+/// Integrity scrubber.
+#[derive(Debug)]
+pub struct Scrubber
+{
+    /// Registered checks.
+    checks: Lock<Vec<fn()>>,
+}
+
+impl Scrubber
+{
+    /// Creates and initializes a new scrubber.
+    ///
+    /// Returns the newly created scrubber.
+    fn new() -> Self
+    {
+        Self { checks: Lock::new(Vec::new()) }
+    }
+
+    /// Registers an integrity check.
+    ///
+    /// * `check`: Function that panics if it finds corruption.
+    pub fn register(&self, check: fn())
+    {
+        self.checks.lock().push(check);
+    }
+
+    /// Runs all the registered checks periodically, yielding to other tasks
+    /// between checks.
+    pub async fn run(&self) -> !
+    {
+        loop {
+            let count = self.checks.lock().len();
+            for idx in 0 .. count {
+                let check = self.checks.lock()[idx];
+                check();
+                Scheduler::relent().await;
+            }
+            TIMER.sleep(SCRUB_PERIOD).await;
+        }
+    }
+}
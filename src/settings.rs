@@ -0,0 +1,323 @@
+//! Player settings.
+//!
+//! Holds the preferences that outlive a game, currently the volume controls
+//! and the render scale, and serializes them into a small versioned
+//! little-endian record.  Storing the record is left to the caller, as with
+//! saved games.  Gains are 8.8 fixed point multipliers so that mixing them
+//! doesn't depend on float rounding.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FormatResult};
+use core::ops::RangeInclusive;
+
+use crate::ui::{Inspect, Inspector, Value};
+
+/// Magic bytes identifying a settings record.
+const MAGIC: [u8; 4] = *b"NBCF";
+/// Version of the settings record.
+const VERSION: u8 = 2;
+/// Length of a settings record in bytes.
+const RECORD_LEN: usize = MAGIC.len() + 1 + 3 * 2 + 1 + 1;
+/// Gain that leaves the volume unchanged.
+pub const UNITY_GAIN: u16 = 0x100;
+/// Range of supported render scales in percent of the display resolution.
+pub const RENDER_SCALES: RangeInclusive<u8> = 50 ..= 100;
+
+/// Player settings.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Settings
+{
+    /// Volume controls.
+    pub volume: Volume,
+    /// Graphics options.
+    pub graphics: Graphics,
+}
+
+/// Volume controls.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Volume
+{
+    /// Gain applied to all output.
+    pub master: u16,
+    /// Gain applied to music.
+    pub music: u16,
+    /// Gain applied to sound effects.
+    pub sfx: u16,
+    /// Whether all output is silenced regardless of the gains.
+    pub muted: bool,
+}
+
+/// Graphics options.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Graphics
+{
+    /// Render scale in percent of the display resolution, which thermal
+    /// relief may lower further.
+    pub render_scale: u8,
+}
+
+/// Category of sound that a volume control applies to.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Category
+{
+    /// Background music.
+    Music,
+    /// Sound effects.
+    #[default]
+    Sfx,
+}
+
+/// Errors that can occur when loading settings.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SettingsError
+{
+    /// The record isn't a settings record.
+    Magic,
+    /// The record is from an unsupported version.
+    Version(u8),
+    /// The record doesn't have the expected length.
+    Length(usize),
+    /// A gain is above unity.
+    Gain(u16),
+    /// The render scale is outside of the supported range.
+    RenderScale(u8),
+}
+
+impl Settings
+{
+    /// Serializes these settings for storage.
+    ///
+    /// Returns the serialized record.
+    pub fn to_bytes(self) -> Vec<u8>
+    {
+        let mut bytes = Vec::with_capacity(RECORD_LEN);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        for gain in [self.volume.master, self.volume.music, self.volume.sfx] {
+            bytes.extend_from_slice(&gain.to_le_bytes());
+        }
+        bytes.push(self.volume.muted as u8);
+        bytes.push(self.graphics.render_scale);
+        bytes
+    }
+
+    /// Applies a binding in `component.field=value` form, such as
+    /// `volume.music=128`.
+    ///
+    /// * `binding`: Binding to apply.
+    ///
+    /// Returns whether the binding was well formed and accepted.
+    pub fn tweak(&mut self, binding: &str) -> bool
+    {
+        Inspector::tweak(&mut [&mut self.volume, &mut self.graphics], binding)
+    }
+
+    /// Takes a snapshot of every setting that can be tweaked.
+    ///
+    /// Returns an inspector holding the snapshot.
+    pub fn inspect(&self) -> Inspector
+    {
+        let mut inspector = Inspector::new();
+        inspector.refresh(&[&self.volume, &self.graphics]);
+        inspector
+    }
+
+    /// Deserializes settings from storage.
+    ///
+    /// * `bytes`: Serialized record.
+    ///
+    /// Returns the deserialized settings, or the first problem found.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SettingsError>
+    {
+        if bytes.len() < MAGIC.len() + 1 {
+            return Err(SettingsError::Length(bytes.len()));
+        }
+        if bytes[.. 4] != MAGIC {
+            return Err(SettingsError::Magic);
+        }
+        if bytes[4] != VERSION {
+            return Err(SettingsError::Version(bytes[4]));
+        }
+        if bytes.len() != RECORD_LEN {
+            return Err(SettingsError::Length(bytes.len()));
+        }
+        let gain = |offset: usize| {
+            let gain = u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+            if gain > UNITY_GAIN {
+                return Err(SettingsError::Gain(gain));
+            }
+            Ok(gain)
+        };
+        let volume = Volume { master: gain(5)?,
+                              music: gain(7)?,
+                              sfx: gain(9)?,
+                              muted: bytes[11] != 0 };
+        let render_scale = bytes[12];
+        if !RENDER_SCALES.contains(&render_scale) {
+            return Err(SettingsError::RenderScale(render_scale));
+        }
+        Ok(Self { volume,
+                  graphics: Graphics { render_scale } })
+    }
+}
+
+impl Volume
+{
+    /// Computes the combined gain of the master and category controls.
+    ///
+    /// * `category`: Category of the sound.
+    ///
+    /// Returns the computed gain, which is zero while muted.
+    pub fn gain(&self, category: Category) -> u16
+    {
+        if self.muted {
+            return 0;
+        }
+        let gain = match category {
+            Category::Music => self.music,
+            Category::Sfx => self.sfx,
+        };
+        (self.master as u32 * gain as u32 / UNITY_GAIN as u32) as u16
+    }
+}
+
+impl Default for Volume
+{
+    fn default() -> Self
+    {
+        Self { master: UNITY_GAIN,
+               music: UNITY_GAIN,
+               sfx: UNITY_GAIN,
+               muted: false }
+    }
+}
+
+impl Inspect for Volume
+{
+    fn name(&self) -> &'static str
+    {
+        "volume"
+    }
+
+    fn fields(&self, visit: &mut dyn FnMut(&'static str, Value))
+    {
+        visit("master", Value::Int(self.master as _));
+        visit("music", Value::Int(self.music as _));
+        visit("sfx", Value::Int(self.sfx as _));
+        visit("muted", Value::Bool(self.muted));
+    }
+
+    fn set(&mut self, field: &str, val: Value) -> bool
+    {
+        let gain = match val {
+            Value::Int(val @ 0 ..= 0x100) => val as u16,
+            Value::Bool(val) if field == "muted" => {
+                self.muted = val;
+                return true;
+            }
+            _ => return false,
+        };
+        match field {
+            "master" => self.master = gain,
+            "music" => self.music = gain,
+            "sfx" => self.sfx = gain,
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl Default for Graphics
+{
+    fn default() -> Self
+    {
+        Self { render_scale: *RENDER_SCALES.end() }
+    }
+}
+
+impl Inspect for Graphics
+{
+    fn name(&self) -> &'static str
+    {
+        "graphics"
+    }
+
+    fn fields(&self, visit: &mut dyn FnMut(&'static str, Value))
+    {
+        visit("render_scale", Value::Int(self.render_scale as _));
+    }
+
+    fn set(&mut self, field: &str, val: Value) -> bool
+    {
+        match (field, val) {
+            ("render_scale", Value::Int(val)) => {
+                let Some(val) = u8::try_from(val).ok().filter(|val| RENDER_SCALES.contains(val)) else {
+                    return false;
+                };
+                self.render_scale = val;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Display for SettingsError
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        match self {
+            Self::Magic => write!(fmt, "not a settings record"),
+            Self::Version(version) => write!(fmt, "unsupported version {version}"),
+            Self::Length(len) => write!(fmt, "unexpected length {len}"),
+            Self::Gain(gain) => write!(fmt, "gain 0x{gain:X} above unity"),
+            Self::RenderScale(scale) => write!(fmt, "unsupported render scale {scale}%"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn round_trip()
+    {
+        let mut settings = Settings::default();
+        assert!(settings.tweak("volume.music=128"));
+        assert!(settings.tweak("volume.muted=true"));
+        assert!(!settings.tweak("volume.sfx=512"));
+        assert!(settings.tweak("graphics.render_scale=75"));
+        assert!(!settings.tweak("graphics.render_scale=25"));
+        let inspector = settings.inspect();
+        let row = inspector.rows().iter().find(|row| row.path == "volume.music").unwrap();
+        assert_eq!(row.val, Value::Int(128));
+        let bytes = settings.to_bytes();
+        assert_eq!(bytes.len(), RECORD_LEN);
+        assert_eq!(Settings::from_bytes(&bytes), Ok(settings));
+        let mut bad = bytes.clone();
+        bad[7 .. 9].copy_from_slice(&0x101u16.to_le_bytes());
+        assert_eq!(Settings::from_bytes(&bad), Err(SettingsError::Gain(0x101)));
+        let mut bad = bytes.clone();
+        bad[12] = 101;
+        assert_eq!(Settings::from_bytes(&bad), Err(SettingsError::RenderScale(101)));
+        assert_eq!(Settings::from_bytes(&bytes[.. 8]), Err(SettingsError::Length(8)));
+        bad[4] = VERSION + 1;
+        assert_eq!(Settings::from_bytes(&bad), Err(SettingsError::Version(VERSION + 1)));
+    }
+
+    #[test]
+    fn gain()
+    {
+        let mut volume = Volume { master: UNITY_GAIN / 2,
+                                  ..Volume::default() };
+        volume.music = UNITY_GAIN / 2;
+        assert_eq!(volume.gain(Category::Music), UNITY_GAIN / 4);
+        assert_eq!(volume.gain(Category::Sfx), UNITY_GAIN / 2);
+        volume.muted = true;
+        assert_eq!(volume.gain(Category::Sfx), 0);
+    }
+}
@@ -2,6 +2,7 @@
 
 #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
 use core::arch::aarch64::*;
+use core::f32::consts::FRAC_2_PI;
 #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
 use core::mem::transmute;
 use core::ops::{Mul, MulAssign};
@@ -12,6 +13,14 @@ use std::simd::StdFloat;
 #[cfg(not(test))]
 use crate::prim::FloatExtra;
 
+/// Most significant bits of a quarter turn in radians, few enough for their
+/// multiples by up to 4096 quarter turns to be exact.
+const FRAC_PI_2_HI: f32 = 1.5703125;
+/// Next most significant bits of a quarter turn in radians.
+const FRAC_PI_2_MID: f32 = 4.837513e-4;
+/// Remaining bits of a quarter turn in radians.
+const FRAC_PI_2_LO: f32 = 7.54979e-8;
+
 /// SIMD matrix type.
 #[allow(non_camel_case_types)]
 pub type f32x4x4 = Matrix;
@@ -36,6 +45,11 @@ pub trait SimdFloatExtra: SimdFloat
     /// Returns the computed result.
     fn fast_sqrt_recip(self) -> Self;
 
+    /// Computes the square root of all lanes in this vector.
+    ///
+    /// Returns the computed result.
+    fn simd_sqrt(self) -> Self;
+
     /// Computes a vector with the same direction as this vector and length 1.0.
     ///
     /// Returns the computed result.
@@ -102,6 +116,46 @@ pub trait SimdFloatExtra: SimdFloat
     ///
     /// Returns a vector with the value replaced.
     fn replace_lane<const LANE: i32>(self, scalar: f32) -> Self;
+
+    /// Returns the first lane of this vector.
+    fn x(self) -> f32;
+
+    /// Returns the second lane of this vector.
+    fn y(self) -> f32;
+
+    /// Returns the third lane of this vector.
+    fn z(self) -> f32;
+
+    /// Returns the last lane of this vector.
+    fn w(self) -> f32;
+
+    /// Returns this vector as a direction, with the last lane set to zero.
+    fn xyz0(self) -> Self;
+
+    /// Returns this vector as a point, with the last lane set to one.
+    fn xyz1(self) -> Self;
+
+    /// Computes the dot product between this and another vector, ignoring the
+    /// last lane.
+    ///
+    /// * `other`: Other vector to compute the dot product with.
+    ///
+    /// Returns the computed result.
+    fn dot(self, other: Self) -> f32;
+
+    /// Computes fast approximations of the sine and cosine of all lanes in
+    /// this vector, in radians, with an absolute error below 1e-6 for inputs
+    /// within 4096 quarter turns of zero.
+    ///
+    /// Returns the computed sines and cosines.
+    fn fast_sin_cos(self) -> (Self, Self);
+
+    /// Finds the lane holding the smallest value in this vector, ignoring NaN
+    /// lanes.
+    ///
+    /// Returns the index of the lane, the lowest one on ties, or [`None`] if
+    /// all lanes are NaN.
+    fn reduce_min_index(self) -> Option<usize>;
 }
 
 pub trait SimdPartialEqExtra: SimdPartialEq
@@ -245,6 +299,21 @@ impl SimdFloatExtra for f32x4
         }
     }
 
+    #[inline(always)]
+    fn simd_sqrt(self) -> Self
+    {
+        #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+        unsafe {
+            let this = transmute::<Self, float32x4_t>(self);
+            let res = vsqrtq_f32(this);
+            transmute::<float32x4_t, Self>(res)
+        }
+        #[cfg(not(all(target_arch = "aarch64", target_feature = "neon")))]
+        {
+            self.sqrt()
+        }
+    }
+
     #[inline(always)]
     fn normalize(self) -> Option<Self>
     {
@@ -369,6 +438,82 @@ impl SimdFloatExtra for f32x4
             this
         }
     }
+
+    #[inline(always)]
+    fn x(self) -> f32
+    {
+        self[0]
+    }
+
+    #[inline(always)]
+    fn y(self) -> f32
+    {
+        self[1]
+    }
+
+    #[inline(always)]
+    fn z(self) -> f32
+    {
+        self[2]
+    }
+
+    #[inline(always)]
+    fn w(self) -> f32
+    {
+        self[3]
+    }
+
+    #[inline(always)]
+    fn xyz0(self) -> Self
+    {
+        self.replace_lane::<3>(0.0)
+    }
+
+    #[inline(always)]
+    fn xyz1(self) -> Self
+    {
+        self.replace_lane::<3>(1.0)
+    }
+
+    #[inline(always)]
+    fn dot(self, other: Self) -> f32
+    {
+        (self.xyz0() * other).reduce_sum()
+    }
+
+    fn fast_sin_cos(self) -> (Self, Self)
+    {
+        // Reduce to the nearest quarter turn, subtracting it in three parts to keep
+        // the bits that don't fit in a single float.
+        let quad = (self * f32x4::splat(FRAC_2_PI) + f32x4::splat(0.5).copysign(self)).cast::<i32>();
+        let turns = quad.cast::<f32>();
+        let rem = self.fused_mul_add(turns, f32x4::splat(-FRAC_PI_2_HI))
+                      .fused_mul_add(turns, f32x4::splat(-FRAC_PI_2_MID))
+                      .fused_mul_add(turns, f32x4::splat(-FRAC_PI_2_LO));
+        let sq = rem * rem;
+        // Taylor polynomials, which are accurate enough within an eighth of a turn.
+        let sin = f32x4::splat(1.0 / 120.0).fused_mul_add(sq, f32x4::splat(-1.0 / 5040.0));
+        let sin = f32x4::splat(-1.0 / 6.0).fused_mul_add(sq, sin);
+        let sin = rem.fused_mul_add(rem * sq, sin);
+        let cos = f32x4::splat(-1.0 / 720.0).fused_mul_add(sq, f32x4::splat(1.0 / 40320.0));
+        let cos = f32x4::splat(1.0 / 24.0).fused_mul_add(sq, cos);
+        let cos = f32x4::splat(-0.5).fused_mul_add(sq, cos);
+        let cos = f32x4::splat(1.0).fused_mul_add(sq, cos);
+        // Rotate the results back to the original quadrant.
+        let zero = i32x4::splat(0);
+        let swap = (quad & i32x4::splat(1)).simd_ne(zero);
+        let (sin, cos) = (swap.select(cos, sin), swap.select(sin, cos));
+        let sin = (quad & i32x4::splat(2)).simd_ne(zero).select(-sin, sin);
+        let cos = ((quad + i32x4::splat(1)) & i32x4::splat(2)).simd_ne(zero)
+                                                              .select(-cos, cos);
+        (sin, cos)
+    }
+
+    #[inline(always)]
+    fn reduce_min_index(self) -> Option<usize>
+    {
+        first_lane(self.simd_eq(f32x4::splat(self.reduce_min())))
+    }
 }
 
 impl SimdPartialEqExtra for f32x4
@@ -441,6 +586,25 @@ impl SimdPartialOrdExtra for f32x4
     }
 }
 
+/// Finds the first lane set in a mask.
+///
+/// * `mask`: Mask to search.
+///
+/// Returns the index of the lane, or [`None`] if no lanes are set.
+#[inline(always)]
+fn first_lane(mask: mask32x4) -> Option<usize>
+{
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    let bits = unsafe {
+        let mask = transmute::<mask32x4, uint32x4_t>(mask);
+        let weights = transmute::<u32x4, uint32x4_t>(u32x4::from_array([0x1, 0x2, 0x4, 0x8]));
+        vaddvq_u32(vandq_u32(mask, weights)) as u64
+    };
+    #[cfg(not(all(target_arch = "aarch64", target_feature = "neon")))]
+    let bits = mask.to_bitmask();
+    (bits != 0).then(|| bits.trailing_zeros() as usize)
+}
+
 #[cfg(test)]
 mod tests
 {
@@ -474,6 +638,14 @@ mod tests
                    f32::from_bits(expected[0]));
     }
 
+    #[test]
+    fn f32x4_simd_sqrt()
+    {
+        let actual = f32x4::from_array([0.0, 1.0, 4.0, 9.0]).simd_sqrt();
+        let expected = f32x4::from_array([0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn f32x4_normalize()
     {
@@ -557,6 +729,48 @@ mod tests
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn f32x4_components()
+    {
+        let vec = f32x4::from_array([1.0, 2.0, 3.0, 4.0]);
+        assert_eq!([vec.x(), vec.y(), vec.z(), vec.w()], [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(vec.xyz0(), f32x4::from_array([1.0, 2.0, 3.0, 0.0]));
+        assert_eq!(vec.xyz1(), f32x4::from_array([1.0, 2.0, 3.0, 1.0]));
+    }
+
+    #[test]
+    fn f32x4_dot()
+    {
+        let left = f32x4::from_array([2.0, 4.0, 8.0, 1.0]);
+        let right = f32x4::from_array([1.0, 0.5, 0.25, 16.0]);
+        assert_eq!(left.dot(right), 6.0);
+        assert_eq!(left.dot(right), left.cross_dot(right).w());
+    }
+
+    #[test]
+    fn f32x4_fast_sin_cos()
+    {
+        for idx in -6000 ..= 6000 {
+            let vals = f32x4::from_array([0.0, 1.0, 2.0, 3.0]) * f32x4::splat(0.25) + f32x4::splat(idx as f32);
+            let (sin, cos) = vals.fast_sin_cos();
+            for lane in 0 .. 4 {
+                let val = vals[lane];
+                assert!((sin[lane] - val.sin()).abs() < 1e-6, "sin({val}) = {}", sin[lane]);
+                assert!((cos[lane] - val.cos()).abs() < 1e-6, "cos({val}) = {}", cos[lane]);
+            }
+        }
+    }
+
+    #[test]
+    fn f32x4_reduce_min_index()
+    {
+        let vec = f32x4::from_array([3.0, -1.0, f32::NAN, -1.0]);
+        assert_eq!(vec.reduce_min_index(), Some(1));
+        let vec = f32x4::from_array([f32::NAN, 2.0, f32::NAN, 5.0]);
+        assert_eq!(vec.reduce_min_index(), Some(1));
+        assert_eq!(f32x4::splat(f32::NAN).reduce_min_index(), None);
+    }
+
     #[test]
     fn f32x4_fused_mul_add()
     {
@@ -0,0 +1,321 @@
+//! SPI master driver.
+//!
+//! Drives the primary SPI controller described in the BCM2711 peripherals
+//! datasheet [1], handing it out as a claimed [`Master`] that owns its pins and
+//! selects devices through the controller's two chip select lines.  Transfers
+//! run asynchronously, fed by a pair of DMA channels when available, or
+//! falling back to polling the FIFOs while yielding to other tasks otherwise.
+//!
+//! [1]: https://datasheets.raspberrypi.com/bcm2711/bcm2711-peripherals.pdf
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::cmp::max;
+use core::hint::spin_loop;
+use core::sync::atomic::{fence, Ordering};
+
+use crate::alloc::{Alloc, UNCACHED_REGION};
+use crate::dma::{Block, Chain, Channel, DMA};
+use crate::gpio::{Function, Pin, Pull, GPIO};
+use crate::sched::Scheduler;
+use crate::sync::{Lazy, Lock};
+use crate::{to_dma, PERRY_RANGE};
+
+/// SPI controller base address.
+const BASE: usize = PERRY_RANGE.start + 0x2204000;
+/// Control and status register.
+const CS: *mut u32 = BASE as _;
+/// FIFO register.
+const FIFO: *mut u32 = (BASE + 0x4) as _;
+/// Clock divider register.
+const CLK: *mut u32 = (BASE + 0x8) as _;
+/// Control and status clock phase flag.
+const CS_CPHA: u32 = 0x4;
+/// Control and status clock polarity flag.
+const CS_CPOL: u32 = 0x8;
+/// Control and status clear FIFOs flags.
+const CS_CLEAR: u32 = 0x30;
+/// Control and status transfer active flag.
+const CS_TA: u32 = 0x80;
+/// Control and status DMA enable flag.
+const CS_DMAEN: u32 = 0x100;
+/// Control and status automatically deassert chip select flag.
+const CS_ADCS: u32 = 0x800;
+/// Control and status transfer done flag.
+const CS_DONE: u32 = 0x10000;
+/// Control and status receive FIFO contains data flag.
+const CS_RXD: u32 = 0x20000;
+/// Control and status transmit FIFO can accept data flag.
+const CS_TXD: u32 = 0x40000;
+/// Transmit data request signal.
+const TX_DREQ: u8 = 6;
+/// Receive data request signal.
+const RX_DREQ: u8 = 7;
+/// Maximum length of a DMA transfer.
+const DMA_LEN_MAX: usize = 0xFFFF;
+/// Clock feeding the controller in hertz.
+const CORE_CLOCK: u32 = 500000000;
+/// Pins used by the controller, starting with the second chip select and
+/// followed by the first chip select, data in, data out, and clock.
+const PINS: [usize; 5] = [7, 8, 9, 10, 11];
+
+/// Global SPI driver instance.
+pub static SPI: Lazy<Spi> = Lazy::new(Spi::new);
+
+/// Uncached memory allocator.
+static UNCACHED: Alloc<0x40> = Alloc::with_region(&UNCACHED_REGION);
+
+/// SPI driver.
+#[derive(Debug)]
+pub struct Spi
+{
+    /// Whether the controller is claimed.
+    claimed: Lock<bool>,
+}
+
+/// Claimed controller acting as an SPI master, which is released when
+/// dropped.
+#[derive(Debug)]
+pub struct Master
+{
+    /// Clock polarity and phase flags.
+    mode: u32,
+    /// Transmit and receive DMA channels, if any were available.
+    chans: Option<[Channel; 2]>,
+    /// Chip select, data and clock pins.
+    _pins: [Pin; 5],
+}
+
+/// Clock polarity and phase.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode
+{
+    /// Clock idles low and data is sampled on the rising edge.
+    Mode0,
+    /// Clock idles low and data is sampled on the falling edge.
+    Mode1,
+    /// Clock idles high and data is sampled on the falling edge.
+    Mode2,
+    /// Clock idles high and data is sampled on the rising edge.
+    Mode3,
+}
+
+/// Chip select line.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChipSelect
+{
+    /// First chip select line.
+    Ce0,
+    /// Second chip select line.
+    Ce1,
+}
+
+impl Spi
+{
+    /// Creates and initializes a new SPI driver.
+    ///
+    /// Returns the newly created driver.
+    fn new() -> Self
+    {
+        Self { claimed: Lock::new(false) }
+    }
+
+    /// Claims the controller along with its pins and, if available, a pair of
+    /// DMA channels.
+    ///
+    /// * `freq`: Maximum clock frequency in hertz.
+    /// * `mode`: Clock polarity and phase.
+    ///
+    /// Returns the claimed controller, or `None` if either the controller or
+    /// its pins are already claimed.
+    ///
+    /// Panics if the frequency is zero.
+    #[track_caller]
+    pub fn claim(&self, freq: u32, mode: Mode) -> Option<Master>
+    {
+        assert!(freq > 0, "Invalid SPI clock frequency: {freq}Hz");
+        {
+            let mut claimed = self.claimed.lock();
+            if *claimed {
+                return None;
+            }
+            *claimed = true;
+        }
+        let mut pins = Vec::with_capacity(PINS.len());
+        for idx in PINS {
+            let Some(mut pin) = GPIO.claim(idx) else {
+                *self.claimed.lock() = false;
+                return None;
+            };
+            pin.set_function(Function::Alt0);
+            pin.set_pull(Pull::None);
+            pins.push(pin);
+        }
+        let pins = pins.try_into().unwrap();
+        let chans = DMA.allocate().zip(DMA.allocate()).map(|(tx, rx)| [tx, rx]);
+        let mode = match mode {
+            Mode::Mode0 => 0,
+            Mode::Mode1 => CS_CPHA,
+            Mode::Mode2 => CS_CPOL,
+            Mode::Mode3 => CS_CPOL | CS_CPHA,
+        };
+        // The divider must be even and is rounded up so the frequency is never
+        // exceeded.
+        let div = ((CORE_CLOCK.div_ceil(freq) + 1) & !0x1).clamp(2, 0xFFFE);
+        unsafe {
+            CS.write_volatile(mode | CS_CLEAR);
+            CLK.write_volatile(div);
+        }
+        fence(Ordering::Release);
+        Some(Master { mode,
+                      chans,
+                      _pins: pins })
+    }
+}
+
+impl Master
+{
+    /// Exchanges data with a device asynchronously, using DMA transfers if
+    /// channels were available when the controller was claimed.
+    ///
+    /// * `cs`: Chip select line of the device.
+    /// * `tx`: Bytes to send, padded with zeros if shorter than `rx`.
+    /// * `rx`: Buffer to fill with the received bytes, which are discarded past
+    ///   its length.
+    pub async fn transfer(&mut self, cs: ChipSelect, tx: &[u8], rx: &mut [u8])
+    {
+        let len = max(tx.len(), rx.len());
+        if len == 0 {
+            return;
+        }
+        if self.chans.is_some() && len <= DMA_LEN_MAX {
+            self.transfer_dma(cs, tx, rx, len).await;
+            return;
+        }
+        self.begin(cs, 0);
+        let (mut sent, mut received) = (0, 0);
+        while received < len {
+            (sent, received) = self.pump(tx, rx, len, sent, received);
+            Scheduler::relent().await;
+        }
+        self.end();
+    }
+
+    /// Exchanges data with a device through the DMA channels.
+    ///
+    /// * `cs`: Chip select line of the device.
+    /// * `tx`: Bytes to send.
+    /// * `rx`: Buffer to fill with the received bytes.
+    /// * `len`: Length of the transfer in bytes.
+    async fn transfer_dma(&mut self, cs: ChipSelect, tx: &[u8], rx: &mut [u8], len: usize)
+    {
+        // The FIFO is accessed a word at a time, and the first word written to
+        // it carries the length and the low byte of the control and status
+        // register.
+        let words = len.div_ceil(4);
+        let mut txbuf = Vec::with_capacity_in((words + 1) * 4, UNCACHED);
+        let header = (len as u32) << 16 | ((self.mode | Self::select(cs) | CS_TA) & 0xFF);
+        txbuf.extend_from_slice(&header.to_le_bytes());
+        txbuf.extend_from_slice(tx);
+        txbuf.resize((words + 1) * 4, 0);
+        let mut rxbuf = Vec::with_capacity_in(words * 4, UNCACHED);
+        rxbuf.resize(words * 4, 0u8);
+        let fifo = to_dma(FIFO as usize);
+        let tx_block = Block::new(to_dma(txbuf.as_ptr() as usize), fifo, txbuf.len()).increment_src()
+                                                                                     .paced_dst(TX_DREQ);
+        let rx_block = Block::new(fifo, to_dma(rxbuf.as_mut_ptr() as usize), rxbuf.len()).increment_dst()
+                                                                                         .paced_src(RX_DREQ)
+                                                                                         .interrupt();
+        self.begin(cs, CS_DMAEN | CS_ADCS);
+        let [tx_chan, rx_chan] = self.chans.as_mut().unwrap();
+        let done = rx_chan.start(Chain::new([rx_block]));
+        tx_chan.start(Chain::new([tx_block]));
+        done.await;
+        tx_chan.stop();
+        rx_chan.stop();
+        self.end();
+        let count = rx.len();
+        rx.copy_from_slice(&rxbuf[.. count]);
+    }
+
+    /// Starts a transfer.
+    ///
+    /// * `cs`: Chip select line of the device.
+    /// * `flags`: Additional control and status flags.
+    fn begin(&self, cs: ChipSelect, flags: u32)
+    {
+        let val = self.mode | Self::select(cs) | flags;
+        unsafe {
+            CS.write_volatile(val | CS_CLEAR);
+            // In DMA mode the transfer is activated by the first word written
+            // to the FIFO instead.
+            if flags & CS_DMAEN == 0 {
+                CS.write_volatile(val | CS_TA);
+            }
+        }
+        fence(Ordering::Release);
+    }
+
+    /// Moves as many bytes as possible between the buffers and the FIFOs.
+    ///
+    /// * `tx`: Bytes to send.
+    /// * `rx`: Buffer to fill with the received bytes.
+    /// * `len`: Length of the transfer in bytes.
+    /// * `sent`: Number of bytes sent so far.
+    /// * `received`: Number of bytes received so far.
+    ///
+    /// Returns the updated number of bytes sent and received.
+    fn pump(&self, tx: &[u8], rx: &mut [u8], len: usize, mut sent: usize, mut received: usize) -> (usize, usize)
+    {
+        fence(Ordering::Acquire);
+        while sent < len && unsafe { CS.read_volatile() } & CS_TXD != 0 {
+            let byte = tx.get(sent).copied().unwrap_or(0);
+            unsafe { FIFO.write_volatile(byte as u32) };
+            sent += 1;
+        }
+        while received < len && unsafe { CS.read_volatile() } & CS_RXD != 0 {
+            let byte = unsafe { FIFO.read_volatile() } as u8;
+            if let Some(slot) = rx.get_mut(received) {
+                *slot = byte;
+            }
+            received += 1;
+        }
+        fence(Ordering::Release);
+        (sent, received)
+    }
+
+    /// Waits for the transfer to complete and deasserts the chip select line.
+    fn end(&self)
+    {
+        fence(Ordering::Acquire);
+        while unsafe { CS.read_volatile() } & CS_DONE == 0 {
+            spin_loop();
+        }
+        unsafe { CS.write_volatile(self.mode | CS_CLEAR) };
+        fence(Ordering::Release);
+    }
+
+    /// Computes the chip select field of the control and status register.
+    ///
+    /// * `cs`: Chip select line.
+    ///
+    /// Returns the computed field.
+    fn select(cs: ChipSelect) -> u32
+    {
+        match cs {
+            ChipSelect::Ce0 => 0,
+            ChipSelect::Ce1 => 1,
+        }
+    }
+}
+
+impl Drop for Master
+{
+    fn drop(&mut self)
+    {
+        unsafe { CS.write_volatile(CS_CLEAR) };
+        fence(Ordering::Release);
+        *SPI.claimed.lock() = false;
+    }
+}
@@ -2,12 +2,28 @@
 //!
 //! The core of all other locks, only acts as an advisor and doesn't actually
 //! own any content.
+//!
+//! When built with the `lockdebug` option, advisors also record where they
+//! were last acquired, and panic with that information when spinning for too
+//! long, making deadlocks across cores diagnosable.
 
 use core::hint::spin_loop;
+#[cfg(lockdebug)]
+use core::panic::Location;
+#[cfg(lockdebug)]
+use core::ptr::null_mut;
+#[cfg(lockdebug)]
+use core::sync::atomic::AtomicPtr;
 use core::sync::atomic::{AtomicUsize, Ordering};
 
+#[cfg(lockdebug)]
+use crate::clock::now;
 use crate::cpu::{id as cpu_id, COUNT as CPU_COUNT};
 
+/// Time in milliseconds spent spinning on a lock before assuming a deadlock.
+#[cfg(lockdebug)]
+pub const TIMEOUT: u64 = 1000;
+
 /// Lock advisor.
 #[repr(align(64))] // Take up an entire cache line.
 #[derive(Debug)]
@@ -15,6 +31,9 @@ pub struct Advisor
 {
     /// The Logical CPU that currently holds the lock.
     affinity: AtomicUsize,
+    /// Location where the lock was last acquired.
+    #[cfg(lockdebug)]
+    location: AtomicPtr<Location<'static>>,
 }
 
 #[cfg(not(test))]
@@ -25,7 +44,9 @@ impl Advisor
     /// Returns the newly created lock advisor.
     pub const fn new() -> Self
     {
-        Self { affinity: AtomicUsize::new(CPU_COUNT) }
+        Self { affinity: AtomicUsize::new(CPU_COUNT),
+               #[cfg(lockdebug)]
+               location: AtomicPtr::new(null_mut()) }
     }
 
     /// Places a hold on the lock, blocking the logical CPU if another logical
@@ -38,14 +59,24 @@ impl Advisor
     pub fn lock(&self)
     {
         let affinity = cpu_id();
-        assert!(self.affinity.load(Ordering::Relaxed) != affinity,
-                "Deadlock detected on core #{affinity}");
+        if self.affinity.load(Ordering::Relaxed) == affinity {
+            self.deadlock();
+        }
+        #[cfg(lockdebug)]
+        let start = now();
         while self.affinity
                   .compare_exchange_weak(CPU_COUNT, affinity, Ordering::SeqCst, Ordering::Relaxed)
                   .is_err()
         {
+            #[cfg(lockdebug)]
+            if now() - start > TIMEOUT {
+                self.deadlock();
+            }
             spin_loop()
         }
+        #[cfg(lockdebug)]
+        self.location
+            .store(Location::caller() as *const _ as *mut _, Ordering::Relaxed);
     }
 
     /// Relinquishes the hold on a lock, unblocking another logical CPU that
@@ -63,4 +94,20 @@ impl Advisor
                 "Logical CPU #{affinity} attempted to relinquish a lock that it doesn't hold");
         self.affinity.store(CPU_COUNT, Ordering::SeqCst);
     }
+
+    /// Panics with as much information as is available about the holder of the
+    /// lock.
+    #[track_caller]
+    fn deadlock(&self) -> !
+    {
+        let affinity = cpu_id();
+        let holder = self.affinity.load(Ordering::Relaxed);
+        #[cfg(lockdebug)]
+        if let Some(location) = unsafe { self.location.load(Ordering::Relaxed).as_ref() } {
+            panic!("Deadlock detected on core #{affinity}: lock held by core #{holder} acquired at {}:{}",
+                   location.file(),
+                   location.line());
+        }
+        panic!("Deadlock detected on core #{affinity}: lock held by core #{holder}");
+    }
 }
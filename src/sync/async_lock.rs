@@ -0,0 +1,153 @@
+//! Asynchronous locking primitives.
+//!
+//! Unlike [`Lock`](super::Lock), tasks awaiting on an [`AsyncLock`] are parked
+//! until the lock is released instead of spinning, so the lock can be held
+//! across await points without stalling other cores.  A task that gives up
+//! waiting withdraws from the queue, passing on the wake-up if it had already
+//! been handed one.  These locks cannot be used from IRQ handlers.
+
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use super::Lock;
+use crate::wait::{WaitId, WaitList};
+
+/// Asynchronous lock container.
+#[derive(Debug)]
+pub struct AsyncLock<T: ?Sized>
+{
+    /// Lock state.
+    state: Lock<State>,
+    /// Protected content.
+    content: UnsafeCell<T>,
+}
+
+/// Asynchronous lock guard whose lifetime determines how long the lock is held.
+#[derive(Debug)]
+pub struct AsyncGuard<'a, T: ?Sized>
+{
+    /// Lock to be released once this guard is dropped.
+    lock: &'a AsyncLock<T>,
+}
+
+/// Future that resolves to a guard once the lock is acquired.
+#[derive(Debug)]
+pub struct Locking<'a, T: ?Sized>
+{
+    /// Lock to acquire.
+    lock: &'a AsyncLock<T>,
+    /// Registration with the tasks waiting for the lock, if parked.
+    waiter: Option<WaitId>,
+}
+
+/// Lock state.
+#[derive(Debug)]
+struct State
+{
+    /// Whether the lock is held.
+    is_locked: bool,
+    /// Tasks waiting for the lock to be released.
+    waiters: WaitList,
+}
+
+impl<T: ?Sized> AsyncLock<T>
+{
+    /// Creates and initializes a new asynchronous lock.
+    ///
+    /// `content`: Content to protect.
+    ///
+    /// Returns the newly created lock.
+    pub const fn new(content: T) -> Self
+        where T: Sized
+    {
+        let state = State { is_locked: false,
+                            waiters: WaitList::new() };
+        Self { state: Lock::new(state),
+               content: UnsafeCell::new(content) }
+    }
+
+    /// Locks access to the content, parking the task if it is already locked.
+    ///
+    /// Returns a future that, when awaited on, resolves to an [`AsyncGuard`]
+    /// which allows access to the content and holds the lock until dropped.
+    pub fn lock(&self) -> Locking<'_, T>
+    {
+        Locking { lock: self,
+                  waiter: None }
+    }
+}
+
+unsafe impl<T: ?Sized + Send> Send for AsyncLock<T> {}
+
+unsafe impl<T: ?Sized + Send> Sync for AsyncLock<T> {}
+
+impl<'a, T: ?Sized> Deref for AsyncGuard<'a, T>
+{
+    type Target = T;
+
+    fn deref(&self) -> &'a Self::Target
+    {
+        unsafe { &*self.lock.content.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for AsyncGuard<'a, T>
+{
+    fn deref_mut(&mut self) -> &'a mut Self::Target
+    {
+        unsafe { &mut *self.lock.content.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for AsyncGuard<'a, T>
+{
+    fn drop(&mut self)
+    {
+        let mut state = self.lock.state.lock();
+        state.is_locked = false;
+        if let Some(waker) = state.waiters.pop() {
+            waker.wake();
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Future for Locking<'a, T>
+{
+    type Output = AsyncGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output>
+    {
+        let lock = self.lock;
+        let mut state = lock.state.lock();
+        if state.is_locked {
+            let waiter = state.waiters.register(self.waiter, (), ctx.waker());
+            self.waiter = Some(waiter);
+            return Poll::Pending;
+        }
+        if let Some(waiter) = self.waiter.take() {
+            state.waiters.unregister(waiter);
+        }
+        state.is_locked = true;
+        Poll::Ready(AsyncGuard { lock })
+    }
+}
+
+impl<'a, T: ?Sized> Drop for Locking<'a, T>
+{
+    fn drop(&mut self)
+    {
+        let Some(waiter) = self.waiter else {
+            return;
+        };
+        let mut state = self.lock.state.lock();
+        if !state.waiters.unregister(waiter) && !state.is_locked {
+            // Hand the wake-up meant for this future over to the next waiter.
+            if let Some(waker) = state.waiters.pop() {
+                waker.wake();
+            }
+        }
+    }
+}
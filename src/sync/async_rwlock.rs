@@ -0,0 +1,239 @@
+//! Asynchronous read-write locking primitives.
+//!
+//! Unlike [`RwLock`](super::RwLock), tasks awaiting on an [`AsyncRwLock`] are
+//! parked until the lock becomes available instead of spinning, so the lock
+//! can be held across await points without stalling other cores.  Releasing
+//! the lock wakes every waiting task, and tasks that give up waiting withdraw
+//! from the queue.  These locks cannot be used from IRQ handlers.
+
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use super::Lock;
+use crate::wait::{WaitId, WaitList};
+
+/// Asynchronous read-write lock.
+#[derive(Debug)]
+pub struct AsyncRwLock<T: ?Sized>
+{
+    /// Lock state.
+    state: Lock<State>,
+    /// Protected content.
+    content: UnsafeCell<T>,
+}
+
+/// Read grant on the lock.
+#[derive(Debug)]
+pub struct AsyncReadGuard<'a, T: ?Sized>
+{
+    /// Lock to which this guard grants shared access to.
+    lock: &'a AsyncRwLock<T>,
+}
+
+/// Write grant on the lock.
+#[derive(Debug)]
+pub struct AsyncWriteGuard<'a, T: ?Sized>
+{
+    /// Lock which this guard grants exclusive access to.
+    lock: &'a AsyncRwLock<T>,
+}
+
+/// Future that resolves to a read guard once shared access is granted.
+#[derive(Debug)]
+pub struct ReadLocking<'a, T: ?Sized>
+{
+    /// Lock to acquire.
+    lock: &'a AsyncRwLock<T>,
+    /// Registration with the tasks waiting for the lock, if parked.
+    waiter: Option<WaitId>,
+}
+
+/// Future that resolves to a write guard once exclusive access is granted.
+#[derive(Debug)]
+pub struct WriteLocking<'a, T: ?Sized>
+{
+    /// Lock to acquire.
+    lock: &'a AsyncRwLock<T>,
+    /// Registration with the tasks waiting for the lock, if parked.
+    waiter: Option<WaitId>,
+}
+
+/// Lock state.
+#[derive(Debug)]
+struct State
+{
+    /// Reader count.
+    share_count: usize,
+    /// Whether a writer holds the lock.
+    is_writing: bool,
+    /// Tasks waiting for the lock to become available.
+    waiters: WaitList,
+}
+
+impl<T: ?Sized> AsyncRwLock<T>
+{
+    /// Creates and initializes a new asynchronous read-write lock.
+    ///
+    /// `content`: Content to protect.
+    ///
+    /// Returns the newly created lock.
+    pub const fn new(content: T) -> Self
+        where T: Sized
+    {
+        let state = State { share_count: 0,
+                            is_writing: false,
+                            waiters: WaitList::new() };
+        Self { state: Lock::new(state),
+               content: UnsafeCell::new(content) }
+    }
+
+    /// Non-exclusively locks access to the content, parking the task if it is
+    /// exclusively locked.
+    ///
+    /// Returns a future that, when awaited on, resolves to an
+    /// [`AsyncReadGuard`] which allows shared immutable access to the content
+    /// and holds the lock until dropped.
+    pub fn rlock(&self) -> ReadLocking<'_, T>
+        where T: Sync
+    {
+        ReadLocking { lock: self,
+                      waiter: None }
+    }
+
+    /// Exclusively locks access to the content, parking the task if it is
+    /// already locked.
+    ///
+    /// Returns a future that, when awaited on, resolves to an
+    /// [`AsyncWriteGuard`] which allows exclusive mutable access to the
+    /// content and holds the lock until dropped.
+    pub fn wlock(&self) -> WriteLocking<'_, T>
+    {
+        WriteLocking { lock: self,
+                       waiter: None }
+    }
+
+    /// Wakes up all the tasks waiting for the lock.
+    fn release(&self, state: &mut State)
+    {
+        state.waiters.drain().for_each(Waker::wake);
+    }
+}
+
+unsafe impl<T: ?Sized + Send> Send for AsyncRwLock<T> {}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for AsyncRwLock<T> {}
+
+impl<'a, T: ?Sized> Deref for AsyncReadGuard<'a, T>
+{
+    type Target = T;
+
+    fn deref(&self) -> &'a Self::Target
+    {
+        unsafe { &*self.lock.content.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for AsyncReadGuard<'a, T>
+{
+    fn drop(&mut self)
+    {
+        let mut state = self.lock.state.lock();
+        state.share_count -= 1;
+        if state.share_count == 0 {
+            self.lock.release(&mut state);
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for AsyncWriteGuard<'a, T>
+{
+    type Target = T;
+
+    fn deref(&self) -> &'a Self::Target
+    {
+        unsafe { &*self.lock.content.get() }
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for AsyncWriteGuard<'a, T>
+{
+    fn deref_mut(&mut self) -> &'a mut Self::Target
+    {
+        unsafe { &mut *self.lock.content.get() }
+    }
+}
+
+impl<'a, T: ?Sized> Drop for AsyncWriteGuard<'a, T>
+{
+    fn drop(&mut self)
+    {
+        let mut state = self.lock.state.lock();
+        state.is_writing = false;
+        self.lock.release(&mut state);
+    }
+}
+
+impl<'a, T: ?Sized> Future for ReadLocking<'a, T>
+{
+    type Output = AsyncReadGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output>
+    {
+        let lock = self.lock;
+        let mut state = lock.state.lock();
+        if state.is_writing {
+            let waiter = state.waiters.register(self.waiter, (), ctx.waker());
+            self.waiter = Some(waiter);
+            return Poll::Pending;
+        }
+        if let Some(waiter) = self.waiter.take() {
+            state.waiters.unregister(waiter);
+        }
+        state.share_count += 1;
+        Poll::Ready(AsyncReadGuard { lock })
+    }
+}
+
+impl<'a, T: ?Sized> Drop for ReadLocking<'a, T>
+{
+    fn drop(&mut self)
+    {
+        if let Some(waiter) = self.waiter {
+            self.lock.state.lock().waiters.unregister(waiter);
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Future for WriteLocking<'a, T>
+{
+    type Output = AsyncWriteGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output>
+    {
+        let lock = self.lock;
+        let mut state = lock.state.lock();
+        if state.is_writing || state.share_count > 0 {
+            let waiter = state.waiters.register(self.waiter, (), ctx.waker());
+            self.waiter = Some(waiter);
+            return Poll::Pending;
+        }
+        if let Some(waiter) = self.waiter.take() {
+            state.waiters.unregister(waiter);
+        }
+        state.is_writing = true;
+        Poll::Ready(AsyncWriteGuard { lock })
+    }
+}
+
+impl<'a, T: ?Sized> Drop for WriteLocking<'a, T>
+{
+    fn drop(&mut self)
+    {
+        if let Some(waiter) = self.waiter {
+            self.lock.state.lock().waiters.unregister(waiter);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Interrupt-safe critical sections.
+//!
+//! Locks taken both by tasks and by IRQ handlers can deadlock if an IRQ
+//! preempts the holder on its own core, since the handler would then spin on a
+//! lock that can never be released.  Holding a [`Critical`] guard masks IRQs
+//! and FIQs on the current core for as long as it lives, so acquiring such
+//! locks after creating one prevents this condition.
+
+use core::arch::asm;
+use core::marker::PhantomData;
+
+/// Critical section guard.
+#[derive(Debug)]
+pub struct Critical
+{
+    /// Interrupt mask state to restore when dropped.
+    daif: usize,
+    /// Zero-sized field to remove the Send trait.
+    _data: PhantomData<*mut ()>,
+}
+
+impl Drop for Critical
+{
+    fn drop(&mut self)
+    {
+        unsafe { asm!("msr daif, {daif}", daif = in (reg) self.daif, options (nomem, nostack, preserves_flags)) };
+    }
+}
+
+/// Masks IRQs and FIQs on the current core.
+///
+/// Returns a guard that restores the previous interrupt mask state when
+/// dropped.
+pub fn critical() -> Critical
+{
+    let daif: usize;
+    unsafe {
+        asm!("mrs {daif}, daif",
+             "msr daifset, #0x3",
+             daif = out (reg) daif,
+             options (nomem, nostack, preserves_flags))
+    };
+    Critical { daif,
+               _data: PhantomData }
+}
@@ -1,11 +1,23 @@
 //! Synchronization primitives.
 
 mod advisor;
+mod async_lock;
+mod async_rwlock;
+mod critical;
 mod lazy;
 mod lock;
+mod notify;
 mod rwlock;
+mod seqlock;
+mod snapshot;
 
 use self::advisor::Advisor;
+pub use self::async_lock::AsyncLock;
+pub use self::async_rwlock::AsyncRwLock;
+pub use self::critical::{critical, Critical};
 pub use self::lazy::Lazy;
-pub use self::lock::Lock;
+pub use self::lock::{Guard, Lock};
+pub use self::notify::Notify;
 pub use self::rwlock::RwLock;
+pub use self::seqlock::SeqLock;
+pub use self::snapshot::{snapshot, Publisher, Subscriber};
@@ -0,0 +1,121 @@
+//! Task notification primitive.
+//!
+//! Works like a condition variable for tasks: tasks register their interest in
+//! an event, and whoever causes that event, including IRQ handlers, wakes one
+//! or all of them.  Notifications carry no state, so woken tasks are expected
+//! to check whatever condition they were waiting for themselves.  Each waiting
+//! future holds a single registration, which it must withdraw once it
+//! completes or is dropped so that no notification reaches a finished task.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use super::{critical, Lock};
+use crate::wait::{WaitId, WaitList};
+
+/// Notification waiter list.
+#[derive(Debug)]
+pub struct Notify
+{
+    /// Tasks waiting to be awakened.
+    waiters: Lock<WaitList>,
+}
+
+/// Future that becomes ready after being notified.
+#[derive(Debug)]
+pub struct Notified<'a>
+{
+    /// Notifier to wait on.
+    notify: &'a Notify,
+    /// Registration with the notifier, if parked.
+    waiter: Option<WaitId>,
+}
+
+impl Notify
+{
+    /// Creates and initializes a new notifier.
+    ///
+    /// Returns the newly created notifier.
+    pub const fn new() -> Self
+    {
+        Self { waiters: Lock::new(WaitList::new()) }
+    }
+
+    /// Registers a waker to be awakened at the next notification, or
+    /// refreshes an earlier registration that is still pending.
+    ///
+    /// * `waiter`: Registration made by a previous poll of the same future, if
+    ///   any.
+    /// * `waker`: Waker to register.
+    ///
+    /// Returns the registration, which must be withdrawn with
+    /// [`unregister`](Self::unregister) once no longer needed.
+    pub fn register(&self, waiter: Option<WaitId>, waker: &Waker) -> WaitId
+    {
+        // The lock may be shared with IRQ handlers.
+        let _critical = critical();
+        self.waiters.lock().register(waiter, (), waker)
+    }
+
+    /// Withdraws a registration, which is a no-op if it has already been
+    /// notified.
+    ///
+    /// * `waiter`: Registration to withdraw.
+    pub fn unregister(&self, waiter: WaitId)
+    {
+        let _critical = critical();
+        self.waiters.lock().unregister(waiter);
+    }
+
+    /// Wakes the task that has been waiting the longest, if any.
+    pub fn notify_one(&self)
+    {
+        let _critical = critical();
+        if let Some(waker) = self.waiters.lock().pop() {
+            waker.wake();
+        }
+    }
+
+    /// Wakes all waiting tasks.
+    pub fn notify_all(&self)
+    {
+        let _critical = critical();
+        self.waiters.lock().drain().for_each(Waker::wake);
+    }
+
+    /// Waits for the next notification.
+    ///
+    /// Returns a future that, when awaited on, blocks the task until it is
+    /// awakened, possibly spuriously.
+    pub fn notified(&self) -> Notified<'_>
+    {
+        Notified { notify: self,
+                   waiter: None }
+    }
+}
+
+impl Future for Notified<'_>
+{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()>
+    {
+        if let Some(waiter) = self.waiter.take() {
+            self.notify.unregister(waiter);
+            return Poll::Ready(());
+        }
+        self.waiter = Some(self.notify.register(None, ctx.waker()));
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_>
+{
+    fn drop(&mut self)
+    {
+        if let Some(waiter) = self.waiter {
+            self.notify.unregister(waiter);
+        }
+    }
+}
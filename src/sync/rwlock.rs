@@ -6,7 +6,11 @@ use core::marker::PhantomData;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicUsize, Ordering};
 
+#[cfg(lockdebug)]
+use super::advisor::TIMEOUT;
 use super::Advisor;
+#[cfg(lockdebug)]
+use crate::clock::now;
 
 /// Read grant on the lock.
 #[derive(Debug)]
@@ -47,6 +51,7 @@ impl<'a, T: Send + Sync + ?Sized> ReadGuard<'a, T>
     /// * `lock`: Lock to grant shared access to.
     ///
     /// Returns the newly created guard.
+    #[track_caller]
     fn new(lock: &'a RwLock<T>) -> Self
     {
         lock.advisor.lock();
@@ -87,7 +92,13 @@ impl<'a, T: ?Sized> WriteGuard<'a, T>
     #[track_caller]
     fn new(lock: &'a RwLock<T>) -> Self
     {
+        #[cfg(lockdebug)]
+        let start = now();
         while lock.share_count.load(Ordering::Relaxed) != 0 {
+            #[cfg(lockdebug)]
+            assert!(now() - start <= TIMEOUT,
+                    "Deadlock detected: write lock waiting on {} readers",
+                    lock.share_count.load(Ordering::Relaxed));
             spin_loop();
         }
         lock.advisor.lock();
@@ -142,6 +153,7 @@ impl<T: ?Sized> RwLock<T>
     ///
     /// Returns a [`ReadGuard`] which allows shared immutable access to the
     /// content and holds the lock until dropped.
+    #[track_caller]
     pub fn rlock(&self) -> ReadGuard<T>
         where T: Send + Sync
     {
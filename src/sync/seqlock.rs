@@ -0,0 +1,83 @@
+//! Sequence locking primitive.
+//!
+//! Lets writers, including IRQ handlers, publish small values that readers
+//! copy out without ever blocking the writers.  Readers instead retry whenever
+//! a write happened while they were copying, which is detected by a sequence
+//! counter that is odd while a write is in progress.
+
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+
+/// Sequence lock.
+#[derive(Debug)]
+pub struct SeqLock<T: Copy>
+{
+    /// Sequence counter, odd while a write is in progress.
+    seq: AtomicUsize,
+    /// Protected content.
+    content: UnsafeCell<T>,
+}
+
+impl<T: Copy> SeqLock<T>
+{
+    /// Creates and initializes a new sequence lock.
+    ///
+    /// * `content`: Content to protect.
+    ///
+    /// Returns the newly created lock.
+    pub const fn new(content: T) -> Self
+    {
+        Self { seq: AtomicUsize::new(0),
+               content: UnsafeCell::new(content) }
+    }
+
+    /// Copies the content, retrying for as long as it is modified in the
+    /// process.
+    ///
+    /// Returns a consistent copy of the content.
+    pub fn read(&self) -> T
+    {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 0x1 != 0 {
+                spin_loop();
+                continue;
+            }
+            // The copy may be torn, in which case it is discarded below.
+            let content = unsafe { self.content.get().read_volatile() };
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return content;
+            }
+        }
+    }
+
+    /// Replaces the content, waiting for any other writer to finish first.
+    ///
+    /// * `content`: New content.
+    pub fn write(&self, content: T)
+    {
+        let mut seq = self.seq.load(Ordering::Relaxed);
+        loop {
+            if seq & 0x1 != 0 {
+                spin_loop();
+                seq = self.seq.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.seq
+                      .compare_exchange_weak(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(actual) => seq = actual,
+            }
+        }
+        fence(Ordering::Release);
+        unsafe { self.content.get().write_volatile(content) };
+        self.seq.store(seq + 2, Ordering::Release);
+    }
+}
+
+unsafe impl<T: Copy + Send> Send for SeqLock<T> {}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
@@ -0,0 +1,115 @@
+//! Lock-free state snapshots.
+//!
+//! Implements a triple buffer shared between a single publisher and a single
+//! subscriber, allowing a producer such as the simulation to publish
+//! consistent snapshots of its state that a consumer such as the renderer reads
+//! without either of them ever blocking the other.  The subscriber always sees
+//! the most recently published snapshot, and never a partially updated one.
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Flag set in the middle buffer index when it contains a snapshot that the
+/// subscriber has not seen yet.
+const FRESH: usize = 0x4;
+
+/// Publisher end.
+#[derive(Debug)]
+pub struct Publisher<T: Send>
+{
+    /// Shared buffers.
+    shared: Arc<Shared<T>>,
+    /// Index of the buffer being written.
+    back: usize,
+}
+
+/// Subscriber end.
+#[derive(Debug)]
+pub struct Subscriber<T: Send>
+{
+    /// Shared buffers.
+    shared: Arc<Shared<T>>,
+    /// Index of the buffer being read.
+    front: usize,
+}
+
+/// Buffers shared between the publisher and the subscriber.
+#[derive(Debug)]
+struct Shared<T: Send>
+{
+    /// Buffers.
+    bufs: [UnsafeCell<T>; 3],
+    /// Index of the buffer not owned by either end, along with the fresh flag.
+    middle: AtomicUsize,
+}
+
+impl<T: Send> Publisher<T>
+{
+    /// Returns the buffer of the next snapshot to publish.  This buffer holds
+    /// an older snapshot, so all of its content must be rewritten.
+    pub fn write(&mut self) -> &mut T
+    {
+        unsafe { &mut *self.shared.bufs[self.back].get() }
+    }
+
+    /// Publishes the snapshot in the buffer returned by [`Self::write`].
+    pub fn publish(&mut self)
+    {
+        let middle = self.shared.middle.swap(self.back | FRESH, Ordering::AcqRel);
+        self.back = middle & !FRESH;
+    }
+
+    /// Replaces the content of the next snapshot and publishes it.
+    ///
+    /// * `val`: Snapshot to publish.
+    pub fn publish_value(&mut self, val: T)
+    {
+        *self.write() = val;
+        self.publish();
+    }
+}
+
+unsafe impl<T: Send> Send for Publisher<T> {}
+
+impl<T: Send> Subscriber<T>
+{
+    /// Returns the most recently published snapshot.
+    pub fn read(&mut self) -> &T
+    {
+        if self.is_fresh() {
+            let middle = self.shared.middle.swap(self.front, Ordering::AcqRel);
+            self.front = middle & !FRESH;
+        }
+        unsafe { &*self.shared.bufs[self.front].get() }
+    }
+
+    /// Returns whether a snapshot newer than the one last read is available.
+    pub fn is_fresh(&self) -> bool
+    {
+        self.shared.middle.load(Ordering::Relaxed) & FRESH != 0
+    }
+}
+
+unsafe impl<T: Send> Send for Subscriber<T> {}
+
+/// Creates a new snapshot triple buffer.
+///
+/// * `init`: Initial snapshot, seen by the subscriber until the first
+///   publication.
+///
+/// Returns the publisher and subscriber ends of the newly created buffer.
+pub fn snapshot<T: Send + Clone>(init: T) -> (Publisher<T>, Subscriber<T>)
+{
+    let bufs = [UnsafeCell::new(init.clone()),
+                UnsafeCell::new(init.clone()),
+                UnsafeCell::new(init)];
+    let shared = Arc::new(Shared { bufs,
+                                   middle: AtomicUsize::new(1) });
+    let publisher = Publisher { shared: shared.clone(),
+                                back: 0 };
+    let subscriber = Subscriber { shared, front: 2 };
+    (publisher, subscriber)
+}
@@ -0,0 +1,169 @@
+//! Temperature and throttling telemetry.
+//!
+//! Periodically queries the firmware for the temperature of the SoC and for
+//! the flags reporting whether the board is undervolted or being throttled,
+//! and notifies registered hooks whenever the board enters or leaves a state
+//! that calls for relief, so that the game can warn the player or lower its
+//! workload.  The meaning of the throttling flags is documented in the
+//! firmware's `vcgencmd` documentation [1].
+//!
+//! [1]: https://www.raspberrypi.com/documentation/computers/os.html#get_throttled
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FormatResult};
+use core::mem::replace;
+
+use crate::sync::{Lazy, Lock};
+use crate::timer::TIMER;
+use crate::{debug, mbox};
+
+/// Get temperature property tag.
+const GET_TEMPERATURE_TAG: u32 = 0x30006;
+/// Get throttled flags property tag.
+const GET_THROTTLED_TAG: u32 = 0x30046;
+/// Identifier of the SoC temperature sensor.
+const SOC_SENSOR_ID: u32 = 0;
+/// Time in milliseconds between queries.
+const MONITOR_PERIOD: u64 = 1000;
+/// Temperature in millidegrees Celsius above which the SoC is considered to be
+/// overheating, which is slightly below the firmware's soft limit.
+const WARN_TEMPERATURE: u32 = 75000;
+/// Throttled flag set while the board is undervolted.
+const UNDERVOLTED: u32 = 0x1;
+/// Throttled flag set while the ARM frequency is capped.
+const FREQ_CAPPED: u32 = 0x2;
+/// Throttled flag set while the ARM cores are throttled.
+const THROTTLED: u32 = 0x4;
+/// Throttled flag set while the soft temperature limit is active.
+const SOFT_LIMITED: u32 = 0x8;
+
+/// Global thermal monitor instance.
+pub static THERMAL: Lazy<Thermal> = Lazy::new(Thermal::new);
+
+/// Thermal monitor.
+#[derive(Debug)]
+pub struct Thermal
+{
+    /// Last queried status.
+    status: Lock<Status>,
+    /// Hooks to call when relief becomes needed or unneeded.
+    hooks: Lock<Vec<fn(Status)>>,
+}
+
+/// Temperature and throttling status.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Status
+{
+    /// SoC temperature in millidegrees Celsius.
+    pub temperature: u32,
+    /// Throttled flags.
+    pub throttled: u32,
+}
+
+impl Thermal
+{
+    /// Creates and initializes a new thermal monitor.
+    ///
+    /// Returns the newly created monitor.
+    fn new() -> Self
+    {
+        Self { status: Lock::new(Status::query()),
+               hooks: Lock::new(Vec::new()) }
+    }
+
+    /// Registers a hook to call whenever the board enters or leaves a state
+    /// that calls for relief.
+    ///
+    /// * `hook`: Function to call with the new status.
+    pub fn register(&self, hook: fn(Status))
+    {
+        self.hooks.lock().push(hook);
+    }
+
+    /// Returns the last queried status.
+    pub fn status(&self) -> Status
+    {
+        *self.status.lock()
+    }
+
+    /// Queries the status periodically, calling the registered hooks whenever
+    /// relief becomes needed or unneeded.
+    pub async fn run(&self) -> !
+    {
+        loop {
+            TIMER.sleep(MONITOR_PERIOD).await;
+            let status = Status::query();
+            let old = replace(&mut *self.status.lock(), status);
+            if status.needs_relief() == old.needs_relief() {
+                continue;
+            }
+            debug!("Thermal status changed: {status}");
+            let count = self.hooks.lock().len();
+            for idx in 0 .. count {
+                let hook = self.hooks.lock()[idx];
+                hook(status);
+            }
+        }
+    }
+}
+
+impl Status
+{
+    /// Queries the firmware for the current status.
+    ///
+    /// Returns the queried status.
+    fn query() -> Self
+    {
+        let temp: [u32; 2];
+        let throttled: u32;
+        mbox! {
+            GET_TEMPERATURE_TAG: SOC_SENSOR_ID => temp,
+            GET_THROTTLED_TAG: 0u32 => throttled,
+        };
+        Self { temperature: temp[1],
+               throttled }
+    }
+
+    /// Returns whether the board is undervolted.
+    pub fn is_undervolted(self) -> bool
+    {
+        self.throttled & UNDERVOLTED != 0
+    }
+
+    /// Returns whether the ARM cores are running below their nominal frequency
+    /// because of either capping or throttling.
+    pub fn is_throttled(self) -> bool
+    {
+        self.throttled & (FREQ_CAPPED | THROTTLED) != 0
+    }
+
+    /// Returns whether the SoC is overheating, either because its temperature
+    /// is above the warning threshold or because the firmware has activated
+    /// its soft temperature limit.
+    pub fn is_overheating(self) -> bool
+    {
+        self.temperature >= WARN_TEMPERATURE || self.throttled & SOFT_LIMITED != 0
+    }
+
+    /// Returns whether the workload should be lowered to relieve the board.
+    pub fn needs_relief(self) -> bool
+    {
+        self.is_overheating() || self.is_undervolted() || self.is_throttled()
+    }
+}
+
+impl Display for Status
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        write!(fmt,
+               "{}.{}C, undervolted: {}, throttled: {}, overheating: {}",
+               self.temperature / 1000,
+               self.temperature % 1000 / 100,
+               self.is_undervolted(),
+               self.is_throttled(),
+               self.is_overheating())
+    }
+}
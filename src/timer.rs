@@ -1,19 +1,28 @@
 //! Timer scheduler.
 //!
-//! Provides timer scheduling functionality piggyhopped on the VSync interrupt
-//! handled by the pixel valve driver since that's the main ticker used by this
-//! project.  This is a best effort implementation that will try to respect the
-//! periodicity of scheduled timers as much as possible, but might delay or even
-//! skip handler calls depending on system load.
+//! Provides timer scheduling functionality driven by the one-shot alarm of the
+//! system timer, which is always armed for the earliest pending deadline.  The
+//! VSync interrupt handled by the pixel valve driver also ticks the scheduler
+//! as a fallback in case an alarm is missed.  This is a best effort
+//! implementation that will try to respect the periodicity of scheduled timers
+//! as much as possible, but might delay or even skip handler calls depending on
+//! system load.  Tasks can also await on [`Sleep`] futures, which are woken up
+//! from the same tick and withdraw themselves from the timer when dropped
+//! before their deadlines.
 
 extern crate alloc;
 
 use alloc::vec::Vec;
 use core::cmp::Reverse;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
 
-use crate::clock::now;
+use crate::clock::{clear_alarm, now, set_alarm, ALARM_IRQ};
+use crate::irq::IRQ;
 use crate::pixvalve::PIXVALVE;
 use crate::sync::{Lazy, Lock};
+use crate::wait::{WaitId, WaitList};
 
 /// Global timer scheduler instance.
 pub static TIMER: Lazy<Timer> = Lazy::new(Timer::new);
@@ -25,6 +34,20 @@ pub struct Timer
     new_timers: Lock<Vec<Event>>,
     /// Scheduled timers.
     timers: Lock<Vec<Event>>,
+    /// Sleeping tasks keyed by their deadlines.
+    sleepers: Lock<WaitList<u64>>,
+    /// Deadline that the alarm is currently armed for, if any.
+    alarm: Lock<Option<u64>>,
+}
+
+/// Future that completes once a deadline has passed.
+#[derive(Debug)]
+pub struct Sleep
+{
+    /// Deadline.
+    deadline: u64,
+    /// Registration with the timer, if parked.
+    waiter: Option<WaitId>,
 }
 
 /// Timer event.
@@ -45,9 +68,24 @@ impl Timer
     /// Returns the newly  created scheduler.
     fn new() -> Self
     {
+        IRQ.register(ALARM_IRQ, Self::ring);
         PIXVALVE.register_vsync(Self::tick);
         Self { new_timers: Lock::new(Vec::new()),
-               timers: Lock::new(Vec::new()) }
+               timers: Lock::new(Vec::new()),
+               sleepers: Lock::new(WaitList::new()),
+               alarm: Lock::new(None) }
+    }
+
+    /// Creates a future that completes after a time interval.
+    ///
+    /// * `interval`: Minimum time interval in milliseconds between this call
+    ///   and the completion of the future.
+    ///
+    /// Returns the newly created future.
+    pub fn sleep(&self, interval: u64) -> Sleep
+    {
+        Sleep { deadline: now() + interval,
+                waiter: None }
     }
 
     /// Registers a handler to be called after a time interval.
@@ -64,6 +102,38 @@ impl Timer
                             period: interval,
                             handler };
         self.new_timers.lock().push(event);
+        self.rearm();
+    }
+
+    /// Arms the alarm for the earliest pending deadline unless it's already
+    /// armed for it.
+    fn rearm(&self)
+    {
+        let mut alarm = self.alarm.lock();
+        let timer = self.timers.lock().last().map(|event| event.deadline);
+        let new_timer = self.new_timers.lock().iter().map(|event| event.deadline).min();
+        let sleeper = self.sleepers.lock().keys().copied().min();
+        let Some(deadline) = [timer, new_timer, sleeper].into_iter().flatten().min() else {
+            return;
+        };
+        if *alarm == Some(deadline) {
+            return;
+        }
+        set_alarm(deadline * 1000);
+        *alarm = Some(deadline);
+    }
+
+    /// Alarm IRQ handler.
+    ///
+    /// Returns whether the alarm had gone off.
+    fn ring() -> bool
+    {
+        if !clear_alarm() {
+            return false;
+        }
+        *TIMER.alarm.lock() = None;
+        Self::tick();
+        true
     }
 
     /// Tick handler.
@@ -80,10 +150,15 @@ impl Timer
             timers.sort_unstable_by_key(|event| Reverse(event.deadline));
         }
         drop(timers);
+        // Wake up all the tasks whose deadlines have passed.
+        let expired = TIMER.sleepers.lock().drain_where(|deadline| *deadline <= now);
+        expired.into_iter().for_each(Waker::wake);
         // Call the handlers of all the expired timers.
         loop {
             let mut timers = TIMER.timers.lock();
             if timers.last().map(|event| event.deadline > now).unwrap_or(true) {
+                drop(timers);
+                TIMER.rearm();
                 return;
             }
             let event = timers.pop().unwrap();
@@ -99,3 +174,32 @@ impl Timer
         }
     }
 }
+
+impl Future for Sleep
+{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()>
+    {
+        if now() >= self.deadline {
+            if let Some(waiter) = self.waiter.take() {
+                TIMER.sleepers.lock().unregister(waiter);
+            }
+            return Poll::Ready(());
+        }
+        let waiter = TIMER.sleepers.lock().register(self.waiter, self.deadline, ctx.waker());
+        self.waiter = Some(waiter);
+        TIMER.rearm();
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep
+{
+    fn drop(&mut self)
+    {
+        if let Some(waiter) = self.waiter {
+            TIMER.sleepers.lock().unregister(waiter);
+        }
+    }
+}
@@ -10,10 +10,11 @@ use core::simd::f32x4;
 use core::sync::atomic::{fence, Ordering};
 
 use crate::alloc::{Alloc, UNCACHED_REGION};
+use crate::clock::now_micros;
 use crate::math::{Angle, Quaternion};
 use crate::pixvalve::PIXVALVE;
 use crate::simd::*;
-use crate::sync::{Lazy, Lock, RwLock};
+use crate::sync::{Lazy, Lock, SeqLock};
 use crate::{mbox, to_dma};
 
 /// Maximum number of touch points tracked by the video core.
@@ -39,8 +40,9 @@ pub struct Touch
 {
     /// Touchscreen buffer.
     state: Lock<Box<State, Alloc<'static, 0x10>>>,
-    /// Saved touch points for comparison.
-    saved: RwLock<[Option<f32x4>; 2]>,
+    /// Saved touch points for comparison along with the time in microseconds
+    /// at which they were captured.
+    saved: SeqLock<([Option<f32x4>; 2], u64)>,
 }
 
 /// Input changes since the last poll.
@@ -49,10 +51,16 @@ pub struct Recognizer
 {
     /// Last saved sample.
     saved: [Option<f32x4>; 2],
+    /// Time at which the last saved sample was captured.
+    stamp: u64,
+    /// Capture time of the last sample if it was new.
+    input_time: Option<u64>,
     /// Amount moved since the last poll.
     pub trans: f32x4,
     /// Amount rotated since the last poll.
     pub rot: Quaternion,
+    /// Factor scaled since the last poll.
+    scale: f32,
     /// First finger's position.
     pos0: Option<f32x4>,
     /// Second finger's position.
@@ -107,10 +115,10 @@ impl Touch
         let state = Box::new_in(state, UNCACHED);
         let addr_in = to_dma(state.as_ref() as *const State as usize) as u32;
         mbox! {SET_TOUCHBUF_TAG: addr_in => _};
-        let saved = Default::default();
+        let saved = ([None, None], 0);
         PIXVALVE.register_vsync(Self::poll);
         Self { state: Lock::new(state),
-               saved: RwLock::new(saved) }
+               saved: SeqLock::new(saved) }
     }
 
     /// Handler that polls the touchscreen buffer and updates the saved state
@@ -125,9 +133,10 @@ impl Touch
         }
         hw_state.points_len = INVALID_POINTS;
         fence(Ordering::Release);
+        let stamp = now_micros();
         // We're only interested in information containing at most two touch points.
         if !(1 ..= 2).contains(&state.points_len) {
-            *TOUCH.saved.wlock() = Default::default();
+            TOUCH.saved.write(([None, None], stamp));
             return;
         }
         let mapper = |point: &Point| {
@@ -138,7 +147,7 @@ impl Touch
         };
         let mut iter = state.points[.. state.points_len as usize].iter().map(mapper).fuse();
         let new = [iter.next(), iter.next()];
-        *TOUCH.saved.wlock() = new;
+        TOUCH.saved.write((new, stamp));
     }
 }
 
@@ -155,8 +164,11 @@ impl Recognizer
     pub fn new() -> Self
     {
         Self { saved: [None, None],
+               stamp: 0,
+               input_time: None,
                trans: f32x4::from_array([0.0; 4]),
                rot: Quaternion::default(),
+               scale: 1.0,
                pos0: None,
                pos1: None }
     }
@@ -173,6 +185,13 @@ impl Recognizer
         self.rot
     }
 
+    /// Returns the factor by which the distance between the two touch points
+    /// changed since last sampled.
+    pub fn scale_delta(&self) -> f32
+    {
+        self.scale
+    }
+
     /// Returns the position of the first touch point.
     pub fn first_position(&self) -> Option<f32x4>
     {
@@ -185,20 +204,34 @@ impl Recognizer
         self.pos1
     }
 
+    /// Returns the time in microseconds at which the touch sensor captured the
+    /// last sample, or [`None`] if it didn't change since the previous one.
+    pub fn input_time(&self) -> Option<u64>
+    {
+        self.input_time
+    }
+
     /// Samples the touch sensor and computes the deltas since the last sample.
     pub fn sample(&mut self)
     {
-        let new = *TOUCH.saved.rlock();
+        let (new, stamp) = TOUCH.saved.read();
+        self.input_time = (stamp != self.stamp).then_some(stamp);
+        self.stamp = stamp;
         let old = self.saved;
         self.saved = new;
         self.pos0 = new[0];
         self.pos1 = new[1];
         match (old[0], old[1], new[0], new[1]) {
             (Some(old0), Some(old1), Some(new0), Some(new1)) => self.compute_rotation(old0, old1, new0, new1),
-            (Some(old), None, Some(new), None) => self.compute_translation(old, new),
+            (Some(old), None, Some(new), None) => {
+                self.compute_translation(old, new);
+                self.rot = Quaternion::default();
+                self.scale = 1.0;
+            }
             _ => {
                 self.rot = Quaternion::default();
                 self.trans = f32x4::from_array([0.0; 4]);
+                self.scale = 1.0;
             }
         }
     }
@@ -212,7 +245,8 @@ impl Recognizer
         self.trans = new - old;
     }
 
-    /// Computes the rotation from a two-finger gesture.
+    /// Computes the rotation, scale, and translation of the midpoint from a
+    /// two-finger gesture.
     ///
     /// * `old0`: First old sample.
     /// * `old1`: Second old sample.
@@ -225,10 +259,13 @@ impl Recognizer
         let sqdist0 = (old0 - new0).sq_len();
         let sqdist1 = (old0 - new1).sq_len();
         let (new0, new1) = if sqdist0 <= sqdist1 { (new0, new1) } else { (new1, new0) };
+        self.trans = (new0 + new1 - old0 - old1).mul_scalar(0.5);
         // Compute the rotation by calculating the angle between the vectors created by
         // the difference between the two contacts in each sample.
         let old = old1 - old0;
         let new = new1 - new0;
+        let scale = new.len() / old.len();
+        self.scale = if scale.is_finite() { scale } else { 1.0 };
         let (Some(old), Some(new)) = (old.normalize(), new.normalize()) else {
             self.rot = Quaternion::default();
             return;
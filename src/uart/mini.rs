@@ -0,0 +1,91 @@
+//! Mini UART driver.
+//!
+//! The Mini UART is one of the auxiliary peripherals, and its baud rate is
+//! derived from the core clock.
+
+use core::sync::atomic::{fence, Ordering};
+
+use super::{Port, BAUD_RATE, RX_PIN, TX_PIN};
+use crate::gpio::{Function, Pin, Pull, GPIO};
+use crate::PERRY_RANGE;
+
+/// Base of the auxiliary peripheral configuration registers
+const AUX_BASE: usize = 0x2215000 + PERRY_RANGE.start;
+/// Auxiliary peripheral pending IRQ register.
+const AUX_IRQ: *const u32 = AUX_BASE as _;
+/// Auxiliary peripheral enabler register.
+const AUX_ENABLES: *mut u32 = (AUX_BASE + 0x4) as _;
+/// Input / output Mini UART register.
+const AUX_MU_IO: *mut u32 = (AUX_BASE + 0x40) as _;
+/// Interrupt enable Mini UART register.
+const AUX_MU_IER: *mut u32 = (AUX_BASE + 0x44) as _;
+/// Data status Mini UART register.
+const AUX_MU_LCR: *mut u32 = (AUX_BASE + 0x4C) as _;
+/// Control MiniUART register.
+const AUX_MU_CNTL: *mut u32 = (AUX_BASE + 0x60) as _;
+/// Mini UART status register.
+const AUX_MU_STAT: *const u32 = (AUX_BASE + 0x64) as _;
+/// Mini UART BAUD rate divisor.
+const AUX_MU_BAUD: *mut u32 = (AUX_BASE + 0x68) as _;
+/// Mini UART IRQ, shared by all the auxiliary peripherals.
+const AUX_IRQ_NUM: u32 = 125;
+
+/// Mini UART driver.
+#[derive(Debug)]
+pub struct MiniUart
+{
+    /// Transmit pin.
+    _tx: Pin,
+    /// Receive pin.
+    _rx: Pin,
+}
+
+impl Port for MiniUart
+{
+    const IRQ: u32 = AUX_IRQ_NUM;
+
+    fn new() -> Self
+    {
+        let mut tx = GPIO.claim(TX_PIN).expect("Mini UART transmit pin already claimed");
+        let mut rx = GPIO.claim(RX_PIN).expect("Mini UART receive pin already claimed");
+        for pin in [&mut tx, &mut rx] {
+            pin.set_function(Function::Alt5);
+            pin.set_pull(Pull::None);
+        }
+        unsafe {
+            AUX_ENABLES.write_volatile(0x1); // Enable the Mini UART.
+            AUX_MU_CNTL.write_volatile(0x0); // Temporarily disable transmission and reception..
+            AUX_MU_IER.write_volatile(0x0); // Disable all interrupts.
+            AUX_MU_LCR.write_volatile(0x3); // Set data bits to 8 (the documentation is wrong).
+            AUX_MU_BAUD.write_volatile(500000000 / BAUD_RATE / 8 - 1); // Set the BAUD rate.
+            AUX_MU_CNTL.write_volatile(0x3); // Enable the transmitter and
+                                             // receiver.
+        }
+        Self { _tx: tx, _rx: rx }
+    }
+
+    fn send(byte: u8) -> bool
+    {
+        fence(Ordering::Acquire);
+        if unsafe { AUX_MU_STAT.read_volatile() } & 0x20 != 0 {
+            return false; // FIFO full.
+        }
+        unsafe { AUX_MU_IO.write_volatile(byte as _) };
+        fence(Ordering::Release);
+        true
+    }
+
+    fn set_tx_interrupt(enable: bool)
+    {
+        // The transmit and receive interrupt enable bits are swapped in the
+        // documentation.
+        unsafe { AUX_MU_IER.write_volatile(if enable { 0x2 } else { 0x0 }) };
+        fence(Ordering::Release);
+    }
+
+    fn is_tx_pending() -> bool
+    {
+        fence(Ordering::Acquire);
+        unsafe { AUX_IRQ.read_volatile() & 0x1 != 0 && AUX_MU_IER.read_volatile() & 0x2 != 0 }
+    }
+}
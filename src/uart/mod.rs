@@ -0,0 +1,261 @@
+//! UART drivers.
+//!
+//! The console is driven by the Mini UART by default, or by the PL011 UART
+//! when built with the `pl011` option, since the baud rate of the former is
+//! derived from the core clock and drifts whenever its frequency scales,
+//! whereas the latter is fed by a dedicated clock.
+//!
+//! Output is queued in a ring buffer that the UART's IRQ handler drains as the
+//! transmit FIFO empties, so that logging only blocks when the buffer is full.
+//! Panics bypass the buffer with a [`Blocking`] writer instead, since they can
+//! happen while the buffer is locked or with IRQs masked.
+//!
+//! Documentation:
+//!
+//! * [BCM2711 ARM Peripherals](https://datasheets.raspberrypi.com/bcm2711/bcm2711-peripherals.pdf)
+//!   2, 5, and 11
+//! * [PrimeCell UART (PL011) Technical Reference Manual](https://developer.arm.com/documentation/ddi0183/latest)
+
+extern crate alloc;
+
+#[cfg(not(pl011))]
+mod mini;
+#[cfg(pl011)]
+mod pl011;
+
+use alloc::vec::Vec;
+use core::cmp::min;
+use core::fmt::{Result as FormatResult, Write};
+use core::hint::spin_loop;
+use core::mem::swap;
+
+#[cfg(not(pl011))]
+use self::mini::MiniUart;
+#[cfg(pl011)]
+use self::pl011::Pl011;
+use crate::irq::IRQ;
+use crate::sync::{critical, Critical, Guard, Lazy, Lock};
+
+/// Console baud rate.
+const BAUD_RATE: u32 = 115200;
+/// Transmit pin.
+const TX_PIN: usize = 14;
+/// Receive pin.
+const RX_PIN: usize = 15;
+/// Size of the transmit ring buffer.
+const TX_BUFFER_SIZE: usize = 0x4000;
+/// Maximum number of mirrored bytes kept until taken, beyond which further
+/// output isn't mirrored.
+const MIRROR_SIZE: usize = 0x4000;
+
+/// Global UART driver instance.
+pub static UART: Lazy<Uart> = Lazy::new(Uart::new);
+
+/// Send formatted diagnostic messages over the console UART.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        let mut uart = $crate::uart::UART.lock();
+        writeln!(uart, $($arg)*).unwrap();
+    }};
+}
+
+/// Hardware driving the console.
+#[cfg(not(pl011))]
+type Console = MiniUart;
+/// Hardware driving the console.
+#[cfg(pl011)]
+type Console = Pl011;
+
+/// Console driver.
+#[derive(Debug)]
+pub struct Uart
+{
+    /// Console hardware and transmit ring buffer, shared with the IRQ handler.
+    state: Lock<State>,
+}
+
+/// Exclusive access to the console, which queues everything written to it for
+/// transmission.
+#[derive(Debug)]
+pub struct Writer<'a>
+{
+    /// Console state, which must be released before IRQs are unmasked.
+    state: Guard<'a, State>,
+    /// Critical section preventing the IRQ handler from preempting the holder.
+    _critical: Critical,
+}
+
+/// Console writer that waits for room in the transmit FIFO without locking or
+/// buffering, for use in panic paths.
+#[derive(Debug)]
+pub struct Blocking;
+
+/// Console state.
+#[derive(Debug)]
+struct State
+{
+    /// Console hardware.
+    _console: Console,
+    /// Bytes waiting to be transmitted.
+    buf: [u8; TX_BUFFER_SIZE],
+    /// Index of the oldest byte in the buffer.
+    head: usize,
+    /// Number of bytes in the buffer.
+    len: usize,
+    /// Copy of the output since it was last taken, if mirroring is enabled.
+    mirror: Option<Vec<u8>>,
+}
+
+/// Operations shared by the UARTs that can drive the console.
+trait Port: Sized
+{
+    /// IRQ raised by the UART.
+    const IRQ: u32;
+
+    /// Creates and initializes a new driver instance.
+    ///
+    /// Returns the newly created driver instance.
+    fn new() -> Self;
+
+    /// Attempts to push a byte into the transmit FIFO.
+    ///
+    /// * `byte`: Byte to transmit.
+    ///
+    /// Returns whether there was room in the FIFO.
+    fn send(byte: u8) -> bool;
+
+    /// Enables or disables the IRQ raised when the transmit FIFO has room.
+    ///
+    /// * `enable`: Whether to enable the IRQ.
+    fn set_tx_interrupt(enable: bool);
+
+    /// Returns whether the transmit FIFO has room and its IRQ is enabled.
+    fn is_tx_pending() -> bool;
+}
+
+impl Uart
+{
+    /// Creates and initializes a new console driver.
+    ///
+    /// Returns the newly created driver.
+    fn new() -> Self
+    {
+        let state = State { _console: Console::new(),
+                            buf: [0; TX_BUFFER_SIZE],
+                            head: 0,
+                            len: 0,
+                            mirror: None };
+        IRQ.register(Console::IRQ, || UART.interrupt());
+        Self { state: Lock::new(state) }
+    }
+
+    /// Locks the console for writing.
+    ///
+    /// Returns a writer that holds the lock until dropped.
+    #[track_caller]
+    pub fn lock(&self) -> Writer<'_>
+    {
+        // The lock is shared with the UART IRQ handler.
+        let critical = critical();
+        Writer { state: self.state.lock(),
+                 _critical: critical }
+    }
+
+    /// Enables or disables keeping a copy of the output for other transports.
+    ///
+    /// * `enable`: Whether to keep a copy of the output.
+    pub fn mirror(&self, enable: bool)
+    {
+        // Allocate outside the critical section.
+        let mirror = enable.then(|| Vec::with_capacity(MIRROR_SIZE));
+        self.lock().state.mirror = mirror;
+    }
+
+    /// Takes the output copied since the last call, if mirroring is enabled.
+    ///
+    /// Returns the copied output, which is empty if mirroring is disabled.
+    pub fn take_mirrored(&self) -> Vec<u8>
+    {
+        let mut mirrored = Vec::with_capacity(MIRROR_SIZE);
+        if let Some(mirror) = self.lock().state.mirror.as_mut() {
+            swap(mirror, &mut mirrored);
+        }
+        mirrored
+    }
+
+    /// UART IRQ handler.
+    ///
+    /// Returns whether the transmit FIFO had room for queued bytes.
+    fn interrupt(&self) -> bool
+    {
+        if !Console::is_tx_pending() {
+            return false;
+        }
+        self.state.lock().drain();
+        true
+    }
+}
+
+impl<'a> Write for Writer<'a>
+{
+    fn write_str(&mut self, msg: &str) -> FormatResult
+    {
+        for byte in msg.as_bytes() {
+            self.state.push(*byte);
+        }
+        self.state.drain();
+        if let Some(mirror) = self.state.mirror.as_mut() {
+            let count = min(MIRROR_SIZE - mirror.len(), msg.len());
+            mirror.extend_from_slice(&msg.as_bytes()[.. count]);
+        }
+        Ok(())
+    }
+}
+
+impl Write for Blocking
+{
+    fn write_str(&mut self, msg: &str) -> FormatResult
+    {
+        // Make sure that the UART is initialized.
+        let _ = &*UART;
+        for byte in msg.as_bytes() {
+            while !Console::send(*byte) {
+                spin_loop()
+            }
+        }
+        Ok(())
+    }
+}
+
+impl State
+{
+    /// Queues a byte for transmission, transmitting the oldest queued byte
+    /// first if the buffer is full.
+    ///
+    /// * `byte`: Byte to queue.
+    fn push(&mut self, byte: u8)
+    {
+        if self.len == TX_BUFFER_SIZE {
+            while !Console::send(self.buf[self.head]) {
+                spin_loop()
+            }
+            self.head = (self.head + 1) % TX_BUFFER_SIZE;
+            self.len -= 1;
+        }
+        self.buf[(self.head + self.len) % TX_BUFFER_SIZE] = byte;
+        self.len += 1;
+    }
+
+    /// Moves as many queued bytes as fit into the transmit FIFO, and keeps the
+    /// transmit IRQ enabled for as long as any are left.
+    fn drain(&mut self)
+    {
+        while self.len > 0 && Console::send(self.buf[self.head]) {
+            self.head = (self.head + 1) % TX_BUFFER_SIZE;
+            self.len -= 1;
+        }
+        Console::set_tx_interrupt(self.len > 0);
+    }
+}
@@ -0,0 +1,100 @@
+//! PL011 UART driver.
+//!
+//! The PL011 UART is fed by a dedicated reference clock, so its baud rate
+//! stays stable when other clocks scale.
+
+use core::sync::atomic::{fence, Ordering};
+
+use super::{Port, BAUD_RATE, RX_PIN, TX_PIN};
+use crate::gpio::{Function, Pin, Pull, GPIO};
+use crate::PERRY_RANGE;
+
+/// Base of the PL011 UART registers.
+const UART0_BASE: usize = 0x2201000 + PERRY_RANGE.start;
+/// PL011 UART data register.
+const UART0_DR: *mut u32 = UART0_BASE as _;
+/// PL011 UART flag register.
+const UART0_FR: *const u32 = (UART0_BASE + 0x18) as _;
+/// PL011 UART integer baud rate divisor register.
+const UART0_IBRD: *mut u32 = (UART0_BASE + 0x24) as _;
+/// PL011 UART fractional baud rate divisor register.
+const UART0_FBRD: *mut u32 = (UART0_BASE + 0x28) as _;
+/// PL011 UART line control register.
+const UART0_LCRH: *mut u32 = (UART0_BASE + 0x2C) as _;
+/// PL011 UART control register.
+const UART0_CR: *mut u32 = (UART0_BASE + 0x30) as _;
+/// PL011 UART interrupt mask register.
+const UART0_IMSC: *mut u32 = (UART0_BASE + 0x38) as _;
+/// PL011 UART masked interrupt status register.
+const UART0_MIS: *const u32 = (UART0_BASE + 0x40) as _;
+/// PL011 UART interrupt clear register.
+const UART0_ICR: *mut u32 = (UART0_BASE + 0x44) as _;
+/// PL011 UART reference clock frequency in hertz, as configured by the
+/// firmware.
+const UART0_CLOCK: u32 = 48000000;
+/// PL011 UART IRQ.
+const UART0_IRQ_NUM: u32 = 153;
+
+/// PL011 UART driver.
+#[derive(Debug)]
+pub struct Pl011
+{
+    /// Transmit pin.
+    _tx: Pin,
+    /// Receive pin.
+    _rx: Pin,
+}
+
+impl Port for Pl011
+{
+    const IRQ: u32 = UART0_IRQ_NUM;
+
+    fn new() -> Self
+    {
+        let mut tx = GPIO.claim(TX_PIN).expect("PL011 UART transmit pin already claimed");
+        let mut rx = GPIO.claim(RX_PIN).expect("PL011 UART receive pin already claimed");
+        for pin in [&mut tx, &mut rx] {
+            pin.set_function(Function::Alt0);
+            pin.set_pull(Pull::None);
+        }
+        // The divisor is expressed in sixty-fourths and rounded to the nearest.
+        let div = (UART0_CLOCK * 4 + BAUD_RATE / 2) / BAUD_RATE;
+        unsafe {
+            UART0_CR.write_volatile(0x0); // Disable the UART while it's being configured.
+            UART0_IMSC.write_volatile(0x0); // Mask all interrupts.
+            UART0_ICR.write_volatile(0x7FF); // Clear all pending interrupts.
+            UART0_IBRD.write_volatile(div >> 6);
+            UART0_FBRD.write_volatile(div & 0x3F);
+            UART0_LCRH.write_volatile(0x70); // Set data bits to 8 and enable the FIFOs.
+            UART0_CR.write_volatile(0x301); // Enable the UART, transmitter, and
+                                            // receiver.
+        }
+        Self { _tx: tx, _rx: rx }
+    }
+
+    fn send(byte: u8) -> bool
+    {
+        fence(Ordering::Acquire);
+        if unsafe { UART0_FR.read_volatile() } & 0x20 != 0 {
+            return false; // FIFO full.
+        }
+        unsafe { UART0_DR.write_volatile(byte as _) };
+        fence(Ordering::Release);
+        true
+    }
+
+    fn set_tx_interrupt(enable: bool)
+    {
+        // The transmit interrupt is only raised when the FIFO drains past its
+        // trigger level, which is guaranteed since it's only enabled while
+        // the FIFO is full.
+        unsafe { UART0_IMSC.write_volatile(if enable { 0x20 } else { 0x0 }) };
+        fence(Ordering::Release);
+    }
+
+    fn is_tx_pending() -> bool
+    {
+        fence(Ordering::Acquire);
+        unsafe { UART0_MIS.read_volatile() & 0x20 != 0 }
+    }
+}
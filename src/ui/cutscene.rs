@@ -0,0 +1,269 @@
+//! Scripted cutscenes.
+//!
+//! Cutscenes are described by plain text scripts with one directive per line,
+//! where empty lines and lines starting with `#` are ignored:
+//!
+//! * `camera <time> <x> <y> <z>`: Camera path keyframe.
+//! * `caption <start> <end> <text>`: Caption shown between two times.
+//! * `stinger <time> <frequency>`: Tone played at a specific time.
+//!
+//! Times are in milliseconds and keyframes must be listed in chronological
+//! order.  The camera follows a Catmull-Rom spline [1] through its keyframes.
+//!
+//! [1]: https://en.wikipedia.org/wiki/Cubic_Hermite_spline#Catmull%E2%80%93Rom_spline
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::simd::f32x4;
+use core::str::FromStr;
+
+use crate::math::{Spline, SplineKind};
+
+/// Parsed cutscene.
+#[derive(Clone, Debug, Default)]
+pub struct Cutscene
+{
+    /// Camera path keyframes as time and position.
+    keys: Vec<(u32, f32x4)>,
+    /// Camera path through the keyframes, if there are at least two.
+    path: Option<Spline>,
+    /// Captions as start time, end time, and text.
+    captions: Vec<(u32, u32, String)>,
+    /// Stingers as time and frequency.
+    stingers: Vec<(u32, u16)>,
+}
+
+/// Cutscene script parsing error.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ParseError
+{
+    /// Line number of the offending directive starting from 1.
+    pub line: usize,
+}
+
+/// Cutscene playback state.
+#[derive(Debug)]
+pub struct Player<'a>
+{
+    /// Cutscene being played.
+    scene: &'a Cutscene,
+    /// Current playback time in milliseconds.
+    time: u32,
+}
+
+impl Cutscene
+{
+    /// Returns the time in milliseconds at which the last directive ends.
+    pub fn duration(&self) -> u32
+    {
+        let keys = self.keys.last().map(|key| key.0);
+        let captions = self.captions.iter().map(|caption| caption.1).max();
+        let stingers = self.stingers.last().map(|stinger| stinger.0);
+        [keys, captions, stingers].into_iter().flatten().max().unwrap_or(0)
+    }
+
+    /// Computes the position of the camera.
+    ///
+    /// * `time`: Time in milliseconds.
+    ///
+    /// Returns the computed position, or [`None`] if the cutscene has no
+    /// camera path.
+    pub fn camera_at(&self, time: u32) -> Option<f32x4>
+    {
+        let last = self.keys.len().checked_sub(1)?;
+        let idx = self.keys.partition_point(|key| key.0 <= time);
+        if idx == 0 {
+            return Some(self.keys[0].1);
+        }
+        if idx > last {
+            return Some(self.keys[last].1);
+        }
+        let (start, end) = (self.keys[idx - 1].0, self.keys[idx].0);
+        let weight = (time - start) as f32 / (end - start) as f32;
+        self.path.as_ref().map(|path| path.point((idx - 1) as f32 + weight))
+    }
+
+    /// Returns the caption shown at the specified time in milliseconds, if any.
+    ///
+    /// * `time`: Time in milliseconds.
+    pub fn caption_at(&self, time: u32) -> Option<&str>
+    {
+        self.captions
+            .iter()
+            .find(|caption| (caption.0 .. caption.1).contains(&time))
+            .map(|caption| caption.2.as_str())
+    }
+
+    /// Returns an iterator over the frequencies of the stingers played within a
+    /// time range.
+    ///
+    /// * `start`: Start of the range in milliseconds, inclusive.
+    /// * `end`: End of the range in milliseconds, exclusive.
+    pub fn stingers_between(&self, start: u32, end: u32) -> impl Iterator<Item = u16> + '_
+    {
+        self.stingers
+            .iter()
+            .filter(move |stinger| (start .. end).contains(&stinger.0))
+            .map(|stinger| stinger.1)
+    }
+
+    /// Starts playing this cutscene from the beginning.
+    ///
+    /// Returns the newly created player.
+    pub fn play(&self) -> Player<'_>
+    {
+        Player { scene: self, time: 0 }
+    }
+}
+
+impl FromStr for Cutscene
+{
+    type Err = ParseError;
+
+    fn from_str(script: &str) -> Result<Self, ParseError>
+    {
+        let mut this = Self::default();
+        for (idx, line) in script.lines().enumerate() {
+            let err = ParseError { line: idx + 1 };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (cmd, args) = line.split_once(' ').unwrap_or((line, ""));
+            let mut words = args.split_whitespace();
+            match cmd {
+                "camera" => {
+                    let time = parse_next(&mut words, err)?;
+                    let x = parse_next(&mut words, err)?;
+                    let y = parse_next(&mut words, err)?;
+                    let z = parse_next(&mut words, err)?;
+                    if words.next().is_some() || this.keys.last().is_some_and(|key| key.0 >= time) {
+                        return Err(err);
+                    }
+                    this.keys.push((time, f32x4::from_array([x, y, z, 1.0])));
+                }
+                "caption" => {
+                    let start = parse_next(&mut words, err)?;
+                    let end = parse_next(&mut words, err)?;
+                    let text = words.collect::<Vec<_>>().join(" ");
+                    if end <= start || text.is_empty() {
+                        return Err(err);
+                    }
+                    this.captions.push((start, end, text));
+                }
+                "stinger" => {
+                    let time = parse_next(&mut words, err)?;
+                    let freq = parse_next(&mut words, err)?;
+                    if words.next().is_some() || freq == 0 || this.stingers.last().is_some_and(|st| st.0 > time) {
+                        return Err(err);
+                    }
+                    this.stingers.push((time, freq));
+                }
+                _ => return Err(err),
+            }
+        }
+        if this.keys.len() >= 2 {
+            let points = this.keys.iter().map(|key| key.1).collect();
+            this.path = Some(Spline::new(SplineKind::CatmullRom, points));
+        }
+        Ok(this)
+    }
+}
+
+impl<'a> Player<'a>
+{
+    /// Advances the playback.
+    ///
+    /// * `delta`: Time to advance in milliseconds.
+    ///
+    /// Returns an iterator over the frequencies of the stingers to play, or
+    /// [`None`] if the cutscene was already over.
+    pub fn advance(&mut self, delta: u32) -> Option<impl Iterator<Item = u16> + 'a>
+    {
+        if self.is_over() {
+            return None;
+        }
+        let start = self.time;
+        self.time = self.time.saturating_add(delta);
+        Some(self.scene.stingers_between(start, self.time))
+    }
+
+    /// Returns the current position of the camera, if any.
+    pub fn camera(&self) -> Option<f32x4>
+    {
+        self.scene.camera_at(self.time)
+    }
+
+    /// Returns the current caption, if any.
+    pub fn caption(&self) -> Option<&'a str>
+    {
+        self.scene.caption_at(self.time)
+    }
+
+    /// Returns whether the cutscene has played until the end.
+    pub fn is_over(&self) -> bool
+    {
+        self.time >= self.scene.duration()
+    }
+}
+
+/// Parses the next word of a directive.
+///
+/// * `words`: Remaining words of the directive.
+/// * `err`: Error to return on failure.
+///
+/// Returns the parsed value.
+fn parse_next<'a, T: FromStr>(words: &mut impl Iterator<Item = &'a str>, err: ParseError) -> Result<T, ParseError>
+{
+    words.next().and_then(|word| word.parse().ok()).ok_or(err)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const SCRIPT: &str = "# Level intro.
+camera 0 0 0 0
+camera 1000 10 0 0
+camera 2000 10 10 0
+
+caption 500 1500 Welcome, keeper.
+stinger 0 440
+stinger 1200 880
+";
+
+    #[test]
+    fn parse()
+    {
+        let scene = SCRIPT.parse::<Cutscene>().unwrap();
+        assert_eq!(scene.duration(), 2000);
+        assert_eq!(scene.caption_at(499), None);
+        assert_eq!(scene.caption_at(500), Some("Welcome, keeper."));
+        assert_eq!(scene.caption_at(1500), None);
+        assert_eq!(scene.camera_at(1000).unwrap().to_array(), [10.0, 0.0, 0.0, 1.0]);
+        assert_eq!(scene.camera_at(5000).unwrap().to_array(), [10.0, 10.0, 0.0, 1.0]);
+        let mid = scene.camera_at(500).unwrap();
+        assert!(mid[0] > 0.0 && mid[0] < 10.0);
+        assert_eq!("camera 0 1 2".parse::<Cutscene>().unwrap_err(), ParseError { line: 1 });
+        assert_eq!("\nfade 0".parse::<Cutscene>().unwrap_err(), ParseError { line: 2 });
+        assert_eq!("camera 10 0 0 0\ncamera 5 0 0 0".parse::<Cutscene>().unwrap_err(),
+                   ParseError { line: 2 });
+    }
+
+    #[test]
+    fn playback()
+    {
+        let scene = SCRIPT.parse::<Cutscene>().unwrap();
+        let mut player = scene.play();
+        assert_eq!(player.advance(100).unwrap().collect::<Vec<_>>(), [440]);
+        assert_eq!(player.advance(1000).unwrap().count(), 0);
+        assert_eq!(player.caption(), Some("Welcome, keeper."));
+        assert_eq!(player.camera(), scene.camera_at(1100));
+        assert_eq!(player.advance(1000).unwrap().collect::<Vec<_>>(), [880]);
+        assert!(player.is_over());
+        assert!(player.advance(100).is_none());
+    }
+}
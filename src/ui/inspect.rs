@@ -0,0 +1,217 @@
+//! Entity inspector.
+//!
+//! Components opt into inspection by implementing [`Inspect`], which lists
+//! their fields along with live values and accepts new values for numeric
+//! fields.  The [`Inspector`] takes snapshots of a picked entity's components
+//! for display and applies cvar-style bindings of the form
+//! `component.field=value` to tweak them on-device.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FormatResult, Write};
+
+/// Inspectable component.
+pub trait Inspect
+{
+    /// Returns the name of this component.
+    fn name(&self) -> &'static str;
+
+    /// Lists the fields of this component.
+    ///
+    /// * `visit`: Function called with the name and current value of each
+    ///   field.
+    fn fields(&self, visit: &mut dyn FnMut(&'static str, Value));
+
+    /// Changes the value of a field.
+    ///
+    /// * `field`: Name of the field to change.
+    /// * `val`: New value.
+    ///
+    /// Returns whether the field exists and accepted the value.
+    fn set(&mut self, field: &str, val: Value) -> bool;
+}
+
+/// Field value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value
+{
+    /// Boolean.
+    Bool(bool),
+    /// Integer.
+    Int(i64),
+    /// Floating point number.
+    Float(f32),
+}
+
+/// Inspector of a single entity.
+#[derive(Debug, Default)]
+pub struct Inspector
+{
+    /// Snapshot of the fields of every component.
+    rows: Vec<Row>,
+}
+
+/// Snapshot of a single field.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row
+{
+    /// Path to the field in `component.field` form.
+    pub path: String,
+    /// Value of the field when the snapshot was taken.
+    pub val: Value,
+}
+
+impl Inspector
+{
+    /// Creates and initializes a new empty inspector.
+    ///
+    /// Returns the newly created inspector.
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Takes a snapshot of the fields of an entity's components.
+    ///
+    /// * `comps`: Components of the picked entity.
+    pub fn refresh(&mut self, comps: &[&dyn Inspect])
+    {
+        self.rows.clear();
+        for comp in comps {
+            let name = comp.name();
+            comp.fields(&mut |field, val| {
+                    let mut path = String::new();
+                    write!(path, "{name}.{field}").unwrap();
+                    self.rows.push(Row { path, val });
+                });
+        }
+    }
+
+    /// Returns the snapshot taken by the last refresh.
+    pub fn rows(&self) -> &[Row]
+    {
+        &self.rows
+    }
+
+    /// Applies a binding in `component.field=value` form to an entity's
+    /// components.
+    ///
+    /// * `comps`: Components of the picked entity.
+    /// * `binding`: Binding to apply.
+    ///
+    /// Returns whether the binding was well formed and accepted by a
+    /// component.
+    pub fn tweak(comps: &mut [&mut dyn Inspect], binding: &str) -> bool
+    {
+        let Some((path, val)) = binding.split_once('=') else {
+            return false;
+        };
+        let Some((name, field)) = path.trim().split_once('.') else {
+            return false;
+        };
+        let Some(val) = Value::parse(val.trim()) else {
+            return false;
+        };
+        comps.iter_mut()
+             .find(|comp| comp.name() == name)
+             .is_some_and(|comp| comp.set(field, val))
+    }
+}
+
+impl Value
+{
+    /// Parses a value from text.
+    ///
+    /// * `text`: Text to parse.
+    ///
+    /// Returns the parsed value, or nothing if the text is not a valid value.
+    fn parse(text: &str) -> Option<Self>
+    {
+        match text {
+            "true" => Some(Self::Bool(true)),
+            "false" => Some(Self::Bool(false)),
+            _ => text.parse()
+                     .map(Self::Int)
+                     .or_else(|_| text.parse().map(Self::Float))
+                     .ok(),
+        }
+    }
+}
+
+impl Display for Value
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        match self {
+            Self::Bool(val) => write!(fmt, "{val}"),
+            Self::Int(val) => write!(fmt, "{val}"),
+            Self::Float(val) => write!(fmt, "{val:.3}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    struct Health
+    {
+        hp: i64,
+        regen: f32,
+    }
+
+    impl Inspect for Health
+    {
+        fn name(&self) -> &'static str
+        {
+            "health"
+        }
+
+        fn fields(&self, visit: &mut dyn FnMut(&'static str, Value))
+        {
+            visit("hp", Value::Int(self.hp));
+            visit("regen", Value::Float(self.regen));
+        }
+
+        fn set(&mut self, field: &str, val: Value) -> bool
+        {
+            match (field, val) {
+                ("hp", Value::Int(val)) => self.hp = val,
+                ("regen", Value::Float(val)) => self.regen = val,
+                ("regen", Value::Int(val)) => self.regen = val as f32,
+                _ => return false,
+            }
+            true
+        }
+    }
+
+    #[test]
+    fn refresh_rows()
+    {
+        let health = Health { hp: 10, regen: 0.5 };
+        let mut inspector = Inspector::new();
+        inspector.refresh(&[&health]);
+        let rows = inspector.rows();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].path, "health.hp");
+        assert_eq!(rows[0].val, Value::Int(10));
+        assert_eq!(rows[1].path, "health.regen");
+        assert_eq!(rows[1].val.to_string(), "0.500");
+    }
+
+    #[test]
+    fn tweak_fields()
+    {
+        let mut health = Health { hp: 10, regen: 0.5 };
+        assert!(Inspector::tweak(&mut [&mut health], "health.hp = 25"));
+        assert!(Inspector::tweak(&mut [&mut health], "health.regen=2"));
+        assert!(!Inspector::tweak(&mut [&mut health], "health.hp=1.5"));
+        assert!(!Inspector::tweak(&mut [&mut health], "mana.mp=5"));
+        assert!(!Inspector::tweak(&mut [&mut health], "health.hp"));
+        assert_eq!(health.hp, 25);
+        assert_eq!(health.regen, 2.0);
+    }
+}
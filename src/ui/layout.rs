@@ -0,0 +1,170 @@
+//! Resolution independent layout.
+//!
+//! Elements are positioned relative to an anchor point on their parent's
+//! bounds, with offsets and sizes expressed either in pixels or as percentages
+//! of the parent's dimensions, so that the same layout reflows correctly
+//! between the 800x480 DSI panel and 1920x1080 HDMI displays.  Coordinates have
+//! their origin at the top left corner of the screen with the vertical axis
+//! pointing down.
+
+use crate::math::Rect;
+
+/// Point on the parent's bounds that an element is attached to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Anchor
+{
+    /// Top right corner.
+    TopRight,
+    /// Bottom left corner.
+    BottomLeft,
+}
+
+/// Length along one of the axes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Length
+{
+    /// Absolute length in pixels.
+    Pixels(i32),
+    /// Percentage of the parent's length along the same axis.
+    Percent(f32),
+}
+
+/// Layout rules for an element.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout
+{
+    /// Anchor point on the parent's bounds.
+    anchor: Anchor,
+    /// Horizontal offset from the anchor, pointing inwards.
+    xoff: Length,
+    /// Vertical offset from the anchor, pointing inwards.
+    yoff: Length,
+    /// Width.
+    width: Length,
+    /// Height.
+    height: Length,
+}
+
+impl Layout
+{
+    /// Creates and initializes a new layout attached to the specified anchor
+    /// with no offset and the size of the parent.
+    ///
+    /// * `anchor`: Anchor point on the parent's bounds.
+    ///
+    /// Returns the newly created layout.
+    pub const fn new(anchor: Anchor) -> Self
+    {
+        Self { anchor,
+               xoff: Length::Pixels(0),
+               yoff: Length::Pixels(0),
+               width: Length::Percent(100.0),
+               height: Length::Percent(100.0) }
+    }
+
+    /// Offsets the element from its anchor.  Offsets point inwards, so positive
+    /// values move an element anchored to the right edge to the left, and an
+    /// element anchored to the bottom edge up.
+    ///
+    /// * `xoff`: Horizontal offset.
+    /// * `yoff`: Vertical offset.
+    ///
+    /// Returns the modified layout.
+    pub const fn with_offset(self, xoff: Length, yoff: Length) -> Self
+    {
+        Self { xoff, yoff, ..self }
+    }
+
+    /// Sets the size of the element.
+    ///
+    /// * `width`: Width.
+    /// * `height`: Height.
+    ///
+    /// Returns the modified layout.
+    pub const fn with_size(self, width: Length, height: Length) -> Self
+    {
+        Self { width, height, ..self }
+    }
+
+    /// Computes the bounds of an element with this layout inside a parent.
+    ///
+    /// * `parent`: Rect of the parent.
+    ///
+    /// Returns the computed bounds.
+    pub fn resolve(&self, parent: Rect) -> Rect
+    {
+        let width = self.width.resolve(parent.width);
+        let height = self.height.resolve(parent.height);
+        let xoff = self.xoff.resolve(parent.width);
+        let yoff = self.yoff.resolve(parent.height);
+        let (x, y) = match self.anchor {
+            Anchor::TopRight => (parent.width - width - xoff, yoff),
+            Anchor::BottomLeft => (xoff, parent.height - height - yoff),
+        };
+        Rect::new(parent.x + x, parent.y + y, width, height)
+    }
+}
+
+impl Length
+{
+    /// Converts this length to pixels.
+    ///
+    /// * `parent`: Length of the parent along the same axis.
+    ///
+    /// Returns the computed length in pixels.
+    fn resolve(self, parent: i32) -> i32
+    {
+        match self {
+            Self::Pixels(pixels) => pixels,
+            Self::Percent(percent) => (parent as f32 * percent / 100.0 + 0.5) as i32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::math::IVec2;
+
+    const DSI: Rect = Rect::new(0, 0, 800, 480);
+    const HDMI: Rect = Rect::new(0, 0, 1920, 1080);
+
+    #[test]
+    fn resolve_corners()
+    {
+        let layout = Layout::new(Anchor::TopRight).with_size(Length::Pixels(100), Length::Pixels(50));
+        assert_eq!(layout.resolve(DSI), Rect::new(700, 0, 100, 50));
+        let layout = Layout::new(Anchor::BottomLeft).with_size(Length::Pixels(100), Length::Pixels(50))
+                                                    .with_offset(Length::Pixels(10), Length::Pixels(20));
+        assert_eq!(layout.resolve(DSI), Rect::new(10, 410, 100, 50));
+        assert_eq!(layout.resolve(HDMI), Rect::new(10, 1010, 100, 50));
+    }
+
+    #[test]
+    fn resolve_percent()
+    {
+        let layout = Layout::new(Anchor::BottomLeft).with_size(Length::Percent(50.0), Length::Percent(25.0))
+                                                    .with_offset(Length::Percent(5.0), Length::Percent(5.0));
+        assert_eq!(layout.resolve(DSI), Rect::new(40, 336, 400, 120));
+        assert_eq!(layout.resolve(HDMI), Rect::new(96, 756, 960, 270));
+        let layout = Layout::new(Anchor::TopRight).with_size(Length::Percent(100.0), Length::Percent(10.0))
+                                                  .with_offset(Length::Pixels(0), Length::Percent(5.0));
+        assert_eq!(layout.resolve(DSI), Rect::new(0, 24, 800, 48));
+        assert_eq!(layout.resolve(HDMI), Rect::new(0, 54, 1920, 108));
+    }
+
+    #[test]
+    fn resolve_nested()
+    {
+        let panel = Layout::new(Anchor::TopRight).with_size(Length::Percent(25.0), Length::Percent(100.0))
+                                                 .resolve(DSI);
+        assert_eq!(panel, Rect::new(600, 0, 200, 480));
+        let button = Layout::new(Anchor::BottomLeft).with_size(Length::Percent(80.0), Length::Pixels(40))
+                                                    .with_offset(Length::Percent(10.0), Length::Pixels(8))
+                                                    .resolve(panel);
+        assert_eq!(button, Rect::new(620, 432, 160, 40));
+        assert!(button.contains(IVec2::new(620, 432)));
+        assert!(!button.contains(IVec2::new(780, 432)));
+    }
+}
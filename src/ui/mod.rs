@@ -0,0 +1,14 @@
+//! User interface toolkit.
+
+mod cutscene;
+mod inspect;
+mod layout;
+mod toast;
+
+#[cfg(not(test))]
+pub use self::cutscene::*;
+pub use self::inspect::*;
+#[cfg(not(test))]
+pub use self::layout::*;
+#[cfg(not(test))]
+pub use self::toast::*;
@@ -0,0 +1,143 @@
+//! Notifications.
+//!
+//! Game events worth telling the player about are posted as notices, which
+//! show up as toasts for a limited time after being posted and remain in a
+//! bounded scrollback afterwards, so that the player can catch up on what
+//! they missed from the pause screen.
+
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use core::fmt::{Display, Formatter, Result as FormatResult};
+
+/// Time in microseconds that a notice is shown as a toast.
+const TOAST_DURATION: u64 = 4000000;
+/// Maximum number of toasts shown at the same time.
+const TOAST_MAX: usize = 3;
+/// Maximum number of notices kept in the scrollback.
+const SCROLLBACK_LEN: usize = 64;
+
+/// Notice queue and scrollback.
+#[derive(Debug)]
+pub struct Notifications
+{
+    /// Notices from the oldest to the newest.
+    notices: VecDeque<Notice>,
+}
+
+/// Notice posted to the player.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Notice
+{
+    /// How much attention the notice deserves.
+    pub severity: Severity,
+    /// Message.
+    pub text: String,
+    /// Time in microseconds at which the notice was posted.
+    pub time: u64,
+}
+
+/// Severity of a notice.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity
+{
+    /// Something the player might want to know.
+    Info,
+    /// Something that will become a problem if left unattended.
+    Warning,
+    /// Something that requires immediate attention.
+    Critical,
+}
+
+impl Notifications
+{
+    /// Creates and initializes a new empty notice queue.
+    ///
+    /// Returns the newly created queue.
+    pub const fn new() -> Self
+    {
+        Self { notices: VecDeque::new() }
+    }
+
+    /// Posts a notice, discarding the oldest one if the scrollback is full.
+    ///
+    /// * `severity`: How much attention the notice deserves.
+    /// * `text`: Message.
+    /// * `now`: Current time in microseconds.
+    pub fn post(&mut self, severity: Severity, text: impl Into<String>, now: u64)
+    {
+        if self.notices.len() == SCROLLBACK_LEN {
+            self.notices.pop_front();
+        }
+        self.notices.push_back(Notice { severity,
+                                        text: text.into(),
+                                        time: now });
+    }
+
+    /// Returns the notices that should currently be shown as toasts, from the
+    /// oldest to the newest.
+    ///
+    /// * `now`: Current time in microseconds.
+    pub fn toasts(&self, now: u64) -> impl Iterator<Item = &Notice>
+    {
+        let count = self.notices
+                        .iter()
+                        .rev()
+                        .take(TOAST_MAX)
+                        .take_while(|notice| now.saturating_sub(notice.time) < TOAST_DURATION)
+                        .count();
+        self.notices.range(self.notices.len() - count ..)
+    }
+
+    /// Returns all the notices in the scrollback, from the oldest to the
+    /// newest.
+    pub fn scrollback(&self) -> impl DoubleEndedIterator<Item = &Notice>
+    {
+        self.notices.iter()
+    }
+}
+
+impl Display for Notice
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        write!(fmt, "[{}] {}", self.severity, self.text)
+    }
+}
+
+impl Display for Severity
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        match self {
+            Self::Info => write!(fmt, "info"),
+            Self::Warning => write!(fmt, "warning"),
+            Self::Critical => write!(fmt, "critical"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn toasts()
+    {
+        let mut notes = Notifications::new();
+        notes.post(Severity::Info, "first", 0);
+        notes.post(Severity::Warning, "second", TOAST_DURATION / 2);
+        let texts = |now| notes.toasts(now).map(|notice| notice.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts(0), ["first", "second"]);
+        assert_eq!(texts(TOAST_DURATION), ["second"]);
+        assert!(texts(TOAST_DURATION * 2).is_empty());
+        (0 .. SCROLLBACK_LEN).for_each(|idx| notes.post(Severity::Critical, alloc::format!("{idx}"), 0));
+        assert_eq!(notes.toasts(0).count(), TOAST_MAX);
+        assert_eq!(notes.scrollback().count(), SCROLLBACK_LEN);
+        let last = notes.scrollback().next_back().unwrap();
+        assert_eq!(alloc::format!("{last}"),
+                   alloc::format!("[critical] {}", SCROLLBACK_LEN - 1));
+    }
+}
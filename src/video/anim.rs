@@ -0,0 +1,324 @@
+//! Skeletal animation.
+//!
+//! Skeletons are hierarchies of bones whose bind poses are expressed as
+//! transformations relative to their parents, with parents always preceding
+//! their children.  Clips animate any subset of the bones with transformation
+//! tracks, and bones without a track in a clip stay in their bind poses.
+//! Sampling a clip produces a pose, which skins meshes on the CPU by blending
+//! the transformations of up to four bones per vertex before the resulting
+//! triangles are submitted for drawing.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::simd::f32x4;
+
+use super::{Triangle, Vertex};
+use crate::math::{Keyframe, Track, Transform};
+use crate::simd::{f32x4x4, SimdFloatExtra};
+
+/// Maximum number of bones influencing a single vertex.
+const INFLUENCES: usize = 4;
+
+/// Bone hierarchy.
+#[derive(Debug)]
+pub struct Skeleton
+{
+    /// Bones, with parents preceding their children.
+    bones: Vec<Bone>,
+}
+
+/// Skeleton bone.
+#[derive(Clone, Copy, Debug)]
+struct Bone
+{
+    /// Index of the parent bone, or `None` for a root bone.
+    parent: Option<usize>,
+    /// Bind pose relative to the parent bone.
+    bind: Transform,
+    /// Transformation from model space to this bone's space in the bind pose.
+    inv_bind: Transform,
+}
+
+/// Keyframed animation clip, such as walking, attacking, or dying.
+#[derive(Debug)]
+pub struct Animation
+{
+    /// Duration in seconds.
+    duration: f32,
+    /// Whether the clip starts over once it ends.
+    looping: bool,
+    /// Tracks animating individual bones.
+    tracks: Vec<BoneTrack>,
+}
+
+/// Keyframes of a single bone.
+#[derive(Debug)]
+struct BoneTrack
+{
+    /// Index of the animated bone.
+    bone: usize,
+    /// Keyframes relative to the parent bone.
+    track: Track,
+}
+
+/// Skinning transformations of all the bones of a skeleton at a point in time.
+#[derive(Debug)]
+pub struct Pose
+{
+    /// Skinning matrices, which transform vertices from the bind pose.
+    mats: Vec<f32x4x4>,
+    /// Rotation matrices for normals.
+    nmats: Vec<f32x4x4>,
+}
+
+/// Mesh deformed by a skeleton.
+#[derive(Debug)]
+pub struct SkinnedMesh
+{
+    /// Triangles in the bind pose.
+    tris: Vec<[SkinnedVertex; 3]>,
+}
+
+/// Vertex deformed by a skeleton.
+#[derive(Clone, Copy, Debug)]
+pub struct SkinnedVertex
+{
+    /// Vertex in the bind pose.
+    vert: Vertex,
+    /// Indices of the influencing bones.
+    bones: [u8; INFLUENCES],
+    /// Weights of the influencing bones, adding up to 1.
+    weights: [f32; INFLUENCES],
+}
+
+impl Skeleton
+{
+    /// Creates and initializes a new empty skeleton.
+    ///
+    /// Returns the newly created skeleton.
+    pub fn new() -> Self
+    {
+        Self { bones: Vec::new() }
+    }
+
+    /// Adds a bone to this skeleton.
+    ///
+    /// * `parent`: Index of the parent bone, or `None` for a root bone.
+    /// * `bind`: Bind pose relative to the parent bone.
+    ///
+    /// Returns the index of the new bone.
+    ///
+    /// Panics if the parent bone doesn't exist.
+    #[track_caller]
+    pub fn add_bone(&mut self, parent: Option<usize>, bind: Transform) -> usize
+    {
+        let global = match parent {
+            Some(parent) => {
+                assert!(parent < self.bones.len(), "Parent bone #{parent} doesn't exist");
+                bind * self.global_bind(parent)
+            }
+            None => bind,
+        };
+        self.bones.push(Bone { parent,
+                               bind,
+                               inv_bind: global.recip() });
+        self.bones.len() - 1
+    }
+
+    /// Samples a clip at a point in time.
+    ///
+    /// * `clip`: Clip to sample.
+    /// * `time`: Time in seconds since the clip started playing.
+    ///
+    /// Returns the computed pose.
+    pub fn pose(&self, clip: &Animation, time: f32) -> Pose
+    {
+        let time = clip.clamp_time(time);
+        let mut locals = self.bones.iter().map(|bone| bone.bind).collect::<Vec<_>>();
+        for track in clip.tracks.iter() {
+            if let Some(local) = locals.get_mut(track.bone) {
+                *local = track.track.sample(time);
+            }
+        }
+        let mut globals = Vec::<Transform>::with_capacity(self.bones.len());
+        for (bone, local) in self.bones.iter().zip(locals) {
+            let global = match bone.parent {
+                Some(parent) => local * globals[parent],
+                None => local,
+            };
+            globals.push(global);
+        }
+        let skins = self.bones
+                        .iter()
+                        .zip(globals)
+                        .map(|(bone, global)| bone.inv_bind * global);
+        let (mats, nmats) = skins.map(|skin| (skin.into_matrix(), skin.rotation().into_matrix()))
+                                 .unzip();
+        Pose { mats, nmats }
+    }
+
+    /// Computes the bind pose of a bone relative to the model.
+    ///
+    /// * `bone`: Index of the bone.
+    ///
+    /// Returns the computed transformation.
+    fn global_bind(&self, bone: usize) -> Transform
+    {
+        let bone = &self.bones[bone];
+        match bone.parent {
+            Some(parent) => bone.bind * self.global_bind(parent),
+            None => bone.bind,
+        }
+    }
+}
+
+impl Animation
+{
+    /// Creates and initializes a new clip without any tracks.
+    ///
+    /// * `duration`: Duration in seconds.
+    /// * `looping`: Whether the clip starts over once it ends.
+    ///
+    /// Returns the newly created clip.
+    pub fn new(duration: f32, looping: bool) -> Self
+    {
+        Self { duration,
+               looping,
+               tracks: Vec::new() }
+    }
+
+    /// Adds a track animating a bone.
+    ///
+    /// * `bone`: Index of the animated bone.
+    /// * `keys`: Keyframes relative to the parent bone, which are sorted by
+    ///   time.
+    ///
+    /// Panics if no keyframes are provided.
+    #[track_caller]
+    pub fn add_track(&mut self, bone: usize, keys: Vec<Keyframe>)
+    {
+        let track = Track::new(keys, false);
+        self.tracks.push(BoneTrack { bone, track });
+    }
+
+    /// Maps the time since the clip started playing into the clip's duration.
+    ///
+    /// * `time`: Time in seconds since the clip started playing.
+    ///
+    /// Returns the mapped time.
+    fn clamp_time(&self, time: f32) -> f32
+    {
+        if self.duration <= 0.0 {
+            return 0.0;
+        }
+        if self.looping {
+            let time = time % self.duration;
+            return if time < 0.0 { time + self.duration } else { time };
+        }
+        time.clamp(0.0, self.duration)
+    }
+}
+
+impl SkinnedMesh
+{
+    /// Creates and initializes a new skinned mesh.
+    ///
+    /// * `tris`: Triangles in the bind pose, with vertices in counter-clockwise
+    ///   order.
+    ///
+    /// Returns the newly created mesh.
+    pub fn new(tris: Vec<[SkinnedVertex; 3]>) -> Self
+    {
+        Self { tris }
+    }
+
+    /// Binds existing geometry to a skeleton.
+    ///
+    /// * `tris`: Triangles in the bind pose.
+    /// * `binder`: Function returning the indices and weights of the bones
+    ///   influencing a vertex given its position in the bind pose.
+    ///
+    /// Returns the newly created mesh.
+    pub fn bind(tris: &[Triangle], binder: impl Fn(f32x4) -> ([u8; INFLUENCES], [f32; INFLUENCES])) -> Self
+    {
+        let bind = |vert: &Vertex| {
+            let (bones, weights) = binder(vert.pos);
+            SkinnedVertex::new(vert.pos, vert.normal, vert.color, bones, weights)
+        };
+        let tris = tris.iter()
+                       .map(|Triangle(vert0, vert1, vert2)| [bind(vert0), bind(vert1), bind(vert2)])
+                       .collect();
+        Self::new(tris)
+    }
+
+    /// Deforms this mesh into a pose.
+    ///
+    /// * `pose`: Pose of the skeleton that this mesh is bound to.
+    ///
+    /// Returns the deformed triangles in model space, ready to be drawn.
+    ///
+    /// Panics if any vertex refers to a bone that is not in the pose.
+    #[track_caller]
+    pub fn skin(&self, pose: &Pose) -> Vec<Triangle>
+    {
+        let skin = |vert: &SkinnedVertex| vert.skin(pose);
+        self.tris
+            .iter()
+            .map(|[vert0, vert1, vert2]| Triangle(skin(vert0), skin(vert1), skin(vert2)))
+            .collect()
+    }
+}
+
+impl SkinnedVertex
+{
+    /// Creates and initializes a new skinned vertex.
+    ///
+    /// * `pos`: Position in the bind pose.
+    /// * `normal`: Normal in the bind pose.
+    /// * `color`: Color.
+    /// * `bones`: Indices of up to four influencing bones.
+    /// * `weights`: Weights of the influencing bones, which are normalized to
+    ///   add up to 1.
+    ///
+    /// Returns the newly created vertex.
+    pub fn new(pos: f32x4, normal: f32x4, color: f32x4, bones: [u8; INFLUENCES], weights: [f32; INFLUENCES]) -> Self
+    {
+        let total = weights.iter().sum::<f32>();
+        let weights = if total > 0.0 {
+            weights.map(|weight| weight / total)
+        } else {
+            [1.0, 0.0, 0.0, 0.0]
+        };
+        let vert = Vertex { pos: pos.replace_lane::<3>(1.0),
+                            normal: normal.replace_lane::<3>(0.0),
+                            color,
+                            uv: f32x4::splat(0.0) };
+        Self { vert, bones, weights }
+    }
+
+    /// Blends the transformations of the influencing bones.
+    ///
+    /// * `pose`: Pose to apply.
+    ///
+    /// Returns the deformed vertex.
+    #[track_caller]
+    fn skin(&self, pose: &Pose) -> Vertex
+    {
+        let mut pos = f32x4::splat(0.0);
+        let mut normal = f32x4::splat(0.0);
+        for (bone, weight) in self.bones.iter().zip(self.weights) {
+            if weight == 0.0 {
+                continue;
+            }
+            let bone = *bone as usize;
+            pos = pos.fused_mul_add(self.vert.pos.mul_mat(pose.mats[bone]), f32x4::splat(weight));
+            normal = normal.fused_mul_add(self.vert.normal.mul_mat(pose.nmats[bone]), f32x4::splat(weight));
+        }
+        let normal = normal.normalize().unwrap_or(self.vert.normal);
+        Vertex { pos: pos.replace_lane::<3>(1.0),
+                 normal,
+                 color: self.vert.color,
+                 uv: self.vert.uv }
+    }
+}
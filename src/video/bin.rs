@@ -0,0 +1,157 @@
+//! Triangle binning.
+//!
+//! Before rasterization every projected triangle is bucketed into the tiles
+//! that its bounding box overlaps, so that each tile only visits the triangles
+//! that may cover it instead of every triangle of every command, making the
+//! cost of drawing a scene grow with its coverage rather than with the product
+//! of tiles and triangles.
+//!
+//! Binning is hierarchical: triangles that span more tiles than fit in a block
+//! go into the coarse bins of the blocks that they overlap, so that large
+//! triangles close to the camera don't have to be added to every single tile
+//! that they touch, whereas all other triangles go straight into the fine bins
+//! of their tiles.  Each tile then merges its fine bin with the coarse bin of
+//! its block, preserving the order in which the triangles were submitted.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::iter;
+use core::simd::prelude::*;
+
+use super::{FrameBuffer, ProjectedTriangle};
+use crate::math::{IVec2, Rect};
+
+/// Number of tiles along each axis of a block.
+const BLOCK_DIM: usize = 4;
+
+/// Per-tile triangle bins.
+#[derive(Debug)]
+pub struct Bins
+{
+    /// Tile width at the rasterization resolution.
+    twidth: usize,
+    /// Tile height at the rasterization resolution.
+    theight: usize,
+    /// Number of tile columns.
+    cols: usize,
+    /// Number of block columns.
+    bcols: usize,
+    /// Triangles overlapping each tile.
+    fine: Vec<Vec<Entry>>,
+    /// Triangles spanning too many tiles to be binned individually, per block.
+    coarse: Vec<Vec<Entry>>,
+}
+
+/// Reference to a triangle in the command queue, ordered by submission.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Entry
+{
+    /// Index of the command.
+    pub cmd: u32,
+    /// Index of the triangle within the command.
+    pub tri: u32,
+}
+
+impl Bins
+{
+    /// Creates and initializes a new empty set of bins.
+    ///
+    /// Returns the newly created bins.
+    pub fn new() -> Self
+    {
+        Self { twidth: 1,
+               theight: 1,
+               cols: 0,
+               bcols: 0,
+               fine: Vec::new(),
+               coarse: Vec::new() }
+    }
+
+    /// Empties all the bins and lays them out to match the tiles of a frame
+    /// buffer, keeping their allocations around for the next frame.
+    ///
+    /// * `fb`: Frame buffer whose tiles are to be binned.
+    pub fn reset(&mut self, fb: &FrameBuffer)
+    {
+        self.twidth = fb.tile_width();
+        self.theight = fb.tile_height();
+        self.cols = fb.raster_width() / self.twidth;
+        let rows = fb.raster_height() / self.theight;
+        self.bcols = self.cols.div_ceil(BLOCK_DIM);
+        let brows = rows.div_ceil(BLOCK_DIM);
+        self.fine.resize_with(self.cols * rows, Vec::new);
+        self.coarse.resize_with(self.bcols * brows, Vec::new);
+        self.fine.iter_mut().chain(self.coarse.iter_mut()).for_each(Vec::clear);
+    }
+
+    /// Adds a triangle to the bins of all the tiles that it may cover.
+    ///
+    /// * `entry`: Reference to the triangle in the command queue.
+    /// * `tri`: Triangle to bin.
+    /// * `clip`: Clipping rectangle of the triangle's command.
+    ///
+    /// Returns whether the triangle was added to any bins, which is not the
+    /// case if it was clipped away.
+    pub fn insert(&mut self, entry: Entry, tri: &ProjectedTriangle, clip: Rect) -> bool
+    {
+        let min = tri.0.proj.simd_min(tri.1.proj).simd_min(tri.2.proj);
+        let max = tri.0.proj.simd_max(tri.1.proj).simd_max(tri.2.proj);
+        if min[2] > 1.0 || max[2] < 0.0 {
+            // The triangle is completely outside the depth range.
+            return false;
+        }
+        // Find the pixels whose centers may lie within the bounding box of the
+        // triangle, rounding towards zero since anything left of or below the
+        // origin is clipped away anyway.
+        let pmin = (min - f32x4::splat(0.5)).cast::<i32>();
+        let pmax = (max + f32x4::splat(0.5)).cast::<i32>();
+        let (pmin, pmax) = (IVec2::new(pmin[0], pmin[1]), IVec2::new(pmax[0], pmax[1]));
+        let rect = Rect::from_corners(pmin, pmax).intersect(clip);
+        if rect.is_empty() {
+            // The triangle was clipped away.
+            return false;
+        }
+        let (col0, row0) = (rect.x as usize / self.twidth, rect.y as usize / self.theight);
+        let col1 = (rect.max().x as usize - 1) / self.twidth;
+        let row1 = (rect.max().y as usize - 1) / self.theight;
+        if (col1 - col0 + 1) * (row1 - row0 + 1) <= BLOCK_DIM * BLOCK_DIM {
+            for row in row0 ..= row1 {
+                for col in col0 ..= col1 {
+                    self.fine[row * self.cols + col].push(entry);
+                }
+            }
+            return true;
+        }
+        for brow in row0 / BLOCK_DIM ..= row1 / BLOCK_DIM {
+            for bcol in col0 / BLOCK_DIM ..= col1 / BLOCK_DIM {
+                self.coarse[brow * self.bcols + bcol].push(entry);
+            }
+        }
+        true
+    }
+
+    /// Lists the triangles that may cover a tile.
+    ///
+    /// * `rect`: Area covered by the tile at the rasterization resolution.
+    ///
+    /// Returns an iterator over references to the triangles in submission
+    /// order.
+    pub fn tile(&self, rect: Rect) -> impl Iterator<Item = Entry> + '_
+    {
+        let (col, row) = (rect.x as usize / self.twidth, rect.y as usize / self.theight);
+        let mut fine = self.fine[row * self.cols + col].as_slice();
+        let mut coarse = self.coarse[row / BLOCK_DIM * self.bcols + col / BLOCK_DIM].as_slice();
+        iter::from_fn(move || {
+            let bin = match (fine.first(), coarse.first()) {
+                (Some(entry0), Some(entry1)) if entry1 < entry0 => &mut coarse,
+                (Some(_), _) => &mut fine,
+                (None, Some(_)) => &mut coarse,
+                (None, None) => return None,
+            };
+            let (entry, rest) = bin.split_first()?;
+            *bin = rest;
+            Some(*entry)
+        })
+    }
+}
@@ -0,0 +1,240 @@
+//! DMA blitter.
+//!
+//! Copies finished tiles to the frame buffer and clears frame buffers with DMA
+//! transfers, freeing the CPUs for rasterization.  Tile color buffers store
+//! pixels in 2x2 quads, so each row of a tile is copied by a 2D transfer that
+//! gathers pairs of pixels from every other quad.  Transfers run on a single
+//! channel in the order in which they were requested.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::simd::u32x4;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::alloc::{Alloc, UNCACHED_REGION};
+use crate::dma::{Block, Chain, Channel, DmaBuffer, Loan, DMA};
+use crate::sync::{critical, Lazy, Lock};
+use crate::to_dma;
+
+/// Maximum width or height of a tile.
+pub const TILE_DIM_MAX: usize = 32;
+
+/// Global blitter instance.
+pub static BLITTER: Lazy<Blitter> = Lazy::new(Blitter::new);
+
+/// Uncached memory allocator.
+static UNCACHED: Alloc<0x40> = Alloc::with_region(&UNCACHED_REGION);
+
+/// Tile color buffer.
+pub type ColorBuffer = [u32x4; TILE_DIM_MAX * TILE_DIM_MAX / 4];
+
+/// DMA blitter.
+#[derive(Debug)]
+pub struct Blitter
+{
+    /// Transfer queue, shared with the DMA IRQ handler, or `None` if no DMA
+    /// channel was available.
+    queue: Lock<Option<Queue>>,
+    /// Idle tile color buffers.
+    pool: Lock<Vec<DmaBuffer<ColorBuffer>>>,
+    /// Whether to copy tiles with DMA transfers.
+    enabled: AtomicBool,
+    /// Source of the clear transfers.
+    zero: Box<u32x4, Alloc<'static, 0x40>>,
+}
+
+/// Transfer queue.
+#[derive(Debug)]
+struct Queue
+{
+    /// Channel running the transfers.
+    chan: Channel,
+    /// Job whose transfers are running.
+    running: Option<Job>,
+    /// Jobs waiting to run.
+    pending: VecDeque<(Chain, Job)>,
+}
+
+/// Work to do once a chain of transfers completes.
+#[derive(Debug)]
+struct Job
+{
+    /// Tile color buffer to return to the pool.
+    buf: Option<Loan<ColorBuffer>>,
+    /// Counter to increment.
+    done: Option<Arc<AtomicU64>>,
+}
+
+impl Blitter
+{
+    /// Creates and initializes a new blitter.
+    ///
+    /// Returns the newly created blitter.
+    fn new() -> Self
+    {
+        let queue = DMA.allocate().map(|mut chan| {
+                                      chan.on_interrupt(Self::finish);
+                                      Queue { chan,
+                                              running: None,
+                                              pending: VecDeque::new() }
+                                  });
+        let enabled = queue.is_some();
+        Self { queue: Lock::new(queue),
+               pool: Lock::new(Vec::new()),
+               enabled: AtomicBool::new(enabled),
+               zero: Box::new_in(u32x4::splat(0), UNCACHED) }
+    }
+
+    /// Enables or disables copying tiles with DMA transfers, which has no
+    /// effect if no DMA channel was available.
+    ///
+    /// * `enable`: Whether to copy tiles with DMA transfers.
+    pub fn set_enabled(&self, enable: bool)
+    {
+        let _critical = critical();
+        let enable = enable && self.queue.lock().is_some();
+        self.enabled.store(enable, Ordering::Relaxed);
+    }
+
+    /// Returns whether tiles are being copied with DMA transfers.
+    pub fn is_enabled(&self) -> bool
+    {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Takes an idle tile color buffer from the pool, or allocates a new one if
+    /// the pool is empty.
+    ///
+    /// Returns the cleared buffer.
+    pub fn color_buffer(&self) -> DmaBuffer<ColorBuffer>
+    {
+        let buf = {
+            // The lock is shared with the DMA IRQ handler.
+            let _critical = critical();
+            self.pool.lock().pop()
+        };
+        let Some(mut buf) = buf else {
+            return DmaBuffer::new([u32x4::splat(0); TILE_DIM_MAX * TILE_DIM_MAX / 4]);
+        };
+        buf.fill(u32x4::splat(0));
+        buf
+    }
+
+    /// Returns a tile color buffer to the pool.
+    ///
+    /// * `buf`: Buffer to return.
+    pub fn recycle(&self, buf: DmaBuffer<ColorBuffer>)
+    {
+        let _critical = critical();
+        self.pool.lock().push(buf);
+    }
+
+    /// Queues a copy of a tile to a frame buffer.
+    ///
+    /// * `buf`: Tile color buffer, which is returned to the pool once the copy
+    ///   completes.
+    /// * `dst`: Address of the top left pixel of the tile in the frame buffer.
+    /// * `size`: Width and height of the tile in pixels.
+    /// * `width`: Width of the frame buffer in pixels.
+    /// * `done`: Counter to increment once the copy completes.
+    ///
+    /// Returns the tile color buffer back if DMA transfers are disabled.
+    pub fn copy_tile(&self, buf: DmaBuffer<ColorBuffer>, dst: usize, size: (usize, usize), width: usize,
+                     done: Arc<AtomicU64>)
+                     -> Result<(), DmaBuffer<ColorBuffer>>
+    {
+        if !self.is_enabled() {
+            return Err(buf);
+        }
+        let (twidth, theight) = size;
+        let pixel = size_of::<u32>();
+        let buf = buf.into_loan();
+        let src = buf.addr();
+        let mut blocks = Vec::with_capacity(theight);
+        for trow in 0 .. theight {
+            // Rows alternate between the top and bottom halves of each quad.
+            let src = src + ((trow >> 1) * (twidth << 1) + ((trow & 0x1) << 1)) * pixel;
+            let dst = to_dma(dst + trow * width * pixel);
+            let block = Block::new(src, dst, pixel * 2).increment_src().increment_dst();
+            blocks.push(block.rows(twidth >> 1, pixel * 4, pixel * 2));
+        }
+        if let Some(last) = blocks.last_mut() {
+            *last = last.interrupt();
+        }
+        let job = Job { buf: Some(buf),
+                        done: Some(done) };
+        self.enqueue(Chain::new(blocks), job);
+        Ok(())
+    }
+
+    /// Queues a clear of a memory region to zero, or clears it with the CPU if
+    /// no DMA channel is available.
+    ///
+    /// * `dst`: Address of the region to clear.
+    /// * `len`: Length of the region in bytes.
+    pub fn clear(&self, dst: *mut u8, len: usize)
+    {
+        let has_chan = {
+            let _critical = critical();
+            self.queue.lock().is_some()
+        };
+        if !has_chan {
+            unsafe { dst.write_bytes(0, len) };
+            return;
+        }
+        let src = to_dma(&*self.zero as *const u32x4 as usize);
+        let block = Block::new(src, to_dma(dst as usize), len).wide_src()
+                                                              .increment_dst()
+                                                              .wide_dst()
+                                                              .interrupt();
+        let job = Job { buf: None, done: None };
+        self.enqueue(Chain::new([block]), job);
+    }
+
+    /// Queues a chain of transfers, starting it right away if the channel is
+    /// idle.
+    ///
+    /// * `chain`: Transfers to run.
+    /// * `job`: Work to do once the transfers complete.
+    fn enqueue(&self, chain: Chain, job: Job)
+    {
+        // The lock is shared with the DMA IRQ handler.
+        let _critical = critical();
+        let mut queue = self.queue.lock();
+        let queue = queue.as_mut().unwrap();
+        if queue.running.is_some() {
+            queue.pending.push_back((chain, job));
+            return;
+        }
+        queue.chan.start(chain);
+        queue.running = Some(job);
+    }
+
+    /// DMA IRQ handler that completes the running job and starts the next.
+    fn finish()
+    {
+        let mut queue = BLITTER.queue.lock();
+        let Some(queue) = queue.as_mut() else {
+            return;
+        };
+        let Some(job) = queue.running.take() else {
+            return;
+        };
+        queue.chan.stop();
+        if let Some((chain, job)) = queue.pending.pop_front() {
+            queue.chan.start(chain);
+            queue.running = Some(job);
+        }
+        if let Some(buf) = job.buf {
+            BLITTER.pool.lock().push(buf.repay());
+        }
+        if let Some(done) = job.done {
+            done.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
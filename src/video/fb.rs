@@ -5,25 +5,77 @@
 //! and draws them to cached tiles of up to 32x32 pixels. Color pixels are
 //! stored in the 32 bit native endian integer XRGB8888 format, whereas depth
 //! pixels are stored in a custom 16-bit native endian floating point format
-//! with just a 5-bit exponent and 11-bit mantissa.
+//! with just a 5-bit exponent and 11-bit mantissa.  Finished tiles are copied
+//! to frame buffers in either the XRGB8888 or the 16-bit native endian RGB565
+//! pixel format, the latter halving the memory and bandwidth used by the frame
+//! buffers at the cost of color precision and of converting tiles with the
+//! CPU.
+//!
+//! When dimming is enabled, the frame buffer also keeps a checksum of the
+//! content of each tile across frames, and tiles whose content remains
+//! unchanged for a while are dimmed when resolved, reducing the risk of
+//! burning static images into the panel.
+//!
+//! A debug mode can also be selected to replace the shaded output with
+//! triangle edges, depth buffer contents, tile boundaries, or an overdraw
+//! heatmap, which makes rasterizer bugs such as seams between triangles and
+//! missing edges visible on screen.
+//!
+//! Triangles are drawn within a clipping rectangle, and only the fragments
+//! inside it are touched, which allows restricting draw commands to viewports
+//! and scissor rectangles.
+//!
+//! Translucent triangles can also be blended over the existing content in
+//! proportion to the alpha of their vertex colors, in which case they are
+//! depth tested but leave the depth buffer untouched, so they must be drawn
+//! after all the opaque triangles and sorted from back to front.
+//!
+//! When supersampling is enabled, triangles are rasterized at twice the
+//! resolution of the frame buffer along each axis, and every 2x2 fragment quad
+//! of a tile is averaged into a single pixel when the tile is resolved, which
+//! smooths jagged edges at four times the rasterization cost.
+//!
+//! When gamma correction is enabled, lighting and blending happen in linear
+//! space, and the results are converted to sRGB and quantized with an ordered
+//! 4x4 dither, which breaks up the banding in smooth gradients.
 
 extern crate alloc;
 
 use alloc::alloc::GlobalAlloc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::alloc::Layout;
-use core::iter::Iterator;
-use core::mem::size_of;
+use core::iter::{Iterator, Rev};
+use core::mem::{size_of, ManuallyDrop};
+use core::ops::Range;
+use core::ptr::null_mut;
 use core::simd::prelude::*;
 use core::slice::from_raw_parts as slice_from_raw_parts;
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, AtomicU8, Ordering};
 
-use super::shader::{Context, Light, Shader, Triangle};
+use super::blit::{ColorBuffer, BLITTER, TILE_DIM_MAX};
+use super::pass::PassState;
+use super::shader::{to_linear, to_srgb, Context, Shader, Surface, Triangle};
 use crate::alloc::{Alloc, UNCACHED_REGION};
-use crate::simd::{SimdFloatExtra, SimdPartialEqExtra, SimdPartialOrdExtra};
-use crate::to_dma;
+use crate::dma::DmaBuffer;
+use crate::math::Rect;
+use crate::raster::Edges;
+use crate::simd::{SimdFloatExtra, SimdPartialOrdExtra};
+use crate::{profile, to_dma};
 
-/// Maximum width or height of a tile.
-const TILE_DIM_MAX: usize = 32;
+/// Number of frames during which the content of a tile must remain unchanged
+/// before it gets dimmed.
+const DIM_STILL_FRAMES: u32 = 60 * 60 * 2;
+/// Color of the edges drawn in wireframe mode.
+const WIREFRAME_COLOR: u32 = 0xFFFFFF;
+/// Color of the tile boundaries drawn in tile mode.
+const TILE_COLOR: u32 = 0xFF00FF;
+/// Intensity added to a color channel of the overdraw heatmap every time a
+/// fragment is drawn.
+const HEAT_STEP: u32 = 0x40;
+/// Ordered 4x4 dither thresholds in sixteenths, grouped by the 2x2 fragment
+/// quads that make up each quarter of the matrix.
+const DITHER: [[u8; 4]; 4] = [[0, 8, 12, 4], [2, 10, 14, 6], [3, 11, 15, 7], [1, 9, 13, 5]];
 
 /// Uncached memory allocator.
 static UNCACHED: Alloc<0x40> = Alloc::with_region(&UNCACHED_REGION);
@@ -31,24 +83,69 @@ static UNCACHED: Alloc<0x40> = Alloc::with_region(&UNCACHED_REGION);
 /// Frame buffer.
 pub struct FrameBuffer
 {
-    /// First frame buffer.
-    fb0: *mut u32,
-    /// Second frame buffer.
-    fb1: *mut u32,
+    /// First frame buffer, or null once released.
+    fb0: AtomicPtr<u8>,
+    /// Second frame buffer, or null once released.
+    fb1: AtomicPtr<u8>,
+    /// Pixel format of both frame buffers.
+    format: PixelFormat,
     /// Image width.
     width: usize,
     /// Image height.
     height: usize,
-    /// Tile width.
+    /// Number of samples along each axis of a pixel.
+    samples: usize,
+    /// Tile width at the rasterization resolution.
     twidth: usize,
-    /// Tile height.
+    /// Tile height at the rasterization resolution.
     theight: usize,
     /// Tile count.
     tcount: usize,
-    /// Id of the next tile to draw.
-    tnext: AtomicU64,
-    /// Finished tile counter.
-    tfinished: AtomicU64,
+    /// Last frame in which each tile was claimed for drawing, plus one.
+    tclaims: Vec<AtomicU64>,
+    /// Finished tile counter, shared with the blitter.
+    tfinished: Arc<AtomicU64>,
+    /// Whether to dim tiles whose content hasn't changed for a while.
+    dim: AtomicBool,
+    /// Debug rendering mode.
+    debug: AtomicU8,
+    /// Whether to shade in linear space and dither the output.
+    gamma: AtomicBool,
+    /// Content checksums of each tile in the last frame.
+    tsums: Vec<AtomicU32>,
+    /// Number of consecutive frames during which the content of each tile
+    /// remained unchanged.
+    tstill: Vec<AtomicU32>,
+}
+
+/// Debug rendering modes.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DebugMode
+{
+    /// Regular shaded output.
+    Off,
+    /// Shaded output with the edges of every visible triangle highlighted.
+    Wireframe,
+    /// Depth buffer contents in grayscale, with the near clipping plane in
+    /// white.
+    Depth,
+    /// Shaded output with the boundaries of every tile highlighted.
+    Tiles,
+    /// Number of fragments drawn to each pixel, going from black through red
+    /// and yellow to white as it increases.
+    Overdraw,
+}
+
+/// Frame buffer pixel formats.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PixelFormat
+{
+    /// 32-bit pixels with 8 bits per color channel.
+    Xrgb8888,
+    /// 16-bit pixels with 5 bits for red and blue and 6 bits for green.
+    Rgb565,
 }
 
 /// Frame buffer iterator.
@@ -58,6 +155,12 @@ pub struct FrameBufferIterator<'a>
     fb: &'a FrameBuffer,
     /// Frame being iterated.
     frame: u64,
+    /// Positions of the tiles in this iterator's own band left to try.
+    band: Range<usize>,
+    /// Positions of all the tiles left to try after the band, in reverse.
+    rest: Rev<Range<usize>>,
+    /// Positions of the tiles in this iterator's own band.
+    own: Range<usize>,
 }
 
 /// Frame buffer tile.
@@ -77,8 +180,12 @@ pub struct Tile<'a>
     min: f32x4,
     // Axis aligned bounding box maximum values.
     max: f32x4,
-    /// Tile's color buffer.
-    cb: [u32x4; TILE_DIM_MAX * TILE_DIM_MAX / 4],
+    /// Lower bound of the values in the depth buffer, below which no fragment
+    /// can pass the depth test.
+    dmin: u32,
+    /// Tile's color buffer, which is handed over to the blitter when the tile
+    /// is dropped.
+    cb: ManuallyDrop<DmaBuffer<ColorBuffer>>,
     /// Tile's depth buffer.
     db: [u16x4; TILE_DIM_MAX * TILE_DIM_MAX / 4],
 }
@@ -89,39 +196,155 @@ impl FrameBuffer
     ///
     /// * `width`: Image width.
     /// * `height`: Image height.
+    /// * `format`: Pixel format.
+    /// * `supersample`: Whether to rasterize at twice the resolution along each
+    ///   axis.
+    /// * `frame`: ID of the first frame to draw.
     ///
     /// Returns the newly created frame buffer.
     ///
     /// Panics if the resolution is not supported or the system runs out of
     /// uncached memory to allocate.
     #[track_caller]
-    pub fn new(width: usize, height: usize) -> Self
+    pub fn new(width: usize, height: usize, format: PixelFormat, supersample: bool, frame: u64) -> Self
     {
+        let samples = if supersample { 2 } else { 1 };
         let mut twidth = 0;
         let mut theight = 0;
-        for sz in (8 ..= TILE_DIM_MAX).step_by(8) {
+        for sz in (8 ..= TILE_DIM_MAX / samples).step_by(8) {
             if width % sz == 0 {
-                twidth = sz;
+                twidth = sz * samples;
             }
             if height % sz == 0 {
-                theight = sz;
+                theight = sz * samples;
             }
         }
         assert!(twidth > 0 && theight > 0, "Invalid width or height");
-        let layout = Layout::from_size_align(width * height * size_of::<u32>(), 64).unwrap();
-        let fb0 = unsafe { UNCACHED.alloc_zeroed(layout).cast::<u32>() };
-        let fb1 = unsafe { UNCACHED.alloc_zeroed(layout).cast::<u32>() };
+        let layout = Layout::from_size_align(width * height * format.depth(), 64).unwrap();
+        let fb0 = unsafe { UNCACHED.alloc(layout) };
+        let fb1 = unsafe { UNCACHED.alloc(layout) };
         assert!(!fb0.is_null() && !fb1.is_null(),
                 "Failed to allocate memory for the frame buffers");
-        Self { fb0,
-               fb1,
+        BLITTER.clear(fb0, layout.size());
+        BLITTER.clear(fb1, layout.size());
+        let tcount = width * height * samples * samples / (twidth * theight);
+        Self { fb0: AtomicPtr::new(fb0),
+               fb1: AtomicPtr::new(fb1),
+               format,
                width,
+               samples,
                height,
                twidth,
                theight,
-               tcount: width * height / (twidth * theight),
-               tnext: AtomicU64::new(0),
-               tfinished: AtomicU64::new(0) }
+               tcount,
+               tclaims: (0 .. tcount).map(|_| AtomicU64::new(frame)).collect(),
+               tfinished: Arc::new(AtomicU64::new(frame * tcount as u64)),
+               dim: AtomicBool::new(false),
+               debug: AtomicU8::new(DebugMode::Off as _),
+               gamma: AtomicBool::new(false),
+               tsums: (0 .. tcount).map(|_| AtomicU32::new(0)).collect(),
+               tstill: (0 .. tcount).map(|_| AtomicU32::new(0)).collect() }
+    }
+
+    /// Enables or disables dimming of tiles whose content hasn't changed for a
+    /// while.
+    ///
+    /// * `enable`: Whether to enable dimming.
+    pub fn set_dimming(&self, enable: bool)
+    {
+        self.dim.store(enable, Ordering::Relaxed);
+        self.tstill.iter().for_each(|still| still.store(0, Ordering::Relaxed));
+    }
+
+    /// Selects the debug rendering mode, which takes effect at the next frame.
+    ///
+    /// * `mode`: Mode to select.
+    pub fn set_debug_mode(&self, mode: DebugMode)
+    {
+        self.debug.store(mode as _, Ordering::Relaxed);
+    }
+
+    /// Returns the selected debug rendering mode.
+    pub fn debug_mode(&self) -> DebugMode
+    {
+        match self.debug.load(Ordering::Relaxed) {
+            1 => DebugMode::Wireframe,
+            2 => DebugMode::Depth,
+            3 => DebugMode::Tiles,
+            4 => DebugMode::Overdraw,
+            _ => DebugMode::Off,
+        }
+    }
+
+    /// Enables or disables gamma correct shading and dithering, which takes
+    /// effect at the next frame.
+    ///
+    /// * `enable`: Whether to enable gamma correction.
+    pub fn set_gamma_correction(&self, enable: bool)
+    {
+        self.gamma.store(enable, Ordering::Relaxed);
+    }
+
+    /// Returns whether gamma correct shading and dithering are enabled.
+    pub fn gamma_correction(&self) -> bool
+    {
+        self.gamma.load(Ordering::Relaxed)
+    }
+
+    /// Returns the pixel format.
+    pub fn format(&self) -> PixelFormat
+    {
+        self.format
+    }
+
+    /// Returns the image width.
+    pub fn width(&self) -> usize
+    {
+        self.width
+    }
+
+    /// Returns the image height.
+    pub fn height(&self) -> usize
+    {
+        self.height
+    }
+
+    /// Returns whether triangles are rasterized at twice the resolution along
+    /// each axis.
+    pub fn is_supersampled(&self) -> bool
+    {
+        self.samples > 1
+    }
+
+    /// Returns the width at which triangles are rasterized.
+    pub fn raster_width(&self) -> usize
+    {
+        self.width * self.samples
+    }
+
+    /// Returns the height at which triangles are rasterized.
+    pub fn raster_height(&self) -> usize
+    {
+        self.height * self.samples
+    }
+
+    /// Returns a clipping rectangle covering the whole image at the
+    /// rasterization resolution, with the origin at the bottom left corner.
+    pub fn bounds(&self) -> Rect
+    {
+        Rect::new(0, 0, self.raster_width() as _, self.raster_height() as _)
+    }
+
+    /// Returns the tile width at the rasterization resolution.
+    pub fn tile_width(&self) -> usize
+    {
+        self.twidth
+    }
+
+    /// Returns the tile height at the rasterization resolution.
+    pub fn tile_height(&self) -> usize
+    {
+        self.theight
     }
 
     /// Returns the current frame ID.
@@ -130,22 +353,92 @@ impl FrameBuffer
         self.tfinished.load(Ordering::Relaxed) / self.tcount as u64
     }
 
-    /// Creates an iterator of tiles awaiting to be drawn.
+    /// Creates an iterator of tiles awaiting to be drawn by one of several
+    /// cooperating cores.  Each core starts with its own contiguous band of
+    /// tiles, which it gets to draw every frame so that the triangles binned
+    /// to them stay in its cache, and then helps with the bands of the other
+    /// cores starting from the opposite end.
+    ///
+    /// * `core`: Index of the core drawing the tiles.
+    /// * `cores`: Number of cores drawing tiles.
     ///
     /// Returns the newly created iterator.
-    pub fn tiles(&self) -> FrameBufferIterator
+    ///
+    /// Panics if the image to draw to has been released.
+    #[track_caller]
+    pub fn tiles(&self, core: usize, cores: usize) -> FrameBufferIterator
     {
-        FrameBufferIterator::new(self)
+        assert!(!self.back().is_null(),
+                "Attempted to draw to a frame buffer whose back image was released");
+        FrameBufferIterator::new(self, core, cores)
     }
 
     /// Returns the DMA address of the frame buffer not currently being drawn.
     pub fn vsync(&self) -> u32
     {
-        let frame = self.frame();
-        if frame & 0x1 == 0 {
-            return to_dma(self.fb0 as _) as _;
+        to_dma(self.front_image() as _) as _
+    }
+
+    /// Frees the image currently being drawn, keeping the other one around
+    /// for display, so that a replacement frame buffer can be allocated before
+    /// this one stops being displayed.  Nothing can be drawn to this frame
+    /// buffer afterwards.
+    pub fn release_back(&self)
+    {
+        let back = if self.frame() & 0x1 == 0 { &self.fb1 } else { &self.fb0 };
+        let buf = back.swap(null_mut(), Ordering::Relaxed);
+        if !buf.is_null() {
+            unsafe { UNCACHED.dealloc(buf, self.layout()) };
         }
-        to_dma(self.fb1 as _) as _
+    }
+
+    /// Returns the pixels of the frame buffer not currently being drawn in the
+    /// XRGB8888 format regardless of the pixel format, with rows stored from
+    /// the bottom of the screen up.
+    pub fn front(&self) -> impl Iterator<Item = u32> + '_
+    {
+        let buf = self.front_image();
+        let len = self.width * self.height;
+        let xrgb = match self.format {
+            PixelFormat::Xrgb8888 => unsafe { slice_from_raw_parts(buf.cast::<u32>(), len) },
+            PixelFormat::Rgb565 => &[],
+        };
+        let rgb = match self.format {
+            PixelFormat::Xrgb8888 => &[],
+            PixelFormat::Rgb565 => unsafe { slice_from_raw_parts(buf.cast::<u16>(), len) },
+        };
+        let expand = |pixel: &u16| {
+            let pixel = *pixel as u32;
+            let red = pixel >> 11;
+            let green = pixel >> 5 & 0x3F;
+            let blue = pixel & 0x1F;
+            (red << 3 | red >> 2) << 16 | (green << 2 | green >> 4) << 8 | blue << 3 | blue >> 2
+        };
+        xrgb.iter().copied().chain(rgb.iter().map(expand))
+    }
+
+    /// Returns the image not currently being drawn.
+    fn front_image(&self) -> *mut u8
+    {
+        if self.frame() & 0x1 == 0 {
+            return self.fb0.load(Ordering::Relaxed);
+        }
+        self.fb1.load(Ordering::Relaxed)
+    }
+
+    /// Returns the image currently being drawn, or null if released.
+    fn back(&self) -> *mut u8
+    {
+        if self.frame() & 0x1 == 0 {
+            return self.fb1.load(Ordering::Relaxed);
+        }
+        self.fb0.load(Ordering::Relaxed)
+    }
+
+    /// Returns the memory layout of each image.
+    fn layout(&self) -> Layout
+    {
+        Layout::from_size_align(self.width * self.height * self.format.depth(), 64).unwrap()
     }
 }
 
@@ -153,10 +446,11 @@ impl Drop for FrameBuffer
 {
     fn drop(&mut self)
     {
-        let layout = Layout::from_size_align(self.width * self.height * size_of::<u16>(), 64).unwrap();
-        unsafe {
-            UNCACHED.dealloc(self.fb0.cast(), layout);
-            UNCACHED.dealloc(self.fb1.cast(), layout);
+        let layout = self.layout();
+        for buf in [self.fb0.get_mut(), self.fb1.get_mut()] {
+            if !buf.is_null() {
+                unsafe { UNCACHED.dealloc(*buf, layout) };
+            }
         }
     }
 }
@@ -165,16 +459,45 @@ unsafe impl Send for FrameBuffer {}
 
 unsafe impl Sync for FrameBuffer {}
 
+impl PixelFormat
+{
+    /// Returns the size of a pixel in bytes.
+    pub fn depth(self) -> usize
+    {
+        match self {
+            Self::Xrgb8888 => size_of::<u32>(),
+            Self::Rgb565 => size_of::<u16>(),
+        }
+    }
+
+    /// Returns the quantization steps of the red and blue, and green channels
+    /// in 8-bit units.
+    fn steps(self) -> (f32, f32)
+    {
+        match self {
+            Self::Xrgb8888 => (1.0, 1.0),
+            Self::Rgb565 => (8.0, 4.0),
+        }
+    }
+}
+
 impl<'a> FrameBufferIterator<'a>
 {
     /// Creates and initializes a new iterator over the tiles of a frame buffer.
     ///
     /// * `fb`: Frame buffer that this iterator borrows tiles from.
+    /// * `core`: Index of the core drawing the tiles.
+    /// * `cores`: Number of cores drawing tiles.
     ///
     /// Returns the newly created iterator.
-    fn new(fb: &'a FrameBuffer) -> Self
+    fn new(fb: &'a FrameBuffer, core: usize, cores: usize) -> Self
     {
-        Self { fb, frame: fb.frame() }
+        let own = core * fb.tcount / cores .. (core + 1) * fb.tcount / cores;
+        Self { fb,
+               frame: fb.frame(),
+               band: own.clone(),
+               rest: (0 .. fb.tcount).rev(),
+               own }
     }
 }
 
@@ -184,20 +507,16 @@ impl<'a> Iterator for FrameBufferIterator<'a>
 
     fn next(&mut self) -> Option<Tile<'a>>
     {
-        let tnext = loop {
-            let tnext = self.fb.tnext.load(Ordering::Relaxed);
-            if tnext / self.fb.tcount as u64 != self.frame {
-                return None;
+        loop {
+            let pos = match self.band.next() {
+                Some(pos) => pos,
+                None => self.rest.find(|pos| !self.own.contains(pos))?,
             };
-            if self.fb
-                   .tnext
-                   .compare_exchange(tnext, tnext + 1, Ordering::Relaxed, Ordering::Relaxed)
-                   .is_ok()
-            {
-                break tnext;
+            if self.fb.tclaims[pos].fetch_max(self.frame + 1, Ordering::Relaxed) <= self.frame {
+                // No other core has claimed this tile yet.
+                return Some(Tile::new(self.fb, self.frame * self.fb.tcount as u64 + pos as u64));
             }
-        };
-        Some(Tile::new(self.fb, tnext))
+        }
     }
 }
 
@@ -212,8 +531,8 @@ impl<'a> Tile<'a>
     fn new(fb: &'a FrameBuffer, id: u64) -> Self
     {
         let pos = id as usize % fb.tcount;
-        let col = pos * fb.twidth % fb.width;
-        let row = pos * fb.twidth / fb.width * fb.theight;
+        let col = pos * fb.twidth % fb.raster_width();
+        let row = pos * fb.twidth / fb.raster_width() * fb.theight;
         let origx = col as f32 + 0.5;
         let origy = row as f32 + 0.5;
         let sizex = (fb.twidth - 1) as f32;
@@ -222,7 +541,7 @@ impl<'a> Tile<'a>
         let pty = f32x4::from_array([origy, origy, origy + sizey, origy + sizey]);
         let min = f32x4::from_array([origx, origy, 0.0, 0.0]);
         let max = f32x4::from_array([origx + sizex, origy + sizey, 1.0, f32::INFINITY]);
-        let cb = [u32x4::splat(0); TILE_DIM_MAX * TILE_DIM_MAX / 4];
+        let cb = ManuallyDrop::new(BLITTER.color_buffer());
         let db = [u16x4::splat(0); TILE_DIM_MAX * TILE_DIM_MAX / 4];
         Self { fb,
                col,
@@ -231,29 +550,56 @@ impl<'a> Tile<'a>
                pty,
                min,
                max,
+               dmin: 0,
                cb,
                db }
     }
 
+    /// Returns the area of the frame buffer covered by this tile at the
+    /// rasterization resolution.
+    pub fn rect(&self) -> Rect
+    {
+        Rect::new(self.col as _, self.row as _, self.fb.twidth as _, self.fb.theight as _)
+    }
+
     /// Draws a triangle to the tile.
     ///
     /// * `tri`: Triangle to draw.
-    /// * `lights`: Lights potentially illuminating the triangle.
-    pub fn draw_triangle(&mut self, tri: &Triangle, lights: &[Light])
+    /// * `surface`: Surface properties of the triangle.
+    /// * `clip`: Clipping rectangle outside of which no fragments are drawn.
+    /// * `state`: Depth and blending state of the triangle's render pass.
+    ///
+    /// Returns the number of fragments shaded.
+    pub fn draw_triangle(&mut self, tri: &Triangle, surface: Surface, clip: &Rect, state: PassState) -> usize
     {
+        profile!("FrameBuffer::draw_triangle");
+        // Convert the clipping rectangle to tile coordinates.
+        let tile = self.rect();
+        let is_unclipped = clip.contains_rect(tile);
+        let clip = clip.intersect(tile) + -tile.min();
+        if clip.is_empty() {
+            // The clipping rectangle is completely outside this tile.
+            return 0;
+        }
+        let (ccol, crow) = (clip.x as usize, clip.y as usize);
+        let (ccolmax, crowmax) = (clip.max().x as usize, clip.max().y as usize);
         // Check whether the axis-aligned bounding boxes of the triangle and tile
         // overlap.
         let tmax = self.max;
         let min = tri.0.proj.simd_min(tri.1.proj).simd_min(tri.2.proj);
         if tmax.simd_lt(min).any() {
             // The triangle is completely outside this tile.
-            return;
+            return 0;
         }
         let tmin = self.min;
         let max = tri.0.proj.simd_max(tri.1.proj).simd_max(tri.2.proj);
         if tmin.simd_gt(max).any() {
             // The triangle is completely outside this tile.
-            return;
+            return 0;
+        }
+        if state.depth_test && Self::depth_bits(f32x4::splat(max[2].min(1.0)))[0] < self.dmin {
+            // The triangle is completely behind what was already drawn to this tile.
+            return 0;
         }
         // Compute the linear barycentric coordinates at the corner control points.
         let ptx = self.ptx;
@@ -267,17 +613,17 @@ impl<'a> Tile<'a>
         let area0 = x1 * y2 - x2 * y1;
         if area0.reduce_max() < 0.0 {
             // The triangle is completely outside this tile.
-            return;
+            return 0;
         }
         let area1 = x2 * y0 - x0 * y2;
         if area1.reduce_max() < 0.0 {
             // The triangle is completely outside this tile.
-            return;
+            return 0;
         }
         let area2 = x0 * y1 - x1 * y0;
         if area2.reduce_max() < 0.0 {
             // The triangle is completely outside this tile.
-            return;
+            return 0;
         }
         let itotal = (area0 + area1 + area2).fast_recip();
         let bary0 = area0 * itotal;
@@ -292,9 +638,19 @@ impl<'a> Tile<'a>
         let vinc1 = (bary1[2] - bary1[0]) * sizeyi;
         let hinc2 = (bary2[1] - bary2[0]) * sizexi;
         let vinc2 = (bary2[2] - bary2[0]) * sizeyi;
+        // Compute how much each barycentric coordinate changes along the major
+        // axis of its edge in a single pixel, so that wireframe edges are
+        // always one pixel thick.
+        let hincs = f32x4::from_array([hinc0, hinc1, hinc2, 0.0]).abs();
+        let vincs = f32x4::from_array([vinc0, vinc1, vinc2, 0.0]).abs();
+        let edges = hincs.simd_max(vincs);
+        let edge0 = f32x4::splat(edges[0]);
+        let edge1 = f32x4::splat(edges[1]);
+        let edge2 = f32x4::splat(edges[2]);
         // Try to reduce the number of tests to the smallest possible axis-aligned
         // bounding box.
         let twidth = self.fb.twidth;
+        let is_covering = bary0.reduce_min() > 0.0 && bary1.reduce_min() > 0.0 && bary2.reduce_min() > 0.0;
         let (tcol, trow, tcolmax, trowmax) =
             if bary0.reduce_min() >= 0.0 && bary1.reduce_min() >= 0.0 && bary2.reduce_min() >= 0.0 {
                 // The triangle overlaps the whole tile.
@@ -414,6 +770,25 @@ impl<'a> Tile<'a>
                               & !0x1;
                 (tcol, trow, tcolmax, trowmax)
             };
+        // Restrict the bounding box to the 2x2 fragment groups overlapping the
+        // clipping rectangle, and remember whether its edges cut through any of
+        // them.
+        let tcol = tcol.max(ccol & !0x1);
+        let trow = trow.max(crow & !0x1);
+        let tcolmax = tcolmax.min((ccolmax + 1) & !0x1);
+        let trowmax = trowmax.min((crowmax + 1) & !0x1);
+        let is_clipped = (ccol | crow | ccolmax | crowmax) & 0x1 != 0;
+        // An opaque triangle covering every fragment of the tile within the depth
+        // range raises the depth of all of them to at least its own minimum,
+        // give or take a rounding step.
+        let is_covering = is_covering && state.depth_write && is_unclipped && min[2] >= 0.0 && max[2] <= 1.0;
+        let dfloor = Self::depth_bits(f32x4::splat(min[2]))[0].saturating_sub(1);
+        let ccols = u32x4::from_array([0, 1, 0, 1]);
+        let crows = u32x4::from_array([0, 0, 1, 1]);
+        let ccolmin = u32x4::splat(ccol as u32);
+        let crowmin = u32x4::splat(crow as u32);
+        let ccolmax = u32x4::splat(ccolmax as u32);
+        let crowmax = u32x4::splat(crowmax as u32);
         // Compute the starting barycentric coordinates and adjust the increments.
         let ftcol = tcol as f32;
         let ftrow = trow as f32;
@@ -433,15 +808,159 @@ impl<'a> Tile<'a>
         // the optimizer.
         let zero = f32x4::splat(0.0);
         let one = f32x4::splat(1.0);
-        let dxm = u32x4::splat(0x3F800000);
-        let dxb = u32x4::splat(0x30000000);
-        let dmm = u32x4::splat(0x7FF000);
-        let ds = u32x4::splat(12);
-        let rgbmul = 255.5f32;
+        let is_linear = self.fb.gamma_correction();
+        let (rgbmul, dithers) = if is_linear {
+            (255.0f32, DITHER.map(|quad| f32x4::from_array(quad.map(|step| (step as f32 + 0.5) / 16.0))))
+        } else {
+            (255.5f32, [zero; 4])
+        };
+        let (rbstep, gstep) = self.fb.format.steps();
+        let rgbmax = f32x4::splat(255.0);
+        let rgbdiv = 255.0f32.recip();
+        let rgbmask = u32x4::splat(0xFF);
         let rshift = u32x4::splat(16);
         let gshift = u32x4::splat(8);
+        let mode = self.fb.debug_mode();
         let is_affine = tri.0.proj[3] == tri.1.proj[3] && tri.0.proj[3] == tri.2.proj[3];
         let is_plane = tri.0.normal.simd_eq(tri.1.normal).all() && tri.0.normal.simd_eq(tri.2.normal).all();
+        let edges = Edges::new(f32x4::from_array([hinc0[0], hinc1[0], hinc2[0], 0.0]),
+                               f32x4::from_array([vinc0[0], vinc1[0], vinc2[0], 0.0]));
+        // Validate only the fragments in a group of 2x2 that belong to the triangle.
+        let coverage = |tcol: usize, trow: usize, hbary0: f32x4, hbary1: f32x4, hbary2: f32x4| {
+            let mut valid = edges.cover([hbary0, hbary1, hbary2]);
+            if is_clipped {
+                // Exclude the fragments outside the clipping rectangle.
+                let cols = u32x4::splat(tcol as u32) + ccols;
+                let rows = u32x4::splat(trow as u32) + crows;
+                valid &= cols.simd_ge(ccolmin) & cols.simd_lt(ccolmax);
+                valid &= rows.simd_ge(crowmin) & rows.simd_lt(crowmax);
+            }
+            valid
+        };
+        // Shade the valid fragments in a group of 2x2.
+        let mut shaded = 0;
+        let mut shade = |tcol: usize, trow: usize, hbary0: f32x4, hbary1: f32x4, hbary2: f32x4, mut valid: mask32x4| {
+            let ctx = if is_affine {
+                // Affine projection.
+                Context { bary0: hbary0,
+                          bary1: hbary1,
+                          bary2: hbary2,
+                          is_plane }
+            } else {
+                // Compute the perspective-correct barycentric coordinates.
+                let w0 = hbary0.mul_lane::<3>(tri.0.proj);
+                let w1 = hbary1.mul_lane::<3>(tri.1.proj);
+                let w2 = hbary2.mul_lane::<3>(tri.2.proj);
+                let itotal = (w0 + w1 + w2).fast_recip();
+                Context { bary0: w0 * itotal,
+                          bary1: w1 * itotal,
+                          bary2: w2 * itotal,
+                          is_plane }
+            };
+            // Offset for these 4 fragments in the tile buffers.
+            let offset = (trow >> 1) * (twidth >> 1) + (tcol >> 1);
+            // Compute the depth and exclude all fragments outside the range between the
+            // values in the depth buffer and the near clipping plane.
+            let odepth = self.db[offset].cast::<u32>();
+            let mut shader = Shader::new(tri, ctx, surface);
+            let fdepth = shader.depth();
+            valid &= fdepth.simd_le(one) & fdepth.simd_gez();
+            let depth = Self::depth_bits(fdepth);
+            if state.depth_test {
+                valid &= depth.simd_gt(odepth);
+            }
+            if !valid.any() {
+                // All the remaining fragments were invalidated by the depth test.
+                return;
+            }
+            shaded += valid.to_bitmask().count_ones() as usize;
+            if state.depth_write {
+                self.db[offset] = valid.select(depth, odepth).cast::<u16>();
+            }
+            let ocolor = self.cb[offset];
+            let color = match mode {
+                // Only triangles that write to the depth buffer are visualized.
+                DebugMode::Depth if !state.depth_write => ocolor,
+                DebugMode::Depth => {
+                    let gray = fdepth.mul_scalar(rgbmul).cast::<u32>();
+                    gray << rshift | gray << gshift | gray
+                }
+                DebugMode::Overdraw => Self::heat(ocolor),
+                _ => {
+                    // Apply shading.
+                    surface.lights.iter().for_each(|l| shader.illuminate(l));
+                    if let Some(map) = surface.shadow {
+                        shader.shadow(map);
+                    }
+                    let alpha = shader.alpha();
+                    let (red, green, blue) = shader.finish(is_linear);
+                    // Compute the RGB888 color values.
+                    let red = red.simd_max(zero).simd_min(one);
+                    let green = green.simd_max(zero).simd_min(one);
+                    let blue = blue.simd_max(zero).simd_min(one);
+                    let (red, green, blue) = if state.blend {
+                        // Blend the source color over the destination color.
+                        let alpha = alpha.simd_max(zero).simd_min(one);
+                        let ored = (ocolor >> rshift & rgbmask).cast::<f32>().mul_scalar(rgbdiv);
+                        let ogreen = (ocolor >> gshift & rgbmask).cast::<f32>().mul_scalar(rgbdiv);
+                        let oblue = (ocolor & rgbmask).cast::<f32>().mul_scalar(rgbdiv);
+                        let (ored, ogreen, oblue) = if is_linear {
+                            (to_linear(ored), to_linear(ogreen), to_linear(oblue))
+                        } else {
+                            (ored, ogreen, oblue)
+                        };
+                        (ored.fused_mul_add(alpha, red - ored),
+                         ogreen.fused_mul_add(alpha, green - ogreen),
+                         oblue.fused_mul_add(alpha, blue - oblue))
+                    } else {
+                        (red, green, blue)
+                    };
+                    let (red, green, blue) = if is_linear {
+                        (to_srgb(red), to_srgb(green), to_srgb(blue))
+                    } else {
+                        (red, green, blue)
+                    };
+                    // Dither in steps of the frame buffer's precision.
+                    let dither = dithers[tcol >> 1 & 0x1 | trow & 0x2];
+                    let rbdither = dither.mul_scalar(rbstep);
+                    let gdither = dither.mul_scalar(gstep);
+                    let red = red.mul_scalar(rgbmul) + rbdither;
+                    let green = green.mul_scalar(rgbmul) + gdither;
+                    let blue = blue.mul_scalar(rgbmul) + rbdither;
+                    let red = red.simd_min(rgbmax).cast::<u32>() << rshift;
+                    let green = green.simd_min(rgbmax).cast::<u32>() << gshift;
+                    let blue = blue.simd_min(rgbmax).cast::<u32>();
+                    let color = red | green | blue;
+                    if mode == DebugMode::Wireframe {
+                        let edge = hbary0.simd_lt(edge0) | hbary1.simd_lt(edge1) | hbary2.simd_lt(edge2);
+                        edge.select(u32x4::splat(WIREFRAME_COLOR), color)
+                    } else {
+                        color
+                    }
+                }
+            };
+            self.cb[offset] = valid.select(color, ocolor);
+        };
+        // Validate the fragments in a group of 4x2 made of two groups of 2x2 side by
+        // side, with the first group in the lower half of the vectors.
+        #[cfg(raster8)]
+        let coverage8 = {
+            let ccols = u32x8::from_array([0, 1, 0, 1, 2, 3, 2, 3]);
+            let crows = u32x8::from_array([0, 0, 1, 1, 0, 0, 1, 1]);
+            let (ccolmin, ccolmax) = (u32x8::splat(ccolmin[0]), u32x8::splat(ccolmax[0]));
+            let (crowmin, crowmax) = (u32x8::splat(crowmin[0]), u32x8::splat(crowmax[0]));
+            move |tcol: usize, trow: usize, hbary0: f32x8, hbary1: f32x8, hbary2: f32x8| {
+                let mut valid = edges.cover8([hbary0, hbary1, hbary2]);
+                if is_clipped {
+                    // Exclude the fragments outside the clipping rectangle.
+                    let cols = u32x8::splat(tcol as u32) + ccols;
+                    let rows = u32x8::splat(trow as u32) + crows;
+                    valid &= cols.simd_ge(ccolmin) & cols.simd_lt(ccolmax);
+                    valid &= rows.simd_ge(crowmin) & rows.simd_lt(crowmax);
+                }
+                valid.to_bitmask()
+            }
+        };
         // Loop over all the fragments in the tile in groups of 2x2, and shade those
         // that belong to the triangle.
         let mut vbary0 = bary0;
@@ -451,73 +970,48 @@ impl<'a> Tile<'a>
             let mut hbary0 = vbary0;
             let mut hbary1 = vbary1;
             let mut hbary2 = vbary2;
-            for tcol in (tcol .. tcolmax).step_by(2) {
-                // Validate only the fragments inside the triangle.
-                let mut valid = hbary0.simd_gtz() & hbary1.simd_gtz() & hbary2.simd_gtz();
-                // Include half of the edges.
-                if (hbary0.simd_eqz() | hbary1.simd_eqz() | hbary2.simd_eqz()).any() {
-                    valid |= hbary0.simd_eqz() & (hinc0.simd_ltz() | hinc0.simd_eqz() & vinc0.simd_ltz());
-                    valid |= hbary1.simd_eqz() & (hinc1.simd_ltz() | hinc1.simd_eqz() & vinc1.simd_ltz());
-                    valid |= hbary2.simd_eqz() & (hinc2.simd_ltz() | hinc2.simd_eqz() & vinc2.simd_ltz());
-                }
-                if !valid.any() {
-                    // All fragments were invalidated.
-                    hbary0 += hinc0;
-                    hbary1 += hinc1;
-                    hbary2 += hinc2;
-                    continue;
+            // Cover as much of the row as possible in groups of 4x2 first, which
+            // doubles the fragments tested per iteration using pairs of registers.
+            #[cfg(raster8)]
+            let tcol = {
+                let mut tcol = tcol;
+                let mut wbary0 = join(hbary0, hbary0 + hinc0);
+                let mut wbary1 = join(hbary1, hbary1 + hinc1);
+                let mut wbary2 = join(hbary2, hbary2 + hinc2);
+                let winc0 = f32x8::splat(hinc0[0] + hinc0[0]);
+                let winc1 = f32x8::splat(hinc1[0] + hinc1[0]);
+                let winc2 = f32x8::splat(hinc2[0] + hinc2[0]);
+                while tcol + 2 < tcolmax {
+                    let valid = coverage8(tcol, trow, wbary0, wbary1, wbary2);
+                    if valid != 0 {
+                        let (lbary0, rbary0) = split(wbary0);
+                        let (lbary1, rbary1) = split(wbary1);
+                        let (lbary2, rbary2) = split(wbary2);
+                        if valid & 0xF != 0 {
+                            shade(tcol, trow, lbary0, lbary1, lbary2, mask32x4::from_bitmask(valid & 0xF));
+                        }
+                        if valid >> 4 != 0 {
+                            shade(tcol + 2,
+                                  trow,
+                                  rbary0,
+                                  rbary1,
+                                  rbary2,
+                                  mask32x4::from_bitmask(valid >> 4));
+                        }
+                    }
+                    wbary0 += winc0;
+                    wbary1 += winc1;
+                    wbary2 += winc2;
+                    tcol += 4;
                 }
-                let ctx = if is_affine {
-                    // Affine projection.
-                    Context { bary0: hbary0,
-                              bary1: hbary1,
-                              bary2: hbary2,
-                              is_plane }
-                } else {
-                    // Compute the perspective-correct barycentric coordinates.
-                    let w0 = hbary0.mul_lane::<3>(tri.0.proj);
-                    let w1 = hbary1.mul_lane::<3>(tri.1.proj);
-                    let w2 = hbary2.mul_lane::<3>(tri.2.proj);
-                    let itotal = (w0 + w1 + w2).fast_recip();
-                    Context { bary0: w0 * itotal,
-                              bary1: w1 * itotal,
-                              bary2: w2 * itotal,
-                              is_plane }
-                };
-                // Offset for these 4 fragments in the tile buffers.
-                let offset = (trow >> 1) * (twidth >> 1) + (tcol >> 1);
-                // Compute the depth and exclude all fragments outside the range between the
-                // values in the depth buffer and the near clipping plane.
-                let odepth = self.db[offset].cast::<u32>();
-                let mut shader = Shader::new(tri, ctx);
-                let depth = shader.depth();
-                valid &= depth.simd_le(one) & depth.simd_gez();
-                let depthb = depth.to_bits().saturating_sub(dxb);
-                let depthx = (depthb & dxm) >> ds;
-                let depthm = (depthb & dmm) >> ds;
-                let depth = depthx | depthm;
-                valid &= depth.simd_gt(odepth);
-                if !valid.any() {
-                    // All the remaining fragments were invalidated by the depth test.
-                    hbary0 += hinc0;
-                    hbary1 += hinc1;
-                    hbary2 += hinc2;
-                    continue;
+                (hbary0, hbary1, hbary2) = (split(wbary0).0, split(wbary1).0, split(wbary2).0);
+                tcol
+            };
+            for tcol in (tcol .. tcolmax).step_by(2) {
+                let valid = coverage(tcol, trow, hbary0, hbary1, hbary2);
+                if valid.any() {
+                    shade(tcol, trow, hbary0, hbary1, hbary2, valid);
                 }
-                self.db[offset] = valid.select(depth, odepth).cast::<u16>();
-                // Apply shading.
-                lights.iter().for_each(|l| shader.illuminate(l));
-                let (red, green, blue) = shader.finish();
-                // Compute the RGB888 color values.
-                let ocolor = self.cb[offset];
-                let red = red.simd_max(zero).simd_min(one);
-                let green = green.simd_max(zero).simd_min(one);
-                let blue = blue.simd_max(zero).simd_min(one);
-                let red = red.mul_scalar(rgbmul).cast::<u32>() << rshift;
-                let green = green.mul_scalar(rgbmul).cast::<u32>() << gshift;
-                let blue = blue.mul_scalar(rgbmul).cast::<u32>();
-                let color = red | green | blue;
-                self.cb[offset] = valid.select(color, ocolor);
                 // Apply horizontal increments.
                 hbary0 += hinc0;
                 hbary1 += hinc1;
@@ -528,6 +1022,114 @@ impl<'a> Tile<'a>
             vbary1 += vinc1;
             vbary2 += vinc2;
         }
+        if is_covering {
+            self.dmin = self.dmin.max(dfloor);
+        }
+        shaded
+    }
+
+    /// Packs depth values into the 16 bits stored in the depth buffer,
+    /// preserving their order within the range between 0 and 1.
+    ///
+    /// * `depth`: Depth values to pack.
+    ///
+    /// Returns the packed values.
+    #[inline]
+    fn depth_bits(depth: f32x4) -> u32x4
+    {
+        let bits = depth.to_bits().saturating_sub(u32x4::splat(0x30000000));
+        let exp = (bits & u32x4::splat(0x3F800000)) >> u32x4::splat(12);
+        let mant = (bits & u32x4::splat(0x7FF000)) >> u32x4::splat(12);
+        exp | mant
+    }
+
+    /// Computes the next overdraw heatmap colors, filling the red channel
+    /// first, then the green channel, and finally the blue channel.
+    ///
+    /// * `color`: Current heatmap colors.
+    ///
+    /// Returns the colors after drawing one more fragment.
+    fn heat(color: u32x4) -> u32x4
+    {
+        let max = u32x4::splat(0xFF);
+        let step = u32x4::splat(HEAT_STEP);
+        let red = color >> u32x4::splat(16) & max;
+        let green = color >> u32x4::splat(8) & max;
+        let blue = color & max;
+        let red_full = red.simd_eq(max);
+        let green_full = green.simd_eq(max);
+        let blue = (red_full & green_full).select((blue + step).simd_min(max), blue);
+        let green = red_full.select((green + step).simd_min(max), green);
+        let red = (red + step).simd_min(max);
+        red << u32x4::splat(16) | green << u32x4::splat(8) | blue
+    }
+
+    /// Highlights the top and left boundaries of this tile, which together
+    /// with those of the neighboring tiles outline every tile.
+    fn outline(&mut self)
+    {
+        let twidth = self.fb.twidth;
+        for tcol in 0 .. twidth {
+            self.cb[tcol >> 1][tcol & 0x1] = TILE_COLOR;
+        }
+        for trow in 0 .. self.fb.theight {
+            self.cb[(trow >> 1) * (twidth >> 1)][(trow & 0x1) << 1] = TILE_COLOR;
+        }
+    }
+
+    /// Averages every 2x2 fragment quad of a supersampled color buffer into a
+    /// single pixel.
+    ///
+    /// * `cb`: Color buffer to downsample, which is returned to the pool.
+    ///
+    /// Returns a color buffer with the resolved pixels, laid out in 2x2 quads
+    /// like the original.
+    fn downsample(&self, cb: DmaBuffer<ColorBuffer>) -> DmaBuffer<ColorBuffer>
+    {
+        profile!("Tile::downsample");
+        let mut res = BLITTER.color_buffer();
+        let twidth = self.fb.twidth >> 1;
+        let theight = self.fb.theight >> 1;
+        // Red and blue as well as green can be summed in place without overflowing
+        // into the neighboring channels.
+        let rbmask = u32x4::splat(0xFF00FF);
+        let gmask = u32x4::splat(0xFF00);
+        for trow in 0 .. theight {
+            for tcol in 0 .. twidth {
+                let quad = cb[trow * twidth + tcol];
+                let rb = ((quad & rbmask).reduce_sum() + 0x20002) >> 2 & 0xFF00FF;
+                let g = ((quad & gmask).reduce_sum() + 0x200) >> 2 & 0xFF00;
+                res[(trow >> 1) * (twidth >> 1) + (tcol >> 1)][(trow & 0x1) << 1 | tcol & 0x1] = rb | g;
+            }
+        }
+        BLITTER.recycle(cb);
+        res
+    }
+
+    /// Updates the checksum of this tile's content and dims it if the content
+    /// hasn't changed for a while.
+    fn dim_if_still(&mut self)
+    {
+        let pos = self.row / self.fb.theight * (self.fb.raster_width() / self.fb.twidth) + self.col / self.fb.twidth;
+        let sum = self.cb
+                      .iter()
+                      .fold(u32x4::splat(0), |sum, color| (sum << 1 | sum >> 31) ^ color)
+                      .reduce_xor();
+        let still = if self.fb.tsums[pos].swap(sum, Ordering::Relaxed) == sum {
+            self.fb.tstill[pos].fetch_add(1, Ordering::Relaxed) + 1
+        } else {
+            self.fb.tstill[pos].store(0, Ordering::Relaxed);
+            0
+        };
+        if still < DIM_STILL_FRAMES {
+            return;
+        }
+        // Scale all color channels down to 75% of their intensity.
+        let half = u32x4::splat(0x7F7F7F);
+        let quarter = u32x4::splat(0x3F3F3F);
+        for color in self.cb.iter_mut() {
+            *color = (*color >> 1 & half) + (*color >> 2 & quarter);
+        }
     }
 }
 
@@ -535,29 +1137,83 @@ impl<'a> Drop for Tile<'a>
 {
     fn drop(&mut self)
     {
-        let buf = if self.fb.frame() & 0x1 == 1 {
-            self.fb.fb0
-        } else {
-            self.fb.fb1
-        };
-        let buf = unsafe { buf.add(self.row * self.fb.width + self.col) };
+        profile!("Tile::drop");
+        let buf = self.fb.back();
+        let depth = self.fb.format.depth();
+        let samples = self.fb.samples;
+        let buf = unsafe { buf.add((self.row / samples * self.fb.width + self.col / samples) * depth) };
         let eindices = usizex8::from_array([0, 1, 4, 5, 8, 9, 12, 13]);
         let oindices = usizex8::from_array([2, 3, 6, 7, 10, 11, 14, 15]);
         let black = u32x8::splat(0);
-        let twidth = self.fb.twidth;
-        let theight = self.fb.theight;
+        let twidth = self.fb.twidth / samples;
+        let theight = self.fb.theight / samples;
         let width = self.fb.width;
+        if self.fb.dim.load(Ordering::Relaxed) {
+            self.dim_if_still();
+        }
+        if self.fb.debug_mode() == DebugMode::Tiles {
+            self.outline();
+        }
+        let cb = unsafe { ManuallyDrop::take(&mut self.cb) };
+        let cb = if samples > 1 { self.downsample(cb) } else { cb };
+        let cb = match self.fb.format {
+            PixelFormat::Xrgb8888 => {
+                let done = self.fb.tfinished.clone();
+                let Err(cb) = BLITTER.copy_tile(cb, buf as usize, (twidth, theight), width, done) else {
+                    // The blitter marks the tile as finished once the copy completes.
+                    return;
+                };
+                cb
+            }
+            // The DMA controller can't convert pixels.
+            PixelFormat::Rgb565 => cb,
+        };
         for trow in 0 .. theight {
             let indices = if trow & 0x1 == 0 { eindices } else { oindices };
-            let buf = unsafe { buf.add(trow * width) };
-            let cb = unsafe { slice_from_raw_parts(self.cb.as_ptr().cast::<u32>(), TILE_DIM_MAX * TILE_DIM_MAX) };
+            let buf = unsafe { buf.add(trow * width * depth) };
+            let cb = unsafe { slice_from_raw_parts(cb.as_ptr().cast::<u32>(), TILE_DIM_MAX * TILE_DIM_MAX) };
             for tcol in (0 .. twidth).step_by(8) {
                 let offset = usizex8::splat((trow >> 1) * (twidth << 1) + (tcol << 1));
                 let indices = indices + offset;
                 let color = u32x8::gather_or(cb, indices, black);
-                unsafe { buf.add(tcol).cast::<u32x8>().write(color) };
+                let buf = unsafe { buf.add(tcol * depth) };
+                match self.fb.format {
+                    PixelFormat::Xrgb8888 => unsafe { buf.cast::<u32x8>().write(color) },
+                    PixelFormat::Rgb565 => {
+                        let red = color >> u32x8::splat(8) & u32x8::splat(0xF800);
+                        let green = color >> u32x8::splat(5) & u32x8::splat(0x7E0);
+                        let blue = color >> u32x8::splat(3) & u32x8::splat(0x1F);
+                        unsafe { buf.cast::<u16x8>().write((red | green | blue).cast::<u16>()) };
+                    }
+                }
             }
         }
+        BLITTER.recycle(cb);
         self.fb.tfinished.fetch_add(1, Ordering::Relaxed);
     }
 }
+
+/// Joins two vectors into a pair of registers.
+///
+/// * `low`: Vector for the lower half.
+/// * `high`: Vector for the upper half.
+///
+/// Returns the joined vector.
+#[cfg(raster8)]
+#[inline(always)]
+fn join(low: f32x4, high: f32x4) -> f32x8
+{
+    simd_swizzle!(low, high, [0, 1, 2, 3, 4, 5, 6, 7])
+}
+
+/// Splits a pair of registers into its halves.
+///
+/// * `vec`: Vector to split.
+///
+/// Returns the lower and upper halves.
+#[cfg(raster8)]
+#[inline(always)]
+fn split(vec: f32x8) -> (f32x4, f32x4)
+{
+    (simd_swizzle!(vec, [0, 1, 2, 3]), simd_swizzle!(vec, [4, 5, 6, 7]))
+}
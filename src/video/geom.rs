@@ -3,6 +3,8 @@
 //! Contains geometry generation functionality.
 
 use super::*;
+use crate::assets::{Mesh, MeshVertex};
+use crate::game::Terrain;
 
 /// Rainbow cube.
 #[derive(Debug)]
@@ -12,6 +14,31 @@ pub struct Cube
     geom: [Triangle; 12],
 }
 
+/// Camera-facing rectangle, such as a torch flame, health bar, or selection
+/// marker.
+#[derive(Clone, Copy, Debug)]
+pub struct Billboard
+{
+    /// Center in world space.
+    pos: f32x4,
+    /// Width.
+    width: f32,
+    /// Height.
+    height: f32,
+    /// Color.
+    color: f32x4,
+}
+
+/// Static model loaded from a mesh asset.
+#[derive(Debug)]
+pub struct Model
+{
+    /// Geometry.
+    geom: Vec<Triangle>,
+    /// Bounding box of the geometry.
+    bounds: Aabb,
+}
+
 impl Cube
 {
     /// Creates and initializes a new rainbow cube.
@@ -44,79 +71,109 @@ impl Cube
         let cfdr = f32x4::from_array([1.0, 1.0, 0.0, 1.0]);
         let cful = f32x4::from_array([0.0, 1.0, 1.0, 1.0]);
         let cfur = f32x4::from_array([1.0, 1.0, 1.0, 1.0]);
+        // Texture coordinates, with every face mapping the whole texture as seen from
+        // outside the cube.
+        let tdl = f32x4::from_array([0.0, 0.0, 0.0, 0.0]);
+        let tdr = f32x4::from_array([1.0, 0.0, 0.0, 0.0]);
+        let tul = f32x4::from_array([0.0, 1.0, 0.0, 0.0]);
+        let tur = f32x4::from_array([1.0, 1.0, 0.0, 0.0]);
         // Cube faces.
         let fb0 = Vertex { pos: vbdl,
                            normal: nb,
-                           color: cbdl };
+                           color: cbdl,
+                           uv: tdr };
         let fb1 = Vertex { pos: vbul,
                            normal: nb,
-                           color: cbul };
+                           color: cbul,
+                           uv: tur };
         let fb2 = Vertex { pos: vbdr,
                            normal: nb,
-                           color: cbdr };
+                           color: cbdr,
+                           uv: tdl };
         let fb3 = Vertex { pos: vbur,
                            normal: nb,
-                           color: cbur };
+                           color: cbur,
+                           uv: tul };
         let ff0 = Vertex { pos: vfdr,
                            normal: nf,
-                           color: cfdr };
+                           color: cfdr,
+                           uv: tdr };
         let ff1 = Vertex { pos: vfur,
                            normal: nf,
-                           color: cfur };
+                           color: cfur,
+                           uv: tur };
         let ff2 = Vertex { pos: vfdl,
                            normal: nf,
-                           color: cfdl };
+                           color: cfdl,
+                           uv: tdl };
         let ff3 = Vertex { pos: vful,
                            normal: nf,
-                           color: cful };
+                           color: cful,
+                           uv: tul };
         let fl0 = Vertex { pos: vfdl,
                            normal: nl,
-                           color: cfdl };
+                           color: cfdl,
+                           uv: tdr };
         let fl1 = Vertex { pos: vful,
                            normal: nl,
-                           color: cful };
+                           color: cful,
+                           uv: tur };
         let fl2 = Vertex { pos: vbdl,
                            normal: nl,
-                           color: cbdl };
+                           color: cbdl,
+                           uv: tdl };
         let fl3 = Vertex { pos: vbul,
                            normal: nl,
-                           color: cbul };
+                           color: cbul,
+                           uv: tul };
         let fr0 = Vertex { pos: vbdr,
                            normal: nr,
-                           color: cbdr };
+                           color: cbdr,
+                           uv: tdr };
         let fr1 = Vertex { pos: vbur,
                            normal: nr,
-                           color: cbur };
+                           color: cbur,
+                           uv: tur };
         let fr2 = Vertex { pos: vfdr,
                            normal: nr,
-                           color: cfdr };
+                           color: cfdr,
+                           uv: tdl };
         let fr3 = Vertex { pos: vfur,
                            normal: nr,
-                           color: cfur };
+                           color: cfur,
+                           uv: tul };
         let fd0 = Vertex { pos: vbdr,
                            normal: nd,
-                           color: cbdr };
+                           color: cbdr,
+                           uv: tdr };
         let fd1 = Vertex { pos: vfdr,
                            normal: nd,
-                           color: cfdr };
+                           color: cfdr,
+                           uv: tur };
         let fd2 = Vertex { pos: vbdl,
                            normal: nd,
-                           color: cbdl };
+                           color: cbdl,
+                           uv: tdl };
         let fd3 = Vertex { pos: vfdl,
                            normal: nd,
-                           color: cfdl };
+                           color: cfdl,
+                           uv: tul };
         let fu0 = Vertex { pos: vfur,
                            normal: nu,
-                           color: cfur };
+                           color: cfur,
+                           uv: tdr };
         let fu1 = Vertex { pos: vbur,
                            normal: nu,
-                           color: cbur };
+                           color: cbur,
+                           uv: tur };
         let fu2 = Vertex { pos: vful,
                            normal: nu,
-                           color: cful };
+                           color: cful,
+                           uv: tdl };
         let fu3 = Vertex { pos: vbul,
                            normal: nu,
-                           color: cbul };
+                           color: cbul,
+                           uv: tul };
         // Cube triangles.
         let t0 = Triangle(fb0, fb1, fb2);
         let t1 = Triangle(fb2, fb1, fb3);
@@ -139,4 +196,127 @@ impl Cube
     {
         &self.geom
     }
+
+    /// Returns the bounding box of the cube.
+    pub fn bounds(&self) -> Aabb
+    {
+        Aabb::new(f32x4::splat(-1.0), f32x4::splat(1.0))
+    }
+}
+
+impl Billboard
+{
+    /// Creates and initializes a new billboard.
+    ///
+    /// * `pos`: Center in world space.
+    /// * `width`: Width in world units.
+    /// * `height`: Height in world units.
+    /// * `color`: Color, including alpha.
+    ///
+    /// Returns the newly created billboard.
+    pub fn new(pos: f32x4, width: f32, height: f32, color: f32x4) -> Self
+    {
+        Self { pos: pos.replace_lane::<3>(1.0),
+               width,
+               height,
+               color }
+    }
+
+    /// Expands this billboard into a pair of triangles facing a camera, with
+    /// texture coordinates covering the whole texture.
+    ///
+    /// * `right`: Horizontal axis of the camera in world space.
+    /// * `up`: Vertical axis of the camera in world space.
+    /// * `normal`: Axis of the camera pointing back at it in world space.
+    ///
+    /// Returns the triangles in world space.
+    pub fn triangles(&self, right: f32x4, up: f32x4, normal: f32x4) -> [Triangle; 2]
+    {
+        let right = right.mul_scalar(self.width * 0.5);
+        let up = up.mul_scalar(self.height * 0.5);
+        let vert = |pos, uv: [f32; 2]| Vertex { pos,
+                                                normal,
+                                                color: self.color,
+                                                uv: f32x4::from_array([uv[0], uv[1], 0.0, 0.0]) };
+        let dl = vert(self.pos - right - up, [0.0, 0.0]);
+        let dr = vert(self.pos + right - up, [1.0, 0.0]);
+        let ul = vert(self.pos - right + up, [0.0, 1.0]);
+        let ur = vert(self.pos + right + up, [1.0, 1.0]);
+        [Triangle(dl, dr, ul), Triangle(ul, dr, ur)]
+    }
+}
+
+impl Model
+{
+    /// Creates and initializes a new model from a loaded mesh.
+    ///
+    /// * `mesh`: Mesh to convert.
+    /// * `palette`: Function returning the color of the vertices of the
+    ///   triangles given the name of their material, or `None` for triangles
+    ///   without one.
+    ///
+    /// Returns the newly created model.
+    pub fn from_mesh(mesh: &Mesh, palette: impl Fn(Option<&str>) -> f32x4) -> Self
+    {
+        let mut colors = vec![palette(None); mesh.tris.len()];
+        for part in mesh.parts.iter() {
+            let color = palette(mesh.materials.get(part.material).map(|name| name.as_str()));
+            if let Some(colors) = colors.get_mut(part.tris.clone()) {
+                colors.fill(color);
+            }
+        }
+        let vert = |vert: &MeshVertex, color| Vertex { pos: vert.pos,
+                                                       normal: vert.normal,
+                                                       color,
+                                                       uv: f32x4::splat(0.0) };
+        let geom =
+            mesh.tris
+                .iter()
+                .zip(colors)
+                .map(|([vert0, vert1, vert2], color)| {
+                    Triangle(vert(vert0, color), vert(vert1, color), vert(vert2, color))
+                })
+                .collect();
+        let points = mesh.tris.iter().flatten().map(|vert| vert.pos).collect::<Vec<_>>();
+        let bounds = Aabb::from_points(&points).unwrap_or(Aabb::new(f32x4::splat(0.0), f32x4::splat(0.0)));
+        Self { geom, bounds }
+    }
+
+    /// Creates and initializes a new flat shaded model from a terrain mesh.
+    ///
+    /// * `terrain`: Terrain mesh to convert.
+    ///
+    /// Returns the newly created model.
+    pub fn from_terrain(terrain: &Terrain) -> Self
+    {
+        let geom = terrain.facets()
+                          .iter()
+                          .map(|facet| {
+                              let vert = |pos| Vertex { pos,
+                                                        normal: facet.normal,
+                                                        color: facet.color,
+                                                        uv: f32x4::splat(0.0) };
+                              let [pos0, pos1, pos2] = facet.corners;
+                              Triangle(vert(pos0), vert(pos1), vert(pos2))
+                          })
+                          .collect();
+        let points = terrain.facets()
+                            .iter()
+                            .flat_map(|facet| facet.corners)
+                            .collect::<Vec<_>>();
+        let bounds = Aabb::from_points(&points).unwrap_or(Aabb::new(f32x4::splat(0.0), f32x4::splat(0.0)));
+        Self { geom, bounds }
+    }
+
+    /// Returns the geometry of the model.
+    pub fn geom(&self) -> &[Triangle]
+    {
+        &self.geom
+    }
+
+    /// Returns the bounding box of the model.
+    pub fn bounds(&self) -> Aabb
+    {
+        self.bounds
+    }
 }
@@ -0,0 +1,79 @@
+//! Debug gizmos.
+//!
+//! Builds the lines of common debug visualizations, such as coordinate axes,
+//! bounding boxes, and paths, to be drawn with
+//! [`Video::draw_lines`](super::Video::draw_lines) in a single command along
+//! with any other lines.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::simd::prelude::*;
+
+use crate::math::{Aabb, Transform};
+use crate::simd::SimdFloatExtra;
+
+/// Debug line with its start and end points in world space and its color.
+pub type Line = (f32x4, f32x4, f32x4);
+
+/// Color of the X axis.
+const X_COLOR: f32x4 = f32x4::from_array([1.0, 0.0, 0.0, 1.0]);
+/// Color of the Y axis.
+const Y_COLOR: f32x4 = f32x4::from_array([0.0, 1.0, 0.0, 1.0]);
+/// Color of the Z axis.
+const Z_COLOR: f32x4 = f32x4::from_array([0.0, 0.0, 1.0, 1.0]);
+
+/// Builds the lines of the coordinate axes of a transformation, in red,
+/// green, and blue for X, Y, and Z respectively.
+///
+/// * `trans`: Transformation whose axes are to be drawn.
+/// * `len`: Length of the axes before scaling.
+///
+/// Returns the lines of the axes.
+pub fn axes_lines(trans: Transform, len: f32) -> [Line; 3]
+{
+    let mat = trans.into_matrix();
+    let origin = f32x4::from_array([0.0, 0.0, 0.0, 1.0]).mul_mat(mat);
+    let axis = |x, y, z| f32x4::from_array([x, y, z, 1.0]).mul_mat(mat);
+    [(origin, axis(len, 0.0, 0.0), X_COLOR),
+     (origin, axis(0.0, len, 0.0), Y_COLOR),
+     (origin, axis(0.0, 0.0, len), Z_COLOR)]
+}
+
+/// Builds the lines of the edges of a bounding box.
+///
+/// * `bounds`: Bounding box in model space.
+/// * `mdl`: Model to world transformation.
+/// * `color`: Color of the edges.
+///
+/// Returns the lines of the edges.
+pub fn aabb_lines(bounds: Aabb, mdl: Transform, color: f32x4) -> [Line; 12]
+{
+    let mat = mdl.into_matrix();
+    let corner = |idx: usize| {
+        let mask = mask32x4::from_array([idx & 0x1 != 0, idx & 0x2 != 0, idx & 0x4 != 0, false]);
+        mask.select(bounds.max(), bounds.min())
+            .replace_lane::<3>(1.0)
+            .mul_mat(mat)
+    };
+    // Every edge joins two corners whose indices differ in a single bit.
+    let mut lines = [(f32x4::splat(0.0), f32x4::splat(0.0), color); 12];
+    let edges = (0 .. 8).flat_map(|idx| [0x1, 0x2, 0x4].map(|bit| (idx, idx | bit)))
+                        .filter(|(idx0, idx1)| idx0 != idx1);
+    for (line, (idx0, idx1)) in lines.iter_mut().zip(edges) {
+        *line = (corner(idx0), corner(idx1), color);
+    }
+    lines
+}
+
+/// Builds the lines joining a sequence of points, such as a route found by
+/// the pathfinder.
+///
+/// * `points`: Points to join in world space.
+/// * `color`: Color of the lines.
+///
+/// Returns the lines between consecutive points.
+pub fn path_lines(points: &[f32x4], color: f32x4) -> Vec<Line>
+{
+    points.windows(2).map(|pair| (pair[0], pair[1], color)).collect()
+}
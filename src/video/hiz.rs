@@ -0,0 +1,204 @@
+//! Coarse occlusion culling.
+//!
+//! Large occluders such as dungeon walls are rasterized into a depth buffer at
+//! an eighth of the rasterization resolution, where every cell stores a depth
+//! that is guaranteed to be covered across the whole cell, and objects are
+//! tested against it before their triangles are submitted.  Coarser levels of
+//! the pyramid keep the farthest depth of the cells that they cover, so that
+//! objects of any size can be tested by looking at no more than two cells along
+//! each axis.
+//!
+//! Everything is conservative: occluders only affect cells that they cover
+//! completely, and objects are only culled when every cell that their
+//! bounding box may touch is closer than their nearest point.  Depth is
+//! reversed like in the depth buffer, so closer means larger.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::simd::prelude::*;
+
+use super::ProjectedTriangle;
+use crate::math::Rect;
+use crate::simd::SimdFloatExtra;
+
+/// Size of a cell at the finest level along each axis, in pixels at the
+/// rasterization resolution.
+const CELL_DIM: usize = 8;
+
+/// Hierarchical depth buffer.
+#[derive(Debug)]
+pub struct DepthPyramid
+{
+    /// Levels, from one cell per block of pixels up to a single cell, with
+    /// their widths, heights, and cell depths in row-major order.
+    levels: Vec<(usize, usize, Vec<f32>)>,
+    /// Whether the coarser levels are out of date with the finest level.
+    dirty: bool,
+}
+
+impl DepthPyramid
+{
+    /// Creates and initializes a new empty pyramid.
+    ///
+    /// * `width`: Width of the rasterization resolution.
+    /// * `height`: Height of the rasterization resolution.
+    ///
+    /// Returns the newly created pyramid.
+    pub fn new(width: usize, height: usize) -> Self
+    {
+        let mut levels = Vec::new();
+        let (mut width, mut height) = (width.div_ceil(CELL_DIM), height.div_ceil(CELL_DIM));
+        loop {
+            levels.push((width, height, vec![0.0; width * height]));
+            if width <= 1 && height <= 1 {
+                break;
+            }
+            (width, height) = (width.div_ceil(2), height.div_ceil(2));
+        }
+        Self { levels, dirty: false }
+    }
+
+    /// Returns the width and height of the rasterization resolution covered by
+    /// this pyramid, rounded up to whole cells.
+    pub fn dimensions(&self) -> (usize, usize)
+    {
+        let (width, height, _) = self.levels[0];
+        (width * CELL_DIM, height * CELL_DIM)
+    }
+
+    /// Removes all the occluders.
+    pub fn clear(&mut self)
+    {
+        self.levels.iter_mut().for_each(|(_, _, cells)| cells.fill(0.0));
+        self.dirty = false;
+    }
+
+    /// Rasterizes an occluding triangle into the finest level.
+    ///
+    /// * `tri`: Triangle to rasterize.
+    /// * `clip`: Clipping rectangle outside of which the triangle is not drawn.
+    pub fn insert(&mut self, tri: &ProjectedTriangle, clip: Rect)
+    {
+        let (vert0, vert1, vert2) = (tri.0.proj, tri.1.proj, tri.2.proj);
+        let depths = f32x4::from_array([vert0[2], vert1[2], vert2[2], 0.5]);
+        let recips = f32x4::from_array([vert0[3], vert1[3], vert2[3], 1.0]);
+        if depths.simd_lt(f32x4::splat(0.0)).any()
+           || depths.simd_gt(f32x4::splat(1.0)).any()
+           || recips.simd_le(f32x4::splat(0.0)).any()
+        {
+            // Part of the triangle is behind the camera or clipped by the near plane.
+            return;
+        }
+        // Only consider the cells lying completely inside the clipping rectangle.
+        let (width, height, cells) = &mut self.levels[0];
+        let col0 = (clip.x.max(0) as usize).div_ceil(CELL_DIM);
+        let row0 = (clip.y.max(0) as usize).div_ceil(CELL_DIM);
+        let col1 = (clip.max().x.max(0) as usize / CELL_DIM).min(*width);
+        let row1 = (clip.max().y.max(0) as usize / CELL_DIM).min(*height);
+        let min = vert0.simd_min(vert1).simd_min(vert2);
+        let max = vert0.simd_max(vert1).simd_max(vert2);
+        let col0 = col0.max(min[0].max(0.0) as usize / CELL_DIM);
+        let row0 = row0.max(min[1].max(0.0) as usize / CELL_DIM);
+        let col1 = col1.min((max[0].max(0.0) as usize).div_ceil(CELL_DIM));
+        let row1 = row1.min((max[1].max(0.0) as usize).div_ceil(CELL_DIM));
+        // Set up the edge functions and the depth plane.
+        let (edge1, edge2) = (vert1 - vert0, vert2 - vert0);
+        let area = edge1[0] * edge2[1] - edge1[1] * edge2[0];
+        if area <= 0.0 {
+            // Degenerate or facing away.
+            return;
+        }
+        let dzdx = (edge1[2] * edge2[1] - edge2[2] * edge1[1]) / area;
+        let dzdy = (edge2[2] * edge1[0] - edge1[2] * edge2[0]) / area;
+        let inside = |x: f32x4, y: f32x4, from: f32x4, to: f32x4| {
+            let (ex, ey) = (f32x4::splat(to[0] - from[0]), f32x4::splat(to[1] - from[1]));
+            let (px, py) = (x - f32x4::splat(from[0]), y - f32x4::splat(from[1]));
+            (ex * py - ey * px).simd_ge(f32x4::splat(0.0)).all()
+        };
+        let offsets = f32x4::from_array([0.0, 1.0, 0.0, 1.0]).mul_scalar(CELL_DIM as f32);
+        let offsets_y = f32x4::from_array([0.0, 0.0, 1.0, 1.0]).mul_scalar(CELL_DIM as f32);
+        for row in row0 .. row1 {
+            for col in col0 .. col1 {
+                let x = f32x4::splat((col * CELL_DIM) as f32) + offsets;
+                let y = f32x4::splat((row * CELL_DIM) as f32) + offsets_y;
+                if !inside(x, y, vert0, vert1) || !inside(x, y, vert1, vert2) || !inside(x, y, vert2, vert0) {
+                    // The triangle doesn't cover the whole cell.
+                    continue;
+                }
+                // Depth is affine in screen space, so its farthest point in the cell is at
+                // one of the corners.
+                let x = x - f32x4::splat(vert0[0]);
+                let y = y - f32x4::splat(vert0[1]);
+                let depth = (f32x4::splat(vert0[2]) + x.mul_scalar(dzdx) + y.mul_scalar(dzdy)).reduce_min();
+                let cell = &mut cells[row * *width + col];
+                *cell = cell.max(depth);
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Checks whether a screen-space bounding box is completely hidden behind
+    /// the occluders.
+    ///
+    /// * `min`: Minimum corner of the bounding box, with the position in the
+    ///   first two lanes.
+    /// * `max`: Maximum corner of the bounding box, with the position in the
+    ///   first two lanes.
+    /// * `depth`: Depth of the point of the bounding box closest to the camera.
+    ///
+    /// Returns whether the bounding box is hidden.
+    pub fn is_occluded(&mut self, min: f32x4, max: f32x4, depth: f32) -> bool
+    {
+        if self.dirty {
+            self.build();
+        }
+        let (width, height) = self.dimensions();
+        let min = min.simd_max(f32x4::splat(0.0));
+        let max = max.simd_min(f32x4::from_array([width as f32 - 1.0, height as f32 - 1.0, 0.0, 0.0]));
+        if min[0] > max[0] || min[1] > max[1] {
+            // The bounding box is completely off screen.
+            return true;
+        }
+        let (mut col0, mut row0) = (min[0] as usize / CELL_DIM, min[1] as usize / CELL_DIM);
+        let (mut col1, mut row1) = (max[0] as usize / CELL_DIM, max[1] as usize / CELL_DIM);
+        // Pick the finest level at which the bounding box touches no more than two
+        // cells along each axis.
+        let mut level = 0;
+        while (col1 - col0 > 1 || row1 - row0 > 1) && level + 1 < self.levels.len() {
+            (col0, row0, col1, row1) = (col0 / 2, row0 / 2, col1 / 2, row1 / 2);
+            level += 1;
+        }
+        let (width, _, cells) = &self.levels[level];
+        (row0 ..= row1).all(|row| (col0 ..= col1).all(|col| cells[row * width + col] > depth))
+    }
+
+    /// Updates the coarser levels of the pyramid, each cell keeping the
+    /// farthest depth among the cells that it covers in the level below.
+    fn build(&mut self)
+    {
+        for level in 1 .. self.levels.len() {
+            let (lower, upper) = self.levels.split_at_mut(level);
+            let (lwidth, lheight, lcells) = &lower[level - 1];
+            let (width, height, cells) = &mut upper[0];
+            for row in 0 .. *height {
+                for col in 0 .. *width {
+                    let cell = |col: usize, row: usize| {
+                        if col < *lwidth && row < *lheight {
+                            lcells[row * lwidth + col]
+                        } else {
+                            // Cells past the edge of the screen hide nothing.
+                            0.0
+                        }
+                    };
+                    let depth = cell(col * 2, row * 2).min(cell(col * 2 + 1, row * 2))
+                                                      .min(cell(col * 2, row * 2 + 1))
+                                                      .min(cell(col * 2 + 1, row * 2 + 1));
+                    cells[row * *width + col] = depth;
+                }
+            }
+        }
+        self.dirty = false;
+    }
+}
@@ -18,43 +18,56 @@
 
 extern crate alloc;
 
+mod anim;
+mod bin;
+mod blit;
 mod fb;
 mod geom;
+mod gizmo;
+mod hiz;
+mod overlay;
+mod particles;
+mod pass;
 mod shader;
+mod shadow;
 
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
+use core::fmt::{Display, Formatter, Result as FormatResult};
 use core::future::Future;
+use core::mem::{replace, size_of};
 use core::pin::Pin;
-use core::simd::f32x4;
-use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
-use core::task::{Context, Poll, Waker};
+use core::simd::prelude::*;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use core::task::{Context, Poll};
 
-pub use self::fb::FrameBuffer;
+pub use self::anim::{Animation, Skeleton, SkinnedMesh};
+use self::bin::{Bins, Entry};
+pub use self::blit::BLITTER;
+pub use self::fb::{DebugMode, FrameBuffer, PixelFormat};
 pub use self::geom::*;
-pub use self::shader::{Light, Triangle as ProjectedTriangle, Vertex as ProjectedVertex};
-use crate::cpu::COUNT as CPU_COUNT;
-use crate::math::{Angle, Projection, Transform};
+pub use self::gizmo::{aabb_lines, axes_lines, path_lines, Line};
+use self::hiz::DepthPyramid;
+pub use self::overlay::Overlay;
+pub use self::particles::{Particles, PARTICLES};
+pub use self::pass::{DrawOrder, Pass};
+use self::shader::Surface;
+pub use self::shader::{Light, Material, Triangle as ProjectedTriangle, Vertex as ProjectedVertex};
+use self::shadow::ShadowMap;
+use crate::cpu::{COUNT as CPU_COUNT, RESERVED as CPU_RESERVED};
+use crate::display::DISPLAY;
+use crate::math::{self, Aabb, Angle, IVec2, Projection, Quaternion, Transform};
 use crate::pixvalve::PIXVALVE;
+use crate::prim::FloatExtra;
 use crate::sched::{Scheduler, SCHED};
-use crate::simd::SimdFloatExtra;
-use crate::sync::{Lazy, Lock, RwLock};
-use crate::{mbox, PERRY_RANGE};
-
-/// Screen width in pixels.
-#[cfg(not(hdmi))]
-const SCREEN_WIDTH: usize = 800;
-#[cfg(hdmi)]
-const SCREEN_WIDTH: usize = 1920;
-/// Screen height in pixels.
-#[cfg(not(hdmi))]
-const SCREEN_HEIGHT: usize = 480;
-#[cfg(hdmi)]
-const SCREEN_HEIGHT: usize = 1080;
-/// Pixel depth in bytes.
-const DEPTH: usize = 4;
-/// Horizontal pitch in bytes.
-const PITCH: usize = SCREEN_WIDTH * DEPTH;
+use crate::settings::RENDER_SCALES;
+use crate::simd::{f32x4x4, SimdFloatExtra};
+use crate::sync::{critical, AsyncRwLock, Lazy, Lock, Notify};
+use crate::timer::TIMER;
+use crate::wait::WaitId;
+use crate::{mbox, profile, PERRY_RANGE};
+
 /// Vertical pitch in rows.
 const VPITCH: usize = 1;
 /// Set plane property tag.
@@ -65,16 +78,22 @@ const HVS_BASE: usize = PERRY_RANGE.start + 0x2400000;
 const HVS_DISPLIST: *const u32 = (HVS_BASE + 0x20) as _;
 /// Hardware video scaler display list buffer.
 const HVS_DISPLIST_BUF: *mut u32 = (HVS_BASE + 0x4000) as _;
-/// Display ID.
-#[cfg(not(hdmi))]
-const DISP_ID: u8 = 0;
-#[cfg(hdmi)]
-const DISP_ID: u8 = 2;
 /// Plane image type XRGB8888 setting.
 const IMG_XRGB8888_TYPE: u8 = 44;
+/// Plane image type RGB565 setting.
+const IMG_RGB565_TYPE: u8 = 1;
 /// Image transformation (bit0 = 180 degree rotation, bit 16 = X flip, bit 17 =
 /// Y flip).
 const IMG_TRANSFORM: u32 = 0x20000;
+/// Width of debug lines in pixels at the rasterization resolution.
+const LINE_WIDTH: f32 = 1.0;
+/// Time in milliseconds between pixel shifts when burn-in mitigation is
+/// enabled.
+const SHIFT_PERIOD: u64 = 180000;
+/// Combined size of the BMP file and information headers.
+const BMP_HEADER_SIZE: usize = 54;
+/// Horizontal and vertical plane offsets cycled through by the pixel shift.
+const SHIFT_OFFSETS: [(usize, usize); 4] = [(0, 0), (1, 0), (1, 1), (0, 1)];
 
 /// Global video driver instance.
 pub static VIDEO: Lazy<Video> = Lazy::new(Video::new);
@@ -82,22 +101,81 @@ pub static VIDEO: Lazy<Video> = Lazy::new(Video::new);
 /// Video driver.
 pub struct Video
 {
-    /// Frame buffer.
-    fb: FrameBuffer,
+    /// Frame buffer, replaced when the render scale changes.
+    fb: Lock<Arc<FrameBuffer>>,
+    /// Replaced frame buffer that may still be on display.
+    retired: Lock<Option<Arc<FrameBuffer>>>,
+    /// Requested render scale in percent of the display resolution.
+    scale: AtomicU32,
+    /// Highest render scale allowed in percent of the display resolution.
+    scale_cap: AtomicU32,
+    /// Requested frame buffer pixel format.
+    format: AtomicU8,
+    /// Whether supersampling is requested.
+    supersample: AtomicBool,
     /// Current frame buffer address.
     cfb: AtomicU32,
+    /// Whether the frame buffer has been replaced and the previous one is
+    /// still on display until the first frame is drawn to the new one.
+    replot: AtomicBool,
     /// Whether this frame has been commited.
     did_commit: AtomicBool,
     /// Current frame.
     frame: AtomicU64,
-    /// VSync waiters.
-    waiters: Lock<Vec<Waker>>,
+    /// Tasks waiting for the next vertical synchronization.
+    vsync: Notify,
+    /// Draw commands awaiting projection.
+    pending: Lock<Vec<Submission>>,
+    /// Number of draw commands submitted in this frame.
+    submitted: AtomicUsize,
     /// Command queue.
-    cmds: RwLock<Vec<Command>>,
+    cmds: AsyncRwLock<Vec<Command>>,
+    /// Triangles from the command queue that may cover each tile.
+    bins: AsyncRwLock<Bins>,
+    /// Whether burn-in mitigation is enabled.
+    burn_in: AtomicBool,
+    /// Index of the current pixel shift offset.
+    shift: AtomicUsize,
+    /// Viewport for subsequent draw commands, or `None` to cover the whole
+    /// screen.
+    viewport: Lock<Option<Rect>>,
+    /// Scissor rectangle for subsequent draw commands, or `None` to not clip
+    /// beyond the viewport.
+    scissor: Lock<Option<Rect>>,
+    /// Render pass of subsequent draw commands.
+    pass: AtomicU8,
+    /// Material of subsequent draw commands.
+    material: Lock<Material>,
+    /// Whether subsequent opaque draw commands occlude other objects.
+    occlude: AtomicBool,
+    /// Coarse depth of the occluders drawn in this frame.
+    hiz: Lock<DepthPyramid>,
+    /// Depth of the shadow casters drawn in this frame as seen from the main
+    /// light, shared with the drawing tasks.
+    shadow: Lock<Arc<ShadowMap>>,
+    /// Statistics of the draw commands of the last committed frame, in
+    /// submission order.
+    stats: Lock<Vec<DrawStats>>,
+}
+
+/// Rectangle in fractions of the render resolution, with the origin at the
+/// bottom left corner of the screen, which keeps its place on screen across
+/// render scale changes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect
+{
+    /// Horizontal position of the left edge.
+    pub x: f32,
+    /// Vertical position of the bottom edge.
+    pub y: f32,
+    /// Width.
+    pub width: f32,
+    /// Height.
+    pub height: f32,
 }
 
 /// Visual triangle.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Triangle(Vertex, Vertex, Vertex);
 
 /// Visual vertex.
@@ -110,6 +188,8 @@ pub struct Vertex
     normal: f32x4,
     /// Color.
     color: f32x4,
+    /// Texture coordinates in the first two lanes.
+    uv: f32x4,
 }
 
 /// Vertical sync future.
@@ -118,6 +198,50 @@ struct VerticalSync
 {
     /// ID of the frame when this future was created.
     frame: u64,
+    /// Registration with the vertical sync notifier, if parked.
+    waiter: Option<WaitId>,
+}
+
+/// Geometry of a draw command awaiting projection.
+#[derive(Debug)]
+enum Geometry
+{
+    /// Triangles in model space.
+    Triangles(Vec<Triangle>),
+    /// Billboards in world space, facing a camera with the given rotation.
+    Billboards(Vec<Billboard>, Quaternion),
+    /// Lines in world space.
+    Lines(Vec<Line>),
+}
+
+/// Draw command awaiting projection.
+#[derive(Debug)]
+struct Submission
+{
+    /// Geometry to draw.
+    geom: Geometry,
+    /// Lights potentially illuminating these triangles.
+    lights: Arc<Vec<Light>>,
+    /// Material of the triangles.
+    material: Material,
+    /// World position of the camera.
+    eye: f32x4,
+    /// Model to world transformation.
+    mdl: f32x4x4,
+    /// World to screen transformation.
+    viewproj: f32x4x4,
+    /// Model to world rotation, applied to the normals.
+    nrot: f32x4x4,
+    /// Offset of the viewport.
+    offset: f32x4,
+    /// Clipping rectangle combining the viewport and scissor rectangle.
+    clip: math::Rect,
+    /// Render pass that the triangles are drawn in.
+    pass: Pass,
+    /// Position of the command in submission order.
+    id: usize,
+    /// Whether the triangles are also rasterized as occluders.
+    occlude: bool,
 }
 
 /// Draw command.
@@ -128,6 +252,41 @@ struct Command
     tris: Vec<ProjectedTriangle>,
     /// Lights potentially illuminating these triangles.
     lights: Arc<Vec<Light>>,
+    /// Areas of the screen that each light may illuminate, within the clipping
+    /// rectangle.
+    lbounds: Vec<math::Rect>,
+    /// Material of the triangles.
+    material: Material,
+    /// World position of the camera.
+    eye: f32x4,
+    /// Clipping rectangle combining the viewport and scissor rectangle.
+    clip: math::Rect,
+    /// Render pass that the triangles are drawn in.
+    pass: Pass,
+    /// Average depth of the triangles, used to sort commands within a pass.
+    depth: f32,
+    /// Position of the command in submission order.
+    id: usize,
+    /// Statistics gathered so far, except for the shaded fragments.
+    stats: DrawStats,
+    /// Number of fragments shaded, counted by all the drawing tasks.
+    fragments: AtomicUsize,
+}
+
+/// Statistics of a draw command.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DrawStats
+{
+    /// Number of triangles submitted.
+    pub submitted: usize,
+    /// Number of triangles discarded for facing away from the camera.
+    pub culled: usize,
+    /// Number of triangles discarded for lying outside the clipping rectangle
+    /// or the depth range.
+    pub clipped: usize,
+    /// Number of fragments that passed the coverage and depth tests and were
+    /// shaded.
+    pub fragments: usize,
 }
 
 /// Set plane property.
@@ -189,54 +348,457 @@ impl Video
     /// Returns the newly created instance.
     fn new() -> Self
     {
-        let fb = FrameBuffer::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+        let format = if cfg!(rgb565) {
+            PixelFormat::Rgb565
+        } else {
+            PixelFormat::Xrgb8888
+        };
+        let fb = FrameBuffer::new(DISPLAY.width(), DISPLAY.height(), format, false, 0);
         let cfb = fb.vsync();
-        let plane_in = SetPlaneProperty { display_id: DISP_ID,
-                                          plane_id: 0,
-                                          img_type: IMG_XRGB8888_TYPE,
-                                          layer: 0,
-                                          width: SCREEN_WIDTH as _,
-                                          height: SCREEN_HEIGHT as _,
-                                          pitch: PITCH as _,
-                                          vpitch: VPITCH as _,
-                                          src_x: 0,
-                                          src_y: 0,
-                                          src_w: (SCREEN_WIDTH << 16) as _,
-                                          src_h: (SCREEN_HEIGHT << 16) as _,
-                                          dst_x: 0,
-                                          dst_y: 0,
-                                          dst_w: SCREEN_WIDTH as _,
-                                          dst_h: SCREEN_HEIGHT as _,
-                                          alpha: 0xFF,
-                                          num_planes: 1,
-                                          is_vu: 0,
-                                          color_encoding: 0,
-                                          planes: [cfb, 0x0, 0x0, 0x0],
-                                          transform: IMG_TRANSFORM };
-        mbox! {SET_PLANE_TAG: plane_in => _};
+        Self::set_plane(&fb, cfb, SHIFT_OFFSETS[0]);
         PIXVALVE.register_vsync(Self::vsync);
-        Self { fb,
-               cfb: AtomicU32::new(cfb + ((PITCH * VPITCH * (SCREEN_HEIGHT - 1)) as u32)),
+        TIMER.schedule(SHIFT_PERIOD, Self::shift);
+        let burn_in = cfg!(burnin);
+        fb.set_dimming(burn_in);
+        let cfb = cfb + Self::last_row_offset(&fb);
+        let hiz = DepthPyramid::new(fb.raster_width(), fb.raster_height());
+        Self { fb: Lock::new(Arc::new(fb)),
+               retired: Lock::new(None),
+               scale: AtomicU32::new(*RENDER_SCALES.end() as _),
+               scale_cap: AtomicU32::new(*RENDER_SCALES.end() as _),
+               format: AtomicU8::new(format as _),
+               supersample: AtomicBool::new(false),
+               cfb: AtomicU32::new(cfb),
+               replot: AtomicBool::new(false),
                did_commit: AtomicBool::new(false),
                frame: AtomicU64::new(0),
-               waiters: Lock::new(Vec::new()),
-               cmds: RwLock::new(Vec::new()) }
+               vsync: Notify::new(),
+               pending: Lock::new(Vec::new()),
+               submitted: AtomicUsize::new(0),
+               cmds: AsyncRwLock::new(Vec::new()),
+               bins: AsyncRwLock::new(Bins::new()),
+               burn_in: AtomicBool::new(burn_in),
+               shift: AtomicUsize::new(0),
+               viewport: Lock::new(None),
+               scissor: Lock::new(None),
+               pass: AtomicU8::new(Pass::Opaque as _),
+               material: Lock::new(Material::default()),
+               occlude: AtomicBool::new(false),
+               hiz: Lock::new(hiz),
+               shadow: Lock::new(Arc::new(ShadowMap::new())),
+               stats: Lock::new(Vec::new()) }
     }
 
-    /// Adds a draw command to the queue.
+    /// Sets the region of the screen that subsequent draw commands project
+    /// their geometry to, which allows drawing different views side by side.
+    /// Geometry is also clipped to the viewport.
+    ///
+    /// * `viewport`: Region to draw to, or `None` to cover the whole screen.
+    pub fn set_viewport(&self, viewport: Option<Rect>)
+    {
+        *self.viewport.lock() = viewport;
+    }
+
+    /// Sets a rectangle outside of which subsequent draw commands leave the
+    /// screen untouched, without affecting the projection.
+    ///
+    /// * `scissor`: Rectangle to clip to, or `None` to only clip to the
+    ///   viewport.
+    pub fn set_scissor(&self, scissor: Option<Rect>)
+    {
+        *self.scissor.lock() = scissor;
+    }
+
+    /// Enables or disables the burn-in mitigation policy, which periodically
+    /// shifts the whole image by a pixel and dims regions of the screen whose
+    /// content hasn't changed for a while.
+    ///
+    /// * `enable`: Whether to enable burn-in mitigation.
+    pub fn set_burn_in_mitigation(&self, enable: bool)
+    {
+        self.burn_in.store(enable, Ordering::Relaxed);
+        self.frame_buffer().set_dimming(enable);
+    }
+
+    /// Returns whether burn-in mitigation is enabled.
+    pub fn burn_in_mitigation(&self) -> bool
+    {
+        self.burn_in.load(Ordering::Relaxed)
+    }
+
+    /// Selects a debug rendering mode to visualize the inner workings of the
+    /// rasterizer.
+    ///
+    /// * `mode`: Mode to select.
+    pub fn set_debug_mode(&self, mode: DebugMode)
+    {
+        self.frame_buffer().set_debug_mode(mode);
+    }
+
+    /// Enables or disables gamma correction, which lights and blends colors in
+    /// linear space and dithers the output to reduce banding.
+    ///
+    /// * `enable`: Whether to enable gamma correction.
+    pub fn set_gamma_correction(&self, enable: bool)
+    {
+        self.frame_buffer().set_gamma_correction(enable);
+    }
+
+    /// Returns whether gamma correction is enabled.
+    pub fn gamma_correction(&self) -> bool
+    {
+        self.frame_buffer().gamma_correction()
+    }
+
+    /// Changes the internal rendering resolution, which takes effect at the
+    /// next commit and is scaled up to the display resolution by the Hardware
+    /// Video Scaler.  Lower scales trade sharpness for frame rate.
+    ///
+    /// * `scale`: Render scale in percent of the display resolution.
+    ///
+    /// Panics if the scale is outside of the supported range.
+    #[track_caller]
+    pub fn set_render_scale(&self, scale: u32)
+    {
+        assert!(u8::try_from(scale).is_ok_and(|scale| RENDER_SCALES.contains(&scale)),
+                "Render scale {scale}% is outside of the supported range");
+        self.scale.store(scale, Ordering::Relaxed);
+    }
+
+    /// Returns the requested render scale in percent of the display
+    /// resolution, which the cap may be holding back.
+    pub fn render_scale(&self) -> u32
+    {
+        self.scale.load(Ordering::Relaxed)
+    }
+
+    /// Limits the internal rendering resolution without changing the
+    /// requested render scale, such as to relieve the board while it's hot,
+    /// which takes effect at the next commit.
+    ///
+    /// * `cap`: Highest render scale allowed in percent of the display
+    ///   resolution.
+    ///
+    /// Panics if the cap is outside of the supported range.
+    #[track_caller]
+    pub fn set_render_scale_cap(&self, cap: u32)
+    {
+        assert!(u8::try_from(cap).is_ok_and(|cap| RENDER_SCALES.contains(&cap)),
+                "Render scale cap {cap}% is outside of the supported range");
+        self.scale_cap.store(cap, Ordering::Relaxed);
+    }
+
+    /// Enables or disables supersampling, which takes effect at the next
+    /// commit.  Supersampling rasterizes at twice the render resolution along
+    /// each axis and averages every 2x2 block of fragments into a pixel,
+    /// smoothing jagged edges at four times the rasterization cost, which can
+    /// be followed in the profiler.
+    ///
+    /// * `enable`: Whether to enable supersampling.
+    pub fn set_supersampling(&self, enable: bool)
+    {
+        self.supersample.store(enable, Ordering::Relaxed);
+    }
+
+    /// Returns whether supersampling is requested.
+    pub fn supersampling(&self) -> bool
+    {
+        self.supersample.load(Ordering::Relaxed)
+    }
+
+    /// Changes the pixel format of the frame buffer, which takes effect at the
+    /// next commit.  RGB565 halves the memory used by the frame buffer at the
+    /// cost of color precision and of copying tiles with the CPU.
+    ///
+    /// * `format`: Pixel format to select.
+    pub fn set_pixel_format(&self, format: PixelFormat)
+    {
+        self.format.store(format as _, Ordering::Relaxed);
+    }
+
+    /// Returns the requested frame buffer pixel format.
+    pub fn pixel_format(&self) -> PixelFormat
+    {
+        match self.format.load(Ordering::Relaxed) {
+            1 => PixelFormat::Rgb565,
+            _ => PixelFormat::Xrgb8888,
+        }
+    }
+
+    /// Captures the image currently on display as an uncompressed 32-bit BMP
+    /// file at the render resolution.  The image may tear if it's captured
+    /// while the next frame is being drawn.
+    ///
+    /// Returns the contents of the BMP file.
+    pub fn capture_frame(&self) -> Vec<u8>
+    {
+        let fb = self.frame_buffer();
+        let (width, height) = (fb.width(), fb.height());
+        let image_size = width * height * size_of::<u32>();
+        let file_size = BMP_HEADER_SIZE + image_size;
+        let mut bmp = Vec::with_capacity(file_size);
+        // File header.
+        bmp.extend_from_slice(b"BM");
+        bmp.extend_from_slice(&(file_size as u32).to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&(BMP_HEADER_SIZE as u32).to_le_bytes());
+        // Information header, with a positive height since both the frame
+        // buffer and the BMP format store rows from the bottom up.
+        bmp.extend_from_slice(&40u32.to_le_bytes());
+        bmp.extend_from_slice(&(width as i32).to_le_bytes());
+        bmp.extend_from_slice(&(height as i32).to_le_bytes());
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&32u16.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&(image_size as u32).to_le_bytes());
+        bmp.extend_from_slice(&[0; 16]);
+        // XRGB8888 pixels in little endian already match the BMP's BGRX
+        // layout.
+        for pixel in fb.front() {
+            bmp.extend_from_slice(&pixel.to_le_bytes());
+        }
+        bmp
+    }
+
+    /// Selects the render pass of subsequent draw commands.  Passes are drawn
+    /// in order, each with its own depth and blending state, and translucent
+    /// passes mix their colors with the existing content in proportion to the
+    /// alpha of their vertex colors.
+    ///
+    /// * `pass`: Pass to draw to.
+    pub fn set_pass(&self, pass: Pass)
+    {
+        self.pass.store(pass as _, Ordering::Relaxed);
+    }
+
+    /// Returns the render pass of subsequent draw commands.
+    pub fn pass(&self) -> Pass
+    {
+        match self.pass.load(Ordering::Relaxed) {
+            0 => Pass::Opaque,
+            1 => Pass::Transparent,
+            2 => Pass::Overlay,
+            _ => Pass::Debug,
+        }
+    }
+
+    /// Selects the material of subsequent draw commands, including particles.
+    ///
+    /// * `material`: Material to shade the triangles with.
+    pub fn set_material(&self, material: Material)
+    {
+        *self.material.lock() = material;
+    }
+
+    /// Enables or disables occlusion for subsequent opaque draw commands,
+    /// whose triangles are then also rasterized at a coarse resolution to hide
+    /// the objects behind them for the rest of the frame.  Only large
+    /// triangles such as walls are worth drawing as occluders.
+    ///
+    /// * `enable`: Whether subsequent draw commands occlude other objects.
+    pub fn set_occluding(&self, enable: bool)
+    {
+        self.occlude.store(enable, Ordering::Relaxed);
+    }
+
+    /// Enables or disables shadows cast by the main light, which take effect
+    /// for subsequent draw commands.  The opaque triangles drawn in the frame
+    /// are rendered again from the light's point of view, and the fragments
+    /// that the light can't see through them are darkened.
+    ///
+    /// * `light`: Light to world transformation, looking down the negative Z
+    ///   axis like a camera, or `None` to disable shadows.
+    /// * `fov`: Field of view of the light.
+    pub fn set_shadows(&self, light: Option<Transform>, fov: Angle)
+    {
+        Arc::make_mut(&mut self.shadow.lock()).set_light(light, fov);
+    }
+
+    /// Checks whether an object is completely hidden behind the occluders
+    /// drawn so far in this frame, in which case its triangles don't need to
+    /// be drawn at all.
+    ///
+    /// * `bounds`: Bounding box of the object in model space.
+    /// * `mdl`: Model to world transformation.
+    /// * `cam`: Camera to world transformation.
+    /// * `fov`: Field of view.
+    ///
+    /// Returns whether the object is hidden.
+    pub fn is_occluded(&self, bounds: Aabb, mdl: Transform, cam: Transform, fov: Angle) -> bool
+    {
+        let (_, offset, viewproj) = self.projection(cam, fov);
+        let Some((min, max)) = Self::project_box(bounds, mdl.into_matrix() * viewproj, offset) else {
+            // The object extends behind the camera.
+            return false;
+        };
+        if max[2] > 1.0 {
+            // The object is cut by the near clipping plane.
+            return false;
+        }
+        self.hiz.lock().is_occluded(min, max, max[2])
+    }
+
+    /// Picks the objects whose projections overlap a rectangle on screen, such
+    /// as the one dragged across the touchscreen to select a group of units.
+    ///
+    /// * `rect`: Selection rectangle in fractions of the render resolution.
+    /// * `objects`: Objects to pick from, each along with its bounding box in
+    ///   model space and its model to world transformation.
+    /// * `cam`: Camera to world transformation.
+    /// * `fov`: Field of view.
+    ///
+    /// Returns the selected objects in the order in which they were provided.
+    /// Objects extending behind the camera are never selected.
+    pub fn select<T>(&self, rect: Rect, objects: impl IntoIterator<Item = (T, Aabb, Transform)>, cam: Transform,
+                     fov: Angle)
+                     -> Vec<T>
+    {
+        let (_, offset, viewproj) = self.projection(cam, fov);
+        let (width, height) = {
+            let fb = self.frame_buffer();
+            (fb.raster_width(), fb.raster_height())
+        };
+        objects.into_iter()
+               .filter(|(_, bounds, mdl)| {
+                   Self::project_box(*bounds, mdl.into_matrix() * viewproj, offset)
+                       .is_some_and(|(min, max)| Rect::from_pixels(min, max, width, height).overlaps(rect))
+               })
+               .map(|(obj, ..)| obj)
+               .collect()
+    }
+
+    /// Adds a draw command to the queue, projected to the current viewport and
+    /// clipped to both the viewport and scissor rectangle.
     ///
     /// * `tris`: Triangles to draw.
     /// * `lights`: Lights potentially illuminating the object.
     /// * `cam`: Camera to world transformation.
     /// * `proj`: Projection transformation.
-    pub fn draw_triangles(&self, tris: &[Triangle], lights: Arc<Vec<Light>>, mdl: Transform, cam: Transform, fov: Angle)
+    ///
+    /// Returns the index of the command's statistics in this frame.
+    pub async fn draw_triangles(&self, tris: &[Triangle], lights: Arc<Vec<Light>>, mdl: Transform, cam: Transform,
+                                fov: Angle)
+                                -> usize
     {
-        let proj = Projection::new_perspective(SCREEN_WIDTH, SCREEN_HEIGHT, fov);
-        let proj = proj.into_matrix();
-        let view = cam.recip().into_matrix();
-        let nrot = mdl.rotation().into_matrix();
-        let mdl = mdl.into_matrix();
-        let mdlviewproj = mdl * view * proj;
+        self.enqueue(Geometry::Triangles(tris.to_vec()), lights, mdl, cam, fov, self.pass())
+            .await
+    }
+
+    /// Adds a draw command with billboards, which are expanded into
+    /// rectangles facing the camera when the command is projected.
+    ///
+    /// * `billboards`: Billboards to draw.
+    /// * `lights`: Lights potentially illuminating the billboards.
+    /// * `cam`: Camera to world transformation.
+    /// * `fov`: Field of view.
+    ///
+    /// Returns the index of the command's statistics in this frame.
+    pub async fn draw_billboards(&self, billboards: &[Billboard], lights: Arc<Vec<Light>>, cam: Transform, fov: Angle)
+                                 -> usize
+    {
+        let geom = Geometry::Billboards(billboards.to_vec(), cam.rotation());
+        self.enqueue(geom, lights, Transform::default(), cam, fov, self.pass())
+            .await
+    }
+
+    /// Adds a draw command with debug lines to the debug pass, regardless of
+    /// the selected pass, where they are drawn over everything else with their
+    /// colors unaffected by lights or materials.
+    ///
+    /// * `lines`: Lines to draw.
+    /// * `cam`: Camera to world transformation.
+    /// * `fov`: Field of view.
+    ///
+    /// Returns the index of the command's statistics in this frame.
+    pub async fn draw_lines(&self, lines: &[Line], cam: Transform, fov: Angle) -> usize
+    {
+        let geom = Geometry::Lines(lines.to_vec());
+        self.enqueue(geom, Arc::new(Vec::new()), Transform::default(), cam, fov, Pass::Debug)
+            .await
+    }
+
+    /// Adds a draw command with all the live particles of a particle system to
+    /// the transparent pass, regardless of the selected pass.
+    ///
+    /// * `particles`: Particle system to draw.
+    /// * `lights`: Lights potentially illuminating the particles.
+    /// * `cam`: Camera to world transformation.
+    /// * `fov`: Field of view.
+    ///
+    /// Returns the index of the command's statistics in this frame.
+    pub async fn draw_particles(&self, particles: &Particles, lights: Arc<Vec<Light>>, cam: Transform, fov: Angle)
+                                -> usize
+    {
+        let geom = Geometry::Billboards(particles.billboards(), cam.rotation());
+        self.enqueue(geom, lights, Transform::default(), cam, fov, Pass::Transparent)
+            .await
+    }
+
+    /// Returns the statistics of the draw commands of the last committed
+    /// frame, indexed by the values returned when they were added to the
+    /// queue.
+    pub fn draw_stats(&self) -> Vec<DrawStats>
+    {
+        self.stats.lock().clone()
+    }
+
+    /// Adds a draw command to the queue, deferring the projection of its
+    /// triangles to the cores that draw the frame unless they occlude other
+    /// objects, in which case they're projected right away so that the
+    /// objects submitted after them can be tested against them.
+    ///
+    /// * `geom`: Geometry to draw.
+    /// * `lights`: Lights potentially illuminating the object.
+    /// * `mdl`: Model to world transformation.
+    /// * `cam`: Camera to world transformation.
+    /// * `fov`: Field of view.
+    /// * `pass`: Render pass to draw the triangles in.
+    ///
+    /// Returns the index of the command's statistics in this frame.
+    async fn enqueue(&self, geom: Geometry, lights: Arc<Vec<Light>>, mdl: Transform, cam: Transform, fov: Angle,
+                     pass: Pass)
+                     -> usize
+    {
+        let (clip, offset, viewproj) = self.projection(cam, fov);
+        let id = self.submitted.fetch_add(1, Ordering::Relaxed);
+        let occlude = pass.state().depth_write && self.occlude.load(Ordering::Relaxed);
+        let material = if let Geometry::Lines(_) = geom {
+            Material::new_unlit()
+        } else {
+            self.material.lock().clone()
+        };
+        let sub = Submission { geom,
+                               lights,
+                               material,
+                               eye: cam.position(),
+                               mdl: mdl.into_matrix(),
+                               viewproj,
+                               nrot: mdl.rotation().into_matrix(),
+                               offset,
+                               clip,
+                               pass,
+                               id,
+                               occlude };
+        if occlude {
+            let cmd = self.project(sub);
+            self.cmds.wlock().await.push(cmd);
+        } else {
+            self.pending.lock().push(sub);
+        }
+        id
+    }
+
+    /// Projects the triangles of a submitted draw command.
+    ///
+    /// * `sub`: Draw command to project.
+    ///
+    /// Returns the projected command.
+    fn project(&self, sub: Submission) -> Command
+    {
+        profile!("Video::project");
+        let (mdl, nrot, offset, clip) = (sub.mdl, sub.nrot, sub.offset, sub.clip);
+        let mdlviewproj = mdl * sub.viewproj;
+        let material = &sub.material;
         let map = |tri: &Triangle| {
             let mut proj0 = tri.0.pos.mul_mat(mdlviewproj);
             let mut proj1 = tri.1.pos.mul_mat(mdlviewproj);
@@ -245,24 +807,27 @@ impl Video
             proj0[3] = 1.0;
             proj1[3] = 1.0;
             proj2[3] = 1.0;
-            proj0 = proj0.mul_lane::<0>(recip);
-            proj1 = proj1.mul_lane::<1>(recip);
-            proj2 = proj2.mul_lane::<2>(recip);
+            proj0 = proj0.mul_lane::<0>(recip) + offset;
+            proj1 = proj1.mul_lane::<1>(recip) + offset;
+            proj2 = proj2.mul_lane::<2>(recip) + offset;
             let normal0 = tri.0.normal.mul_mat(nrot);
             let normal1 = tri.1.normal.mul_mat(nrot);
             let normal2 = tri.2.normal.mul_mat(nrot);
-            let proj0 = ProjectedVertex { pos: tri.0.pos,
+            let proj0 = ProjectedVertex { pos: tri.0.pos.mul_mat(mdl),
                                           proj: proj0,
                                           normal: normal0,
-                                          color: tri.0.color };
-            let proj1 = ProjectedVertex { pos: tri.1.pos,
+                                          color: tri.0.color,
+                                          uv: material.transform_uv(tri.0.uv) };
+            let proj1 = ProjectedVertex { pos: tri.1.pos.mul_mat(mdl),
                                           proj: proj1,
                                           normal: normal1,
-                                          color: tri.1.color };
-            let proj2 = ProjectedVertex { pos: tri.2.pos,
+                                          color: tri.1.color,
+                                          uv: material.transform_uv(tri.1.uv) };
+            let proj2 = ProjectedVertex { pos: tri.2.pos.mul_mat(mdl),
                                           proj: proj2,
                                           normal: normal2,
-                                          color: tri.2.color };
+                                          color: tri.2.color,
+                                          uv: material.transform_uv(tri.2.uv) };
             ProjectedTriangle(proj0, proj1, proj2)
         };
         let filter = |tri: &ProjectedTriangle| {
@@ -271,9 +836,188 @@ impl Video
             let area = vert1[0] * vert2[1] - vert1[1] * vert2[0];
             area > 0.0
         };
-        let tris = tris.iter().map(map).filter(filter).collect::<Vec<_>>();
-        let cmd = Command { tris, lights };
-        self.cmds.wlock().push(cmd);
+        let submitted = match &sub.geom {
+            Geometry::Triangles(tris) => tris.len(),
+            Geometry::Billboards(billboards, _) => billboards.len() * 2,
+            Geometry::Lines(lines) => lines.len() * 2,
+        };
+        let mut stats = DrawStats { submitted,
+                                    ..DrawStats::default() };
+        let state = sub.pass.state();
+        let mut tris = if clip.is_empty() && !state.depth_write {
+            Vec::new()
+        } else {
+            match sub.geom {
+                Geometry::Triangles(tris) => tris.iter().map(map).collect::<Vec<_>>(),
+                Geometry::Billboards(billboards, rot) => {
+                    let right = f32x4::from_array([1.0, 0.0, 0.0, 0.0]) * rot;
+                    let up = f32x4::from_array([0.0, 1.0, 0.0, 0.0]) * rot;
+                    let normal = f32x4::from_array([0.0, 0.0, 1.0, 0.0]) * rot;
+                    billboards.iter()
+                              .flat_map(|billboard| billboard.triangles(right, up, normal))
+                              .map(|tri| map(&tri))
+                              .collect()
+                }
+                Geometry::Lines(lines) => {
+                    let tris =
+                        lines.iter()
+                             .flat_map(|line| Self::line_triangles(line, sub.viewproj, offset).into_iter().flatten())
+                             .collect::<Vec<_>>();
+                    // Lines completely behind the near clipping plane don't face away from the
+                    // camera.
+                    stats.clipped = submitted - tris.len();
+                    tris
+                }
+            }
+        };
+        if state.depth_write {
+            // Triangles facing away from the camera or outside the clipping rectangle
+            // may still cast shadows onto the visible ones.
+            let mut shadow = self.shadow.lock();
+            if shadow.is_enabled() {
+                let shadow = Arc::make_mut(&mut shadow);
+                tris.iter().for_each(|tri| shadow.insert(tri));
+            }
+        }
+        if clip.is_empty() {
+            // Keep the command around for its statistics.
+            stats.clipped = submitted;
+            tris.clear();
+        } else {
+            tris.retain(filter);
+        }
+        stats.culled = stats.submitted - stats.clipped - tris.len();
+        if sub.occlude {
+            let mut hiz = self.hiz.lock();
+            tris.iter().for_each(|tri| hiz.insert(tri, clip));
+        }
+        if state.order != DrawOrder::Submission {
+            tris.sort_unstable_by(|tri0, tri1| state.order.compare(tri0.depth(), tri1.depth()));
+        }
+        let depth = tris.iter().map(ProjectedTriangle::depth).sum::<f32>() / tris.len().max(1) as f32;
+        let lbounds = sub.lights
+                         .iter()
+                         .map(|light| Self::light_bounds(light, sub.viewproj, offset, clip))
+                         .collect();
+        Command { tris,
+                  lights: sub.lights,
+                  lbounds,
+                  material: sub.material,
+                  eye: sub.eye,
+                  clip,
+                  pass: sub.pass,
+                  depth,
+                  id: sub.id,
+                  stats,
+                  fragments: AtomicUsize::new(0) }
+    }
+
+    /// Expands a line into a thin quad on screen.
+    ///
+    /// * `line`: Line to expand.
+    /// * `viewproj`: World to screen transformation.
+    /// * `offset`: Offset of the viewport.
+    ///
+    /// Returns the triangles of the quad, or `None` if the line is completely
+    /// behind the near clipping plane or too short to be drawn.
+    fn line_triangles(line: &Line, viewproj: f32x4x4, offset: f32x4) -> Option<[ProjectedTriangle; 2]>
+    {
+        let (start, end, color) = *line;
+        let mut proj0 = start.replace_lane::<3>(1.0).mul_mat(viewproj);
+        let mut proj1 = end.replace_lane::<3>(1.0).mul_mat(viewproj);
+        // The depth after the perspective divide is 1 at the near clipping plane, so
+        // the difference between the last two lanes changes sign there.
+        let (dist0, dist1) = (proj0[3] - proj0[2], proj1[3] - proj1[2]);
+        if dist0 < 0.0 && dist1 < 0.0 {
+            return None;
+        }
+        if dist0 < 0.0 {
+            proj0 += (proj1 - proj0).mul_scalar(dist0 / (dist0 - dist1));
+        } else if dist1 < 0.0 {
+            proj1 += (proj0 - proj1).mul_scalar(dist1 / (dist1 - dist0));
+        }
+        let screen = |proj: f32x4| proj.replace_lane::<3>(1.0).mul_scalar(proj[3].recip()) + offset;
+        let (screen0, screen1) = (screen(proj0), screen(proj1));
+        let (dx, dy) = (screen1[0] - screen0[0], screen1[1] - screen0[1]);
+        let len = (dx * dx + dy * dy).sqrt();
+        if len < f32::EPSILON {
+            return None;
+        }
+        let scale = LINE_WIDTH * 0.5 / len;
+        let perp = f32x4::from_array([-dy * scale, dx * scale, 0.0, 0.0]);
+        let vert = |proj, pos| ProjectedVertex { proj,
+                                                 pos,
+                                                 normal: f32x4::splat(0.0),
+                                                 color,
+                                                 uv: f32x4::splat(0.0) };
+        let tri0 = ProjectedTriangle(vert(screen0 - perp, start),
+                                     vert(screen1 - perp, end),
+                                     vert(screen1 + perp, end));
+        let tri1 = ProjectedTriangle(vert(screen0 - perp, start),
+                                     vert(screen1 + perp, end),
+                                     vert(screen0 + perp, start));
+        Some([tri0, tri1])
+    }
+
+    /// Computes the area of the screen that a light may illuminate.
+    ///
+    /// * `light`: Light to bound.
+    /// * `viewproj`: World to screen transformation.
+    /// * `offset`: Offset of the viewport.
+    /// * `clip`: Clipping rectangle of the illuminated command.
+    ///
+    /// Returns the bounding rectangle of the light within the clipping
+    /// rectangle, which is the whole clipping rectangle if the light reaches
+    /// behind the camera.
+    fn light_bounds(light: &Light, viewproj: f32x4x4, offset: f32x4, clip: math::Rect) -> math::Rect
+    {
+        let Some((min, max)) = Self::project_box(light.bounds(), viewproj, offset) else {
+            // The light reaches behind the camera.
+            return clip;
+        };
+        let pmin = (min - f32x4::splat(0.5)).cast::<i32>();
+        let pmax = (max + f32x4::splat(0.5)).cast::<i32>();
+        let (pmin, pmax) = (IVec2::new(pmin[0], pmin[1]), IVec2::new(pmax[0], pmax[1]));
+        math::Rect::from_corners(pmin, pmax).intersect(clip)
+    }
+
+    /// Projects the corners of a bounding box to the screen.
+    ///
+    /// * `bounds`: Bounding box to project.
+    /// * `mat`: Transformation from the space of the bounding box to the
+    ///   screen.
+    /// * `offset`: Offset of the viewport.
+    ///
+    /// Returns the minimum and maximum coordinates of the projected corners at
+    /// the rasterization resolution, or `None` if any corner lies behind the
+    /// camera.
+    fn project_box(bounds: Aabb, mat: f32x4x4, offset: f32x4) -> Option<(f32x4, f32x4)>
+    {
+        let mut min = f32x4::splat(f32::INFINITY);
+        let mut max = f32x4::splat(f32::NEG_INFINITY);
+        for idx in 0 .. 8 {
+            let proj = bounds.corner(idx).mul_mat(mat);
+            if proj[3] <= 0.0 {
+                return None;
+            }
+            let proj = proj.mul_scalar(proj[3].recip()) + offset;
+            min = min.simd_min(proj);
+            max = max.simd_max(proj);
+        }
+        Some((min, max))
+    }
+
+    /// Projects draw commands awaiting projection until there are none left.
+    async fn project_pending(&self)
+    {
+        loop {
+            let Some(sub) = self.pending.lock().pop() else {
+                break;
+            };
+            let cmd = self.project(sub);
+            self.cmds.wlock().await.push(cmd);
+            Scheduler::relent().await;
+        }
     }
 
     /// Commits all the commands added to the queue, drawing them to the
@@ -289,43 +1033,309 @@ impl Video
             vsync.await;
             return;
         }
-        let tasks = <[(); CPU_COUNT]>::map([(); CPU_COUNT], |_| SCHED.spawn(self.draw()));
+        let _retired = self.retired.lock().take();
+        // Keep the replaced frame buffer on display until the new one has a frame
+        // to show.
+        let old = self.rescale(frame);
+        // Leave the reserved logical CPU alone for latency-critical tasks.
+        let cpus = (0 .. CPU_COUNT).filter(|cpu| *cpu != CPU_RESERVED);
+        // Project the deferred commands in parallel.
+        let tasks = cpus.clone()
+                        .map(|cpu| SCHED.spawn_pinned(cpu, self.project_pending()))
+                        .collect::<Vec<_>>();
         for task in tasks {
             task.await;
         }
-        self.cmds.wlock().clear();
+        // Draw the passes in order, each sorting its commands as it sees fit and
+        // falling back to submission order, since the commands were projected in no
+        // particular order.
+        {
+            let mut cmds = self.cmds.wlock().await;
+            cmds.sort_by(|cmd0, cmd1| {
+                    cmd0.pass
+                        .cmp(&cmd1.pass)
+                        .then_with(|| cmd0.pass.state().order.compare(cmd0.depth, cmd1.depth))
+                        .then_with(|| cmd0.id.cmp(&cmd1.id))
+                });
+            // Bin the triangles so that each tile only visits those that may cover it.
+            let mut bins = self.bins.wlock().await;
+            profile!("Video::bin");
+            bins.reset(&self.frame_buffer());
+            for (cidx, cmd) in cmds.iter_mut().enumerate() {
+                for (tidx, tri) in cmd.tris.iter().enumerate() {
+                    let entry = Entry { cmd: cidx as _,
+                                        tri: tidx as _ };
+                    if !bins.insert(entry, tri, cmd.clip) {
+                        cmd.stats.clipped += 1;
+                    }
+                }
+            }
+        }
+        // Draw the tiles in parallel, each core starting with its own band.
+        let cores = cpus.clone().count();
+        let tasks = cpus.enumerate()
+                        .map(|(core, cpu)| SCHED.spawn_pinned(cpu, self.draw(core, cores)))
+                        .collect::<Vec<_>>();
+        for task in tasks {
+            task.await;
+        }
+        {
+            let mut cmds = self.cmds.wlock().await;
+            let mut stats = vec![DrawStats::default(); self.submitted.swap(0, Ordering::Relaxed)];
+            for cmd in cmds.iter() {
+                stats[cmd.id] = DrawStats { fragments: cmd.fragments.load(Ordering::Relaxed),
+                                            ..cmd.stats };
+            }
+            *self.stats.lock() = stats;
+            cmds.clear();
+        }
+        {
+            // Start over with the occluders of the next frame, which are projected to the
+            // frame buffer as it is now.
+            let fb = self.frame_buffer();
+            let mut hiz = self.hiz.lock();
+            if hiz.dimensions() == (fb.raster_width(), fb.raster_height()) {
+                hiz.clear();
+            } else {
+                *hiz = DepthPyramid::new(fb.raster_width(), fb.raster_height());
+            }
+            // The drawing tasks are done with the shadow map, so it can be cleared in
+            // place.
+            let mut shadow = self.shadow.lock();
+            if shadow.is_enabled() {
+                Arc::make_mut(&mut shadow).clear();
+            }
+        }
         let vsync = VerticalSync::new(frame);
         vsync.await;
+        if let Some(old) = old {
+            self.present();
+            // The Hardware Video Scaler may still be scanning the old frame buffer
+            // out, so it's only freed at the end of the next commit.
+            *self.retired.lock() = Some(old);
+        }
+    }
+
+    /// Computes the projection of the current viewport.
+    ///
+    /// * `cam`: Camera to world transformation.
+    /// * `fov`: Field of view.
+    ///
+    /// Returns the clipping rectangle combining the viewport and scissor
+    /// rectangle, the offset of the viewport, and the view-projection matrix.
+    fn projection(&self, cam: Transform, fov: Angle) -> (math::Rect, f32x4, f32x4x4)
+    {
+        let (width, height, bounds) = {
+            let fb = self.frame_buffer();
+            (fb.raster_width(), fb.raster_height(), fb.bounds())
+        };
+        let viewport = self.viewport.lock().map_or(bounds, |rect| rect.to_clip(width, height));
+        let scissor = self.scissor.lock().map_or(bounds, |rect| rect.to_clip(width, height));
+        let clip = viewport.intersect(scissor);
+        let offset = f32x4::from_array([viewport.x as f32, viewport.y as f32, 0.0, 0.0]);
+        let proj = Projection::new_perspective(viewport.width as _, viewport.height as _, fov);
+        let view = cam.recip().into_matrix();
+        (clip, offset, view * proj.into_matrix())
+    }
+
+    /// Replaces the frame buffer if the render scale, pixel format, or
+    /// supersampling setting has changed, leaving the old one on display.
+    ///
+    /// * `frame`: Current frame.
+    ///
+    /// Returns the replaced frame buffer, if any, which must be kept alive
+    /// until the new one is presented.
+    fn rescale(&self, frame: u64) -> Option<Arc<FrameBuffer>>
+    {
+        let scale = self.scale
+                        .load(Ordering::Relaxed)
+                        .min(self.scale_cap.load(Ordering::Relaxed)) as usize;
+        // Dimensions must be multiples of the minimum tile size.
+        let width = (DISPLAY.width() * scale / 100) & !0x7;
+        let height = (DISPLAY.height() * scale / 100) & !0x7;
+        let format = self.pixel_format();
+        let supersample = self.supersampling();
+        let old = self.frame_buffer();
+        if old.width() == width
+           && old.height() == height
+           && old.format() == format
+           && old.is_supersampled() == supersample
+        {
+            return None;
+        }
+        // Only the image on display is still needed, so the other one makes room
+        // for the new frame buffer.
+        old.release_back();
+        let fb = FrameBuffer::new(width, height, format, supersample, frame);
+        fb.set_dimming(self.burn_in_mitigation());
+        fb.set_debug_mode(old.debug_mode());
+        fb.set_gamma_correction(old.gamma_correction());
+        let _critical = critical();
+        let mut cur = self.fb.lock();
+        self.replot.store(true, Ordering::Relaxed);
+        Some(replace(&mut *cur, Arc::new(fb)))
+    }
+
+    /// Displays the first frame drawn to a replacement frame buffer, once it
+    /// has been completed.
+    fn present(&self)
+    {
+        let fb = self.frame_buffer();
+        let buf = fb.vsync();
+        // The firmware is slow to respond, so this can't happen in a critical
+        // section.
+        Self::set_plane(&fb, buf, SHIFT_OFFSETS[self.shift.load(Ordering::Relaxed)]);
+        let _critical = critical();
+        let _fb = self.fb.lock();
+        self.cfb.store(buf + Self::last_row_offset(&fb), Ordering::Relaxed);
+        self.replot.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns the current frame buffer.
+    fn frame_buffer(&self) -> Arc<FrameBuffer>
+    {
+        // The lock is shared with the vertical synchronization IRQ handler.
+        let _critical = critical();
+        self.fb.lock().clone()
     }
 
     /// Draws tiles to the frame buffer.
-    async fn draw(&self)
+    ///
+    /// * `core`: Index of the core drawing the tiles.
+    /// * `cores`: Number of cores drawing tiles.
+    async fn draw(&self, core: usize, cores: usize)
     {
-        for mut tile in self.fb.tiles() {
+        let fb = self.frame_buffer();
+        let shadow = self.shadow.lock().clone();
+        let shadow = shadow.is_enabled().then_some(&*shadow);
+        let mut lights = Vec::new();
+        for mut tile in fb.tiles(core, cores) {
             {
-                let cmds = self.cmds.rlock();
-                for cmd in cmds.iter() {
-                    for tri in cmd.tris.iter() {
-                        tile.draw_triangle(tri, &cmd.lights);
+                let cmds = self.cmds.rlock().await;
+                let bins = self.bins.rlock().await;
+                // Count the fragments of each run of triangles from the same command
+                // locally, to avoid contending for the counters with other tiles.
+                let mut run = None;
+                let mut fragments = 0;
+                for entry in bins.tile(tile.rect()) {
+                    if run != Some(entry.cmd) {
+                        if let Some(cmd) = run {
+                            cmds[cmd as usize].fragments.fetch_add(fragments, Ordering::Relaxed);
+                        }
+                        run = Some(entry.cmd);
+                        fragments = 0;
+                        // Only shade the lights that may reach this tile.
+                        let cmd = &cmds[entry.cmd as usize];
+                        lights.clear();
+                        lights.extend(cmd.lights
+                                         .iter()
+                                         .zip(&cmd.lbounds)
+                                         .filter(|(_, bounds)| !bounds.intersect(tile.rect()).is_empty())
+                                         .map(|(light, _)| *light));
                     }
+                    let cmd = &cmds[entry.cmd as usize];
+                    let state = cmd.pass.state();
+                    let surface = Surface { lights: &lights,
+                                            shadow: shadow.filter(|_| state.shadowed),
+                                            material: &cmd.material,
+                                            eye: cmd.eye };
+                    fragments += tile.draw_triangle(&cmd.tris[entry.tri as usize], surface, &cmd.clip, state);
+                }
+                if let Some(cmd) = run {
+                    cmds[cmd as usize].fragments.fetch_add(fragments, Ordering::Relaxed);
                 }
             }
             Scheduler::relent().await;
         }
     }
 
+    /// Configures the frame buffer plane on the Hardware Video Scaler,
+    /// scaling it to cover the whole display.
+    ///
+    /// * `fb`: Frame buffer to display.
+    /// * `buf`: DMA address of the frame buffer image to display.
+    /// * `offset`: Horizontal and vertical offsets of the plane in display
+    ///   pixels.
+    fn set_plane(fb: &FrameBuffer, buf: u32, offset: (usize, usize))
+    {
+        let (xoff, yoff) = offset;
+        let (width, height) = (fb.width(), fb.height());
+        let (dwidth, dheight) = (DISPLAY.width(), DISPLAY.height());
+        let img_type = match fb.format() {
+            PixelFormat::Xrgb8888 => IMG_XRGB8888_TYPE,
+            PixelFormat::Rgb565 => IMG_RGB565_TYPE,
+        };
+        let plane_in = SetPlaneProperty { display_id: DISPLAY.id(),
+                                          plane_id: 0,
+                                          img_type,
+                                          layer: 0,
+                                          width: width as _,
+                                          height: height as _,
+                                          pitch: (width * fb.format().depth()) as _,
+                                          vpitch: VPITCH as _,
+                                          src_x: 0,
+                                          src_y: 0,
+                                          src_w: ((width << 16) * (dwidth - xoff) / dwidth) as _,
+                                          src_h: ((height << 16) * (dheight - yoff) / dheight) as _,
+                                          dst_x: xoff as _,
+                                          dst_y: yoff as _,
+                                          dst_w: (dwidth - xoff) as _,
+                                          dst_h: (dheight - yoff) as _,
+                                          alpha: 0xFF,
+                                          num_planes: 1,
+                                          is_vu: 0,
+                                          color_encoding: 0,
+                                          planes: [buf, 0x0, 0x0, 0x0],
+                                          transform: IMG_TRANSFORM };
+        mbox! {SET_PLANE_TAG: plane_in => _};
+    }
+
+    /// Timer handler that shifts the plane by a pixel when burn-in mitigation
+    /// is enabled.
+    ///
+    /// Returns true to remain scheduled.
+    fn shift() -> bool
+    {
+        // The plane is configured again when a replacement frame buffer is
+        // presented.
+        if !VIDEO.burn_in_mitigation() || VIDEO.replot.load(Ordering::Relaxed) {
+            return true;
+        }
+        let shift = (VIDEO.shift.load(Ordering::Relaxed) + 1) % SHIFT_OFFSETS.len();
+        VIDEO.shift.store(shift, Ordering::Relaxed);
+        // The firmware expects the beginning of the buffer and rebuilds the display
+        // list, which the vertical synchronization handler will find again on the next
+        // flip.
+        let fb = VIDEO.fb.lock();
+        let cfb = VIDEO.cfb.load(Ordering::Relaxed) - Self::last_row_offset(&fb);
+        Self::set_plane(&fb, cfb, SHIFT_OFFSETS[shift]);
+        true
+    }
+
+    /// Returns the offset in bytes from the beginning of a frame buffer image
+    /// to its last row.
+    ///
+    /// * `fb`: Frame buffer whose image to measure.
+    fn last_row_offset(fb: &FrameBuffer) -> u32
+    {
+        (fb.width() * fb.format().depth() * VPITCH * (fb.height() - 1)) as u32
+    }
+
     /// Flips the frame buffers and reinitializes the frame drawing cycle.
     fn vsync()
     {
-        if VIDEO.frame.load(Ordering::Relaxed) == VIDEO.fb.frame() {
+        let fb = VIDEO.fb.lock();
+        if VIDEO.frame.load(Ordering::Relaxed) == fb.frame() {
             return;
         }
         let cfb = VIDEO.cfb.load(Ordering::Relaxed);
-        let ofb = VIDEO.fb.vsync();
+        let ofb = fb.vsync();
         // Frame buffer pointers must point at the beginning of the last row instead of
         // the first because we are telling the HVS to draw with the Y axis flipped.
-        let ofb = ofb + ((PITCH * VPITCH * (SCREEN_HEIGHT - 1)) as u32);
-        if ofb == cfb {
+        let ofb = ofb + Self::last_row_offset(&fb);
+        // The display list still refers to the replaced frame buffer until the
+        // replacement is presented.
+        if ofb == cfb && !VIDEO.replot.load(Ordering::Relaxed) {
             // Look for the index of the frame buffer pointers in the HVS display list
             // buffer.  This should only loop a lot when the firmware configuration changes,
             // after that it should find the index to update very quickly.
@@ -333,31 +1343,119 @@ impl Video
                 let mut idx = unsafe { HVS_DISPLIST.read_volatile() as usize };
                 'inner: loop {
                     let ctrl = unsafe { HVS_DISPLIST_BUF.add(idx).read_volatile() };
-                    // Look for a plane with unity scaling.
-                    if ctrl >> 15 & 0x1 != 0 {
-                        // Check whether this plane contains one of our frame buffers.
-                        let fb = unsafe { HVS_DISPLIST_BUF.add(idx + 5).read_volatile() };
-                        if fb == cfb || fb == ofb {
-                            // Found the index to update.
-                            break 'outer idx + 5;
-                        }
-                    }
                     // Check whether this is an end control word.
                     if ctrl >> 30 == 0x2 {
                         break 'inner;
                     }
+                    // Check whether this plane contains one of our frame buffers, whose position
+                    // depends on whether the plane is scaled.
+                    let size = (ctrl >> 24 & 0x3F) as usize;
+                    for word in idx + 1 .. idx + size {
+                        let fb = unsafe { HVS_DISPLIST_BUF.add(word).read_volatile() };
+                        if fb == cfb || fb == ofb {
+                            // Found the index to update.
+                            break 'outer word;
+                        }
+                    }
                     // Skip to the next plane.
-                    idx += (ctrl >> 24 & 0x3F) as usize;
+                    idx += size;
                 }
             };
             VIDEO.cfb.store(ofb, Ordering::Relaxed);
             unsafe { HVS_DISPLIST_BUF.add(idx).write_volatile(ofb) };
         }
         VIDEO.did_commit.store(false, Ordering::SeqCst);
-        VIDEO.frame.store(VIDEO.fb.frame(), Ordering::SeqCst);
-        let mut waiters = VIDEO.waiters.lock();
-        waiters.iter().for_each(|waker| waker.wake_by_ref());
-        waiters.clear();
+        VIDEO.frame.store(fb.frame(), Ordering::SeqCst);
+        VIDEO.vsync.notify_all();
+    }
+}
+
+impl Rect
+{
+    /// Creates and initializes a new rectangle.
+    ///
+    /// * `x`: Horizontal position of the left edge.
+    /// * `y`: Vertical position of the bottom edge.
+    /// * `width`: Width.
+    /// * `height`: Height.
+    ///
+    /// Returns the newly created rectangle.
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self
+    {
+        Self { x, y, width, height }
+    }
+
+    /// Creates and initializes a new rectangle spanning two opposite corners
+    /// given in any order, such as the start and end of a drag.
+    ///
+    /// * `x0`: Horizontal position of the first corner.
+    /// * `y0`: Vertical position of the first corner.
+    /// * `x1`: Horizontal position of the second corner.
+    /// * `y1`: Vertical position of the second corner.
+    ///
+    /// Returns the newly created rectangle.
+    pub fn from_corners(x0: f32, y0: f32, x1: f32, y1: f32) -> Self
+    {
+        Self { x: x0.min(x1),
+               y: y0.min(y1),
+               width: (x1 - x0).abs(),
+               height: (y1 - y0).abs() }
+    }
+
+    /// Checks whether this rectangle overlaps another.
+    ///
+    /// * `other`: Rectangle to check against.
+    ///
+    /// Returns whether the rectangles share any area.
+    pub fn overlaps(self, other: Self) -> bool
+    {
+        self.x < other.x + other.width
+        && other.x < self.x + self.width
+        && self.y < other.y + other.height
+        && other.y < self.y + self.height
+    }
+
+    /// Converts a bounding box in pixels to fractions of the frame buffer.
+    ///
+    /// * `min`: Minimum pixel coordinates.
+    /// * `max`: Maximum pixel coordinates.
+    /// * `width`: Frame buffer width.
+    /// * `height`: Frame buffer height.
+    ///
+    /// Returns the converted rectangle.
+    fn from_pixels(min: f32x4, max: f32x4, width: usize, height: usize) -> Self
+    {
+        // Pixel centers lie at integer coordinates.
+        let (width, height) = (width as f32, height as f32);
+        Self { x: (min[0] + 0.5) / width,
+               y: (min[1] + 0.5) / height,
+               width: (max[0] - min[0]) / width,
+               height: (max[1] - min[1]) / height }
+    }
+
+    /// Converts this rectangle to a clipping rectangle in pixels, clamped to
+    /// the frame buffer.
+    ///
+    /// * `width`: Frame buffer width.
+    /// * `height`: Frame buffer height.
+    ///
+    /// Returns the computed clipping rectangle.
+    fn to_clip(self, width: usize, height: usize) -> math::Rect
+    {
+        let scale = |frac: f32, max: usize| ((frac * max as f32 + 0.5) as usize).min(max) as i32;
+        let min = math::IVec2::new(scale(self.x, width), scale(self.y, height));
+        let max = math::IVec2::new(scale(self.x + self.width, width), scale(self.y + self.height, height));
+        math::Rect::from_corners(min, max)
+    }
+}
+
+impl Display for DrawStats
+{
+    fn fmt(&self, fmt: &mut Formatter) -> FormatResult
+    {
+        write!(fmt,
+               "{} triangles submitted, {} culled, {} clipped, {} fragments shaded",
+               self.submitted, self.culled, self.clipped, self.fragments)
     }
 }
 
@@ -370,7 +1468,7 @@ impl VerticalSync
     /// Returns the newly created future.
     fn new(frame: u64) -> Self
     {
-        Self { frame }
+        Self { frame, waiter: None }
     }
 }
 
@@ -378,13 +1476,26 @@ impl Future for VerticalSync
 {
     type Output = ();
 
-    fn poll(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()>
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()>
     {
+        // Register before checking to not miss a notification in between.
+        let waiter = VIDEO.vsync.register(self.waiter.take(), ctx.waker());
         let frame = VIDEO.frame.load(Ordering::Relaxed);
         if frame != self.frame {
+            VIDEO.vsync.unregister(waiter);
             return Poll::Ready(());
         }
-        VIDEO.waiters.lock().push(ctx.waker().clone());
+        self.waiter = Some(waiter);
         Poll::Pending
     }
 }
+
+impl Drop for VerticalSync
+{
+    fn drop(&mut self)
+    {
+        if let Some(waiter) = self.waiter {
+            VIDEO.vsync.unregister(waiter);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Overlay planes.
+//!
+//! Small XRGB8888 images composited by the Hardware Video Scaler on top of the
+//! frame buffer plane, scaled up to any size at no cost to the renderer.
+//! Overlays are single buffered in uncached memory, since they're meant for
+//! tiny images that change rarely, so updating one in the middle of a scan out
+//! tears for at most a frame.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::{SetPlaneProperty, IMG_XRGB8888_TYPE, SET_PLANE_TAG, VPITCH};
+use crate::alloc::{Alloc, UNCACHED_REGION};
+use crate::display::DISPLAY;
+use crate::math::Rect;
+use crate::ui::{Layout, Length};
+use crate::{mbox, to_dma};
+
+/// Uncached memory allocator.
+static UNCACHED: Alloc<0x40> = Alloc::with_region(&UNCACHED_REGION);
+
+/// Overlay plane.
+#[derive(Debug)]
+pub struct Overlay
+{
+    /// Plane ID, which must differ from that of every other plane.
+    plane_id: u8,
+    /// Width of the image in pixels.
+    width: usize,
+    /// Height of the image in pixels.
+    height: usize,
+    /// Position and size of the plane on the display in pixels.
+    dst: (usize, usize, usize, usize),
+    /// Image in row-major order.
+    buf: Vec<u32, Alloc<'static, 0x40>>,
+}
+
+impl Overlay
+{
+    /// Creates and shows a new blank overlay on the display.
+    ///
+    /// * `plane_id`: Plane ID, which must be greater than 0 as that is used by
+    ///   the frame buffer plane.
+    /// * `width`: Width of the image in pixels.
+    /// * `height`: Height of the image in pixels.
+    /// * `scale`: Number of display pixels along each axis per image pixel.
+    /// * `layout`: Placement of the overlay on the display, whose size is
+    ///   replaced with that of the scaled image.
+    ///
+    /// Returns the newly created overlay.
+    ///
+    /// Panics if the plane ID is 0 or the overlay doesn't fit the display.
+    #[track_caller]
+    pub fn new(plane_id: u8, width: usize, height: usize, scale: usize, layout: Layout) -> Self
+    {
+        assert!(plane_id > 0, "Plane ID 0 is reserved for the frame buffer");
+        let display = Rect::new(0, 0, DISPLAY.width() as _, DISPLAY.height() as _);
+        let size = (Length::Pixels((width * scale) as _), Length::Pixels((height * scale) as _));
+        let dst = layout.with_size(size.0, size.1).resolve(display);
+        assert!(display.contains_rect(dst), "Overlay doesn't fit the display");
+        let (dst_x, dst_y) = (dst.x as usize, dst.y as usize);
+        let (dst_w, dst_h) = (dst.width as usize, dst.height as usize);
+        let mut buf = Vec::with_capacity_in(width * height, UNCACHED);
+        buf.resize(width * height, 0);
+        let this = Self { plane_id,
+                          width,
+                          height,
+                          dst: (dst_x, dst_y, dst_w, dst_h),
+                          buf };
+        this.set_plane(0xFF);
+        this
+    }
+
+    /// Replaces the image of this overlay.
+    ///
+    /// * `pixels`: New image in row-major order.
+    ///
+    /// Panics if the image doesn't have as many pixels as the overlay.
+    #[track_caller]
+    pub fn update(&mut self, pixels: &[u32])
+    {
+        assert_eq!(pixels.len(), self.buf.len(), "Image size mismatch");
+        self.buf.copy_from_slice(pixels);
+    }
+
+    /// Configures this overlay's plane on the Hardware Video Scaler.
+    ///
+    /// * `alpha`: Opacity of the plane.
+    fn set_plane(&self, alpha: u8)
+    {
+        let (dst_x, dst_y, dst_w, dst_h) = self.dst;
+        let plane_in = SetPlaneProperty { display_id: DISPLAY.id(),
+                                          plane_id: self.plane_id,
+                                          img_type: IMG_XRGB8888_TYPE,
+                                          layer: self.plane_id as _,
+                                          width: self.width as _,
+                                          height: self.height as _,
+                                          pitch: (self.width * 4) as _,
+                                          vpitch: VPITCH as _,
+                                          src_x: 0,
+                                          src_y: 0,
+                                          src_w: (self.width << 16) as _,
+                                          src_h: (self.height << 16) as _,
+                                          dst_x: dst_x as _,
+                                          dst_y: dst_y as _,
+                                          dst_w: dst_w as _,
+                                          dst_h: dst_h as _,
+                                          alpha,
+                                          num_planes: 1,
+                                          is_vu: 0,
+                                          color_encoding: 0,
+                                          planes: [to_dma(self.buf.as_ptr() as usize) as u32, 0x0, 0x0, 0x0],
+                                          transform: 0 };
+        mbox! {SET_PLANE_TAG: plane_in => _};
+    }
+}
+
+impl Drop for Overlay
+{
+    fn drop(&mut self)
+    {
+        // Make the plane fully transparent before its image is freed.
+        self.set_plane(0x0);
+    }
+}
@@ -0,0 +1,167 @@
+//! Particle system.
+//!
+//! Keeps a bounded pool of short-lived particles that are integrated on a
+//! worker task and drawn as billboards in a single blended draw
+//! command, which is enough for spell effects, dust from digging, and torch
+//! flames.  Particles fade out as they approach the end of their lifetimes, and
+//! new particles are dropped while the pool is full.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::simd::f32x4;
+
+use super::Billboard;
+use crate::clock::now_micros;
+use crate::game::Rng;
+use crate::simd::SimdFloatExtra;
+use crate::sync::{Lazy, Lock};
+use crate::timer::TIMER;
+
+/// Maximum number of live particles.
+const CAPACITY: usize = 1024;
+/// Time in milliseconds between updates.
+const UPDATE_PERIOD: u64 = 16;
+/// Acceleration applied to all particles in units per second squared.
+const GRAVITY: f32x4 = f32x4::from_array([0.0, -2.0, 0.0, 0.0]);
+/// Seed of the generator that scatters particles in bursts.
+const SEED: u64 = 0x5EED;
+
+/// Global particle system instance.
+pub static PARTICLES: Lazy<Particles> = Lazy::new(Particles::new);
+
+/// Particle system.
+#[derive(Debug)]
+pub struct Particles
+{
+    /// Pool of live particles along with the generator used to scatter bursts.
+    pool: Lock<(Vec<Particle>, Rng)>,
+}
+
+/// Particle.
+#[derive(Clone, Copy, Debug)]
+struct Particle
+{
+    /// World position.
+    pos: f32x4,
+    /// Velocity in units per second.
+    vel: f32x4,
+    /// Color, with the alpha at the beginning of the particle's life.
+    color: f32x4,
+    /// Remaining lifetime in seconds.
+    life: f32,
+    /// Total lifetime in seconds.
+    lifetime: f32,
+    /// Length of the sides of the quad.
+    size: f32,
+}
+
+impl Particles
+{
+    /// Creates and initializes a new particle system.
+    ///
+    /// Returns the newly created particle system.
+    fn new() -> Self
+    {
+        Self { pool: Lock::new((Vec::with_capacity(CAPACITY), Rng::new(SEED))) }
+    }
+
+    /// Emits a single particle.
+    ///
+    /// * `pos`: World position.
+    /// * `vel`: Velocity in units per second.
+    /// * `color`: Color, including the initial alpha.
+    /// * `size`: Length of the sides of the quad.
+    /// * `lifetime`: Lifetime in seconds.
+    ///
+    /// Returns whether the particle was emitted, which doesn't happen while the
+    /// pool is full.
+    pub fn emit(&self, pos: f32x4, vel: f32x4, color: f32x4, size: f32, lifetime: f32) -> bool
+    {
+        let mut pool = self.pool.lock();
+        if pool.0.len() == CAPACITY {
+            return false;
+        }
+        let part = Particle { pos: pos.xyz1(),
+                              vel: vel.xyz0(),
+                              color,
+                              life: lifetime,
+                              lifetime,
+                              size };
+        pool.0.push(part);
+        true
+    }
+
+    /// Emits a burst of particles scattered in random directions.
+    ///
+    /// * `count`: Number of particles to emit.
+    /// * `pos`: World position of the origin of the burst.
+    /// * `speed`: Maximum speed of each particle in units per second.
+    /// * `color`: Color, including the initial alpha.
+    /// * `size`: Length of the sides of each quad.
+    /// * `lifetime`: Lifetime in seconds.
+    ///
+    /// Returns the number of particles that fit in the pool.
+    pub fn burst(&self, count: usize, pos: f32x4, speed: f32, color: f32x4, size: f32, lifetime: f32) -> usize
+    {
+        let mut pool = self.pool.lock();
+        let (parts, rng) = &mut *pool;
+        let count = count.min(CAPACITY - parts.len());
+        for _ in 0 .. count {
+            let mut unit = || rng.below(0x10000) as f32 / 32768.0 - 1.0;
+            let vel = f32x4::from_array([unit(), unit(), unit(), 0.0]).mul_scalar(speed);
+            let part = Particle { pos: pos.xyz1(),
+                                  vel,
+                                  color,
+                                  life: lifetime,
+                                  lifetime,
+                                  size };
+            parts.push(part);
+        }
+        count
+    }
+
+    /// Moves the particles and retires those that reached the end of their
+    /// lifetimes periodically.
+    pub async fn run(&self) -> !
+    {
+        let mut last = now_micros();
+        loop {
+            TIMER.sleep(UPDATE_PERIOD).await;
+            let now = now_micros();
+            let delta = (now - last) as f32 / 1000000.0;
+            last = now;
+            self.update(delta);
+        }
+    }
+
+    /// Builds the billboards of all the live particles.
+    ///
+    /// Returns the billboards in world space.
+    pub fn billboards(&self) -> Vec<Billboard>
+    {
+        let pool = self.pool.lock();
+        pool.0
+            .iter()
+            .map(|part| {
+                let alpha = part.color[3] * part.life / part.lifetime;
+                Billboard::new(part.pos, part.size, part.size, part.color.replace_lane::<3>(alpha))
+            })
+            .collect()
+    }
+
+    /// Integrates the motion of all particles and retires the dead ones.
+    ///
+    /// * `delta`: Time elapsed since the last update in seconds.
+    fn update(&self, delta: f32)
+    {
+        let mut pool = self.pool.lock();
+        let gravity = GRAVITY.mul_scalar(delta);
+        pool.0.retain_mut(|part| {
+                  part.life -= delta;
+                  part.pos += part.vel.mul_scalar(delta);
+                  part.vel += gravity;
+                  part.life > 0.0
+              });
+    }
+}
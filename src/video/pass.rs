@@ -0,0 +1,105 @@
+//! Render passes.
+//!
+//! Draw commands are grouped into passes that are drawn one after the other,
+//! each with its own depth and blending state and its own ordering of commands
+//! and triangles, so that features such as translucency, heads-up markers, and
+//! debug visualizations don't have to share a single set of rules.
+
+use core::cmp::Ordering;
+
+/// Render pass, in drawing order.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Pass
+{
+    /// Solid geometry, tested against and written to the depth buffer.
+    Opaque,
+    /// Translucent geometry blended over the solid geometry, tested against
+    /// but not written to the depth buffer.
+    Transparent,
+    /// Markers blended over the scene regardless of depth.
+    Overlay,
+    /// Debug visualizations drawn over everything else.
+    Debug,
+}
+
+/// Order in which commands and their triangles are drawn within a pass.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DrawOrder
+{
+    /// Closest first, so that hidden triangles fail the depth test before
+    /// being shaded.
+    FrontToBack,
+    /// Farthest first, so that blended triangles compose correctly.
+    BackToFront,
+    /// As submitted.
+    Submission,
+}
+
+/// Fixed function state of a render pass.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PassState
+{
+    /// Whether fragments behind the content of the depth buffer are discarded.
+    pub depth_test: bool,
+    /// Whether fragments update the depth buffer.
+    pub depth_write: bool,
+    /// Whether fragments are blended over the existing content in proportion
+    /// to their alpha.
+    pub blend: bool,
+    /// Order in which commands and their triangles are drawn.
+    pub order: DrawOrder,
+    /// Whether fragments are darkened where the shadow map hides them from
+    /// the main light.
+    pub shadowed: bool,
+}
+
+impl Pass
+{
+    /// Returns the fixed function state of this pass.
+    pub fn state(self) -> PassState
+    {
+        match self {
+            Self::Opaque => PassState { depth_test: true,
+                                        depth_write: true,
+                                        blend: false,
+                                        order: DrawOrder::FrontToBack,
+                                        shadowed: true },
+            Self::Transparent => PassState { depth_test: true,
+                                             depth_write: false,
+                                             blend: true,
+                                             order: DrawOrder::BackToFront,
+                                             shadowed: true },
+            Self::Overlay => PassState { depth_test: false,
+                                         depth_write: false,
+                                         blend: true,
+                                         order: DrawOrder::Submission,
+                                         shadowed: false },
+            Self::Debug => PassState { depth_test: false,
+                                       depth_write: false,
+                                       blend: false,
+                                       order: DrawOrder::Submission,
+                                       shadowed: false },
+        }
+    }
+}
+
+impl DrawOrder
+{
+    /// Compares the depths of two draw commands or triangles.
+    ///
+    /// * `depth0`: Depth of the first item.
+    /// * `depth1`: Depth of the second item.
+    ///
+    /// Returns whether the first item is drawn before, after, or in
+    /// submission order with the second item.
+    pub fn compare(self, depth0: f32, depth1: f32) -> Ordering
+    {
+        // Depth is reversed, so closer items have larger depths.
+        match self {
+            Self::FrontToBack => depth1.total_cmp(&depth0),
+            Self::BackToFront => depth0.total_cmp(&depth1),
+            Self::Submission => Ordering::Equal,
+        }
+    }
+}
@@ -1,7 +1,30 @@
 //! Fragment shader.
+//!
+//! Vertex colors are authored in sRGB.  When gamma correction is requested
+//! they are converted to linear space before being lit, and the lit colors are
+//! converted back to sRGB once blending is done, using polynomial and square
+//! root approximations of the sRGB transfer functions.
+//!
+//! Surfaces are lit with the Blinn-Phong model, where the material of a draw
+//! command tints the diffuse light reflected by the vertex colors, adds
+//! specular highlights where the direction halfway between the light and the
+//! camera lines up with the surface normal, and emits its own light
+//! regardless of the lights around it.
+//!
+//! Materials can also have a texture, sampled at the nearest texel of its base
+//! level and repeated along both axes, which modulates the vertex colors so
+//! that a single texture can be tinted differently across a mesh.  Texture
+//! coordinates are offset and scaled per draw command, which lets surfaces
+//! such as lava and water flow by scrolling them over time.
 
+extern crate alloc;
+
+use alloc::sync::Arc;
 use core::simd::prelude::*;
 
+use super::shadow::ShadowMap;
+use crate::assets::{Texture, TextureFormat};
+use crate::math::{Aabb, Sphere};
 use crate::simd::SimdFloatExtra;
 
 /// Fragment shader state.
@@ -12,12 +35,60 @@ pub struct Shader<'a>
     tri: &'a Triangle,
     /// Shader context.
     ctx: Context,
+    /// Surface properties of the triangle.
+    surface: Surface<'a>,
     /// Combined red light.
     red: f32x4,
     /// Combined green light.
     green: f32x4,
     /// Combined blue light.
     blue: f32x4,
+    /// Combined red specular highlights.
+    sred: f32x4,
+    /// Combined green specular highlights.
+    sgreen: f32x4,
+    /// Combined blue specular highlights.
+    sblue: f32x4,
+    /// Red, green, blue, and alpha of the surface before lighting, once
+    /// computed.
+    albedo: Option<(f32x4, f32x4, f32x4, f32x4)>,
+}
+
+/// Surface properties shared by all the triangles of a draw command.
+#[derive(Clone, Copy, Debug)]
+pub struct Surface<'a>
+{
+    /// Lights potentially illuminating the triangles.
+    pub lights: &'a [Light],
+    /// Shadow map darkening the fragments hidden from the main light, if any.
+    pub shadow: Option<&'a ShadowMap>,
+    /// Material of the triangles.
+    pub material: &'a Material,
+    /// World position of the camera.
+    pub eye: f32x4,
+}
+
+/// Surface shading parameters.
+#[derive(Clone, Debug)]
+pub struct Material
+{
+    /// Color multiplied with the vertex colors.
+    diffuse: f32x4,
+    /// Texture multiplied with the vertex colors, if any.
+    texture: Option<Arc<Texture>>,
+    /// Offset added to the scaled texture coordinates.
+    uvoffset: f32x4,
+    /// Scale applied to the texture coordinates.
+    uvscale: f32x4,
+    /// Intensity of the specular highlights.
+    specular: f32,
+    /// Specular exponent, with larger values making highlights smaller and
+    /// sharper.
+    power: u32,
+    /// Color emitted regardless of the lights.
+    emissive: f32x4,
+    /// Whether the lights affect the surface at all.
+    lit: bool,
 }
 
 /// Triangle to draw, with vertices in counter-clockwise order.
@@ -36,6 +107,8 @@ pub struct Vertex
     pub normal: f32x4,
     /// Color.
     pub color: f32x4,
+    /// Texture coordinates in the first two lanes.
+    pub uv: f32x4,
 }
 
 /// Light.
@@ -72,17 +145,23 @@ impl<'a> Shader<'a>
     ///
     /// * `tri`: Triangle to shade.
     /// * `ctx`: Shader context.
+    /// * `surface`: Surface properties of the triangle.
     ///
     /// Returns the newly created shader.
     #[inline]
-    pub const fn new(tri: &'a Triangle, ctx: Context) -> Self
+    pub const fn new(tri: &'a Triangle, ctx: Context, surface: Surface<'a>) -> Self
     {
         let zero = f32x4::from_array([0.0; 4]);
         Self { tri,
                ctx,
+               surface,
                red: zero,
                green: zero,
-               blue: zero }
+               blue: zero,
+               sred: zero,
+               sgreen: zero,
+               sblue: zero,
+               albedo: None }
     }
 
     /// Returns the depth of the fragments.
@@ -123,33 +202,133 @@ impl<'a> Shader<'a>
         let diry = diffy * idist;
         let dirz = diffz * idist;
         let dist = idist.fast_recip();
-        let intensity = (normalx * dirx).fused_mul_add(normaly, diry)
-                                        .fused_mul_add(normalz, dirz)
-                                        .simd_max(f32x4::splat(0.4));
-        let intensity = (light.radius - dist) * light.attn * intensity;
+        let cos = (normalx * dirx).fused_mul_add(normaly, diry)
+                                  .fused_mul_add(normalz, dirz);
+        let falloff = (light.radius - dist) * light.attn;
+        let intensity = falloff * cos.simd_max(f32x4::splat(0.4));
         let red = intensity.mul_lane::<0>(light.color);
         let green = intensity.mul_lane::<1>(light.color);
         let blue = intensity.mul_lane::<2>(light.color);
         self.red = self.red.simd_max(red);
         self.green = self.green.simd_max(green);
         self.blue = self.blue.simd_max(blue);
+        let mat = self.surface.material;
+        if mat.specular <= 0.0 {
+            return;
+        }
+        let eye = self.surface.eye;
+        let viewx = f32x4::splat(eye[0]) - posx;
+        let viewy = f32x4::splat(eye[1]) - posy;
+        let viewz = f32x4::splat(eye[2]) - posz;
+        let iview = (viewx * viewx).fused_mul_add(viewy, viewy)
+                                   .fused_mul_add(viewz, viewz)
+                                   .fast_sqrt_recip();
+        let halfx = viewx.fused_mul_add(iview, dirx);
+        let halfy = viewy.fused_mul_add(iview, diry);
+        let halfz = viewz.fused_mul_add(iview, dirz);
+        let ihalf = (halfx * halfx).fused_mul_add(halfy, halfy)
+                                   .fused_mul_add(halfz, halfz)
+                                   .fast_sqrt_recip();
+        let cos_half = (normalx * halfx).fused_mul_add(normaly, halfy)
+                                        .fused_mul_add(normalz, halfz)
+                       * ihalf;
+        // Surfaces facing away from the light have no highlights.
+        let zero = f32x4::splat(0.0);
+        let cos_half = cos.simd_gt(zero).select(cos_half.simd_max(zero), zero);
+        let intensity = falloff * powi(cos_half, mat.power).mul_scalar(mat.specular);
+        let red = intensity.mul_lane::<0>(light.color);
+        let green = intensity.mul_lane::<1>(light.color);
+        let blue = intensity.mul_lane::<2>(light.color);
+        self.sred = self.sred.simd_max(red);
+        self.sgreen = self.sgreen.simd_max(green);
+        self.sblue = self.sblue.simd_max(blue);
+    }
+
+    /// Darkens the light combined so far on the fragments that the shadow map
+    /// hides from the main light.
+    ///
+    /// * `map`: Shadow map to look the fragments up in.
+    #[inline]
+    pub fn shadow(&mut self, map: &ShadowMap)
+    {
+        let posx = self.lerp_attr::<0>(self.tri.0.pos, self.tri.1.pos, self.tri.2.pos);
+        let posy = self.lerp_attr::<1>(self.tri.0.pos, self.tri.1.pos, self.tri.2.pos);
+        let posz = self.lerp_attr::<2>(self.tri.0.pos, self.tri.1.pos, self.tri.2.pos);
+        let vis = map.visibility(posx, posy, posz);
+        self.red *= vis;
+        self.green *= vis;
+        self.blue *= vis;
+        self.sred *= vis;
+        self.sgreen *= vis;
+        self.sblue *= vis;
+    }
+
+    /// Returns the alpha of the surface, combining the vertex colors with the
+    /// material.
+    #[inline]
+    #[must_use]
+    pub fn alpha(&mut self) -> f32x4
+    {
+        self.albedo().3
     }
 
     /// Consumes self and finishes shading.
     ///
+    /// * `is_linear`: Whether to light the vertex colors in linear space.
+    ///
     /// Returns the computed red, green, and blue values with all shading
-    /// effects applied to all fragments.
+    /// effects applied to all fragments, in linear space if requested.
     #[inline]
     #[must_use]
-    pub fn finish(self) -> (f32x4, f32x4, f32x4)
+    pub fn finish(mut self, is_linear: bool) -> (f32x4, f32x4, f32x4)
     {
+        let (red, green, blue, _) = self.albedo();
+        let emissive = self.surface.material.emissive;
+        let (red, green, blue, emissive) = if is_linear {
+            (to_linear(red), to_linear(green), to_linear(blue), to_linear(emissive))
+        } else {
+            (red, green, blue, emissive)
+        };
+        let (lred, lgreen, lblue) = if self.surface.material.lit {
+            (self.red, self.green, self.blue)
+        } else {
+            (f32x4::splat(1.0), f32x4::splat(1.0), f32x4::splat(1.0))
+        };
+        let red = self.sred.fused_mul_add(red, lred) + f32x4::splat(emissive[0]);
+        let green = self.sgreen.fused_mul_add(green, lgreen) + f32x4::splat(emissive[1]);
+        let blue = self.sblue.fused_mul_add(blue, lblue) + f32x4::splat(emissive[2]);
+        (red, green, blue)
+    }
+
+    /// Combines the vertex colors with the material's diffuse color and
+    /// texture, computing them on first use.
+    ///
+    /// Returns the red, green, blue, and alpha of the surface.
+    #[inline]
+    fn albedo(&mut self) -> (f32x4, f32x4, f32x4, f32x4)
+    {
+        if let Some(albedo) = self.albedo {
+            return albedo;
+        }
+        let mat = self.surface.material;
         let red = self.lerp_attr::<0>(self.tri.0.color, self.tri.1.color, self.tri.2.color);
         let green = self.lerp_attr::<1>(self.tri.0.color, self.tri.1.color, self.tri.2.color);
         let blue = self.lerp_attr::<2>(self.tri.0.color, self.tri.1.color, self.tri.2.color);
-        let red = red * self.red;
-        let green = green * self.green;
-        let blue = blue * self.blue;
-        (red, green, blue)
+        let alpha = self.lerp_attr::<3>(self.tri.0.color, self.tri.1.color, self.tri.2.color);
+        let red = red.mul_scalar(mat.diffuse[0]);
+        let green = green.mul_scalar(mat.diffuse[1]);
+        let blue = blue.mul_scalar(mat.diffuse[2]);
+        let alpha = alpha.mul_scalar(mat.diffuse[3]);
+        let albedo = if let Some(tex) = &mat.texture {
+            let u = self.lerp_attr::<0>(self.tri.0.uv, self.tri.1.uv, self.tri.2.uv);
+            let v = self.lerp_attr::<1>(self.tri.0.uv, self.tri.1.uv, self.tri.2.uv);
+            let (tred, tgreen, tblue, talpha) = sample(tex, u, v);
+            (red * tred, green * tgreen, blue * tblue, alpha * talpha)
+        } else {
+            (red, green, blue, alpha)
+        };
+        self.albedo = Some(albedo);
+        albedo
     }
 
     /// Computes the linear interpolation for the specified vertex attributes.
@@ -169,6 +348,212 @@ impl<'a> Shader<'a>
     }
 }
 
+/// Samples the nearest texels of the base level of a texture, repeating it
+/// along both axes.
+///
+/// * `tex`: Texture to sample.
+/// * `u`: Horizontal texture coordinates, with 1 covering the whole width.
+/// * `v`: Vertical texture coordinates, with 1 covering the whole height.
+///
+/// Returns the red, green, blue, and alpha channels of the texels.
+fn sample(tex: &Texture, u: f32x4, v: f32x4) -> (f32x4, f32x4, f32x4, f32x4)
+{
+    let Some(level) = tex.level(0) else {
+        let one = f32x4::splat(1.0);
+        return (one, one, one, one);
+    };
+    let (width, height) = (level.width as i32, level.height as i32);
+    // Round down, correcting the casts that round negative values towards zero.
+    let x = u.mul_scalar(width as f32);
+    let y = v.mul_scalar(height as f32);
+    let (col, row) = (x.cast::<i32>(), y.cast::<i32>());
+    let one = i32x4::splat(1);
+    let col = col.cast::<f32>().simd_gt(x).select(col - one, col);
+    let row = row.cast::<f32>().simd_gt(y).select(row - one, row);
+    let mut texels = [[0.0; 4]; 4];
+    for lane in 0 .. 4 {
+        let idx = (row[lane].rem_euclid(height) * width + col[lane].rem_euclid(width)) as usize;
+        let texel = match tex.format() {
+            TextureFormat::Rgb565 => {
+                let texel = u16::from_le_bytes([level.data[idx * 2], level.data[idx * 2 + 1]]);
+                [(texel >> 11) as f32 / 31.0,
+                 (texel >> 5 & 0x3F) as f32 / 63.0,
+                 (texel & 0x1F) as f32 / 31.0,
+                 1.0]
+            }
+            TextureFormat::Rgba8888 => {
+                let texel = &level.data[idx * 4 .. idx * 4 + 4];
+                [texel[0], texel[1], texel[2], texel[3]].map(|channel| channel as f32 / 255.0)
+            }
+        };
+        texels.iter_mut()
+              .zip(texel)
+              .for_each(|(channel, value)| channel[lane] = value);
+    }
+    let [red, green, blue, alpha] = texels.map(f32x4::from_array);
+    (red, green, blue, alpha)
+}
+
+/// Raises values to a non-negative integer power by repeated squaring.
+///
+/// * `base`: Values to raise.
+/// * `exp`: Exponent.
+///
+/// Returns the computed results.
+#[inline(always)]
+#[must_use]
+fn powi(base: f32x4, exp: u32) -> f32x4
+{
+    let (mut base, mut exp, mut res) = (base, exp, f32x4::splat(1.0));
+    while exp != 0 {
+        if exp & 0x1 != 0 {
+            res *= base;
+        }
+        base *= base;
+        exp >>= 1;
+    }
+    res
+}
+
+/// Converts sRGB color channels to linear space.
+///
+/// * `srgb`: Channel values between 0 and 1 in sRGB.
+///
+/// Returns the converted values.
+#[inline(always)]
+#[must_use]
+pub fn to_linear(srgb: f32x4) -> f32x4
+{
+    let res = f32x4::splat(0.6821711).fused_mul_add(srgb, f32x4::splat(0.30530602));
+    let res = f32x4::splat(0.012522878).fused_mul_add(srgb, res);
+    srgb * res
+}
+
+/// Converts linear color channels to sRGB.
+///
+/// * `linear`: Channel values between 0 and 1 in linear space.
+///
+/// Returns the converted values.
+#[inline(always)]
+#[must_use]
+pub fn to_srgb(linear: f32x4) -> f32x4
+{
+    let sqrt = linear.simd_sqrt();
+    let sqrt2 = sqrt.simd_sqrt();
+    let sqrt3 = sqrt2.simd_sqrt();
+    let res = sqrt.mul_scalar(0.5851224);
+    let res = res.fused_mul_add(sqrt2, f32x4::splat(0.78314036));
+    res.fused_mul_add(sqrt3, f32x4::splat(-0.36826274))
+}
+
+impl Triangle
+{
+    /// Returns the average projected depth of the vertices.
+    pub fn depth(&self) -> f32
+    {
+        (self.0.proj[2] + self.1.proj[2] + self.2.proj[2]) / 3.0
+    }
+}
+
+impl Material
+{
+    /// Creates and initializes a new matte material without highlights or
+    /// emission.
+    ///
+    /// * `diffuse`: Color multiplied with the vertex colors, including alpha.
+    ///
+    /// Returns the newly created material.
+    pub fn new(diffuse: f32x4) -> Self
+    {
+        Self { diffuse,
+               specular: 0.0,
+               power: 1,
+               emissive: f32x4::splat(0.0),
+               texture: None,
+               uvoffset: f32x4::splat(0.0),
+               uvscale: f32x4::splat(1.0),
+               lit: true }
+    }
+
+    /// Creates and initializes a new material that shows the vertex colors as
+    /// they are, regardless of the lights.
+    ///
+    /// Returns the newly created material.
+    pub fn new_unlit() -> Self
+    {
+        Self { lit: false,
+               ..Self::default() }
+    }
+
+    /// Textures the material, multiplying the texels with the vertex colors.
+    ///
+    /// * `texture`: Texture to apply.
+    ///
+    /// Returns the modified material.
+    pub fn with_texture(self, texture: Arc<Texture>) -> Self
+    {
+        Self { texture: Some(texture),
+               ..self }
+    }
+
+    /// Scales and offsets the texture coordinates, which can scroll the
+    /// texture across the surface when the offset changes over time.
+    ///
+    /// * `offset`: Offset added to the scaled coordinates, in the first two
+    ///   lanes.
+    /// * `scale`: Scale applied to the coordinates, in the first two lanes.
+    ///
+    /// Returns the modified material.
+    pub fn with_uv_transform(self, offset: f32x4, scale: f32x4) -> Self
+    {
+        Self { uvoffset: offset,
+               uvscale: scale,
+               ..self }
+    }
+
+    /// Applies the material's texture coordinate transformation.
+    ///
+    /// * `uv`: Texture coordinates to transform, in the first two lanes.
+    ///
+    /// Returns the transformed coordinates.
+    pub fn transform_uv(&self, uv: f32x4) -> f32x4
+    {
+        uv.fused_mul_add(self.uvscale, self.uvoffset)
+    }
+
+    /// Adds specular highlights to the material.
+    ///
+    /// * `intensity`: Intensity of the highlights relative to the light.
+    /// * `power`: Specular exponent, with larger values making highlights
+    ///   smaller and sharper.
+    ///
+    /// Returns the modified material.
+    pub fn with_specular(self, intensity: f32, power: u32) -> Self
+    {
+        Self { specular: intensity,
+               power,
+               ..self }
+    }
+
+    /// Makes the material emit light of its own.
+    ///
+    /// * `emissive`: Color emitted regardless of the lights.
+    ///
+    /// Returns the modified material.
+    pub fn with_emissive(self, emissive: f32x4) -> Self
+    {
+        Self { emissive, ..self }
+    }
+}
+
+impl Default for Material
+{
+    fn default() -> Self
+    {
+        Self::new(f32x4::splat(1.0))
+    }
+}
+
 impl Light
 {
     /// Creates and initializes a new omni light.
@@ -185,4 +570,17 @@ impl Light
                radius: f32x4::splat(radius),
                attn: f32x4::splat(radius.recip()) }
     }
+
+    /// Returns the bounding box of the space beyond which this light has no
+    /// effect.
+    pub fn bounds(&self) -> Aabb
+    {
+        Aabb::new(self.pos - self.radius, self.pos + self.radius)
+    }
+
+    /// Returns the sphere beyond which this light has no effect.
+    pub fn reach(&self) -> Sphere
+    {
+        Sphere::new(self.pos, self.radius[0])
+    }
 }
@@ -0,0 +1,169 @@
+//! Projected shadows.
+//!
+//! The triangles of the passes that write to the depth buffer are also
+//! rasterized without shading from the point of view of the main light into a
+//! small depth buffer, the shadow map, which keeps the depth of the surfaces
+//! closest to the light.  Fragments are then projected into the shadow map
+//! while shading, and those farther from the light than what the shadow map
+//! recorded are darkened, which grounds objects on the surfaces below them.
+//!
+//! Depth is reversed like in the depth buffer, so closer means larger, and a
+//! bias proportional to the distance from the light keeps surfaces from
+//! shadowing themselves due to the limited resolution of the shadow map.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::simd::prelude::*;
+
+use super::ProjectedTriangle;
+use crate::math::{Angle, Projection, Transform};
+use crate::simd::{f32x4x4, SimdFloatExtra};
+
+/// Width and height of the shadow map.
+const SHADOW_DIM: usize = 256;
+/// Fraction of the distance from the light by which fragments must be behind
+/// the shadow map to be in shadow.
+const DEPTH_BIAS: f32 = 0.02;
+/// Fraction of the light kept by fragments in shadow.
+const SHADOW_LIGHT: f32 = 0.5;
+
+/// Depth buffer rendered from the point of view of the main light.
+#[derive(Clone, Debug)]
+pub struct ShadowMap
+{
+    /// World to light projection transformation, or `None` if shadows are
+    /// disabled.
+    viewproj: Option<f32x4x4>,
+    /// Depths of the surfaces closest to the light in row-major order.
+    depths: Vec<f32>,
+}
+
+impl ShadowMap
+{
+    /// Creates and initializes a new shadow map with shadows disabled.
+    ///
+    /// Returns the newly created shadow map.
+    pub fn new() -> Self
+    {
+        Self { viewproj: None,
+               depths: Vec::new() }
+    }
+
+    /// Places the main light, or disables shadows.
+    ///
+    /// * `light`: Light to world transformation, looking down the negative Z
+    ///   axis like a camera, or `None` to disable shadows.
+    /// * `fov`: Field of view of the light.
+    pub fn set_light(&mut self, light: Option<Transform>, fov: Angle)
+    {
+        let Some(light) = light else {
+            self.viewproj = None;
+            self.depths = Vec::new();
+            return;
+        };
+        let proj = Projection::new_perspective(SHADOW_DIM, SHADOW_DIM, fov);
+        self.viewproj = Some(light.recip().into_matrix() * proj.into_matrix());
+        if self.depths.is_empty() {
+            self.depths = vec![0.0; SHADOW_DIM * SHADOW_DIM];
+        }
+    }
+
+    /// Returns whether shadows are enabled.
+    pub fn is_enabled(&self) -> bool
+    {
+        self.viewproj.is_some()
+    }
+
+    /// Removes all the shadow casters.
+    pub fn clear(&mut self)
+    {
+        self.depths.fill(0.0);
+    }
+
+    /// Rasterizes a shadow casting triangle, regardless of which way it's
+    /// facing.
+    ///
+    /// * `tri`: Triangle to rasterize, with its vertices in world space.
+    pub fn insert(&mut self, tri: &ProjectedTriangle)
+    {
+        let Some(viewproj) = self.viewproj else {
+            return;
+        };
+        let project = |pos: f32x4| {
+            let proj = pos.xyz1().mul_mat(viewproj);
+            (proj.w() > 0.0).then(|| proj.mul_scalar(proj.w().recip()))
+        };
+        let (Some(vert0), Some(vert1), Some(vert2)) = (project(tri.0.pos), project(tri.1.pos), project(tri.2.pos))
+        else {
+            // Part of the triangle is behind the light.
+            return;
+        };
+        let min = vert0.simd_min(vert1).simd_min(vert2);
+        let max = vert0.simd_max(vert1).simd_max(vert2);
+        if max[2] > 1.0 {
+            // The triangle is cut by the near clipping plane.
+            return;
+        }
+        // Pixel centers lie at integer coordinates.
+        let col0 = min[0].max(0.0) as usize;
+        let row0 = min[1].max(0.0) as usize;
+        let col1 = (max[0] + 1.0).max(0.0).min(SHADOW_DIM as f32) as usize;
+        let row1 = (max[1] + 1.0).max(0.0).min(SHADOW_DIM as f32) as usize;
+        let (edge1, edge2) = (vert1 - vert0, vert2 - vert0);
+        let area = edge1[0] * edge2[1] - edge1[1] * edge2[0];
+        if area == 0.0 {
+            // Degenerate or seen edge-on.
+            return;
+        }
+        // Dividing by the signed area makes the weights of either winding positive
+        // inside the triangle.
+        let iarea = area.recip();
+        for row in row0 .. row1 {
+            for col in col0 .. col1 {
+                let (x, y) = (col as f32 - vert0[0], row as f32 - vert0[1]);
+                let weight1 = (x * edge2[1] - y * edge2[0]) * iarea;
+                let weight2 = (edge1[0] * y - edge1[1] * x) * iarea;
+                if weight1 < 0.0 || weight2 < 0.0 || weight1 + weight2 > 1.0 {
+                    continue;
+                }
+                let depth = vert0[2] + weight1 * edge1[2] + weight2 * edge2[2];
+                let texel = &mut self.depths[row * SHADOW_DIM + col];
+                *texel = texel.max(depth);
+            }
+        }
+    }
+
+    /// Computes how much of the main light reaches a group of fragments.
+    ///
+    /// * `x`: World horizontal positions of the fragments.
+    /// * `y`: World vertical positions of the fragments.
+    /// * `z`: World depth positions of the fragments.
+    ///
+    /// Returns the fraction of the light kept by each fragment.
+    pub fn visibility(&self, x: f32x4, y: f32x4, z: f32x4) -> f32x4
+    {
+        let Some(viewproj) = self.viewproj else {
+            return f32x4::splat(1.0);
+        };
+        let mut vis = [1.0; 4];
+        for (lane, vis) in vis.iter_mut().enumerate() {
+            let proj = f32x4::from_array([x[lane], y[lane], z[lane], 1.0]).mul_mat(viewproj);
+            if proj.w() <= 0.0 {
+                // Behind the light.
+                continue;
+            }
+            let proj = proj.mul_scalar(proj.w().recip());
+            let (col, row) = (proj[0] + 0.5, proj[1] + 0.5);
+            if col < 0.0 || row < 0.0 || col >= SHADOW_DIM as f32 || row >= SHADOW_DIM as f32 {
+                // Outside the light's field of view.
+                continue;
+            }
+            if self.depths[row as usize * SHADOW_DIM + col as usize] > proj[2] * (1.0 + DEPTH_BIAS) {
+                *vis = SHADOW_LIGHT;
+            }
+        }
+        f32x4::from_array(vis)
+    }
+}
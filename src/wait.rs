@@ -0,0 +1,336 @@
+//! Waker registries.
+//!
+//! Futures that park their tasks until something happens register their wakers
+//! in a [`WaitList`] and hold on to the returned [`WaitId`], which lets them
+//! refresh their registration in place when polled again instead of piling up
+//! wakers, and withdraw it once they complete or are dropped, so that no waker
+//! is left behind to wake a task that no longer exists.
+
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::task::Waker;
+
+/// Parked tasks in registration order, each tagged with a key.
+#[derive(Debug)]
+pub struct WaitList<K = ()>
+{
+    /// Registered waiters, longest waiting first.
+    waiters: VecDeque<Waiter<K>>,
+    /// Identifier of the next registration.
+    next: u64,
+}
+
+/// Identifier of a registration in a wait list.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WaitId(u64);
+
+/// Registered waiter.
+#[derive(Debug)]
+struct Waiter<K>
+{
+    /// Registration identifier.
+    id: WaitId,
+    /// Key that the registration was tagged with.
+    key: K,
+    /// Waker of the parked task.
+    waker: Waker,
+}
+
+impl<K> WaitList<K>
+{
+    /// Creates and initializes a new empty wait list.
+    ///
+    /// Returns the newly created list.
+    pub const fn new() -> Self
+    {
+        Self { waiters: VecDeque::new(),
+               next: 0 }
+    }
+
+    /// Registers a waker, or refreshes an existing registration in place.
+    ///
+    /// * `id`: Registration made by a previous poll of the same future, if any.
+    /// * `key`: Key to tag the registration with.
+    /// * `waker`: Waker to register.
+    ///
+    /// Returns the identifier of the registration, which is a new one if the
+    /// previous registration has already been woken.
+    pub fn register(&mut self, id: Option<WaitId>, key: K, waker: &Waker) -> WaitId
+    {
+        if let Some(waiter) = id.and_then(|id| self.waiters.iter_mut().find(|waiter| waiter.id == id)) {
+            waiter.key = key;
+            if !waiter.waker.will_wake(waker) {
+                waiter.waker = waker.clone();
+            }
+            return waiter.id;
+        }
+        let id = WaitId(self.next);
+        self.next += 1;
+        let waiter = Waiter { id,
+                              key,
+                              waker: waker.clone() };
+        self.waiters.push_back(waiter);
+        id
+    }
+
+    /// Withdraws a registration.
+    ///
+    /// * `id`: Identifier of the registration to withdraw.
+    ///
+    /// Returns whether the registration was still pending, which is not the
+    /// case if it has already been woken.
+    pub fn unregister(&mut self, id: WaitId) -> bool
+    {
+        let Some(idx) = self.waiters.iter().position(|waiter| waiter.id == id) else {
+            return false;
+        };
+        self.waiters.remove(idx);
+        true
+    }
+
+    /// Removes the registration that has been waiting the longest.
+    ///
+    /// Returns its waker, or nothing if the list is empty.
+    pub fn pop(&mut self) -> Option<Waker>
+    {
+        self.waiters.pop_front().map(|waiter| waiter.waker)
+    }
+
+    /// Removes all the registrations.
+    ///
+    /// Returns an iterator over their wakers in registration order.
+    pub fn drain(&mut self) -> impl Iterator<Item = Waker> + '_
+    {
+        self.waiters.drain(..).map(|waiter| waiter.waker)
+    }
+
+    /// Removes the registrations whose keys match a predicate.
+    ///
+    /// * `pred`: Predicate to match the keys against.
+    ///
+    /// Returns the wakers of the removed registrations in registration order.
+    pub fn drain_where(&mut self, mut pred: impl FnMut(&K) -> bool) -> Vec<Waker>
+    {
+        let mut wakers = Vec::new();
+        self.waiters.retain(|waiter| {
+                        if !pred(&waiter.key) {
+                            return true;
+                        }
+                        wakers.push(waiter.waker.clone());
+                        false
+                    });
+        wakers
+    }
+
+    /// Returns an iterator over the keys of all the registrations.
+    pub fn keys(&self) -> impl Iterator<Item = &K> + '_
+    {
+        self.waiters.iter().map(|waiter| &waiter.key)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use alloc::boxed::Box;
+    use alloc::sync::Arc;
+    use alloc::task::Wake;
+    use core::future::Future;
+    use core::pin::Pin;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::task::{Context, Poll};
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Waker that counts how many times it was woken.
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    impl Wake for Counter
+    {
+        fn wake(self: Arc<Self>)
+        {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Stand-in for the timer's sleep future, which completes once woken.
+    struct Sleep<'a>
+    {
+        /// Sleeping tasks keyed by their deadlines.
+        sleepers: &'a Mutex<WaitList<u64>>,
+        /// Deadline.
+        deadline: u64,
+        /// Registration with the sleepers, if parked.
+        waiter: Option<WaitId>,
+    }
+
+    impl<'a> Sleep<'a>
+    {
+        fn new(sleepers: &'a Mutex<WaitList<u64>>, deadline: u64) -> Self
+        {
+            Self { sleepers,
+                   deadline,
+                   waiter: None }
+        }
+    }
+
+    impl Future for Sleep<'_>
+    {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()>
+        {
+            let mut sleepers = self.sleepers.lock().unwrap();
+            match self.waiter {
+                Some(waiter) if !sleepers.unregister(waiter) => {
+                    drop(sleepers);
+                    self.waiter = None;
+                    Poll::Ready(())
+                }
+                _ => {
+                    let waiter = sleepers.register(None, self.deadline, ctx.waker());
+                    drop(sleepers);
+                    self.waiter = Some(waiter);
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    impl Drop for Sleep<'_>
+    {
+        fn drop(&mut self)
+        {
+            if let Some(waiter) = self.waiter {
+                self.sleepers.lock().unwrap().unregister(waiter);
+            }
+        }
+    }
+
+    /// Stand-in for the notifier's future, which completes once woken.
+    struct Notified<'a>
+    {
+        /// Waiting tasks.
+        waiters: &'a Mutex<WaitList>,
+        /// Registration with the waiters, if parked.
+        waiter: Option<WaitId>,
+    }
+
+    impl Future for Notified<'_>
+    {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()>
+        {
+            let mut waiters = self.waiters.lock().unwrap();
+            if let Some(waiter) = self.waiter.take() {
+                waiters.unregister(waiter);
+                return Poll::Ready(());
+            }
+            let waiter = waiters.register(None, (), ctx.waker());
+            drop(waiters);
+            self.waiter = Some(waiter);
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn refresh_in_place()
+    {
+        let mut list = WaitList::new();
+        let waker = Waker::from(Arc::new(Counter::default()));
+        let id = list.register(None, 1, &waker);
+        assert_eq!(list.register(Some(id), 2, &waker), id);
+        assert_eq!(list.keys().copied().collect::<Vec<_>>(), [2]);
+    }
+
+    #[test]
+    fn wake_in_order()
+    {
+        let mut list = WaitList::new();
+        let counters = [0, 1, 2].map(|_| Arc::new(Counter::default()));
+        let ids = counters.clone()
+                          .map(|counter| list.register(None, (), &Waker::from(counter)));
+        assert!(list.unregister(ids[1]));
+        list.pop().unwrap().wake();
+        assert_eq!(counters.each_ref().map(|counter| counter.0.load(Ordering::Relaxed)),
+                   [1, 0, 0]);
+        // A woken registration is no longer pending, and refreshing it makes a
+        // new one.
+        assert!(!list.unregister(ids[0]));
+        let waker = Waker::from(counters[0].clone());
+        assert_ne!(list.register(Some(ids[0]), (), &waker), ids[0]);
+        list.drain().for_each(Waker::wake);
+        assert_eq!(counters.each_ref().map(|counter| counter.0.load(Ordering::Relaxed)),
+                   [2, 0, 1]);
+    }
+
+    #[test]
+    fn notify_one()
+    {
+        // Mirrors how the notifier wakes the task that has waited the longest.
+        let waiters = Mutex::new(WaitList::new());
+        let counters = [0, 1, 2].map(|_| Arc::new(Counter::default()));
+        let wakers = counters.clone().map(Waker::from);
+        let mut tasks = [0, 1, 2].map(|_| {
+                                     Box::pin(Notified { waiters: &waiters,
+                                                         waiter: None })
+                                 });
+        for (task, waker) in tasks.iter_mut().zip(&wakers) {
+            assert!(task.as_mut().poll(&mut Context::from_waker(waker)).is_pending());
+        }
+        waiters.lock().unwrap().pop().unwrap().wake();
+        assert_eq!(counters.each_ref().map(|counter| counter.0.load(Ordering::Relaxed)),
+                   [1, 0, 0]);
+        assert!(tasks[0].as_mut().poll(&mut Context::from_waker(&wakers[0])).is_ready());
+        assert_eq!(waiters.lock().unwrap().keys().count(), 2);
+    }
+
+    #[test]
+    fn drop_pending_sleep()
+    {
+        // Mirrors how the timer keys sleeping tasks by their deadlines.
+        let mut sleepers = WaitList::new();
+        let counter = Arc::new(Counter::default());
+        let waker = Waker::from(counter.clone());
+        let dropped = sleepers.register(None, 10, &waker);
+        let pending = sleepers.register(None, 20, &waker);
+        // The first sleep is dropped before its deadline.
+        assert!(sleepers.unregister(dropped));
+        assert!(sleepers.drain_where(|deadline| *deadline <= 15).is_empty());
+        assert_eq!(sleepers.keys().copied().collect::<Vec<_>>(), [20]);
+        sleepers.drain_where(|deadline| *deadline <= 25)
+                .into_iter()
+                .for_each(Waker::wake);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+        assert!(!sleepers.unregister(pending));
+    }
+
+    #[test]
+    fn stop_scene_mid_sleep()
+    {
+        let sleepers = Mutex::new(WaitList::new());
+        let counter = Arc::new(Counter::default());
+        let waker = Waker::from(counter.clone());
+        let mut ctx = Context::from_waker(&waker);
+        // Scene task ticking every 10 milliseconds until stopped.
+        let mut scene = Box::pin(async {
+            for deadline in (10 ..).step_by(10) {
+                Sleep::new(&sleepers, deadline).await;
+            }
+        });
+        assert!(scene.as_mut().poll(&mut ctx).is_pending());
+        let expired = sleepers.lock().unwrap().drain_where(|deadline| *deadline <= 10);
+        expired.into_iter().for_each(Waker::wake);
+        assert!(scene.as_mut().poll(&mut ctx).is_pending());
+        // Stopping the scene drops its task while it sleeps.
+        drop(scene);
+        assert_eq!(sleepers.lock().unwrap().keys().count(), 0);
+        assert!(sleepers.lock().unwrap().drain_where(|_| true).is_empty());
+        assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+    }
+}